@@ -0,0 +1,56 @@
+//! Benchmark of packing and unpacking of protocol messages
+//!
+//! Run with `cargo bench --features bench --bench message_pack`
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use rhizome_p2p::network::consts::MSG_STORE;
+use rhizome_p2p::network::protocol::ProtocolMessage;
+
+fn store_message(value_size: usize) -> ProtocolMessage {
+    ProtocolMessage {
+        msg_type: MSG_STORE,
+        id: [7u8; 16],
+        node_id: [1u8; 20],
+        payload: serde_json::json!({
+            "key": vec![42u8; 32],
+            "value": vec![0xABu8; value_size],
+            "ttl": 86400,
+            "publisher": vec![1u8; 20],
+            "published_at": 1_700_000_000.0,
+            "hops": 0,
+            "record_type": "message"
+        }),
+        timestamp: 1_700_000_000.0,
+    }
+}
+
+fn pack_unpack(c: &mut Criterion) {
+    let mut group = c.benchmark_group("message_pack");
+
+    for value_size in [64, 1024, 16 * 1024] {
+        let message = store_message(value_size);
+        let packed = rmp_serde::to_vec(&message).unwrap();
+        group.throughput(Throughput::Bytes(packed.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("pack", value_size),
+            &message,
+            |b, message| b.iter(|| black_box(rmp_serde::to_vec(message).unwrap())),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("unpack", value_size),
+            &packed,
+            |b, packed| {
+                b.iter(|| black_box(rmp_serde::from_slice::<ProtocolMessage>(packed).unwrap()))
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, pack_unpack);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+//! Benchmark of ranking of collected popularity metrics
+//!
+//! Run with `cargo bench --features bench --bench ranking`
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use rand::Rng;
+use rhizome_p2p::popularity::metrics::MetricsCollector;
+use rhizome_p2p::popularity::ranking::PopularityRanker;
+use rhizome_p2p::storage::keys::Key;
+
+fn filled_collector(rng: &mut impl Rng, items: u32) -> MetricsCollector {
+    let collector = MetricsCollector::with_capacity(items as usize, 16);
+    for i in 0..items {
+        let key = Key::hashed(&i.to_be_bytes());
+        for _ in 0..rng.gen_range(1..10) {
+            collector.record_find_value(key.clone(), Some(vec![rng.r#gen::<u8>(); 20]));
+        }
+        collector.record_store(key.clone(), rng.gen_range(1..20));
+        collector.record_social_engagement(key.clone(), rng.gen_range(0..5));
+        collector.record_view_time(key, rng.gen_range(0.0..120.0));
+    }
+    collector
+}
+
+fn rank(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let ranker = PopularityRanker::new(0.5, 0.2, 86400.0);
+    let mut group = c.benchmark_group("ranking");
+    group.sample_size(10);
+
+    for items in [10_000, 100_000] {
+        let metrics = filled_collector(&mut rng, items).get_all_metrics();
+
+        group.bench_with_input(
+            BenchmarkId::new("rank_items_top100", metrics.len()),
+            &metrics,
+            |b, metrics| b.iter(|| black_box(ranker.rank_items(metrics, Some(100)))),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, rank);
+criterion_main!(benches);
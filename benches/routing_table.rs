@@ -0,0 +1,67 @@
+//! Benchmark of closest nodes lookup in routing table
+//!
+//! Run with `cargo bench --features bench --bench routing_table`
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use rand::Rng;
+use rhizome_p2p::dht::node::{Node, NodeID};
+use rhizome_p2p::dht::routing_table::RoutingTable;
+
+fn random_id(rng: &mut impl Rng) -> NodeID {
+    let mut id = [0u8; 20];
+    rng.fill(&mut id);
+    NodeID::new(id)
+}
+
+fn filled_table(rng: &mut impl Rng, nodes: usize) -> RoutingTable {
+    let mut table = RoutingTable::new(random_id(rng), 20, 160);
+    for i in 0..nodes {
+        let node = Node::new(random_id(rng), "127.0.0.1".to_string(), 1024 + i as u16);
+        table.add_node(node);
+    }
+    table
+}
+
+fn lookups(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let mut group = c.benchmark_group("routing_table");
+
+    for size in [100, 1_000, 10_000] {
+        let table = filled_table(&mut rng, size);
+        let targets: Vec<NodeID> = (0..64).map(|_| random_id(&mut rng)).collect();
+        let stored = table.get_all_nodes().len();
+
+        group.bench_with_input(
+            BenchmarkId::new("find_closest_nodes", stored),
+            &table,
+            |b, table| {
+                let mut i = 0;
+                b.iter(|| {
+                    let target = &targets[i % targets.len()];
+                    i += 1;
+                    black_box(table.find_closest_nodes(target, 20))
+                });
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("closest_nodes_iter", stored),
+            &table,
+            |b, table| {
+                let mut i = 0;
+                b.iter(|| {
+                    let target = &targets[i % targets.len()];
+                    i += 1;
+                    black_box(table.closest_nodes_iter(target, 20).count())
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, lookups);
+criterion_main!(benches);
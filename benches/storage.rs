@@ -0,0 +1,57 @@
+//! Benchmark of put and get of local storage
+//!
+//! Run with `cargo bench --features bench --bench storage`
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use rhizome_p2p::config::StorageConfig;
+use rhizome_p2p::storage::keys::Key;
+use rhizome_p2p::storage::main::Storage;
+
+/// Count of keys which are written in a circle, so database size stays fixed
+const KEYS: u32 = 1_000;
+
+fn put_get(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let data_dir =
+        std::env::temp_dir().join(format!("rhizome-bench-storage-{}", std::process::id()));
+    let config = StorageConfig {
+        data_dir: data_dir.clone(),
+        ..StorageConfig::default()
+    };
+    let storage = Storage::new(config).unwrap();
+    let keys: Vec<Key> = (0..KEYS).map(|i| Key::hashed(&i.to_be_bytes())).collect();
+
+    let mut group = c.benchmark_group("storage");
+    for value_size in [64, 1024, 16 * 1024] {
+        let value = vec![0xABu8; value_size];
+
+        group.bench_with_input(BenchmarkId::new("put", value_size), &value, |b, value| {
+            let mut i = 0;
+            b.iter(|| {
+                let key = keys[i % keys.len()].clone();
+                i += 1;
+                runtime
+                    .block_on(storage.put(key, value.clone(), 3600))
+                    .unwrap()
+            });
+        });
+
+        group.bench_function(BenchmarkId::new("get", value_size), |b| {
+            let mut i = 0;
+            b.iter(|| {
+                let key = keys[i % keys.len()].clone();
+                i += 1;
+                black_box(runtime.block_on(storage.get(key)).unwrap())
+            });
+        });
+    }
+    group.finish();
+
+    drop(storage);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+criterion_group!(benches, put_get);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+//! Fuzzing of protocol handlers with random and mutated messages of every type
+//!
+//! Run with `cargo run --example fuzz_protocol --features fuzz -- [rounds] [seed]`
+//!
+//! Exit code is 1 if some handler panicked or hung.
+
+use std::net::SocketAddr;
+
+use rhizome_p2p::config::Config;
+use rhizome_p2p::network::fuzz::FuzzHarness;
+use rhizome_p2p::node::full_node::FullNode;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let rounds: usize = args.next().map(|v| v.parse()).transpose()?.unwrap_or(100);
+    let seed: u64 = args.next().map(|v| v.parse()).transpose()?.unwrap_or(1);
+
+    let dir = std::env::temp_dir().join(format!("rhizome-fuzz-{}", std::process::id()));
+    let mut config = Config::from_file(None).for_instance(&dir);
+    config.network.listen_host = "127.0.0.1".to_string();
+    config.network.listen_port = 0;
+    config.network.bootstrap_nodes = Vec::new();
+    config.dht.warm_up = false;
+    config.node.rng_seed = Some(seed);
+    config.security.admin_token_file = Some(dir.join("admin.token"));
+
+    let node = FullNode::new(config).await?;
+    node.start().await?;
+
+    // Nobody listens on the discard port, answers are lost
+    let sink: SocketAddr = "127.0.0.1:9".parse()?;
+    let report = FuzzHarness::new(node.network_protocol.clone(), seed, sink)
+        .run(rounds)
+        .await;
+    node.stop().await?;
+    let _ = std::fs::remove_dir_all(&dir);
+
+    println!(
+        "messages: {}, handled: {}, rejected: {}",
+        report.messages, report.handled, report.rejected
+    );
+    for (msg_type, panic) in &report.panics {
+        println!("panic on {:#04x}: {}", msg_type, panic);
+    }
+    for msg_type in &report.cancelled {
+        println!("cancelled on {:#04x}", msg_type);
+    }
+    for msg_type in &report.hangs {
+        println!("hang on {:#04x}", msg_type);
+    }
+    if !report.passed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
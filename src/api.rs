@@ -11,15 +11,38 @@
 //! - 🔄 Smart replication: Automatic distribution of data to k-nearest nodes.
 //! - 📈 Popularity system: Content in demand gets storage priority and a higher TTL.
 //! - 📦 Modularity: You can use it as a ready-made CLI node, or connect it as a library (cargo lib) to your project.
+//!
+//! ## Cargo features
+//! Features `popularity`, `replication`, `signatures` and `json-logs` are enabled by default. Embedders who need only DHT and storage can use
+//! `default-features = false` and enable what they need:
+//! - `popularity` - metrics, ranking and trending of content and their exchange between nodes.
+//! - `replication` - replication of popular data with signed storage receipts _(needs `popularity` and `signatures`)_.
+//! - `signatures` - RSA node key, signed storage receipts and thread bundles.
+//! - `json-logs` - JSON format of the log file.
+//! - `bench` - criterion benchmarks of hot paths.
+//! - `protobuf` - protobuf wire format of protocol messages.
+//! - `webhooks` - JSON POST of selected node events to URLs from `webhooks` config.
+//! - `wasm-plugins` - content policy plugins in WASM which accept, reject and score stored records.
+//! - `port-mapping` - UPnP IGD and NAT-PMP mapping of the node port on home routers.
+//! - `mailbox` - encrypted direct messages kept in the DHT for offline recipients _(needs `signatures`)_.
+//! - `portability` - export of node keys and authored content into encrypted archive for other device _(needs `signatures`)_.
 
 uniffi::setup_scaffolding!("rhizome_p2p");
 
 /// Configuration Module
 pub mod config;
+/// Events of the node for subscribers
+pub mod events;
 /// Rhizome Exceptions Module
 pub mod exceptions;
 /// Module for logging and registration of events
 pub mod logger;
+/// Encrypted store-and-forward direct messages
+#[cfg(feature = "mailbox")]
+pub mod mailbox;
+/// Encrypted archive of node identity and authored content
+#[cfg(feature = "portability")]
+pub mod portability;
 
 /// Kademlia DHT realization
 pub mod dht;
@@ -28,30 +51,90 @@ pub mod network;
 /// Module for work with nodes: types of nodes and their main functions
 pub mod node;
 /// Module for work with exchange of popular data and analyze metrics for this data
+#[cfg(feature = "popularity")]
 pub mod popularity;
 /// Need for data copying to other nodes in network
 pub mod replication;
 /// Security module for create network more stable
 pub mod security;
+/// Conformance test vectors of the wire protocol for other implementations
+pub mod spec;
 /// Local storage in node for fast data choosing
 pub mod storage;
 /// Support module for uniffi (need to build mobile versions)
 mod uniffi_bindgen;
 /// Some help functional for work with serialization and crypto
 pub mod utils;
+/// Delivery of node events to external HTTP endpoints
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
 
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task;
 use tokio::time::{Duration, sleep};
+use tracing::{debug, warn};
 
 use crate::config::Config;
-use crate::exceptions::{DHTError, NetworkError, RhizomeError};
+use crate::dht::budget::QueryBudget;
+#[cfg(any(feature = "popularity", feature = "mailbox"))]
+use crate::dht::node::Node;
+#[cfg(feature = "mailbox")]
+use crate::dht::node::NodeID;
+#[cfg(feature = "popularity")]
+use crate::dht::protocol::NetworkProtocolTrait;
+use crate::dht::protocol::ValueWithMeta;
+use crate::events::NodeEvent;
+use crate::exceptions::{DHTError, NetworkError, RhizomeError, SecurityError, StorageError};
+#[cfg(feature = "mailbox")]
+use crate::mailbox::{
+    MAX_MAILBOX_CONTENT, MailboxEnvelope, MailboxMessage, append_to_mailbox, decode_mailbox,
+    mailbox_key, open_message, remove_from_mailbox, seal_message,
+};
+use crate::network::peer_stats::PeerSoftware;
+use crate::node::base_node::BootstrapStatus;
 use crate::node::full_node::FullNode;
-use crate::storage::keys::KeyManager;
+#[cfg(feature = "portability")]
+use crate::portability::{
+    IdentityArchive, PortableRecord, install_identity, open_archive, seal_archive,
+};
+#[cfg(feature = "popularity")]
+use crate::replication::receipt::StorageReceipt;
+use crate::security::admin_tokens::AdminRole;
+use crate::security::audit::AuditEventKind;
+#[cfg(feature = "signatures")]
+use crate::security::blacklist::SignedBlocklist;
+use crate::security::blacklist::Subnet;
+#[cfg(feature = "popularity")]
+use crate::security::rate_limiter::RateLimiter;
+use crate::storage::bundle::ReplicatedThread;
+use crate::storage::client_store::ClientStore;
+use crate::storage::data_types::{
+    ChainIssue, ChainLink, VectorClock, Versioned, chained_bytes, merge_message_count,
+    merge_timestamps, reconcile, verify_chain,
+};
+#[cfg(feature = "popularity")]
+use crate::storage::keys::RECORD_ARCHIVE;
+#[cfg(feature = "signatures")]
+use crate::storage::keys::RECORD_BLOCKLIST;
+#[cfg(feature = "mailbox")]
+use crate::storage::keys::RECORD_MAILBOX;
+use crate::storage::keys::{Key, KeyManager, RECORD_MESSAGE, RECORD_MESSAGE_PART, RECORD_THREAD};
+#[cfg(feature = "popularity")]
+use crate::storage::main::{StoreOrigin, TypedRecord};
+use crate::utils::cache::TtlCache;
 use crate::utils::crypto::hash_key;
-use crate::utils::serialization::{deserialize, serialize};
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::node_id_from_public_key_der;
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::{NodeSigner, verify_signature};
+use crate::utils::pacer::WritePacer;
+use crate::utils::serialization::{from_msgpack, to_msgpack};
 use crate::utils::time::get_now_i64;
+use crate::utils::time::{SystemClock, duration_from_secs};
 
 #[derive(uniffi::Record, serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct ThreadMetadataBridge {
@@ -64,6 +147,205 @@ pub struct ThreadMetadataBridge {
     pub message_count: i32,
     pub last_activity: i64,
     pub popularity_score: f64,
+    /// Hex key of the thread archive on seed nodes _(None if thread is not archived)_
+    #[serde(default)]
+    pub archive_key: Option<String>,
+    /// Times of the latest messages, the oldest first _(at most 100)_
+    #[serde(default)]
+    pub recent_messages: Vec<i64>,
+    /// Messages added by every writer, concurrent updates of activity are merged by it
+    #[serde(default)]
+    pub version: VectorClock,
+    /// Chain hash of the latest message, new message links to it
+    #[serde(default)]
+    pub last_message_hash: Option<String>,
+}
+
+/// Thread from the client store with the time of its last sync
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct CachedThreadBridge {
+    pub thread: ThreadMetadataBridge,
+    /// Time when the thread was fetched from the network, changes after it are asked
+    /// by [`RhizomeClient::get_thread_digest`]
+    pub synced_at: i64,
+}
+
+/// Unread state of the subscribed thread
+#[derive(uniffi::Record, serde::Serialize, Clone, Debug)]
+pub struct ThreadDigestBridge {
+    pub thread_id: String,
+    /// Count of messages added after `since` _(counted up to 100 for old threads)_
+    pub new_messages: u32,
+    pub last_activity: i64,
+}
+
+impl ThreadMetadataBridge {
+    /// Digest of the thread for messages after `since`
+    fn digest(&self, since: i64) -> ThreadDigestBridge {
+        let new_messages = if self.created_at > since {
+            self.message_count.max(0) as u32
+        } else {
+            self.recent_messages
+                .iter()
+                .filter(|at| **at > since)
+                .count() as u32
+        };
+        ThreadDigestBridge {
+            thread_id: self.id.clone(),
+            new_messages,
+            last_activity: self.last_activity,
+        }
+    }
+}
+
+impl Versioned for ThreadMetadataBridge {
+    fn version(&self) -> &VectorClock {
+        &self.version
+    }
+
+    fn version_mut(&mut self) -> &mut VectorClock {
+        &mut self.version
+    }
+
+    /// Activity is summed up, description and archive are kept from this version
+    fn merge_fields(&mut self, other: &Self) {
+        self.message_count = merge_message_count(
+            self.message_count,
+            &self.version,
+            other.message_count,
+            &other.version,
+        );
+        if other.last_activity > self.last_activity {
+            self.last_message_hash = other.last_message_hash.clone();
+        }
+        self.last_activity = self.last_activity.max(other.last_activity);
+        self.popularity_score = self.popularity_score.max(other.popularity_score);
+        self.recent_messages = merge_timestamps(
+            &self.recent_messages,
+            &other.recent_messages,
+            MAX_RECENT_MESSAGES,
+        );
+        if self.archive_key.is_none() {
+            self.archive_key = other.archive_key.clone();
+        }
+    }
+}
+
+/// Max count of message times kept in thread metadata
+const MAX_RECENT_MESSAGES: usize = 100;
+
+/// Source and freshness of a found value
+#[derive(uniffi::Record, serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ValueMetaBridge {
+    /// Hex node ID of the node which supplied the value _(None if found locally)_
+    pub source_node_id: Option<String>,
+    /// `address:port` of the source node _(None if found locally)_
+    pub source_address: Option<String>,
+    /// Time when the source stored the value _(0.0 if unknown)_
+    pub stored_at: f64,
+    /// Time until which the source keeps the value _(None if unknown)_
+    pub expires_at: Option<f64>,
+    /// Hex node ID of the original publisher _(None if unknown)_
+    pub publisher: Option<String>,
+}
+
+impl From<&ValueWithMeta> for ValueMetaBridge {
+    fn from(found: &ValueWithMeta) -> Self {
+        Self {
+            source_node_id: found.source.as_ref().map(|n| n.node_id.to_string()),
+            source_address: found
+                .source
+                .as_ref()
+                .map(|n| format!("{}:{}", n.address, n.port)),
+            stored_at: found.stored_at,
+            expires_at: found.expires_at,
+            publisher: (!found.publisher.is_empty()).then(|| hex::encode(&found.publisher)),
+        }
+    }
+}
+
+/// Connection state of the node for display in apps
+#[derive(uniffi::Record, Clone, Debug, Default)]
+pub struct HealthBridge {
+    pub is_running: bool,
+    /// Count of peers in routing table
+    pub peer_count: u32,
+    /// Count of peers in every bucket of routing table, from the farthest one
+    pub bucket_fill: Vec<u32>,
+    /// Max count of peers in one bucket _(k)_
+    pub bucket_size: u32,
+    /// Time of the last lookup answered by a peer _(None if there was no such lookup)_
+    pub last_lookup_at: Option<f64>,
+    /// Bytes which can be stored until the storage limit
+    pub storage_headroom: u64,
+    /// Result of joining the network: "not_started", "in_progress", "connected",
+    /// "unreachable" or "no_bootstrap_nodes"
+    pub bootstrap_status: String,
+}
+
+/// Connection state after which the started node is ready for use
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct ReadyCriteria {
+    /// Min count of peers in routing table
+    pub min_peers: u32,
+    /// Min count of buckets of routing table with at least one peer
+    pub min_filled_buckets: u32,
+}
+
+impl ReadyCriteria {
+    fn is_met(&self, health: &HealthBridge) -> bool {
+        health.peer_count >= self.min_peers
+            && health.bucket_fill.iter().filter(|fill| **fill > 0).count()
+                >= self.min_filled_buckets as usize
+    }
+}
+
+/// Receiver of progress of joining the network, implemented by the app
+#[uniffi::export(with_foreign)]
+pub trait JoinProgressListener: Send + Sync {
+    /// Connection state changed _(new peers, bootstrap result)_
+    fn on_progress(&self, health: HealthBridge);
+}
+
+/// Limits of DHT lookups and stores made by the client _(None keeps node defaults)_
+#[derive(uniffi::Record, Clone, Debug, Default)]
+pub struct QueryOptions {
+    /// Max count of peers asked by one lookup, stores also go to at most this count of peers
+    pub max_peers: Option<u32>,
+    /// Max count of requests in flight
+    pub max_parallel: Option<u32>,
+    /// Seconds after which the call stops, lookups fail with `DeadlineExceeded`
+    pub timeout_secs: Option<f64>,
+}
+
+impl QueryOptions {
+    /// Budget of one call, its deadline starts now by the system clock of the client node
+    fn budget(&self) -> QueryBudget {
+        let budget = QueryBudget {
+            max_peers: self.max_peers.map(|peers| peers as usize),
+            max_parallel: self.max_parallel.map(|parallel| parallel as usize),
+            deadline: None,
+        };
+        match self.timeout_secs {
+            Some(seconds) => budget.with_timeout(&SystemClock, seconds),
+            None => budget,
+        }
+    }
+}
+
+/// Interval in milliseconds between checks of the joining node
+const JOIN_CHECK_INTERVAL: u64 = 250;
+
+/// Count of keys looked up by one prefetch batch
+const PREFETCH_BATCH: usize = 16;
+
+/// Pause in milliseconds between prefetch batches
+const PREFETCH_PAUSE: u64 = 200;
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct MessageWithMetaBridge {
+    pub message: MessageBridge,
+    pub meta: ValueMetaBridge,
 }
 
 #[derive(uniffi::Record, serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -76,12 +358,263 @@ pub struct MessageBridge {
     pub timestamp: i64,
     pub content_type: String,
     pub attachments: Vec<String>,
+    /// Hex hashes of parts of large content, the stored record has empty content then
+    #[serde(default)]
+    pub content_parts: Vec<String>,
+    /// Chain hash of the latest message seen by the author _(None for the first one)_
+    #[serde(default)]
+    pub prev_hash: Option<String>,
+    /// Hex DER of the public key which made `author_signature` _(None if the signature
+    /// was made by the app)_
+    #[serde(default)]
+    pub author_key: Option<String>,
+}
+
+impl MessageBridge {
+    /// Bytes signed by the author: the chained fields without the signature itself
+    #[cfg(feature = "signatures")]
+    fn signed_bytes(&self) -> Vec<u8> {
+        chained_bytes(
+            &self.id,
+            &self.thread_id,
+            self.parent_id.as_deref(),
+            &self.content,
+            None,
+            self.timestamp,
+            self.prev_hash.as_deref(),
+        )
+    }
+
+    /// Sign the message with its link by the key of our node
+    #[cfg(feature = "signatures")]
+    fn sign(&mut self, signer: &NodeSigner) {
+        self.author_signature = Some(hex::encode(signer.sign(&self.signed_bytes())));
+        self.author_key = Some(hex::encode(signer.public_key_der()));
+    }
+}
+
+impl ChainLink for MessageBridge {
+    fn link_id(&self) -> &str {
+        &self.id
+    }
+
+    fn prev_hash(&self) -> Option<&str> {
+        self.prev_hash.as_deref()
+    }
+
+    fn chained_bytes(&self) -> Vec<u8> {
+        chained_bytes(
+            &self.id,
+            &self.thread_id,
+            self.parent_id.as_deref(),
+            &self.content,
+            self.author_signature.as_deref(),
+            self.timestamp,
+            self.prev_hash.as_deref(),
+        )
+    }
+
+    #[cfg(feature = "signatures")]
+    fn author_verified(&self) -> bool {
+        let Some(author_key) = &self.author_key else {
+            return true;
+        };
+        let (Ok(public_key), Some(Ok(signature))) = (
+            hex::decode(author_key),
+            self.author_signature.as_deref().map(hex::decode),
+        ) else {
+            return false;
+        };
+        verify_signature(&public_key, &self.signed_bytes(), &signature)
+    }
+
+    /// Signature by a key can not be checked without signatures
+    #[cfg(not(feature = "signatures"))]
+    fn author_verified(&self) -> bool {
+        self.author_key.is_none()
+    }
+}
+
+/// Max size in bytes of message content kept in the message record, larger content is
+/// stored in parts of this size
+const MESSAGE_PART_SIZE: usize = 8 * 1024;
+/// Max count of parts of one message, 1 MiB of content
+const MAX_MESSAGE_PARTS: usize = 128;
+/// Max count of message parts stored or read at once
+const MESSAGE_PARTS_IN_FLIGHT: usize = 8;
+
+/// Direct message received by our node or fetched from our mailbox
+#[cfg(feature = "mailbox")]
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct DirectMessageBridge {
+    pub id: String,
+    /// Hex node ID of the sender
+    pub sender_id: String,
+    /// Hex DER of the public key of the sender, answer is sent to it
+    pub sender_public_key: String,
+    pub content: Vec<u8>,
+    pub sent_at: i64,
+}
+
+#[cfg(feature = "mailbox")]
+impl From<MailboxMessage> for DirectMessageBridge {
+    fn from(message: MailboxMessage) -> Self {
+        Self {
+            id: message.id,
+            sender_id: hex::encode(message.sender_id),
+            sender_public_key: hex::encode(message.sender_key),
+            content: message.content,
+            sent_at: message.sent_at,
+        }
+    }
+}
+
+/// Result of sending the direct message
+#[cfg(feature = "mailbox")]
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct DirectDeliveryBridge {
+    /// ID of the message
+    pub id: String,
+    /// Recipient confirmed delivery, otherwise the message waits in its mailbox
+    pub delivered: bool,
+}
+
+/// Max count of messages in exported thread bundle or archive
+#[cfg(any(feature = "signatures", feature = "popularity"))]
+const MAX_BUNDLE_MESSAGES: usize = 10_000;
+/// Max count of nodes asked for messages during thread export or archival
+#[cfg(any(feature = "signatures", feature = "popularity"))]
+const MAX_BUNDLE_CRAWL_NODES: usize = 64;
+
+/// Max size of messages in one chunk of the thread archive
+#[cfg(feature = "popularity")]
+const MAX_ARCHIVE_CHUNK_SIZE: usize = 256 * 1024;
+/// Count of thread records read from storage at once during archival
+#[cfg(feature = "popularity")]
+const ARCHIVE_SCAN_PAGE: usize = 256;
+
+/// Inactive thread with its messages, kept on seed nodes
+///
+/// Large archive is split into chunks, this one has the first messages and the thread.
+/// Messages of other chunks are kept under [`KeyManager::get_thread_archive_chunk_key`].
+#[cfg(feature = "popularity")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ThreadArchive {
+    thread: ThreadMetadataBridge,
+    messages: Vec<MessageBridge>,
+    archived_at: i64,
+    /// Count of messages in all chunks, every chunk has at least one
+    #[serde(default)]
+    message_count: u32,
+    /// Hashes of chunks after this one in their order, chunks are checked by them
+    #[serde(default)]
+    chunk_hashes: Vec<[u8; 32]>,
+}
+
+/// Count of seeds which signed valid receipts for the stored archive chunk
+#[cfg(all(feature = "popularity", feature = "signatures"))]
+fn proven_archive_replicas(
+    key: &Key,
+    seeds: &[Node],
+    answers: &[(bool, Option<StorageReceipt>)],
+) -> usize {
+    seeds
+        .iter()
+        .zip(answers)
+        .filter(|(seed, (stored, receipt))| {
+            *stored
+                && receipt.as_ref().is_some_and(|receipt| {
+                    receipt.storer_id[..] == seed.node_id.0[..] && receipt.verify(key)
+                })
+        })
+        .count()
+}
+
+/// Receipts can not be checked without `signatures`, so no archive is proven
+#[cfg(all(feature = "popularity", not(feature = "signatures")))]
+fn proven_archive_replicas(
+    _key: &Key,
+    _seeds: &[Node],
+    _answers: &[(bool, Option<StorageReceipt>)],
+) -> usize {
+    0
+}
+
+/// Stored record is proven to be published by the node, only such threads are archived
+#[cfg(all(feature = "popularity", feature = "signatures"))]
+fn published_by(record: &TypedRecord, node_id: &[u8]) -> bool {
+    record
+        .proof
+        .as_ref()
+        .is_some_and(|proof| node_id_from_public_key_der(&proof.public_key)[..] == node_id[..])
+}
+
+/// Publisher can not be proven without `signatures`, so no thread is archived
+#[cfg(all(feature = "popularity", not(feature = "signatures")))]
+fn published_by(_record: &TypedRecord, _node_id: &[u8]) -> bool {
+    false
+}
+
+/// Thread metadata with its messages signed by the exporting node
+///
+/// Bundle can be shared offline and imported into any network.
+#[cfg(feature = "signatures")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ThreadBundle {
+    thread: ThreadMetadataBridge,
+    messages: Vec<MessageBridge>,
+    exported_at: i64,
+    /// Node ID of the exporting node
+    signer_id: Vec<u8>,
+    /// DER of the public key of the exporting node
+    public_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+#[cfg(feature = "signatures")]
+impl ThreadBundle {
+    /// Bytes which are covered by the signature
+    fn signed_bytes(
+        thread: &ThreadMetadataBridge,
+        messages: &[MessageBridge],
+        exported_at: i64,
+    ) -> Result<Vec<u8>, RhizomeError> {
+        to_msgpack(&(thread, messages, exported_at))
+            .map_err(|_| RhizomeError::Dht(DHTError::General))
+    }
+}
+
+/// Kind of user reaction on the content
+#[cfg(feature = "popularity")]
+#[derive(uniffi::Enum, Clone, Copy, Debug, PartialEq)]
+pub enum EngagementKind {
+    Like,
+    Reply,
+    Share,
+    Bookmark,
+}
+
+#[cfg(feature = "popularity")]
+impl EngagementKind {
+    /// How many social engagements this reaction is worth
+    pub fn weight(&self) -> u64 {
+        match self {
+            EngagementKind::Like | EngagementKind::Bookmark => 1,
+            EngagementKind::Reply => 2,
+            EngagementKind::Share => 3,
+        }
+    }
 }
 
 #[derive(uniffi::Object)]
 pub struct RhizomeClient {
     // Оборачиваем внутреннее состояние для возможности работы через &self
     inner: Arc<RwLock<ClientInner>>,
+    /// Pacing of published threads and messages, kept out of `inner` so waiting writes do
+    /// not block `stop`
+    write_pacer: Arc<WritePacer>,
+    /// Limits of DHT queries of this client handle
+    query_options: QueryOptions,
 }
 
 struct ClientInner {
@@ -89,6 +622,20 @@ struct ClientInner {
     pub node: Option<Arc<FullNode>>,
     pub key_manager: KeyManager,
     pub is_running: bool,
+    /// Cap of view and engagement reports from this client
+    #[cfg(feature = "popularity")]
+    pub report_limiter: Mutex<RateLimiter>,
+    /// Recently found thread metadata by thread id
+    pub thread_cache: Mutex<TtlCache<String, ThreadMetadataBridge>>,
+    /// Recently found messages by message id
+    pub message_cache: Mutex<TtlCache<String, MessageBridge>>,
+    /// Threads and messages seen by the app, kept between restarts
+    pub client_store: Option<ClientStore>,
+    /// Updates of thread metadata by this client go one by one, so every one gets its
+    /// own count in the version clock
+    pub thread_update_lock: Mutex<()>,
+    /// Keys which are looked up by running prefetches
+    pub prefetching: Mutex<HashSet<Key>>,
 }
 
 /// API client for work with protocol
@@ -107,37 +654,116 @@ impl RhizomeClient {
             }
         };
 
+        #[cfg(feature = "popularity")]
+        let report_limiter = RateLimiter::new(
+            final_config.popularity.report_rate_limit as usize,
+            final_config.popularity.report_rate_window as u64,
+            final_config.popularity.report_rate_limit_per_key as usize,
+        );
+
+        let cache_ttl = final_config.storage.client_cache_ttl as f64;
+        let cache_size = final_config.storage.client_cache_size;
+        let key_manager = KeyManager::for_network(&final_config.network.network_id);
+        let client_store = final_config
+            .storage
+            .client_store_dir
+            .as_ref()
+            .and_then(|dir| {
+                ClientStore::open(
+                    dir,
+                    final_config.storage.client_store_max_size,
+                    final_config.storage.client_store_max_entries,
+                    final_config.storage.client_store_ttl as f64,
+                )
+                .inspect_err(|e| warn!(error = %e, "Client store was not opened"))
+                .ok()
+            });
+        let write_pacer = Arc::new(WritePacer::new(
+            final_config.storage.client_write_limit as usize,
+            final_config.storage.client_write_window,
+            final_config.storage.client_write_queue,
+        ));
+
         Arc::new(Self {
             inner: Arc::new(RwLock::new(ClientInner {
                 config: final_config,
                 node: None,
-                key_manager: KeyManager::new(),
+                key_manager,
                 is_running: false,
+                #[cfg(feature = "popularity")]
+                report_limiter: Mutex::new(report_limiter),
+                thread_cache: Mutex::new(TtlCache::new(cache_ttl, cache_size)),
+                message_cache: Mutex::new(TtlCache::new(cache_ttl, cache_size)),
+                client_store,
+                thread_update_lock: Mutex::new(()),
+                prefetching: Mutex::new(HashSet::new()),
             })),
+            write_pacer,
+            query_options: QueryOptions::default(),
         })
     }
 
-    pub async fn start(&self) -> Result<(), RhizomeError> {
-        let mut inner = self.inner.write().await;
-        if inner.is_running {
-            return Err(RhizomeError::Network(NetworkError::General));
-        }
+    /// Handle of the same client whose lookups and stores are limited by `options`
+    ///
+    /// Latency-sensitive screens can use it to bound the worst case, other calls keep
+    /// the defaults. Node, caches and write pacing are shared with this client.
+    pub fn with_query_options(&self, options: QueryOptions) -> Arc<RhizomeClient> {
+        Arc::new(Self {
+            inner: self.inner.clone(),
+            write_pacer: self.write_pacer.clone(),
+            query_options: options,
+        })
+    }
 
-        let node = FullNode::new(inner.config.clone())
-            .await
-            .map_err(|_| RhizomeError::Dht(DHTError::General))?;
+    pub async fn start(&self) -> Result<(), RhizomeError> {
+        self.start_node().await?;
+        sleep(Duration::from_secs(1)).await;
+        Ok(())
+    }
 
-        let node_arc = Arc::new(node);
-        node_arc
-            .start()
-            .await
-            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+    /// Start the node and wait until it has joined the network by `criteria`
+    ///
+    /// `listener` gets connection state every time it changes. If no bootstrap node answers
+    /// and no peer came, `BootstrapError` is returned at once, if criteria are not met in
+    /// `timeout_secs` - `JoinTimeout`. The node keeps running in both cases, so the app can
+    /// wait more or call `stop`.
+    pub async fn start_and_wait(
+        &self,
+        criteria: ReadyCriteria,
+        timeout_secs: f64,
+        listener: Option<Arc<dyn JoinProgressListener>>,
+    ) -> Result<HealthBridge, RhizomeError> {
+        self.start_node().await?;
 
-        inner.node = Some(node_arc);
-        inner.is_running = true;
+        let deadline = tokio::time::Instant::now() + duration_from_secs(timeout_secs);
+        let mut reported: Option<(u32, Vec<u32>, String)> = None;
+        loop {
+            let health = self.health().await;
+            let state = (
+                health.peer_count,
+                health.bucket_fill.clone(),
+                health.bootstrap_status.clone(),
+            );
+            if let Some(listener) = &listener
+                && reported.as_ref() != Some(&state)
+            {
+                listener.on_progress(health.clone());
+                reported = Some(state);
+            }
 
-        sleep(Duration::from_secs(1)).await;
-        Ok(())
+            if criteria.is_met(&health) {
+                return Ok(health);
+            }
+            if health.peer_count == 0
+                && health.bootstrap_status == BootstrapStatus::Unreachable.to_string()
+            {
+                return Err(RhizomeError::Network(NetworkError::BootstrapError));
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(RhizomeError::Network(NetworkError::JoinTimeout));
+            }
+            sleep(Duration::from_millis(JOIN_CHECK_INTERVAL)).await;
+        }
     }
 
     pub async fn stop(&self) -> Result<(), RhizomeError> {
@@ -162,6 +788,8 @@ impl RhizomeClient {
         creator_pubkey: Option<String>,
         ttl: i32,
     ) -> Result<ThreadMetadataBridge, RhizomeError> {
+        self.write_pacer.acquire().await?;
+        let budget = self.query_options.budget();
         let inner = self.inner.read().await;
         let node = inner
             .node
@@ -181,26 +809,23 @@ impl RhizomeClient {
             message_count: 0,
             last_activity: get_now_i64(),
             popularity_score: 0.0,
+            archive_key: None,
+            recent_messages: Vec::new(),
+            version: VectorClock::default(),
+            last_message_hash: None,
         };
 
         let meta_key = inner.key_manager.get_thread_meta_key(&thread_id);
         let meta_data =
-            serialize(&thread_meta, "msgpack").map_err(|_| RhizomeError::Dht(DHTError::General))?;
-        node.store(&meta_key, &meta_data, ttl).await?;
+            to_msgpack(&thread_meta).map_err(|_| RhizomeError::Dht(DHTError::General))?;
+        node.store_record_with_budget(&meta_key, &meta_data, ttl, RECORD_THREAD, &budget)
+            .await?;
+        inner.thread_cache.lock().await.invalidate(&thread_id);
+        Self::remember_thread(&inner, &thread_meta).await;
 
         // Обновление индекса
-        let threads_key = inner.key_manager.get_global_threads_key();
-        let mut thread_list: Vec<String> = match node.find_value(&threads_key).await {
-            Ok(data) => deserialize(&data, "msgpack").unwrap_or_default(),
-            Err(_) => Vec::new(),
-        };
-
-        if !thread_list.contains(&thread_id) {
-            thread_list.push(thread_id);
-            let list_data = serialize(&thread_list, "msgpack")
-                .map_err(|_| RhizomeError::Dht(DHTError::General))?;
-            node.store(&threads_key, &list_data, 86400).await?;
-        }
+        Self::add_to_global_index(&inner, node, thread_id.clone(), &budget).await?;
+        node.events.emit(NodeEvent::ThreadCreated { thread_id });
 
         Ok(thread_meta)
     }
@@ -214,6 +839,8 @@ impl RhizomeClient {
         content_type: String,
         ttl: i32,
     ) -> Result<MessageBridge, RhizomeError> {
+        self.write_pacer.acquire().await?;
+        let budget = self.query_options.budget();
         let inner = self.inner.read().await;
         let node = inner
             .node
@@ -223,77 +850,2181 @@ impl RhizomeClient {
         let timestamp = get_now_i64();
         let message_id = format!("msg_{}_{}", thread_id, timestamp);
 
-        let signature = author_signature.unwrap_or_else(|| {
-            format!("sig_{}", hex::encode(&hash_key(message_id.as_bytes())[..8]))
-        });
+        let meta_key = inner.key_manager.get_thread_meta_key(&thread_id);
+        let meta = match node.find_value_with_budget(&meta_key, &budget).await {
+            Ok(found) => from_msgpack::<ThreadMetadataBridge>(&found.value)
+                .ok()
+                .map(|meta| (found.value, meta)),
+            Err(_) => None,
+        };
 
-        let message = MessageBridge {
+        #[cfg_attr(not(feature = "signatures"), allow(unused_mut))]
+        let mut message = MessageBridge {
             id: message_id.clone(),
             thread_id: thread_id.clone(),
             parent_id,
             content,
-            author_signature: Some(signature),
+            author_signature,
             timestamp,
             content_type,
             attachments: vec![],
+            content_parts: vec![],
+            prev_hash: meta
+                .as_ref()
+                .and_then(|(_, meta)| meta.last_message_hash.clone()),
+            author_key: None,
         };
+        // Signature of the node covers the link, so the history can not be rewritten
+        #[cfg(feature = "signatures")]
+        if message.author_signature.is_none() {
+            message.sign(&node.signer);
+        }
+        let message_hash = message.chain_hash();
+        let message = Self::store_message(&inner, node, message, ttl, &budget).await?;
 
-        let message_hash = hex::encode(&hash_key(message_id.as_bytes())[..8]);
-        let message_key = inner.key_manager.get_message_key(&message_hash);
-        let message_data =
-            serialize(&message, "msgpack").map_err(|_| RhizomeError::Dht(DHTError::General))?;
-
-        node.store(&message_key, &message_data, ttl).await?;
+        if let Some((found, _)) = meta {
+            let _update = inner.thread_update_lock.lock().await;
+            Self::update_thread(node, &meta_key, &found, ttl, timestamp, &message_hash).await?;
+        }
+        inner.thread_cache.lock().await.invalidate(&thread_id);
+        inner
+            .message_cache
+            .lock()
+            .await
+            .insert(message_id, message.clone());
+        Self::remember_message(&inner, &message).await;
 
-        // Здесь мы бы вызвали update_thread, но для краткости опустим (логика аналогична)
         Ok(message)
     }
 
-    // Для API используем String (JSON), так как UniFFI не поддерживает динамический Value
-    pub async fn get_popular_threads_json(&self, limit: u32) -> Result<String, RhizomeError> {
+    /// Find thread metadata by id
+    ///
+    /// Found threads are cached for `storage.client_cache_ttl` seconds, local writes to
+    /// the thread drop its cached value.
+    pub async fn find_thread(
+        &self,
+        thread_id: String,
+    ) -> Result<Option<ThreadMetadataBridge>, RhizomeError> {
         let inner = self.inner.read().await;
+        if let Some(thread) = inner.thread_cache.lock().await.get(&thread_id) {
+            return Ok(Some(thread));
+        }
+
         let node = inner
             .node
             .as_ref()
             .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
 
-        let all_metrics = node
-            .metrics_collector
-            .read()
-            .await
-            .get_all_metrics()
-            .clone();
-        let ranked = node
-            .popularity_ranker
-            .rank_items(&all_metrics, Some(limit as usize));
-
-        let result = serde_json::json!(
-            ranked
-                .iter()
-                .map(|item| {
-                    serde_json::json!({
-                        "key": hex::encode(&item.key),
-                        "score": item.score
-                    })
-                })
-                .collect::<Vec<_>>()
+        let meta_key = inner.key_manager.get_thread_meta_key(&thread_id);
+        let budget = self.query_options.budget();
+        let (found, bundle) = tokio::join!(
+            node.find_value_with_budget(&meta_key, &budget),
+            Self::find_thread_bundle(&inner, node, &thread_id),
         );
+        let mut thread = match found {
+            Ok(found) => from_msgpack::<ThreadMetadataBridge>(&found.value).ok(),
+            Err(RhizomeError::Dht(DHTError::ValueNotFound)) => None,
+            Err(e) if bundle.is_none() => return Err(e),
+            Err(_) => None,
+        };
+        if let Some(bundle) = bundle {
+            thread = Self::unpack_thread_bundle(&inner, thread, bundle).await;
+        }
 
-        Ok(result.to_string())
+        if let Some(thread) = &thread {
+            inner
+                .thread_cache
+                .lock()
+                .await
+                .insert(thread_id, thread.clone());
+            Self::remember_thread(&inner, thread).await;
+        }
+        Ok(thread)
     }
 
-    pub async fn get_node_info_json(&self) -> String {
+    /// New message counts and last activity of several threads at once
+    ///
+    /// Threads which are not cached are fetched by one batched lookup. Threads which
+    /// were not found are left out of the result.
+    pub async fn get_thread_digest(
+        &self,
+        thread_ids: Vec<String>,
+        since: i64,
+    ) -> Result<Vec<ThreadDigestBridge>, RhizomeError> {
         let inner = self.inner.read().await;
-        match &inner.node {
-            Some(node) => {
-                serde_json::json!({
-                    "node_id": hex::encode(node.node_id.0),
-                    "node_type": format!("{:?}", node.node_type),
-                    "is_running": inner.is_running,
-                    "address": format!("{}:{}", inner.config.network.listen_host, inner.config.network.listen_port),
-                }).to_string()
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let mut threads: HashMap<String, ThreadMetadataBridge> = HashMap::new();
+        {
+            let mut cache = inner.thread_cache.lock().await;
+            for thread_id in &thread_ids {
+                if let Some(thread) = cache.get(thread_id) {
+                    threads.insert(thread_id.clone(), thread);
+                }
             }
-            None => serde_json::json!({"status": "not_initialized"}).to_string(),
         }
+
+        let missing: Vec<(String, Key)> = thread_ids
+            .iter()
+            .filter(|thread_id| !threads.contains_key(*thread_id))
+            .map(|thread_id| {
+                (
+                    thread_id.clone(),
+                    inner.key_manager.get_thread_meta_key(thread_id),
+                )
+            })
+            .collect();
+        if !missing.is_empty() {
+            let keys: Vec<Key> = missing.iter().map(|(_, key)| key.clone()).collect();
+            let found = node.find_values(&keys).await?;
+            for (thread_id, key) in missing {
+                if let Some(thread) = found
+                    .get(&key)
+                    .and_then(|data| from_msgpack::<ThreadMetadataBridge>(data).ok())
+                {
+                    inner
+                        .thread_cache
+                        .lock()
+                        .await
+                        .insert(thread_id.clone(), thread.clone());
+                    Self::remember_thread(&inner, &thread).await;
+                    threads.insert(thread_id, thread);
+                }
+            }
+        }
+
+        Ok(thread_ids
+            .iter()
+            .filter_map(|thread_id| threads.get(thread_id))
+            .map(|thread| thread.digest(since))
+            .collect())
+    }
+
+    /// Warm caches with threads and messages which the app is going to show
+    ///
+    /// Return at once, lookups go in background by small batches with pauses, so other
+    /// calls are not delayed much. Found items get into the lookup cache and the client
+    /// store, cached items are skipped. Prefetch is not counted as interest in popularity.
+    /// Found values are not put into our storage, batch answers do not carry the proof of
+    /// their publisher. Items which another prefetch looks up are skipped.
+    pub async fn prefetch(
+        &self,
+        mut thread_ids: Vec<String>,
+        mut message_ids: Vec<String>,
+    ) -> Result<(), RhizomeError> {
+        let inner = self.inner.read().await;
+        if inner.node.is_none() {
+            return Err(RhizomeError::Dht(DHTError::NodeNotFound));
+        }
+        let mut keys = Vec::new();
+        {
+            let mut prefetching = inner.prefetching.lock().await;
+            thread_ids.retain(|thread_id| {
+                let key = inner.key_manager.get_thread_meta_key(thread_id);
+                let fresh = prefetching.insert(key.clone());
+                if fresh {
+                    keys.push(key);
+                }
+                fresh
+            });
+            message_ids.retain(|message_id| {
+                let message_hash = hex::encode(&hash_key(message_id.as_bytes())[..8]);
+                let key = inner.key_manager.get_message_key(&message_hash);
+                let fresh = prefetching.insert(key.clone());
+                if fresh {
+                    keys.push(key);
+                }
+                fresh
+            });
+        }
+        drop(inner);
+        task::spawn(Self::run_prefetch(
+            self.inner.clone(),
+            thread_ids,
+            message_ids,
+            keys,
+        ));
+        Ok(())
+    }
+
+    /// Find message by id in the network together with the node which supplied it
+    ///
+    /// Cache is not used, so the source is always known.
+    pub async fn find_message_with_meta(
+        &self,
+        message_id: String,
+    ) -> Result<Option<MessageWithMetaBridge>, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let message_hash = hex::encode(&hash_key(message_id.as_bytes())[..8]);
+        let message_key = inner.key_manager.get_message_key(&message_hash);
+        let budget = self.query_options.budget();
+        let found = match node.find_value_with_budget(&message_key, &budget).await {
+            Ok(found) => found,
+            Err(RhizomeError::Dht(DHTError::ValueNotFound)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let Ok(message) = from_msgpack::<MessageBridge>(&found.value) else {
+            return Ok(None);
+        };
+
+        let Some(message) = Self::assemble_message(&inner.key_manager, node, message).await else {
+            return Ok(None);
+        };
+        Self::remember_message(&inner, &message).await;
+        Ok(Some(MessageWithMetaBridge {
+            message,
+            meta: ValueMetaBridge::from(&found),
+        }))
+    }
+
+    /// Find message by id, found messages are cached like threads
+    ///
+    /// Messages never change, so the one from the client store is returned without lookup,
+    /// unless our node knows that the message was deleted.
+    pub async fn find_message(
+        &self,
+        message_id: String,
+    ) -> Result<Option<MessageBridge>, RhizomeError> {
+        let inner = self.inner.read().await;
+        let message_hash = hex::encode(&hash_key(message_id.as_bytes())[..8]);
+        let message_key = inner.key_manager.get_message_key(&message_hash);
+        if let Some(node) = &inner.node
+            && node
+                .storage
+                .get_tombstone(message_key.clone())
+                .await?
+                .is_some()
+        {
+            inner.message_cache.lock().await.invalidate(&message_id);
+            if let Some(store) = &inner.client_store {
+                store.remove_message(&message_id).await?;
+            }
+            return Ok(None);
+        }
+        if let Some(message) = inner.message_cache.lock().await.get(&message_id) {
+            return Ok(Some(message));
+        }
+        if let Some(store) = &inner.client_store
+            && let Some(data) = store.message(&message_id).await?
+            && let Ok(message) = from_msgpack::<MessageBridge>(&data)
+        {
+            return Ok(Some(message));
+        }
+
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        let budget = self.query_options.budget();
+        let message = match node.find_value_with_budget(&message_key, &budget).await {
+            Ok(found) => from_msgpack::<MessageBridge>(&found.value).ok(),
+            Err(RhizomeError::Dht(DHTError::ValueNotFound)) => None,
+            Err(e) => return Err(e),
+        };
+        let message = match message {
+            Some(message) => Self::assemble_message(&inner.key_manager, node, message).await,
+            None => None,
+        };
+
+        if let Some(message) = &message {
+            inner
+                .message_cache
+                .lock()
+                .await
+                .insert(message_id, message.clone());
+            Self::remember_message(&inner, message).await;
+        }
+        Ok(message)
+    }
+
+    /// Threads from the client store, the latest active first
+    ///
+    /// Does not need running node, so the app can render them at once after restart.
+    /// Empty if `storage.client_store_dir` is not set.
+    pub async fn get_cached_threads(&self) -> Result<Vec<CachedThreadBridge>, RhizomeError> {
+        let inner = self.inner.read().await;
+        let Some(store) = &inner.client_store else {
+            return Ok(Vec::new());
+        };
+
+        let mut threads: Vec<CachedThreadBridge> = store
+            .threads()
+            .await?
+            .into_iter()
+            .filter_map(|(data, synced_at)| {
+                Some(CachedThreadBridge {
+                    thread: from_msgpack(&data).ok()?,
+                    synced_at: synced_at as i64,
+                })
+            })
+            .collect();
+        threads.sort_by_key(|cached| std::cmp::Reverse(cached.thread.last_activity));
+        Ok(threads)
+    }
+
+    /// Messages of the thread from the client store, the oldest first
+    pub async fn get_cached_messages(
+        &self,
+        thread_id: String,
+    ) -> Result<Vec<MessageBridge>, RhizomeError> {
+        let inner = self.inner.read().await;
+        let Some(store) = &inner.client_store else {
+            return Ok(Vec::new());
+        };
+
+        let mut messages: Vec<MessageBridge> = store
+            .thread_messages(&thread_id)
+            .await?
+            .iter()
+            .filter_map(|data| from_msgpack(data).ok())
+            .collect();
+        messages.sort_by_key(|m| m.timestamp);
+        Ok(messages)
+    }
+
+    /// Check that no message was removed, moved or forged in the thread history
+    ///
+    /// Messages are expected in order of the thread, the oldest first _(as returned by
+    /// [`RhizomeClient::get_cached_messages`])_. `last_message_hash` of the thread checks
+    /// that the tail of the history was not cut. Returns messages whose link to the
+    /// previous message or signature is broken, empty list for intact history.
+    pub fn verify_message_history(
+        &self,
+        messages: Vec<MessageBridge>,
+        last_message_hash: Option<String>,
+    ) -> Vec<ChainIssue> {
+        verify_chain(&messages, last_message_hash.as_deref())
+    }
+
+    /// Remove all threads and messages from the client store
+    pub async fn clear_client_store(&self) -> Result<(), RhizomeError> {
+        let inner = self.inner.read().await;
+        if let Some(store) = &inner.client_store {
+            store.clear().await?;
+        }
+        Ok(())
+    }
+
+    /// Security events from the audit log in JSON format, the newest first
+    ///
+    /// `kind` is one of `signature_failure`, `rate_limit_exceeded`, `puzzle_failed`,
+    /// `blacklist_added`, `blacklist_removed`, `store_rejected`, `access_denied`
+    /// _(all kinds if None)_
+    pub async fn get_security_audit_json(
+        &self,
+        admin_token: String,
+        kind: Option<String>,
+        max_age_seconds: u32,
+        limit: u32,
+    ) -> Result<String, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::ReadOnly)?;
+
+        let kind = match kind {
+            Some(name) => Some(
+                AuditEventKind::parse(&name)
+                    .ok_or(RhizomeError::Security(SecurityError::General))?,
+            ),
+            None => None,
+        };
+        let since = get_now_i64() as f64 - max_age_seconds as f64;
+        let records = node.audit.query(kind, since, limit as usize);
+        serde_json::to_string(&records).map_err(|_| RhizomeError::Security(SecurityError::General))
+    }
+
+    /// Check the token of an admin API request, return its role
+    ///
+    /// Applications which expose the admin API call it before every request with the role
+    /// the request needs, tokens of lower roles and unknown tokens get `AccessDenied`.
+    /// Privileged methods _(audit, blacklists, identity)_ take the token and check it
+    /// themselves.
+    pub async fn authorize_admin(
+        &self,
+        token: String,
+        required: AdminRole,
+    ) -> Result<AdminRole, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        Ok(node.admin_tokens.authorize(&token, required)?)
+    }
+
+    /// Drop all messages of the peer with hex `node_id` for `seconds`
+    pub async fn blacklist_peer(
+        &self,
+        admin_token: String,
+        node_id: String,
+        seconds: u32,
+        reason: String,
+    ) -> Result<(), RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+        let peer_id =
+            hex::decode(&node_id).map_err(|_| RhizomeError::Security(SecurityError::General))?;
+
+        node.network_protocol
+            .blacklist
+            .add(&peer_id, seconds as f64, &reason);
+        Ok(())
+    }
+
+    /// Remove the peer from the blacklist, return `true` if it was there
+    pub async fn unblacklist_peer(
+        &self,
+        admin_token: String,
+        node_id: String,
+    ) -> Result<bool, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+        let peer_id =
+            hex::decode(&node_id).map_err(|_| RhizomeError::Security(SecurityError::General))?;
+
+        Ok(node.network_protocol.blacklist.remove(&peer_id))
+    }
+
+    /// Drop all packets from addresses of the `subnet` _(exm. `203.0.113.0/24`)_ for `seconds`
+    pub async fn blacklist_subnet(
+        &self,
+        admin_token: String,
+        subnet: String,
+        seconds: u32,
+        reason: String,
+    ) -> Result<(), RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+        let subnet =
+            Subnet::parse(&subnet).ok_or(RhizomeError::Security(SecurityError::General))?;
+
+        node.network_protocol
+            .blacklist
+            .add_subnet(subnet, seconds as f64, &reason);
+        Ok(())
+    }
+
+    /// Remove the subnet from the blacklist, return `true` if it was there
+    pub async fn unblacklist_subnet(
+        &self,
+        admin_token: String,
+        subnet: String,
+    ) -> Result<bool, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+        let subnet =
+            Subnet::parse(&subnet).ok_or(RhizomeError::Security(SecurityError::General))?;
+
+        Ok(node.network_protocol.blacklist.remove_subnet(&subnet))
+    }
+
+    /// Refuse records published by the node with hex `publisher_id` for `seconds`
+    pub async fn blacklist_publisher(
+        &self,
+        admin_token: String,
+        publisher_id: String,
+        seconds: u32,
+        reason: String,
+    ) -> Result<(), RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+        let publisher_id = hex::decode(&publisher_id)
+            .map_err(|_| RhizomeError::Security(SecurityError::General))?;
+
+        node.network_protocol
+            .blacklist
+            .add_publisher(&publisher_id, seconds as f64, &reason);
+        Ok(())
+    }
+
+    /// Accept records of the publisher again, return `true` if it was blocked
+    pub async fn unblacklist_publisher(
+        &self,
+        admin_token: String,
+        publisher_id: String,
+    ) -> Result<bool, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+        let publisher_id = hex::decode(&publisher_id)
+            .map_err(|_| RhizomeError::Security(SecurityError::General))?;
+
+        Ok(node
+            .network_protocol
+            .blacklist
+            .remove_publisher(&publisher_id))
+    }
+
+    /// Reputation of the peer with hex `node_id` with its score as JSON
+    ///
+    /// Peer without history gets `null` statistics and neutral score.
+    pub async fn get_peer_reputation_json(&self, node_id: String) -> Result<String, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        let peer_id =
+            hex::decode(&node_id).map_err(|_| RhizomeError::Security(SecurityError::General))?;
+
+        let reputation = &node.network_protocol.reputation;
+        Ok(serde_json::json!({
+            "score": reputation.score(&peer_id),
+            "reputable": reputation.is_reputable(&peer_id),
+            "stats": reputation.get(&peer_id),
+        })
+        .to_string())
+    }
+
+    /// Traffic counters of all known peers as JSON object by hex node ID
+    ///
+    /// Counters contain requests in both directions, unanswered requests, bytes and last RTT.
+    pub async fn get_peer_stats(&self) -> Result<String, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        Ok(serde_json::json!(node.network_protocol.peer_stats.all()).to_string())
+    }
+
+    /// Drop all cached threads and messages
+    pub async fn clear_cache(&self) {
+        let inner = self.inner.read().await;
+        inner.thread_cache.lock().await.clear();
+        inner.message_cache.lock().await.clear();
+    }
+
+    /// Threads published during last `max_age_seconds` found by crawling the network
+    ///
+    /// Does not need global index key, every visited node gives threads it stores.
+    pub async fn crawl_recent_threads_json(
+        &self,
+        max_age_seconds: u32,
+        limit: u32,
+    ) -> Result<String, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let since = get_now_i64() as f64 - max_age_seconds as f64;
+        let limit = limit as usize;
+        let mut threads: Vec<ThreadMetadataBridge> = Vec::new();
+
+        node.dht_protocol
+            .iterate_closest(&node.node_id, RECORD_THREAD, since, 64, |record| {
+                if let Ok(thread) = from_msgpack::<ThreadMetadataBridge>(&record.value) {
+                    threads.push(thread);
+                }
+                threads.len() < limit
+            })
+            .await?;
+
+        threads.sort_by_key(|t| std::cmp::Reverse(t.created_at));
+        serde_json::to_string(&threads).map_err(|_| RhizomeError::Dht(DHTError::General))
+    }
+
+    pub async fn get_node_info_json(&self) -> String {
+        let inner = self.inner.read().await;
+        match &inner.node {
+            Some(node) => {
+                serde_json::json!({
+                    "node_id": node.node_id.to_string(),
+                    "node_type": format!("{:?}", node.node_type),
+                    "is_running": inner.is_running,
+                    "status": node.status(),
+                    "mode": node.mode.get(),
+                    "bootstrap": node.bootstrap_status(),
+                    "address": node.transport.get_address().await.to_string(),
+                    "external_address": node.network_protocol.external_address.get().map(|a| a.to_string()),
+                    "software": PeerSoftware::local(),
+                    "peer_versions": node.network_protocol.peer_stats.versions(),
+                    "health": {
+                        "status": if node.supervisor.is_healthy() && !node.disk_watchdog.is_degraded() { "ok" } else { "degraded" },
+                        "tasks": node.supervisor.loops(),
+                        "disk": node.disk_watchdog.status(),
+                    },
+                    "storage": node.storage.stats(),
+                    "top_publishers": node.storage.quotas().top(10),
+                    "port_mapping": node.port_mapping().await,
+                }).to_string()
+            }
+            None => serde_json::json!({"status": "not_initialized"}).to_string(),
+        }
+    }
+
+    /// Connection state of the node: peers, lookups, storage and bootstrap
+    pub async fn health(&self) -> HealthBridge {
+        let inner = self.inner.read().await;
+        let Some(node) = &inner.node else {
+            return HealthBridge {
+                bootstrap_status: BootstrapStatus::NotStarted.to_string(),
+                ..HealthBridge::default()
+            };
+        };
+
+        let (bucket_fill, bucket_size) = {
+            let rt = node.routing_table.read().await;
+            let fill: Vec<u32> = rt.buckets.iter().map(|b| b.nodes.len() as u32).collect();
+            (fill, rt.k as u32)
+        };
+        HealthBridge {
+            is_running: inner.is_running,
+            peer_count: bucket_fill.iter().sum(),
+            bucket_fill,
+            bucket_size,
+            last_lookup_at: node.dht_protocol.last_lookup_at(),
+            storage_headroom: node
+                .config
+                .storage
+                .max_storage_size
+                .saturating_sub(node.storage.stats().bytes_used),
+            bootstrap_status: node.bootstrap_status().to_string(),
+        }
+    }
+}
+
+/// Signed thread bundles
+#[cfg(feature = "signatures")]
+#[uniffi::export]
+impl RhizomeClient {
+    /// Export thread metadata with all found messages as one signed msgpack bundle
+    pub async fn export_thread(&self, thread_id: String) -> Result<Vec<u8>, RhizomeError> {
+        let thread = self
+            .find_thread(thread_id.clone())
+            .await?
+            .ok_or(RhizomeError::Dht(DHTError::ValueNotFound))?;
+
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let messages = Self::collect_thread_messages(&inner, node, &thread).await?;
+
+        let exported_at = get_now_i64();
+        let signed = ThreadBundle::signed_bytes(&thread, &messages, exported_at)?;
+        let bundle = ThreadBundle {
+            signature: node.signer.sign(&signed),
+            signer_id: node.signer.node_id().to_vec(),
+            public_key: node.signer.public_key_der().to_vec(),
+            thread,
+            messages,
+            exported_at,
+        };
+
+        to_msgpack(&bundle).map_err(|_| RhizomeError::Dht(DHTError::General))
+    }
+
+    /// Verify bundle made by [`RhizomeClient::export_thread`] and publish its thread and
+    /// messages from our node
+    ///
+    /// `author_id` is the hex node ID of the node which exported the bundle, it must be
+    /// known from elsewhere. Key embedded in the bundle proves nothing by itself, so it
+    /// must belong to this node. Messages signed by their authors must carry valid
+    /// signatures. Messages of other threads in the bundle are ignored.
+    pub async fn import_thread(
+        &self,
+        bundle: Vec<u8>,
+        author_id: String,
+    ) -> Result<ThreadMetadataBridge, RhizomeError> {
+        let author_id = hex::decode(author_id.trim())
+            .map_err(|_| RhizomeError::Security(SecurityError::General))?;
+        let bundle: ThreadBundle =
+            from_msgpack(&bundle).map_err(|_| RhizomeError::Security(SecurityError::General))?;
+        let signed =
+            ThreadBundle::signed_bytes(&bundle.thread, &bundle.messages, bundle.exported_at)?;
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        if bundle.signer_id != author_id
+            || node_id_from_public_key_der(&bundle.public_key)[..] != author_id[..]
+        {
+            node.audit.record(
+                AuditEventKind::AccessDenied,
+                Some(&bundle.signer_id),
+                None,
+                format!("thread bundle for {} of other author", bundle.thread.id),
+            );
+            return Err(RhizomeError::Security(SecurityError::AccessDenied));
+        }
+        if !verify_signature(&bundle.public_key, &signed, &bundle.signature)
+            || !bundle.messages.iter().all(|m| m.author_verified())
+        {
+            node.audit.record(
+                AuditEventKind::SignatureFailure,
+                Some(&bundle.signer_id),
+                None,
+                format!("invalid thread bundle for {}", bundle.thread.id),
+            );
+            return Err(RhizomeError::Security(SecurityError::InvalidSignature));
+        }
+        let ttl = inner.config.storage.default_ttl;
+        let thread = bundle.thread;
+
+        let meta_key = inner.key_manager.get_thread_meta_key(&thread.id);
+        let meta_data = to_msgpack(&thread).map_err(|_| RhizomeError::Dht(DHTError::General))?;
+        let budget = self.query_options.budget();
+        node.store_record_with_budget(&meta_key, &meta_data, ttl, RECORD_THREAD, &budget)
+            .await?;
+
+        let mut message_cache = inner.message_cache.lock().await;
+        for message in bundle
+            .messages
+            .into_iter()
+            .filter(|m| m.thread_id == thread.id)
+        {
+            message_cache.invalidate(&message.id);
+            Self::store_message(&inner, node, message, ttl, &budget).await?;
+        }
+        drop(message_cache);
+        inner.thread_cache.lock().await.invalidate(&thread.id);
+
+        Self::add_to_global_index(&inner, node, thread.id.clone(), &budget).await?;
+        Ok(thread)
+    }
+
+    /// Export blocked node IDs, subnets and publishers as one signed msgpack file
+    ///
+    /// Entries of the subscribed blocklist are not exported.
+    pub async fn export_blocklist(&self) -> Result<Vec<u8>, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        SignedBlocklist::issue(node.network_protocol.blacklist.export(), &node.signer)
+            .and_then(|signed| signed.to_bytes())
+            .ok_or(RhizomeError::Security(SecurityError::General))
+    }
+
+    /// Verify file made by [`RhizomeClient::export_blocklist`] and add its active entries to
+    /// the blacklist, return count of them
+    ///
+    /// Only files of our node, of the subscribed author and of `security.blocklist_authors`
+    /// are imported.
+    pub async fn import_blocklist(
+        &self,
+        admin_token: String,
+        blocklist: Vec<u8>,
+    ) -> Result<u32, RhizomeError> {
+        let signed = SignedBlocklist::from_bytes(&blocklist)
+            .ok_or(RhizomeError::Security(SecurityError::General))?;
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+
+        if !signed.verify() {
+            node.audit.record(
+                AuditEventKind::SignatureFailure,
+                Some(&signed.signer_id),
+                None,
+                "invalid imported blocklist",
+            );
+            return Err(RhizomeError::Security(SecurityError::InvalidSignature));
+        }
+        let source = hex::encode(&signed.signer_id);
+        let security = &inner.config.security;
+        let trusted = signed.signer_id[..] == node.signer.node_id()[..]
+            || security
+                .blocklist_subscription
+                .iter()
+                .chain(&security.blocklist_authors)
+                .any(|author| author.trim().eq_ignore_ascii_case(&source));
+        if !trusted {
+            node.audit.record(
+                AuditEventKind::AccessDenied,
+                Some(&signed.signer_id),
+                None,
+                "blocklist of untrusted author",
+            );
+            return Err(RhizomeError::Security(SecurityError::AccessDenied));
+        }
+        let count = node
+            .network_protocol
+            .blacklist
+            .import(&signed.list, &source);
+        Ok(count as u32)
+    }
+
+    /// Publish our blacklist signed in the DHT for nodes subscribed to our node ID
+    ///
+    /// Subscribers publish the list again on their refreshes, so it lives while they do.
+    pub async fn publish_blocklist(&self, admin_token: String) -> Result<(), RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+
+        let data = SignedBlocklist::issue(node.network_protocol.blacklist.export(), &node.signer)
+            .and_then(|signed| signed.to_bytes())
+            .ok_or(RhizomeError::Security(SecurityError::General))?;
+        let key = inner
+            .key_manager
+            .get_blocklist_key(&hex::encode(node.signer.node_id()));
+        let ttl = inner.config.security.blocklist_ttl();
+        let budget = self.query_options.budget();
+        node.store_record_with_budget(&key, &data, ttl, RECORD_BLOCKLIST, &budget)
+            .await?;
+        Ok(())
+    }
+
+    /// Generate new identity of the node and announce it to peers
+    ///
+    /// Return hex of the new node ID, it is used after restart of the client.
+    pub async fn rotate_identity(&self, admin_token: String) -> Result<String, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+        let report = node.rotate_identity().await?;
+        Ok(hex::encode(report.link.new_id))
+    }
+}
+
+/// Direct messages to online nodes and through mailboxes in the DHT
+#[cfg(feature = "mailbox")]
+#[uniffi::export]
+impl RhizomeClient {
+    /// Hex DER of the public key of our node, other users send direct messages to it
+    pub async fn get_public_key(&self) -> Result<String, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        Ok(hex::encode(node.signer.public_key_der()))
+    }
+
+    /// Encrypt `data` for the owner of hex DER `recipient_public_key` and send it directly
+    ///
+    /// Online recipient gets the message in `MSG_DIRECT` and confirms delivery with signed
+    /// receipt. If it does not answer, the message is put into the mailbox of the recipient.
+    /// Content over `MAX_MAILBOX_CONTENT` bytes is rejected with `ValueTooLarge`.
+    pub async fn send_direct(
+        &self,
+        recipient_public_key: String,
+        data: Vec<u8>,
+    ) -> Result<DirectDeliveryBridge, RhizomeError> {
+        if data.len() > MAX_MAILBOX_CONTENT {
+            return Err(RhizomeError::Storage(StorageError::ValueTooLarge));
+        }
+        let recipient_key = hex::decode(&recipient_public_key)
+            .map_err(|_| RhizomeError::Security(SecurityError::General))?;
+
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let envelope = seal_message(&node.signer, &recipient_key, &data, get_now_i64())
+            .ok_or(RhizomeError::Security(SecurityError::General))?;
+        if let Some(recipient) = Self::find_recipient(node, &recipient_key).await
+            && node
+                .network_protocol
+                .send_direct(&envelope, &recipient_key, &recipient)
+                .await
+                .unwrap_or(false)
+        {
+            return Ok(DirectDeliveryBridge {
+                id: envelope.id,
+                delivered: true,
+            });
+        }
+
+        Self::put_in_mailbox(&inner, node, &recipient_key, &envelope).await?;
+        Ok(DirectDeliveryBridge {
+            id: envelope.id,
+            delivered: false,
+        })
+    }
+
+    /// Encrypt message for the owner of hex DER `recipient_public_key` and put it into
+    /// the mailbox of the recipient
+    ///
+    /// Recipient does not need to be online. Return ID of the message.
+    pub async fn send_direct_message(
+        &self,
+        recipient_public_key: String,
+        content: String,
+    ) -> Result<String, RhizomeError> {
+        if content.len() > MAX_MAILBOX_CONTENT {
+            return Err(RhizomeError::Storage(StorageError::ValueTooLarge));
+        }
+        let recipient_key = hex::decode(&recipient_public_key)
+            .map_err(|_| RhizomeError::Security(SecurityError::General))?;
+
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let envelope = seal_message(
+            &node.signer,
+            &recipient_key,
+            content.as_bytes(),
+            get_now_i64(),
+        )
+        .ok_or(RhizomeError::Security(SecurityError::General))?;
+        Self::put_in_mailbox(&inner, node, &recipient_key, &envelope).await?;
+        Ok(envelope.id)
+    }
+
+    /// Fetch direct messages received by our node and from our mailbox, fetched messages
+    /// are removed from the mailbox
+    ///
+    /// Messages which we can not decrypt or whose signature is not valid are removed too.
+    pub async fn fetch_mailbox(&self) -> Result<Vec<DirectMessageBridge>, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let mut messages: Vec<DirectMessageBridge> = node
+            .network_protocol
+            .direct_inbox
+            .take_all()
+            .await?
+            .iter()
+            .filter_map(|envelope| open_message(&node.signer, envelope))
+            .map(DirectMessageBridge::from)
+            .collect();
+
+        let key = mailbox_key(&inner.key_manager, node.signer.public_key_der());
+        let envelopes = match node.find_value(&key).await {
+            Ok(data) => decode_mailbox(Some(&data)),
+            Err(RhizomeError::Dht(DHTError::ValueNotFound)) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        if !envelopes.is_empty() {
+            for message in envelopes
+                .iter()
+                .filter_map(|envelope| open_message(&node.signer, envelope))
+            {
+                // Delivery receipt may be lost, so the sender put the message into mailbox too
+                if !messages.iter().any(|m| m.id == message.id) {
+                    messages.push(message.into());
+                }
+            }
+
+            let fetched: Vec<String> = envelopes.into_iter().map(|e| e.id).collect();
+            node.update_value(
+                &key,
+                inner.config.storage.mailbox_ttl,
+                RECORD_MAILBOX,
+                |current| remove_from_mailbox(current, &fetched),
+            )
+            .await?;
+        }
+
+        messages.sort_by_key(|m| m.sent_at);
+        Ok(messages)
+    }
+}
+
+/// Reports and rankings of the content popularity
+#[cfg(feature = "popularity")]
+#[uniffi::export]
+impl RhizomeClient {
+    /// Archive threads without activity during `storage.archive_after_days`
+    ///
+    /// Only threads whose metadata our node published are archived, metadata of other
+    /// authors is not changed. Messages of every such thread are bundled and stored on
+    /// seeds which volunteered to keep archives, thread metadata gets the archive key and
+    /// local copies of the messages are removed only after `storage.archive_replicas`
+    /// seeds signed receipts of every chunk of the archive. Return count of archived
+    /// threads.
+    pub async fn archive_inactive_threads(&self) -> Result<u32, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let seeds = Self::archive_seeds(node).await;
+        if seeds.len() < inner.config.storage.archive_replicas.max(1) {
+            return Ok(0);
+        }
+
+        let inactive_since = get_now_i64() - inner.config.storage.archive_after_days as i64 * 86400;
+        let mut archived = 0;
+        let mut cursor = None;
+        loop {
+            let page = node
+                .storage
+                .records_by_type_page(RECORD_THREAD.to_string(), 0.0, cursor, ARCHIVE_SCAN_PAGE)
+                .await?;
+            for record in page.records {
+                if !published_by(&record, &node.node_id.0) {
+                    continue;
+                }
+                let Ok(thread) = from_msgpack::<ThreadMetadataBridge>(&record.value) else {
+                    continue;
+                };
+                if thread.archive_key.is_some() || thread.last_activity > inactive_since {
+                    continue;
+                }
+                if Self::archive_thread(&inner, node, thread, &seeds).await? {
+                    archived += 1;
+                }
+            }
+            cursor = match page.next {
+                Some(next) => Some(next),
+                None => break,
+            };
+        }
+        Ok(archived)
+    }
+
+    /// Messages of the archived thread from its archive on seed nodes
+    ///
+    /// Archive is read from the seeds which keep archives, the same nodes it was stored on.
+    pub async fn find_archived_messages(
+        &self,
+        thread_id: String,
+    ) -> Result<Vec<MessageBridge>, RhizomeError> {
+        let archive_key = self
+            .find_thread(thread_id.clone())
+            .await?
+            .and_then(|thread| thread.archive_key)
+            .and_then(|key| Key::from_hex(&key))
+            .ok_or(RhizomeError::Dht(DHTError::ValueNotFound))?;
+
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        let seeds = Self::archive_seeds(node).await;
+
+        let head = Self::find_archive_chunk(node, &archive_key, None, &seeds).await?;
+        let archive: ThreadArchive =
+            from_msgpack(&head).map_err(|_| RhizomeError::Dht(DHTError::General))?;
+        // Every chunk has a message, so the count of messages bounds what is read
+        let message_count = archive.message_count as usize;
+        if archive.chunk_hashes.len() >= message_count.max(1)
+            || archive.messages.len() > message_count
+        {
+            return Err(RhizomeError::Dht(DHTError::General));
+        }
+        let max_size = MAX_ARCHIVE_CHUNK_SIZE.saturating_mul(message_count);
+        let mut size = 0;
+        let mut messages = archive.messages;
+        for (index, hash) in (1..).zip(&archive.chunk_hashes) {
+            let chunk_key = inner
+                .key_manager
+                .get_thread_archive_chunk_key(&thread_id, index);
+            let chunk = Self::find_archive_chunk(node, &chunk_key, Some(hash), &seeds).await?;
+            size += chunk.len();
+            let chunk: Vec<MessageBridge> =
+                from_msgpack(&chunk).map_err(|_| RhizomeError::Dht(DHTError::General))?;
+            messages.extend(chunk);
+            if size > max_size || messages.len() > message_count {
+                return Err(RhizomeError::Dht(DHTError::General));
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Report how many seconds user viewed the content with hex `key`
+    ///
+    /// Reports are rate limited per client and per key, view time of one report is capped.
+    pub async fn report_view(&self, key: String, seconds: f64) -> Result<(), RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        let key = Key::from_hex(&key).ok_or(RhizomeError::Dht(DHTError::General))?;
+
+        inner
+            .report_limiter
+            .lock()
+            .await
+            .check_rate_limit(Some(&key))?;
+
+        if seconds.is_nan() {
+            return Err(RhizomeError::Dht(DHTError::General));
+        }
+        let seconds = seconds.clamp(0.0, inner.config.popularity.max_view_seconds);
+        node.metrics_collector.record_view_time(key, seconds);
+        Ok(())
+    }
+
+    /// Report user reaction on the content with hex `key`
+    ///
+    /// Reports are rate limited per client and per key.
+    pub async fn report_engagement(
+        &self,
+        key: String,
+        kind: EngagementKind,
+    ) -> Result<(), RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        let key = Key::from_hex(&key).ok_or(RhizomeError::Dht(DHTError::General))?;
+
+        inner
+            .report_limiter
+            .lock()
+            .await
+            .check_rate_limit(Some(&key))?;
+
+        node.metrics_collector
+            .record_social_engagement(key, kind.weight());
+        Ok(())
+    }
+
+    // Для API используем String (JSON), так как UniFFI не поддерживает динамический Value
+    pub async fn get_popular_threads_json(&self, limit: u32) -> Result<String, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let all_metrics = node.metrics_collector.get_all_metrics();
+        let ranked = node
+            .popularity_ranker
+            .rank_items(&all_metrics, Some(limit as usize));
+
+        let result = serde_json::json!(
+            ranked
+                .iter()
+                .map(|item| {
+                    serde_json::json!({
+                        "key": hex::encode(&item.key),
+                        "score": item.score
+                    })
+                })
+                .collect::<Vec<_>>()
+        );
+
+        Ok(result.to_string())
+    }
+
+    /// Explanation of the popularity score of the content with hex `key` in JSON format
+    ///
+    /// Every factor has its normalized value, weight and points of the final score.
+    pub async fn explain_popularity_json(&self, key: String) -> Result<String, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        let key = Key::from_hex(&key).ok_or(RhizomeError::Dht(DHTError::General))?;
+
+        let metrics = node
+            .metrics_collector
+            .get_metrics(&key)
+            .ok_or(RhizomeError::Dht(DHTError::ValueNotFound))?;
+        let explanation = node.popularity_ranker.explain(&metrics);
+
+        Ok(serde_json::json!({
+            "key": hex::encode(&key),
+            "popularity_threshold": node.popularity_ranker.popularity_threshold,
+            "active_threshold": node.popularity_ranker.active_threshold,
+            "explanation": explanation,
+        })
+        .to_string())
+    }
+
+    /// Items with the fastest growing popularity in JSON format
+    pub async fn get_trending_items_json(&self, limit: u32) -> Result<String, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+
+        let trending = node
+            .popularity_exchanger
+            .get_trending_api(limit as usize)
+            .await;
+
+        Ok(serde_json::Value::Array(trending).to_string())
+    }
+}
+
+/// Moving of identity and content to other device
+#[cfg(feature = "portability")]
+#[uniffi::export]
+impl RhizomeClient {
+    /// Save node keys with threads, messages and profile published by our node into
+    /// archive at `path` encrypted by `passphrase`
+    ///
+    /// Return count of exported records.
+    pub async fn export_identity_and_content(
+        &self,
+        admin_token: String,
+        path: String,
+        passphrase: String,
+    ) -> Result<u32, RhizomeError> {
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        node.admin_tokens
+            .authorize(&admin_token, AdminRole::Admin)?;
+
+        let private_key = std::fs::read(&inner.config.node.node_key_file)
+            .map_err(|_| RhizomeError::Security(SecurityError::General))?;
+        let profile_key = inner
+            .key_manager
+            .get_user_profile_key(&node.node_id.to_string());
+        let now = node.storage.now();
+        let records: Vec<PortableRecord> = node
+            .storage
+            .records_by_publisher(node.node_id.0.to_vec())
+            .await?
+            .into_iter()
+            .filter(|record| {
+                [RECORD_THREAD, RECORD_MESSAGE, RECORD_MESSAGE_PART]
+                    .contains(&record.record_type.as_str())
+                    || record.key == profile_key
+            })
+            .map(|record| PortableRecord {
+                key: record.key.as_bytes().to_vec(),
+                value: record.value,
+                record_type: record.record_type,
+                ttl: (record.expires_at - now).max(0.0) as i32,
+            })
+            .collect();
+
+        let count = records.len() as u32;
+        let archive = IdentityArchive {
+            node_id: node.node_id.0.to_vec(),
+            private_key,
+            network_id: inner.key_manager.network_id().to_string(),
+            records,
+            exported_at: get_now_i64(),
+        };
+        let sealed = task::spawn_blocking(move || seal_archive(&archive, &passphrase))
+            .await
+            .ok()
+            .flatten()
+            .ok_or(RhizomeError::Security(SecurityError::General))?;
+        std::fs::write(&path, sealed).map_err(|_| RhizomeError::Storage(StorageError::General))?;
+        Ok(count)
+    }
+
+    /// Take identity from archive made by [`RhizomeClient::export_identity_and_content`],
+    /// start the node with it and publish the archived records again
+    ///
+    /// Must be called instead of `start`. Identity files of this device are kept with
+    /// `.retired` suffix and come back if the node does not start with the imported
+    /// identity. Return count of published records.
+    pub async fn import_identity_and_content(
+        &self,
+        path: String,
+        passphrase: String,
+    ) -> Result<u32, RhizomeError> {
+        let sealed =
+            std::fs::read(&path).map_err(|_| RhizomeError::Storage(StorageError::General))?;
+        let archive = task::spawn_blocking(move || open_archive(&sealed, &passphrase))
+            .await
+            .ok()
+            .flatten()
+            .ok_or(RhizomeError::Security(SecurityError::DecryptionFailed))?;
+
+        let retired = {
+            let inner = self.inner.read().await;
+            if inner.is_running {
+                return Err(RhizomeError::Network(NetworkError::General));
+            }
+            if archive.network_id != inner.key_manager.network_id() {
+                warn!(network = %archive.network_id, "Archive belongs to other network");
+                return Err(RhizomeError::Security(SecurityError::General));
+            }
+            install_identity(
+                &archive,
+                &inner.config.node.node_id_file,
+                &inner.config.node.node_key_file,
+            )
+            .map_err(|e| {
+                warn!(error = %e, "Identity of archive was not installed");
+                match e.kind() {
+                    std::io::ErrorKind::InvalidData => {
+                        RhizomeError::Security(SecurityError::General)
+                    }
+                    _ => RhizomeError::Storage(StorageError::General),
+                }
+            })?
+        };
+        if let Err(e) = self.start_node().await {
+            if let Err(restore) = retired.restore() {
+                warn!(error = %restore, "Retired identity was not restored");
+            }
+            return Err(e);
+        }
+
+        let inner = self.inner.read().await;
+        let node = inner
+            .node
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::NodeNotFound))?;
+        let mut published = 0;
+        for record in archive.records.iter().filter(|record| record.ttl > 0) {
+            let key = Key::from(record.key.as_slice());
+            match node
+                .store_record(&key, &record.value, record.ttl, &record.record_type)
+                .await
+            {
+                Ok(_) => published += 1,
+                Err(e) => warn!(key = %key, error = %e, "Archived record was not published"),
+            }
+        }
+        Ok(published)
+    }
+}
+
+impl RhizomeClient {
+    /// Create and start the node, error if it is already running
+    async fn start_node(&self) -> Result<(), RhizomeError> {
+        let mut inner = self.inner.write().await;
+        if inner.is_running {
+            return Err(RhizomeError::Network(NetworkError::General));
+        }
+
+        let node = FullNode::new(inner.config.clone())
+            .await
+            .map_err(|_| RhizomeError::Dht(DHTError::General))?;
+
+        let report = node.preflight().await;
+        for check in report.problems() {
+            warn!(check = check.name, status = ?check.status, detail = %check.detail, "Preflight check did not pass");
+        }
+        if !report.passed() {
+            return Err(RhizomeError::Network(NetworkError::General));
+        }
+
+        let node_arc = Arc::new(node);
+        node_arc
+            .start()
+            .await
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+
+        inner.node = Some(node_arc);
+        inner.is_running = true;
+        Ok(())
+    }
+
+    /// Append sealed message to the mailbox of the owner of `recipient_key`
+    #[cfg(feature = "mailbox")]
+    async fn put_in_mailbox(
+        inner: &ClientInner,
+        node: &FullNode,
+        recipient_key: &[u8],
+        envelope: &MailboxEnvelope,
+    ) -> Result<(), RhizomeError> {
+        let appended = node
+            .update_value(
+                &mailbox_key(&inner.key_manager, recipient_key),
+                inner.config.storage.mailbox_ttl,
+                RECORD_MAILBOX,
+                |current| append_to_mailbox(current, envelope),
+            )
+            .await?;
+        if !appended {
+            return Err(RhizomeError::Dht(DHTError::General));
+        }
+        Ok(())
+    }
+
+    /// Online node with ID of the public key _(None if it is not found)_
+    #[cfg(feature = "mailbox")]
+    async fn find_recipient(node: &FullNode, recipient_key: &[u8]) -> Option<Node> {
+        let recipient_id = NodeID::new(node_id_from_public_key_der(recipient_key));
+        let known = node
+            .routing_table
+            .read()
+            .await
+            .find_closest_nodes(&recipient_id, 1)
+            .into_iter()
+            .find(|n| n.node_id == recipient_id);
+        if known.is_some() {
+            return known;
+        }
+        node.dht_protocol
+            .find_node(&recipient_id)
+            .await
+            .ok()?
+            .into_iter()
+            .find(|n| n.node_id == recipient_id)
+    }
+
+    /// Look up threads and messages which are not cached, see [`RhizomeClient::prefetch`]
+    ///
+    /// Lock of the client is not held during lookups, so `stop` does not wait for them.
+    /// `keys` are no longer in flight when it ends.
+    async fn run_prefetch(
+        inner: Arc<RwLock<ClientInner>>,
+        thread_ids: Vec<String>,
+        message_ids: Vec<String>,
+        keys: Vec<Key>,
+    ) {
+        Self::prefetch_threads(&inner, &thread_ids).await;
+        Self::prefetch_messages(&inner, &message_ids).await;
+        let inner = inner.read().await;
+        let mut prefetching = inner.prefetching.lock().await;
+        for key in &keys {
+            prefetching.remove(key);
+        }
+    }
+
+    async fn prefetch_threads(inner: &RwLock<ClientInner>, thread_ids: &[String]) {
+        for batch in thread_ids.chunks(PREFETCH_BATCH) {
+            let (node, missing) = {
+                let inner = inner.read().await;
+                let Some(node) = inner.node.clone() else {
+                    return;
+                };
+                let mut missing = Vec::new();
+                let mut cache = inner.thread_cache.lock().await;
+                for thread_id in batch {
+                    if cache.get(thread_id).is_none() {
+                        missing.push((thread_id, inner.key_manager.get_thread_meta_key(thread_id)));
+                    }
+                }
+                (node, missing)
+            };
+            if missing.is_empty() {
+                continue;
+            }
+
+            // Lookup through the protocol is not recorded as interest in the key
+            let keys: Vec<Key> = missing.iter().map(|(_, key)| key.clone()).collect();
+            let Ok(found) = node.dht_protocol.find_values(&keys).await else {
+                return;
+            };
+            let inner = inner.read().await;
+            for (thread_id, key) in missing {
+                let Some(data) = found.get(&key) else {
+                    continue;
+                };
+                let Ok(thread) = from_msgpack::<ThreadMetadataBridge>(data) else {
+                    continue;
+                };
+                inner
+                    .thread_cache
+                    .lock()
+                    .await
+                    .insert(thread_id.clone(), thread.clone());
+                Self::remember_thread(&inner, &thread).await;
+            }
+            drop(inner);
+            sleep(Duration::from_millis(PREFETCH_PAUSE)).await;
+        }
+    }
+
+    async fn prefetch_messages(inner: &RwLock<ClientInner>, message_ids: &[String]) {
+        for batch in message_ids.chunks(PREFETCH_BATCH) {
+            let (node, keys, missing) = {
+                let inner = inner.read().await;
+                let Some(node) = inner.node.clone() else {
+                    return;
+                };
+                let mut missing = Vec::new();
+                for message_id in batch {
+                    if inner.message_cache.lock().await.get(message_id).is_some() {
+                        continue;
+                    }
+                    if let Some(store) = &inner.client_store
+                        && let Ok(Some(_)) = store.message(message_id).await
+                    {
+                        continue;
+                    }
+                    let message_hash = hex::encode(&hash_key(message_id.as_bytes())[..8]);
+                    missing.push((message_id, inner.key_manager.get_message_key(&message_hash)));
+                }
+                (node, inner.key_manager.clone(), missing)
+            };
+            if missing.is_empty() {
+                continue;
+            }
+
+            let lookup: Vec<Key> = missing.iter().map(|(_, key)| key.clone()).collect();
+            let Ok(found) = node.dht_protocol.find_values(&lookup).await else {
+                return;
+            };
+            let mut assembled = Vec::new();
+            for (message_id, key) in missing {
+                let Some(data) = found.get(&key) else {
+                    continue;
+                };
+                let Ok(message) = from_msgpack::<MessageBridge>(data) else {
+                    continue;
+                };
+                if let Some(message) = Self::assemble_message(&keys, &node, message).await {
+                    assembled.push((message_id, message));
+                }
+            }
+
+            let inner = inner.read().await;
+            for (message_id, message) in assembled {
+                inner
+                    .message_cache
+                    .lock()
+                    .await
+                    .insert(message_id.clone(), message.clone());
+                Self::remember_message(&inner, &message).await;
+            }
+            drop(inner);
+            sleep(Duration::from_millis(PREFETCH_PAUSE)).await;
+        }
+    }
+
+    /// Count new message in thread metadata by compare-and-swap and link it as the latest
+    ///
+    /// The message is counted in the entry of our node in the version clock. Version of
+    /// other writer which conflicts with our attempt is merged with it, so messages of
+    /// both are kept and ours is counted once.
+    async fn update_thread(
+        node: &FullNode,
+        meta_key: &Key,
+        found: &[u8],
+        ttl: i32,
+        timestamp: i64,
+        message_hash: &str,
+    ) -> Result<(), RhizomeError> {
+        let writer = hex::encode(&node.node_id.0[..8]);
+        let mut target = None;
+        let mut last_attempt: Option<ThreadMetadataBridge> = None;
+        node.update_value(meta_key, ttl, RECORD_THREAD, |current| {
+            let Some(meta) =
+                current.and_then(|data| from_msgpack::<ThreadMetadataBridge>(data).ok())
+            else {
+                return found.to_vec();
+            };
+            let mut meta = match last_attempt.take() {
+                Some(attempt) => reconcile(meta, attempt),
+                None => meta,
+            };
+            let target = *target.get_or_insert(meta.version.get(&writer) + 1);
+            if meta.version.get(&writer) < target {
+                meta.version.counters.insert(writer.clone(), target);
+                meta.version.prune(&writer);
+                meta.message_count = meta.message_count.saturating_add(1);
+                if timestamp >= meta.last_activity {
+                    meta.last_message_hash = Some(message_hash.to_string());
+                }
+                meta.last_activity = meta.last_activity.max(timestamp);
+                meta.recent_messages =
+                    merge_timestamps(&meta.recent_messages, &[timestamp], MAX_RECENT_MESSAGES);
+            }
+            let data = to_msgpack(&meta).unwrap_or_else(|_| found.to_vec());
+            last_attempt = Some(meta);
+            data
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Save thread to the client store, failures only make the next start slower
+    async fn remember_thread(inner: &ClientInner, thread: &ThreadMetadataBridge) {
+        let Some(store) = &inner.client_store else {
+            return;
+        };
+        if let Ok(data) = to_msgpack(thread)
+            && let Err(e) = store.save_thread(&thread.id, data).await
+        {
+            debug!(thread_id = %thread.id, error = %e, "Thread was not saved to client store");
+        }
+    }
+
+    /// Save message to the client store
+    async fn remember_message(inner: &ClientInner, message: &MessageBridge) {
+        let Some(store) = &inner.client_store else {
+            return;
+        };
+        if let Ok(data) = to_msgpack(message)
+            && let Err(e) = store
+                .save_message(&message.thread_id, &message.id, data)
+                .await
+        {
+            debug!(message_id = %message.id, error = %e, "Message was not saved to client store");
+        }
+    }
+
+    /// Bundle of popular thread replicated with its recent messages
+    ///
+    /// Only the closest known node is asked, bundles exist only for popular threads and
+    /// full lookup for every other thread would be wasted.
+    async fn find_thread_bundle(
+        inner: &ClientInner,
+        node: &FullNode,
+        thread_id: &str,
+    ) -> Option<ReplicatedThread> {
+        if !node.config.popularity.thread_bundles {
+            return None;
+        }
+        let key = inner.key_manager.get_thread_bundle_key(thread_id);
+        let found = node
+            .dht_protocol
+            .find_values(std::slice::from_ref(&key))
+            .await
+            .ok()?;
+        ReplicatedThread::unpack(found.get(&key)?).filter(|bundle| bundle.thread_id == thread_id)
+    }
+
+    /// Put messages of the bundle into caches, return the thread merged with its bundled
+    /// metadata
+    ///
+    /// Records are used only if they are signed by their publishers for their own keys,
+    /// so the node which made the bundle can not forge them. Messages stored in parts are
+    /// left out, they are assembled when asked by id.
+    async fn unpack_thread_bundle(
+        inner: &ClientInner,
+        thread: Option<ThreadMetadataBridge>,
+        bundle: ReplicatedThread,
+    ) -> Option<ThreadMetadataBridge> {
+        let meta_key = inner.key_manager.get_thread_meta_key(&bundle.thread_id);
+        let bundled = Some(&bundle.meta)
+            .filter(|meta| meta.is_signed_for(&meta_key, RECORD_THREAD))
+            .and_then(|meta| from_msgpack::<ThreadMetadataBridge>(&meta.value).ok())
+            .filter(|meta| meta.id == bundle.thread_id);
+        let thread = match (thread, bundled) {
+            (Some(thread), Some(bundled)) => Some(reconcile(thread, bundled)),
+            (thread, bundled) => thread.or(bundled),
+        };
+
+        for record in &bundle.messages {
+            let Ok(message) = from_msgpack::<MessageBridge>(&record.value) else {
+                continue;
+            };
+            if message.thread_id != bundle.thread_id || !message.content_parts.is_empty() {
+                continue;
+            }
+            let message_hash = hex::encode(&hash_key(message.id.as_bytes())[..8]);
+            let message_key = inner.key_manager.get_message_key(&message_hash);
+            if !record.is_signed_for(&message_key, RECORD_MESSAGE) {
+                debug!(message_id = %message.id, "Bundled message without valid proof ignored");
+                continue;
+            }
+            inner
+                .message_cache
+                .lock()
+                .await
+                .insert(message.id.clone(), message.clone());
+            Self::remember_message(inner, &message).await;
+        }
+        thread
+    }
+
+    /// Store message record, content over [`MESSAGE_PART_SIZE`] is stored in parts
+    ///
+    /// Parts are addressed by their hashes, so they can be checked on read. Up to
+    /// [`MESSAGE_PARTS_IN_FLIGHT`] parts are stored at once, content of more than
+    /// [`MAX_MESSAGE_PARTS`] parts is refused. Return the message with listed parts.
+    async fn store_message(
+        inner: &ClientInner,
+        node: &FullNode,
+        mut message: MessageBridge,
+        ttl: i32,
+        budget: &QueryBudget,
+    ) -> Result<MessageBridge, RhizomeError> {
+        if message.content.len() > MAX_MESSAGE_PARTS * MESSAGE_PART_SIZE {
+            return Err(RhizomeError::Storage(StorageError::ValueTooLarge));
+        }
+        let mut record = message.clone();
+        if message.content.len() > MESSAGE_PART_SIZE {
+            let chunks: Vec<&[u8]> = message
+                .content
+                .as_bytes()
+                .chunks(MESSAGE_PART_SIZE)
+                .collect();
+            let parts: Vec<String> = chunks
+                .iter()
+                .map(|part| hex::encode(hash_key(part)))
+                .collect();
+            let stores: Vec<_> = chunks
+                .into_iter()
+                .zip(&parts)
+                .map(|(part, part_hash)| {
+                    let part_key = inner.key_manager.get_message_part_key(part_hash);
+                    async move {
+                        node.store_record_with_budget(
+                            &part_key,
+                            part,
+                            ttl,
+                            RECORD_MESSAGE_PART,
+                            budget,
+                        )
+                        .await
+                    }
+                })
+                .collect();
+            let mut stores =
+                futures::stream::iter(stores).buffer_unordered(MESSAGE_PARTS_IN_FLIGHT);
+            while let Some(stored) = stores.next().await {
+                stored?;
+            }
+            drop(stores);
+            record.content = String::new();
+            record.content_parts = parts.clone();
+            message.content_parts = parts;
+        }
+
+        let message_hash = hex::encode(&hash_key(message.id.as_bytes())[..8]);
+        let message_key = inner.key_manager.get_message_key(&message_hash);
+        let message_data = to_msgpack(&record).map_err(|_| RhizomeError::Dht(DHTError::General))?;
+        node.store_record_with_budget(&message_key, &message_data, ttl, RECORD_MESSAGE, budget)
+            .await?;
+        Ok(message)
+    }
+
+    /// Fill content of the message stored in parts by one batched lookup
+    ///
+    /// Return None if some part is lost or does not match its hash, or the message has
+    /// more than [`MAX_MESSAGE_PARTS`] parts.
+    async fn assemble_message(
+        keys: &KeyManager,
+        node: &FullNode,
+        mut message: MessageBridge,
+    ) -> Option<MessageBridge> {
+        if message.content_parts.is_empty() {
+            return Some(message);
+        }
+        if message.content_parts.len() > MAX_MESSAGE_PARTS {
+            warn!(message_id = %message.id, parts = message.content_parts.len(), "Message has too many parts");
+            return None;
+        }
+        let part_keys: Vec<Key> = message
+            .content_parts
+            .iter()
+            .map(|part_hash| keys.get_message_part_key(part_hash))
+            .collect();
+        let found = node.find_values(&part_keys).await.ok()?;
+
+        let mut content = Vec::new();
+        for (part_hash, part_key) in message.content_parts.iter().zip(&part_keys) {
+            let part = found.get(part_key)?;
+            if part.len() > MESSAGE_PART_SIZE || hex::encode(hash_key(part)) != *part_hash {
+                warn!(message_id = %message.id, "Message part does not match its hash");
+                return None;
+            }
+            content.extend_from_slice(part);
+        }
+        message.content = String::from_utf8(content).ok()?;
+        Some(message)
+    }
+
+    /// Crawl messages of the thread over the network, the oldest first
+    #[cfg(any(feature = "signatures", feature = "popularity"))]
+    async fn collect_thread_messages(
+        inner: &ClientInner,
+        node: &FullNode,
+        thread: &ThreadMetadataBridge,
+    ) -> Result<Vec<MessageBridge>, RhizomeError> {
+        let mut messages: Vec<MessageBridge> = Vec::new();
+        node.dht_protocol
+            .iterate_closest(
+                &node.node_id,
+                RECORD_MESSAGE,
+                thread.created_at as f64,
+                MAX_BUNDLE_CRAWL_NODES,
+                |record| {
+                    if let Ok(message) = from_msgpack::<MessageBridge>(&record.value)
+                        && message.thread_id == thread.id
+                        && !messages.iter().any(|m| m.id == message.id)
+                    {
+                        messages.push(message);
+                    }
+                    messages.len() < MAX_BUNDLE_MESSAGES
+                },
+            )
+            .await?;
+
+        let mut assembled = Vec::with_capacity(messages.len());
+        for message in messages {
+            if let Some(message) = Self::assemble_message(&inner.key_manager, node, message).await {
+                assembled.push(message);
+            }
+        }
+        assembled.sort_by_key(|m| m.timestamp);
+        Ok(assembled)
+    }
+
+    /// Seeds from the routing table which volunteered to keep archives
+    #[cfg(feature = "popularity")]
+    async fn archive_seeds(node: &FullNode) -> Vec<Node> {
+        let archivists = node
+            .popularity_exchanger
+            .seed_registry
+            .read()
+            .await
+            .archivists();
+        node.routing_table
+            .read()
+            .await
+            .get_all_nodes()
+            .into_iter()
+            .filter(|n| archivists.iter().any(|id| id[..] == n.node_id.0[..]))
+            .collect()
+    }
+
+    /// Split messages of the archive into chunks of [`MAX_ARCHIVE_CHUNK_SIZE`]
+    ///
+    /// Message larger than the limit takes a chunk alone. There is always at least one
+    /// chunk, so the archive of a thread without messages is stored too.
+    #[cfg(feature = "popularity")]
+    fn archive_chunks(messages: Vec<MessageBridge>) -> Vec<Vec<MessageBridge>> {
+        let mut chunks = Vec::new();
+        let mut chunk = Vec::new();
+        let mut size = 0;
+        for message in messages {
+            let message_size = to_msgpack(&message).map_or(0, |data| data.len());
+            if !chunk.is_empty() && size + message_size > MAX_ARCHIVE_CHUNK_SIZE {
+                chunks.push(std::mem::take(&mut chunk));
+                size = 0;
+            }
+            size += message_size;
+            chunk.push(message);
+        }
+        chunks.push(chunk);
+        chunks
+    }
+
+    /// Store the archive chunk on seeds, return count of seeds which proved they keep it
+    #[cfg(feature = "popularity")]
+    async fn store_archive_chunk(
+        inner: &ClientInner,
+        node: &FullNode,
+        key: &Key,
+        data: &[u8],
+        seeds: &[Node],
+    ) -> usize {
+        let mut origin = StoreOrigin::new(node.node_id.0.to_vec(), node.storage.now())
+            .with_record_type(RECORD_ARCHIVE);
+        node.network_protocol
+            .sign_origin(key, Some(data), &mut origin);
+        let answers = node
+            .network_protocol
+            .store_many_with_receipts(key, data, inner.config.storage.archive_ttl, &origin, seeds)
+            .await;
+        proven_archive_replicas(key, seeds, &answers)
+    }
+
+    /// Read the archive chunk from the first seed which has it with the `hash`
+    #[cfg(feature = "popularity")]
+    async fn find_archive_chunk(
+        node: &FullNode,
+        key: &Key,
+        hash: Option<&[u8; 32]>,
+        seeds: &[Node],
+    ) -> Result<Vec<u8>, RhizomeError> {
+        for seed in seeds {
+            if let Ok(Some(value)) = node.network_protocol.find_value(key, seed).await
+                && hash.is_none_or(|hash| hash_key(&value) == *hash)
+            {
+                return Ok(value);
+            }
+        }
+        Err(RhizomeError::Dht(DHTError::ValueNotFound))
+    }
+
+    /// Store thread with its messages on archive seeds and mark the thread as archived
+    ///
+    /// Chunks after the first one are stored before it, so a found archive is complete.
+    /// Local copies of archived messages are removed only if every chunk is proven by
+    /// receipts of `storage.archive_replicas` seeds, otherwise `false` is returned.
+    /// Thread whose chunks exceed [`MAX_ARCHIVE_CHUNK_SIZE`] per message on average is not
+    /// archived, readers refuse such archives.
+    #[cfg(feature = "popularity")]
+    async fn archive_thread(
+        inner: &ClientInner,
+        node: &FullNode,
+        thread: ThreadMetadataBridge,
+        seeds: &[Node],
+    ) -> Result<bool, RhizomeError> {
+        let archive_key = inner.key_manager.get_thread_archive_key(&thread.id);
+        let archive_ttl = inner.config.storage.archive_ttl;
+        let required = inner.config.storage.archive_replicas.max(1);
+
+        let messages = Self::collect_thread_messages(inner, node, &thread).await?;
+        let mut chunks = Self::archive_chunks(messages.clone()).into_iter();
+        let head = chunks.next().unwrap_or_default();
+        let chunks = chunks
+            .map(|chunk| to_msgpack(&chunk).map_err(|_| RhizomeError::Dht(DHTError::General)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let size: usize = chunks.iter().map(Vec::len).sum();
+        if size > MAX_ARCHIVE_CHUNK_SIZE.saturating_mul(messages.len()) {
+            debug!(thread = %thread.id, size = size, "Thread is too large for the archive");
+            return Ok(false);
+        }
+        let mut chunk_hashes = Vec::new();
+        for (index, chunk_data) in (1..).zip(chunks) {
+            let chunk_key = inner
+                .key_manager
+                .get_thread_archive_chunk_key(&thread.id, index);
+            if Self::store_archive_chunk(inner, node, &chunk_key, &chunk_data, seeds).await
+                < required
+            {
+                return Ok(false);
+            }
+            chunk_hashes.push(hash_key(&chunk_data));
+        }
+
+        let archive = ThreadArchive {
+            messages: head,
+            thread,
+            archived_at: get_now_i64(),
+            message_count: messages.len() as u32,
+            chunk_hashes,
+        };
+        let archive_data =
+            to_msgpack(&archive).map_err(|_| RhizomeError::Dht(DHTError::General))?;
+        if Self::store_archive_chunk(inner, node, &archive_key, &archive_data, seeds).await
+            < required
+        {
+            return Ok(false);
+        }
+
+        let archived = ThreadMetadataBridge {
+            archive_key: Some(archive_key.to_string()),
+            ..archive.thread.clone()
+        };
+        let archived_data =
+            to_msgpack(&archived).map_err(|_| RhizomeError::Dht(DHTError::General))?;
+        let meta_key = inner.key_manager.get_thread_meta_key(&archived.id);
+        node.update_value(&meta_key, archive_ttl, RECORD_THREAD, |current| {
+            let Some(mut meta) =
+                current.and_then(|data| from_msgpack::<ThreadMetadataBridge>(data).ok())
+            else {
+                return archived_data.clone();
+            };
+            meta.archive_key = archived.archive_key.clone();
+            to_msgpack(&meta).unwrap_or_else(|_| archived_data.clone())
+        })
+        .await?;
+
+        let mut message_cache = inner.message_cache.lock().await;
+        for message in &messages {
+            let message_hash = hex::encode(&hash_key(message.id.as_bytes())[..8]);
+            node.storage
+                .delete(inner.key_manager.get_message_key(&message_hash))
+                .await?;
+            for part_hash in &message.content_parts {
+                node.storage
+                    .delete(inner.key_manager.get_message_part_key(part_hash))
+                    .await?;
+            }
+            message_cache.invalidate(&message.id);
+        }
+        drop(message_cache);
+        inner.thread_cache.lock().await.invalidate(&archived.id);
+        Ok(true)
+    }
+
+    /// Add thread id to the global list of threads
+    async fn add_to_global_index(
+        inner: &ClientInner,
+        node: &FullNode,
+        thread_id: String,
+        budget: &QueryBudget,
+    ) -> Result<(), RhizomeError> {
+        let threads_key = inner.key_manager.get_global_threads_key();
+        let mut thread_list: Vec<String> =
+            match node.find_value_with_budget(&threads_key, budget).await {
+                Ok(found) => from_msgpack(&found.value).unwrap_or_default(),
+                // Partial lookup must not replace the index with the single thread
+                Err(RhizomeError::Dht(DHTError::DeadlineExceeded)) => {
+                    return Err(DHTError::DeadlineExceeded.into());
+                }
+                Err(_) => Vec::new(),
+            };
+
+        if !thread_list.contains(&thread_id) {
+            thread_list.push(thread_id);
+            let list_data =
+                to_msgpack(&thread_list).map_err(|_| RhizomeError::Dht(DHTError::General))?;
+            node.store_record_with_budget(&threads_key, &list_data, 86400, "", budget)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "popularity"))]
+mod tests {
+    use super::*;
+    use crate::storage::data_types::ChainIssueKind;
+
+    fn message(id: usize, content_size: usize) -> MessageBridge {
+        MessageBridge {
+            id: id.to_string(),
+            thread_id: "thread".to_string(),
+            parent_id: None,
+            content: "x".repeat(content_size),
+            author_signature: None,
+            timestamp: id as i64,
+            content_type: "text/plain".to_string(),
+            attachments: Vec::new(),
+            content_parts: Vec::new(),
+            prev_hash: None,
+            author_key: None,
+        }
+    }
+
+    #[test]
+    fn large_archive_is_split_into_chunks() {
+        assert_eq!(RhizomeClient::archive_chunks(Vec::new()).len(), 1);
+
+        let messages: Vec<_> = (0..10).map(|id| message(id, 100 * 1024)).collect();
+        let chunks = RhizomeClient::archive_chunks(messages);
+        assert_eq!(chunks.iter().map(Vec::len).sum::<usize>(), 10);
+        assert!(chunks.len() >= 4);
+        for chunk in &chunks {
+            let size: usize = chunk.iter().map(|m| to_msgpack(m).unwrap().len()).sum();
+            assert!(chunk.len() == 1 || size <= MAX_ARCHIVE_CHUNK_SIZE);
+        }
+
+        let oversized = RhizomeClient::archive_chunks(vec![message(0, MAX_ARCHIVE_CHUNK_SIZE * 2)]);
+        assert_eq!(oversized.len(), 1);
+    }
+
+    /// Messages linked one after another
+    fn chain(count: usize) -> Vec<MessageBridge> {
+        let mut messages: Vec<MessageBridge> = Vec::new();
+        for id in 0..count {
+            let mut next = message(id, 10);
+            next.prev_hash = messages.last().map(|m| m.chain_hash());
+            messages.push(next);
+        }
+        messages
+    }
+
+    #[test]
+    fn history_has_one_start_and_the_latest_message() {
+        let messages = chain(3);
+        let latest = messages[2].chain_hash();
+        assert!(verify_chain(&messages, Some(&latest)).is_empty());
+
+        // Cut tail is found only by the hash of the latest message
+        assert!(verify_chain(&messages[..2], None).is_empty());
+        let cut = verify_chain(&messages[..2], Some(&latest));
+        assert_eq!(cut.len(), 1);
+        assert_eq!(cut[0].kind, ChainIssueKind::MissingLatest);
+
+        let mut restarted = messages.clone();
+        restarted[1].prev_hash = None;
+        let issues = verify_chain(&restarted, None);
+        assert_eq!(issues[0].message_id, "1");
+        assert_eq!(issues[0].kind, ChainIssueKind::ExtraGenesis);
+    }
+
+    #[cfg(feature = "signatures")]
+    #[test]
+    fn signature_of_author_covers_the_link() {
+        let (private_key, public_key) = crate::utils::crypto::generate_keypair();
+        let node_id = crate::utils::crypto::node_id_from_public_key(&public_key);
+        let signer = NodeSigner::new(private_key, node_id.to_vec());
+
+        let mut messages = chain(2);
+        for message in &mut messages {
+            message.sign(&signer);
+        }
+        messages[1].prev_hash = Some(messages[0].chain_hash());
+        // Link changed after signing is not covered by the signature
+        let issues = verify_chain(&messages, None);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].message_id, "1");
+        assert_eq!(issues[0].kind, ChainIssueKind::BadSignature);
+
+        messages[1].sign(&signer);
+        assert!(verify_chain(&messages, None).is_empty());
+    }
+
+    #[cfg(feature = "signatures")]
+    #[test]
+    fn only_threads_published_by_our_node_are_archived() {
+        use crate::storage::proof::PublisherProof;
+
+        let (private_key, public_key) = crate::utils::crypto::generate_keypair();
+        let node_id = crate::utils::crypto::node_id_from_public_key(&public_key);
+        let signer = NodeSigner::new(private_key, node_id.to_vec());
+        let mut record = TypedRecord {
+            key: Key::from(b"thread".as_slice()),
+            value: Vec::new(),
+            published_at: 100.0,
+            proof: None,
+        };
+        assert!(!published_by(&record, &node_id));
+
+        record.proof = Some(PublisherProof::issue(
+            &signer,
+            b"thread",
+            Some(b""),
+            100.0,
+            RECORD_THREAD,
+        ));
+        assert!(published_by(&record, &node_id));
+        assert!(!published_by(&record, &[7u8; 20]));
     }
 }
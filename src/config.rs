@@ -9,9 +9,16 @@
 //! - **Partial Configuration**: Supports loading incomplete YAML files by providing sensible defaults for missing fields.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::dht::node::Endpoint;
+use crate::network::chaos::FaultConfig;
+use crate::security::admin_tokens::AdminRole;
+use crate::utils::time::MAX_DURATION_SECS;
 
 // --- Default Value Providers ---
 // These functions provide default values for Serde when a field is missing in the YAML file.
@@ -34,6 +41,39 @@ fn d_ping_to() -> f64 {
 fn d_req_to() -> f64 {
     10.0
 }
+fn d_read_repair() -> i32 {
+    2
+}
+fn d_read_quorum() -> i32 {
+    2
+}
+fn d_keepalive_idle() -> i32 {
+    900
+}
+fn d_max_failed_pings() -> i32 {
+    3
+}
+fn d_warm_up_peers() -> usize {
+    8
+}
+fn d_warm_up_timeout() -> i32 {
+    120
+}
+fn d_partition_merge_peers() -> usize {
+    5
+}
+fn d_partition_merge_window() -> f64 {
+    60.0
+}
+fn d_negative_cache_base() -> f64 {
+    2.0
+}
+fn d_negative_cache_max() -> f64 {
+    60.0
+}
+fn d_holder_pointer_ttl() -> i32 {
+    3600
+}
 fn d_data_dir() -> PathBuf {
     PathBuf::from("data")
 }
@@ -55,6 +95,75 @@ fn d_ttl_priv() -> i32 {
 fn d_ttl_min() -> i32 {
     3600
 }
+fn d_publisher_quota_share() -> f64 {
+    0.1
+}
+fn d_compression_level() -> i32 {
+    3
+}
+fn d_write_mode() -> String {
+    "write_through".to_string()
+}
+fn d_write_back_interval() -> f64 {
+    0.5
+}
+fn d_write_back_batch() -> usize {
+    1000
+}
+fn d_archive_after_days() -> u32 {
+    30
+}
+fn d_archive_ttl() -> i32 {
+    31536000
+}
+fn d_archive_replicas() -> usize {
+    3
+}
+fn d_mailbox_ttl() -> i32 {
+    604800
+}
+fn d_client_cache_ttl() -> i32 {
+    30
+}
+fn d_client_cache_size() -> usize {
+    1000
+}
+fn d_client_store_size() -> u64 {
+    256 * 1024 * 1024
+}
+fn d_client_store_entries() -> usize {
+    10000
+}
+fn d_client_store_ttl() -> i32 {
+    2592000
+}
+fn d_client_write_limit() -> u32 {
+    0
+}
+fn d_client_write_window() -> f64 {
+    60.0
+}
+fn d_client_write_queue() -> usize {
+    50
+}
+fn d_dedup_window() -> i32 {
+    600
+}
+fn d_dedup_max() -> usize {
+    100_000
+}
+fn d_tombstone_ttl() -> i32 {
+    604800
+}
+fn d_min_free_space() -> u64 {
+    512 * 1024 * 1024
+}
+fn d_resume_free_space() -> u64 {
+    1024 * 1024 * 1024
+}
+fn d_disk_check_interval() -> i32 {
+    30
+}
 fn d_host() -> String {
     "0.0.0.0".to_string()
 }
@@ -67,6 +176,21 @@ fn d_max_conn() -> i32 {
 fn d_conn_to() -> f64 {
     30.0
 }
+fn d_gossip_fanout() -> i32 {
+    3
+}
+fn d_gossip_ttl() -> i32 {
+    6
+}
+fn d_gossip_interval() -> i32 {
+    30
+}
+fn d_mapping_lease() -> u32 {
+    3600
+}
+fn d_wire_format() -> String {
+    "msgpack".to_string()
+}
 fn d_node_type() -> String {
     "full".to_string()
 }
@@ -79,6 +203,9 @@ fn d_false() -> bool {
 fn d_id_file() -> PathBuf {
     PathBuf::from("node_id.pem")
 }
+fn d_key_file() -> PathBuf {
+    PathBuf::from("node_key.der")
+}
 fn d_state_file() -> PathBuf {
     PathBuf::from("node_state.json")
 }
@@ -97,15 +224,84 @@ fn d_pop_thr() -> f64 {
 fn d_act_thr() -> f64 {
     5.0
 }
+fn d_max_tracked() -> usize {
+    100_000
+}
+fn d_metrics_shards() -> usize {
+    16
+}
+fn d_metrics_age() -> u64 {
+    30
+}
+fn d_metrics_cleanup() -> i32 {
+    86400
+}
+fn d_trend_win() -> i32 {
+    21600
+}
+fn d_report_lim() -> i32 {
+    60
+}
+fn d_report_key_lim() -> i32 {
+    10
+}
+fn d_report_win() -> i32 {
+    60
+}
+fn d_max_view() -> f64 {
+    3600.0
+}
+fn d_decay_half_life() -> i32 {
+    86400
+}
+fn d_demotion_thr() -> f64 {
+    5.0
+}
+fn d_bundle_messages() -> usize {
+    50
+}
 fn d_ring_size() -> i32 {
     8
 }
 fn d_rate_lim() -> i32 {
     100
 }
+fn d_rate_lim_node() -> i32 {
+    20
+}
 fn d_rate_win() -> i32 {
     60
 }
+fn d_puzzle_difficulty() -> u8 {
+    16
+}
+fn d_puzzle_load() -> f64 {
+    0.8
+}
+fn d_audit_file() -> PathBuf {
+    PathBuf::from("audit.log")
+}
+fn d_audit_size() -> u64 {
+    10 * 1024 * 1024
+}
+fn d_audit_files() -> usize {
+    5
+}
+fn d_timestamp_window() -> f64 {
+    300.0
+}
+fn d_plugin_fuel() -> u64 {
+    1_000_000
+}
+fn d_plugin_memory() -> usize {
+    16 * 1024 * 1024
+}
+fn d_blocklist_refresh() -> f64 {
+    3600.0
+}
+fn d_webhook_timeout() -> f64 {
+    10.0
+}
 fn d_log_level() -> String {
     "INFO".to_string()
 }
@@ -125,7 +321,7 @@ pub struct DHTConfig {
     /// Number of bits in the Node ID (usually 160).
     #[serde(default = "d_bits")]
     pub node_id_bits: i32,
-    /// Total number of buckets in the routing table.
+    /// Max number of buckets in the routing table (buckets are split on demand).
     #[serde(default = "d_bits")]
     pub bucket_count: i32,
     /// Interval in seconds for refreshing the routing table.
@@ -137,6 +333,45 @@ pub struct DHTConfig {
     /// Timeout in seconds for standard DHT requests (FIND_NODE, etc).
     #[serde(default = "d_req_to")]
     pub request_timeout: f64,
+    /// Max count of closest nodes which get found value by read repair (0 disables it).
+    #[serde(default = "d_read_repair")]
+    pub read_repair_count: i32,
+    /// Count of replicas which must answer on quorum read.
+    #[serde(default = "d_read_quorum")]
+    pub read_quorum: i32,
+    /// Seconds without any traffic from peer before we send explicit PING.
+    #[serde(default = "d_keepalive_idle")]
+    pub keepalive_idle: i32,
+    /// Peer is removed from routing table after this count of failed PINGs in a row.
+    #[serde(default = "d_max_failed_pings")]
+    pub max_failed_pings: i32,
+    /// Push stored keys to a new peer which is closer to them than we are.
+    #[serde(default = "d_true")]
+    pub transfer_on_join: bool,
+    /// Fill routing table and estimate clocks of peers before accepting stores and being advertised.
+    #[serde(default = "d_true")]
+    pub warm_up: bool,
+    /// Count of peers in routing table after which warm-up may finish.
+    #[serde(default = "d_warm_up_peers")]
+    pub warm_up_min_peers: usize,
+    /// Max duration of warm-up in seconds, node starts serving after it anyway.
+    #[serde(default = "d_warm_up_timeout")]
+    pub warm_up_timeout: i32,
+    /// Count of unknown far-away peers joining within `partition_merge_window` taken as healed partition (0 - off).
+    #[serde(default = "d_partition_merge_peers")]
+    pub partition_merge_peers: usize,
+    /// Window in seconds for counting peers of the healed partition.
+    #[serde(default = "d_partition_merge_window")]
+    pub partition_merge_window: f64,
+    /// Seconds a not found key is answered as missing without lookup, doubled on every next miss (0 - off).
+    #[serde(default = "d_negative_cache_base")]
+    pub negative_cache_base: f64,
+    /// Max seconds of the backoff for a not found key.
+    #[serde(default = "d_negative_cache_max")]
+    pub negative_cache_max: f64,
+    /// Seconds a light or mobile node points lookups to the peer holding a value it refused to store (0 - off).
+    #[serde(default = "d_holder_pointer_ttl")]
+    pub holder_pointer_ttl: i32,
 }
 
 impl Default for DHTConfig {
@@ -169,6 +404,84 @@ pub struct StorageConfig {
     /// Minimum guaranteed TTL regardless of popularity.
     #[serde(default = "d_ttl_min")]
     pub min_guaranteed_ttl: i32,
+    /// Time in seconds for which API client keeps found threads and messages in memory.
+    #[serde(default = "d_client_cache_ttl")]
+    pub client_cache_ttl: i32,
+    /// Max count of threads and messages in API client cache (each).
+    #[serde(default = "d_client_cache_size")]
+    pub client_cache_size: usize,
+    /// Directory of the persistent store of threads and messages seen by API client (off if None).
+    #[serde(default)]
+    pub client_store_dir: Option<PathBuf>,
+    /// Max size in bytes of the API client persistent store.
+    #[serde(default = "d_client_store_size")]
+    pub client_store_max_size: u64,
+    /// Max count of threads and messages in the API client persistent store (each), the least recently synced are evicted.
+    #[serde(default = "d_client_store_entries")]
+    pub client_store_max_entries: usize,
+    /// Time in seconds after which threads and messages not synced again are evicted from the API client persistent store.
+    #[serde(default = "d_client_store_ttl")]
+    pub client_store_ttl: i32,
+    /// Max count of threads and messages which API client publishes per `client_write_window`, next ones wait (0 disables pacing).
+    #[serde(default = "d_client_write_limit")]
+    pub client_write_limit: u32,
+    /// Window in seconds of the API client write pacing.
+    #[serde(default = "d_client_write_window")]
+    pub client_write_window: f64,
+    /// Max count of writes waiting for pacing, next writes fail with rate limit error.
+    #[serde(default = "d_client_write_queue")]
+    pub client_write_queue: usize,
+    /// Time in seconds for which answers to processed STORE requests are kept for duplicates.
+    #[serde(default = "d_dedup_window")]
+    pub dedup_window: i32,
+    /// Max count of remembered processed STORE requests.
+    #[serde(default = "d_dedup_max")]
+    pub dedup_max_entries: usize,
+    /// Time in seconds for which tombstones of deleted values are kept and replicated.
+    #[serde(default = "d_tombstone_ttl")]
+    pub tombstone_ttl: i32,
+    /// Free disk space in bytes below which the node goes to degraded mode and rejects inbound stores.
+    #[serde(default = "d_min_free_space")]
+    pub min_free_space: u64,
+    /// Free disk space in bytes above which the node leaves degraded mode.
+    #[serde(default = "d_resume_free_space")]
+    pub resume_free_space: u64,
+    /// Interval in seconds between checks of free disk space.
+    #[serde(default = "d_disk_check_interval")]
+    pub disk_check_interval: i32,
+    /// Days without activity after which threads are archived to seed nodes.
+    #[serde(default = "d_archive_after_days")]
+    pub archive_after_days: u32,
+    /// TTL of thread archives on seed nodes.
+    #[serde(default = "d_archive_ttl")]
+    pub archive_ttl: i32,
+    /// Count of seeds which must sign receipts of the archive before local messages are removed.
+    #[serde(default = "d_archive_replicas")]
+    pub archive_replicas: usize,
+    /// TTL of mailboxes with direct messages, it is renewed by every new message.
+    #[serde(default = "d_mailbox_ttl")]
+    pub mailbox_ttl: i32,
+    /// Max part of `max_storage_size` which data of one publisher may take (0 disables quotas).
+    #[serde(default = "d_publisher_quota_share")]
+    pub publisher_quota_share: f64,
+    /// Own quotas in bytes of single publishers by hex node ID (0 - unlimited).
+    #[serde(default)]
+    pub publisher_quotas: HashMap<String, u64>,
+    /// Values of at least this size in bytes are stored compressed with zstd (off if None, light and mobile nodes use 4096).
+    #[serde(default)]
+    pub compression_threshold: Option<usize>,
+    /// zstd level of stored values, higher saves more space for more CPU.
+    #[serde(default = "d_compression_level")]
+    pub compression_level: i32,
+    /// Durability of puts and inbound STOREs ("write_through" - commit of every put, "write_back" - group commit, faster for busy seed nodes but the last puts are lost on crash).
+    #[serde(default = "d_write_mode")]
+    pub write_mode: String,
+    /// Interval in seconds between group commits of write-back puts.
+    #[serde(default = "d_write_back_interval")]
+    pub write_back_interval: f64,
+    /// Count of queued write-back puts which are committed at once without waiting for the interval.
+    #[serde(default = "d_write_back_batch")]
+    pub write_back_max_batch: usize,
 }
 
 impl Default for StorageConfig {
@@ -186,6 +499,15 @@ pub struct NetworkConfig {
     /// The port number to listen on.
     #[serde(default = "d_port")]
     pub listen_port: i32,
+    /// Name of the network interface to bind (e.g. "eth0"), overrides `listen_host`.
+    #[serde(default)]
+    pub listen_interface: Option<String>,
+    /// Ports `[first, last]` tried in order when `listen_port` is taken.
+    #[serde(default)]
+    pub listen_port_range: Option<(u16, u16)>,
+    /// ID of the deployment (e.g. "testnet"), peers and DHT keys of other networks are kept apart.
+    #[serde(default)]
+    pub network_id: String,
     /// A list of bootstrap node addresses (e.g., "1.2.3.4:8468").
     #[serde(default)]
     pub bootstrap_nodes: Vec<String>,
@@ -195,6 +517,49 @@ pub struct NetworkConfig {
     /// Timeout in seconds for establishing a connection.
     #[serde(default = "d_conn_to")]
     pub connection_timeout: f64,
+    /// Count of random peers which get every gossip message.
+    #[serde(default = "d_gossip_fanout")]
+    pub gossip_fanout: i32,
+    /// Max count of hops of gossip message.
+    #[serde(default = "d_gossip_ttl")]
+    pub gossip_ttl: i32,
+    /// Interval in seconds between gossip digest exchanges.
+    #[serde(default = "d_gossip_interval")]
+    pub gossip_interval: i32,
+    /// Preferred format of messages ("msgpack", "cbor", "protobuf"), used with peers which support it.
+    #[serde(default = "d_wire_format")]
+    pub wire_format: String,
+    /// Endpoints advertised to peers besides the address they see (LAN, WAN, relay, onion).
+    #[serde(default)]
+    pub endpoints: Vec<Endpoint>,
+    /// File where all received and sent packets are recorded for debugging (off if None).
+    #[serde(default)]
+    pub capture_file: Option<PathBuf>,
+    /// Map the listen port on the home router by UPnP IGD or NAT-PMP.
+    #[serde(default = "d_false")]
+    pub port_mapping: bool,
+    /// Lease of the router mapping in seconds, it is renewed at half of the lease.
+    #[serde(default = "d_mapping_lease")]
+    pub port_mapping_lease: u32,
+    /// NAT-PMP router address (default gateway if None).
+    #[serde(default)]
+    pub nat_pmp_gateway: Option<String>,
+    /// Faults injected into sent packets (drop, duplication, reordering, latency), only for simulations.
+    /// Node must be built with `fault-injection` feature.
+    #[serde(default)]
+    pub faults: Option<FaultConfig>,
+    /// Size of the socket receive buffer in bytes (SO_RCVBUF), OS default if None.
+    #[serde(default)]
+    pub recv_buffer_size: Option<usize>,
+    /// Size of the socket send buffer in bytes (SO_SNDBUF), OS default if None.
+    #[serde(default)]
+    pub send_buffer_size: Option<usize>,
+    /// TTL (hop limit on IPv6) of sent packets, OS default if None.
+    #[serde(default)]
+    pub ip_ttl: Option<u32>,
+    /// DSCP class of sent packets from 0 to 63 (e.g. 46 for expedited forwarding), IPv4 only.
+    #[serde(default)]
+    pub dscp: Option<u8>,
 }
 
 impl Default for NetworkConfig {
@@ -215,9 +580,19 @@ pub struct NodeConfig {
     /// Path to the file containing the Node's identity (PEM).
     #[serde(default = "d_id_file")]
     pub node_id_file: PathBuf,
+    /// Path to the private key of the node (PKCS#8 DER), used for signing storage receipts.
+    #[serde(default = "d_key_file")]
+    pub node_key_file: PathBuf,
     /// Path to the JSON file where node state is persisted across reboots.
     #[serde(default = "d_state_file")]
     pub state_file: PathBuf,
+    /// Seed of the node random generator and of a new node key for reproducible simulations.
+    /// Node must be built with `seeded-rng` feature.
+    #[serde(default)]
+    pub rng_seed: Option<u64>,
+    /// Seed node announces that it keeps archives of inactive threads.
+    #[serde(default = "d_false")]
+    pub archive_volunteer: bool,
 }
 
 impl Default for NodeConfig {
@@ -244,6 +619,45 @@ pub struct PopularityConfig {
     /// Score threshold for "active" status.
     #[serde(default = "d_act_thr")]
     pub active_threshold: f64,
+    /// Max count of keys tracked by the metrics collector.
+    #[serde(default = "d_max_tracked")]
+    pub max_tracked_items: usize,
+    /// Count of metrics collector shards (keys are split by prefix).
+    #[serde(default = "d_metrics_shards")]
+    pub metrics_shards: usize,
+    /// Metrics without requests for this many days are dropped.
+    #[serde(default = "d_metrics_age")]
+    pub metrics_max_age_days: u64,
+    /// How often to drop old metrics, in seconds.
+    #[serde(default = "d_metrics_cleanup")]
+    pub metrics_cleanup_interval: i32,
+    /// Sliding window in seconds for trending detection.
+    #[serde(default = "d_trend_win")]
+    pub trending_window: i32,
+    /// Max count of view/engagement reports from one client per window.
+    #[serde(default = "d_report_lim")]
+    pub report_rate_limit: i32,
+    /// Max count of view/engagement reports for one key per window.
+    #[serde(default = "d_report_key_lim")]
+    pub report_rate_limit_per_key: i32,
+    /// Window size in seconds for report rate limiting.
+    #[serde(default = "d_report_win")]
+    pub report_rate_window: i32,
+    /// Max view time in seconds accepted in one report.
+    #[serde(default = "d_max_view")]
+    pub max_view_seconds: f64,
+    /// Score halves for every this many seconds without requests (0 disables decay).
+    #[serde(default = "d_decay_half_life")]
+    pub decay_half_life: i32,
+    /// Popular content whose score falls below this loses its TTL boost and extra replicas, content promoted before restart keeps them until its TTL expires.
+    #[serde(default = "d_demotion_thr")]
+    pub demotion_threshold: f64,
+    /// Replicate popular threads as one compressed bundle with their recent messages.
+    #[serde(default = "d_false")]
+    pub thread_bundles: bool,
+    /// Max count of recent messages in a thread bundle.
+    #[serde(default = "d_bundle_messages")]
+    pub thread_bundle_messages: usize,
 }
 
 impl Default for PopularityConfig {
@@ -270,12 +684,64 @@ pub struct SecurityConfig {
     /// Route traffic through the I2P network.
     #[serde(default = "d_false")]
     pub enable_i2p: bool,
-    /// Maximum allowed requests per window from a single peer.
+    /// Maximum allowed requests per window from all peers.
     #[serde(default = "d_rate_lim")]
     pub rate_limit_requests: i32,
+    /// Maximum allowed requests per window from a single peer.
+    #[serde(default = "d_rate_lim_node")]
+    pub rate_limit_per_node: i32,
     /// Window size in seconds for the rate limiter.
     #[serde(default = "d_rate_win")]
     pub rate_limit_window: i32,
+    /// Leading zero bits of handshake puzzle for new peers under load (0 disables puzzles).
+    #[serde(default = "d_puzzle_difficulty")]
+    pub puzzle_difficulty: u8,
+    /// Part of the rate limit after which new peers must solve puzzles.
+    #[serde(default = "d_puzzle_load")]
+    pub puzzle_load_threshold: f64,
+    /// Path to the append-only log of security events.
+    #[serde(default = "d_audit_file")]
+    pub audit_log_file: PathBuf,
+    /// Size in bytes after which the audit log is rotated.
+    #[serde(default = "d_audit_size")]
+    pub audit_log_max_size: u64,
+    /// Count of kept audit log files including the current one.
+    #[serde(default = "d_audit_files")]
+    pub audit_log_files: usize,
+    /// Max difference in seconds between message timestamp and our clock.
+    /// It is 600s wider for peers whose clock offset is not learned yet.
+    #[serde(default = "d_timestamp_window")]
+    pub timestamp_window: f64,
+    /// Reject messages with timestamps out of `timestamp_window` (disable only in tests).
+    #[serde(default = "d_true")]
+    pub check_timestamps: bool,
+    /// Paths to WASM modules of the content policy for records of other nodes.
+    #[serde(default)]
+    pub policy_plugins: Vec<PathBuf>,
+    /// Fuel of one plugin call, the call fails when fuel is over.
+    #[serde(default = "d_plugin_fuel")]
+    pub plugin_fuel: u64,
+    /// Max size in bytes of the linear memory of one plugin call, larger growth fails.
+    #[serde(default = "d_plugin_memory")]
+    pub plugin_memory: usize,
+    /// Records with lower score of any plugin are rejected (0.0 - only explicit rejects).
+    #[serde(default)]
+    pub policy_min_score: f64,
+    /// Hex node ID of the author of the subscribed community blocklist.
+    #[serde(default)]
+    pub blocklist_subscription: Option<String>,
+    /// Interval in seconds between refreshes of the subscribed blocklist.
+    #[serde(default = "d_blocklist_refresh")]
+    pub blocklist_refresh_interval: f64,
+    /// Hex node IDs of authors whose blocklist files are imported, besides our node and the subscribed author.
+    #[serde(default)]
+    pub blocklist_authors: Vec<String>,
+    /// Tokens of the admin API, one admin token is generated on the first start if empty.
+    #[serde(default)]
+    pub admin_tokens: Vec<AdminTokenConfig>,
+    /// File which receives the generated admin token (default `admin.token` in `storage.data_dir`).
+    #[serde(default)]
+    pub admin_token_file: Option<PathBuf>,
 }
 
 impl Default for SecurityConfig {
@@ -284,6 +750,59 @@ impl Default for SecurityConfig {
     }
 }
 
+impl SecurityConfig {
+    /// Refresh interval of the blocklist within a minute and a week, default for invalid one
+    pub fn blocklist_interval(&self) -> f64 {
+        let interval = self.blocklist_refresh_interval;
+        if interval.is_finite() {
+            interval.clamp(60.0, 7.0 * 86400.0)
+        } else {
+            d_blocklist_refresh()
+        }
+    }
+
+    /// TTL of the blocklist in the DHT, it outlives several refreshes
+    pub fn blocklist_ttl(&self) -> i32 {
+        (self.blocklist_interval() * 3.0) as i32
+    }
+}
+
+/// Token of the admin API with its access level.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdminTokenConfig {
+    /// Secret which clients of the admin API send with requests.
+    pub token: String,
+    /// Access level of the token (`read_only` or `admin`).
+    #[serde(default)]
+    pub role: AdminRole,
+}
+
+/// External endpoint which receives node events as JSON POST requests.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookConfig {
+    /// URL of the endpoint (`http://` or `https://`).
+    pub url: String,
+    /// Names of sent events (`thread_created`, `peer_blacklisted`, `storage_full`), all if empty.
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Seconds to wait for the endpoint answer.
+    #[serde(default = "d_webhook_timeout")]
+    pub timeout: f64,
+}
+
+/// Set seconds of the `name` field to `default` if they are not finite or out of range
+fn ensure_seconds(name: &str, seconds: &mut f64, min: f64, default: f64) {
+    if !(min..=MAX_DURATION_SECS).contains(seconds) {
+        warn!(
+            field = name,
+            value = *seconds,
+            default,
+            "Invalid config value, default is used"
+        );
+        *seconds = default;
+    }
+}
+
 /// The master configuration object for the entire Rhizome system.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
@@ -299,9 +818,15 @@ pub struct Config {
     pub popularity: PopularityConfig,
     #[serde(default)]
     pub security: SecurityConfig,
+    /// Endpoints which receive node events.
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
     /// Global logging level ("DEBUG", "INFO", "WARN", "ERROR").
     #[serde(default = "d_log_level")]
     pub log_level: String,
+    /// Levels of single subsystems over `log_level` (e.g. `network: DEBUG`, `storage: WARN`).
+    #[serde(default)]
+    pub log_levels: HashMap<String, String>,
     /// Optional path to the log file. If None, logs to stdout.
     pub log_file: Option<PathBuf>,
 }
@@ -310,7 +835,8 @@ impl Config {
     /// Loads the configuration from a YAML file and environment variables.
     ///
     /// It first attempts to load `.env` variables, then reads the specified YAML file.
-    /// Environment variables (like `LOG_LEVEL`) override settings found in the file.
+    /// Environment variables (like `LOG_LEVEL`) override settings found in the file,
+    /// `LOG_LEVELS=network=debug,storage=warn` overrides levels of single subsystems.
     /// If no file is found, it uses internal defaults for all parameters.
     ///
     /// # Arguments
@@ -331,10 +857,106 @@ impl Config {
         if let Ok(env_level) = env::var("LOG_LEVEL") {
             config.log_level = env_level;
         }
+        if let Ok(env_levels) = env::var("LOG_LEVELS") {
+            for (module, level) in env_levels
+                .split(',')
+                .filter_map(|directive| directive.split_once('='))
+            {
+                config
+                    .log_levels
+                    .insert(module.trim().to_string(), level.trim().to_string());
+            }
+        }
+
+        config.validate();
+        config
+    }
+
+    /// Replace values which the node can not use with their defaults
+    ///
+    /// Seconds are turned into durations or used as bounds of `clamp`, which panic on
+    /// infinite, NaN and negative values, so such values are not kept. Every replaced
+    /// value is logged.
+    pub fn validate(&mut self) {
+        ensure_seconds(
+            "dht.ping_timeout",
+            &mut self.dht.ping_timeout,
+            0.1,
+            d_ping_to(),
+        );
+        ensure_seconds(
+            "dht.request_timeout",
+            &mut self.dht.request_timeout,
+            0.1,
+            d_req_to(),
+        );
+        ensure_seconds(
+            "popularity.max_view_seconds",
+            &mut self.popularity.max_view_seconds,
+            0.0,
+            d_max_view(),
+        );
+        ensure_seconds(
+            "storage.write_back_interval",
+            &mut self.storage.write_back_interval,
+            0.01,
+            d_write_back_interval(),
+        );
+        for (index, hook) in self.webhooks.iter_mut().enumerate() {
+            ensure_seconds(
+                &format!("webhooks[{}].timeout", index),
+                &mut hook.timeout,
+                0.1,
+                d_webhook_timeout(),
+            );
+        }
+        self.webhooks.retain(|hook| {
+            let valid = hook.url.starts_with("http://") || hook.url.starts_with("https://");
+            if !valid {
+                warn!(url = %hook.url, "Webhook URL is not http or https, webhook is skipped");
+            }
+            valid
+        });
+    }
 
+    /// Copy of the config with files of the node under `dir`
+    ///
+    /// Relative paths of the data directory, identity, state, audit log and packet capture
+    /// are resolved against `dir`, so several nodes in one process do not share files.
+    /// Absolute paths are kept.
+    pub fn for_instance(&self, dir: &Path) -> Self {
+        let rebase = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        };
+
+        let mut config = self.clone();
+        rebase(&mut config.storage.data_dir);
+        rebase(&mut config.node.node_id_file);
+        rebase(&mut config.node.node_key_file);
+        rebase(&mut config.node.state_file);
+        rebase(&mut config.security.audit_log_file);
+        if let Some(path) = config.security.admin_token_file.as_mut() {
+            rebase(path);
+        }
+        if let Some(path) = config.network.capture_file.as_mut() {
+            rebase(path);
+        }
+        if let Some(path) = config.storage.client_store_dir.as_mut() {
+            rebase(path);
+        }
         config
     }
 
+    /// File which receives the generated admin token
+    pub fn admin_token_path(&self) -> PathBuf {
+        self.security
+            .admin_token_file
+            .clone()
+            .unwrap_or_else(|| self.storage.data_dir.join("admin.token"))
+    }
+
     /// Persists the current configuration state to a YAML file.
     ///
     /// # Errors
@@ -0,0 +1,92 @@
+use std::future::Future;
+use tokio::time::{Instant, timeout};
+
+use crate::utils::time::{Clock, duration_from_secs};
+
+/// Bounds of work of one DHT query
+///
+/// Lookups normally go on until no closer node is found, which can take many rounds in a
+/// large or unhealthy network. Budget lets latency-sensitive callers cap the worst case.
+/// Limits only lower the defaults of the node, `None` keeps them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueryBudget {
+    /// Max count of peers asked during the lookup, stores also go to at most this count of peers
+    pub max_peers: Option<usize>,
+    /// Max count of requests in flight _(capped by `alpha` for lookups)_
+    pub max_parallel: Option<usize>,
+    /// Time after which the query stops with what it has, by [`Clock::instant`] of the node
+    pub deadline: Option<Instant>,
+}
+
+impl QueryBudget {
+    /// Budget without limits
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Budget with deadline `seconds` from now by the clock _(invalid ones are clamped)_
+    pub fn with_timeout(mut self, clock: &dyn Clock, seconds: f64) -> Self {
+        self.deadline = Some(clock.instant() + duration_from_secs(seconds));
+        self
+    }
+
+    /// Count of parallel requests, `default` if not limited
+    pub fn parallel(&self, default: usize) -> usize {
+        self.max_parallel
+            .map_or(default, |limit| limit.clamp(1, default.max(1)))
+    }
+
+    /// Count of peers which may still be asked after `contacted` ones
+    pub fn peers_left(&self, contacted: usize) -> usize {
+        self.max_peers
+            .map_or(usize::MAX, |limit| limit.saturating_sub(contacted))
+    }
+
+    pub fn is_expired(&self, clock: &dyn Clock) -> bool {
+        self.deadline
+            .is_some_and(|deadline| clock.instant() >= deadline)
+    }
+
+    /// Run part of the query until the deadline by the clock _(None if the deadline passed)_
+    pub async fn run<F: Future>(&self, clock: &dyn Clock, future: F) -> Option<F::Output> {
+        match self.deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(clock.instant());
+                timeout(left, future).await.ok()
+            }
+            None => Some(future.await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::time::{SystemClock, VirtualClock};
+    use std::time::Duration;
+
+    #[test]
+    fn invalid_timeout_is_clamped() {
+        let clock = SystemClock;
+        let budget = QueryBudget::unlimited();
+        assert!(budget.with_timeout(&clock, f64::NAN).is_expired(&clock));
+        assert!(budget.with_timeout(&clock, -5.0).is_expired(&clock));
+        assert!(
+            !budget
+                .with_timeout(&clock, f64::INFINITY)
+                .is_expired(&clock)
+        );
+        assert!(!budget.with_timeout(&clock, 1e300).is_expired(&clock));
+    }
+
+    #[tokio::test]
+    async fn deadline_follows_the_clock_of_the_node() {
+        let clock = VirtualClock::new(1000.0);
+        let budget = QueryBudget::unlimited().with_timeout(&clock, 10.0);
+        assert!(!budget.is_expired(&clock));
+
+        clock.advance(Duration::from_secs(11));
+        assert!(budget.is_expired(&clock));
+        assert_eq!(budget.run(&clock, std::future::pending::<()>()).await, None);
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::dht::node::Node;
+use crate::storage::keys::Key;
+use crate::utils::time::{Clock, SystemClock};
+
+/// Max count of remembered holders of one key
+pub const MAX_HOLDERS_PER_KEY: usize = 3;
+/// Max count of keys with pointers, pointers which expire first are dropped on overflow
+pub const HOLDER_POINTERS_CAPACITY: usize = 10_000;
+
+/// Peers which hold values that our node refused to store
+///
+/// Light and mobile nodes often are among the closest nodes of a key, but have no space
+/// for its value. Instead of the value they keep a pointer to the peer which sent it and
+/// answer FIND_VALUE by the pointers, so lookups ending at them still find the value.
+pub struct HolderPointers {
+    /// Max time of life of a pointer in seconds
+    max_ttl: f64,
+    /// Key -> (holder, time until which the pointer is valid)
+    pointers: RwLock<HashMap<Key, Vec<(Node, f64)>>>,
+    /// Clock of the node
+    time: Arc<dyn Clock>,
+}
+
+impl HolderPointers {
+    pub fn new(max_ttl: f64) -> Self {
+        Self {
+            max_ttl: max_ttl.max(0.0),
+            pointers: RwLock::new(HashMap::new()),
+            time: Arc::new(SystemClock),
+        }
+    }
+
+    /// Same pointers which expire by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
+
+    /// Remember that `holder` keeps the value for `ttl` seconds
+    pub fn record(&self, key: &Key, holder: Node, ttl: i32) {
+        let now = self.time.now();
+        let expires_at = now + (ttl.max(0) as f64).min(self.max_ttl);
+        let mut pointers = self.pointers.write().unwrap();
+        if pointers.len() >= HOLDER_POINTERS_CAPACITY && !pointers.contains_key(key) {
+            pointers.retain(|_, holders| holders.iter().any(|(_, expires)| *expires > now));
+            if pointers.len() >= HOLDER_POINTERS_CAPACITY
+                && let Some(oldest) = pointers
+                    .iter()
+                    .min_by(|a, b| Self::last_expiry(a.1).total_cmp(&Self::last_expiry(b.1)))
+                    .map(|(key, _)| key.clone())
+            {
+                pointers.remove(&oldest);
+            }
+        }
+
+        let holders = pointers.entry(key.clone()).or_default();
+        holders.retain(|(node, expires)| *expires > now && node.node_id != holder.node_id);
+        holders.push((holder, expires_at));
+        if holders.len() > MAX_HOLDERS_PER_KEY {
+            holders.sort_by(|a, b| b.1.total_cmp(&a.1));
+            holders.truncate(MAX_HOLDERS_PER_KEY);
+        }
+    }
+
+    /// Holders of the key whose pointers are still valid
+    pub fn holders(&self, key: &Key) -> Vec<Node> {
+        let now = self.time.now();
+        self.pointers
+            .read()
+            .unwrap()
+            .get(key)
+            .map(|holders| {
+                holders
+                    .iter()
+                    .filter(|(_, expires)| *expires > now)
+                    .map(|(node, _)| node.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Remove expired pointers, return count of removed keys
+    pub fn cleanup_expired(&self) -> usize {
+        let now = self.time.now();
+        let mut pointers = self.pointers.write().unwrap();
+        let before = pointers.len();
+        pointers.retain(|_, holders| {
+            holders.retain(|(_, expires)| *expires > now);
+            !holders.is_empty()
+        });
+        before - pointers.len()
+    }
+
+    fn last_expiry(holders: &[(Node, f64)]) -> f64 {
+        holders
+            .iter()
+            .map(|(_, expires)| *expires)
+            .fold(0.0, f64::max)
+    }
+}
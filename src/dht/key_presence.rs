@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::storage::keys::Key;
+use crate::utils::bloom::BloomFilter;
+use crate::utils::time::get_now_f64;
+
+/// Filters of peers which were not refreshed for this time are forgotten
+pub const KEY_FILTER_EXPIRY_SECONDS: f64 = 3600.0;
+/// False positive rate of our advertised filter
+pub const KEY_FILTER_FP_RATE: f64 = 0.01;
+
+/// Bloom filters of stored keys of our node and our peers
+///
+/// Peers send their filters during popularity exchange. The index is used as a hint:
+/// `find_value` asks first peers which likely have the key, and replication estimates
+/// count of replicas without probing every peer.
+#[derive(Default)]
+pub struct KeyPresenceIndex {
+    /// Filter of our own storage
+    local: RwLock<Option<BloomFilter>>,
+    /// Peer node ID -> (filter, time when we received it)
+    peers: RwLock<HashMap<Vec<u8>, (BloomFilter, f64)>>,
+}
+
+impl KeyPresenceIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild filter of our storage
+    ///
+    /// Storage with too many keys for a filter within one message is not advertised.
+    pub fn set_local_keys(&self, keys: &[Key]) {
+        let filter = BloomFilter::from_keys(keys.iter(), KEY_FILTER_FP_RATE);
+        *self.local.write().unwrap() = filter;
+    }
+
+    /// Our filter in compressed form for sending
+    pub fn local_compressed(&self) -> Option<Vec<u8>> {
+        self.local
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|f| f.to_compressed())
+    }
+
+    /// Save filter received from peer
+    ///
+    /// Saturated filter would make the peer a likely holder of every key, so it replaces
+    /// the previous filter of the peer with nothing.
+    pub fn record_peer(&self, peer_id: Vec<u8>, filter: BloomFilter) {
+        let now = get_now_f64();
+        let mut peers = self.peers.write().unwrap();
+        peers.retain(|_, (_, received)| now - *received <= KEY_FILTER_EXPIRY_SECONDS);
+        if filter.is_saturated() {
+            peers.remove(&peer_id);
+            return;
+        }
+        peers.insert(peer_id, (filter, now));
+    }
+
+    /// Check that peer likely has the key
+    pub fn peer_may_have(&self, peer_id: &[u8], key: &[u8]) -> bool {
+        let now = get_now_f64();
+        self.peers
+            .read()
+            .unwrap()
+            .get(peer_id)
+            .is_some_and(|(filter, received)| {
+                now - *received <= KEY_FILTER_EXPIRY_SECONDS && filter.contains(key)
+            })
+    }
+
+    /// Estimated count of peers which store the key
+    pub fn estimate_coverage(&self, key: &[u8]) -> usize {
+        let now = get_now_f64();
+        self.peers
+            .read()
+            .unwrap()
+            .values()
+            .filter(|(filter, received)| {
+                now - *received <= KEY_FILTER_EXPIRY_SECONDS && filter.contains(key)
+            })
+            .count()
+    }
+
+    /// Count of peers with known filters
+    pub fn peer_count(&self) -> usize {
+        self.peers.read().unwrap().len()
+    }
+}
@@ -1,3 +1,17 @@
+/// Bounds of work of one DHT query
+pub mod budget;
+/// Pointers to peers which hold values refused by our node
+///
+/// Light and mobile nodes answer lookups by them instead of the value
+pub mod holder_pointers;
+/// Bloom filters of keys stored by peers
+///
+/// Hints for lookup and replication which peers likely have the key
+pub mod key_presence;
+/// Short memory of keys which were not found
+///
+/// Repeated lookups of missing keys wait for exponential backoff
+pub mod negative_cache;
 /// Basic description of node in Kademlia DHT
 ///
 /// Describe `Who`
@@ -6,6 +20,10 @@
 /// - `NodeId` - uniq identifier in Kademlia DHT network
 /// - `Node` - implementation of Kademlia DHT node with state, last seen and TTL
 pub mod node;
+/// Detection of healed network partitions
+///
+/// Sudden influx of unknown far-away nodes triggers republish and popularity exchange
+pub mod partition;
 /// Realization of Kademlia Work
 ///
 /// Describe `How`
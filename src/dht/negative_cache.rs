@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::storage::keys::Key;
+use crate::utils::time::{Clock, SystemClock};
+
+/// Max count of remembered missing keys, the oldest are dropped first
+pub const NEGATIVE_CACHE_CAPACITY: usize = 4096;
+
+/// Misses of one key
+#[derive(Debug, Clone, Copy)]
+struct Miss {
+    /// Count of lookups in a row which did not find the key
+    count: u32,
+    /// Time before which the key is answered as missing without lookup
+    retry_at: f64,
+}
+
+/// Short memory of keys which were not found in the network
+///
+/// Clients poll for threads which are not propagated yet, and every poll would be a full
+/// lookup. After a miss the key is answered as missing for `base` seconds, the delay is
+/// doubled with every next miss up to `max`. Found or locally stored key is forgotten.
+pub struct NegativeCache {
+    base: f64,
+    max: f64,
+    misses: Mutex<HashMap<Key, Miss>>,
+    /// Clock of the node
+    time: Arc<dyn Clock>,
+}
+
+impl NegativeCache {
+    pub fn new(base: f64, max: f64) -> Self {
+        Self {
+            base: base.max(0.0),
+            max: max.max(base),
+            misses: Mutex::new(HashMap::new()),
+            time: Arc::new(SystemClock),
+        }
+    }
+
+    /// Same cache which counts delays by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
+
+    /// Key was missing recently, the lookup should not be repeated yet
+    pub fn is_missing(&self, key: &Key) -> bool {
+        let now = self.time.now();
+        self.misses
+            .lock()
+            .unwrap()
+            .get(key)
+            .is_some_and(|miss| now < miss.retry_at)
+    }
+
+    /// Lookup did not find the key, the next lookup is postponed
+    pub fn record_miss(&self, key: &Key) {
+        if self.base <= 0.0 {
+            return;
+        }
+        let now = self.time.now();
+        let mut misses = self.misses.lock().unwrap();
+        if misses.len() >= NEGATIVE_CACHE_CAPACITY && !misses.contains_key(key) {
+            // Keys which may be asked again first are the least useful
+            misses.retain(|_, miss| miss.retry_at > now);
+            if misses.len() >= NEGATIVE_CACHE_CAPACITY
+                && let Some(oldest) = misses
+                    .iter()
+                    .min_by(|a, b| a.1.retry_at.total_cmp(&b.1.retry_at))
+                    .map(|(key, _)| key.clone())
+            {
+                misses.remove(&oldest);
+            }
+        }
+
+        let miss = misses.entry(key.clone()).or_insert(Miss {
+            count: 0,
+            retry_at: now,
+        });
+        let delay = (self.base * 2f64.powi(miss.count.min(30) as i32)).min(self.max);
+        miss.count = miss.count.saturating_add(1);
+        miss.retry_at = now + delay;
+    }
+
+    /// Key was found or stored, forget its misses
+    pub fn forget(&self, key: &Key) {
+        self.misses.lock().unwrap().remove(key);
+    }
+
+    /// Forget all misses _(e.g. after the node joined a bigger network)_
+    pub fn clear(&self) {
+        self.misses.lock().unwrap().clear();
+    }
+}
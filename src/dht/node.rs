@@ -1,8 +1,12 @@
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::str::FromStr;
 
 use crate::utils::crypto::compute_distance;
-use crate::utils::time::get_now_f64;
+use crate::utils::rng::NodeRng;
 
 /// 160-bits node identifier for Kademlia DHT Network
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -14,6 +18,21 @@ impl NodeID {
         Self(id)
     }
 
+    /// Position of the data key in the ID space: first 20 bytes padded with zeros
+    pub fn from_key(key: &[u8]) -> Self {
+        let mut id = [0u8; 20];
+        let len = key.len().min(20);
+        id[..len].copy_from_slice(&key[..len]);
+        Self(id)
+    }
+
+    /// Random identifier from the node generator
+    pub fn random(rng: &NodeRng) -> Self {
+        let mut id = [0u8; 20];
+        rng.fill_bytes(&mut id);
+        Self(id)
+    }
+
     /// Calculate XOR-distance between nodes
     pub fn distance_to(&self, other: &NodeID) -> [u8; 20] {
         let dist_vec = compute_distance(&self.0, &other.0);
@@ -21,6 +40,15 @@ impl NodeID {
         res.copy_from_slice(&dist_vec[..20]);
         res
     }
+
+    /// Length of common prefix of the IDs, nodes with the same one share a k-bucket
+    pub fn prefix_len(&self, other: &NodeID) -> usize {
+        let distance = self.distance_to(other);
+        match distance.iter().position(|byte| *byte != 0) {
+            Some(i) => i * 8 + distance[i].leading_zeros() as usize,
+            None => distance.len() * 8,
+        }
+    }
 }
 
 /// Create beautiful output on Debug mode
@@ -32,6 +60,108 @@ impl fmt::Debug for NodeID {
     }
 }
 
+/// Full identifier in hex
+impl fmt::Display for NodeID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+/// Parse identifier from 40 hex chars
+impl FromStr for NodeID {
+    type Err = hex::FromHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut id = [0u8; 20];
+        hex::decode_to_slice(s, &mut id)?;
+        Ok(Self(id))
+    }
+}
+
+/// On the wire identifier is a hex string
+impl Serialize for NodeID {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(self.0))
+    }
+}
+
+/// Hex string is expected, array of bytes from older nodes is also accepted
+impl<'de> Deserialize<'de> for NodeID {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NodeIDVisitor;
+
+        impl<'de> Visitor<'de> for NodeIDVisitor {
+            type Value = NodeID;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("20 bytes node ID as hex string or array")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<NodeID, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<NodeID, E> {
+                let id = v
+                    .try_into()
+                    .map_err(|_| E::invalid_length(v.len(), &self))?;
+                Ok(NodeID(id))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<NodeID, A::Error> {
+                let mut id = [0u8; 20];
+                for (i, byte) in id.iter_mut().enumerate() {
+                    *byte = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                }
+                if seq.next_element::<u8>()?.is_some() {
+                    return Err(de::Error::invalid_length(21, &self));
+                }
+                Ok(NodeID(id))
+            }
+        }
+
+        deserializer.deserialize_any(NodeIDVisitor)
+    }
+}
+
+/// Kind of the network path to the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointKind {
+    /// Address in the local network
+    Lan,
+    /// Public address
+    Wan,
+    /// UDP address of a relay which forwards packets to the node
+    Relay,
+    /// Onion service address, reachable only through Tor
+    Onion,
+}
+
+/// One of the addresses advertised by the node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub kind: EndpointKind,
+    /// IP address or onion host
+    pub address: String,
+    pub port: u16,
+    /// Endpoints with lower value are tried first
+    #[serde(default)]
+    pub priority: u8,
+}
+
+impl Endpoint {
+    /// Address for direct UDP packets _(None for onion and not parsed endpoints)_
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        if self.kind == EndpointKind::Onion {
+            return None;
+        }
+        format!("{}:{}", self.address, self.port).parse().ok()
+    }
+}
+
 /// Node in the network
 #[derive(Clone, Debug, PartialEq)]
 pub struct Node {
@@ -45,29 +175,33 @@ pub struct Node {
     pub last_seen: f64,
     /// Counter of bad requests to the node _(work like TTL in ipv4)_
     pub failed_pings: u32,
+    /// Smoothed round-trip time in seconds _(None if we did not measure it yet)_
+    pub rtt: Option<f64>,
+    /// Other endpoints advertised by the node, `address` and `port` are the active one
+    pub endpoints: Vec<Endpoint>,
 }
 
 impl Node {
     /// Create new node
     ///
-    /// Last seen of node is now after node create
+    /// Node is not seen yet, routing table marks it as seen when adds it
     pub fn new(node_id: NodeID, address: String, port: u16) -> Self {
-        let now = get_now_f64();
-
         Self {
             node_id,
             address,
             port,
-            last_seen: now,
+            last_seen: 0.0,
             failed_pings: 0,
+            rtt: None,
+            endpoints: Vec::new(),
         }
     }
 
     /// Update node time
     ///
-    /// Call if we have some pings from node
-    pub fn update_seen(&mut self) {
-        self.last_seen = get_now_f64();
+    /// Call if we have some pings from node at `now`
+    pub fn update_seen(&mut self, now: f64) {
+        self.last_seen = now;
         self.failed_pings = 0;
     }
 
@@ -78,11 +212,34 @@ impl Node {
         self.failed_pings += 1;
     }
 
+    /// Save measured round-trip time
+    ///
+    /// Value is smoothed like TCP SRTT, so one slow answer does not change it a lot
+    pub fn record_rtt(&mut self, sample: f64) {
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => rtt * 0.875 + sample * 0.125,
+            None => sample,
+        });
+    }
+
+    /// Reachable advertised addresses except the active one, the best first
+    pub fn fallback_addrs(&self) -> Vec<SocketAddr> {
+        let active: Option<SocketAddr> = format!("{}:{}", self.address, self.port).parse().ok();
+        let mut endpoints: Vec<&Endpoint> = self.endpoints.iter().collect();
+        endpoints.sort_by_key(|e| e.priority);
+        let mut addrs: Vec<SocketAddr> = Vec::new();
+        for addr in endpoints.into_iter().filter_map(Endpoint::socket_addr) {
+            if Some(addr) != active && !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+        addrs
+    }
+
     /// Check is node valid
     ///
-    /// Function compare current time with time of last seen of the node
-    pub fn is_stale(&self, timeout: f64) -> bool {
-        let now = get_now_f64();
+    /// Function compare `now` with time of last seen of the node
+    pub fn is_stale(&self, timeout: f64, now: f64) -> bool {
         (now - self.last_seen) > timeout
     }
 }
@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::net::IpAddr;
+
+use crate::dht::node::NodeID;
+use crate::security::blacklist::Subnet;
+
+/// Peers sharing fewer prefix bits with us are far-away _(3/4 of the ID space)_
+const FAR_PREFIX_BITS: usize = 2;
+/// Seconds after detected merge when the next one is not reported
+///
+/// Every merge costs a republish of all our keys, so it is reported rarely.
+const MERGE_COOLDOWN: f64 = 1800.0;
+
+/// Detector of healed network partitions
+///
+/// While the network is split, each side fills its routing tables only with its own nodes.
+/// When the split heals, nodes of the other side join our table all at once and most of
+/// them are far from us. Arrival of `threshold` unknown far-away nodes from different
+/// subnets within `window` seconds is taken as a merge, so data and rankings can
+/// reconverge at once instead of waiting for periodic timers. Node IDs are chosen by the
+/// nodes themselves, so only nodes which answered our ping should be recorded and one
+/// subnet is counted once.
+pub struct PartitionDetector {
+    node_id: NodeID,
+    threshold: usize,
+    window: f64,
+    /// Arrival times of far-away nodes within the window with their subnets
+    arrivals: VecDeque<(f64, Subnet)>,
+    last_merge: Option<f64>,
+}
+
+impl PartitionDetector {
+    pub fn new(node_id: NodeID, threshold: usize, window: f64) -> Self {
+        Self {
+            node_id,
+            threshold,
+            window,
+            arrivals: VecDeque::new(),
+            last_merge: None,
+        }
+    }
+
+    /// Node is far from us in the ID space
+    pub fn is_far(&self, node_id: &NodeID) -> bool {
+        let distance = self.node_id.distance_to(node_id);
+        let prefix = match distance.iter().position(|byte| *byte != 0) {
+            Some(i) => i * 8 + distance[i].leading_zeros() as usize,
+            None => distance.len() * 8,
+        };
+        prefix < FAR_PREFIX_BITS
+    }
+
+    /// Arrival of the node at `now` would be counted
+    ///
+    /// Nodes which are near, from an already counted subnet or which arrive during the
+    /// cooldown are not, so they need no check.
+    pub fn counts(&mut self, node_id: &NodeID, address: IpAddr, now: f64) -> bool {
+        if self.threshold == 0
+            || !self.is_far(node_id)
+            || self
+                .last_merge
+                .is_some_and(|merged| now - merged < MERGE_COOLDOWN)
+        {
+            return false;
+        }
+        self.expire(now);
+        let subnet = Subnet::group(address);
+        !self.arrivals.iter().any(|(_, counted)| *counted == subnet)
+    }
+
+    /// Record verified node which was added to routing table at `now`
+    ///
+    /// Return `true` if the arrival completes a merge.
+    pub fn record_arrival(&mut self, node_id: &NodeID, address: IpAddr, now: f64) -> bool {
+        if !self.counts(node_id, address, now) {
+            return false;
+        }
+        self.arrivals.push_back((now, Subnet::group(address)));
+
+        if self.arrivals.len() < self.threshold {
+            return false;
+        }
+        self.arrivals.clear();
+        self.last_merge = Some(now);
+        true
+    }
+
+    fn expire(&mut self, now: f64) {
+        while self
+            .arrivals
+            .front()
+            .is_some_and(|(arrived, _)| now - arrived > self.window)
+        {
+            self.arrivals.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_needs_far_nodes_from_different_subnets() {
+        let mut detector = PartitionDetector::new(NodeID::new([0u8; 20]), 3, 60.0);
+        let far = |n: u8| {
+            let mut id = [0u8; 20];
+            id[0] = 0xF0;
+            id[19] = n;
+            NodeID::new(id)
+        };
+        let address = |a: &str| a.parse::<IpAddr>().unwrap();
+
+        // Many IDs from one subnet are one arrival
+        for n in 0..10 {
+            assert!(!detector.record_arrival(&far(n), address("203.0.113.1"), 1.0));
+        }
+        let near = NodeID::new([1u8; 20]);
+        assert!(!detector.record_arrival(&near, address("198.51.100.1"), 2.0));
+        assert!(!detector.record_arrival(&far(10), address("198.51.100.1"), 2.0));
+        assert!(detector.record_arrival(&far(11), address("192.0.2.1"), 3.0));
+
+        // Merges are not reported again during the cooldown
+        assert!(!detector.counts(&far(12), address("10.0.0.1"), 4.0));
+    }
+}
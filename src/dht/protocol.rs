@@ -1,14 +1,142 @@
 use async_trait::async_trait;
 use futures::future::join_all;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::sync::RwLock;
-use tracing::debug;
+use tracing::{Instrument, debug};
 
+use crate::dht::budget::QueryBudget;
+use crate::dht::key_presence::KeyPresenceIndex;
+use crate::dht::negative_cache::NegativeCache;
 use crate::dht::node::{Node, NodeID};
 use crate::dht::routing_table::RoutingTable;
 use crate::exceptions::{DHTError, RhizomeError};
-use crate::storage::main::Storage;
+use crate::replication::receipt::StorageReceipt;
+use crate::security::reputation::ReputationTable;
+use crate::storage::keys::Key;
+use crate::storage::main::{CasOutcome, Storage, StoreOrigin, StoredValue, TypedRecord};
+use crate::storage::proof::PublisherProof;
+use crate::utils::crypto::hash_key;
+
+/// TTL of the values which are stored by read repair
+pub const READ_REPAIR_TTL: i32 = 86400;
+
+/// Count of local records read at once by the crawl
+const LOCAL_RECORDS_PAGE: usize = 256;
+
+/// Value returned by remote node with its version info
+#[derive(Debug, Clone)]
+pub struct VersionedValue {
+    pub value: Vec<u8>,
+    /// Time when the remote node stored the value _(0.0 if unknown)_
+    pub stored_at: f64,
+    /// Time signed by the original publisher in `proof` _(0.0 if the value is not proven)_
+    ///
+    /// Unlike `stored_at` it is the same on every replica of one publish.
+    pub published_at: f64,
+    /// Time until which the remote node keeps the value _(None if unknown)_
+    pub expires_at: Option<f64>,
+    /// Node ID of the original publisher _(empty if unknown)_
+    pub publisher: Vec<u8>,
+    /// Type of the record set by publisher _(empty if untyped)_
+    pub record_type: String,
+    /// Signature of the publisher verified against the value _(None if there is no valid one)_
+    pub proof: Option<PublisherProof>,
+    /// Holder which the asked node pointed to and which gave the value _(None if the
+    /// asked node gave it)_
+    pub holder: Option<Node>,
+}
+
+/// Found value with information about its source
+#[derive(Debug, Clone)]
+pub struct ValueWithMeta {
+    pub value: Vec<u8>,
+    /// Node which supplied the value _(None if value is from our storage)_
+    pub source: Option<Node>,
+    /// Time when the source stored the value _(0.0 if unknown)_
+    pub stored_at: f64,
+    /// Time until which the source keeps the value _(None if unknown)_
+    pub expires_at: Option<f64>,
+    /// Node ID of the original publisher _(empty if unknown)_
+    pub publisher: Vec<u8>,
+}
+
+impl ValueWithMeta {
+    fn new(versioned: VersionedValue, source: Option<Node>) -> Self {
+        Self {
+            value: versioned.value,
+            source: versioned.holder.or(source),
+            stored_at: versioned.stored_at,
+            expires_at: versioned.expires_at,
+            publisher: versioned.publisher,
+        }
+    }
+}
+
+impl VersionedValue {
+    /// Origin of the value proven by the publisher _(None without valid proof)_
+    pub fn proven_origin(&self) -> Option<StoreOrigin> {
+        let proof = self.proof.clone()?;
+        Some(StoreOrigin {
+            publisher: self.publisher.clone(),
+            published_at: proof.signed_at,
+            hops: 1,
+            record_type: self.record_type.clone(),
+            proof: Some(proof),
+        })
+    }
+}
+
+impl From<StoredValue> for VersionedValue {
+    /// Stored proof was verified when the value was stored
+    fn from(stored: StoredValue) -> Self {
+        Self {
+            value: stored.value,
+            stored_at: stored.stored_at,
+            published_at: stored.origin.proof.as_ref().map_or(0.0, |p| p.signed_at),
+            expires_at: (stored.expires_at < f64::MAX).then_some(stored.expires_at),
+            publisher: stored.origin.publisher,
+            record_type: stored.origin.record_type,
+            proof: stored.origin.proof,
+            holder: None,
+        }
+    }
+}
+
+/// Consistency level of the read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadConsistency {
+    /// Return the first found value
+    One,
+    /// Collect answers from `R` replicas between k-closest nodes and return the newest value
+    Quorum(usize),
+}
+
+/// Result of STORE on the closest nodes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StoreResult {
+    /// Count of remote nodes which were asked to store the value
+    pub attempted: usize,
+    /// Count of remote nodes which accepted the value
+    pub succeeded: usize,
+    /// Nodes which did not accept the value, the closest first
+    pub closest_failed: Vec<NodeID>,
+}
+
+impl StoreResult {
+    /// Value is on at least one remote node, or only local when there are no peers
+    pub fn is_stored(&self) -> bool {
+        self.meets(1)
+    }
+
+    /// At least `factor` remote nodes accepted the value _(or all asked nodes if fewer)_
+    pub fn meets(&self, factor: usize) -> bool {
+        self.succeeded >= factor.min(self.attempted)
+    }
+}
 
 /// Interface of the Network protocol for avoid cycle refs
 ///
@@ -23,16 +151,132 @@ pub trait NetworkProtocolTrait: Send + Sync {
     ) -> Result<Vec<Node>, RhizomeError>;
     async fn find_value(
         &self,
-        key: &[u8],
+        key: &Key,
         remote_node: &Node,
     ) -> Result<Option<Vec<u8>>, RhizomeError>;
     async fn store(
         &self,
-        key: &[u8],
+        key: &Key,
         value: &[u8],
         ttl: i32,
         remote_node: &Node,
     ) -> Result<bool, RhizomeError>;
+    /// Store value on remote node with information about its original publisher
+    async fn store_with_origin(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        _origin: &StoreOrigin,
+        remote_node: &Node,
+    ) -> Result<bool, RhizomeError> {
+        self.store(key, value, ttl, remote_node).await
+    }
+    /// Store value on remote node and get its signed receipt _(None if node does not sign)_
+    async fn store_with_receipt(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+        remote_node: &Node,
+    ) -> Result<(bool, Option<StorageReceipt>), RhizomeError> {
+        Ok((
+            self.store_with_origin(key, value, ttl, origin, remote_node)
+                .await?,
+            None,
+        ))
+    }
+    /// Store value on several remote nodes, results are in the order of `nodes`
+    ///
+    /// Transport may send the whole round by one batch instead of one write per node.
+    async fn store_many_with_receipts(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+        nodes: &[Node],
+    ) -> Vec<(bool, Option<StorageReceipt>)> {
+        join_all(
+            nodes
+                .iter()
+                .map(|node| self.store_with_receipt(key, value, ttl, origin, node)),
+        )
+        .await
+        .into_iter()
+        .map(|result| result.unwrap_or((false, None)))
+        .collect()
+    }
+    /// Save value on remote node only if its current value has `expected_hash`
+    #[allow(clippy::too_many_arguments)]
+    async fn store_cas(
+        &self,
+        key: &Key,
+        expected_hash: Option<[u8; 32]>,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+        remote_node: &Node,
+    ) -> Result<CasOutcome, RhizomeError>;
+    /// Find values of several keys on remote node in one round trip
+    ///
+    /// Return only found values
+    async fn find_values(
+        &self,
+        keys: &[Key],
+        remote_node: &Node,
+    ) -> Result<HashMap<Key, Vec<u8>>, RhizomeError> {
+        let mut found = HashMap::new();
+        for key in keys {
+            if let Some(value) = self.find_value(key, remote_node).await? {
+                found.insert(key.clone(), value);
+            }
+        }
+        Ok(found)
+    }
+    /// Get records of the type published after `since` from remote node
+    async fn list_records(
+        &self,
+        _record_type: &str,
+        _since: f64,
+        _remote_node: &Node,
+    ) -> Result<Vec<TypedRecord>, RhizomeError> {
+        Ok(Vec::new())
+    }
+    /// Replace value on remote node with tombstone, `origin.published_at` is the time of
+    /// deletion
+    async fn delete(
+        &self,
+        _key: &Key,
+        _ttl: i32,
+        _origin: &StoreOrigin,
+        _remote_node: &Node,
+    ) -> Result<bool, RhizomeError> {
+        Ok(false)
+    }
+    /// Attach signature of our node to the origin of a record which our node publishes
+    fn sign_origin(&self, _key: &Key, _value: Option<&[u8]>, _origin: &mut StoreOrigin) {}
+    /// Find value with the time when remote node stored it
+    async fn find_value_versioned(
+        &self,
+        key: &Key,
+        remote_node: &Node,
+    ) -> Result<Option<VersionedValue>, RhizomeError> {
+        Ok(self
+            .find_value(key, remote_node)
+            .await?
+            .map(|value| VersionedValue {
+                value,
+                stored_at: 0.0,
+                published_at: 0.0,
+                expires_at: None,
+                publisher: Vec::new(),
+                record_type: String::new(),
+                proof: None,
+                holder: None,
+            }))
+    }
 }
 
 /// The heart of the Kademlia DHT protocol
@@ -45,6 +289,16 @@ pub struct DHTProtocol {
     pub network_protocol: Option<Arc<dyn NetworkProtocolTrait>>,
     /// Parallelism parameter _(usually 3)_
     pub alpha: usize,
+    /// Max count of nodes which get found value by read repair _(0 - disabled)_
+    pub read_repair_limit: usize,
+    /// Bloom filters of peers for asking first nodes which likely have the key
+    pub key_presence: Option<Arc<KeyPresenceIndex>>,
+    /// Scores of peers, lookups ask peers with bad reputation last
+    pub reputation: Option<Arc<ReputationTable>>,
+    /// Keys recently not found, repeated lookups of them are answered without network
+    pub negative_cache: Option<Arc<NegativeCache>>,
+    /// Bits of time of the last lookup answered by a peer _(0 - never)_
+    last_lookup: AtomicU64,
 }
 
 impl DHTProtocol {
@@ -58,6 +312,35 @@ impl DHTProtocol {
             storage,
             network_protocol,
             alpha: 3,
+            read_repair_limit: 2,
+            key_presence: None,
+            reputation: None,
+            negative_cache: None,
+            last_lookup: AtomicU64::new(0),
+        }
+    }
+
+    /// Time of the last lookup answered by a peer _(None if there was no such lookup)_
+    pub fn last_lookup_at(&self) -> Option<f64> {
+        let bits = self.last_lookup.load(Ordering::Relaxed);
+        (bits != 0).then(|| f64::from_bits(bits))
+    }
+
+    fn record_lookup(&self) {
+        self.last_lookup
+            .store(self.storage.now().to_bits(), Ordering::Relaxed);
+    }
+
+    /// Key was not found recently and its backoff is not over
+    fn is_known_missing(&self, key: &Key) -> bool {
+        self.negative_cache
+            .as_ref()
+            .is_some_and(|cache| cache.is_missing(key))
+    }
+
+    fn forget_missing(&self, key: &Key) {
+        if let Some(cache) = &self.negative_cache {
+            cache.forget(key);
         }
     }
 
@@ -70,14 +353,14 @@ impl DHTProtocol {
         if let Some(net) = &self.network_protocol {
             let result = net.ping(node).await;
             if result {
-                node.update_seen();
+                node.update_seen(self.storage.now());
             } else {
                 node.record_failed_ping();
             }
             result
         } else {
             tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-            node.update_seen();
+            node.update_seen(self.storage.now());
             true
         }
     }
@@ -86,10 +369,25 @@ impl DHTProtocol {
     ///
     /// Algorithm finds the closest nodes for our node by using our alpha
     pub async fn find_node(&self, target_id: &NodeID) -> Result<Vec<Node>, RhizomeError> {
+        self.find_node_with_budget(target_id, &QueryBudget::unlimited())
+            .await
+    }
+
+    /// Same as [`DHTProtocol::find_node`] within the budget
+    ///
+    /// When the budget is over, the closest nodes found so far are returned.
+    pub async fn find_node_with_budget(
+        &self,
+        target_id: &NodeID,
+        budget: &QueryBudget,
+    ) -> Result<Vec<Node>, RhizomeError> {
+        let parallel = budget.parallel(self.alpha);
         let mut closest = {
             let rt = self.routing_table.read().await;
-            rt.find_closest_nodes(target_id, self.alpha)
+            rt.find_closest_nodes(target_id, rt.k)
         };
+        self.sort_for_lookup(&mut closest, target_id);
+        closest.truncate(self.alpha);
 
         let net = match &self.network_protocol {
             Some(n) => n,
@@ -104,7 +402,7 @@ impl DHTProtocol {
             let candidates: Vec<Node> = closest
                 .iter()
                 .filter(|n| !queried.contains(&n.node_id))
-                .take(self.alpha)
+                .take(parallel.min(budget.peers_left(queried.len())))
                 .cloned()
                 .collect();
 
@@ -117,7 +415,12 @@ impl DHTProtocol {
                 tasks.push(net.find_node(target_id, node));
             }
 
-            let results = join_all(tasks).await;
+            let Some(results) = budget.run(&**self.storage.clock(), join_all(tasks)).await else {
+                break;
+            };
+            if results.iter().any(|result| result.is_ok()) {
+                self.record_lookup();
+            }
             let mut new_nodes_found = false;
 
             for found_nodes in results.into_iter().flatten() {
@@ -136,7 +439,7 @@ impl DHTProtocol {
             }
 
             let mut all_found: Vec<Node> = seen_nodes.values().cloned().collect();
-            all_found.sort_by_key(|n| n.node_id.distance_to(target_id));
+            self.sort_for_lookup(&mut all_found, target_id);
             closest = all_found.into_iter().take(self.alpha).collect();
 
             if !new_nodes_found {
@@ -152,11 +455,38 @@ impl DHTProtocol {
     /// Firstly we should check in our local storage:
     /// - If we have the value we will return them
     ///  
+    /// If peers advertised Bloom filters with the key, we ask them first.
+    ///
     /// If we do not have data we start iterative find.
-    /// If some node send signal we choose the data and return them.
-    pub async fn find_value(&self, key: &[u8]) -> Result<Vec<u8>, RhizomeError> {
-        if let Some(val) = self.storage.get(key.to_vec()).await? {
-            return Ok(val);
+    /// Every round asks `alpha` nodes in parallel and returns the first found value,
+    /// other requests of the round are cancelled. Only if nobody has the value we
+    /// ask the same nodes for closer nodes.
+    pub async fn find_value(&self, key: &Key) -> Result<Vec<u8>, RhizomeError> {
+        Ok(self.find_value_with_meta(key).await?.value)
+    }
+
+    /// Same as [`DHTProtocol::find_value`], but also return the node which supplied the
+    /// value and how long it has kept it
+    pub async fn find_value_with_meta(&self, key: &Key) -> Result<ValueWithMeta, RhizomeError> {
+        self.find_value_with_budget(key, &QueryBudget::unlimited())
+            .await
+    }
+
+    /// Same as [`DHTProtocol::find_value_with_meta`] within the budget
+    ///
+    /// Return `DeadlineExceeded` if the deadline passed before the value was found.
+    /// Key which was not found recently is answered by `ValueNotFound` without lookup
+    /// until its backoff is over.
+    pub async fn find_value_with_budget(
+        &self,
+        key: &Key,
+        budget: &QueryBudget,
+    ) -> Result<ValueWithMeta, RhizomeError> {
+        if let Some(stored) = self.storage.get_with_meta(key.clone()).await? {
+            return Ok(ValueWithMeta::new(stored.into(), None));
+        }
+        if self.is_known_missing(key) {
+            return Err(RhizomeError::Dht(DHTError::ValueNotFound));
         }
 
         let net = self
@@ -164,49 +494,111 @@ impl DHTProtocol {
             .as_ref()
             .ok_or(RhizomeError::Dht(DHTError::ValueNotFound))?;
 
-        let mut id_bytes = [0u8; 20];
-        let len = key.len().min(20);
-        id_bytes[..len].copy_from_slice(&key[..len]);
-        let target_id = NodeID::new(id_bytes);
+        let target_id = key.node_id();
+
+        let parallel = budget.parallel(self.alpha);
+        let expired = || RhizomeError::Dht(DHTError::DeadlineExceeded);
+
+        let mut queried: HashSet<NodeID> = HashSet::new();
+        // Peers which answered that they have no value
+        let mut not_found = 0;
+        let mut hinted = self.nodes_likely_having(key, &target_id).await;
+        hinted.truncate(parallel.min(budget.peers_left(0)));
+        if !hinted.is_empty() {
+            let mut hinted_tasks: FuturesUnordered<_> = hinted
+                .iter()
+                .map(|node| async move { (node, net.find_value_versioned(key, node).await) })
+                .collect();
+
+            let found = budget
+                .run(&**self.storage.clock(), async {
+                    while let Some((node, result)) = hinted_tasks.next().await {
+                        match result {
+                            Ok(Some(val)) => {
+                                return Some(ValueWithMeta::new(val, Some(node.clone())));
+                            }
+                            Ok(None) => not_found += 1,
+                            Err(_) => {}
+                        }
+                    }
+                    None
+                })
+                .await
+                .ok_or_else(expired)?;
+            if let Some(found) = found {
+                self.record_lookup();
+                self.forget_missing(key);
+                return Ok(found);
+            }
+            drop(hinted_tasks);
+            queried.extend(hinted.iter().map(|n| n.node_id));
+        }
 
         let mut closest = {
             let rt = self.routing_table.read().await;
-            rt.find_closest_nodes(&target_id, self.alpha)
+            rt.find_closest_nodes(&target_id, rt.k)
         };
+        self.sort_for_lookup(&mut closest, &target_id);
+        closest.truncate(self.alpha);
 
         let mut seen_nodes: HashMap<NodeID, Node> =
             closest.iter().map(|n| (n.node_id, n.clone())).collect();
-        let mut queried: HashSet<NodeID> = HashSet::new();
+        let mut lacking: Vec<Node> = Vec::new();
+        // Budget of peers ended before all closest nodes were asked
+        let mut truncated = false;
 
         loop {
             let candidates: Vec<Node> = closest
                 .iter()
                 .filter(|n| !queried.contains(&n.node_id))
-                .take(self.alpha)
+                .take(parallel.min(budget.peers_left(queried.len())))
                 .cloned()
                 .collect();
 
             if candidates.is_empty() {
+                truncated = closest.iter().any(|n| !queried.contains(&n.node_id));
                 break;
             }
 
-            let mut value_tasks = Vec::new();
-            for node in &candidates {
-                value_tasks.push(net.find_value(key, node));
-            }
-            let results = join_all(value_tasks).await;
+            // Hedged requests: the first answer with value wins,
+            // requests which are still in flight are cancelled by drop
+            let mut value_tasks: FuturesUnordered<_> = candidates
+                .iter()
+                .map(|node| async move { (node, net.find_value_versioned(key, node).await) })
+                .collect();
 
-            for result in results {
-                if let Ok(Some(val)) = result {
-                    return Ok(val);
-                }
+            let found = budget
+                .run(&**self.storage.clock(), async {
+                    while let Some((node, result)) = value_tasks.next().await {
+                        match result {
+                            Ok(Some(val)) => return Some((node, val)),
+                            Ok(None) => {
+                                self.record_lookup();
+                                lacking.push(node.clone());
+                            }
+                            Err(_) => {}
+                        }
+                    }
+                    None
+                })
+                .await
+                .ok_or_else(expired)?;
+            drop(value_tasks);
+            if let Some((node, val)) = found {
+                self.record_lookup();
+                self.forget_missing(key);
+                self.read_repair(key, &val.value, &target_id, lacking);
+                return Ok(ValueWithMeta::new(val, Some(node.clone())));
             }
 
             let mut node_tasks = Vec::new();
             for node in &candidates {
                 node_tasks.push(net.find_node(&target_id, node));
             }
-            let node_results = join_all(node_tasks).await;
+            let node_results = budget
+                .run(&**self.storage.clock(), join_all(node_tasks))
+                .await
+                .ok_or_else(expired)?;
 
             for nodes in node_results.into_iter().flatten() {
                 for n in nodes {
@@ -219,7 +611,7 @@ impl DHTProtocol {
             }
 
             let mut all_found: Vec<Node> = seen_nodes.values().cloned().collect();
-            all_found.sort_by_key(|n| n.node_id.distance_to(&target_id));
+            self.sort_for_lookup(&mut all_found, &target_id);
             closest = all_found.into_iter().take(self.alpha).collect();
 
             if queried.len() >= seen_nodes.len() {
@@ -227,52 +619,1021 @@ impl DHTProtocol {
             }
         }
 
+        // Lookups cut by the deadline returned earlier. Only complete lookups in which
+        // some peer answered are misses, without answers the network was not asked.
+        if let Some(cache) = &self.negative_cache
+            && !truncated
+            && not_found + lacking.len() > 0
+        {
+            cache.record_miss(key);
+        }
         Err(RhizomeError::Dht(DHTError::ValueNotFound))
     }
 
+    /// Up to `alpha` nodes of routing table whose Bloom filter contains the key
+    async fn nodes_likely_having(&self, key: &Key, target_id: &NodeID) -> Vec<Node> {
+        let index = match &self.key_presence {
+            Some(index) if index.peer_count() > 0 => index,
+            _ => return Vec::new(),
+        };
+
+        let mut nodes: Vec<Node> = self
+            .routing_table
+            .read()
+            .await
+            .get_all_nodes()
+            .into_iter()
+            .filter(|n| index.peer_may_have(&n.node_id.0, key))
+            .collect();
+        self.sort_for_lookup(&mut nodes, target_id);
+        nodes.truncate(self.alpha);
+        nodes
+    }
+
+    /// Sort nodes by distance to the target
+    ///
+    /// Reputation only breaks ties between nodes of the same distance bucket, so peers with
+    /// bad reputation go after the good ones of their bucket, but never after farther ones.
+    fn sort_for_lookup(&self, nodes: &mut [Node], target_id: &NodeID) {
+        match &self.reputation {
+            Some(reputation) => nodes.sort_by_cached_key(|n| {
+                (
+                    std::cmp::Reverse(n.node_id.prefix_len(target_id)),
+                    !reputation.is_reputable_node(n),
+                    n.node_id.distance_to(target_id),
+                )
+            }),
+            None => nodes.sort_by_key(|n| n.node_id.distance_to(target_id)),
+        }
+    }
+
+    /// Crawl of the ID space
+    ///
+    /// Walk nodes from the closest to `target` and further, ask every node for records of
+    /// `record_type` published after `since` and give every new record to `visitor`.
+    /// Walk stops when `visitor` returns `false`, `max_nodes` nodes were asked or there
+    /// are no more nodes. Our local records go first.
+    ///
+    /// Return count of asked remote nodes
+    pub async fn iterate_closest<F>(
+        &self,
+        target: &NodeID,
+        record_type: &str,
+        since: f64,
+        max_nodes: usize,
+        mut visitor: F,
+    ) -> Result<usize, RhizomeError>
+    where
+        F: FnMut(&TypedRecord) -> bool,
+    {
+        let mut seen_keys: HashSet<Key> = HashSet::new();
+
+        let mut cursor = None;
+        loop {
+            let page = self
+                .storage
+                .records_by_type_page(record_type.to_string(), since, cursor, LOCAL_RECORDS_PAGE)
+                .await?;
+            for record in &page.records {
+                if seen_keys.insert(record.key.clone()) && !visitor(record) {
+                    return Ok(0);
+                }
+            }
+            cursor = page.next;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        let net = match &self.network_protocol {
+            Some(n) => n,
+            None => return Ok(0),
+        };
+
+        let mut frontier: Vec<Node> = {
+            let rt = self.routing_table.read().await;
+            rt.find_closest_nodes(target, rt.k)
+        };
+        let mut visited: HashSet<NodeID> = HashSet::new();
+
+        while visited.len() < max_nodes {
+            frontier.retain(|n| !visited.contains(&n.node_id));
+            frontier.sort_by_key(|n| n.node_id.distance_to(target));
+
+            let batch: Vec<Node> = frontier
+                .iter()
+                .take(self.alpha.min(max_nodes - visited.len()))
+                .cloned()
+                .collect();
+            if batch.is_empty() {
+                break;
+            }
+
+            let tasks: Vec<_> = batch
+                .iter()
+                .map(|node| async move {
+                    let records = net.list_records(record_type, since, node).await;
+                    let neighbors = net.find_node(target, node).await;
+                    (records, neighbors)
+                })
+                .collect();
+
+            for node in &batch {
+                visited.insert(node.node_id);
+            }
+
+            for (records, neighbors) in join_all(tasks).await {
+                for record in records.unwrap_or_default() {
+                    if seen_keys.insert(record.key.clone()) && !visitor(&record) {
+                        return Ok(visited.len());
+                    }
+                }
+                for node in neighbors.unwrap_or_default() {
+                    if !visited.contains(&node.node_id)
+                        && !frontier.iter().any(|n| n.node_id == node.node_id)
+                    {
+                        frontier.push(node);
+                    }
+                }
+            }
+        }
+
+        debug!(
+            record_type = record_type,
+            nodes = visited.len(),
+            records = seen_keys.len(),
+            "Crawl completed"
+        );
+
+        Ok(visited.len())
+    }
+
+    /// Conditional store _(compare-and-swap)_
+    ///
+    /// Value is written only where current value has `expected_hash` _(`None` - no value)_.
+    /// Local storage is checked first, then k-closest nodes. If any replica has other value
+    /// its value is returned as conflict, so caller can merge and retry.
+    pub async fn store_cas(
+        &self,
+        key: &Key,
+        expected_hash: Option<[u8; 32]>,
+        value: &[u8],
+        ttl: i32,
+        record_type: &str,
+    ) -> Result<CasOutcome, RhizomeError> {
+        let mut origin = StoreOrigin::new(
+            self.routing_table.read().await.node_id.0.to_vec(),
+            self.storage.now(),
+        )
+        .with_record_type(record_type);
+        if let Some(net) = &self.network_protocol {
+            net.sign_origin(key, Some(value), &mut origin);
+        }
+
+        let local = self
+            .storage
+            .compare_and_swap(
+                key.clone(),
+                expected_hash,
+                value.to_vec(),
+                ttl,
+                origin.clone(),
+            )
+            .await?;
+        if let CasOutcome::Conflict(_) = local {
+            return Ok(local);
+        }
+
+        let net = match &self.network_protocol {
+            Some(n) => n,
+            None => return Ok(CasOutcome::Applied),
+        };
+
+        let closest = self.find_closest_to_key(key).await?;
+        let tasks: Vec<_> = closest
+            .iter()
+            .map(|node| net.store_cas(key, expected_hash, value, ttl, &origin, node))
+            .collect();
+
+        let mut applied = 0;
+        let mut conflict = None;
+        for result in join_all(tasks).await.into_iter().flatten() {
+            match result {
+                CasOutcome::Applied => applied += 1,
+                CasOutcome::Conflict(current) => {
+                    if conflict.is_none() {
+                        conflict = Some(current);
+                    }
+                }
+            }
+        }
+
+        debug!(
+            key = %key.short_hex(),
+            applied = applied,
+            conflict = conflict.is_some(),
+            "STORE_CAS completed"
+        );
+
+        Ok(match conflict {
+            Some(current) => CasOutcome::Conflict(current),
+            None => CasOutcome::Applied,
+        })
+    }
+
+    /// Find values of several keys
+    ///
+    /// Local values go first. Other keys are grouped by the closest known node and asked
+    /// by one batch request per node. Keys which were not found in batches are looked up
+    /// one by one.
+    pub async fn find_values(&self, keys: &[Key]) -> Result<HashMap<Key, Vec<u8>>, RhizomeError> {
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+
+        for key in keys {
+            match self.storage.get(key.clone()).await? {
+                Some(value) => {
+                    found.insert(key.clone(), value);
+                }
+                None => missing.push(key.clone()),
+            }
+        }
+
+        let net = match &self.network_protocol {
+            Some(n) => n,
+            None => return Ok(found),
+        };
+
+        missing.retain(|key| !self.is_known_missing(key));
+
+        let mut groups: HashMap<NodeID, (Node, Vec<Key>)> = HashMap::new();
+        {
+            let rt = self.routing_table.read().await;
+            for key in &missing {
+                if let Some(node) = rt.closest_nodes_iter(&key.node_id(), 1).next() {
+                    groups
+                        .entry(node.node_id)
+                        .or_insert_with(|| (node.clone(), Vec::new()))
+                        .1
+                        .push(key.clone());
+                }
+            }
+        }
+
+        let tasks: Vec<_> = groups
+            .values()
+            .map(|(node, group)| net.find_values(group, node))
+            .collect();
+        for values in join_all(tasks).await.into_iter().flatten() {
+            found.extend(values);
+        }
+
+        for key in missing {
+            if found.contains_key(&key) {
+                continue;
+            }
+            if let Ok(value) = self.find_value(&key).await {
+                found.insert(key, value);
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Find value with requested consistency
+    pub async fn find_value_with_consistency(
+        &self,
+        key: &Key,
+        consistency: ReadConsistency,
+    ) -> Result<Vec<u8>, RhizomeError> {
+        match consistency {
+            ReadConsistency::One => self.find_value(key).await,
+            ReadConsistency::Quorum(r) => self.find_value_quorum(key, r).await,
+        }
+    }
+
+    /// Quorum read
+    ///
+    /// Ask all k-closest nodes of the key and wait for `r` answers with value (our local
+    /// value is also an answer). The value with the latest publish time proven by the
+    /// publisher wins, on equal time the most common checksum wins. Without proven answers
+    /// the most common checksum wins. Replicas with other value or without value are
+    /// repaired, but only by a proven winner.
+    pub async fn find_value_quorum(&self, key: &Key, r: usize) -> Result<Vec<u8>, RhizomeError> {
+        let local = self.storage.get_with_meta(key.clone()).await?;
+
+        let net = match &self.network_protocol {
+            Some(n) => n,
+            None => {
+                return match local {
+                    Some(stored) if r <= 1 => Ok(stored.value),
+                    Some(_) => Err(RhizomeError::Dht(DHTError::QuorumNotReached)),
+                    None => Err(RhizomeError::Dht(DHTError::ValueNotFound)),
+                };
+            }
+        };
+
+        let (remote, lacking) = self.ask_closest(net, key).await?;
+        let mut answers: Vec<(Option<Node>, VersionedValue)> = Vec::new();
+        if let Some(stored) = local {
+            answers.push((None, VersionedValue::from(stored)));
+        }
+        answers.extend(remote.into_iter().map(|(node, v)| (Some(node), v)));
+
+        if answers.is_empty() {
+            return Err(RhizomeError::Dht(DHTError::ValueNotFound));
+        }
+        if answers.len() < r {
+            debug!(
+                key = %key.short_hex(),
+                answers = answers.len(),
+                quorum = r,
+                "Read quorum not reached"
+            );
+            return Err(RhizomeError::Dht(DHTError::QuorumNotReached));
+        }
+
+        let winner =
+            Self::quorum_winner(&answers).ok_or(RhizomeError::Dht(DHTError::ValueNotFound))?;
+        let (winner, origin) = (winner.value.clone(), winner.proven_origin());
+        // Majority of unproven answers may be a lie of few nodes, it is not spread
+        let Some(origin) = origin else {
+            return Ok(winner);
+        };
+
+        let winner_checksum = hash_key(&winner);
+        let mut divergent: Vec<Node> = answers
+            .iter()
+            .filter(|(_, v)| hash_key(&v.value) != winner_checksum)
+            .filter_map(|(node, _)| node.clone())
+            .collect();
+        divergent.extend(lacking);
+
+        if !divergent.is_empty() {
+            let key_vec = key.clone();
+            let value = winner.clone();
+            let net = net.clone();
+            tokio::spawn(
+                async move {
+                    let tasks: Vec<_> = divergent
+                        .iter()
+                        .map(|node| {
+                            net.store_with_origin(&key_vec, &value, READ_REPAIR_TTL, &origin, node)
+                        })
+                        .collect();
+                    let repaired = join_all(tasks)
+                        .await
+                        .into_iter()
+                        .filter(|r| matches!(r, Ok(true)))
+                        .count();
+                    debug!(repaired = repaired, "Quorum read repair completed");
+                }
+                .in_current_span(),
+            );
+        }
+
+        Ok(winner)
+    }
+
+    /// Winner of the quorum read between answers, see [`DHTProtocol::find_value_quorum`]
+    fn quorum_winner(answers: &[(Option<Node>, VersionedValue)]) -> Option<&VersionedValue> {
+        let mut checksum_votes: HashMap<[u8; 32], usize> = HashMap::new();
+        for (_, v) in answers {
+            *checksum_votes.entry(hash_key(&v.value)).or_default() += 1;
+        }
+        let votes = |v: &VersionedValue| checksum_votes[&hash_key(&v.value)];
+
+        let proven = answers
+            .iter()
+            .map(|(_, v)| v)
+            .filter(|v| v.proof.is_some())
+            .max_by(|a, b| {
+                a.published_at
+                    .total_cmp(&b.published_at)
+                    .then_with(|| votes(a).cmp(&votes(b)))
+                    .then_with(|| a.stored_at.total_cmp(&b.stored_at))
+            });
+        proven.or_else(|| {
+            answers.iter().map(|(_, v)| v).max_by(|a, b| {
+                votes(a)
+                    .cmp(&votes(b))
+                    .then_with(|| a.stored_at.total_cmp(&b.stored_at))
+            })
+        })
+    }
+
+    /// Values of the key on its k-closest nodes, local storage is not read
+    ///
+    /// One answer per holder, for callers which choose between versions themselves.
+    pub async fn find_remote_versions(
+        &self,
+        key: &Key,
+    ) -> Result<Vec<VersionedValue>, RhizomeError> {
+        let net = self
+            .network_protocol
+            .as_ref()
+            .ok_or(RhizomeError::Dht(DHTError::ValueNotFound))?;
+        let (answers, _) = self.ask_closest(net, key).await?;
+        Ok(answers.into_iter().map(|(_, v)| v).collect())
+    }
+
+    /// Ask all k-closest nodes of the key for its value
+    ///
+    /// Return answers with the node which holds the value and nodes without the value.
+    async fn ask_closest(
+        &self,
+        net: &Arc<dyn NetworkProtocolTrait>,
+        key: &Key,
+    ) -> Result<(Vec<(Node, VersionedValue)>, Vec<Node>), RhizomeError> {
+        let closest = self.find_closest_to_key(key).await?;
+
+        let tasks: Vec<_> = closest
+            .iter()
+            .map(|node| async move { (node, net.find_value_versioned(key, node).await) })
+            .collect();
+        let results = join_all(tasks).await;
+
+        let mut answers: Vec<(Node, VersionedValue)> = Vec::new();
+        let mut lacking: Vec<Node> = Vec::new();
+        for (node, result) in results {
+            match result {
+                // Value of a holder which the node points to is the vote of the holder
+                Ok(Some(mut v)) => {
+                    let source = match v.holder.take() {
+                        Some(holder) => {
+                            lacking.push(node.clone());
+                            holder
+                        }
+                        None => node.clone(),
+                    };
+                    if !answers.iter().any(|(n, _)| n.node_id == source.node_id) {
+                        answers.push((source, v));
+                    }
+                }
+                Ok(None) => lacking.push(node.clone()),
+                Err(_) => {}
+            }
+        }
+        Ok((answers, lacking))
+    }
+
+    /// Read repair
+    ///
+    /// Value was found on distant node, so we send it to the closest nodes which answered
+    /// that they do not have it. Work in background and do not delay the lookup result.
+    fn read_repair(&self, key: &Key, value: &[u8], target_id: &NodeID, mut lacking: Vec<Node>) {
+        if self.read_repair_limit == 0 || lacking.is_empty() {
+            return;
+        }
+        let net = match &self.network_protocol {
+            Some(n) => n.clone(),
+            None => return,
+        };
+
+        lacking.sort_by_key(|n| n.node_id.distance_to(target_id));
+        lacking.truncate(self.read_repair_limit);
+
+        let key = key.clone();
+        let value = value.to_vec();
+        tokio::spawn(
+            async move {
+                let tasks: Vec<_> = lacking
+                    .iter()
+                    .map(|node| net.store(&key, &value, READ_REPAIR_TTL, node))
+                    .collect();
+                let repaired = join_all(tasks)
+                    .await
+                    .into_iter()
+                    .filter(|r| matches!(r, Ok(true)))
+                    .count();
+
+                debug!(
+                    key = %key.short_hex(),
+                    repaired = repaired,
+                    "Read repair completed"
+                );
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// Find k-closest nodes for the key
+    pub async fn find_closest_to_key(&self, key: &Key) -> Result<Vec<Node>, RhizomeError> {
+        let target_id = key.node_id();
+
+        let k = { self.routing_table.read().await.k };
+        let mut closest = self.find_node(&target_id).await?;
+        closest.truncate(k);
+        Ok(closest)
+    }
+
+    /// Store data on our node and on chosen remote nodes
+    ///
+    /// Return count of remote nodes which accepted the data
+    pub async fn store_to_nodes(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+        nodes: &[Node],
+    ) -> Result<usize, RhizomeError> {
+        let (success_count, _) = self
+            .store_to_nodes_with_receipts(key, value, ttl, origin, nodes)
+            .await?;
+        Ok(success_count)
+    }
+
+    /// Same as [`DHTProtocol::store_to_nodes`], but also return receipts of the storers
+    ///
+    /// Receipts are not verified here
+    pub async fn store_to_nodes_with_receipts(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+        nodes: &[Node],
+    ) -> Result<(usize, Vec<(Node, StorageReceipt)>), RhizomeError> {
+        self.storage
+            .put_with_origin(key.clone(), value.to_vec(), ttl, origin.clone())
+            .await?;
+        self.forget_missing(key);
+
+        let net = match &self.network_protocol {
+            Some(n) => n,
+            None => return Ok((0, Vec::new())),
+        };
+
+        let mut success_count = 0;
+        let mut receipts = Vec::new();
+        let answers = net
+            .store_many_with_receipts(key, value, ttl, origin, nodes)
+            .await;
+        for (node, (success, receipt)) in nodes.iter().zip(answers) {
+            if success {
+                success_count += 1;
+            }
+            receipts.extend(receipt.map(|receipt| (node.clone(), receipt)));
+        }
+
+        debug!(
+            key = %key.short_hex(),
+            success = success_count,
+            receipts = receipts.len(),
+            attempted = nodes.len(),
+            "STORE to chosen nodes completed"
+        );
+
+        Ok((success_count, receipts))
+    }
+
     /// Store data
     ///
     /// Firstly in our local store
     /// Secondly send data for our closest nodes
-    pub async fn store(&self, key: &[u8], value: &[u8], ttl: i32) -> Result<bool, RhizomeError> {
-        self.storage.put(key.to_vec(), value.to_vec(), ttl).await?;
+    ///
+    /// Our node is saved as the publisher of the data
+    pub async fn store(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+    ) -> Result<StoreResult, RhizomeError> {
+        self.store_record(key, value, ttl, "").await
+    }
+
+    /// Store data with record type, so it can be found by [`DHTProtocol::iterate_closest`]
+    pub async fn store_record(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        record_type: &str,
+    ) -> Result<StoreResult, RhizomeError> {
+        self.store_record_with_budget(key, value, ttl, record_type, &QueryBudget::unlimited())
+            .await
+    }
+
+    /// Same as [`DHTProtocol::store_record`] within the budget
+    ///
+    /// Value is always saved locally. Nodes which did not answer before the deadline are
+    /// counted as failed.
+    pub async fn store_record_with_budget(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        record_type: &str,
+        budget: &QueryBudget,
+    ) -> Result<StoreResult, RhizomeError> {
+        let mut origin = StoreOrigin::new(
+            self.routing_table.read().await.node_id.0.to_vec(),
+            self.storage.now(),
+        )
+        .with_record_type(record_type);
+        if let Some(net) = &self.network_protocol {
+            net.sign_origin(key, Some(value), &mut origin);
+        }
+        self.storage
+            .put_with_origin(key.clone(), value.to_vec(), ttl, origin.clone())
+            .await?;
+        self.forget_missing(key);
 
         let net = match &self.network_protocol {
             Some(n) => n,
-            None => return Ok(true),
+            None => return Ok(StoreResult::default()),
         };
 
-        let mut id_bytes = [0u8; 20];
-        let len = key.len().min(20);
-        id_bytes[..len].copy_from_slice(&key[..len]);
-        let target_id = NodeID::new(id_bytes);
+        let target_id = key.node_id();
+
+        let mut closest_nodes = self.find_node_with_budget(&target_id, budget).await?;
+        let k = { self.routing_table.read().await.k };
+        closest_nodes.truncate(k.min(budget.peers_left(0)));
+
+        let store_tasks: Vec<_> = closest_nodes
+            .iter()
+            .map(|node| {
+                let node_id = node.node_id;
+                net.store_with_origin(key, value, ttl, &origin, node)
+                    .map(move |stored| (node_id, stored))
+            })
+            .collect();
+        let mut store_tasks = futures::stream::iter(store_tasks)
+            .buffer_unordered(budget.parallel(closest_nodes.len()));
+
+        let mut result = StoreResult {
+            attempted: closest_nodes.len(),
+            ..StoreResult::default()
+        };
+        let mut answered: HashSet<NodeID> = HashSet::new();
+        while let Some(Some((node_id, stored))) = budget
+            .run(&**self.storage.clock(), store_tasks.next())
+            .await
+        {
+            answered.insert(node_id);
+            if matches!(stored, Ok(true)) {
+                result.succeeded += 1;
+            } else {
+                result.closest_failed.push(node_id);
+            }
+        }
+        drop(store_tasks);
+        for node in &closest_nodes {
+            if !answered.contains(&node.node_id) {
+                result.closest_failed.push(node.node_id);
+            }
+        }
+
+        debug!(
+            key = %key.short_hex(),
+            success = result.succeeded,
+            attempted = result.attempted,
+            "STORE completed"
+        );
 
-        let closest_nodes = self.find_node(&target_id).await?;
+        Ok(result)
+    }
 
-        if closest_nodes.is_empty() {
-            return Ok(true);
+    /// Delete data
+    ///
+    /// Value is replaced with a tombstone in our store and on the closest nodes, so nodes
+    /// which still hold old copies can not bring it back. Tombstone lives `ttl` seconds.
+    pub async fn delete(&self, key: &Key, ttl: i32) -> Result<StoreResult, RhizomeError> {
+        let mut origin = StoreOrigin::new(
+            self.routing_table.read().await.node_id.0.to_vec(),
+            self.storage.now(),
+        );
+        if let Some(net) = &self.network_protocol {
+            net.sign_origin(key, None, &mut origin);
+        }
+        if !self
+            .storage
+            .tombstone(key.clone(), ttl, origin.clone())
+            .await?
+        {
+            debug!(key = %key.short_hex(), "Local copy is newer than deletion, kept");
         }
+        self.spread_tombstone(key, ttl, &origin).await
+    }
 
+    /// Send tombstone to the closest nodes of the key
+    pub async fn spread_tombstone(
+        &self,
+        key: &Key,
+        ttl: i32,
+        origin: &StoreOrigin,
+    ) -> Result<StoreResult, RhizomeError> {
+        let net = match &self.network_protocol {
+            Some(n) => n,
+            None => return Ok(StoreResult::default()),
+        };
+
+        let mut closest_nodes = self.find_node(&key.node_id()).await?;
         let k = { self.routing_table.read().await.k };
-        let mut store_tasks = Vec::new();
+        closest_nodes.truncate(k);
+
+        let origin = origin.forwarded();
+        let delete_tasks: Vec<_> = closest_nodes
+            .iter()
+            .map(|node| net.delete(key, ttl, &origin, node))
+            .collect();
 
-        for node in closest_nodes.iter().take(k) {
-            store_tasks.push(net.store(key, value, ttl, node));
+        let mut result = StoreResult {
+            attempted: closest_nodes.len(),
+            ..StoreResult::default()
+        };
+        for (node, deleted) in closest_nodes.iter().zip(join_all(delete_tasks).await) {
+            if matches!(deleted, Ok(true)) {
+                result.succeeded += 1;
+            } else {
+                result.closest_failed.push(node.node_id);
+            }
         }
 
-        let results = join_all(store_tasks).await;
-        let success_count = results
+        debug!(
+            key = %key.short_hex(),
+            success = result.succeeded,
+            attempted = result.attempted,
+            "DELETE completed"
+        );
+
+        Ok(result)
+    }
+
+    /// Keys of our storage which should be pushed to the new node
+    ///
+    /// Rule from the Kademlia paper: the new node is closer to the key than the farthest
+    /// of k closest nodes we know, and we are closer than any of them. So only one old
+    /// owner pushes the key instead of all k.
+    ///
+    /// Only regions of the ID space where we are the closest known node are read from
+    /// storage, routing table is not locked while they are read.
+    pub async fn keys_for_new_node(&self, new_node: &Node) -> Result<Vec<Key>, RhizomeError> {
+        let regions = Self::transfer_regions(&*self.routing_table.read().await, new_node);
+        let mut keys = Vec::new();
+        for (prefix, bits) in regions {
+            let (lower, upper) = Self::prefix_range(&prefix, bits);
+            keys.extend(self.storage.keys_in_range(lower, upper).await?);
+        }
+
+        let rt = self.routing_table.read().await;
+        Ok(keys
             .into_iter()
-            .filter(|r| matches!(r, Ok(true)))
-            .count();
+            .filter(|key| {
+                let target = key.node_id();
+                let neighbors: Vec<&Node> = rt
+                    .closest_nodes_iter(&target, rt.k + 1)
+                    .filter(|n| n.node_id != new_node.node_id)
+                    .take(rt.k)
+                    .collect();
+                let (Some(first), Some(last)) = (neighbors.first(), neighbors.last()) else {
+                    return true;
+                };
+                new_node.node_id.distance_to(&target) < last.node_id.distance_to(&target)
+                    && rt.node_id.distance_to(&target) < first.node_id.distance_to(&target)
+            })
+            .collect())
+    }
+
+    /// Regions of the ID space where keys for the new node may be, as prefixes with their
+    /// lengths in bits
+    ///
+    /// Known node which shares exactly `i` bits with our ID is closer than us to all keys
+    /// which share exactly `i` bits with it. So only keys which share the prefix of our ID
+    /// and the ID of the new node and keys of levels without known nodes are left.
+    fn transfer_regions(rt: &RoutingTable, new_node: &Node) -> Vec<(NodeID, usize)> {
+        let shared = rt.node_id.prefix_len(&new_node.node_id);
+        let mut occupied = vec![false; shared];
+        for node in rt.get_all_nodes() {
+            let level = rt.node_id.prefix_len(&node.node_id);
+            if node.node_id != new_node.node_id && level < shared {
+                occupied[level] = true;
+            }
+        }
+
+        let mut regions: Vec<(NodeID, usize)> = (0..shared)
+            .filter(|level| !occupied[*level])
+            .map(|level| {
+                let mut prefix = rt.node_id;
+                prefix.0[level / 8] ^= 0x80 >> (level % 8);
+                (prefix, level + 1)
+            })
+            .collect();
+        regions.push((rt.node_id, shared));
+        regions
+    }
+
+    /// Lowest key with the first `bits` of the prefix and the lowest key above them
+    /// _(None if there is none)_
+    fn prefix_range(prefix: &NodeID, bits: usize) -> (Vec<u8>, Option<Vec<u8>>) {
+        let mut lower = prefix.0;
+        for (i, byte) in lower.iter_mut().enumerate() {
+            let kept = bits.saturating_sub(i * 8).min(8) as u32;
+            *byte &= !0xFFu8.checked_shr(kept).unwrap_or(0);
+        }
+        if bits == 0 {
+            return (lower.to_vec(), None);
+        }
+
+        let mut upper = lower;
+        let mut index = (bits - 1) / 8;
+        let mut add = 0x80u8 >> ((bits - 1) % 8);
+        loop {
+            let (sum, carry) = upper[index].overflowing_add(add);
+            upper[index] = sum;
+            if !carry {
+                return (lower.to_vec(), Some(upper.to_vec()));
+            }
+            if index == 0 {
+                return (lower.to_vec(), None);
+            }
+            index -= 1;
+            add = 1;
+        }
+    }
+
+    /// We are one of k closest nodes to the key which we know
+    ///
+    /// Otherwise we keep the value only as an extra replica.
+    pub async fn is_responsible_for(&self, key: &Key) -> bool {
+        let target = key.node_id();
+        let rt = self.routing_table.read().await;
+        let own_distance = rt.node_id.distance_to(&target);
+        rt.closest_nodes_iter(&target, rt.k)
+            .filter(|n| n.node_id.distance_to(&target) < own_distance)
+            .count()
+            < rt.k
+    }
+
+    /// Push our keys which the new node now owns to it (transfer on join)
+    ///
+    /// Values keep their original publisher and the remaining TTL.
+    /// Return count of keys which the node accepted.
+    pub async fn transfer_keys_to(&self, new_node: &Node) -> Result<usize, RhizomeError> {
+        let net = match &self.network_protocol {
+            Some(n) => n,
+            None => return Ok(0),
+        };
+
+        let keys = self.keys_for_new_node(new_node).await?;
+        let mut transferred = 0;
+        for key in &keys {
+            let Some(stored) = self.storage.get_with_meta(key.clone()).await? else {
+                continue;
+            };
+            let ttl = (stored.expires_at - self.storage.now()) as i32;
+            if ttl <= 0 {
+                continue;
+            }
+            let origin = stored.origin.forwarded();
+            if matches!(
+                net.store_with_origin(key, &stored.value, ttl, &origin, new_node)
+                    .await,
+                Ok(true)
+            ) {
+                transferred += 1;
+            }
+        }
+
+        if !keys.is_empty() {
+            debug!(
+                node_id = %hex::encode(&new_node.node_id.0[..8]),
+                transferred = transferred,
+                attempted = keys.len(),
+                "Keys transferred to new node"
+            );
+        }
+        Ok(transferred)
+    }
+
+    /// Push our keys to their k closest nodes again _(exm. after healed partition)_
+    ///
+    /// Only the closest known owner of the key looks up its current k closest nodes, so the
+    /// key is not pushed by all k owners. Values keep their original publisher and the
+    /// remaining TTL. Return count of keys which at least one node accepted.
+    pub async fn republish_keys(&self) -> Result<usize, RhizomeError> {
+        let net = match &self.network_protocol {
+            Some(n) => n,
+            None => return Ok(0),
+        };
+
+        let keys: Vec<Key> = {
+            let keys = self.storage.keys().await?;
+            let rt = self.routing_table.read().await;
+            keys.into_iter()
+                .filter(|key| {
+                    let target = key.node_id();
+                    rt.closest_nodes_iter(&target, 1)
+                        .next()
+                        .is_none_or(|closest| {
+                            rt.node_id.distance_to(&target) < closest.node_id.distance_to(&target)
+                        })
+                })
+                .collect()
+        };
+
+        let mut republished = 0;
+        for key in &keys {
+            let Some(stored) = self.storage.get_with_meta(key.clone()).await? else {
+                continue;
+            };
+            let ttl = (stored.expires_at - self.storage.now()) as i32;
+            if ttl <= 0 {
+                continue;
+            }
+            let closest = match self.find_closest_to_key(key).await {
+                Ok(closest) => closest,
+                Err(e) => {
+                    debug!(key = %key.short_hex(), error = %e, "Failed to find owners for republish");
+                    continue;
+                }
+            };
+            let origin = stored.origin.forwarded();
+            let store_tasks: Vec<_> = closest
+                .iter()
+                .map(|node| net.store_with_origin(key, &stored.value, ttl, &origin, node))
+                .collect();
+            if join_all(store_tasks)
+                .await
+                .into_iter()
+                .any(|stored| matches!(stored, Ok(true)))
+            {
+                republished += 1;
+            }
+        }
 
         debug!(
-            key = %hex::encode(&key[..key.len().min(8)]),
-            success = success_count,
-            attempted = k,
-            "STORE completed"
+            republished = republished,
+            attempted = keys.len(),
+            "Keys republished"
         );
+        Ok(republished)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(value: &[u8], stored_at: f64) -> (Option<Node>, VersionedValue) {
+        let versioned = VersionedValue {
+            value: value.to_vec(),
+            stored_at,
+            published_at: 0.0,
+            expires_at: None,
+            publisher: Vec::new(),
+            record_type: String::new(),
+            proof: None,
+            holder: None,
+        };
+        (None, versioned)
+    }
+
+    #[test]
+    fn unproven_answers_are_decided_by_majority() {
+        let answers = vec![
+            answer(b"old", 10.0),
+            answer(b"old", 11.0),
+            answer(b"liar", 1e12),
+        ];
+        let winner = DHTProtocol::quorum_winner(&answers).unwrap();
+        assert_eq!(winner.value, b"old");
+        assert!(winner.proven_origin().is_none());
+    }
+
+    #[cfg(feature = "signatures")]
+    #[test]
+    fn latest_proven_publish_wins() {
+        use crate::utils::crypto::{NodeSigner, generate_keypair, node_id_from_public_key};
+
+        let (private_key, public_key) = generate_keypair();
+        let node_id = node_id_from_public_key(&public_key);
+        let signer = NodeSigner::new(private_key, node_id.to_vec());
+        let proven = |value: &[u8], signed_at: f64| {
+            let (node, mut versioned) = answer(value, 0.0);
+            versioned.publisher = node_id.to_vec();
+            versioned.published_at = signed_at;
+            versioned.proof = Some(PublisherProof::issue(
+                &signer,
+                b"key",
+                Some(value),
+                signed_at,
+                "",
+            ));
+            (node, versioned)
+        };
 
-        Ok(success_count > 0)
+        let answers = vec![
+            answer(b"unproven", 1e12),
+            answer(b"unproven", 1e12),
+            proven(b"first", 100.0),
+            proven(b"second", 200.0),
+        ];
+        let winner = DHTProtocol::quorum_winner(&answers).unwrap();
+        assert_eq!(winner.value, b"second");
+        let origin = winner.proven_origin().unwrap();
+        assert_eq!(origin.published_at, 200.0);
+        assert_eq!(origin.publisher, node_id.to_vec());
     }
 }
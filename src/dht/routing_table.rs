@@ -1,6 +1,25 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
 use crate::config::d_bucket_timeout;
 use crate::dht::node::{Node, NodeID};
-use crate::utils::time::get_now_f64;
+use crate::utils::time::{Clock, SystemClock};
+
+/// Count of routing events buffered for every subscriber
+pub const ROUTING_EVENTS_CAPACITY: usize = 1024;
+
+/// Change of the routing table
+#[derive(Debug, Clone)]
+pub enum RoutingEvent {
+    /// Node which was not in the table before was added
+    NodeAdded(Node),
+    /// Node was removed _(unresponsive, replaced as stale or known under other ID)_
+    NodeEvicted(NodeID),
+    /// Last bucket was split, `depth` is the index of the split bucket
+    BucketSplit { depth: usize },
+}
 
 /// K-Buckets for saving nodes with their distance
 pub struct KBucket {
@@ -13,37 +32,45 @@ pub struct KBucket {
 }
 
 impl KBucket {
-    pub fn new(k: usize) -> Self {
+    pub fn new(k: usize, now: f64) -> Self {
         Self {
             k,
             nodes: Vec::with_capacity(k),
-            last_updated: get_now_f64(),
+            last_updated: now,
         }
     }
 
     /// Node add with LRU logic
-    pub fn add_node(&mut self, node: Node) -> bool {
+    pub fn add_node(&mut self, mut node: Node, now: f64) -> bool {
         if let Some(index) = self.nodes.iter().position(|n| n.node_id == node.node_id) {
-            self.nodes.remove(index);
+            let previous = self.nodes.remove(index);
+            // Contacts from peers do not always carry endpoints, keep the known ones
+            if node.endpoints.is_empty() {
+                node.endpoints = previous.endpoints;
+            }
             self.nodes.push(node);
-            self.last_updated = get_now_f64();
+            self.last_updated = now;
             return true;
         }
 
         if self.nodes.len() < self.k {
             self.nodes.push(node);
-            self.last_updated = get_now_f64();
+            self.last_updated = now;
             return true;
         }
 
         false
     }
 
-    /// Remove node from bucket
-    pub fn remove_node(&mut self, node_id: &NodeID) {
-        if let Some(index) = self.nodes.iter().position(|n| &n.node_id == node_id) {
-            self.nodes.remove(index);
-            self.last_updated = get_now_f64();
+    /// Remove node from bucket, return `false` if there was no such node
+    pub fn remove_node(&mut self, node_id: &NodeID, now: f64) -> bool {
+        match self.nodes.iter().position(|n| &n.node_id == node_id) {
+            Some(index) => {
+                self.nodes.remove(index);
+                self.last_updated = now;
+                true
+            }
+            None => false,
         }
     }
 
@@ -58,97 +85,304 @@ impl KBucket {
     }
 }
 
+/// Node with its distance to the target _(ordered by distance)_
+struct Candidate<'a> {
+    distance: [u8; 20],
+    node: &'a Node,
+}
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance == other.distance
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.cmp(&other.distance)
+    }
+}
+
 /// Routing table
+///
+/// Buckets are created on demand like in the original Kademlia tree. Bucket `i` keeps
+/// nodes which share exactly `i` prefix bits with our id, the last bucket keeps all
+/// nodes near our id. Only the last bucket is split when it is full.
 pub struct RoutingTable {
     /// Our node id
     pub node_id: NodeID,
     /// K volume of buckets
     pub k: usize,
-    /// 160-counted buckets for 160-bits NodeId
+    /// Max count of buckets _(bits in NodeId)_
+    pub max_buckets: usize,
+    /// Buckets ordered by shared prefix length with our id
     pub buckets: Vec<KBucket>,
+    /// Channel of table changes
+    events: broadcast::Sender<RoutingEvent>,
+    /// Clock of the node
+    time: Arc<dyn Clock>,
 }
 
 impl RoutingTable {
     pub fn new(node_id: NodeID, k: usize, bucket_count: usize) -> Self {
-        let mut buckets = Vec::with_capacity(bucket_count);
-        for _ in 0..bucket_count {
-            buckets.push(KBucket::new(k));
-        }
-
+        let time: Arc<dyn Clock> = Arc::new(SystemClock);
         Self {
             node_id,
             k,
-            buckets,
+            max_buckets: bucket_count.max(1),
+            buckets: vec![KBucket::new(k, time.now())],
+            events: broadcast::channel(ROUTING_EVENTS_CAPACITY).0,
+            time,
         }
     }
 
-    /// Find bucket index for node id by XOR distance algo
-    fn get_bucket_index(&self, target_id: &NodeID) -> usize {
+    /// Same table which marks nodes as seen by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        for bucket in &mut self.buckets {
+            bucket.last_updated = now;
+        }
+        self.time = clock;
+        self
+    }
+
+    /// Get receiver of all next changes of the table
+    ///
+    /// Slow subscribers lose the oldest events instead of blocking the table.
+    pub fn subscribe(&self) -> broadcast::Receiver<RoutingEvent> {
+        self.events.subscribe()
+    }
+
+    /// Send event if somebody listens, event is built only in this case
+    fn emit(&self, event: impl FnOnce() -> RoutingEvent) {
+        if self.events.receiver_count() > 0 {
+            let _ = self.events.send(event());
+        }
+    }
+
+    /// Length of common prefix between our id and target id
+    fn prefix_len(&self, target_id: &NodeID) -> usize {
         let distance = self.node_id.distance_to(target_id);
 
         for (i, &byte) in distance.iter().enumerate() {
             if byte != 0 {
-                let leading_zeros = byte.leading_zeros() as usize;
-                let index = i * 8 + leading_zeros;
-                return index.min(self.buckets.len() - 1);
+                return i * 8 + byte.leading_zeros() as usize;
             }
         }
 
-        self.buckets.len() - 1
+        distance.len() * 8
+    }
+
+    /// Find bucket index for node id by XOR distance algo
+    fn get_bucket_index(&self, target_id: &NodeID) -> usize {
+        self.prefix_len(target_id).min(self.buckets.len() - 1)
+    }
+
+    /// Split the last bucket _(the one with our id)_ into two
+    ///
+    /// Nodes with longer common prefix go to the new last bucket
+    fn split_last_bucket(&mut self) {
+        let depth = self.buckets.len() - 1;
+        let nodes = std::mem::take(&mut self.buckets[depth].nodes);
+        let (near, far): (Vec<Node>, Vec<Node>) = nodes
+            .into_iter()
+            .partition(|n| self.prefix_len(&n.node_id) > depth);
+
+        self.buckets[depth].nodes = far;
+        let mut new_bucket = KBucket::new(self.k, self.time.now());
+        new_bucket.nodes = near;
+        new_bucket.last_updated = self.buckets[depth].last_updated;
+        self.buckets.push(new_bucket);
+        self.emit(|| RoutingEvent::BucketSplit { depth });
     }
 
     /// Add node in routing table
-    pub fn add_node(&mut self, node: Node) -> bool {
+    ///
+    /// Node which was never seen is marked as seen now.
+    pub fn add_node(&mut self, mut node: Node) -> bool {
         if node.node_id == self.node_id {
             return false;
         }
 
-        let bucket_index = self.get_bucket_index(&node.node_id);
+        if node.last_seen <= 0.0 {
+            node.last_seen = self.time.now();
+        }
+        let announced = (self.events.receiver_count() > 0 && !self.contains(&node.node_id))
+            .then(|| node.clone());
+        let added = self.insert_node(node);
+        if added && let Some(node) = announced {
+            self.emit(|| RoutingEvent::NodeAdded(node));
+        }
+        added
+    }
+
+    fn insert_node(&mut self, node: Node) -> bool {
+        let now = self.time.now();
+        let mut bucket_index = self.get_bucket_index(&node.node_id);
+
+        while self.buckets[bucket_index].is_full()
+            && bucket_index == self.buckets.len() - 1
+            && self.buckets.len() < self.max_buckets
+            && !self.buckets[bucket_index]
+                .nodes
+                .iter()
+                .any(|n| n.node_id == node.node_id)
+        {
+            self.split_last_bucket();
+            bucket_index = self.get_bucket_index(&node.node_id);
+        }
 
         if self.buckets[bucket_index].is_full() {
             let stale_index = self.buckets[bucket_index]
                 .nodes
                 .iter()
-                .position(|n| n.is_stale(d_bucket_timeout()));
+                .position(|n| n.is_stale(d_bucket_timeout(), now));
 
             if let Some(idx) = stale_index {
-                self.buckets[bucket_index].nodes.remove(idx);
-                return self.buckets[bucket_index].add_node(node);
+                let stale = self.buckets[bucket_index].nodes.remove(idx);
+                self.emit(|| RoutingEvent::NodeEvicted(stale.node_id));
+                return self.buckets[bucket_index].add_node(node, now);
             }
             return false;
         }
 
-        self.buckets[bucket_index].add_node(node)
+        self.buckets[bucket_index].add_node(node, now)
     }
 
     /// Remove node
     pub fn remove_node(&mut self, node_id: &NodeID) {
         let bucket_index = self.get_bucket_index(node_id);
-        self.buckets[bucket_index].remove_node(node_id);
+        if self.buckets[bucket_index].remove_node(node_id, self.time.now()) {
+            self.emit(|| RoutingEvent::NodeEvicted(*node_id));
+        }
     }
 
     /// Find closest nodes
     pub fn find_closest_nodes(&self, target_id: &NodeID, count: usize) -> Vec<Node> {
-        let bucket_index = self.get_bucket_index(target_id);
-        let mut closest_nodes: Vec<Node> = Vec::new();
+        self.closest_nodes_iter(target_id, count).cloned().collect()
+    }
 
-        for offset in 0..self.buckets.len() {
-            let idx = (bucket_index + offset) % self.buckets.len();
-            let nodes = self.buckets[idx].get_nodes();
-            closest_nodes.extend(nodes);
+    /// Iterate over `count` closest nodes to target sorted by distance
+    ///
+    /// Use bounded max-heap over borrowed nodes, so nothing is cloned until the caller wants it
+    pub fn closest_nodes_iter(
+        &self,
+        target_id: &NodeID,
+        count: usize,
+    ) -> impl Iterator<Item = &Node> + '_ {
+        let mut heap: BinaryHeap<Candidate<'_>> = BinaryHeap::with_capacity(count + 1);
 
-            if closest_nodes.len() >= count * 2 {
-                break;
+        if count > 0 {
+            for node in self.buckets.iter().flat_map(|b| b.nodes.iter()) {
+                let distance = node.node_id.distance_to(target_id);
+                if heap.len() < count {
+                    heap.push(Candidate { distance, node });
+                } else if heap.peek().is_some_and(|far| distance < far.distance) {
+                    heap.pop();
+                    heap.push(Candidate { distance, node });
+                }
             }
         }
 
-        closest_nodes.sort_by_key(|n| n.node_id.distance_to(target_id));
+        heap.into_sorted_vec().into_iter().map(|c| c.node)
+    }
 
-        if closest_nodes.len() > count {
-            closest_nodes.truncate(count);
+    /// Mark node as alive because we got some message from it
+    ///
+    /// Any traffic works as keep-alive: node and its bucket are refreshed without PING.
+    /// Return `false` if we do not know the node.
+    pub fn touch(&mut self, node_id: &NodeID) -> bool {
+        let bucket_index = self.get_bucket_index(node_id);
+        let bucket = &mut self.buckets[bucket_index];
+        match bucket.nodes.iter_mut().find(|n| &n.node_id == node_id) {
+            Some(node) => {
+                node.update_seen(self.time.now());
+                bucket.last_updated = node.last_seen;
+                true
+            }
+            None => false,
         }
+    }
 
-        closest_nodes
+    /// Nodes which we did not hear longer than `idle_seconds`, the most idle first
+    pub fn idle_nodes(&self, idle_seconds: f64) -> Vec<Node> {
+        let now = self.time.now();
+        let mut nodes: Vec<Node> = self
+            .buckets
+            .iter()
+            .flat_map(|b| b.nodes.iter())
+            .filter(|n| n.is_stale(idle_seconds, now))
+            .cloned()
+            .collect();
+        nodes.sort_by(|a, b| a.last_seen.total_cmp(&b.last_seen));
+        nodes
+    }
+
+    /// Save failed PING of the node
+    ///
+    /// Return count of failed pings in a row _(0 if we do not know the node)_
+    pub fn record_failed_ping(&mut self, node_id: &NodeID) -> u32 {
+        let bucket_index = self.get_bucket_index(node_id);
+        match self.buckets[bucket_index]
+            .nodes
+            .iter_mut()
+            .find(|n| &n.node_id == node_id)
+        {
+            Some(node) => {
+                node.record_failed_ping();
+                node.failed_pings
+            }
+            None => 0,
+        }
+    }
+
+    /// Save measured round-trip time of the node if we know it
+    pub fn record_rtt(&mut self, node_id: &NodeID, rtt: f64) {
+        let bucket_index = self.get_bucket_index(node_id);
+        if let Some(node) = self.buckets[bucket_index]
+            .nodes
+            .iter_mut()
+            .find(|n| &n.node_id == node_id)
+        {
+            node.record_rtt(rtt);
+        }
+    }
+
+    /// Node is in the table
+    pub fn contains(&self, node_id: &NodeID) -> bool {
+        let bucket_index = self.get_bucket_index(node_id);
+        self.buckets[bucket_index]
+            .nodes
+            .iter()
+            .any(|n| &n.node_id == node_id)
+    }
+
+    /// Node from the table with its known address
+    pub fn get_node(&self, node_id: &NodeID) -> Option<Node> {
+        let bucket_index = self.get_bucket_index(node_id);
+        self.buckets[bucket_index]
+            .nodes
+            .iter()
+            .find(|n| &n.node_id == node_id)
+            .cloned()
+    }
+
+    /// Round-trip time of the node if it is known
+    pub fn rtt_of(&self, node_id: &NodeID) -> Option<f64> {
+        let bucket_index = self.get_bucket_index(node_id);
+        self.buckets[bucket_index]
+            .nodes
+            .iter()
+            .find(|n| &n.node_id == node_id)
+            .and_then(|n| n.rtt)
     }
 
     /// Getting all table nodes
@@ -0,0 +1,92 @@
+//! # Events Module
+//!
+//! Typed events of the node which subsystems and embedding applications can subscribe to.
+//! Events are delivered by a `tokio::sync::broadcast` channel: slow subscribers lose the
+//! oldest events instead of blocking the node.
+
+use tokio::sync::broadcast;
+
+use crate::node::lifecycle::NodeStatus;
+use crate::node::mode::NodeMode;
+
+#[cfg(feature = "replication")]
+use crate::replication::report::{KeyReplicationReport, ReplicationReport};
+
+/// Default count of events buffered for every subscriber
+pub const DEFAULT_EVENTS_CAPACITY: usize = 1024;
+
+/// Event of the node
+#[derive(Debug, Clone)]
+pub enum NodeEvent {
+    /// Replication of one key finished (successfully or not)
+    #[cfg(feature = "replication")]
+    ReplicationProgress(KeyReplicationReport),
+    /// Replication round finished
+    #[cfg(feature = "replication")]
+    ReplicationCompleted(ReplicationReport),
+    /// Free disk space fell below `min_free_bytes`, node went to degraded mode
+    DiskSpaceLow {
+        free_bytes: u64,
+        min_free_bytes: u64,
+    },
+    /// Free disk space is enough again, node left degraded mode
+    DiskSpaceRecovered { free_bytes: u64 },
+    /// New thread was created through the API of this node
+    ThreadCreated { thread_id: String },
+    /// Peer was blocked, its messages are dropped
+    PeerBlacklisted {
+        peer_id: Vec<u8>,
+        seconds: f64,
+        reason: String,
+    },
+    /// Node moved to the next stage of its life
+    StatusChanged { status: NodeStatus },
+    /// Node switched between warm-up, normal and read-only mode
+    ModeChanged { mode: NodeMode },
+    /// Node generated new identity, it is used after restart
+    #[cfg(feature = "signatures")]
+    IdentityRotated { old_id: Vec<u8>, new_id: Vec<u8> },
+    /// Background task of the node panicked, it will be restarted
+    BackgroundTaskFailed {
+        name: String,
+        error: String,
+        /// Count of restarts of this task
+        restarts: u32,
+    },
+}
+
+/// Sender of the node events
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<NodeEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENTS_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Send event to all subscribers
+    ///
+    /// Event is dropped if nobody listens.
+    pub fn emit(&self, event: NodeEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Get receiver of all next events
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Count of active subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
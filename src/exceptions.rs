@@ -45,6 +45,14 @@ pub enum DHTError {
     #[error("Value not found in DHT")]
     ValueNotFound,
 
+    /// Not enough replicas answered to satisfy the requested read quorum.
+    #[error("Read quorum not reached")]
+    QuorumNotReached,
+
+    /// The query did not finish before the deadline of its budget.
+    #[error("Query deadline exceeded")]
+    DeadlineExceeded,
+
     /// An unspecified error occurred within the DHT logic.
     #[error("General DHT error")]
     General,
@@ -57,6 +65,34 @@ pub enum StorageError {
     #[error("Storage full")]
     StorageFull,
 
+    /// The node is in read-only mode and does not accept data.
+    #[error("Storage is read-only")]
+    ReadOnly,
+
+    /// The node has just started and does not accept data yet.
+    #[error("Node is warming up")]
+    WarmingUp,
+
+    /// The publisher of the value used its storage quota on this node.
+    #[error("Publisher quota exceeded")]
+    QuotaExceeded,
+
+    /// The content policy of the node rejected the value.
+    #[error("Rejected by content policy")]
+    PolicyRejected,
+
+    /// The value was deleted later than the written copy was published.
+    #[error("Value was deleted")]
+    Deleted,
+
+    /// The stored value could not be decompressed.
+    #[error("Stored value is corrupted")]
+    Corrupted,
+
+    /// The value is larger than the limit of its kind.
+    #[error("Value is too large")]
+    ValueTooLarge,
+
     /// Data could not be successfully synchronized across replicas.
     #[error("Replication error")]
     ReplicationError,
@@ -66,6 +102,15 @@ pub enum StorageError {
     General,
 }
 
+impl From<heed::Error> for StorageError {
+    fn from(error: heed::Error) -> Self {
+        match error {
+            heed::Error::Mdb(heed::MdbError::MapFull) => StorageError::StorageFull,
+            _ => StorageError::General,
+        }
+    }
+}
+
 /// Errors specific to network transport and connectivity.
 #[derive(Error, Debug, uniffi::Error)]
 pub enum NetworkError {
@@ -73,6 +118,10 @@ pub enum NetworkError {
     #[error("Bootstrap process failed")]
     BootstrapError,
 
+    /// The node did not find enough peers to be ready within the given time.
+    #[error("Network was not joined in time")]
+    JoinTimeout,
+
     /// The remote node or local rate limiter blocked the request due to frequency.
     #[error("Rate limit exceeded")]
     RateLimitError,
@@ -89,6 +138,14 @@ pub enum SecurityError {
     #[error("Invalid signature")]
     InvalidSignature,
 
+    /// Encrypted data is corrupted or the passphrase is wrong.
+    #[error("Decryption failed")]
+    DecryptionFailed,
+
+    /// Token of the admin API is unknown or its role is too low.
+    #[error("Access denied")]
+    AccessDenied,
+
     /// An unspecified error occurred during security processing.
     #[error("General security error")]
     General,
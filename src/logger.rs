@@ -1,57 +1,95 @@
 //! # Logger Module
 //!
 //! This module provides a centralized utility for initializing the global logging system
-//! based on the `tracing` ecosystem. It supports multiple output formats:
+//! based on the `tracing` ecosystem. Logging is global for the process, so several nodes in
+//! one process share it and their records are told apart by the `node` span. It supports multiple output formats:
 //! - **Console Output**: Optimized for human readability in the terminal.
-//! - **File Output**: Structured JSON format, ideal for log aggregation and analysis.
+//! - **File Output**: Structured JSON format, ideal for log aggregation and analysis
+//!   _(plain text without the `json-logs` feature)_.
 //!
 //! ## Features
 //! - Configurable log levels via function arguments or `RUST_LOG` environment variable.
+//! - Separate levels of subsystems, so one subsystem can be debugged without UDP traces of others.
 //! - Timestamp formatting using the RFC 3339 standard.
 //! - Contextual logging (e.g., including Node IDs).
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::path::PathBuf;
 use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 
 /// Initializes the global tracing subscriber for the application.
 ///
-/// This function should be called **once** during the application startup (usually in `main`).
-/// It configures how logs are filtered, formatted, and where they are written.
+/// This function should be called during the application startup (usually in `main`).
+/// It configures how logs are filtered, formatted, and where they are written. Later calls
+/// keep the first subscriber and return `false`.
 ///
 /// # Arguments
 ///
 /// * `log_level` - A string representing the default log level (e.g., "info", "debug", "trace").
-/// * `log_file` - An optional path to a file. If `Some`, logs will be written in **JSON format** to that file.
+/// * `log_file` - An optional path to a file. If `Some`, logs will be written in **JSON format** to that file
+///   _(plain text without the `json-logs` feature)_.
 /// * `node_id` - An optional string slice representing the unique ID of the current node for startup context.
 ///
 /// # Panics
 ///
-/// This function will panic if:
-/// * It fails to create or open the file specified in `log_file`.
-/// * The global subscriber has already been initialized by another part of the code.
+/// This function will panic if it fails to create or open the file specified in `log_file`.
 #[allow(dead_code)]
-pub fn setup_logging(log_level: &str, log_file: Option<PathBuf>, node_id: Option<&str>) {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(log_level));
+pub fn setup_logging(log_level: &str, log_file: Option<PathBuf>, node_id: Option<&str>) -> bool {
+    setup_logging_with_levels(log_level, &HashMap::new(), log_file, node_id)
+}
+
+/// Same as [`setup_logging`], but with separate levels of subsystems
+///
+/// # Arguments
+///
+/// * `module_levels` - Subsystem -> level, e.g. `network` -> `debug`. Short names of crate
+///   modules (`dht`, `network`, `storage`, ...) are expanded to `rhizome_p2p::<name>`,
+///   names with `::` are used as is.
+///
+/// `RUST_LOG` environment variable, if set, replaces all levels.
+#[allow(dead_code)]
+pub fn setup_logging_with_levels(
+    log_level: &str,
+    module_levels: &HashMap<String, String>,
+    log_file: Option<PathBuf>,
+    node_id: Option<&str>,
+) -> bool {
+    // Do not truncate the log file of the subscriber which is already set
+    if tracing::dispatcher::has_been_set() {
+        return false;
+    }
+
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| build_filter(log_level, module_levels));
 
     let timer = fmt::time::ChronoLocal::rfc_3339();
 
-    if let Some(path) = log_file {
+    let installed = if let Some(path) = log_file {
         let file = File::create(path).expect("Failed to create log file");
 
+        #[cfg(feature = "json-logs")]
         let layer = fmt::layer().with_timer(timer).json().with_writer(file);
+        #[cfg(not(feature = "json-logs"))]
+        let layer = fmt::layer()
+            .with_timer(timer)
+            .with_ansi(false)
+            .with_writer(file);
 
         tracing_subscriber::registry()
             .with(filter)
             .with(layer)
-            .init();
+            .try_init()
     } else {
         let layer = fmt::layer().with_timer(timer).with_writer(std::io::stdout);
 
         tracing_subscriber::registry()
             .with(filter)
             .with(layer)
-            .init();
+            .try_init()
+    };
+    if installed.is_err() {
+        return false;
     }
 
     if let Some(id) = node_id {
@@ -62,6 +100,25 @@ pub fn setup_logging(log_level: &str, log_file: Option<PathBuf>, node_id: Option
             "Logging initialized"
         );
     }
+    true
+}
+
+/// Filter with default level and levels of subsystems, invalid directives are ignored
+pub fn build_filter(log_level: &str, module_levels: &HashMap<String, String>) -> EnvFilter {
+    let mut directives = vec![log_level.to_string()];
+    for (module, level) in module_levels {
+        if module.contains("::") {
+            directives.push(format!("{}={}", module, level));
+        } else {
+            directives.push(format!(
+                "{}::{}={}",
+                env!("CARGO_CRATE_NAME"),
+                module,
+                level
+            ));
+        }
+    }
+    EnvFilter::builder().parse_lossy(directives.join(","))
 }
 
 /// Logs an informational message indicating that a specific module has been initialized.
@@ -0,0 +1,267 @@
+//! # Mailbox Module
+//!
+//! Store-and-forward direct messages for recipients which are offline. Message is signed
+//! by the sender, encrypted to the public key of the recipient and appended to the mailbox
+//! of the recipient in the DHT. Key of the mailbox is derived from the public key of the
+//! recipient, so the recipient finds it when it comes online and removes fetched messages.
+//!
+//! Nodes which keep the mailbox see only count and size of messages, not their senders.
+//! Online recipient gets the same envelope directly in `MSG_DIRECT` and confirms delivery
+//! with signed receipt, mailbox is used only if it does not answer.
+//!
+//! Whole mailbox is one value within [`MAX_MAILBOX_BYTES`], so it is stored and read in one
+//! datagram. Envelopes are kept in order of their arrival, the earliest are dropped first.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::exceptions::StorageError;
+use crate::network::fragments::MAX_VALUE_CHUNK;
+use crate::storage::keys::{Key, KeyManager};
+use crate::storage::main::Storage;
+use crate::utils::crypto::{NodeSigner, node_id_from_public_key_der, seal_for, verify_signature};
+use crate::utils::serialization::{from_msgpack, to_msgpack};
+
+/// Max count of messages in one mailbox, the oldest are dropped
+pub const MAX_MAILBOX_MESSAGES: usize = 100;
+
+/// Max length of message content in bytes, its envelope fits into [`MAX_MAILBOX_BYTES`]
+pub const MAX_MAILBOX_CONTENT: usize = 4 * 1024;
+
+/// Max size of the encoded mailbox value
+pub const MAX_MAILBOX_BYTES: usize = MAX_VALUE_CHUNK;
+
+/// Key of the mailbox of the owner of DER encoded public key
+pub fn mailbox_key(keys: &KeyManager, public_key_der: &[u8]) -> Key {
+    keys.get_mailbox_key(&hex::encode(node_id_from_public_key_der(public_key_der)))
+}
+
+/// Encrypted message in the mailbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailboxEnvelope {
+    /// Random hex ID, fetched messages are acknowledged by it
+    pub id: String,
+    /// Time of sending
+    pub sent_at: i64,
+    /// Signed letter sealed for the recipient
+    pub sealed: Vec<u8>,
+}
+
+/// Content of the envelope, only the recipient can read it
+#[derive(Serialize, Deserialize)]
+struct Letter {
+    /// Node ID of the sender
+    sender_id: Vec<u8>,
+    /// DER of the public key of the sender
+    sender_key: Vec<u8>,
+    content: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl Letter {
+    /// Bytes which are covered by the signature
+    ///
+    /// Recipient _(ID of its public key)_ and envelope ID are signed too, so letter can not
+    /// be resent to other recipient or duplicated under new ID.
+    fn signed_bytes(
+        recipient_id: &[u8],
+        envelope_id: &str,
+        sender_id: &[u8],
+        content: &[u8],
+        sent_at: i64,
+    ) -> Option<Vec<u8>> {
+        to_msgpack(&(recipient_id, envelope_id, sender_id, content, sent_at)).ok()
+    }
+}
+
+/// Message opened by the recipient
+#[derive(Debug, Clone)]
+pub struct MailboxMessage {
+    pub id: String,
+    /// Node ID of the sender
+    pub sender_id: Vec<u8>,
+    /// DER of the public key of the sender, answer is sealed for it
+    pub sender_key: Vec<u8>,
+    pub content: Vec<u8>,
+    pub sent_at: i64,
+}
+
+/// Sign the message and seal it for the recipient
+///
+/// Return `None` if the public key of the recipient is not valid.
+pub fn seal_message(
+    signer: &NodeSigner,
+    recipient_key: &[u8],
+    content: &[u8],
+    sent_at: i64,
+) -> Option<MailboxEnvelope> {
+    let id = hex::encode(rand::random::<[u8; 16]>());
+    let recipient_id = node_id_from_public_key_der(recipient_key);
+    let signed = Letter::signed_bytes(&recipient_id, &id, signer.node_id(), content, sent_at)?;
+    let letter = Letter {
+        sender_id: signer.node_id().to_vec(),
+        sender_key: signer.public_key_der().to_vec(),
+        content: content.to_vec(),
+        signature: signer.sign(&signed),
+    };
+    Some(MailboxEnvelope {
+        id,
+        sent_at,
+        sealed: seal_for(recipient_key, &to_msgpack(&letter).ok()?)?,
+    })
+}
+
+/// Decrypt the envelope and check the signature of the sender
+///
+/// Return `None` if the envelope is not for us or the signature is not valid.
+pub fn open_message(signer: &NodeSigner, envelope: &MailboxEnvelope) -> Option<MailboxMessage> {
+    let letter: Letter = from_msgpack(&signer.open(&envelope.sealed)?).ok()?;
+    let signed = Letter::signed_bytes(
+        &node_id_from_public_key_der(signer.public_key_der()),
+        &envelope.id,
+        &letter.sender_id,
+        &letter.content,
+        envelope.sent_at,
+    )?;
+    if !verify_signature(&letter.sender_key, &signed, &letter.signature) {
+        return None;
+    }
+    Some(MailboxMessage {
+        id: envelope.id.clone(),
+        sender_id: letter.sender_id,
+        sender_key: letter.sender_key,
+        content: letter.content,
+        sent_at: envelope.sent_at,
+    })
+}
+
+/// Envelopes of the mailbox value _(empty if there is no value or it is broken)_
+pub fn decode_mailbox(data: Option<&[u8]>) -> Vec<MailboxEnvelope> {
+    data.and_then(|data| from_msgpack(data).ok())
+        .unwrap_or_default()
+}
+
+/// Mailbox value with the new envelope at its end
+///
+/// Envelopes which arrived first are dropped while there are more than
+/// [`MAX_MAILBOX_MESSAGES`] or the value is bigger than [`MAX_MAILBOX_BYTES`]. Time of
+/// sending is set by the sender, so it does not decide which envelopes are dropped.
+pub fn append_to_mailbox(data: Option<&[u8]>, envelope: &MailboxEnvelope) -> Vec<u8> {
+    let mut envelopes = decode_mailbox(data);
+    if !envelopes.iter().any(|e| e.id == envelope.id) {
+        envelopes.push(envelope.clone());
+    }
+    let overflow = envelopes.len().saturating_sub(MAX_MAILBOX_MESSAGES);
+    envelopes.drain(..overflow);
+    loop {
+        let value = to_msgpack(&envelopes).unwrap_or_default();
+        if value.len() <= MAX_MAILBOX_BYTES || envelopes.len() <= 1 {
+            return value;
+        }
+        envelopes.remove(0);
+    }
+}
+
+/// Mailbox value without acknowledged envelopes
+pub fn remove_from_mailbox(data: Option<&[u8]>, ids: &[String]) -> Vec<u8> {
+    let mut envelopes = decode_mailbox(data);
+    envelopes.retain(|e| !ids.contains(&e.id));
+    to_msgpack(&envelopes).unwrap_or_default()
+}
+
+/// Bytes which the recipient signs in the delivery receipt of the direct message
+pub fn receipt_bytes(envelope_id: &str) -> Vec<u8> {
+    format!("delivered:{}", envelope_id).into_bytes()
+}
+
+/// Direct messages received by our node until the client fetches them
+///
+/// Envelopes stay sealed in the inbox of the node storage, so messages whose delivery was
+/// confirmed to senders are not lost on restart. Without storage they are kept in memory.
+pub struct DirectInbox {
+    storage: Option<Arc<Storage>>,
+    envelopes: Mutex<VecDeque<MailboxEnvelope>>,
+}
+
+impl DirectInbox {
+    pub fn new(storage: Option<Arc<Storage>>) -> Self {
+        Self {
+            storage,
+            envelopes: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Keep the envelope, the oldest ones over [`MAX_MAILBOX_MESSAGES`] are dropped
+    ///
+    /// Return `false` if the envelope is already in the inbox.
+    pub async fn push(&self, envelope: &MailboxEnvelope) -> Result<bool, StorageError> {
+        if let Some(storage) = &self.storage {
+            let item = to_msgpack(envelope).map_err(|_| StorageError::General)?;
+            return storage
+                .push_inbox(envelope.id.as_bytes().to_vec(), item, MAX_MAILBOX_MESSAGES)
+                .await;
+        }
+        let mut envelopes = self.envelopes.lock().unwrap();
+        if envelopes.iter().any(|e| e.id == envelope.id) {
+            return Ok(false);
+        }
+        if envelopes.len() >= MAX_MAILBOX_MESSAGES {
+            envelopes.pop_front();
+        }
+        envelopes.push_back(envelope.clone());
+        Ok(true)
+    }
+
+    /// Take all received envelopes, the oldest first
+    pub async fn take_all(&self) -> Result<Vec<MailboxEnvelope>, StorageError> {
+        let Some(storage) = &self.storage else {
+            return Ok(self.envelopes.lock().unwrap().drain(..).collect());
+        };
+        Ok(storage
+            .take_inbox()
+            .await?
+            .iter()
+            .filter_map(|item| from_msgpack(item).ok())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::crypto::{generate_keypair, node_id_from_public_key};
+
+    fn signer() -> NodeSigner {
+        let (private_key, public_key) = generate_keypair();
+        NodeSigner::new(private_key, node_id_from_public_key(&public_key).to_vec())
+    }
+
+    #[test]
+    fn mailbox_keeps_latest_arrivals_within_budget() {
+        let signer = signer();
+        let content: Vec<u8> = (0..MAX_MAILBOX_CONTENT).map(|_| rand::random()).collect();
+        let largest = seal_message(&signer, signer.public_key_der(), &content, 10).unwrap();
+        let data = append_to_mailbox(None, &largest);
+        assert!(data.len() <= MAX_MAILBOX_BYTES);
+
+        // Envelope which arrived first is dropped, though it was sent later
+        let backdated = seal_message(&signer, signer.public_key_der(), b"short", 0).unwrap();
+        let data = append_to_mailbox(Some(&data), &backdated);
+        assert!(data.len() <= MAX_MAILBOX_BYTES);
+        let ids: Vec<String> = decode_mailbox(Some(&data))
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(ids, vec![backdated.id.clone()]);
+
+        let later = seal_message(&signer, signer.public_key_der(), b"later", 20).unwrap();
+        let data = append_to_mailbox(Some(&data), &later);
+        let data = append_to_mailbox(Some(&data), &backdated);
+        let ids: Vec<String> = decode_mailbox(Some(&data))
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(ids, vec![backdated.id.clone(), later.id.clone()]);
+    }
+}
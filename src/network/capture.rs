@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::network::protocol::{NetworkProtocol, ProtocolMessage};
+use crate::network::transport::Message;
+use crate::utils::serialization::decode_message;
+use crate::utils::time::{duration_from_secs, get_now_f64};
+
+/// First line of every capture file
+const CAPTURE_HEADER: &str = r#"{"format":"rhizome-capture","version":1}"#;
+/// Max count of packets waiting for the capture writer, new packets are dropped above it
+const CAPTURE_QUEUE: usize = 4096;
+
+/// Direction of the captured packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureDirection {
+    Inbound,
+    Outbound,
+}
+
+/// One packet in the capture file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapturedPacket {
+    /// Time of receiving or sending
+    pub timestamp: f64,
+    pub direction: CaptureDirection,
+    /// Address of the peer
+    pub address: SocketAddr,
+    /// Packet bytes in hex, they are fed back on replay
+    pub raw: String,
+    /// Decoded message _(None if packet is not a valid message)_
+    pub message: Option<ProtocolMessage>,
+}
+
+/// Packet which waits for the capture writer
+struct TappedPacket {
+    timestamp: f64,
+    direction: CaptureDirection,
+    address: SocketAddr,
+    data: Vec<u8>,
+}
+
+/// Packet tap of the transport
+///
+/// Every packet is written as one JSON line with its decoded message, so the file can be
+/// read by eye and replayed by [`replay_capture`]. Packets are decoded and written by a
+/// blocking task, so the receive and send loops do not wait for the disk.
+pub struct PacketTap {
+    packets: mpsc::Sender<TappedPacket>,
+    /// Packets were dropped because the writer fell behind
+    overflowed: AtomicBool,
+}
+
+impl PacketTap {
+    /// Open capture file, new packets are appended to it
+    ///
+    /// Writer task stops when the tap is dropped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let is_new = !path.exists() || path.metadata()?.len() == 0;
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if is_new {
+            writeln!(file, "{}", CAPTURE_HEADER)?;
+        }
+        let (packets, receiver) = mpsc::channel(CAPTURE_QUEUE);
+        tokio::task::spawn_blocking(move || Self::write_loop(file, receiver));
+        Ok(Self {
+            packets,
+            overflowed: AtomicBool::new(false),
+        })
+    }
+
+    /// Queue packet for the capture file
+    pub fn record(&self, direction: CaptureDirection, address: SocketAddr, data: &[u8]) {
+        let packet = TappedPacket {
+            timestamp: get_now_f64(),
+            direction,
+            address,
+            data: data.to_vec(),
+        };
+        if self.packets.try_send(packet).is_err() && !self.overflowed.swap(true, Ordering::Relaxed)
+        {
+            warn!("Capture writer falls behind, captured packets are dropped");
+        }
+    }
+
+    fn write_loop(mut file: File, mut packets: mpsc::Receiver<TappedPacket>) {
+        while let Some(tapped) = packets.blocking_recv() {
+            let packet = CapturedPacket {
+                timestamp: tapped.timestamp,
+                direction: tapped.direction,
+                address: tapped.address,
+                raw: hex::encode(&tapped.data),
+                message: decode_message(&tapped.data).ok(),
+            };
+            let Ok(line) = serde_json::to_string(&packet) else {
+                continue;
+            };
+            if let Err(e) = writeln!(file, "{}", line) {
+                warn!(error = %e, "Failed to write captured packet");
+            }
+        }
+    }
+}
+
+/// Read packets from the capture file, broken lines are skipped
+pub fn read_capture(path: &Path) -> io::Result<Vec<CapturedPacket>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut packets = Vec::new();
+    for line in reader.lines() {
+        if let Ok(packet) = serde_json::from_str::<CapturedPacket>(&line?) {
+            packets.push(packet);
+        }
+    }
+    Ok(packets)
+}
+
+/// Feed inbound packets of the capture back into the protocol
+///
+/// Packets are handled as if they came now from their recorded addresses. With `realtime`
+/// the recorded pauses between packets are kept. Recorded timestamps are old, so protocol
+/// for replay should have `timestamp_window = None`. Return count of replayed packets.
+pub async fn replay_capture(
+    protocol: &NetworkProtocol,
+    packets: &[CapturedPacket],
+    realtime: bool,
+) -> usize {
+    let mut previous: Option<f64> = None;
+    let mut replayed = 0;
+    for packet in packets
+        .iter()
+        .filter(|p| p.direction == CaptureDirection::Inbound)
+    {
+        let Ok(data) = hex::decode(&packet.raw) else {
+            continue;
+        };
+        if realtime && let Some(previous) = previous {
+            let pause = (packet.timestamp - previous).max(0.0);
+            tokio::time::sleep(duration_from_secs(pause)).await;
+        }
+        previous = Some(packet.timestamp);
+
+        protocol
+            .handle_incoming_message(Message {
+                data,
+                address: packet.address,
+                timestamp: get_now_f64(),
+            })
+            .await;
+        replayed += 1;
+    }
+    replayed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir::TempDir;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn tapped_packets_are_written_by_the_writer_task() {
+        let dir = TempDir::new("capture");
+        let path = dir.join("traffic.capture");
+        let address: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+
+        let tap = PacketTap::open(&path).unwrap();
+        tap.record(CaptureDirection::Inbound, address, b"not a message");
+        tap.record(CaptureDirection::Outbound, address, b"other");
+        drop(tap);
+
+        let mut packets = Vec::new();
+        for _ in 0..100 {
+            packets = read_capture(&path).unwrap();
+            if packets.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].direction, CaptureDirection::Inbound);
+        assert_eq!(packets[0].raw, hex::encode(b"not a message"));
+        assert!(packets[0].message.is_none());
+    }
+}
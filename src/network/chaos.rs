@@ -0,0 +1,133 @@
+#[cfg(feature = "fault-injection")]
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "fault-injection")]
+use std::sync::RwLock;
+#[cfg(feature = "fault-injection")]
+use std::time::Duration;
+
+#[cfg(feature = "fault-injection")]
+use crate::utils::rng::NodeRng;
+#[cfg(feature = "fault-injection")]
+use crate::utils::time::duration_from_secs;
+
+/// Distribution of latency added to every sent packet
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LatencyDistribution {
+    /// Packets are sent at once
+    #[default]
+    None,
+    /// Same latency for every packet
+    Constant { ms: f64 },
+    /// Latency between `min_ms` and `max_ms` with equal chances
+    Uniform { min_ms: f64, max_ms: f64 },
+    /// Normal distribution cut at zero
+    Normal { mean_ms: f64, std_dev_ms: f64 },
+    /// Exponential distribution, mostly short latency with a long tail
+    Exponential { mean_ms: f64 },
+}
+
+#[cfg(feature = "fault-injection")]
+impl LatencyDistribution {
+    /// Random latency in milliseconds
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        let ms = match *self {
+            Self::None => 0.0,
+            Self::Constant { ms } => ms,
+            Self::Uniform { min_ms, max_ms } => min_ms + (max_ms - min_ms) * rng.r#gen::<f64>(),
+            Self::Normal {
+                mean_ms,
+                std_dev_ms,
+            } => {
+                // Box-Muller transform
+                let u1 = 1.0 - rng.r#gen::<f64>();
+                let u2 = rng.r#gen::<f64>();
+                mean_ms
+                    + std_dev_ms * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+            }
+            Self::Exponential { mean_ms } => -mean_ms * (1.0 - rng.r#gen::<f64>()).ln(),
+        };
+        if ms.is_finite() { ms.max(0.0) } else { 0.0 }
+    }
+}
+
+/// Faults injected into outbound packets of the transport
+///
+/// Faults are injected only by builds with `fault-injection` feature.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct FaultConfig {
+    /// Chance that packet is lost _(0.0 - 1.0)_
+    #[serde(default)]
+    pub drop_rate: f64,
+    /// Chance that packet is sent twice
+    #[serde(default)]
+    pub duplicate_rate: f64,
+    /// Chance that packet is held back, so later packets overtake it
+    #[serde(default)]
+    pub reorder_rate: f64,
+    /// How long reordered packet is held back in milliseconds
+    #[serde(default)]
+    pub reorder_delay_ms: f64,
+    /// Latency of every packet
+    #[serde(default)]
+    pub latency: LatencyDistribution,
+}
+
+/// Fault injection layer of the transport
+///
+/// Every outbound packet is planned here: it can be dropped, duplicated, held back
+/// or delayed. It is made for simulations which check timeouts and retries, it must never
+/// be on in production. Faults can be changed while the node runs, so one test can
+/// go through several network conditions. With seeded random generator decisions are
+/// reproducible.
+#[cfg(feature = "fault-injection")]
+pub struct FaultInjector {
+    config: RwLock<FaultConfig>,
+    rng: NodeRng,
+}
+
+#[cfg(feature = "fault-injection")]
+impl FaultInjector {
+    pub fn new(config: FaultConfig, seed: Option<u64>) -> Self {
+        Self {
+            config: RwLock::new(config),
+            rng: NodeRng::new(seed),
+        }
+    }
+
+    pub fn config(&self) -> FaultConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replace faults for next packets
+    pub fn set_config(&self, config: FaultConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Delays of copies of the packet which are sent _(empty if packet is dropped)_
+    pub fn plan(&self) -> Vec<Duration> {
+        let config = self.config.read().unwrap();
+        self.rng.with(|rng| {
+            if rng.r#gen::<f64>() < config.drop_rate {
+                return Vec::new();
+            }
+            let copies = if rng.r#gen::<f64>() < config.duplicate_rate {
+                2
+            } else {
+                1
+            };
+            (0..copies)
+                .map(|_| {
+                    let mut ms = config.latency.sample(rng);
+                    if rng.r#gen::<f64>() < config.reorder_rate
+                        && config.reorder_delay_ms.is_finite()
+                    {
+                        ms += config.reorder_delay_ms.max(0.0);
+                    }
+                    duration_from_secs(ms / 1000.0)
+                })
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+
+/// Count of last samples used for estimation of one peer
+pub const MAX_SKEW_SAMPLES: usize = 16;
+/// Samples with larger offset in seconds are treated as broken clock and ignored
+pub const MAX_TRUSTED_SKEW: f64 = 86400.0;
+/// Max count of peers with tracked clocks, the peer tracked longest is forgotten first
+pub const MAX_TRACKED_CLOCKS: usize = 4096;
+/// Seconds which timestamps of peers with unknown offset may differ beyond the usual
+/// window, so their offset can be learned
+pub const UNLEARNED_SKEW_ALLOWANCE: f64 = 600.0;
+
+/// Estimator of clock offsets of peers
+///
+/// Works like NTP with one timestamp: every message carries time of sending on the peer,
+/// so `remote_time + rtt / 2 - local_receive_time` is a sample of the peer offset.
+/// Median of last samples is used, so delayed packets do not move the estimation.
+/// Positive offset means that the peer clock is ahead of ours.
+#[derive(Default)]
+pub struct ClockSkew {
+    clocks: RwLock<TrackedClocks>,
+}
+
+#[derive(Default)]
+struct TrackedClocks {
+    /// Peer node ID -> last offset samples
+    samples: HashMap<Vec<u8>, VecDeque<f64>>,
+    /// Peers in order they were first tracked
+    order: VecDeque<Vec<u8>>,
+}
+
+impl ClockSkew {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Save new sample of the peer offset
+    ///
+    /// `rtt` is round-trip time to the peer if it is known
+    pub fn record(&self, peer_id: &[u8], remote_time: f64, local_time: f64, rtt: Option<f64>) {
+        let sample = remote_time + rtt.unwrap_or(0.0) / 2.0 - local_time;
+        if !sample.is_finite() || sample.abs() > MAX_TRUSTED_SKEW {
+            return;
+        }
+
+        let mut clocks = self.clocks.write().unwrap();
+        if !clocks.samples.contains_key(peer_id) {
+            while clocks.samples.len() >= MAX_TRACKED_CLOCKS {
+                let Some(oldest) = clocks.order.pop_front() else {
+                    break;
+                };
+                clocks.samples.remove(&oldest);
+            }
+            clocks.order.push_back(peer_id.to_vec());
+        }
+        let peer_samples = clocks.samples.entry(peer_id.to_vec()).or_default();
+        if peer_samples.len() >= MAX_SKEW_SAMPLES {
+            peer_samples.pop_front();
+        }
+        peer_samples.push_back(sample);
+    }
+
+    /// Estimated offset of the peer clock in seconds _(0 if unknown)_
+    pub fn offset(&self, peer_id: &[u8]) -> f64 {
+        self.known_offset(peer_id).unwrap_or(0.0)
+    }
+
+    /// Estimated offset of the peer clock in seconds _(None if there are no samples yet)_
+    pub fn known_offset(&self, peer_id: &[u8]) -> Option<f64> {
+        self.clocks
+            .read()
+            .unwrap()
+            .samples
+            .get(peer_id)
+            .map(|samples| {
+                let mut sorted: Vec<f64> = samples.iter().copied().collect();
+                sorted.sort_by(|a, b| a.total_cmp(b));
+                sorted[sorted.len() / 2]
+            })
+    }
+
+    /// Convert time from the peer clock to our clock
+    pub fn to_local(&self, peer_id: &[u8], remote_time: f64) -> f64 {
+        remote_time - self.offset(peer_id)
+    }
+
+    /// Forget the peer
+    pub fn remove(&self, peer_id: &[u8]) {
+        let mut clocks = self.clocks.write().unwrap();
+        if clocks.samples.remove(peer_id).is_some() {
+            clocks.order.retain(|peer| peer != peer_id);
+        }
+    }
+
+    /// Count of peers with known offset
+    pub fn peer_count(&self) -> usize {
+        self.clocks.read().unwrap().samples.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_peers_are_forgotten_when_tracking_is_full() {
+        let skew = ClockSkew::new();
+        for peer in 0..MAX_TRACKED_CLOCKS as u32 + 1 {
+            skew.record(&peer.to_be_bytes(), 105.0, 100.0, None);
+        }
+        assert_eq!(skew.peer_count(), MAX_TRACKED_CLOCKS);
+        assert_eq!(skew.known_offset(&0u32.to_be_bytes()), None);
+        let newest = MAX_TRACKED_CLOCKS as u32;
+        assert_eq!(skew.known_offset(&newest.to_be_bytes()), Some(5.0));
+    }
+}
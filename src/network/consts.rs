@@ -2,6 +2,12 @@
 //!
 //! Need to be serialized for fast data transfer
 
+/// Version of the wire protocol, it grows with every incompatible change of messages
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version of peers which this build still understands
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
 /// Message for check node status
 pub const MSG_PING: u8 = 0x01;
 
@@ -37,3 +43,54 @@ pub const MSG_GLOBAL_RANKING_REQUEST: u8 = 0x0B;
 
 /// Answer with global ranking
 pub const MSG_GLOBAL_RANKING_RESPONSE: u8 = 0x0C;
+
+/// Push of gossip messages _(no answer)_
+pub const MSG_GOSSIP_PUSH: u8 = 0x0D;
+
+/// Request with digest of known gossip messages
+pub const MSG_GOSSIP_DIGEST: u8 = 0x0E;
+
+/// Answer with messages missed by requester and ids missed by us
+pub const MSG_GOSSIP_DIGEST_RESPONSE: u8 = 0x0F;
+
+/// Request for records of some type stored on the node
+pub const MSG_LIST_RECORDS: u8 = 0x10;
+
+/// Answer with records of requested type
+pub const MSG_LIST_RECORDS_RESPONSE: u8 = 0x11;
+
+/// Request for values of several keys
+pub const MSG_FIND_VALUES: u8 = 0x12;
+
+/// Answer with found values, missing keys and keys left for next chunk
+pub const MSG_FIND_VALUES_RESPONSE: u8 = 0x13;
+
+/// Request to save value only if current value has expected hash
+pub const MSG_STORE_CAS: u8 = 0x14;
+
+/// Approve of conditional write or conflicting current value
+pub const MSG_STORE_CAS_RESPONSE: u8 = 0x15;
+
+/// Puzzle which must be solved before the request is processed _(answer on any request)_
+pub const MSG_PUZZLE: u8 = 0x16;
+
+/// Solution of the puzzle, the waiting request is processed after it
+pub const MSG_PUZZLE_SOLUTION: u8 = 0x17;
+
+/// Request to replace value with tombstone
+pub const MSG_DELETE: u8 = 0x18;
+
+/// Approve or Reject of the tombstone
+pub const MSG_DELETE_RESPONSE: u8 = 0x19;
+
+/// Direct message sealed for the recipient node
+pub const MSG_DIRECT: u8 = 0x1A;
+
+/// Delivery receipt of the direct message signed by the recipient
+pub const MSG_DIRECT_RESPONSE: u8 = 0x1B;
+
+/// Fragment of a large STORE value _(no answer)_
+pub const MSG_STORE_FRAGMENT: u8 = 0x1C;
+
+/// Answer on STORE of fragmented value with fragments which were not received
+pub const MSG_STORE_FRAGMENTS_MISSING: u8 = 0x1D;
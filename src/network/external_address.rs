@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::RwLock;
+
+use crate::security::blacklist::Subnet;
+use crate::utils::time::get_now_f64;
+
+/// Count of peers which must see the same address before we trust it
+pub const MIN_ADDRESS_VOTES: usize = 2;
+
+/// Observations older than this time in seconds are not counted
+const VOTE_MAX_AGE: f64 = 3600.0;
+
+/// Subnets whose votes are kept, new ones are ignored while all are fresh
+const MAX_VOTERS: usize = 1024;
+
+/// Our address as peers see it
+///
+/// Every peer reports the source address of our PING in its PONG. Node IDs are chosen by
+/// peers themselves, so votes are counted by the subnet of the address we pinged
+/// _(see [`Subnet::group`])_. One subnet has one vote, the address seen by most subnets
+/// is our external address.
+#[derive(Default)]
+pub struct ExternalAddress {
+    /// Reporter subnet -> observed address and time of the report
+    votes: RwLock<HashMap<Subnet, (SocketAddr, f64)>>,
+}
+
+impl ExternalAddress {
+    /// Peer at `reporter` saw our packets coming from `observed`
+    pub fn record(&self, reporter: IpAddr, observed: SocketAddr) {
+        if observed.ip().is_unspecified() || observed.port() == 0 {
+            return;
+        }
+        let now = get_now_f64();
+        let voter = Subnet::group(reporter);
+        let mut votes = self.votes.write().unwrap();
+        votes.retain(|_, (_, at)| now - *at < VOTE_MAX_AGE);
+        if votes.len() >= MAX_VOTERS && !votes.contains_key(&voter) {
+            return;
+        }
+        votes.insert(voter, (observed, now));
+    }
+
+    /// Address seen by most peers if at least [`MIN_ADDRESS_VOTES`] peers saw it
+    pub fn get(&self) -> Option<SocketAddr> {
+        let now = get_now_f64();
+        let mut counts: HashMap<SocketAddr, usize> = HashMap::new();
+        for (addr, at) in self.votes.read().unwrap().values() {
+            if now - at < VOTE_MAX_AGE {
+                *counts.entry(*addr).or_default() += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count >= MIN_ADDRESS_VOTES)
+            .max_by_key(|(addr, count)| (*count, std::cmp::Reverse(*addr)))
+            .map(|(addr, _)| addr)
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::utils::time::{Clock, SystemClock};
+
+/// Size of one fragment of a large STORE, encoded fragment fits into one IP packet
+pub const FRAGMENT_SIZE: usize = 512;
+/// Values bigger than this are sent in fragments to peers which support them
+pub const FRAGMENTED_STORE_THRESHOLD: usize = 8 * 1024;
+/// Max size of a value sent in fragments
+pub const MAX_FRAGMENTED_VALUE_SIZE: usize = 1024 * 1024;
+/// Oldest protocol version of peers which accept fragments
+pub const FRAGMENTS_SINCE_PROTOCOL: u32 = 2;
+/// Max count of fragments sent in one round, bigger bursts overflow receive buffers
+pub const FRAGMENT_WINDOW: usize = 64;
+/// Transfer is given up after this count of rounds in a row without received fragments
+pub const MAX_STALLED_ROUNDS: usize = 3;
+/// Round is repeated if the node does not answer in this many times of the last round
+pub const ROUND_TIMEOUT_FACTOR: u32 = 4;
+/// Shortest wait for the answer on a round
+pub const MIN_ROUND_TIMEOUT: Duration = Duration::from_millis(500);
+/// Max count of indices of missing fragments in one answer
+///
+/// One window is sent per round anyway, so the answer is not bigger than the STORE.
+pub const MAX_REPORTED_MISSING: usize = FRAGMENT_WINDOW;
+/// Max size of the value part in one FIND_VALUE answer, bigger values are read by parts
+///
+/// Part is within one datagram even in JSON of the protobuf codec _(4 bytes per byte)_.
+pub const MAX_VALUE_CHUNK: usize = 12 * 1024;
+/// Max count of unfinished transfers from one address
+const MAX_TRANSFERS_PER_PEER: usize = 4;
+/// Max summary size of unfinished transfers from one address
+const MAX_PEER_BYTES: usize = 2 * MAX_FRAGMENTED_VALUE_SIZE;
+/// Max summary size of all unfinished transfers
+const MAX_ASSEMBLY_BYTES: usize = 16 * 1024 * 1024;
+/// Fragments per second which one address may send _(STOREs of rounds included)_
+const FRAGMENT_RATE: f64 = 4096.0;
+/// Fragments which one address may send at once
+const FRAGMENT_BURST: f64 = 2.0 * FRAGMENT_WINDOW as f64;
+/// Max count of open reads of big values by parts
+const MAX_OPEN_READS: usize = 1024;
+/// Unfinished transfer is dropped after this time in seconds without new fragments
+const TRANSFER_TTL: f64 = 60.0;
+
+/// Count of fragments of the value
+pub fn fragment_count(size: usize) -> usize {
+    size.div_ceil(FRAGMENT_SIZE)
+}
+
+/// Source address and transfer ID
+///
+/// Node ID in the message is chosen by the sender, the address is at least the one which
+/// answered the opening STORE.
+type TransferKey = (IpAddr, [u8; 16]);
+
+/// Transfer of one value which is not finished yet
+struct Transfer {
+    size: usize,
+    fragments: Vec<Option<Vec<u8>>>,
+    updated_at: f64,
+}
+
+impl Transfer {
+    fn missing(&self) -> Vec<u32> {
+        self.fragments
+            .iter()
+            .enumerate()
+            .filter(|(_, fragment)| fragment.is_none())
+            .map(|(index, _)| index as u32)
+            .take(MAX_REPORTED_MISSING)
+            .collect()
+    }
+}
+
+/// Fragments which the address may send now, refilled with time
+struct Allowance {
+    tokens: f64,
+    updated_at: f64,
+}
+
+impl Allowance {
+    fn take(&mut self, now: f64) -> bool {
+        self.tokens =
+            (self.tokens + (now - self.updated_at).max(0.0) * FRAGMENT_RATE).min(FRAGMENT_BURST);
+        self.updated_at = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Result of the attempt to finish the transfer
+#[derive(Debug, Clone, PartialEq)]
+pub enum Assembly {
+    /// All fragments are received, the transfer is forgotten
+    Complete(Vec<u8>),
+    /// Indices of fragments which were not received yet
+    Missing(Vec<u32>),
+    /// Transfer was not opened or it has expired
+    Unknown,
+    /// Size of the value is not accepted
+    Rejected,
+}
+
+#[derive(Default)]
+struct Transfers {
+    transfers: HashMap<TransferKey, Transfer>,
+    allowances: HashMap<IpAddr, Allowance>,
+}
+
+impl Transfers {
+    fn expire(&mut self, now: f64) {
+        self.transfers
+            .retain(|_, t| now - t.updated_at < TRANSFER_TTL);
+        let transfers = &self.transfers;
+        self.allowances
+            .retain(|peer, _| transfers.keys().any(|(p, _)| p == peer));
+    }
+}
+
+/// Values of large STOREs assembled from fragments
+///
+/// Sender opens the transfer by STORE with the size but without the value, this STORE
+/// passes the rate limiter and puzzles like any other one and is answered by the ID of
+/// the transfer and fragments which we miss. Fragments are accepted only for open
+/// transfers from the same address, then the STORE is repeated with the ID and answered
+/// by fragments which were lost, so only they are sent again. Fragments and repeated STOREs are counted by the fragment rate of
+/// the address instead of the rate limiter, memory of unfinished transfers is limited per
+/// address and in total.
+pub struct FragmentAssembler {
+    state: Mutex<Transfers>,
+    /// Clock of the node
+    time: Arc<dyn Clock>,
+}
+
+impl Default for FragmentAssembler {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(Transfers::default()),
+            time: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl FragmentAssembler {
+    /// Same assembler which expires transfers by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
+
+    /// Start the transfer of the value, return its ID _(None if it is not accepted now)_
+    ///
+    /// ID is chosen by us, so nobody but the address which got it can send fragments.
+    pub fn open(&self, peer: IpAddr, size: usize) -> Option<[u8; 16]> {
+        if size <= FRAGMENTED_STORE_THRESHOLD || size > MAX_FRAGMENTED_VALUE_SIZE {
+            return None;
+        }
+        let now = self.time.now();
+        let mut state = self.state.lock().unwrap();
+        state.expire(now);
+        let (peer_count, peer_bytes) = state
+            .transfers
+            .iter()
+            .filter(|((p, _), _)| *p == peer)
+            .fold((0, 0), |(count, bytes), (_, t)| (count + 1, bytes + t.size));
+        let total: usize = state.transfers.values().map(|t| t.size).sum();
+        if peer_count >= MAX_TRANSFERS_PER_PEER
+            || peer_bytes + size > MAX_PEER_BYTES
+            || total + size > MAX_ASSEMBLY_BYTES
+        {
+            return None;
+        }
+
+        let transfer: [u8; 16] = rand::random();
+        state.transfers.insert(
+            (peer, transfer),
+            Transfer {
+                size,
+                fragments: vec![None; fragment_count(size)],
+                updated_at: now,
+            },
+        );
+        state.allowances.entry(peer).or_insert(Allowance {
+            tokens: FRAGMENT_BURST,
+            updated_at: now,
+        });
+        Some(transfer)
+    }
+
+    /// Save received fragment, return `false` if it was dropped
+    pub fn accept(
+        &self,
+        peer: IpAddr,
+        transfer: [u8; 16],
+        size: usize,
+        index: usize,
+        data: Vec<u8>,
+    ) -> bool {
+        let now = self.time.now();
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let Some(entry) = state.transfers.get_mut(&(peer, transfer)) else {
+            return false;
+        };
+        if entry.size != size || index >= entry.fragments.len() {
+            return false;
+        }
+        let expected_len = FRAGMENT_SIZE.min(size - index * FRAGMENT_SIZE);
+        if data.len() != expected_len
+            || !state
+                .allowances
+                .get_mut(&peer)
+                .is_some_and(|allowance| allowance.take(now))
+        {
+            return false;
+        }
+
+        entry.updated_at = now;
+        if entry.fragments[index].is_none() {
+            entry.fragments[index] = Some(data);
+        }
+        true
+    }
+
+    /// STORE of the open transfer from the address is within its fragment rate
+    ///
+    /// None if the address has no such transfer.
+    pub fn round(&self, peer: IpAddr, transfer: [u8; 16]) -> Option<bool> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        if !state.transfers.contains_key(&(peer, transfer)) {
+            return None;
+        }
+        Some(
+            state
+                .allowances
+                .get_mut(&peer)
+                .is_some_and(|allowance| allowance.take(self.time.now())),
+        )
+    }
+
+    /// Take the assembled value of the transfer
+    pub fn finish(&self, peer: IpAddr, transfer: [u8; 16], size: usize) -> Assembly {
+        if size == 0 || size > MAX_FRAGMENTED_VALUE_SIZE {
+            return Assembly::Rejected;
+        }
+        let mut state = self.state.lock().unwrap();
+        let entry = match state.transfers.get(&(peer, transfer)) {
+            Some(entry) if entry.size == size => entry,
+            Some(_) => return Assembly::Rejected,
+            None => return Assembly::Unknown,
+        };
+        let missing = entry.missing();
+        if !missing.is_empty() {
+            return Assembly::Missing(missing);
+        }
+
+        let Some(entry) = state.transfers.remove(&(peer, transfer)) else {
+            return Assembly::Rejected;
+        };
+        let mut value = Vec::with_capacity(size);
+        for fragment in entry.fragments.into_iter().flatten() {
+            value.extend(fragment);
+        }
+        Assembly::Complete(value)
+    }
+}
+
+/// Permission to read one big value by parts
+struct ValueRead {
+    key: Vec<u8>,
+    parts_left: usize,
+    updated_at: f64,
+}
+
+/// Reads of big values by parts
+///
+/// First FIND_VALUE of a big value passes the rate limiter and is answered by the first
+/// part with a random read ID. Requests of the next parts with this ID skip the rate
+/// limiter, but only from the same address and only for parts of one copy of the value.
+/// Node which spoofed the address does not see the ID, so parts are not sent to others.
+pub struct ValueReads {
+    reads: Mutex<HashMap<(IpAddr, [u8; 16]), ValueRead>>,
+    /// Clock of the node
+    time: Arc<dyn Clock>,
+}
+
+impl Default for ValueReads {
+    fn default() -> Self {
+        Self {
+            reads: Mutex::new(HashMap::new()),
+            time: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl ValueReads {
+    /// Same reads which expire by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
+
+    /// ID of the read of the value by parts _(None if too many reads are open now)_
+    pub fn open(&self, peer: IpAddr, key: &[u8], size: usize) -> Option<[u8; 16]> {
+        let now = self.time.now();
+        let mut reads = self.reads.lock().unwrap();
+        reads.retain(|_, read| read.parts_left > 0 && now - read.updated_at < TRANSFER_TTL);
+        if reads.len() >= MAX_OPEN_READS
+            || reads.keys().filter(|(p, _)| *p == peer).count() >= MAX_TRANSFERS_PER_PEER
+        {
+            return None;
+        }
+        let id: [u8; 16] = rand::random();
+        reads.insert(
+            (peer, id),
+            ValueRead {
+                key: key.to_vec(),
+                parts_left: size.div_ceil(MAX_VALUE_CHUNK).saturating_sub(1),
+                updated_at: now,
+            },
+        );
+        Some(id)
+    }
+
+    /// Request of the next part may skip the rate limiter
+    pub fn allow_part(&self, peer: IpAddr, id: [u8; 16], key: &[u8]) -> bool {
+        let mut reads = self.reads.lock().unwrap();
+        match reads.get_mut(&(peer, id)) {
+            Some(read) if read.key == key && read.parts_left > 0 => {
+                read.parts_left -= 1;
+                read.updated_at = self.time.now();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const PEER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    fn value(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn lost_fragments_are_reported_and_value_is_assembled() {
+        let assembler = FragmentAssembler::default();
+        let value = value(20 * FRAGMENT_SIZE + 100);
+        let fragments: Vec<&[u8]> = value.chunks(FRAGMENT_SIZE).collect();
+        let id = assembler.open(PEER, value.len()).unwrap();
+
+        for (index, fragment) in fragments.iter().enumerate() {
+            if index != 3 && index != 20 {
+                assert!(assembler.accept(PEER, id, value.len(), index, fragment.to_vec()));
+            }
+        }
+        assert_eq!(
+            assembler.finish(PEER, id, value.len()),
+            Assembly::Missing(vec![3, 20])
+        );
+
+        assert!(assembler.accept(PEER, id, value.len(), 3, fragments[3].to_vec()));
+        assert!(assembler.accept(PEER, id, value.len(), 20, fragments[20].to_vec()));
+        assert_eq!(
+            assembler.finish(PEER, id, value.len()),
+            Assembly::Complete(value.clone())
+        );
+        assert_eq!(assembler.finish(PEER, id, value.len()), Assembly::Unknown);
+    }
+
+    #[test]
+    fn fragments_need_open_transfer_of_same_address() {
+        let assembler = FragmentAssembler::default();
+        let size = 20 * FRAGMENT_SIZE;
+        let fragment = vec![0; FRAGMENT_SIZE];
+        assert!(!assembler.accept(PEER, [1; 16], size, 0, fragment.clone()));
+
+        let id = assembler.open(PEER, size).unwrap();
+        assert!(!assembler.accept(OTHER, id, size, 0, fragment.clone()));
+        assert!(!assembler.accept(PEER, id, size + 1, 0, fragment.clone()));
+        assert!(!assembler.accept(PEER, id, size, 20, fragment.clone()));
+        assert!(!assembler.accept(PEER, id, size, 0, vec![0; 10]));
+        assert!(assembler.accept(PEER, id, size, 0, fragment));
+        assert_eq!(assembler.round(OTHER, id), None);
+        assert_eq!(assembler.round(PEER, id), Some(true));
+    }
+
+    #[test]
+    fn transfers_are_limited_per_address() {
+        let assembler = FragmentAssembler::default();
+        assert!(assembler.open(PEER, FRAGMENTED_STORE_THRESHOLD).is_none());
+        assert!(
+            assembler
+                .open(PEER, MAX_FRAGMENTED_VALUE_SIZE + 1)
+                .is_none()
+        );
+
+        assert!(assembler.open(PEER, MAX_FRAGMENTED_VALUE_SIZE).is_some());
+        assert!(assembler.open(PEER, MAX_FRAGMENTED_VALUE_SIZE).is_some());
+        let small = FRAGMENTED_STORE_THRESHOLD + 1;
+        assert!(assembler.open(PEER, small).is_none());
+        assert!(assembler.open(OTHER, small).is_some());
+    }
+
+    #[test]
+    fn fragment_rate_is_limited_per_address() {
+        let assembler = FragmentAssembler::default();
+        let size = MAX_FRAGMENTED_VALUE_SIZE;
+        let id = assembler.open(PEER, size).unwrap();
+        let accepted = (0..fragment_count(size))
+            .filter(|index| assembler.accept(PEER, id, size, *index, vec![0; FRAGMENT_SIZE]))
+            .count();
+        assert!(accepted >= FRAGMENT_BURST as usize);
+        assert!(accepted < fragment_count(size));
+    }
+
+    #[test]
+    fn parts_are_read_once_by_the_same_address() {
+        let reads = ValueReads::default();
+        let id = reads.open(PEER, b"key", 3 * MAX_VALUE_CHUNK).unwrap();
+        assert!(!reads.allow_part(OTHER, id, b"key"));
+        assert!(!reads.allow_part(PEER, id, b"other"));
+        assert!(!reads.allow_part(PEER, [0; 16], b"key"));
+        assert!(reads.allow_part(PEER, id, b"key"));
+        assert!(reads.allow_part(PEER, id, b"key"));
+        assert!(!reads.allow_part(PEER, id, b"key"));
+    }
+}
@@ -0,0 +1,576 @@
+use rand::Rng;
+use rand::rngs::StdRng;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::network::consts::*;
+use crate::network::fragments::{FRAGMENT_SIZE, MAX_FRAGMENTED_VALUE_SIZE, fragment_count};
+use crate::network::peer_stats::PeerSoftware;
+use crate::network::protocol::{NetworkProtocol, ProtocolMessage};
+use crate::network::transport::Message;
+use crate::storage::keys::MAX_KEY_SIZE;
+use crate::utils::rng::NodeRng;
+use crate::utils::serialization::{self, WireFormat};
+use crate::utils::time::get_now_f64;
+
+/// All message types of the protocol
+pub const MSG_TYPES: [u8; 29] = [
+    MSG_PING,
+    MSG_PONG,
+    MSG_FIND_NODE,
+    MSG_FIND_NODE_RESPONSE,
+    MSG_FIND_VALUE,
+    MSG_FIND_VALUE_RESPONSE,
+    MSG_STORE,
+    MSG_STORE_RESPONSE,
+    MSG_POPULARITY_EXCHANGE,
+    MSG_POPULARITY_EXCHANGE_RESPONSE,
+    MSG_GLOBAL_RANKING_REQUEST,
+    MSG_GLOBAL_RANKING_RESPONSE,
+    MSG_GOSSIP_PUSH,
+    MSG_GOSSIP_DIGEST,
+    MSG_GOSSIP_DIGEST_RESPONSE,
+    MSG_LIST_RECORDS,
+    MSG_LIST_RECORDS_RESPONSE,
+    MSG_FIND_VALUES,
+    MSG_FIND_VALUES_RESPONSE,
+    MSG_STORE_CAS,
+    MSG_STORE_CAS_RESPONSE,
+    MSG_PUZZLE,
+    MSG_PUZZLE_SOLUTION,
+    MSG_DELETE,
+    MSG_DELETE_RESPONSE,
+    MSG_DIRECT,
+    MSG_DIRECT_RESPONSE,
+    MSG_STORE_FRAGMENT,
+    MSG_STORE_FRAGMENTS_MISSING,
+];
+
+/// Max count of mutations applied to one message
+const MAX_MUTATIONS: usize = 4;
+/// Max length of generated byte arrays and strings
+const MAX_GENERATED_LEN: usize = 2048;
+/// Nesting of the deepest generated JSON value
+const MAX_NESTING: usize = 64;
+
+/// Generator of random protocol messages for fuzzing
+///
+/// Valid messages have the payload fields which the handler of the type reads, with
+/// random values. Mutated messages are valid ones with dropped fields, fields of wrong
+/// type, extreme numbers, huge or deeply nested values. Packets are encoded messages
+/// in a random wire format, some of them have flipped or cut bytes. Same seed gives
+/// the same corpus, so a found crash can be reproduced.
+pub struct FuzzCorpus {
+    rng: NodeRng,
+}
+
+impl FuzzCorpus {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: NodeRng::new(Some(seed)),
+        }
+    }
+
+    /// Valid message of the type from random sender
+    pub fn valid(&self, msg_type: u8) -> ProtocolMessage {
+        self.rng.with(|rng| ProtocolMessage {
+            msg_type,
+            id: rng.r#gen(),
+            node_id: rng.r#gen(),
+            payload: valid_payload(rng, msg_type),
+            timestamp: get_now_f64(),
+        })
+    }
+
+    /// Valid message of the type changed by random mutations
+    pub fn mutated(&self, msg_type: u8) -> ProtocolMessage {
+        let mut message = self.valid(msg_type);
+        self.rng.with(|rng| {
+            for _ in 0..rng.gen_range(1..=MAX_MUTATIONS) {
+                mutate_message(rng, &mut message);
+            }
+        });
+        message
+    }
+
+    /// Packed valid or mutated message, sometimes with damaged bytes
+    pub fn packet(&self, msg_type: u8) -> Vec<u8> {
+        let message = if self.rng.with(|rng| rng.gen_bool(0.5)) {
+            self.valid(msg_type)
+        } else {
+            self.mutated(msg_type)
+        };
+        self.rng.with(|rng| {
+            let formats = WireFormat::supported();
+            let format = formats[rng.gen_range(0..formats.len())];
+            let mut data = serialization::codec(format)
+                .and_then(|codec| codec.encode(&message).ok())
+                .unwrap_or_default();
+            match rng.gen_range(0..4) {
+                0 if !data.is_empty() => {
+                    let cut = rng.gen_range(0..data.len());
+                    data.truncate(cut);
+                }
+                1 if !data.is_empty() => {
+                    for _ in 0..rng.gen_range(1..=8) {
+                        let i = rng.gen_range(0..data.len());
+                        data[i] ^= 1 << rng.gen_range(0..8);
+                    }
+                }
+                2 => data.extend(random_bytes(rng)),
+                _ => {}
+            }
+            data
+        })
+    }
+}
+
+fn random_bytes(rng: &mut StdRng) -> Vec<u8> {
+    let len = rng.gen_range(0..=MAX_GENERATED_LEN);
+    (0..len).map(|_| rng.r#gen()).collect()
+}
+
+fn random_hex(rng: &mut StdRng, bytes: usize) -> String {
+    hex::encode((0..bytes).map(|_| rng.r#gen::<u8>()).collect::<Vec<u8>>())
+}
+
+/// Key of 32 bytes, sometimes longer than storage accepts
+fn random_key(rng: &mut StdRng) -> Vec<u8> {
+    let len = if rng.gen_bool(0.05) {
+        rng.gen_range(MAX_KEY_SIZE..=2 * MAX_KEY_SIZE)
+    } else {
+        32
+    };
+    (0..len).map(|_| rng.r#gen()).collect()
+}
+
+fn random_node_id(rng: &mut StdRng) -> Vec<u8> {
+    (0..20).map(|_| rng.r#gen()).collect()
+}
+
+fn random_contact(rng: &mut StdRng) -> Value {
+    json!({
+        "node_id": random_node_id(rng),
+        "address": format!("127.0.0.{}", rng.gen_range(1..255)),
+        "port": rng.r#gen::<u16>(),
+    })
+}
+
+fn random_metrics(rng: &mut StdRng) -> Value {
+    json!({
+        "find_value_count": rng.gen_range(0..1000),
+        "store_count": rng.gen_range(0..1000),
+        "replication_count": rng.gen_range(0..50),
+        "last_seen": get_now_f64() - rng.gen_range(0.0..86400.0),
+        "first_seen": get_now_f64() - rng.gen_range(86400.0..864000.0),
+        "request_rate": rng.gen_range(0.0..10.0),
+    })
+}
+
+fn random_gossip(rng: &mut StdRng) -> Value {
+    let topics = [
+        "seed_announcement",
+        "moderation",
+        "version_telemetry",
+        "identity_rotation",
+    ];
+    json!({
+        "id": random_hex(rng, 32),
+        "topic": topics[rng.gen_range(0..topics.len())],
+        "origin": random_node_id(rng),
+        "data": {"version": random_hex(rng, 4)},
+        "ttl": rng.gen_range(0..10),
+        "created_at": get_now_f64(),
+    })
+}
+
+/// Payload with the fields read by the handler of the type
+fn valid_payload(rng: &mut StdRng, msg_type: u8) -> Value {
+    let now = get_now_f64();
+    let mut payload = match msg_type {
+        MSG_PING | MSG_PONG => json!({
+            "node_id": random_node_id(rng),
+            "address": format!("127.0.0.1:{}", rng.r#gen::<u16>()),
+            "observed": format!("127.0.0.1:{}", rng.r#gen::<u16>()),
+            "codecs": WireFormat::supported(),
+            "software": PeerSoftware::local(),
+        }),
+        MSG_FIND_NODE => json!({"target_id": random_node_id(rng)}),
+        MSG_FIND_NODE_RESPONSE => {
+            let nodes: Vec<Value> = (0..rng.gen_range(0..20))
+                .map(|_| random_contact(rng))
+                .collect();
+            json!({"nodes": nodes})
+        }
+        MSG_FIND_VALUE | MSG_GLOBAL_RANKING_REQUEST => json!({"key": random_key(rng)}),
+        MSG_FIND_VALUE_RESPONSE => json!({
+            "value": random_bytes(rng),
+            "published_at": now,
+            "version": rng.r#gen::<u32>(),
+        }),
+        MSG_FIND_VALUES => {
+            let keys: Vec<Vec<u8>> = (0..rng.gen_range(0..120))
+                .map(|_| random_key(rng))
+                .collect();
+            json!({"keys": keys})
+        }
+        MSG_FIND_VALUES_RESPONSE => json!({
+            "values": [{"key": random_key(rng), "value": random_bytes(rng)}],
+            "missing": [random_key(rng)],
+            "remaining": [random_key(rng)],
+        }),
+        MSG_STORE | MSG_STORE_CAS => {
+            let record_types = ["", "thread", "message", "archive", "mailbox"];
+            let record_type = record_types[rng.gen_range(0..record_types.len())];
+            let mut payload = json!({
+                "key": random_key(rng),
+                "value": random_bytes(rng),
+                "ttl": rng.gen_range(-10..1_000_000),
+                "publisher": random_node_id(rng),
+                "published_at": now,
+                "hops": rng.gen_range(0..5),
+                "record_type": record_type,
+            });
+            if msg_type == MSG_STORE_CAS && rng.gen_bool(0.5) {
+                payload["expected"] = json!(random_hex(rng, 32));
+            }
+            if msg_type == MSG_STORE && rng.gen_bool(0.2) {
+                payload.as_object_mut().map(|fields| fields.remove("value"));
+                payload["size"] = json!(rng.gen_range(0..2 * MAX_FRAGMENTED_VALUE_SIZE));
+                if rng.gen_bool(0.5) {
+                    payload["transfer"] = json!(random_hex(rng, 16));
+                }
+            }
+            payload
+        }
+        MSG_STORE_RESPONSE | MSG_STORE_CAS_RESPONSE | MSG_DELETE_RESPONSE => json!({
+            "success": rng.gen_bool(0.5),
+            "receipt": random_bytes(rng),
+        }),
+        MSG_POPULARITY_EXCHANGE | MSG_POPULARITY_EXCHANGE_RESPONSE => {
+            let items: Vec<Value> = (0..rng.gen_range(0..20))
+                .map(|_| {
+                    json!({
+                        "key": random_hex(rng, 32),
+                        "score": rng.gen_range(-1.0..20.0),
+                        "metrics": random_metrics(rng),
+                    })
+                })
+                .collect();
+            json!({
+                "node_id": random_node_id(rng),
+                "node_type": if rng.gen_bool(0.5) { "seed" } else { "peer" },
+                "items": items,
+                "bloom": random_hex(rng, 64),
+            })
+        }
+        MSG_GLOBAL_RANKING_RESPONSE => json!({"ranking": [{
+            "key": random_hex(rng, 32),
+            "score": rng.gen_range(0.0..20.0),
+            "metrics": random_metrics(rng),
+        }]}),
+        MSG_GOSSIP_PUSH | MSG_GOSSIP_DIGEST_RESPONSE => {
+            let messages: Vec<Value> = (0..rng.gen_range(0..10))
+                .map(|_| random_gossip(rng))
+                .collect();
+            json!({"messages": messages, "want": [random_hex(rng, 32)]})
+        }
+        MSG_GOSSIP_DIGEST => {
+            let digest: Vec<String> = (0..rng.gen_range(0..50))
+                .map(|_| random_hex(rng, 32))
+                .collect();
+            json!({"digest": digest})
+        }
+        MSG_LIST_RECORDS => json!({
+            "record_type": if rng.gen_bool(0.5) { "thread" } else { "message" },
+            "since": now - rng.gen_range(0.0..86400.0),
+            "limit": rng.gen_range(0..100),
+        }),
+        MSG_LIST_RECORDS_RESPONSE => json!({"records": [{
+            "key": random_key(rng),
+            "value": random_bytes(rng),
+            "published_at": now,
+        }]}),
+        MSG_DELETE => json!({
+            "key": random_key(rng),
+            "ttl": rng.gen_range(0..100_000),
+            "publisher": random_node_id(rng),
+            "published_at": now,
+            "hops": rng.gen_range(0..5),
+        }),
+        MSG_PUZZLE => json!({
+            "challenge": random_hex(rng, 16),
+            "difficulty": rng.gen_range(0..24),
+        }),
+        MSG_PUZZLE_SOLUTION => json!({
+            "challenge": random_hex(rng, 16),
+            "nonce": rng.r#gen::<u64>(),
+        }),
+        MSG_DIRECT => json!({"envelope": {
+            "id": random_hex(rng, 16),
+            "sent_at": now as i64,
+            "sealed": random_bytes(rng),
+        }}),
+        MSG_DIRECT_RESPONSE => json!({
+            "delivered": rng.gen_bool(0.5),
+            "signature": random_bytes(rng),
+        }),
+        MSG_STORE_FRAGMENT => {
+            let size = rng.gen_range(0..64 * FRAGMENT_SIZE);
+            json!({
+                "transfer": random_hex(rng, 16),
+                "size": size,
+                "index": rng.gen_range(0..=fragment_count(size)),
+                "data": (0..FRAGMENT_SIZE).map(|_| rng.r#gen::<u8>()).collect::<Vec<u8>>(),
+            })
+        }
+        MSG_STORE_FRAGMENTS_MISSING => {
+            let missing: Vec<u32> = (0..rng.gen_range(0..100)).map(|_| rng.r#gen()).collect();
+            json!({"transfer": random_hex(rng, 16), "missing": missing})
+        }
+        _ => json!({}),
+    };
+    if rng.gen_bool(0.1) {
+        payload["warming_up"] = json!(true);
+    }
+    payload
+}
+
+/// Random JSON value of any type, including extreme ones
+fn random_value(rng: &mut StdRng) -> Value {
+    match rng.gen_range(0..10) {
+        0 => Value::Null,
+        1 => json!(rng.gen_bool(0.5)),
+        2 => {
+            let numbers = [i64::MIN, i64::MAX, -1, 0];
+            json!(numbers[rng.gen_range(0..numbers.len())])
+        }
+        3 => {
+            let numbers = [f64::MAX, f64::MIN, f64::EPSILON, u64::MAX as f64];
+            json!(numbers[rng.gen_range(0..numbers.len())])
+        }
+        4 => {
+            let len = rng.gen_range(0..MAX_GENERATED_LEN);
+            json!(random_hex(rng, len))
+        }
+        5 => json!(random_bytes(rng)),
+        6 => {
+            let mut value = json!(0);
+            for _ in 0..rng.gen_range(1..=MAX_NESTING) {
+                value = json!([value]);
+            }
+            value
+        }
+        7 => json!({"nested": {"key": random_hex(rng, 8)}}),
+        8 => json!("\u{0}\u{ffff}not valid hex"),
+        _ => json!([Value::Null, "text", -1]),
+    }
+}
+
+fn mutate_message(rng: &mut StdRng, message: &mut ProtocolMessage) {
+    let fields: Vec<String> = message
+        .payload
+        .as_object()
+        .map(|fields| fields.keys().cloned().collect())
+        .unwrap_or_default();
+    match rng.gen_range(0..6) {
+        0 if !fields.is_empty() => {
+            let field = &fields[rng.gen_range(0..fields.len())];
+            message.payload.as_object_mut().unwrap().remove(field);
+        }
+        1 if !fields.is_empty() => {
+            let field = fields[rng.gen_range(0..fields.len())].clone();
+            message.payload[field] = random_value(rng);
+        }
+        2 => {
+            let field = format!("unknown_{}", rng.r#gen::<u16>());
+            if let Some(fields) = message.payload.as_object_mut() {
+                fields.insert(field, random_value(rng));
+            }
+        }
+        3 => message.payload = random_value(rng),
+        4 => message.msg_type = rng.r#gen(),
+        _ => {
+            message.node_id = [[0u8; 20], [0xFF; 20]][rng.gen_range(0..2)];
+        }
+    }
+}
+
+/// Result of the fuzz run
+#[derive(Debug, Default, Clone)]
+pub struct FuzzReport {
+    /// Count of fed messages and packets
+    pub messages: usize,
+    /// Requests which were answered or ignored without error
+    pub handled: usize,
+    /// Requests which were rejected with error
+    pub rejected: usize,
+    /// Message types and panic messages of handlers which panicked
+    ///
+    /// Panics of blocking tasks are counted too, even if the handler turned them into an
+    /// error.
+    pub panics: Vec<(u8, String)>,
+    /// Message types of handlers whose task was cancelled
+    pub cancelled: Vec<u8>,
+    /// Message types of handlers which did not finish within the request timeout
+    pub hangs: Vec<u8>,
+}
+
+impl FuzzReport {
+    /// Every message was answered or dropped cleanly
+    pub fn passed(&self) -> bool {
+        self.panics.is_empty() && self.cancelled.is_empty() && self.hangs.is_empty()
+    }
+}
+
+/// Harness which feeds the corpus to the protocol
+///
+/// Structured messages go straight to [`NetworkProtocol::handle_request`], so rate limits
+/// and puzzles do not hide the handlers. Packets go through
+/// [`NetworkProtocol::handle_incoming_message`] to cover decoding. Answers are sent to
+/// `sink`, which should be an address nobody listens on.
+pub struct FuzzHarness {
+    protocol: Arc<NetworkProtocol>,
+    corpus: FuzzCorpus,
+    sink: SocketAddr,
+    /// Max time of one handler
+    pub limit: Duration,
+}
+
+impl FuzzHarness {
+    pub fn new(protocol: Arc<NetworkProtocol>, seed: u64, sink: SocketAddr) -> Self {
+        let limit = protocol.request_timeout * 2;
+        Self {
+            protocol,
+            corpus: FuzzCorpus::new(seed),
+            sink,
+            limit,
+        }
+    }
+
+    /// Feed `rounds` messages and packets of every type
+    ///
+    /// Panic hook of the process is replaced during the run, so panics of any thread are
+    /// caught and reported for the message which caused them.
+    pub async fn run(&self, rounds: usize) -> FuzzReport {
+        let caught = Arc::new(Mutex::new(Vec::new()));
+        let previous_hook = std::panic::take_hook();
+        let hook_caught = caught.clone();
+        std::panic::set_hook(Box::new(move |info| {
+            hook_caught.lock().unwrap().push(info.to_string());
+        }));
+
+        let report = self.feed_corpus(rounds, &caught).await;
+
+        std::panic::set_hook(previous_hook);
+        report
+    }
+
+    async fn feed_corpus(&self, rounds: usize, caught: &Mutex<Vec<String>>) -> FuzzReport {
+        let mut report = FuzzReport::default();
+        for round in 0..rounds {
+            for msg_type in MSG_TYPES {
+                let message = if round % 2 == 0 {
+                    self.corpus.valid(msg_type)
+                } else {
+                    self.corpus.mutated(msg_type)
+                };
+                self.feed_request(message, &mut report).await;
+                Self::take_caught(msg_type, caught, &mut report);
+                self.feed_packet(msg_type, self.corpus.packet(msg_type), &mut report)
+                    .await;
+                Self::take_caught(msg_type, caught, &mut report);
+            }
+        }
+        report
+    }
+
+    /// Move panics caught by the hook to the report
+    fn take_caught(msg_type: u8, caught: &Mutex<Vec<String>>, report: &mut FuzzReport) {
+        let panics = std::mem::take(&mut *caught.lock().unwrap());
+        report
+            .panics
+            .extend(panics.into_iter().map(|panic| (msg_type, panic)));
+    }
+
+    /// Count the task which did not finish normally, its panic is reported by the hook
+    fn record_join_error(msg_type: u8, error: tokio::task::JoinError, report: &mut FuzzReport) {
+        if !error.is_panic() {
+            report.cancelled.push(msg_type);
+        }
+    }
+
+    async fn feed_request(&self, message: ProtocolMessage, report: &mut FuzzReport) {
+        let protocol = self.protocol.clone();
+        let sink = self.sink;
+        let msg_type = message.msg_type;
+        let task = tokio::spawn(async move {
+            protocol
+                .handle_request(
+                    message.msg_type,
+                    message.id,
+                    message.node_id,
+                    message.payload,
+                    sink,
+                )
+                .await
+        });
+        report.messages += 1;
+        match timeout(self.limit, task).await {
+            Ok(Ok(Ok(()))) => report.handled += 1,
+            Ok(Ok(Err(_))) => report.rejected += 1,
+            Ok(Err(e)) => Self::record_join_error(msg_type, e, report),
+            Err(_) => report.hangs.push(msg_type),
+        }
+    }
+
+    async fn feed_packet(&self, msg_type: u8, data: Vec<u8>, report: &mut FuzzReport) {
+        let protocol = self.protocol.clone();
+        let message = Message {
+            data,
+            address: self.sink,
+            timestamp: get_now_f64(),
+        };
+        let task = tokio::spawn(async move { protocol.handle_incoming_message(message).await });
+        report.messages += 1;
+        match timeout(self.limit, task).await {
+            Ok(Ok(())) => report.handled += 1,
+            Ok(Err(e)) => Self::record_join_error(msg_type, e, report),
+            Err(_) => report.hangs.push(msg_type),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::node::full_node::FullNode;
+    use crate::utils::temp_dir::TempDir;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn handlers_survive_fuzzed_messages() {
+        let dir = TempDir::new("fuzz-test");
+        let mut config = Config::from_file(None).for_instance(dir.path());
+        config.network.listen_host = "127.0.0.1".to_string();
+        config.network.listen_port = 0;
+        config.network.bootstrap_nodes = Vec::new();
+        config.dht.warm_up = false;
+        config.node.rng_seed = Some(7);
+        config.security.admin_token_file = Some(dir.join("admin.token"));
+
+        let node = FullNode::new(config).await.unwrap();
+        node.start().await.unwrap();
+        let sink: SocketAddr = "127.0.0.1:9".parse().unwrap();
+        let report = FuzzHarness::new(node.network_protocol.clone(), 7, sink)
+            .run(4)
+            .await;
+        node.stop().await.unwrap();
+
+        assert!(report.passed(), "{:?}", report);
+        assert_eq!(report.messages, 4 * MSG_TYPES.len() * 2);
+    }
+}
@@ -0,0 +1,341 @@
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::dht::node::Node;
+use crate::network::clock::ClockSkew;
+use crate::utils::crypto::hash_key;
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::{NodeSigner, node_id_from_public_key_der, verify_signature};
+use crate::utils::time::{Clock, SystemClock};
+
+/// Messages older than this time in seconds are forgotten and not accepted
+pub const GOSSIP_MESSAGE_LIFETIME: f64 = 3600.0;
+/// Max count of messages in one push
+pub const MAX_GOSSIP_BATCH: usize = 32;
+
+/// Kind of the cluster-wide announcement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GossipTopic {
+    /// Node announces that it works as seed
+    SeedAnnouncement,
+    /// Moderation record _(ban, hide, etc)_
+    Moderation,
+    /// Version of software of the node
+    VersionTelemetry,
+    /// Node moved to new ID, data is signed link between old and new identity
+    IdentityRotation,
+}
+
+/// One announcement which is spread over the network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GossipMessage {
+    /// Hex of hash over topic, origin, data and creation time
+    pub id: String,
+    pub topic: GossipTopic,
+    /// Node ID of the author
+    pub origin: Vec<u8>,
+    pub data: Value,
+    /// How many hops the message can do yet
+    pub ttl: u32,
+    /// Time of creation on origin node
+    pub created_at: f64,
+    /// DER of the public key of the origin, its SHA-1 must be equal to `origin`
+    #[serde(default)]
+    pub public_key: Vec<u8>,
+    /// Signature of the origin over everything except `ttl`, which changes on the way
+    #[serde(default)]
+    pub signature: Vec<u8>,
+}
+
+impl GossipMessage {
+    /// Message created at `created_at` by the clock of the origin
+    pub fn new(
+        topic: GossipTopic,
+        origin: Vec<u8>,
+        data: Value,
+        ttl: u32,
+        created_at: f64,
+    ) -> Self {
+        let id = Self::compute_id(topic, &origin, &data, created_at);
+        Self {
+            id,
+            topic,
+            origin,
+            data,
+            ttl,
+            created_at,
+            public_key: Vec::new(),
+            signature: Vec::new(),
+        }
+    }
+
+    /// Bytes of the content, its hash is the id and the origin signs them
+    fn content_bytes(topic: GossipTopic, origin: &[u8], data: &Value, created_at: f64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(serde_json::to_string(&topic).unwrap_or_default().as_bytes());
+        buf.extend_from_slice(origin);
+        buf.extend_from_slice(data.to_string().as_bytes());
+        buf.extend_from_slice(&created_at.to_be_bytes());
+        buf
+    }
+
+    fn compute_id(topic: GossipTopic, origin: &[u8], data: &Value, created_at: f64) -> String {
+        let content = Self::content_bytes(topic, origin, data, created_at);
+        hex::encode(&hash_key(&content)[..16])
+    }
+
+    /// Check that id matches content, so message was not changed on the way
+    ///
+    /// Anybody can compute the id, only [`GossipMessage::is_signed_by_origin`] tells
+    /// that the origin made the message.
+    pub fn is_valid(&self) -> bool {
+        self.id == Self::compute_id(self.topic, &self.origin, &self.data, self.created_at)
+    }
+
+    /// Sign the message of our node, `origin` must be the node ID of the signer
+    #[cfg(feature = "signatures")]
+    pub fn sign(&mut self, signer: &NodeSigner) {
+        let content = Self::content_bytes(self.topic, &self.origin, &self.data, self.created_at);
+        self.public_key = signer.public_key_der().to_vec();
+        self.signature = signer.sign(&content);
+    }
+
+    /// Check that the message is signed by the key of its origin
+    #[cfg(feature = "signatures")]
+    pub fn is_signed_by_origin(&self) -> bool {
+        let content = Self::content_bytes(self.topic, &self.origin, &self.data, self.created_at);
+        node_id_from_public_key_der(&self.public_key)[..] == self.origin[..]
+            && verify_signature(&self.public_key, &content, &self.signature)
+    }
+
+    /// Node without `signatures` can not check signatures, messages of peers are not trusted
+    #[cfg(not(feature = "signatures"))]
+    pub fn is_signed_by_origin(&self) -> bool {
+        false
+    }
+
+    pub fn is_expired(&self, now: f64) -> bool {
+        self.is_expired_with_offset(now, 0.0)
+    }
+
+    /// Check expiration at `now` when clock of the origin is ahead of ours by `origin_offset`
+    pub fn is_expired_with_offset(&self, now: f64, origin_offset: f64) -> bool {
+        now - (self.created_at - origin_offset) > GOSSIP_MESSAGE_LIFETIME
+    }
+}
+
+/// Epidemic broadcast state
+///
+/// Every new message is pushed to `fanout` random peers, which push it further while
+/// `ttl` allows. Periodically nodes exchange digests _(ids of known messages)_ and pull
+/// messages they missed. Messages of peers are accepted only with the signature of their
+/// origin, so nobody can announce in the name of other node.
+pub struct Gossip {
+    /// Count of peers for one push
+    pub fanout: usize,
+    /// Hops limit of new messages
+    pub ttl: u32,
+    /// Known messages by id
+    messages: Mutex<HashMap<String, GossipMessage>>,
+    /// Channel for subscribers of new messages
+    sender: broadcast::Sender<GossipMessage>,
+    /// Clock offsets of peers for correction of creation time
+    pub clock: Option<Arc<ClockSkew>>,
+    /// Key for signing our messages _(messages are not signed if None)_
+    #[cfg(feature = "signatures")]
+    pub signer: Option<Arc<NodeSigner>>,
+    /// Clock of the node
+    time: Arc<dyn Clock>,
+}
+
+impl Gossip {
+    pub fn new(fanout: usize, ttl: u32) -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self {
+            fanout,
+            ttl,
+            messages: Mutex::new(HashMap::new()),
+            sender,
+            clock: None,
+            #[cfg(feature = "signatures")]
+            signer: None,
+            time: Arc::new(SystemClock),
+        }
+    }
+
+    /// Same gossip which dates and expires messages by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
+
+    /// Create our own message and remember it
+    #[cfg_attr(not(feature = "signatures"), allow(unused_mut))]
+    pub fn create(&self, topic: GossipTopic, origin: Vec<u8>, data: Value) -> GossipMessage {
+        let mut message = GossipMessage::new(topic, origin, data, self.ttl, self.time.now());
+        #[cfg(feature = "signatures")]
+        if let Some(signer) = &self.signer {
+            message.sign(signer);
+        }
+        self.remember(message.clone());
+        message
+    }
+
+    /// Save received message
+    ///
+    /// Return `true` if message is new, valid, signed by its origin and not expired
+    pub fn accept(&self, message: GossipMessage) -> bool {
+        if self.is_expired(&message) || !message.is_valid() {
+            return false;
+        }
+        if self.messages.lock().unwrap().contains_key(&message.id) {
+            return false;
+        }
+        message.is_signed_by_origin() && self.remember(message)
+    }
+
+    /// Save the message and notify subscribers, return `false` if it is known
+    fn remember(&self, message: GossipMessage) -> bool {
+        let mut messages = self.messages.lock().unwrap();
+        if messages.contains_key(&message.id) {
+            return false;
+        }
+        messages.insert(message.id.clone(), message.clone());
+        drop(messages);
+
+        let _ = self.sender.send(message);
+        true
+    }
+
+    /// Ids of all known messages
+    pub fn digest(&self) -> Vec<String> {
+        self.messages.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Ids from remote digest which we do not know
+    pub fn missing(&self, remote_digest: &[String]) -> Vec<String> {
+        let messages = self.messages.lock().unwrap();
+        remote_digest
+            .iter()
+            .filter(|id| !messages.contains_key(*id))
+            .take(MAX_GOSSIP_BATCH)
+            .cloned()
+            .collect()
+    }
+
+    /// Our messages which are absent in remote digest
+    pub fn messages_not_in(&self, remote_digest: &[String]) -> Vec<GossipMessage> {
+        let remote: HashSet<&String> = remote_digest.iter().collect();
+        self.messages
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|m| !remote.contains(&m.id) && !self.is_expired(m))
+            .take(MAX_GOSSIP_BATCH)
+            .cloned()
+            .collect()
+    }
+
+    /// Get known messages by ids
+    pub fn get_many(&self, ids: &[String]) -> Vec<GossipMessage> {
+        let messages = self.messages.lock().unwrap();
+        ids.iter()
+            .filter_map(|id| messages.get(id).cloned())
+            .take(MAX_GOSSIP_BATCH)
+            .collect()
+    }
+
+    /// Forget expired messages
+    pub fn cleanup_expired(&self) -> usize {
+        let mut messages = self.messages.lock().unwrap();
+        let before = messages.len();
+        messages.retain(|_, m| !self.is_expired(m));
+        before - messages.len()
+    }
+
+    /// Check expiration with creation time converted to our clock
+    ///
+    /// Origin is named by the message itself, so its offset may only make the message
+    /// expire sooner, never live longer than by our clock.
+    fn is_expired(&self, message: &GossipMessage) -> bool {
+        let offset = self
+            .clock
+            .as_ref()
+            .map(|clock| clock.offset(&message.origin).max(0.0))
+            .unwrap_or(0.0);
+        message.is_expired_with_offset(self.time.now(), offset)
+    }
+
+    /// Choose `fanout` random peers
+    pub fn select_peers(&self, mut nodes: Vec<Node>) -> Vec<Node> {
+        nodes.shuffle(&mut rand::thread_rng());
+        nodes.truncate(self.fanout);
+        nodes
+    }
+
+    /// Subscribe to new messages
+    pub fn subscribe(&self) -> broadcast::Receiver<GossipMessage> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for Gossip {
+    fn default() -> Self {
+        Self::new(3, 6)
+    }
+}
+
+#[cfg(all(test, feature = "signatures"))]
+mod tests {
+    use super::*;
+    use crate::utils::crypto::{generate_keypair, node_id_from_public_key};
+    use serde_json::json;
+
+    fn signer() -> NodeSigner {
+        let (private_key, public_key) = generate_keypair();
+        let node_id = node_id_from_public_key(&public_key);
+        NodeSigner::new(private_key, node_id.to_vec())
+    }
+
+    #[test]
+    fn only_messages_signed_by_origin_are_accepted() {
+        let signer = signer();
+        let origin = signer.node_id().to_vec();
+        let data = json!({"archive": true});
+        let gossip = Gossip::default();
+
+        let mut signed = GossipMessage::new(
+            GossipTopic::SeedAnnouncement,
+            origin,
+            data,
+            3,
+            gossip.time.now(),
+        );
+        signed.sign(&signer);
+
+        let mut forged = signed.clone();
+        forged.origin = vec![7u8; 20];
+        forged.id = GossipMessage::compute_id(
+            forged.topic,
+            &forged.origin,
+            &forged.data,
+            forged.created_at,
+        );
+        assert!(forged.is_valid());
+        assert!(!gossip.accept(forged));
+
+        let mut unsigned = signed.clone();
+        unsigned.signature.clear();
+        assert!(!gossip.accept(unsigned));
+
+        let mut hopped = signed.clone();
+        hopped.ttl -= 1;
+        assert!(gossip.accept(hopped));
+        assert!(!gossip.accept(signed));
+    }
+}
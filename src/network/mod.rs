@@ -1,12 +1,38 @@
+/// Recording of packets to a file and their replay for reproducing bugs
+pub mod capture;
+/// Fault injection into sent packets for simulations
+///
+/// Packet drop, duplication, reordering and latency distributions.
+pub mod chaos;
+/// Estimation of clock offsets of peers
+pub mod clock;
 /// Consts for each type of message
 ///
 /// Need for serialization in network.
 pub mod consts;
+/// Discovery of our external address from addresses observed by peers
+pub mod external_address;
+/// Assembly of large STORE values from fragments with selective retransmission
+pub mod fragments;
+/// Generator of random and mutated protocol messages with harness of their handlers
+#[cfg(any(test, feature = "fuzz"))]
+pub mod fuzz;
+/// Epidemic broadcast of cluster-wide announcements
+///
+/// Push new messages to random peers and pull missed ones by digest exchange.
+pub mod gossip;
+/// Traffic counters of every peer for debugging and diagnostics
+pub mod peer_stats;
+/// Mapping of the node port on the home router by UPnP IGD or NAT-PMP
+#[cfg(feature = "port-mapping")]
+pub mod port_mapping;
 /// Network protocol
 ///
 /// Module for sending data and receive data from internet.
 /// They will transfer abstract command like (Ping, Store) in real bytes and send it by UDP.
 /// Protocol work with answers and responsibility for safety.
 pub mod protocol;
+/// Priority classes of messages for outbound scheduling and rate limits
+pub mod qos;
 /// Module with realization of UDP
 pub mod transport;
@@ -0,0 +1,302 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::RwLock;
+
+use crate::network::consts::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use crate::utils::time::get_now_f64;
+
+/// Peers without traffic during this time in seconds are forgotten
+pub const PEER_STATS_MAX_AGE: f64 = 86400.0;
+/// Max count of peers with counters, the least recently seen one is forgotten for a new one
+pub const MAX_TRACKED_PEERS: usize = 4096;
+/// Max length of the announced crate version and of one feature name
+const MAX_SOFTWARE_NAME_LEN: usize = 32;
+/// Max count of announced features which are kept
+const MAX_SOFTWARE_FEATURES: usize = 16;
+
+/// Features which change what the node sends or accepts
+const ANNOUNCED_FEATURES: &[(&str, bool)] = &[
+    ("popularity", cfg!(feature = "popularity")),
+    ("replication", cfg!(feature = "replication")),
+    ("signatures", cfg!(feature = "signatures")),
+    ("protobuf", cfg!(feature = "protobuf")),
+    ("mailbox", cfg!(feature = "mailbox")),
+    ("compression", cfg!(feature = "compression")),
+    ("wasm-plugins", cfg!(feature = "wasm-plugins")),
+    ("portability", cfg!(feature = "portability")),
+];
+
+/// Software of the node announced in PING and PONG
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerSoftware {
+    /// Version of the crate
+    pub version: String,
+    /// Version of the wire protocol
+    pub protocol: u32,
+    /// Oldest protocol version which the node understands
+    #[serde(default)]
+    pub min_protocol: u32,
+    /// Enabled cargo features
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl PeerSoftware {
+    /// Software of this build
+    pub fn local() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol: PROTOCOL_VERSION,
+            min_protocol: MIN_PROTOCOL_VERSION,
+            features: ANNOUNCED_FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+        }
+    }
+
+    /// Software from PING or PONG payload _(None for old nodes which do not announce it)_
+    ///
+    /// Software with too long version is not accepted, too long feature names and features
+    /// over [`MAX_SOFTWARE_FEATURES`] are dropped.
+    pub fn from_payload(payload: &serde_json::Value) -> Option<Self> {
+        let mut software: Self = serde_json::from_value(payload.get("software")?.clone()).ok()?;
+        if software.version.len() > MAX_SOFTWARE_NAME_LEN {
+            return None;
+        }
+        software
+            .features
+            .retain(|feature| feature.len() <= MAX_SOFTWARE_NAME_LEN);
+        software.features.truncate(MAX_SOFTWARE_FEATURES);
+        Some(software)
+    }
+
+    /// Both nodes understand the protocol of each other
+    pub fn is_compatible(&self) -> bool {
+        self.protocol >= MIN_PROTOCOL_VERSION && self.min_protocol <= PROTOCOL_VERSION
+    }
+}
+
+/// Software versions of known peers
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VersionSummary {
+    /// Count of peers by crate version
+    pub versions: BTreeMap<String, usize>,
+    /// Count of peers by protocol version
+    pub protocols: BTreeMap<u32, usize>,
+    /// Count of peers which speak incompatible protocol
+    pub incompatible: usize,
+    /// Count of peers which did not announce their software
+    pub unknown: usize,
+}
+
+/// Traffic counters of one peer
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PeerStats {
+    /// Last address of the peer
+    pub address: Option<SocketAddr>,
+    /// Count of our requests to the peer
+    pub requests_sent: u64,
+    /// Count of requests of the peer to us
+    pub requests_received: u64,
+    /// Count of our requests which the peer did not answer
+    pub failures: u64,
+    /// Bytes of our requests and answers to the peer
+    pub bytes_sent: u64,
+    /// Bytes of all messages from the peer
+    pub bytes_received: u64,
+    /// RTT in seconds of the last answered request _(None if never answered)_
+    pub last_rtt: Option<f64>,
+    /// Time of the last message from or to the peer
+    pub updated_at: f64,
+    /// Software announced by the peer _(None if not announced yet)_
+    pub software: Option<PeerSoftware>,
+}
+
+/// Traffic counters of peers by their node ID
+#[derive(Default)]
+pub struct PeerStatsTable {
+    peers: RwLock<HashMap<Vec<u8>, PeerStats>>,
+    /// Peer node ID by its last address, answers are sent by address only
+    addresses: RwLock<HashMap<SocketAddr, Vec<u8>>>,
+}
+
+impl PeerStatsTable {
+    fn update(&self, peer_id: &[u8], apply: impl FnOnce(&mut PeerStats)) {
+        let mut peers = self.peers.write().unwrap();
+        if peers.len() >= MAX_TRACKED_PEERS && !peers.contains_key(peer_id) {
+            let oldest = peers
+                .iter()
+                .min_by(|a, b| a.1.updated_at.total_cmp(&b.1.updated_at))
+                .map(|(oldest, _)| oldest.clone());
+            if let Some(oldest) = oldest {
+                peers.remove(&oldest);
+                self.addresses
+                    .write()
+                    .unwrap()
+                    .retain(|_, known| *known != oldest);
+            }
+        }
+        let stats = peers.entry(peer_id.to_vec()).or_default();
+        apply(stats);
+        stats.updated_at = get_now_f64();
+    }
+
+    /// We sent request of `bytes` to the peer
+    pub fn record_request_sent(&self, peer_id: &[u8], bytes: usize) {
+        self.update(peer_id, |s| {
+            s.requests_sent += 1;
+            s.bytes_sent += bytes as u64;
+        });
+    }
+
+    /// We sent answer of `bytes` to the address, it is counted for the last peer on it
+    pub fn record_response_sent(&self, address: SocketAddr, bytes: usize) {
+        let peer_id = self.addresses.read().unwrap().get(&address).cloned();
+        if let Some(peer_id) = peer_id {
+            self.update(&peer_id, |s| s.bytes_sent += bytes as u64);
+        }
+    }
+
+    /// Message of `bytes` came from the peer on `address`
+    ///
+    /// Only the last address of every peer is kept, so there are not more addresses than
+    /// peers.
+    pub fn record_received(&self, peer_id: &[u8], address: SocketAddr, bytes: usize) {
+        let mut previous = None;
+        self.update(peer_id, |s| {
+            previous = s.address.replace(address);
+            s.bytes_received += bytes as u64;
+        });
+        let mut addresses = self.addresses.write().unwrap();
+        if let Some(previous) = previous.filter(|previous| *previous != address)
+            && addresses
+                .get(&previous)
+                .is_some_and(|known| known == peer_id)
+        {
+            addresses.remove(&previous);
+        }
+        addresses.insert(address, peer_id.to_vec());
+    }
+
+    /// Message of the peer was a request, not an answer to ours
+    pub fn record_request_received(&self, peer_id: &[u8]) {
+        self.update(peer_id, |s| s.requests_received += 1);
+    }
+
+    /// Peer answered our request in `rtt` seconds
+    pub fn record_rtt(&self, peer_id: &[u8], rtt: f64) {
+        self.update(peer_id, |s| s.last_rtt = Some(rtt));
+    }
+
+    /// Peer did not answer our request
+    pub fn record_failure(&self, peer_id: &[u8]) {
+        self.update(peer_id, |s| s.failures += 1);
+    }
+
+    /// Peer announced its software, return true if it is new or changed
+    pub fn record_software(&self, peer_id: &[u8], software: PeerSoftware) -> bool {
+        let mut changed = false;
+        self.update(peer_id, |s| {
+            changed = s.software.as_ref() != Some(&software);
+            s.software = Some(software);
+        });
+        changed
+    }
+
+    /// Versions of all known peers
+    pub fn versions(&self) -> VersionSummary {
+        let mut summary = VersionSummary::default();
+        for stats in self.peers.read().unwrap().values() {
+            let Some(software) = &stats.software else {
+                summary.unknown += 1;
+                continue;
+            };
+            *summary
+                .versions
+                .entry(software.version.clone())
+                .or_default() += 1;
+            *summary.protocols.entry(software.protocol).or_default() += 1;
+            if !software.is_compatible() {
+                summary.incompatible += 1;
+            }
+        }
+        summary
+    }
+
+    pub fn get(&self, peer_id: &[u8]) -> Option<PeerStats> {
+        self.peers.read().unwrap().get(peer_id).cloned()
+    }
+
+    /// Counters of all known peers by hex node ID
+    pub fn all(&self) -> HashMap<String, PeerStats> {
+        self.peers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(peer_id, stats)| (hex::encode(peer_id), stats.clone()))
+            .collect()
+    }
+
+    /// Forget peers without traffic during [`PEER_STATS_MAX_AGE`]
+    pub fn cleanup_expired(&self) -> usize {
+        let now = get_now_f64();
+        let mut peers = self.peers.write().unwrap();
+        let before = peers.len();
+        peers.retain(|_, s| now - s.updated_at < PEER_STATS_MAX_AGE);
+        self.addresses
+            .write()
+            .unwrap()
+            .retain(|_, peer_id| peers.contains_key(peer_id));
+        before - peers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announced_software_and_peers_are_bounded() {
+        let long = "x".repeat(MAX_SOFTWARE_NAME_LEN + 1);
+        let payload = serde_json::json!({"software": {"version": long, "protocol": 1}});
+        assert!(PeerSoftware::from_payload(&payload).is_none());
+
+        let mut features: Vec<String> = (0..MAX_SOFTWARE_FEATURES * 2)
+            .map(|i| format!("f{i}"))
+            .collect();
+        features.insert(0, long);
+        let payload = serde_json::json!({
+            "software": {"version": "1.0.0", "protocol": 1, "features": features}
+        });
+        let software = PeerSoftware::from_payload(&payload).unwrap();
+        assert_eq!(software.features.len(), MAX_SOFTWARE_FEATURES);
+        assert_eq!(software.features[0], "f0");
+
+        let table = PeerStatsTable::default();
+        let address: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        table.record_received(b"first", address, 10);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        for i in 0..MAX_TRACKED_PEERS as u32 {
+            table.record_request_sent(&i.to_be_bytes(), 10);
+        }
+        assert_eq!(table.all().len(), MAX_TRACKED_PEERS);
+        assert!(table.get(b"first").is_none());
+        table.record_response_sent(address, 10);
+        assert!(table.get(b"first").is_none());
+    }
+
+    #[test]
+    fn only_last_address_of_peer_is_kept() {
+        let table = PeerStatsTable::default();
+        for port in 5000..5100 {
+            let address: SocketAddr = format!("127.0.0.1:{port}").parse().unwrap();
+            table.record_received(b"peer", address, 10);
+        }
+        assert_eq!(table.addresses.read().unwrap().len(), 1);
+        let last: SocketAddr = "127.0.0.1:5099".parse().unwrap();
+        assert_eq!(table.get(b"peer").unwrap().address, Some(last));
+    }
+}
@@ -0,0 +1,298 @@
+use igd_next::aio::Gateway;
+use igd_next::aio::tokio::{Tokio, search_gateway};
+use igd_next::{PortMappingProtocol, SearchOptions};
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::RwLock;
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+
+use crate::utils::time::{duration_from_secs, get_now_f64};
+
+/// Port of NAT-PMP service on the router
+const NAT_PMP_PORT: u16 = 5351;
+
+/// Attempts of one NAT-PMP request, the wait is doubled after every attempt
+const NAT_PMP_ATTEMPTS: u32 = 4;
+
+/// Description of the mapping on the router
+const MAPPING_DESCRIPTION: &str = "rhizome-p2p";
+
+/// Shortest wait in seconds before renewal, routers may grant very short lifetimes
+const MIN_RENEW_INTERVAL: f64 = 1.0;
+
+/// Protocol used for the port mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingMethod {
+    /// UPnP Internet Gateway Device
+    Upnp,
+    NatPmp,
+}
+
+/// Active mapping of our UDP port on the router
+#[derive(Debug, Clone, Serialize)]
+pub struct PortMapping {
+    pub method: MappingMethod,
+    /// Our address in the local network
+    pub local_addr: SocketAddr,
+    /// Public IP of the router _(None if router did not tell it)_
+    pub external_ip: Option<IpAddr>,
+    pub external_port: u16,
+    /// Time when the router forgets the mapping
+    pub expires_at: f64,
+}
+
+impl PortMapping {
+    /// Wait before renewal, half of the lifetime which the router granted
+    pub fn renew_in(&self, now: f64) -> Duration {
+        duration_from_secs(((self.expires_at - now) / 2.0).max(MIN_RENEW_INTERVAL))
+    }
+}
+
+/// Router which maps our port
+enum Router {
+    Upnp(Gateway<Tokio>),
+    NatPmp(SocketAddr),
+}
+
+/// Mapping of our UDP port on the home router
+///
+/// UPnP IGD is tried first, then NAT-PMP on the default gateway. Mapping has limited
+/// lease and must be renewed before it expires.
+pub struct PortMapper {
+    /// Lease of the mapping in seconds
+    pub lease: u32,
+    /// NAT-PMP router address _(default gateway if None)_
+    pub nat_pmp_gateway: Option<IpAddr>,
+    router: RwLock<Option<Router>>,
+    mapping: RwLock<Option<PortMapping>>,
+}
+
+impl PortMapper {
+    pub fn new(lease: u32, nat_pmp_gateway: Option<IpAddr>) -> Self {
+        Self {
+            lease: lease.max(60),
+            nat_pmp_gateway,
+            router: RwLock::new(None),
+            mapping: RwLock::new(None),
+        }
+    }
+
+    /// Current mapping _(None if there is no mapping)_
+    pub async fn mapping(&self) -> Option<PortMapping> {
+        self.mapping.read().await.clone()
+    }
+
+    /// Map local UDP `port` on the router or renew the existing mapping
+    pub async fn map(&self, port: u16) -> Option<PortMapping> {
+        let mut router = self.router.write().await;
+        if router.is_none() {
+            *router = self.discover().await;
+        }
+        let mapping = match router.as_ref()? {
+            Router::Upnp(gateway) => self.map_upnp(gateway, port).await,
+            Router::NatPmp(gateway) => self.map_nat_pmp(*gateway, port).await,
+        };
+        match &mapping {
+            Some(m) => {
+                debug!(external_port = m.external_port, method = ?m.method, "Port mapping renewed")
+            }
+            // Router may be changed or rebooted, search it again next time
+            None => *router = None,
+        }
+        *self.mapping.write().await = mapping.clone();
+        mapping
+    }
+
+    /// Remove the mapping from the router
+    pub async fn unmap(&self) {
+        let Some(mapping) = self.mapping.write().await.take() else {
+            return;
+        };
+        let removed = match self.router.read().await.as_ref() {
+            Some(Router::Upnp(gateway)) => gateway
+                .remove_port(PortMappingProtocol::UDP, mapping.external_port)
+                .await
+                .is_ok(),
+            Some(Router::NatPmp(gateway)) => {
+                Self::nat_pmp_request(*gateway, mapping.local_addr.port(), 0, 0)
+                    .await
+                    .is_some()
+            }
+            None => false,
+        };
+        if removed {
+            info!(
+                external_port = mapping.external_port,
+                "Port mapping removed"
+            );
+        } else {
+            warn!(
+                external_port = mapping.external_port,
+                "Failed to remove port mapping"
+            );
+        }
+    }
+
+    /// Find router which supports UPnP IGD or NAT-PMP
+    async fn discover(&self) -> Option<Router> {
+        match search_gateway(SearchOptions {
+            timeout: Some(Duration::from_secs(5)),
+            ..Default::default()
+        })
+        .await
+        {
+            Ok(gateway) => return Some(Router::Upnp(gateway)),
+            Err(e) => debug!(error = %e, "UPnP gateway not found"),
+        }
+        let gateway = self.nat_pmp_gateway.or_else(default_gateway)?;
+        Some(Router::NatPmp(SocketAddr::new(gateway, NAT_PMP_PORT)))
+    }
+
+    async fn map_upnp(&self, gateway: &Gateway<Tokio>, port: u16) -> Option<PortMapping> {
+        let local_addr = SocketAddr::new(local_ip_towards(gateway.addr).await?, port);
+        let external_port = match gateway
+            .add_port(
+                PortMappingProtocol::UDP,
+                port,
+                local_addr,
+                self.lease,
+                MAPPING_DESCRIPTION,
+            )
+            .await
+        {
+            Ok(()) => port,
+            // Same external port is taken by other host, let router choose one
+            Err(_) => gateway
+                .add_any_port(
+                    PortMappingProtocol::UDP,
+                    local_addr,
+                    self.lease,
+                    MAPPING_DESCRIPTION,
+                )
+                .await
+                .map_err(|e| warn!(error = %e, "UPnP port mapping failed"))
+                .ok()?,
+        };
+        Some(PortMapping {
+            method: MappingMethod::Upnp,
+            local_addr,
+            external_ip: gateway.get_external_ip().await.ok(),
+            external_port,
+            expires_at: get_now_f64() + self.lease as f64,
+        })
+    }
+
+    async fn map_nat_pmp(&self, gateway: SocketAddr, port: u16) -> Option<PortMapping> {
+        let local_addr = SocketAddr::new(local_ip_towards(gateway).await?, port);
+        let (external_port, lifetime) =
+            Self::nat_pmp_request(gateway, port, port, self.lease).await?;
+        Some(PortMapping {
+            method: MappingMethod::NatPmp,
+            local_addr,
+            external_ip: Self::nat_pmp_external_ip(gateway).await,
+            external_port,
+            expires_at: get_now_f64() + lifetime as f64,
+        })
+    }
+
+    /// Send NAT-PMP request to the router and wait for the answer of `answer_len` bytes
+    async fn nat_pmp_call(
+        gateway: SocketAddr,
+        request: &[u8],
+        answer_len: usize,
+    ) -> Option<Vec<u8>> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.ok()?;
+        socket.connect(gateway).await.ok()?;
+        let mut wait = Duration::from_millis(250);
+        let mut buf = [0u8; 16];
+        for _ in 0..NAT_PMP_ATTEMPTS {
+            socket.send(request).await.ok()?;
+            if let Ok(Ok(len)) = timeout(wait, socket.recv(&mut buf)).await
+                && len >= answer_len
+                // Answer opcode is request opcode + 128, result code 0 is success
+                && buf[1] == request[1] + 128
+                && buf[2..4] == [0, 0]
+            {
+                return Some(buf[..len].to_vec());
+            }
+            wait *= 2;
+        }
+        None
+    }
+
+    /// Map UDP port by NAT-PMP, return external port and lifetime
+    ///
+    /// Lifetime 0 removes the mapping.
+    async fn nat_pmp_request(
+        gateway: SocketAddr,
+        internal_port: u16,
+        external_port: u16,
+        lifetime: u32,
+    ) -> Option<(u16, u32)> {
+        let mut request = vec![0u8, 1, 0, 0];
+        request.extend_from_slice(&internal_port.to_be_bytes());
+        request.extend_from_slice(&external_port.to_be_bytes());
+        request.extend_from_slice(&lifetime.to_be_bytes());
+        let answer = Self::nat_pmp_call(gateway, &request, 16).await?;
+        Some((
+            u16::from_be_bytes([answer[10], answer[11]]),
+            u32::from_be_bytes([answer[12], answer[13], answer[14], answer[15]]),
+        ))
+    }
+
+    async fn nat_pmp_external_ip(gateway: SocketAddr) -> Option<IpAddr> {
+        let answer = Self::nat_pmp_call(gateway, &[0, 0], 12).await?;
+        Some(IpAddr::V4(Ipv4Addr::new(
+            answer[8], answer[9], answer[10], answer[11],
+        )))
+    }
+}
+
+/// Our local IP used for packets to `peer`
+async fn local_ip_towards(peer: SocketAddr) -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.ok()?;
+    socket.connect(peer).await.ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// IPv4 default gateway from the kernel routing table
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Option<IpAddr> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        // Addresses in the table are in host byte order
+        Some(IpAddr::V4(Ipv4Addr::from(gateway.to_ne_bytes())))
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Option<IpAddr> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renewal_follows_granted_lifetime() {
+        let mapping = PortMapping {
+            method: MappingMethod::NatPmp,
+            local_addr: "192.168.1.2:8468".parse().unwrap(),
+            external_ip: None,
+            external_port: 8468,
+            expires_at: 1000.0 + 600.0,
+        };
+        assert_eq!(mapping.renew_in(1000.0), Duration::from_secs(300));
+        assert_eq!(mapping.renew_in(1600.0), Duration::from_secs(1));
+    }
+}
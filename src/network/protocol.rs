@@ -1,24 +1,62 @@
 use async_trait::async_trait;
-use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{Mutex, RwLock, oneshot};
+use tokio::time::Instant;
 use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
 
-use crate::dht::node::{Node, NodeID};
-use crate::dht::protocol::NetworkProtocolTrait;
+use crate::dht::holder_pointers::HolderPointers;
+use crate::dht::node::{Endpoint, EndpointKind, Node, NodeID};
+use crate::dht::protocol::{NetworkProtocolTrait, VersionedValue};
 use crate::dht::routing_table::RoutingTable;
+use crate::exceptions::StorageError;
 use crate::exceptions::{NetworkError, RhizomeError};
+#[cfg(feature = "mailbox")]
+use crate::mailbox::{DirectInbox, MailboxEnvelope, open_message, receipt_bytes};
+use crate::network::clock::{ClockSkew, UNLEARNED_SKEW_ALLOWANCE};
 use crate::network::consts::*;
+use crate::network::external_address::ExternalAddress;
+use crate::network::fragments::{
+    Assembly, FRAGMENT_SIZE, FRAGMENT_WINDOW, FRAGMENTED_STORE_THRESHOLD, FRAGMENTS_SINCE_PROTOCOL,
+    FragmentAssembler, MAX_FRAGMENTED_VALUE_SIZE, MAX_REPORTED_MISSING, MAX_STALLED_ROUNDS,
+    MAX_VALUE_CHUNK, MIN_ROUND_TIMEOUT, ROUND_TIMEOUT_FACTOR, ValueReads, fragment_count,
+};
+use crate::network::gossip::{Gossip, GossipMessage, GossipTopic, MAX_GOSSIP_BATCH};
+use crate::network::peer_stats::{MAX_TRACKED_PEERS, PeerSoftware, PeerStatsTable};
+use crate::network::qos::MessagePriority;
 use crate::network::transport::{Message, UDPTransport};
+use crate::node::mode::ModeSwitch;
+#[cfg(feature = "popularity")]
 use crate::popularity::exchanger::PopularityExchanger;
+#[cfg(feature = "signatures")]
+use crate::replication::receipt::ReceiptCache;
+use crate::replication::receipt::StorageReceipt;
+use crate::security::audit::{AuditEventKind, AuditLog};
+use crate::security::blacklist::Blacklist;
+#[cfg(feature = "signatures")]
+use crate::security::blacklist::SignedBlocklist;
+#[cfg(feature = "wasm-plugins")]
+use crate::security::content_policy::{ContentPolicy, PolicyDecision};
+use crate::security::puzzle::{MAX_PARKED_REQUESTS, PuzzleGuard};
 use crate::security::rate_limiter::RateLimiter;
-use crate::storage::main::Storage;
-use crate::utils::time::get_now_f64;
+use crate::security::reputation::ReputationTable;
+use crate::storage::disk::DiskWatchdog;
+use crate::storage::keys::{Key, MAX_KEY_SIZE};
+#[cfg(feature = "signatures")]
+use crate::storage::keys::{KeyManager, RECORD_BLOCKLIST};
+use crate::storage::main::{CasOutcome, Durability, Storage, StoreOrigin, TypedRecord};
+use crate::storage::proof::PublisherProof;
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::NodeSigner;
+#[cfg(feature = "mailbox")]
+use crate::utils::crypto::verify_signature;
+use crate::utils::rng::NodeRng;
+use crate::utils::serialization::{self, WireCodec, WireFormat};
+use crate::utils::time::{Clock, SystemClock};
 
 /// Message structure
 #[derive(Serialize, Deserialize, Debug)]
@@ -37,6 +75,61 @@ pub struct ProtocolMessage {
 }
 
 type ResponseSender = oneshot::Sender<(u8, serde_json::Value)>;
+/// Requests waiting for answers by message ID
+type PendingRequests = Arc<Mutex<HashMap<[u8; 16], ResponseSender>>>;
+
+/// Entry of [`NetworkProtocol::pending_requests`] which is removed when it is dropped
+///
+/// Future of the request can be dropped at any await by its caller, so the entry is not
+/// left to the code after the await.
+struct PendingRequest {
+    requests: PendingRequests,
+    msg_id: [u8; 16],
+}
+
+impl PendingRequest {
+    fn new(requests: &PendingRequests, msg_id: [u8; 16]) -> Self {
+        Self {
+            requests: requests.clone(),
+            msg_id,
+        }
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        // Lock can not be awaited here, busy lock is left to a task
+        if let Ok(mut requests) = self.requests.try_lock() {
+            requests.remove(&self.msg_id);
+        } else if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let (requests, msg_id) = (self.requests.clone(), self.msg_id);
+            runtime.spawn(async move {
+                requests.lock().await.remove(&msg_id);
+            });
+        }
+    }
+}
+
+/// Max count of records in one LIST_RECORDS answer
+const MAX_LISTED_RECORDS: usize = 50;
+/// Max summary size of keys and values in one LIST_RECORDS answer _(fits UDP message)_
+const MAX_LISTED_BYTES: usize = 16 * 1024;
+/// Max count of keys in one FIND_VALUES request
+const MAX_BATCH_KEYS: usize = 100;
+/// Max summary size of values in one FIND_VALUES answer chunk
+const MAX_BATCH_BYTES: usize = 16 * 1024;
+/// Max count of endpoints accepted from one peer
+const MAX_ENDPOINTS: usize = 8;
+/// Max count of holders from one FIND_VALUE answer which are asked for the value
+const MAX_FOLLOWED_HOLDERS: usize = 2;
+/// Max lifetime in seconds of a tombstone received from other node
+const MAX_TOMBSTONE_TTL: i64 = 30 * 86400;
+/// Direct messages opened in a minute from all peers, every one costs RSA decryption
+#[cfg(feature = "mailbox")]
+const DIRECT_MESSAGES_PER_MINUTE: usize = 60;
+/// Direct messages opened in a minute from one peer
+#[cfg(feature = "mailbox")]
+const DIRECT_MESSAGES_PER_PEER: usize = 10;
 
 /// Network protocol for sending data by UDP
 pub struct NetworkProtocol {
@@ -51,13 +144,73 @@ pub struct NetworkProtocol {
     /// Local node storage
     pub storage: Option<Arc<Storage>>,
     /// Exchanger of the popularity
+    #[cfg(feature = "popularity")]
     pub popularity_exchanger: Arc<RwLock<Option<Arc<PopularityExchanger>>>>,
     /// Protection of DDOS and spam
     pub rate_limiter: Arc<Mutex<RateLimiter>>,
     /// List of items which we are wait
-    pub pending_requests: Arc<Mutex<HashMap<[u8; 16], ResponseSender>>>,
+    pub pending_requests: PendingRequests,
     /// How much time we need to wait the answer
     pub request_timeout: Duration,
+    /// How much time we wait the PONG
+    pub ping_timeout: Duration,
+    /// State of cluster-wide announcements
+    pub gossip: Arc<Gossip>,
+    /// Estimated clock offsets of peers
+    pub clock: Arc<ClockSkew>,
+    /// Clock of the node _(clock of the storage by default)_
+    pub time: Arc<dyn Clock>,
+    /// Puzzles for unknown peers under high load
+    pub puzzles: Arc<PuzzleGuard>,
+    /// Peers whose messages are dropped
+    pub blacklist: Arc<Blacklist>,
+    /// Our endpoints advertised in PING and PONG
+    pub endpoints: Vec<Endpoint>,
+    /// Our address as peers see it, reported in their PONG
+    pub external_address: Arc<ExternalAddress>,
+    /// Max difference in seconds between message timestamp and our clock _(None - any)_
+    pub timestamp_window: Option<f64>,
+    /// Scores of peers by answers to our requests
+    pub reputation: Arc<ReputationTable>,
+    /// Traffic counters of peers
+    pub peer_stats: Arc<PeerStatsTable>,
+    /// Log of security events
+    pub audit: Option<Arc<AuditLog>>,
+    /// Free disk space watcher, inbound stores are rejected in degraded mode
+    pub disk_watchdog: Option<Arc<DiskWatchdog>>,
+    /// Mode of the node, inbound stores are rejected in read-only mode and during warm-up
+    pub mode: Arc<ModeSwitch>,
+    /// Requests waiting for puzzle solution by peer node ID
+    pub parked_requests: Mutex<HashMap<[u8; 20], (ProtocolMessage, SocketAddr)>>,
+    /// Source of message IDs
+    pub rng: Arc<NodeRng>,
+    /// Preferred format of outgoing messages
+    pub wire_format: WireFormat,
+    /// ID of our network, messages of other networks are rejected _(empty - main network)_
+    pub network_id: String,
+    /// Formats agreed with peers by address _(msgpack if unknown)_
+    pub peer_formats: std::sync::Mutex<HashMap<SocketAddr, WireFormat>>,
+    /// Key for signing receipts of accepted STORE _(no receipts if None)_
+    #[cfg(feature = "signatures")]
+    pub receipt_signer: Option<Arc<NodeSigner>>,
+    /// Receipts already signed for repeated STORE
+    #[cfg(feature = "signatures")]
+    issued_receipts: ReceiptCache,
+    /// Operator's plugins which check data of other nodes before STORE
+    #[cfg(feature = "wasm-plugins")]
+    pub content_policy: Option<Arc<ContentPolicy>>,
+    /// Direct messages to us waiting for the client
+    #[cfg(feature = "mailbox")]
+    pub direct_inbox: Arc<DirectInbox>,
+    /// Limit of direct messages which are opened, checked before decryption
+    #[cfg(feature = "mailbox")]
+    direct_limiter: Mutex<RateLimiter>,
+    /// Peers holding values which we refused to store _(None - pointers are not kept)_
+    pub holder_pointers: Option<Arc<HolderPointers>>,
+    /// Unfinished transfers of large values sent to us in fragments
+    pub fragments: Arc<FragmentAssembler>,
+    /// Reads of our big values by parts
+    pub value_reads: Arc<ValueReads>,
 }
 
 impl NetworkProtocol {
@@ -68,16 +221,60 @@ impl NetworkProtocol {
         routing_table: Option<Arc<RwLock<RoutingTable>>>,
         storage: Option<Arc<Storage>>,
     ) -> Self {
+        #[cfg(feature = "mailbox")]
+        let direct_inbox = Arc::new(DirectInbox::new(storage.clone()));
+        let time: Arc<dyn Clock> = match &storage {
+            Some(storage) => storage.clock().clone(),
+            None => Arc::new(SystemClock),
+        };
         Self {
             transport,
             node_id,
             local_address,
             routing_table,
             storage,
+            #[cfg(feature = "popularity")]
             popularity_exchanger: Arc::new(RwLock::new(None)),
-            rate_limiter: Arc::new(Mutex::new(RateLimiter::new(100, 60, 20))),
+            rate_limiter: Arc::new(Mutex::new(
+                RateLimiter::new(100, 60, 20).with_clock(time.clone()),
+            )),
             pending_requests: Arc::new(Mutex::new(HashMap::new())),
             request_timeout: Duration::from_secs(10),
+            ping_timeout: Duration::from_secs(5),
+            gossip: Arc::new(Gossip::default().with_clock(time.clone())),
+            clock: Arc::new(ClockSkew::new()),
+            puzzles: Arc::new(PuzzleGuard::default()),
+            blacklist: Arc::new(Blacklist::default().with_clock(time.clone())),
+            endpoints: Vec::new(),
+            external_address: Arc::new(ExternalAddress::default()),
+            timestamp_window: Some(300.0),
+            reputation: Arc::new(ReputationTable::default().with_clock(time.clone())),
+            peer_stats: Arc::new(PeerStatsTable::default()),
+            audit: None,
+            disk_watchdog: None,
+            mode: Arc::new(ModeSwitch::default()),
+            parked_requests: Mutex::new(HashMap::new()),
+            rng: Arc::new(NodeRng::default()),
+            wire_format: WireFormat::Msgpack,
+            network_id: String::new(),
+            peer_formats: std::sync::Mutex::new(HashMap::new()),
+            #[cfg(feature = "signatures")]
+            receipt_signer: None,
+            #[cfg(feature = "signatures")]
+            issued_receipts: ReceiptCache::default(),
+            #[cfg(feature = "wasm-plugins")]
+            content_policy: None,
+            #[cfg(feature = "mailbox")]
+            direct_inbox,
+            #[cfg(feature = "mailbox")]
+            direct_limiter: Mutex::new(
+                RateLimiter::new(DIRECT_MESSAGES_PER_MINUTE, 60, DIRECT_MESSAGES_PER_PEER)
+                    .with_clock(time.clone()),
+            ),
+            holder_pointers: None,
+            fragments: Arc::new(FragmentAssembler::default().with_clock(time.clone())),
+            value_reads: Arc::new(ValueReads::default().with_clock(time.clone())),
+            time,
         }
     }
 
@@ -108,31 +305,318 @@ impl NetworkProtocol {
 
     /// Validation of incoming messages
     ///
-    /// Deserialize data and check rate limit
+    /// Deserialize data, check timestamp and rate limit. Under high load requests of
+    /// unknown peers are parked until the peer solves a puzzle.
     pub async fn handle_incoming_message(&self, message: Message) {
-        let raw_msg: Result<ProtocolMessage, _> = rmp_serde::from_slice(&message.data);
+        let raw_msg = serialization::decode_message(&message.data);
 
         if let Ok(m) = raw_msg {
+            if self.blacklist.blocks(&m.node_id, message.address.ip()) {
+                return;
+            }
+            if !self.timestamp_is_fresh(&m) {
+                debug!(address = %message.address, timestamp = m.timestamp, "Message timestamp out of window");
+                self.reputation
+                    .record_validation_failure(message.address.ip());
+                self.audit(
+                    AuditEventKind::TimestampRejected,
+                    &m.node_id,
+                    message.address,
+                    format!(
+                        "message type {:#04x}, timestamp {}",
+                        m.msg_type, m.timestamp
+                    ),
+                );
+                return;
+            }
+            self.peer_stats
+                .record_received(&m.node_id, message.address, message.data.len());
+
+            if m.msg_type == MSG_PUZZLE_SOLUTION {
+                self.handle_puzzle_solution(m, message.address).await;
+                return;
+            }
+
+            if m.msg_type == MSG_STORE_FRAGMENT {
+                self.accept_fragment(&m, message.address);
+                return;
+            }
+
+            if let Some(allowed) = self.fragment_round(&m, message.address).await {
+                if allowed {
+                    self.dispatch_message(m, message.address).await;
+                }
+                return;
+            }
+
             let mut limiter = self.rate_limiter.lock().await;
-            if limiter.check_rate_limit(Some(&m.node_id)).is_err() {
+            let priority = MessagePriority::for_msg_type(m.msg_type);
+            if limiter
+                .check_rate_limit_with_priority(Some(&m.node_id), priority)
+                .is_err()
+            {
                 warn!(address = %message.address, "Rate limit exceeded");
+                self.audit(
+                    AuditEventKind::RateLimitExceeded,
+                    &m.node_id,
+                    message.address,
+                    format!("message type {:#04x}", m.msg_type),
+                );
                 return;
             }
+            let load = limiter.load();
             drop(limiter);
 
-            let mut pending = self.pending_requests.lock().await;
-            if let Some(sender) = pending.remove(&m.id) {
-                let _ = sender.send((m.msg_type, m.payload));
+            if m.msg_type == MSG_PUZZLE {
+                self.solve_puzzle(m, message.address).await;
                 return;
             }
-            drop(pending);
 
-            if let Err(e) = self
-                .handle_request(m.msg_type, m.id, m.payload, message.address)
-                .await
-            {
-                error!(error = %e, "Error handling request");
+            if self.puzzle_required(&m, load).await {
+                self.send_puzzle(m, message.address).await;
+                return;
+            }
+
+            self.dispatch_message(m, message.address).await;
+        }
+    }
+
+    /// Origin of the data from STORE or STORE_CAS payload
+    ///
+    /// Publisher from the payload is accepted only with its valid signature of the record,
+    /// otherwise the data is published by the sender. So quotas and blocks of publishers
+    /// can not be moved to other nodes, and data from the network always has a publisher.
+    fn parse_store_origin(
+        &self,
+        payload: &serde_json::Value,
+        sender_id: &[u8; 20],
+        key: &Key,
+        value: &[u8],
+    ) -> StoreOrigin {
+        let hops = payload.get("hops").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+        let record_type = payload
+            .get("record_type")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let (publisher, published_at, proof) =
+            match self.proven_publisher(payload, key, Some(value), &record_type) {
+                Some((publisher, proof)) => (publisher, Some(proof.signed_at), Some(proof)),
+                None => (
+                    sender_id.to_vec(),
+                    payload.get("published_at").and_then(|v| v.as_f64()),
+                    None,
+                ),
+            };
+        // Offset of the publisher named by the payload is not ours to trust, the time is
+        // corrected by the offset of the sender and is never later than now
+        let now = self.time.now();
+        let published_at = published_at
+            .map(|t| self.clock.to_local(sender_id, t).min(now))
+            .unwrap_or(now);
+        StoreOrigin {
+            publisher,
+            published_at,
+            hops,
+            record_type,
+            proof,
+        }
+    }
+
+    /// Timestamp of the message is within `timestamp_window` from our clock
+    ///
+    /// Our estimate of the sender clock offset is taken into account, so old messages can
+    /// not be replayed and stale values can not be pushed with fake fresh time. Until the
+    /// offset is learned the window is wider by [`UNLEARNED_SKEW_ALLOWANCE`], otherwise
+    /// peers with skewed clocks would never be heard.
+    fn timestamp_is_fresh(&self, m: &ProtocolMessage) -> bool {
+        let window = match self.timestamp_window {
+            Some(window) => window,
+            None => return true,
+        };
+        let (offset, window) = match self.clock.known_offset(&m.node_id) {
+            Some(offset) => (offset, window),
+            None => (0.0, window + UNLEARNED_SKEW_ALLOWANCE),
+        };
+        m.timestamp.is_finite() && (m.timestamp - offset - self.time.now()).abs() <= window
+    }
+
+    /// Pass answer to waiting request or process request
+    async fn dispatch_message(&self, m: ProtocolMessage, address: SocketAddr) {
+        if let Some(network) = self.foreign_network(&m.payload) {
+            self.reject_foreign_network(m, address, &network).await;
+            return;
+        }
+
+        let warming_up = Self::peer_is_warming_up(&m.payload);
+        self.observe_sender(&m.node_id, m.timestamp, address, warming_up)
+            .await;
+
+        let mut pending = self.pending_requests.lock().await;
+        if let Some(sender) = pending.remove(&m.id) {
+            let _ = sender.send((m.msg_type, m.payload));
+            return;
+        }
+        drop(pending);
+        self.peer_stats.record_request_received(&m.node_id);
+
+        if matches!(
+            m.msg_type,
+            MSG_STORE | MSG_STORE_CAS | MSG_DELETE | MSG_DIRECT
+        ) && self
+            .resend_processed_response(&m.node_id, m.id, address)
+            .await
+        {
+            debug!(address = %address, "Duplicate STORE answered from cache");
+            return;
+        }
+
+        if let Err(e) = self
+            .handle_request(m.msg_type, m.id, m.node_id, m.payload, address)
+            .await
+        {
+            error!(error = %e, "Error handling request");
+        }
+    }
+
+    /// Request of unknown and not verified peer while we are close to the rate limit
+    ///
+    /// Answers to our requests and messages of peers from routing table always pass
+    async fn puzzle_required(&self, m: &ProtocolMessage, load: f64) -> bool {
+        if !self.puzzles.is_required(&m.node_id, load)
+            || self.pending_requests.lock().await.contains_key(&m.id)
+        {
+            return false;
+        }
+        match &self.routing_table {
+            Some(rt) => !rt.read().await.contains(&NodeID::new(m.node_id)),
+            None => true,
+        }
+    }
+
+    /// Park the request and answer with puzzle
+    async fn send_puzzle(&self, m: ProtocolMessage, address: SocketAddr) {
+        let puzzle = self.puzzles.issue(&m.node_id);
+        let msg_id = m.id;
+
+        let mut parked = self.parked_requests.lock().await;
+        if parked.len() >= MAX_PARKED_REQUESTS && !parked.contains_key(&m.node_id) {
+            debug!(address = %address, "Too many requests wait for puzzle, dropped");
+            return;
+        }
+        parked.insert(m.node_id, (m, address));
+        drop(parked);
+
+        let payload = serde_json::json!({
+            "challenge": hex::encode(&puzzle.challenge),
+            "issued_at": puzzle.issued_at,
+            "difficulty": puzzle.difficulty
+        });
+        if let Err(e) = self
+            .send_response(MSG_PUZZLE, msg_id, payload, address)
+            .await
+        {
+            debug!(error = %e, "Failed to send puzzle");
+        }
+    }
+
+    /// Solve puzzle received on our request and send solution
+    ///
+    /// Request stays pending, the answer comes after the solution is checked
+    async fn solve_puzzle(&self, m: ProtocolMessage, address: SocketAddr) {
+        if !self.pending_requests.lock().await.contains_key(&m.id) {
+            return;
+        }
+
+        let challenge = match m
+            .payload
+            .get("challenge")
+            .and_then(|v| v.as_str())
+            .and_then(|h| hex::decode(h).ok())
+        {
+            Some(c) => c,
+            None => return,
+        };
+        let issued_at = m
+            .payload
+            .get("issued_at")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0);
+        let difficulty = m
+            .payload
+            .get("difficulty")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(u64::MAX)
+            .min(u8::MAX as u64) as u8;
+
+        let our_id = self.node_id.0;
+        let puzzle_challenge = challenge.clone();
+        let nonce = match tokio::task::spawn_blocking(move || {
+            PuzzleGuard::solve(&puzzle_challenge, &our_id, difficulty)
+        })
+        .await
+        {
+            Ok(Some(nonce)) => nonce,
+            _ => {
+                warn!(address = %address, difficulty = difficulty, "Puzzle is too hard, not solved");
+                return;
             }
+        };
+
+        let payload = serde_json::json!({
+            "challenge": hex::encode(&challenge),
+            "issued_at": issued_at,
+            "nonce": nonce
+        });
+        if let Ok(data) = self.pack_message(MSG_PUZZLE_SOLUTION, m.id, payload, address)
+            && let Err(e) = self
+                .transport
+                .send_with_priority(
+                    &data,
+                    address,
+                    MessagePriority::for_msg_type(MSG_PUZZLE_SOLUTION),
+                )
+                .await
+        {
+            debug!(error = %e, "Failed to send puzzle solution");
+        }
+    }
+
+    /// Check solution of the peer and process its parked request
+    async fn handle_puzzle_solution(&self, m: ProtocolMessage, address: SocketAddr) {
+        let challenge = m
+            .payload
+            .get("challenge")
+            .and_then(|v| v.as_str())
+            .and_then(|h| hex::decode(h).ok())
+            .unwrap_or_default();
+        let issued_at = m
+            .payload
+            .get("issued_at")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0);
+        let nonce = m.payload.get("nonce").and_then(|v| v.as_u64()).unwrap_or(0);
+
+        if !self
+            .puzzles
+            .verify(&m.node_id, &challenge, issued_at, nonce)
+        {
+            debug!(address = %address, "Wrong puzzle solution");
+            self.reputation.record_validation_failure(address.ip());
+            self.audit(
+                AuditEventKind::PuzzleFailed,
+                &m.node_id,
+                address,
+                "wrong or expired solution",
+            );
+            return;
+        }
+
+        let parked = self.parked_requests.lock().await.remove(&m.node_id);
+        if let Some((request, request_address)) = parked
+            && request.id == m.id
+        {
+            self.dispatch_message(request, request_address).await;
         }
     }
 
@@ -142,36 +626,57 @@ impl NetworkProtocol {
     /// - `MSG_FIND_NODE`: Find neighbors in our routing table and send in response
     /// - `MSG_FIND_VALUE`: Check local storage: if we have data we will return them or send
     ///   our neighbors which maybe know data
-    /// - `MSG_STORE`: Chose data from message and save it in our store
+    /// - `MSG_FIND_VALUES`: Send values of several keys, keys which do not fit in one answer
+    ///   are returned as `remaining`
+    /// - `MSG_STORE`: Chose data from message and save it in our store, answer with signed
+    ///   receipt if we have signing key
+    /// - `MSG_STORE_CAS`: Save data only if our current value has expected hash, otherwise
+    ///   send our current value back
+    /// - `MSG_LIST_RECORDS`: Send our records of requested type
     /// - `MSG_POPULARITY_EXCHANGE`: Exchange information about content popularity
+    /// - `MSG_GOSSIP_PUSH`: Accept new gossip messages and push them further
+    /// - `MSG_GOSSIP_DIGEST`: Send gossip messages missed by sender and ask for ours missed
+    /// - `MSG_DIRECT`: Open direct message sealed for us, keep it in the inbox and answer with
+    ///   signed delivery receipt
     pub async fn handle_request(
         &self,
         msg_type: u8,
         msg_id: [u8; 16],
+        sender_id: [u8; 20],
         payload: serde_json::Value,
         address: SocketAddr,
     ) -> Result<(), RhizomeError> {
+        if Self::has_oversized_key(&payload) {
+            debug!(msg_type, address = %address, "Request with too long key dropped");
+            return Ok(());
+        }
         match msg_type {
             MSG_PING => {
                 if let Some(rt_link) = &self.routing_table
-                    && let Some(id_val) = payload.get("node_id").and_then(|v| v.as_array())
+                    && !Self::peer_is_warming_up(&payload)
+                    && let Some(id) = payload
+                        .get("node_id")
+                        .and_then(|v| NodeID::deserialize(v).ok())
                 {
-                    let mut id_bytes = [0u8; 20];
-                    for (i, v) in id_val.iter().enumerate().take(20) {
-                        id_bytes[i] = v.as_u64().unwrap_or(0) as u8;
-                    }
-                    let sender_node = Node::new(
-                        NodeID::new(id_bytes),
-                        address.ip().to_string(),
-                        address.port(),
-                    );
+                    let mut sender_node = Node::new(id, address.ip().to_string(), address.port());
+                    sender_node.endpoints = Self::parse_endpoints(&payload).unwrap_or_default();
                     rt_link.write().await.add_node(sender_node);
                 }
 
-                let response_payload = serde_json::json!({
-                    "node_id": self.node_id.0,
-                    "address": self.local_address.to_string()
+                self.negotiate_format(address, &payload);
+                self.observe_software(&sender_id, address, &payload);
+
+                let mut response_payload = serde_json::json!({
+                    "node_id": self.node_id,
+                    "address": self.advertised_address().await.to_string(),
+                    "observed": address.to_string(),
+                    "codecs": WireFormat::supported(),
+                    "software": PeerSoftware::local()
                 });
+                let endpoints = self.advertised_endpoints();
+                if !endpoints.is_empty() {
+                    response_payload["endpoints"] = serde_json::json!(endpoints);
+                }
                 self.send_response(MSG_PONG, msg_id, response_payload, address)
                     .await?;
             }
@@ -180,26 +685,14 @@ impl NetworkProtocol {
                 if let (Some(rt_link), Some(target_val)) =
                     (&self.routing_table, payload.get("target_id"))
                 {
-                    let mut id_bytes = [0u8; 20];
-                    if let Some(arr) = target_val.as_array() {
-                        for (i, v) in arr.iter().enumerate().take(20) {
-                            id_bytes[i] = v.as_u64().unwrap_or(0) as u8;
-                        }
-                    }
+                    let target_id =
+                        NodeID::deserialize(target_val).unwrap_or(NodeID::new([0u8; 20]));
 
                     let rt = rt_link.read().await;
-                    let closest = rt.find_closest_nodes(&NodeID::new(id_bytes), rt.k);
+                    let closest = rt.find_closest_nodes(&target_id, rt.k);
 
-                    let nodes_data: Vec<serde_json::Value> = closest
-                        .iter()
-                        .map(|n| {
-                            serde_json::json!({
-                                "node_id": n.node_id.0,
-                                "address": n.address,
-                                "port": n.port
-                            })
-                        })
-                        .collect();
+                    let nodes_data: Vec<serde_json::Value> =
+                        closest.iter().map(Self::contact_json).collect();
 
                     self.send_response(
                         MSG_FIND_NODE_RESPONSE,
@@ -213,59 +706,228 @@ impl NetworkProtocol {
 
             MSG_FIND_VALUE => {
                 if let (Some(storage), Some(key_val)) = (&self.storage, payload.get("key")) {
-                    let key_bytes: Vec<u8> =
-                        serde_json::from_value(key_val.clone()).unwrap_or_default();
-                    let value = storage.get(key_bytes.clone()).await?;
+                    let key: Key = serde_json::from_value(key_val.clone()).unwrap_or_default();
+                    let value = storage.get_with_meta(key.clone()).await?;
+                    // Requester which sends offset reads big values by parts
+                    let offset = payload.get("offset").and_then(|v| v.as_u64());
 
-                    if let Some(v) = value {
-                        self.send_response(
-                            MSG_FIND_VALUE_RESPONSE,
-                            msg_id,
-                            serde_json::json!({"found": true, "value": v}),
-                            address,
-                        )
-                        .await?;
+                    if let Some(stored) = value {
+                        if offset.unwrap_or(0) == 0 {
+                            storage.record_access(&key);
+                        }
+                        let mut response = serde_json::json!({
+                            "found": true,
+                            "stored_at": stored.stored_at,
+                            "published_at": stored.origin.published_at,
+                            "expires_at": (stored.expires_at < f64::MAX).then_some(stored.expires_at),
+                            "publisher": stored.origin.publisher,
+                            "record_type": stored.origin.record_type,
+                            "proof": stored.origin.proof
+                        });
+                        match offset {
+                            Some(offset) if stored.value.len() > MAX_VALUE_CHUNK => {
+                                let start = (offset as usize).min(stored.value.len());
+                                let end = (start + MAX_VALUE_CHUNK).min(stored.value.len());
+                                response["value"] = serde_json::json!(stored.value[start..end]);
+                                response["size"] = serde_json::json!(stored.value.len());
+                                if start == 0
+                                    && let Some(read) = self.value_reads.open(
+                                        address.ip(),
+                                        &key,
+                                        stored.value.len(),
+                                    )
+                                {
+                                    response["read"] = serde_json::json!(hex::encode(read));
+                                }
+                            }
+                            _ => response["value"] = serde_json::json!(stored.value),
+                        }
+                        self.send_response(MSG_FIND_VALUE_RESPONSE, msg_id, response, address)
+                            .await?;
                     } else if let Some(rt_link) = &self.routing_table {
-                        let mut id_bytes = [0u8; 20];
-                        let len = key_bytes.len().min(20);
-                        id_bytes[..len].copy_from_slice(&key_bytes[..len]);
-
                         let rt = rt_link.read().await;
-                        let closest = rt.find_closest_nodes(&NodeID::new(id_bytes), rt.k);
-                        let nodes_data: Vec<serde_json::Value> = closest.iter().map(|n| {
-                            serde_json::json!({"node_id": n.node_id.0, "address": n.address, "port": n.port})
-                        }).collect();
+                        let closest = rt.find_closest_nodes(&key.node_id(), rt.k);
+                        let nodes_data: Vec<serde_json::Value> =
+                            closest.iter().map(Self::contact_json).collect();
+                        let mut response = serde_json::json!({"found": false, "nodes": nodes_data});
+                        if let Some(pointers) = &self.holder_pointers {
+                            let holders = pointers.holders(&key);
+                            if !holders.is_empty() {
+                                response["holders"] =
+                                    holders.iter().map(Self::contact_json).collect();
+                            }
+                        }
 
-                        self.send_response(
-                            MSG_FIND_VALUE_RESPONSE,
-                            msg_id,
-                            serde_json::json!({"found": false, "nodes": nodes_data}),
-                            address,
-                        )
-                        .await?;
+                        self.send_response(MSG_FIND_VALUE_RESPONSE, msg_id, response, address)
+                            .await?;
                     }
                 }
             }
 
-            MSG_STORE => {
-                if let (Some(storage), Some(key_val), Some(val_val)) =
-                    (&self.storage, payload.get("key"), payload.get("value"))
-                {
-                    let key: Vec<u8> = serde_json::from_value(key_val.clone()).unwrap_or_default();
-                    let value: Vec<u8> =
-                        serde_json::from_value(val_val.clone()).unwrap_or_default();
-                    let ttl = payload.get("ttl").and_then(|v| v.as_i64()).unwrap_or(86400) as i32;
+            MSG_FIND_VALUES => {
+                if let Some(storage) = &self.storage {
+                    let mut keys: Vec<Key> =
+                        serde_json::from_value(payload.get("keys").cloned().unwrap_or_default())
+                            .unwrap_or_default();
+                    keys.truncate(MAX_BATCH_KEYS);
+                    // Requester which reads by parts gets only keys of big values
+                    let parts = payload
+                        .get("parts")
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false);
+
+                    let mut values = Vec::new();
+                    let mut missing = Vec::new();
+                    let mut remaining = Vec::new();
+                    let mut large = Vec::new();
+                    let mut total_size = 0;
+
+                    for key in keys {
+                        if total_size >= MAX_BATCH_BYTES {
+                            remaining.push(key);
+                            continue;
+                        }
+                        match storage.get(key.clone()).await? {
+                            Some(value) if parts && value.len() > MAX_VALUE_CHUNK => {
+                                large.push(key);
+                            }
+                            // First value is sent always, even if it is big
+                            Some(value)
+                                if values.is_empty()
+                                    || total_size + value.len() <= MAX_BATCH_BYTES =>
+                            {
+                                total_size += value.len();
+                                storage.record_access(&key);
+                                values.push(serde_json::json!({"key": key, "value": value}));
+                            }
+                            Some(_) => {
+                                total_size = MAX_BATCH_BYTES;
+                                remaining.push(key);
+                            }
+                            None => missing.push(key),
+                        }
+                    }
 
-                    storage.put(key, value, ttl).await?;
                     self.send_response(
-                        MSG_STORE_RESPONSE,
+                        MSG_FIND_VALUES_RESPONSE,
                         msg_id,
-                        serde_json::json!({"success": true}),
+                        serde_json::json!({
+                            "values": values,
+                            "missing": missing,
+                            "remaining": remaining,
+                            "large": large
+                        }),
                         address,
                     )
                     .await?;
                 }
             }
+
+            MSG_STORE => {
+                if let (Some(storage), Some(key_val)) = (&self.storage, payload.get("key"))
+                    && let Some(value) = self.store_value(&payload, msg_id, address).await?
+                {
+                    let key: Key = serde_json::from_value(key_val.clone()).unwrap_or_default();
+                    let ttl = payload.get("ttl").and_then(|v| v.as_i64()).unwrap_or(86400) as i32;
+
+                    let origin = self.parse_store_origin(&payload, &sender_id, &key, &value);
+
+                    #[cfg(not(feature = "signatures"))]
+                    let receipt = serde_json::Value::Null;
+                    #[cfg(feature = "signatures")]
+                    let receipt = self.receipt_signer.as_ref().map(|signer| {
+                        let now = self.time.now();
+                        self.issued_receipts
+                            .issue(signer, &key, value.len(), now + ttl as f64, now)
+                    });
+                    let accepted = serde_json::json!({"success": true, "receipt": receipt});
+                    // Answer for retries is saved together with the value, so a retry
+                    // never finds the value without the answer or vice versa
+                    let mut answer_recorded = false;
+                    let stored = if let Some(refusal) = self.refuse_inbound_store() {
+                        Err(refusal)
+                    } else if self.blocks_origin(&origin, &sender_id)
+                        || !self
+                            .blocklist_accepted(storage, &key, &value, &origin)
+                            .await
+                        || !self.policy_accepts(&key, &value).await
+                    {
+                        Err(StorageError::PolicyRejected)
+                    } else if storage.durability() == Durability::WriteThrough {
+                        let request_id = Self::request_key(&sender_id, &msg_id);
+                        let answer = serde_json::to_vec(&accepted)
+                            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+                        let (txn_key, origin) = (key.clone(), origin.forwarded());
+                        let stored = storage
+                            .transaction(move |txn| {
+                                txn.put(&txn_key, &value, ttl, origin)?;
+                                txn.record_processed(&request_id, MSG_STORE_RESPONSE, answer)
+                            })
+                            .await;
+                        answer_recorded = stored.is_ok();
+                        stored
+                    } else {
+                        storage
+                            .put_with_origin(key.clone(), value, ttl, origin.forwarded())
+                            .await
+                    };
+                    // Sender keeps the value, lookups ending at us are pointed to it
+                    if let (
+                        Err(
+                            StorageError::StorageFull
+                            | StorageError::ReadOnly
+                            | StorageError::WarmingUp,
+                        ),
+                        Some(pointers),
+                    ) = (&stored, &self.holder_pointers)
+                    {
+                        let holder = Node::new(
+                            NodeID::new(sender_id),
+                            address.ip().to_string(),
+                            address.port(),
+                        );
+                        pointers.record(&key, holder, ttl);
+                    }
+                    let response = match stored {
+                        Ok(()) => accepted,
+                        Err(StorageError::Deleted) => {
+                            let tombstone = storage.get_tombstone(key).await?;
+                            serde_json::json!({
+                                "success": false,
+                                "tombstone": tombstone.map(|t| serde_json::json!({
+                                    "ttl": t.ttl(storage.now()),
+                                    "publisher": t.origin.publisher,
+                                    "deleted_at": t.origin.published_at,
+                                    "proof": t.origin.proof
+                                }))
+                            })
+                        }
+                        Err(e) => {
+                            self.audit(
+                                AuditEventKind::StoreRejected,
+                                &sender_id,
+                                address,
+                                e.to_string(),
+                            );
+                            serde_json::json!({"success": false})
+                        }
+                    };
+                    if answer_recorded {
+                        self.send_response(MSG_STORE_RESPONSE, msg_id, response, address)
+                            .await?;
+                    } else {
+                        self.send_processed_response(
+                            MSG_STORE_RESPONSE,
+                            msg_id,
+                            sender_id,
+                            response,
+                            address,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            #[cfg(feature = "popularity")]
             MSG_POPULARITY_EXCHANGE => {
                 let exchanger_lock = self.popularity_exchanger.read().await;
                 if let Some(exchanger) = exchanger_lock.as_ref() {
@@ -285,37 +947,300 @@ impl NetworkProtocol {
                         self.send_response(
                             MSG_POPULARITY_EXCHANGE_RESPONSE,
                             msg_id,
-                            serde_json::json!({"items": items}),
+                            exchanger.build_exchange_payload(items),
                             address,
                         )
                         .await?;
                     }
 
-                    if let Some(received_items) = payload.get("items").and_then(|v| v.as_array()) {
+                    if payload.get("items").is_some_and(|v| v.is_array()) {
+                        let (sender_id, sender_is_seed) =
+                            PopularityExchanger::parse_sender(&payload);
+                        if let Some(id) = &sender_id {
+                            exchanger.record_key_filter(id, &payload);
+                        }
                         exchanger
-                            .process_received_items(received_items.clone())
+                            .process_received_items(&payload, sender_id, sender_is_seed)
                             .await;
                     }
                 }
             }
+            #[cfg(feature = "popularity")]
             MSG_GLOBAL_RANKING_REQUEST => {
                 let exchanger_lock = self.popularity_exchanger.read().await;
                 if let Some(exchanger) = exchanger_lock.as_ref() {
                     let ranking = exchanger.get_global_ranking_api().await;
+                    let trending = exchanger.get_trending_api(100).await;
                     self.send_response(
                         MSG_GLOBAL_RANKING_RESPONSE,
                         msg_id,
-                        serde_json::json!({"ranking": ranking}),
+                        serde_json::json!({"ranking": ranking, "trending": trending}),
+                        address,
+                    )
+                    .await?;
+                }
+            }
+            MSG_STORE_CAS => {
+                if let (Some(storage), Some(key_val), Some(val_val)) =
+                    (&self.storage, payload.get("key"), payload.get("value"))
+                {
+                    let key: Key = serde_json::from_value(key_val.clone()).unwrap_or_default();
+                    let value: Vec<u8> =
+                        serde_json::from_value(val_val.clone()).unwrap_or_default();
+                    let ttl = payload.get("ttl").and_then(|v| v.as_i64()).unwrap_or(86400) as i32;
+                    let expected = match payload.get("expected").and_then(|v| v.as_str()) {
+                        Some(hex_hash) => Some(Self::parse_hash(hex_hash)?),
+                        None => None,
+                    };
+                    let origin = self.parse_store_origin(&payload, &sender_id, &key, &value);
+
+                    let swapped = if let Some(refusal) = self.refuse_inbound_store() {
+                        Err(refusal)
+                    } else if self.blocks_origin(&origin, &sender_id)
+                        || !self
+                            .blocklist_accepted(storage, &key, &value, &origin)
+                            .await
+                        || !self.policy_accepts(&key, &value).await
+                    {
+                        Err(StorageError::PolicyRejected)
+                    } else {
+                        // Answer is remembered with the write, retry of an applied swap
+                        // gets the same answer instead of a conflict with its own value
+                        let request_id = Self::request_key(&sender_id, &msg_id);
+                        let origin = origin.forwarded();
+                        storage
+                            .transaction(move |txn| {
+                                let outcome =
+                                    txn.compare_and_swap(&key, expected, &value, ttl, origin)?;
+                                let response = serde_json::to_vec(&Self::cas_response(&outcome))
+                                    .map_err(|_| StorageError::General)?;
+                                txn.record_processed(
+                                    &request_id,
+                                    MSG_STORE_CAS_RESPONSE,
+                                    response,
+                                )?;
+                                Ok(outcome)
+                            })
+                            .await
+                    };
+                    let response = match swapped {
+                        Ok(outcome) => Self::cas_response(&outcome),
+                        // Sender learns the refusal instead of waiting for a timeout
+                        Err(e) => {
+                            self.audit(
+                                AuditEventKind::StoreRejected,
+                                &sender_id,
+                                address,
+                                e.to_string(),
+                            );
+                            serde_json::json!({"success": false, "refused": true})
+                        }
+                    };
+
+                    self.send_response(MSG_STORE_CAS_RESPONSE, msg_id, response, address)
+                        .await?;
+                }
+            }
+            MSG_DELETE => {
+                if let (Some(storage), Some(key_val)) = (&self.storage, payload.get("key")) {
+                    let key: Key = serde_json::from_value(key_val.clone()).unwrap_or_default();
+                    let ttl = payload.get("ttl").and_then(|v| v.as_i64()).unwrap_or(0);
+                    let ttl = ttl.clamp(0, MAX_TOMBSTONE_TTL) as i32;
+                    // Only the publisher deletes its records: by its signature or by itself,
+                    // records stored with its signature only by the signature
+                    let (publisher, deleted_at, proof) =
+                        match self.proven_publisher(&payload, &key, None, "") {
+                            Some((publisher, proof)) => (publisher, proof.signed_at, Some(proof)),
+                            None => (
+                                sender_id.to_vec(),
+                                payload
+                                    .get("deleted_at")
+                                    .and_then(|v| v.as_f64())
+                                    .unwrap_or_else(|| self.time.now()),
+                                None,
+                            ),
+                        };
+                    let deleted_at = self
+                        .clock
+                        .to_local(&publisher, deleted_at)
+                        .min(self.time.now());
+                    let origin = StoreOrigin {
+                        publisher,
+                        published_at: deleted_at,
+                        hops: payload.get("hops").and_then(|v| v.as_u64()).unwrap_or(0) as u32,
+                        record_type: String::new(),
+                        proof,
+                    };
+
+                    let applied = !self.mode.is_read_only()
+                        && storage.tombstone(key, ttl, origin.forwarded()).await?;
+                    self.send_processed_response(
+                        MSG_DELETE_RESPONSE,
+                        msg_id,
+                        sender_id,
+                        serde_json::json!({"success": applied}),
+                        address,
+                    )
+                    .await?;
+                }
+            }
+            MSG_LIST_RECORDS => {
+                if let Some(storage) = &self.storage {
+                    let record_type = payload
+                        .get("record_type")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    let since = payload.get("since").and_then(|v| v.as_f64()).unwrap_or(0.0);
+                    let limit = payload
+                        .get("limit")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(MAX_LISTED_RECORDS as u64)
+                        .min(MAX_LISTED_RECORDS as u64) as usize;
+
+                    let records = storage.records_by_type(record_type, since, limit).await?;
+
+                    let mut total_size = 0;
+                    let records_data: Vec<serde_json::Value> = records
+                        .into_iter()
+                        .take_while(|r| {
+                            total_size += r.key.len() + r.value.len();
+                            total_size <= MAX_LISTED_BYTES
+                        })
+                        .map(|r| {
+                            serde_json::json!({
+                                "key": r.key,
+                                "value": r.value,
+                                "published_at": r.published_at
+                            })
+                        })
+                        .collect();
+
+                    self.send_response(
+                        MSG_LIST_RECORDS_RESPONSE,
+                        msg_id,
+                        serde_json::json!({"records": records_data}),
                         address,
                     )
                     .await?;
                 }
             }
+            MSG_GOSSIP_PUSH => {
+                let fresh: Vec<GossipMessage> = Self::parse_gossip_messages(&payload)
+                    .into_iter()
+                    .filter(|m| self.gossip.accept(m.clone()))
+                    .collect();
+                if !fresh.is_empty() {
+                    self.gossip_spread(fresh).await;
+                }
+            }
+            MSG_GOSSIP_DIGEST => {
+                let remote_digest: Vec<String> =
+                    serde_json::from_value(payload.get("digest").cloned().unwrap_or_default())
+                        .unwrap_or_default();
+                let messages = self.gossip.messages_not_in(&remote_digest);
+                let want = self.gossip.missing(&remote_digest);
+
+                self.send_response(
+                    MSG_GOSSIP_DIGEST_RESPONSE,
+                    msg_id,
+                    serde_json::json!({"messages": messages, "want": want}),
+                    address,
+                )
+                .await?;
+            }
+            #[cfg(feature = "mailbox")]
+            MSG_DIRECT => {
+                if self
+                    .direct_limiter
+                    .lock()
+                    .await
+                    .check_rate_limit(Some(&sender_id))
+                    .is_err()
+                {
+                    debug!(address = %address, "Too many direct messages, dropped");
+                    return Ok(());
+                }
+                let opened = self.receipt_signer.as_ref().and_then(|signer| {
+                    let envelope: MailboxEnvelope =
+                        serde_json::from_value(payload.get("envelope")?.clone()).ok()?;
+                    let message = open_message(signer, &envelope)?;
+                    Some((signer, envelope, message))
+                });
+                let response = match opened {
+                    Some((signer, envelope, message)) => {
+                        // Delivery is confirmed only after the envelope is kept
+                        self.direct_inbox.push(&envelope).await?;
+                        let signature = signer.sign(&receipt_bytes(&message.id));
+                        debug!(
+                            sender = %hex::encode(&message.sender_id[..message.sender_id.len().min(8)]),
+                            "Direct message received"
+                        );
+                        serde_json::json!({"delivered": true, "signature": signature})
+                    }
+                    None => serde_json::json!({"delivered": false}),
+                };
+                self.send_processed_response(
+                    MSG_DIRECT_RESPONSE,
+                    msg_id,
+                    sender_id,
+                    response,
+                    address,
+                )
+                .await?;
+            }
             _ => debug!("Unhandled message type: {}", msg_type),
         }
         Ok(())
     }
 
+    /// Send direct message to the node, return `true` if it confirmed delivery
+    ///
+    /// Receipt must be signed by `recipient_key`, so other node on the address of the
+    /// recipient can not confirm delivery.
+    #[cfg(feature = "mailbox")]
+    pub async fn send_direct(
+        &self,
+        envelope: &MailboxEnvelope,
+        recipient_key: &[u8],
+        remote_node: &Node,
+    ) -> Result<bool, RhizomeError> {
+        let msg_id = self.generate_msg_id();
+        let (tx, rx) = oneshot::channel();
+
+        let addr: SocketAddr = format!("{}:{}", remote_node.address, remote_node.port)
+            .parse()
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+
+        let payload = serde_json::json!({ "envelope": envelope });
+        let data = self.pack_message(MSG_DIRECT, msg_id, payload, addr)?;
+        let _pending = self.register_request(msg_id, tx).await;
+        let started = self.time.instant();
+        self.send_request(&remote_node.node_id, &data, addr, MSG_DIRECT)
+            .await?;
+
+        match timeout(self.request_timeout, rx).await {
+            Ok(Ok((MSG_DIRECT_RESPONSE, payload))) => {
+                self.observe_rtt(&remote_node.node_id, started).await;
+                let signature: Vec<u8> =
+                    serde_json::from_value(payload.get("signature").cloned().unwrap_or_default())
+                        .unwrap_or_default();
+                Ok(
+                    payload.get("delivered").and_then(|v| v.as_bool()) == Some(true)
+                        && verify_signature(
+                            recipient_key,
+                            &receipt_bytes(&envelope.id),
+                            &signature,
+                        ),
+                )
+            }
+            _ => {
+                self.observe_failure(&remote_node.node_id);
+                Ok(false)
+            }
+        }
+    }
+
     /// Send response to the node
     pub async fn send_response(
         &self,
@@ -324,88 +1249,1405 @@ impl NetworkProtocol {
         payload: serde_json::Value,
         address: SocketAddr,
     ) -> Result<(), RhizomeError> {
-        let data = self.pack_message(msg_type, msg_id, payload)?;
-        self.transport.send(&data, address).await?;
+        let data = self.pack_message(msg_type, msg_id, payload, address)?;
+        self.transport
+            .send_with_priority(&data, address, MessagePriority::for_msg_type(msg_type))
+            .await?;
+        self.peer_stats.record_response_sent(address, data.len());
         Ok(())
     }
 
-    /// Serialize message
-    pub fn pack_message(
+    /// Value of STORE, sent inline or assembled from fragments of the transfer
+    ///
+    /// STORE with the size but without the value and the transfer opens the transfer.
+    /// Return `None` if there is no value to store, the sender is already answered then:
+    /// by fragments which we still miss or by refusal.
+    async fn store_value(
         &self,
-        msg_type: u8,
+        payload: &serde_json::Value,
         msg_id: [u8; 16],
-        payload: serde_json::Value,
-    ) -> Result<Vec<u8>, RhizomeError> {
-        let msg = ProtocolMessage {
-            msg_type,
-            id: msg_id,
-            node_id: self.node_id.0,
-            payload,
-            timestamp: get_now_f64(),
+        address: SocketAddr,
+    ) -> Result<Option<Vec<u8>>, RhizomeError> {
+        if let Some(value) = payload.get("value") {
+            return Ok(Some(
+                serde_json::from_value(value.clone()).unwrap_or_default(),
+            ));
+        }
+        let Some(size) = payload.get("size").and_then(|v| v.as_u64()) else {
+            return Ok(None);
+        };
+        let size = size as usize;
+        let peer = address.ip();
+        // Node which does not accept values now does not open transfers
+        let (transfer, assembly) = match payload.get("transfer") {
+            Some(transfer) => {
+                let transfer = Self::transfer_id(transfer);
+                let assembly = transfer.map(|transfer| self.fragments.finish(peer, transfer, size));
+                (transfer, assembly)
+            }
+            None if self.refuse_inbound_store().is_none() => {
+                let transfer = self.fragments.open(peer, size);
+                let count = fragment_count(size).min(MAX_REPORTED_MISSING);
+                let missing = Assembly::Missing((0..count as u32).collect());
+                (transfer, transfer.map(|_| missing))
+            }
+            None => (None, None),
         };
-        rmp_serde::to_vec(&msg).map_err(|_| RhizomeError::Network(NetworkError::General))
+        match (transfer, assembly) {
+            (_, Some(Assembly::Complete(value))) => Ok(Some(value)),
+            // Node which does not accept values now does not wait for the rest of it
+            (Some(transfer), Some(Assembly::Missing(missing)))
+                if self.refuse_inbound_store().is_none() =>
+            {
+                self.send_response(
+                    MSG_STORE_FRAGMENTS_MISSING,
+                    msg_id,
+                    serde_json::json!({"transfer": hex::encode(transfer), "missing": missing}),
+                    address,
+                )
+                .await?;
+                Ok(None)
+            }
+            _ => {
+                self.send_response(
+                    MSG_STORE_RESPONSE,
+                    msg_id,
+                    serde_json::json!({"success": false}),
+                    address,
+                )
+                .await?;
+                Ok(None)
+            }
+        }
     }
 
-    /// Get global ranking
-    pub async fn get_global_ranking_remote(
-        &self,
-        node: &Node,
-    ) -> Result<Vec<serde_json::Value>, RhizomeError> {
-        let msg_id = self.generate_msg_id();
-        let (tx, rx) = tokio::sync::oneshot::channel();
-
-        self.pending_requests.lock().await.insert(msg_id, tx);
-
-        let addr: std::net::SocketAddr = format!("{}:{}", node.address, node.port).parse().unwrap();
+    /// Save fragment of large STORE value, fragments are not answered
+    fn accept_fragment(&self, m: &ProtocolMessage, address: SocketAddr) {
+        if self.storage.is_none() || self.foreign_network(&m.payload).is_some() {
+            return;
+        }
+        let (Some(transfer), Some(data)) = (
+            m.payload.get("transfer").and_then(Self::transfer_id),
+            m.payload
+                .get("data")
+                .and_then(|v| serde_json::from_value::<Vec<u8>>(v.clone()).ok()),
+        ) else {
+            return;
+        };
+        let size = m.payload.get("size").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+        let index = m
+            .payload
+            .get("index")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(u64::MAX) as usize;
+        if !self
+            .fragments
+            .accept(address.ip(), transfer, size, index, data)
+        {
+            debug!(address = %address, index = index, "Fragment of STORE dropped");
+        }
+    }
+
+    /// Message is a round of large STORE or read which is not counted by the rate limiter
+    ///
+    /// Rounds ask for missing fragments, so STORE of transfer which we opened for the
+    /// address and answer on our round are expected. STORE which opens the transfer is
+    /// counted, the next ones are counted by the fragment rate of the address and dropped
+    /// above it. Parts of a big value are read by the ID which we gave to the address with
+    /// the first part, answers with parts are expected by our reads. Return `None` if the
+    /// message is not a round, `Some(false)` if it is dropped.
+    async fn fragment_round(&self, m: &ProtocolMessage, address: SocketAddr) -> Option<bool> {
+        let expected = match m.msg_type {
+            MSG_FIND_VALUE => {
+                let read = m.payload.get("read").and_then(Self::transfer_id)?;
+                let key: Key = serde_json::from_value(m.payload.get("key")?.clone()).ok()?;
+                self.value_reads.allow_part(address.ip(), read, &key)
+            }
+            MSG_STORE => {
+                let transfer = m.payload.get("transfer").and_then(Self::transfer_id)?;
+                return self.fragments.round(address.ip(), transfer);
+            }
+            MSG_STORE_FRAGMENTS_MISSING => self.pending_requests.lock().await.contains_key(&m.id),
+            MSG_FIND_VALUE_RESPONSE if m.payload.get("size").is_some() => {
+                self.pending_requests.lock().await.contains_key(&m.id)
+            }
+            _ => false,
+        };
+        expected.then_some(true)
+    }
+
+    /// ID of the fragmented transfer from its hex form
+    fn transfer_id(value: &serde_json::Value) -> Option<[u8; 16]> {
+        hex::decode(value.as_str()?).ok()?.try_into().ok()
+    }
+
+    /// Peer announced protocol which accepts large values in fragments
+    fn accepts_fragments(&self, node_id: &NodeID) -> bool {
+        self.peer_stats
+            .get(&node_id.0)
+            .and_then(|stats| stats.software)
+            .is_some_and(|software| software.protocol >= FRAGMENTS_SINCE_PROTOCOL)
+    }
+
+    /// Send large value in fragments, only lost fragments are sent again
+    ///
+    /// First STORE without fragments opens the transfer on the node. Every next round
+    /// pushes a window of missing fragments by one batch and asks to store the value by
+    /// the ID of the transfer, the node answers by fragments which it still misses. Window
+    /// keeps the burst within receive buffer of the node.
+    async fn store_fragmented(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+        remote_node: &Node,
+    ) -> Result<(bool, Option<StorageReceipt>), RhizomeError> {
+        let addr: SocketAddr = format!("{}:{}", remote_node.address, remote_node.port)
+            .parse()
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+        let mut payload = Self::store_payload(key, ttl, origin);
+        payload["size"] = serde_json::json!(value.len());
+
+        let fragments: Vec<&[u8]> = value.chunks(FRAGMENT_SIZE).collect();
+        // ID is given by the node, nothing is sent before it opened the transfer
+        let mut transfer: Option<serde_json::Value> = None;
+        let mut missing: Vec<usize> = Vec::new();
+        let mut window: Vec<usize> = Vec::new();
+        let mut stalled_rounds = 0;
+        let mut round_timeout = self.request_timeout;
+        let started = self.time.instant();
+
+        while stalled_rounds < MAX_STALLED_ROUNDS {
+            // Node may answer before it processed the last window, its fragments are sent
+            // again only when nothing else is missing
+            let mut next: Vec<usize> = missing
+                .iter()
+                .copied()
+                .filter(|index| !window.contains(index))
+                .take(FRAGMENT_WINDOW)
+                .collect();
+            if next.is_empty() {
+                next = missing.iter().copied().take(FRAGMENT_WINDOW).collect();
+            }
+            window = next;
+
+            let mut packets = Vec::with_capacity(window.len());
+            for &index in &window {
+                let fragment = serde_json::json!({
+                    "transfer": transfer,
+                    "size": value.len(),
+                    "index": index,
+                    "data": fragments[index]
+                });
+                let data =
+                    self.pack_message(MSG_STORE_FRAGMENT, self.generate_msg_id(), fragment, addr)?;
+                packets.push((remote_node.node_id, data, addr));
+            }
+            if !packets.is_empty() {
+                self.send_requests(packets, MSG_STORE_FRAGMENT).await?;
+            }
+
+            let msg_id = self.generate_msg_id();
+            let (tx, rx) = oneshot::channel();
+            let _pending = self.register_request(msg_id, tx).await;
+            let data = self.pack_message(MSG_STORE, msg_id, payload.clone(), addr)?;
+            let round_started = self.time.instant();
+            self.send_request(&remote_node.node_id, &data, addr, MSG_STORE)
+                .await?;
+
+            match timeout(round_timeout, rx).await {
+                Ok(Ok((MSG_STORE_RESPONSE, answer))) => {
+                    self.observe_rtt(&remote_node.node_id, started).await;
+                    return Ok(self.store_answer(key, answer).await);
+                }
+                Ok(Ok((MSG_STORE_FRAGMENTS_MISSING, answer))) => {
+                    let mut reported = serde_json::from_value::<Vec<usize>>(
+                        answer.get("missing").cloned().unwrap_or_default(),
+                    )
+                    .unwrap_or_default();
+                    reported.retain(|index| *index < fragments.len());
+                    reported.sort_unstable();
+                    reported.dedup();
+                    // Lowest missing fragments are reported, so fragment which left the
+                    // list was received
+                    let progressed = transfer.is_none()
+                        || missing
+                            .iter()
+                            .any(|index| reported.binary_search(index).is_err());
+                    if transfer.is_none() {
+                        match answer.get("transfer") {
+                            Some(id) if Self::transfer_id(id).is_some() => {
+                                payload["transfer"] = id.clone();
+                                transfer = Some(id.clone());
+                            }
+                            _ => break,
+                        }
+                    }
+                    stalled_rounds = if progressed { 0 } else { stalled_rounds + 1 };
+                    // Lost STORE is noticed soon, the node answers rounds at once
+                    round_timeout = (self.time.instant().duration_since(round_started)
+                        * ROUND_TIMEOUT_FACTOR)
+                        .max(MIN_ROUND_TIMEOUT)
+                        .min(self.request_timeout);
+                    debug!(
+                        key = %key.short_hex(),
+                        missing = reported.len(),
+                        total = fragments.len(),
+                        "Node misses fragments of STORE"
+                    );
+                    missing = reported;
+                }
+                Ok(_) => {
+                    break;
+                }
+                // STORE or its answer was lost, missing fragments are sent in the next round
+                Err(_) => {
+                    stalled_rounds += 1;
+                }
+            }
+        }
+        self.observe_failure(&remote_node.node_id);
+        Ok((false, None))
+    }
+
+    /// Send our request to the node and count it in peer statistics
+    async fn send_request(
+        &self,
+        node_id: &NodeID,
+        data: &[u8],
+        addr: SocketAddr,
+        msg_type: u8,
+    ) -> Result<(), RhizomeError> {
+        self.peer_stats.record_request_sent(&node_id.0, data.len());
+        self.transport
+            .send_with_priority(data, addr, MessagePriority::for_msg_type(msg_type))
+            .await?;
+        Ok(())
+    }
+
+    /// Send several requests of one type and count them in peer statistics
+    ///
+    /// Requests are queued together, so transport writes them by one batch
+    async fn send_requests(
+        &self,
+        requests: Vec<(NodeID, Vec<u8>, SocketAddr)>,
+        msg_type: u8,
+    ) -> Result<(), RhizomeError> {
+        let mut packets = Vec::with_capacity(requests.len());
+        for (node_id, data, addr) in requests {
+            self.peer_stats.record_request_sent(&node_id.0, data.len());
+            packets.push((data, addr));
+        }
+        self.transport
+            .send_batch(packets, MessagePriority::for_msg_type(msg_type))
+            .await?;
+        Ok(())
+    }
+
+    /// Send the same kind of request to several nodes and wait for all answers
+    ///
+    /// Answers are in the order of `requests`, `None` if node did not answer in time or
+    /// answered by other type. RTT of every node is observed when its answer comes, node
+    /// without answer is observed as failed.
+    async fn request_many(
+        &self,
+        msg_type: u8,
+        response_type: u8,
+        requests: Vec<(&Node, serde_json::Value)>,
+    ) -> Result<Vec<Option<serde_json::Value>>, RhizomeError> {
+        let mut packets = Vec::with_capacity(requests.len());
+        let mut waiting = Vec::with_capacity(requests.len());
+        for (node, payload) in requests {
+            let addr: SocketAddr = match format!("{}:{}", node.address, node.port).parse() {
+                Ok(addr) => addr,
+                Err(_) => {
+                    waiting.push(None);
+                    continue;
+                }
+            };
+            let msg_id = self.generate_msg_id();
+            let data = self.pack_message(msg_type, msg_id, payload, addr)?;
+            packets.push((node.node_id, data, addr));
+            waiting.push(Some((node.node_id, msg_id)));
+        }
+
+        // Requests are registered only when all of them are packed
+        let mut receivers = Vec::with_capacity(waiting.len());
+        {
+            let mut pending = self.pending_requests.lock().await;
+            for waiting in waiting {
+                receivers.push(waiting.map(|(node_id, msg_id)| {
+                    let (tx, rx) = oneshot::channel();
+                    pending.insert(msg_id, tx);
+                    let guard = PendingRequest::new(&self.pending_requests, msg_id);
+                    (node_id, guard, rx)
+                }));
+            }
+        }
+        let started = self.time.instant();
+        self.send_requests(packets, msg_type).await?;
+
+        let answers = futures::future::join_all(receivers.into_iter().map(|waiting| async move {
+            let (node_id, _pending, rx) = waiting?;
+            match timeout(self.request_timeout, rx).await {
+                Ok(Ok((msg_type, payload))) if msg_type == response_type => {
+                    self.observe_rtt(&node_id, started).await;
+                    Some(payload)
+                }
+                _ => {
+                    self.observe_failure(&node_id);
+                    None
+                }
+            }
+        }))
+        .await;
+        Ok(answers)
+    }
+
+    /// Answer to the compare-and-swap with its outcome
+    fn cas_response(outcome: &CasOutcome) -> serde_json::Value {
+        match outcome {
+            CasOutcome::Applied => serde_json::json!({"success": true}),
+            CasOutcome::Conflict(current) => {
+                serde_json::json!({"success": false, "current": current})
+            }
+        }
+    }
+
+    /// Send answer to request which changes our state and remember it
+    ///
+    /// Retransmitted request with the same id gets the same answer without processing
+    pub async fn send_processed_response(
+        &self,
+        msg_type: u8,
+        msg_id: [u8; 16],
+        sender_id: [u8; 20],
+        payload: serde_json::Value,
+        address: SocketAddr,
+    ) -> Result<(), RhizomeError> {
+        if let Some(storage) = &self.storage {
+            let response = serde_json::to_vec(&payload)
+                .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+            storage
+                .record_processed(Self::request_key(&sender_id, &msg_id), msg_type, response)
+                .await?;
+        }
+        self.send_response(msg_type, msg_id, payload, address).await
+    }
+
+    /// Send remembered answer if request was already processed
+    ///
+    /// Return `true` if answer was found
+    async fn resend_processed_response(
+        &self,
+        sender_id: &[u8; 20],
+        msg_id: [u8; 16],
+        address: SocketAddr,
+    ) -> bool {
+        let storage = match &self.storage {
+            Some(s) => s,
+            None => return false,
+        };
+
+        let processed = match storage
+            .processed_response(Self::request_key(sender_id, &msg_id))
+            .await
+        {
+            Ok(Some(p)) => p,
+            _ => return false,
+        };
+
+        let payload: serde_json::Value = match serde_json::from_slice(&processed.response) {
+            Ok(p) => p,
+            Err(_) => return false,
+        };
+        if let Err(e) = self
+            .send_response(processed.response_type, msg_id, payload, address)
+            .await
+        {
+            debug!(error = %e, "Failed to resend cached answer");
+        }
+        true
+    }
+
+    /// Write security event about the peer
+    fn audit(
+        &self,
+        kind: AuditEventKind,
+        peer_id: &[u8],
+        address: SocketAddr,
+        details: impl Into<String>,
+    ) {
+        if let Some(audit) = &self.audit {
+            audit.record(kind, Some(peer_id), Some(address.to_string()), details);
+        }
+    }
+
+    /// Peer rejected our copy because the value was deleted, delete our copy too
+    ///
+    /// Only tombstone signed by the publisher is applied, the peer itself can not delete
+    /// our copy. Storage keeps our copy if it was published after deletion or by other
+    /// publisher.
+    async fn accept_remote_tombstone(&self, key: &Key, tombstone: &serde_json::Value) {
+        let storage = match &self.storage {
+            Some(s) => s,
+            None => return,
+        };
+        let Some((publisher, proof)) = self.proven_publisher(tombstone, key, None, "") else {
+            return;
+        };
+        let deleted_at = self
+            .clock
+            .to_local(&publisher, proof.signed_at)
+            .min(self.time.now());
+        let ttl = tombstone.get("ttl").and_then(|v| v.as_i64()).unwrap_or(0);
+        let ttl = ttl.clamp(0, MAX_TOMBSTONE_TTL) as i32;
+        let origin = StoreOrigin {
+            publisher,
+            published_at: deleted_at,
+            hops: 1,
+            record_type: String::new(),
+            proof: Some(proof),
+        };
+        if let Ok(true) = storage.tombstone(key.clone(), ttl, origin).await {
+            debug!(key = %key.short_hex(), "Value deleted by tombstone of peer");
+        }
+    }
+
+    /// Attach our signature to the origin of a record published by our node
+    ///
+    /// Origins of other publishers and already signed ones are not changed. `value` is
+    /// None for deletion of the key.
+    #[cfg(feature = "signatures")]
+    pub fn sign_origin(&self, key: &Key, value: Option<&[u8]>, origin: &mut StoreOrigin) {
+        if origin.proof.is_none()
+            && origin.publisher == self.node_id.0
+            && let Some(signer) = self
+                .receipt_signer
+                .as_ref()
+                .filter(|signer| signer.is_bound_to_node_id())
+        {
+            origin.proof = Some(PublisherProof::issue(
+                signer,
+                key,
+                value,
+                origin.published_at,
+                &origin.record_type,
+            ));
+        }
+    }
+
+    #[cfg(not(feature = "signatures"))]
+    pub fn sign_origin(&self, _key: &Key, _value: Option<&[u8]>, _origin: &mut StoreOrigin) {}
+
+    /// Publisher and its proof from the payload if the proof is valid for the record
+    #[cfg(feature = "signatures")]
+    fn proven_publisher(
+        &self,
+        payload: &serde_json::Value,
+        key: &Key,
+        value: Option<&[u8]>,
+        record_type: &str,
+    ) -> Option<(Vec<u8>, PublisherProof)> {
+        let publisher: Vec<u8> = serde_json::from_value(payload.get("publisher")?.clone()).ok()?;
+        let proof: PublisherProof = serde_json::from_value(payload.get("proof")?.clone()).ok()?;
+        proof
+            .verify(&publisher, key, value, record_type)
+            .then_some((publisher, proof))
+    }
+
+    #[cfg(not(feature = "signatures"))]
+    fn proven_publisher(
+        &self,
+        _payload: &serde_json::Value,
+        _key: &Key,
+        _value: Option<&[u8]>,
+        _record_type: &str,
+    ) -> Option<(Vec<u8>, PublisherProof)> {
+        None
+    }
+
+    /// Error for data of other nodes if we do not accept it now
+    ///
+    /// Node does not accept data in read-only mode, during warm-up and when disk is almost
+    /// full
+    fn refuse_inbound_store(&self) -> Option<StorageError> {
+        if self.mode.is_read_only() {
+            Some(StorageError::ReadOnly)
+        } else if self.mode.is_warming_up() {
+            Some(StorageError::WarmingUp)
+        } else if self
+            .disk_watchdog
+            .as_ref()
+            .is_some_and(|disk| disk.is_degraded())
+        {
+            Some(StorageError::StorageFull)
+        } else {
+            None
+        }
+    }
+
+    /// Records of the store are refused by the blacklist
+    ///
+    /// Besides the sender, only the publisher proven by its signature is checked.
+    fn blocks_origin(&self, origin: &StoreOrigin, sender_id: &[u8]) -> bool {
+        self.blacklist.blocks_publisher(sender_id)
+            || (origin.proof.is_some() && self.blacklist.blocks_publisher(&origin.publisher))
+    }
+
+    /// Blocklist is a valid list stored under the key of its author
+    ///
+    /// Decided by the values, not by the record type the sender gives. Stored list is
+    /// replaced only by a newer list of the same author _(or the same list again)_, so an
+    /// old copy can not roll it back and other values can not overwrite it.
+    #[cfg(feature = "signatures")]
+    async fn blocklist_accepted(
+        &self,
+        storage: &Storage,
+        key: &Key,
+        value: &[u8],
+        origin: &StoreOrigin,
+    ) -> bool {
+        let incoming = SignedBlocklist::from_bytes(value);
+        let stored = match storage.get(key.clone()).await {
+            Ok(stored) => stored,
+            Err(_) => return false,
+        };
+        let current = stored
+            .as_deref()
+            .and_then(SignedBlocklist::from_bytes)
+            .filter(|list| self.is_blocklist_of_author(key, list));
+        if let Some(current) = current {
+            return incoming.is_some_and(|signed| {
+                signed.signer_id == current.signer_id
+                    && (signed.list.created_at > current.list.created_at
+                        || stored.as_deref() == Some(value))
+                    && self.is_blocklist_of_author(key, &signed)
+            });
+        }
+        match incoming {
+            Some(signed) => self.is_blocklist_of_author(key, &signed),
+            None => origin.record_type != RECORD_BLOCKLIST,
+        }
+    }
+
+    /// List is signed by its author and `key` is the blocklist key of the author
+    #[cfg(feature = "signatures")]
+    fn is_blocklist_of_author(&self, key: &Key, signed: &SignedBlocklist) -> bool {
+        let author_key = KeyManager::for_network(&self.network_id)
+            .get_blocklist_key(&hex::encode(&signed.signer_id));
+        author_key == *key && signed.verify()
+    }
+
+    #[cfg(not(feature = "signatures"))]
+    async fn blocklist_accepted(
+        &self,
+        _storage: &Storage,
+        _key: &Key,
+        _value: &[u8],
+        _origin: &StoreOrigin,
+    ) -> bool {
+        true
+    }
+
+    /// Content policy plugins accept the value of other node
+    #[cfg(feature = "wasm-plugins")]
+    async fn policy_accepts(&self, key: &Key, value: &[u8]) -> bool {
+        let Some(policy) = self.content_policy.clone() else {
+            return true;
+        };
+        let (key, value) = (key.to_vec(), value.to_vec());
+        // Plugins are CPU-bound, fuel limits their time
+        let decision = tokio::task::spawn_blocking(move || policy.check(&key, &value)).await;
+        matches!(decision, Ok(PolicyDecision::Accept { .. }))
+    }
+
+    #[cfg(not(feature = "wasm-plugins"))]
+    async fn policy_accepts(&self, _key: &Key, _value: &[u8]) -> bool {
+        true
+    }
+
+    /// Contact of the node in FIND_NODE and FIND_VALUE answers
+    fn contact_json(node: &Node) -> serde_json::Value {
+        let mut contact = serde_json::json!({
+            "node_id": node.node_id,
+            "address": node.address,
+            "port": node.port
+        });
+        if !node.endpoints.is_empty() {
+            contact["endpoints"] = serde_json::json!(node.endpoints);
+        }
+        contact
+    }
+
+    /// Our address for peers: majority-observed external address or the bound one
+    pub async fn advertised_address(&self) -> SocketAddr {
+        match self.external_address.get() {
+            Some(addr) => addr,
+            None => self.transport.get_address().await,
+        }
+    }
+
+    /// Configured endpoints with the observed external address as WAN endpoint
+    fn advertised_endpoints(&self) -> Vec<Endpoint> {
+        let mut endpoints = self.endpoints.clone();
+        if let Some(external) = self.external_address.get()
+            && !endpoints.iter().any(|e| e.socket_addr() == Some(external))
+            && endpoints.len() < MAX_ENDPOINTS
+        {
+            endpoints.push(Endpoint {
+                kind: EndpointKind::Wan,
+                address: external.ip().to_string(),
+                port: external.port(),
+                priority: 0,
+            });
+        }
+        endpoints
+    }
+
+    /// One FIND_VALUE request, return the value or holders which the node points to
+    async fn request_value(
+        &self,
+        key: &Key,
+        remote_node: &Node,
+    ) -> Result<(Option<VersionedValue>, Vec<Node>), RhizomeError> {
+        match self.request_value_part(key, 0, None, remote_node).await? {
+            Some(payload) => {
+                if payload
+                    .get("found")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+                {
+                    let Some(value) = self.read_value_parts(key, &payload, remote_node).await?
+                    else {
+                        return Ok((None, Vec::new()));
+                    };
+                    let peer_id = &remote_node.node_id.0;
+                    let stored_at = payload
+                        .get("stored_at")
+                        .and_then(|v| v.as_f64())
+                        .filter(|t| *t > 0.0)
+                        .map(|t| self.clock.to_local(peer_id, t))
+                        .unwrap_or(0.0);
+                    let expires_at = payload
+                        .get("expires_at")
+                        .and_then(|v| v.as_f64())
+                        .map(|t| self.clock.to_local(peer_id, t));
+                    let record_type = payload
+                        .get("record_type")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    // Publish time of the answering node is only its claim, the time
+                    // signed by the publisher is compared
+                    let (publisher, proof) =
+                        match self.proven_publisher(&payload, key, Some(&value), &record_type) {
+                            Some((publisher, proof)) => (publisher, Some(proof)),
+                            None => (
+                                payload
+                                    .get("publisher")
+                                    .and_then(|v| serde_json::from_value(v.clone()).ok())
+                                    .unwrap_or_default(),
+                                None,
+                            ),
+                        };
+                    Ok((
+                        Some(VersionedValue {
+                            value,
+                            stored_at,
+                            published_at: proof.as_ref().map_or(0.0, |p| p.signed_at),
+                            expires_at,
+                            publisher,
+                            record_type,
+                            proof,
+                            holder: None,
+                        }),
+                        Vec::new(),
+                    ))
+                } else {
+                    Ok((None, Self::parse_contacts(payload.get("holders"))))
+                }
+            }
+            None => Ok((None, Vec::new())),
+        }
+    }
+
+    /// Answer on FIND_VALUE for the part of the value from `offset` _(None on failure)_
+    ///
+    /// `read` is the ID of the read which the node gave with the first part.
+    async fn request_value_part(
+        &self,
+        key: &Key,
+        offset: usize,
+        read: Option<&serde_json::Value>,
+        remote_node: &Node,
+    ) -> Result<Option<serde_json::Value>, RhizomeError> {
+        let addr: SocketAddr = format!("{}:{}", remote_node.address, remote_node.port)
+            .parse()
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+        let msg_id = self.generate_msg_id();
+        let (tx, rx) = oneshot::channel();
+        let _pending = self.register_request(msg_id, tx).await;
+
+        let mut payload = serde_json::json!({"key": key, "offset": offset});
+        if let Some(read) = read {
+            payload["read"] = read.clone();
+        }
+        let data = self.pack_message(MSG_FIND_VALUE, msg_id, payload, addr)?;
+        let started = self.time.instant();
+        self.send_request(&remote_node.node_id, &data, addr, MSG_FIND_VALUE)
+            .await?;
+
+        match timeout(self.request_timeout, rx).await {
+            Ok(Ok((MSG_FIND_VALUE_RESPONSE, payload))) => {
+                self.observe_rtt(&remote_node.node_id, started).await;
+                Ok(Some(payload))
+            }
+            _ => {
+                self.observe_failure(&remote_node.node_id);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whole value from the first answer, the rest of a big value is read by parts
+    ///
+    /// Parts must be of the same stored copy, so value replaced during reading is not
+    /// glued from two versions.
+    async fn read_value_parts(
+        &self,
+        key: &Key,
+        first: &serde_json::Value,
+        remote_node: &Node,
+    ) -> Result<Option<Vec<u8>>, RhizomeError> {
+        let part = |payload: &serde_json::Value| -> Vec<u8> {
+            serde_json::from_value(payload.get("value").cloned().unwrap_or_default())
+                .unwrap_or_default()
+        };
+        let mut value = part(first);
+        let Some(size) = first.get("size").and_then(|v| v.as_u64()) else {
+            return Ok(Some(value));
+        };
+        let size = size as usize;
+        if size > MAX_FRAGMENTED_VALUE_SIZE {
+            return Ok(None);
+        }
+
+        while value.len() < size {
+            let Some(next) = self
+                .request_value_part(key, value.len(), first.get("read"), remote_node)
+                .await?
+            else {
+                return Ok(None);
+            };
+            let next_part = part(&next);
+            if next.get("stored_at") != first.get("stored_at")
+                || next.get("size") != first.get("size")
+                || next_part.is_empty()
+            {
+                return Ok(None);
+            }
+            value.extend(next_part);
+        }
+        Ok((value.len() == size).then_some(value))
+    }
+
+    /// Contacts made by [`NetworkProtocol::contact_json`], broken ones are skipped
+    fn parse_contacts(list: Option<&serde_json::Value>) -> Vec<Node> {
+        let Some(list) = list.and_then(|v| v.as_array()) else {
+            return Vec::new();
+        };
+        list.iter()
+            .filter_map(|n_val| {
+                let id = n_val
+                    .get("node_id")
+                    .and_then(|v| NodeID::deserialize(v).ok())?;
+                let addr = n_val.get("address").and_then(|v| v.as_str())?;
+                let port = n_val.get("port").and_then(|v| v.as_u64())?;
+                let mut node = Node::new(id, addr.to_string(), port as u16);
+                node.endpoints = Self::parse_endpoints(n_val).unwrap_or_default();
+                Some(node)
+            })
+            .collect()
+    }
+
+    /// Endpoints advertised in the payload _(None if the peer sent none)_
+    fn parse_endpoints(payload: &serde_json::Value) -> Option<Vec<Endpoint>> {
+        let mut endpoints: Vec<Endpoint> =
+            serde_json::from_value(payload.get("endpoints")?.clone()).ok()?;
+        endpoints.truncate(MAX_ENDPOINTS);
+        Some(endpoints)
+    }
+
+    /// Sender of the message is warming up and should not be given to other peers yet
+    fn peer_is_warming_up(payload: &serde_json::Value) -> bool {
+        payload
+            .get("warming_up")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Network of the sender if it is not ours _(no `network` field - main network)_
+    fn foreign_network(&self, payload: &serde_json::Value) -> Option<String> {
+        let network = payload
+            .get("network")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        (network != self.network_id).then(|| network.to_string())
+    }
+
+    /// Drop message of other network, the sender never gets to the routing table
+    ///
+    /// PING is answered with bare PONG, so the peer sees our network and drops us too.
+    /// PONG is passed to the waiting ping, which fails at once instead of timeout.
+    async fn reject_foreign_network(&self, m: ProtocolMessage, address: SocketAddr, network: &str) {
+        debug!(address = %address, network, "Message of other network");
+        self.audit(
+            AuditEventKind::NetworkMismatch,
+            &m.node_id,
+            address,
+            format!("network {:?}, ours {:?}", network, self.network_id),
+        );
+        match m.msg_type {
+            MSG_PING => {
+                let payload = serde_json::json!({ "node_id": self.node_id });
+                let _ = self.send_response(MSG_PONG, m.id, payload, address).await;
+            }
+            MSG_PONG => {
+                if let Some(sender) = self.pending_requests.lock().await.remove(&m.id) {
+                    let _ = sender.send((m.msg_type, m.payload));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Key of the request in dedup store
+    fn request_key(sender_id: &[u8; 20], msg_id: &[u8; 16]) -> Vec<u8> {
+        [sender_id.as_slice(), msg_id.as_slice()].concat()
+    }
+
+    /// Serialize message in the format agreed with the peer on `address`
+    ///
+    /// During warm-up every message is marked, so peers do not add us to routing tables.
+    /// Messages of other than the main network carry its ID.
+    pub fn pack_message(
+        &self,
+        msg_type: u8,
+        msg_id: [u8; 16],
+        mut payload: serde_json::Value,
+        address: SocketAddr,
+    ) -> Result<Vec<u8>, RhizomeError> {
+        if !self.network_id.is_empty()
+            && let Some(fields) = payload.as_object_mut()
+        {
+            fields.insert(
+                "network".to_string(),
+                serde_json::Value::String(self.network_id.clone()),
+            );
+        }
+        if self.mode.is_warming_up()
+            && let Some(fields) = payload.as_object_mut()
+        {
+            fields.insert("warming_up".to_string(), serde_json::Value::Bool(true));
+        }
+        let msg = ProtocolMessage {
+            msg_type,
+            id: msg_id,
+            node_id: self.node_id.0,
+            payload,
+            timestamp: self.time.now(),
+        };
+        self.codec_for(address)
+            .encode(&msg)
+            .map_err(|_| RhizomeError::Network(NetworkError::General))
+    }
+
+    /// Codec of messages to the peer
+    fn codec_for(&self, address: SocketAddr) -> &'static dyn WireCodec {
+        let format = self
+            .peer_formats
+            .lock()
+            .unwrap()
+            .get(&address)
+            .copied()
+            .unwrap_or_default();
+        serialization::codec(format).unwrap_or(&serialization::MsgpackCodec)
+    }
+
+    /// Choose format for the peer by its advertised `codecs`
+    ///
+    /// Our preferred format is used if the peer supports it, otherwise msgpack. Peers
+    /// which advertise nothing are old nodes and get msgpack.
+    fn negotiate_format(&self, address: SocketAddr, payload: &serde_json::Value) {
+        let codecs: Vec<WireFormat> = payload
+            .get("codecs")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default();
+        let format = if codecs.contains(&self.wire_format) && self.wire_format.is_supported() {
+            self.wire_format
+        } else {
+            WireFormat::Msgpack
+        };
+
+        let mut formats = self.peer_formats.lock().unwrap();
+        if format == WireFormat::Msgpack {
+            formats.remove(&address);
+            return;
+        }
+        // Peer whose format is forgotten gets msgpack until its next PING
+        if formats.len() >= MAX_TRACKED_PEERS
+            && !formats.contains_key(&address)
+            && let Some(forgotten) = formats.keys().next().copied()
+        {
+            formats.remove(&forgotten);
+        }
+        if formats.insert(address, format) != Some(format) {
+            debug!(address = %address, format = %format, "Wire format agreed with peer");
+        }
+    }
+
+    /// Remember software announced in PING or PONG
+    ///
+    /// Incompatible peer is reported once, until it announces other software.
+    fn observe_software(&self, peer_id: &[u8], address: SocketAddr, payload: &serde_json::Value) {
+        let Some(software) = PeerSoftware::from_payload(payload) else {
+            return;
+        };
+        let compatible = software.is_compatible();
+        let version = software.version.clone();
+        let protocol = software.protocol;
+        if self.peer_stats.record_software(peer_id, software) && !compatible {
+            warn!(
+                address = %address,
+                version = %version,
+                protocol,
+                ours = PROTOCOL_VERSION,
+                "Peer speaks incompatible protocol version"
+            );
+        }
+    }
+
+    /// Get global ranking
+    pub async fn get_global_ranking_remote(
+        &self,
+        node: &Node,
+    ) -> Result<Vec<serde_json::Value>, RhizomeError> {
+        let msg_id = self.generate_msg_id();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        let _pending = self.register_request(msg_id, tx).await;
+
+        let addr: std::net::SocketAddr = format!("{}:{}", node.address, node.port).parse().unwrap();
 
         let payload = serde_json::json!({});
-        let data = self.pack_message(MSG_GLOBAL_RANKING_REQUEST, msg_id, payload)?;
-        self.transport.send(&data, addr).await?;
+        let data = self.pack_message(MSG_GLOBAL_RANKING_REQUEST, msg_id, payload, addr)?;
+        self.send_request(&node.node_id, &data, addr, MSG_GLOBAL_RANKING_REQUEST)
+            .await?;
+
+        match tokio::time::timeout(self.request_timeout, rx).await {
+            Ok(Ok((msg_type, response_payload))) => {
+                if msg_type == MSG_GLOBAL_RANKING_RESPONSE {
+                    return Ok(response_payload["ranking"]
+                        .as_array()
+                        .cloned()
+                        .unwrap_or_default());
+                }
+                Err(RhizomeError::Network(NetworkError::General))
+            }
+            _ => Err(RhizomeError::Network(NetworkError::General)),
+        }
+    }
+
+    /// Send our top items to the node and get its top items back
+    pub async fn exchange_popularity_remote(
+        &self,
+        node: &Node,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, RhizomeError> {
+        let msg_id = self.generate_msg_id();
+        let (tx, rx) = oneshot::channel();
+
+        let _pending = self.register_request(msg_id, tx).await;
+
+        let addr: SocketAddr = format!("{}:{}", node.address, node.port)
+            .parse()
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+
+        let data = self.pack_message(MSG_POPULARITY_EXCHANGE, msg_id, payload, addr)?;
+        self.send_request(&node.node_id, &data, addr, MSG_POPULARITY_EXCHANGE)
+            .await?;
+
+        match timeout(self.request_timeout, rx).await {
+            Ok(Ok((msg_type, response_payload)))
+                if msg_type == MSG_POPULARITY_EXCHANGE_RESPONSE =>
+            {
+                Ok(response_payload)
+            }
+            _ => Err(RhizomeError::Network(NetworkError::General)),
+        }
+    }
+
+    /// Exchange top items with several nodes by one batch of requests
+    ///
+    /// Answers are in the order of `nodes`, `None` for node which did not answer
+    pub async fn exchange_popularity_many(
+        &self,
+        nodes: &[Node],
+        payload: serde_json::Value,
+    ) -> Result<Vec<Option<serde_json::Value>>, RhizomeError> {
+        let requests = nodes.iter().map(|node| (node, payload.clone())).collect();
+        self.request_many(
+            MSG_POPULARITY_EXCHANGE,
+            MSG_POPULARITY_EXCHANGE_RESPONSE,
+            requests,
+        )
+        .await
+    }
+
+    /// Payload of STORE request without the value
+    fn store_payload(key: &Key, ttl: i32, origin: &StoreOrigin) -> serde_json::Value {
+        serde_json::json!({
+            "key": key,
+            "ttl": ttl,
+            "publisher": origin.publisher,
+            "published_at": origin.published_at,
+            "hops": origin.hops,
+            "record_type": origin.record_type,
+            "proof": origin.proof
+        })
+    }
+
+    /// Read answer to STORE, delete our copy if the node answered by tombstone
+    async fn store_answer(
+        &self,
+        key: &Key,
+        payload: serde_json::Value,
+    ) -> (bool, Option<StorageReceipt>) {
+        let success = payload
+            .get("success")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let receipt = payload
+            .get("receipt")
+            .and_then(|v| serde_json::from_value::<StorageReceipt>(v.clone()).ok());
+        if let Some(tombstone) = payload.get("tombstone").filter(|t| !t.is_null()) {
+            self.accept_remote_tombstone(key, tombstone).await;
+        }
+        (success, receipt)
+    }
 
-        match tokio::time::timeout(self.request_timeout, rx).await {
-            Ok(Ok((msg_type, response_payload))) => {
-                if msg_type == MSG_GLOBAL_RANKING_RESPONSE {
-                    return Ok(response_payload["ranking"]
-                        .as_array()
-                        .cloned()
-                        .unwrap_or_default());
-                }
-                Err(RhizomeError::Network(NetworkError::General))
+    /// Create new gossip message and push it to random peers
+    pub async fn gossip_broadcast(
+        &self,
+        topic: GossipTopic,
+        data: serde_json::Value,
+    ) -> GossipMessage {
+        let message = self.gossip.create(topic, self.node_id.0.to_vec(), data);
+        self.gossip_spread(vec![message.clone()]).await;
+        message
+    }
+
+    /// Push messages to `fanout` random peers with decreased TTL
+    async fn gossip_spread(&self, messages: Vec<GossipMessage>) {
+        let messages: Vec<GossipMessage> = messages
+            .into_iter()
+            .filter(|m| m.ttl > 0)
+            .map(|mut m| {
+                m.ttl -= 1;
+                m
+            })
+            .collect();
+        if messages.is_empty() {
+            return;
+        }
+
+        let peers = match &self.routing_table {
+            Some(rt) => self.gossip.select_peers(rt.read().await.get_all_nodes()),
+            None => return,
+        };
+
+        for node in peers {
+            if let Err(e) = self.gossip_push_remote(&node, &messages).await {
+                debug!(error = %e, "Gossip push failed");
             }
-            _ => {
-                self.pending_requests.lock().await.remove(&msg_id);
-                Err(RhizomeError::Network(NetworkError::General))
+        }
+    }
+
+    /// Send messages to the node without waiting for answer
+    async fn gossip_push_remote(
+        &self,
+        node: &Node,
+        messages: &[GossipMessage],
+    ) -> Result<(), RhizomeError> {
+        let addr: SocketAddr = format!("{}:{}", node.address, node.port)
+            .parse()
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+
+        let payload = serde_json::json!({"messages": messages});
+        let data = self.pack_message(MSG_GOSSIP_PUSH, self.generate_msg_id(), payload, addr)?;
+        self.send_request(&node.node_id, &data, addr, MSG_GOSSIP_PUSH)
+            .await?;
+        Ok(())
+    }
+
+    /// Anti-entropy round with the node
+    ///
+    /// Send our digest, accept messages which we missed and push messages which node wants.
+    /// Return count of new messages
+    pub async fn gossip_pull_remote(&self, node: &Node) -> Result<usize, RhizomeError> {
+        let msg_id = self.generate_msg_id();
+        let (tx, rx) = oneshot::channel();
+
+        let _pending = self.register_request(msg_id, tx).await;
+
+        let addr: SocketAddr = format!("{}:{}", node.address, node.port)
+            .parse()
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+
+        let payload = serde_json::json!({"digest": self.gossip.digest()});
+        let data = self.pack_message(MSG_GOSSIP_DIGEST, msg_id, payload, addr)?;
+        self.send_request(&node.node_id, &data, addr, MSG_GOSSIP_DIGEST)
+            .await?;
+
+        match timeout(self.request_timeout, rx).await {
+            Ok(Ok((msg_type, response_payload))) if msg_type == MSG_GOSSIP_DIGEST_RESPONSE => {
+                let fresh = Self::parse_gossip_messages(&response_payload)
+                    .into_iter()
+                    .filter(|m| self.gossip.accept(m.clone()))
+                    .count();
+
+                let want: Vec<String> = serde_json::from_value(
+                    response_payload.get("want").cloned().unwrap_or_default(),
+                )
+                .unwrap_or_default();
+                let wanted = self.gossip.get_many(&want);
+                if !wanted.is_empty() {
+                    self.gossip_push_remote(node, &wanted).await?;
+                }
+
+                Ok(fresh)
             }
+            _ => Err(RhizomeError::Network(NetworkError::General)),
         }
     }
 
-    /// Generate uniq message id
-    pub fn generate_msg_id(&self) -> [u8; 16] {
-        rand::thread_rng().r#gen()
+    /// Parse hex SHA-256 hash from message
+    fn parse_hash(hex_hash: &str) -> Result<[u8; 32], RhizomeError> {
+        hex::decode(hex_hash)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(RhizomeError::Network(NetworkError::General))
     }
-}
 
-#[async_trait]
-impl NetworkProtocolTrait for NetworkProtocol {
-    async fn ping(&self, node: &Node) -> bool {
+    /// Key of the request does not fit storage, see [`MAX_KEY_SIZE`]
+    fn has_oversized_key(payload: &serde_json::Value) -> bool {
+        let too_long = |key: &serde_json::Value| {
+            key.as_array()
+                .is_some_and(|bytes| bytes.len() > MAX_KEY_SIZE)
+        };
+        payload.get("key").is_some_and(too_long)
+            || payload
+                .get("keys")
+                .and_then(|keys| keys.as_array())
+                .is_some_and(|keys| keys.iter().any(too_long))
+    }
+
+    fn parse_gossip_messages(payload: &serde_json::Value) -> Vec<GossipMessage> {
+        let mut messages: Vec<GossipMessage> =
+            serde_json::from_value(payload.get("messages").cloned().unwrap_or_default())
+                .unwrap_or_default();
+        messages.truncate(MAX_GOSSIP_BATCH);
+        messages
+    }
+
+    /// Send PING to the address, return PONG payload and RTT in seconds
+    async fn ping_addr(
+        &self,
+        node_id: &NodeID,
+        addr: SocketAddr,
+    ) -> Option<(serde_json::Value, f64)> {
         let msg_id = self.generate_msg_id();
         let (tx, rx) = oneshot::channel();
 
-        self.pending_requests.lock().await.insert(msg_id, tx);
+        let mut payload = serde_json::json!({
+            "node_id": self.node_id,
+            "codecs": WireFormat::supported(),
+            "software": PeerSoftware::local()
+        });
+        let endpoints = self.advertised_endpoints();
+        if !endpoints.is_empty() {
+            payload["endpoints"] = serde_json::json!(endpoints);
+        }
+        let data = self.pack_message(MSG_PING, msg_id, payload, addr).ok()?;
+
+        let _pending = self.register_request(msg_id, tx).await;
+        let started = self.time.instant();
+        let _ = self.send_request(node_id, &data, addr, MSG_PING).await;
+
+        match timeout(self.ping_timeout, rx).await {
+            Ok(Ok((MSG_PONG, response_payload))) => Some((
+                response_payload,
+                self.time.instant().duration_since(started).as_secs_f64(),
+            )),
+            Ok(Ok(_)) => None,
+            _ => None,
+        }
+    }
+
+    /// Ping the node and learn its real identity from PONG
+    ///
+    /// Node in routing table is refreshed. If we knew the node under other ID _(exm.
+    /// bootstrap node with zero ID)_ the placeholder is replaced with the real node.
+    /// If the active address does not answer, other advertised endpoints are tried by
+    /// priority and the first answered one becomes active.
+    /// Return node as it answered or `None` if it is not reachable.
+    pub async fn ping_remote(&self, node: &Node) -> Option<Node> {
+        let active: Option<SocketAddr> = format!("{}:{}", node.address, node.port).parse().ok();
+        let mut answer = None;
+        for addr in active.into_iter().chain(node.fallback_addrs()) {
+            if let Some((response, rtt)) = self.ping_addr(&node.node_id, addr).await {
+                answer = Some((addr, response, rtt));
+                break;
+            }
+        }
+        let Some((addr, response, rtt)) = answer else {
+            self.observe_failure(&node.node_id);
+            return None;
+        };
+        if let Some(network) = self.foreign_network(&response) {
+            warn!(
+                address = %addr,
+                network = %network,
+                ours = %self.network_id,
+                "Peer belongs to other network"
+            );
+            return None;
+        }
+        self.negotiate_format(addr, &response);
 
-        let addr: SocketAddr = format!("{}:{}", node.address, node.port).parse().unwrap();
-        let payload = serde_json::json!({"node_id": self.node_id.0});
+        let learned_id = response
+            .get("node_id")
+            .and_then(|v| NodeID::deserialize(v).ok())
+            .unwrap_or(node.node_id);
+        self.observe_software(&learned_id.0, addr, &response);
 
-        if let Ok(data) = self.pack_message(MSG_PING, msg_id, payload) {
-            let _ = self.transport.send(&data, addr).await;
+        let mut learned = node.clone();
+        learned.node_id = learned_id;
+        if Some(addr) != active {
+            debug!(
+                node_id = %hex::encode(&learned_id.0[..8]),
+                address = %addr,
+                "Switched to fallback endpoint"
+            );
+            learned.address = addr.ip().to_string();
+            learned.port = addr.port();
+        }
+        if let Some(endpoints) = Self::parse_endpoints(&response) {
+            learned.endpoints = endpoints;
+        }
+        learned.update_seen(self.time.now());
+        if let Some(observed) = response
+            .get("observed")
+            .and_then(|v| v.as_str())
+            .and_then(|v| v.parse().ok())
+        {
+            self.external_address.record(addr.ip(), observed);
+        }
+        self.reputation.record_response(&learned_id.0, rtt);
+        self.peer_stats.record_rtt(&learned_id.0, rtt);
 
-            if let Ok(Ok((msg_type, _))) = timeout(self.request_timeout, rx).await {
-                return msg_type == MSG_PONG;
+        if let Some(rt) = &self.routing_table
+            && learned_id != self.node_id
+            && !Self::peer_is_warming_up(&response)
+        {
+            let mut rt = rt.write().await;
+            if learned_id != node.node_id {
+                debug!(
+                    address = %addr,
+                    node_id = %hex::encode(&learned_id.0[..8]),
+                    "Learned node ID from PONG"
+                );
+                rt.remove_node(&node.node_id);
             }
+            rt.add_node(learned.clone());
+            rt.record_rtt(&learned_id, rtt);
+        }
+
+        Some(learned)
+    }
+
+    /// Liveness piggybacking
+    ///
+    /// Every message proves that sender is alive, so known node is refreshed and unknown
+    /// one is added to routing table _(unless it is warming up)_. Thanks to this we ping
+    /// only really idle peers. Time of sending is a sample of the sender clock offset.
+    async fn observe_sender(
+        &self,
+        sender_id: &[u8; 20],
+        sent_at: f64,
+        address: SocketAddr,
+        warming_up: bool,
+    ) {
+        let rt = match &self.routing_table {
+            Some(rt) => rt,
+            None => return,
+        };
+
+        let node_id = NodeID::new(*sender_id);
+        if node_id == self.node_id || sender_id.iter().all(|b| *b == 0) {
+            return;
+        }
+
+        let mut rt = rt.write().await;
+        if !rt.touch(&node_id) && !warming_up {
+            rt.add_node(Node::new(node_id, address.ip().to_string(), address.port()));
+        }
+        let rtt = rt.rtt_of(&node_id);
+        drop(rt);
+
+        self.clock.record(sender_id, sent_at, self.time.now(), rtt);
+    }
+
+    /// Save round-trip time of the answered request in routing table
+    async fn observe_rtt(&self, node_id: &NodeID, started: Instant) {
+        let rtt = self.time.instant().duration_since(started).as_secs_f64();
+        self.reputation.record_response(&node_id.0, rtt);
+        self.peer_stats.record_rtt(&node_id.0, rtt);
+        if let Some(rt) = &self.routing_table {
+            rt.write().await.record_rtt(node_id, rtt);
         }
+    }
+
+    /// Remember that the node did not answer our request
+    fn observe_failure(&self, node_id: &NodeID) {
+        self.reputation.record_failure(&node_id.0);
+        self.peer_stats.record_failure(&node_id.0);
+    }
+
+    /// Generate uniq message id
+    pub fn generate_msg_id(&self) -> [u8; 16] {
+        let mut msg_id = [0u8; 16];
+        self.rng.fill_bytes(&mut msg_id);
+        msg_id
+    }
+
+    /// Wait for the answer of the request, the entry lives as long as the returned guard
+    async fn register_request(&self, msg_id: [u8; 16], tx: ResponseSender) -> PendingRequest {
+        self.pending_requests.lock().await.insert(msg_id, tx);
+        PendingRequest::new(&self.pending_requests, msg_id)
+    }
+}
+
+#[async_trait]
+impl NetworkProtocolTrait for NetworkProtocol {
+    async fn ping(&self, node: &Node) -> bool {
+        self.ping_remote(node).await.is_some()
+    }
 
-        self.pending_requests.lock().await.remove(&msg_id);
-        false
+    fn sign_origin(&self, key: &Key, value: Option<&[u8]>, origin: &mut StoreOrigin) {
+        NetworkProtocol::sign_origin(self, key, value, origin)
     }
 
     async fn find_node(
@@ -416,42 +2658,25 @@ impl NetworkProtocolTrait for NetworkProtocol {
         let msg_id = self.generate_msg_id();
         let (tx, rx) = oneshot::channel();
 
-        self.pending_requests.lock().await.insert(msg_id, tx);
+        let _pending = self.register_request(msg_id, tx).await;
 
         let addr: SocketAddr = format!("{}:{}", remote_node.address, remote_node.port)
             .parse()
             .unwrap();
-        let payload = serde_json::json!({"target_id": target_id.0});
+        let payload = serde_json::json!({"target_id": target_id});
 
-        let data = self.pack_message(MSG_FIND_NODE, msg_id, payload)?;
-        self.transport.send(&data, addr).await?;
+        let data = self.pack_message(MSG_FIND_NODE, msg_id, payload, addr)?;
+        let started = self.time.instant();
+        self.send_request(&remote_node.node_id, &data, addr, MSG_FIND_NODE)
+            .await?;
 
         match timeout(self.request_timeout, rx).await {
             Ok(Ok((msg_type, payload))) if msg_type == MSG_FIND_NODE_RESPONSE => {
-                let mut nodes = Vec::new();
-                if let Some(nodes_arr) = payload.get("nodes").and_then(|v| v.as_array()) {
-                    for n_val in nodes_arr {
-                        if let (Some(id_arr), Some(addr), Some(port)) = (
-                            n_val.get("node_id").and_then(|v| v.as_array()),
-                            n_val.get("address").and_then(|v| v.as_str()),
-                            n_val.get("port").and_then(|v| v.as_u64()),
-                        ) {
-                            let mut id_bytes = [0u8; 20];
-                            for (i, v) in id_arr.iter().enumerate().take(20) {
-                                id_bytes[i] = v.as_u64().unwrap_or(0) as u8;
-                            }
-                            nodes.push(Node::new(
-                                NodeID::new(id_bytes),
-                                addr.to_string(),
-                                port as u16,
-                            ));
-                        }
-                    }
-                }
-                Ok(nodes)
+                self.observe_rtt(&remote_node.node_id, started).await;
+                Ok(Self::parse_contacts(payload.get("nodes")))
             }
             _ => {
-                self.pending_requests.lock().await.remove(&msg_id);
+                self.observe_failure(&remote_node.node_id);
                 Err(RhizomeError::Network(NetworkError::General))
             }
         }
@@ -459,70 +2684,511 @@ impl NetworkProtocolTrait for NetworkProtocol {
 
     async fn find_value(
         &self,
-        key: &[u8],
+        key: &Key,
         remote_node: &Node,
     ) -> Result<Option<Vec<u8>>, RhizomeError> {
+        Ok(self
+            .find_value_versioned(key, remote_node)
+            .await?
+            .map(|v| v.value))
+    }
+
+    /// Holders which the node points to are asked too, but their own pointers are not
+    /// followed
+    ///
+    /// Only holders from our routing table are asked at addresses which we know, so a node
+    /// can not send our requests to other addresses. Value of a holder names it as its source.
+    async fn find_value_versioned(
+        &self,
+        key: &Key,
+        remote_node: &Node,
+    ) -> Result<Option<VersionedValue>, RhizomeError> {
+        let (found, holders) = self.request_value(key, remote_node).await?;
+        if found.is_some() {
+            return Ok(found);
+        }
+        let Some(rt) = &self.routing_table else {
+            return Ok(None);
+        };
+        let known: Vec<Node> = {
+            let rt = rt.read().await;
+            holders
+                .iter()
+                .filter(|h| h.node_id != self.node_id && h.node_id != remote_node.node_id)
+                .filter_map(|h| rt.get_node(&h.node_id))
+                .take(MAX_FOLLOWED_HOLDERS)
+                .collect()
+        };
+        for holder in known {
+            if let Ok((Some(mut value), _)) = self.request_value(key, &holder).await {
+                value.holder = Some(holder);
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn find_values(
+        &self,
+        keys: &[Key],
+        remote_node: &Node,
+    ) -> Result<HashMap<Key, Vec<u8>>, RhizomeError> {
+        let addr: SocketAddr = format!("{}:{}", remote_node.address, remote_node.port)
+            .parse()
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+
+        let mut found = HashMap::new();
+        let mut pending: Vec<Key> = keys.to_vec();
+        let mut large: Vec<Key> = Vec::new();
+
+        // Every answer is one chunk, keys which did not fit are asked again
+        while !pending.is_empty() {
+            let rest = pending.split_off(pending.len().min(MAX_BATCH_KEYS));
+            let msg_id = self.generate_msg_id();
+            let (tx, rx) = oneshot::channel();
+            let _pending = self.register_request(msg_id, tx).await;
+
+            let data = self.pack_message(
+                MSG_FIND_VALUES,
+                msg_id,
+                serde_json::json!({"keys": pending, "parts": true}),
+                addr,
+            )?;
+            let started = self.time.instant();
+            self.send_request(&remote_node.node_id, &data, addr, MSG_FIND_VALUES)
+                .await?;
+
+            let payload = match timeout(self.request_timeout, rx).await {
+                Ok(Ok((MSG_FIND_VALUES_RESPONSE, payload))) => payload,
+                _ => {
+                    self.observe_failure(&remote_node.node_id);
+                    break;
+                }
+            };
+            self.observe_rtt(&remote_node.node_id, started).await;
+
+            let values = payload
+                .get("values")
+                .and_then(|v| v.as_array())
+                .cloned()
+                .unwrap_or_default();
+            let received = values.len();
+            for item in values {
+                let key: Option<Key> = item
+                    .get("key")
+                    .and_then(|v| serde_json::from_value(v.clone()).ok());
+                let value: Option<Vec<u8>> = item
+                    .get("value")
+                    .and_then(|v| serde_json::from_value(v.clone()).ok());
+                if let (Some(key), Some(value)) = (key, value) {
+                    found.insert(key, value);
+                }
+            }
+
+            let remaining: Vec<Key> =
+                serde_json::from_value(payload.get("remaining").cloned().unwrap_or_default())
+                    .unwrap_or_default();
+            large.extend(
+                serde_json::from_value::<Vec<Key>>(
+                    payload.get("large").cloned().unwrap_or_default(),
+                )
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|key| keys.contains(key)),
+            );
+            if received == 0 && !remaining.is_empty() {
+                break;
+            }
+
+            pending = remaining;
+            pending.extend(rest);
+        }
+
+        // Values which do not fit into one answer are read by parts
+        for key in large {
+            if let Ok((Some(value), _)) = self.request_value(&key, remote_node).await {
+                found.insert(key, value.value);
+            }
+        }
+
+        Ok(found)
+    }
+
+    async fn store_cas(
+        &self,
+        key: &Key,
+        expected_hash: Option<[u8; 32]>,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+        remote_node: &Node,
+    ) -> Result<CasOutcome, RhizomeError> {
         let msg_id = self.generate_msg_id();
         let (tx, rx) = oneshot::channel();
 
-        self.pending_requests.lock().await.insert(msg_id, tx);
         let addr: SocketAddr = format!("{}:{}", remote_node.address, remote_node.port)
             .parse()
-            .unwrap();
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+
+        let _pending = self.register_request(msg_id, tx).await;
 
-        let data = self.pack_message(MSG_FIND_VALUE, msg_id, serde_json::json!({"key": key}))?;
-        self.transport.send(&data, addr).await?;
+        let payload = serde_json::json!({
+            "key": key,
+            "value": value,
+            "ttl": ttl,
+            "expected": expected_hash.map(hex::encode),
+            "publisher": origin.publisher,
+            "published_at": origin.published_at,
+            "hops": origin.hops,
+            "record_type": origin.record_type,
+            "proof": origin.proof
+        });
+        let data = self.pack_message(MSG_STORE_CAS, msg_id, payload, addr)?;
+        let started = self.time.instant();
+        self.send_request(&remote_node.node_id, &data, addr, MSG_STORE_CAS)
+            .await?;
 
         match timeout(self.request_timeout, rx).await {
-            Ok(Ok((msg_type, payload))) if msg_type == MSG_FIND_VALUE_RESPONSE => {
+            Ok(Ok((MSG_STORE_CAS_RESPONSE, payload))) => {
+                self.observe_rtt(&remote_node.node_id, started).await;
+                // Refused swap is neither applied nor a conflict, peer did not fail though
                 if payload
-                    .get("found")
+                    .get("refused")
                     .and_then(|v| v.as_bool())
                     .unwrap_or(false)
                 {
-                    let val: Vec<u8> =
-                        serde_json::from_value(payload.get("value").cloned().unwrap_or_default())
-                            .unwrap_or_default();
-                    Ok(Some(val))
+                    Err(RhizomeError::Storage(StorageError::ReplicationError))
+                } else if payload
+                    .get("success")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+                {
+                    Ok(CasOutcome::Applied)
                 } else {
-                    Ok(None)
+                    let current: Option<Vec<u8>> =
+                        serde_json::from_value(payload.get("current").cloned().unwrap_or_default())
+                            .unwrap_or_default();
+                    Ok(CasOutcome::Conflict(current))
                 }
             }
             _ => {
-                self.pending_requests.lock().await.remove(&msg_id);
-                Ok(None)
+                self.observe_failure(&remote_node.node_id);
+                Err(RhizomeError::Network(NetworkError::General))
+            }
+        }
+    }
+
+    async fn list_records(
+        &self,
+        record_type: &str,
+        since: f64,
+        remote_node: &Node,
+    ) -> Result<Vec<TypedRecord>, RhizomeError> {
+        let msg_id = self.generate_msg_id();
+        let (tx, rx) = oneshot::channel();
+
+        let addr: SocketAddr = format!("{}:{}", remote_node.address, remote_node.port)
+            .parse()
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+
+        let _pending = self.register_request(msg_id, tx).await;
+
+        let payload = serde_json::json!({
+            "record_type": record_type,
+            "since": since,
+            "limit": MAX_LISTED_RECORDS
+        });
+        let data = self.pack_message(MSG_LIST_RECORDS, msg_id, payload, addr)?;
+        let started = self.time.instant();
+        self.send_request(&remote_node.node_id, &data, addr, MSG_LIST_RECORDS)
+            .await?;
+
+        match timeout(self.request_timeout, rx).await {
+            Ok(Ok((MSG_LIST_RECORDS_RESPONSE, payload))) => {
+                self.observe_rtt(&remote_node.node_id, started).await;
+                let records = payload
+                    .get("records")
+                    .and_then(|v| v.as_array())
+                    .cloned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|r| {
+                        Some(TypedRecord {
+                            key: serde_json::from_value(r.get("key")?.clone()).ok()?,
+                            value: serde_json::from_value(r.get("value")?.clone()).ok()?,
+                            published_at: r.get("published_at")?.as_f64()?,
+                            proof: None,
+                        })
+                    })
+                    .collect();
+                Ok(records)
+            }
+            _ => {
+                self.observe_failure(&remote_node.node_id);
+                Err(RhizomeError::Network(NetworkError::General))
             }
         }
     }
 
     async fn store(
         &self,
-        key: &[u8],
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        remote_node: &Node,
+    ) -> Result<bool, RhizomeError> {
+        let mut origin = StoreOrigin::new(self.node_id.0.to_vec(), self.time.now());
+        self.sign_origin(key, Some(value), &mut origin);
+        self.store_with_origin(key, value, ttl, &origin, remote_node)
+            .await
+    }
+
+    async fn store_with_origin(
+        &self,
+        key: &Key,
         value: &[u8],
         ttl: i32,
+        origin: &StoreOrigin,
         remote_node: &Node,
     ) -> Result<bool, RhizomeError> {
+        let (success, _) = self
+            .store_with_receipt(key, value, ttl, origin, remote_node)
+            .await?;
+        Ok(success)
+    }
+
+    async fn store_with_receipt(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+        remote_node: &Node,
+    ) -> Result<(bool, Option<StorageReceipt>), RhizomeError> {
+        if value.len() > FRAGMENTED_STORE_THRESHOLD && self.accepts_fragments(&remote_node.node_id)
+        {
+            return self
+                .store_fragmented(key, value, ttl, origin, remote_node)
+                .await;
+        }
+
         let msg_id = self.generate_msg_id();
         let (tx, rx) = oneshot::channel();
 
-        self.pending_requests.lock().await.insert(msg_id, tx);
+        let _pending = self.register_request(msg_id, tx).await;
         let addr: SocketAddr = format!("{}:{}", remote_node.address, remote_node.port)
             .parse()
             .unwrap();
 
-        let payload = serde_json::json!({"key": key, "value": value, "ttl": ttl});
-        let data = self.pack_message(MSG_STORE, msg_id, payload)?;
-        self.transport.send(&data, addr).await?;
+        let mut payload = Self::store_payload(key, ttl, origin);
+        payload["value"] = serde_json::json!(value);
+        let data = self.pack_message(MSG_STORE, msg_id, payload, addr)?;
+        let started = self.time.instant();
+        self.send_request(&remote_node.node_id, &data, addr, MSG_STORE)
+            .await?;
+
+        match timeout(self.request_timeout, rx).await {
+            Ok(Ok((msg_type, payload))) if msg_type == MSG_STORE_RESPONSE => {
+                self.observe_rtt(&remote_node.node_id, started).await;
+                Ok(self.store_answer(key, payload).await)
+            }
+            _ => {
+                self.observe_failure(&remote_node.node_id);
+                Ok((false, None))
+            }
+        }
+    }
+
+    async fn store_many_with_receipts(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+        nodes: &[Node],
+    ) -> Vec<(bool, Option<StorageReceipt>)> {
+        // Fragments of large value are already sent by one batch per node
+        if value.len() > FRAGMENTED_STORE_THRESHOLD {
+            let stores = nodes
+                .iter()
+                .map(|node| self.store_with_receipt(key, value, ttl, origin, node));
+            return futures::future::join_all(stores)
+                .await
+                .into_iter()
+                .map(|result| result.unwrap_or((false, None)))
+                .collect();
+        }
+
+        let mut payload = Self::store_payload(key, ttl, origin);
+        payload["value"] = serde_json::json!(value);
+        let requests = nodes.iter().map(|node| (node, payload.clone())).collect();
+        let answers = match self
+            .request_many(MSG_STORE, MSG_STORE_RESPONSE, requests)
+            .await
+        {
+            Ok(answers) => answers,
+            Err(_) => vec![None; nodes.len()],
+        };
+
+        let mut results = Vec::with_capacity(nodes.len());
+        for answer in answers {
+            match answer {
+                Some(payload) => results.push(self.store_answer(key, payload).await),
+                None => results.push((false, None)),
+            }
+        }
+        results
+    }
+
+    async fn delete(
+        &self,
+        key: &Key,
+        ttl: i32,
+        origin: &StoreOrigin,
+        remote_node: &Node,
+    ) -> Result<bool, RhizomeError> {
+        let msg_id = self.generate_msg_id();
+        let (tx, rx) = oneshot::channel();
+
+        let addr: SocketAddr = format!("{}:{}", remote_node.address, remote_node.port)
+            .parse()
+            .map_err(|_| RhizomeError::Network(NetworkError::General))?;
+
+        let _pending = self.register_request(msg_id, tx).await;
+
+        let mut origin = origin.clone();
+        self.sign_origin(key, None, &mut origin);
+        let payload = serde_json::json!({
+            "key": key,
+            "ttl": ttl,
+            "publisher": origin.publisher,
+            "deleted_at": origin.published_at,
+            "hops": origin.hops,
+            "proof": origin.proof
+        });
+        let data = self.pack_message(MSG_DELETE, msg_id, payload, addr)?;
+        let started = self.time.instant();
+        self.send_request(&remote_node.node_id, &data, addr, MSG_DELETE)
+            .await?;
 
         match timeout(self.request_timeout, rx).await {
-            Ok(Ok((msg_type, payload))) if msg_type == MSG_STORE_RESPONSE => Ok(payload
-                .get("success")
-                .and_then(|v| v.as_bool())
-                .unwrap_or(false)),
+            Ok(Ok((MSG_DELETE_RESPONSE, payload))) => {
+                self.observe_rtt(&remote_node.node_id, started).await;
+                Ok(payload
+                    .get("success")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false))
+            }
             _ => {
-                self.pending_requests.lock().await.remove(&msg_id);
+                self.observe_failure(&remote_node.node_id);
                 Ok(false)
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "signatures")]
+    use crate::config::StorageConfig;
+    #[cfg(feature = "signatures")]
+    use crate::security::blacklist::Blocklist;
+    #[cfg(feature = "signatures")]
+    use crate::utils::crypto::{generate_keypair, node_id_from_public_key};
+    #[cfg(feature = "signatures")]
+    use crate::utils::temp_dir::TempDir;
+
+    fn protocol() -> NetworkProtocol {
+        let transport = Arc::new(UDPTransport::new("127.0.0.1", 0));
+        NetworkProtocol::new(
+            transport,
+            NodeID::new([1u8; 20]),
+            "127.0.0.1:0".parse().unwrap(),
+            None,
+            None,
+        )
+    }
+
+    #[cfg(feature = "signatures")]
+    fn signed_list(signer: &NodeSigner, created_at: f64) -> Vec<u8> {
+        let list = Blocklist {
+            created_at,
+            ..Blocklist::default()
+        };
+        SignedBlocklist::issue(list, signer)
+            .and_then(|signed| signed.to_bytes())
+            .unwrap()
+    }
+
+    #[cfg(feature = "signatures")]
+    fn signer() -> NodeSigner {
+        let (private_key, public_key) = generate_keypair();
+        let node_id = node_id_from_public_key(&public_key);
+        NodeSigner::new(private_key, node_id.to_vec())
+    }
+
+    #[cfg(feature = "signatures")]
+    #[tokio::test]
+    async fn stored_blocklist_is_replaced_only_by_newer_list_of_its_author() {
+        let protocol = protocol();
+        let dir = TempDir::new("blocklist");
+        let storage = Storage::new(StorageConfig {
+            data_dir: dir.path().to_path_buf(),
+            ..StorageConfig::default()
+        })
+        .unwrap();
+        let (author, other) = (signer(), signer());
+        let keys = KeyManager::for_network(&protocol.network_id);
+        let key = keys.get_blocklist_key(&hex::encode(author.node_id()));
+        // Senders do not label the records, values decide
+        let origin = StoreOrigin::default();
+
+        let first = signed_list(&author, 10.0);
+        let other_key = keys.get_blocklist_key(&hex::encode(other.node_id()));
+        assert!(
+            !protocol
+                .blocklist_accepted(&storage, &other_key, &first, &origin)
+                .await
+        );
+        assert!(
+            protocol
+                .blocklist_accepted(&storage, &key, &first, &origin)
+                .await
+        );
+        storage.put(key.clone(), first.clone(), 60).await.unwrap();
+
+        for refused in [
+            b"other value".to_vec(),
+            signed_list(&author, 5.0),
+            signed_list(&other, 20.0),
+        ] {
+            assert!(
+                !protocol
+                    .blocklist_accepted(&storage, &key, &refused, &origin)
+                    .await
+            );
+        }
+        assert!(
+            protocol
+                .blocklist_accepted(&storage, &key, &first, &origin)
+                .await
+        );
+        let newer = signed_list(&author, 20.0);
+        assert!(
+            protocol
+                .blocklist_accepted(&storage, &key, &newer, &origin)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelled_request_is_not_left_waiting() {
+        let protocol = protocol();
+        let node = Node::new(NodeID::new([2u8; 20]), "127.0.0.1".to_string(), 9);
+
+        let ping = protocol.ping_remote(&node);
+        assert!(timeout(Duration::from_millis(50), ping).await.is_err());
+        assert!(protocol.pending_requests.lock().await.is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::network::consts::*;
+
+/// Priority class of the message
+///
+/// Order of variants is the order of service: `Control` is sent first and is limited last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum MessagePriority {
+    /// PING/PONG and other liveness messages
+    Control,
+    /// FIND_NODE and FIND_VALUE lookups, direct messages
+    #[default]
+    Lookup,
+    /// STORE of replicas
+    Replication,
+    /// Popularity exchange, global ranking and gossip
+    Popularity,
+}
+
+impl MessagePriority {
+    /// All classes from the highest to the lowest
+    pub const ALL: [MessagePriority; 4] = [
+        MessagePriority::Control,
+        MessagePriority::Lookup,
+        MessagePriority::Replication,
+        MessagePriority::Popularity,
+    ];
+
+    /// Class of the message by its type _(answers have class of the request)_
+    pub fn for_msg_type(msg_type: u8) -> Self {
+        match msg_type {
+            MSG_PING | MSG_PONG | MSG_PUZZLE | MSG_PUZZLE_SOLUTION => MessagePriority::Control,
+            MSG_FIND_NODE
+            | MSG_FIND_NODE_RESPONSE
+            | MSG_FIND_VALUE
+            | MSG_FIND_VALUE_RESPONSE
+            | MSG_FIND_VALUES
+            | MSG_FIND_VALUES_RESPONSE
+            | MSG_LIST_RECORDS
+            | MSG_LIST_RECORDS_RESPONSE
+            | MSG_DIRECT
+            | MSG_DIRECT_RESPONSE => MessagePriority::Lookup,
+            MSG_STORE
+            | MSG_STORE_RESPONSE
+            | MSG_STORE_CAS
+            | MSG_STORE_CAS_RESPONSE
+            | MSG_DELETE
+            | MSG_DELETE_RESPONSE
+            | MSG_STORE_FRAGMENT
+            | MSG_STORE_FRAGMENTS_MISSING => MessagePriority::Replication,
+            _ => MessagePriority::Popularity,
+        }
+    }
+
+    /// Index of the class in outbound queues
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Part of the global rate limit which this class can use
+    ///
+    /// Low classes are rejected earlier, so there is always room for control and lookups
+    pub fn limit_share(self) -> f64 {
+        match self {
+            MessagePriority::Control => 1.5,
+            MessagePriority::Lookup => 1.0,
+            MessagePriority::Replication => 0.8,
+            MessagePriority::Popularity => 0.6,
+        }
+    }
+
+    /// Size of the outbound queue of the class
+    pub fn queue_capacity(self) -> usize {
+        match self {
+            MessagePriority::Control => 4096,
+            MessagePriority::Lookup => 2048,
+            MessagePriority::Replication | MessagePriority::Popularity => 1024,
+        }
+    }
+}
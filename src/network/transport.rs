@@ -1,11 +1,19 @@
-use std::net::SocketAddr;
+use socket2::SockRef;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::Interest;
 use tokio::net::UdpSocket;
-use tokio::sync::{Mutex, oneshot};
-use tracing::{error, info};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tracing::{Instrument, error, info, warn};
 
 use crate::exceptions::{NetworkError, RhizomeError};
+use crate::network::capture::{CaptureDirection, PacketTap};
+#[cfg(feature = "fault-injection")]
+use crate::network::chaos::FaultInjector;
+use crate::network::qos::MessagePriority;
 use crate::utils::time::get_now_f64;
 
 /// Raw Message
@@ -21,6 +29,28 @@ pub struct Message {
     pub timestamp: f64,
 }
 
+/// Options of the UDP socket, OS defaults are kept for `None`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SocketOptions {
+    /// Size of the receive buffer in bytes _(SO_RCVBUF)_
+    pub recv_buffer_size: Option<usize>,
+    /// Size of the send buffer in bytes _(SO_SNDBUF)_
+    pub send_buffer_size: Option<usize>,
+    /// TTL of sent packets _(hop limit on IPv6)_
+    pub ttl: Option<u32>,
+    /// DSCP class of sent packets _(0..=63, IPv4 only)_
+    pub dscp: Option<u8>,
+}
+
+/// Max count of queued bulk packets written to the socket at once
+pub const MAX_SEND_BATCH: usize = 32;
+
+/// Packet waiting in outbound queue
+struct Outbound {
+    data: Vec<u8>,
+    address: SocketAddr,
+}
+
 /// Main UDP structure
 pub struct UDPTransport {
     /// IP for connection _(0.0.0.0)_
@@ -37,6 +67,34 @@ pub struct UDPTransport {
     pub stop_tx: Mutex<Option<oneshot::Sender<()>>>,
     /// Thread safety status value
     pub is_running: AtomicBool,
+    /// Outbound queues by priority class _(index is [`MessagePriority::index`])_
+    outbound: Mutex<Option<Vec<mpsc::Sender<Outbound>>>>,
+    /// Capture of all received and sent packets for debugging _(off if None)_
+    pub tap: Option<Arc<PacketTap>>,
+    /// Ports tried in order when `port` is taken _(first, last)_
+    pub port_range: Option<(u16, u16)>,
+    /// Faults injected into sent packets in simulations _(off if None)_
+    #[cfg(feature = "fault-injection")]
+    pub faults: Option<Arc<FaultInjector>>,
+    /// Buffer sizes and marking of the socket
+    pub socket_options: SocketOptions,
+}
+
+/// IP address of the network interface with `name`
+///
+/// IPv4 address is preferred, link-local addresses are used only if there is nothing else.
+pub fn interface_address(name: &str) -> io::Result<IpAddr> {
+    let mut addrs: Vec<if_addrs::Interface> = if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter(|i| i.name == name)
+        .collect();
+    addrs.sort_by_key(|i| (i.is_link_local(), !i.ip().is_ipv4()));
+    addrs.first().map(|i| i.ip()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("network interface {} has no address", name),
+        )
+    })
 }
 
 impl UDPTransport {
@@ -47,6 +105,12 @@ impl UDPTransport {
             socket: Arc::new(Mutex::new(None)),
             stop_tx: Mutex::new(None),
             is_running: AtomicBool::new(false),
+            outbound: Mutex::new(None),
+            tap: None,
+            port_range: None,
+            #[cfg(feature = "fault-injection")]
+            faults: None,
+            socket_options: SocketOptions::default(),
         }
     }
 
@@ -62,11 +126,8 @@ impl UDPTransport {
             return Ok(());
         }
 
-        let addr = format!("{}:{}", self.host, self.port);
-        let socket = UdpSocket::bind(&addr).await.map_err(|e| {
-            error!("Failed to bind socket: {}", e);
-            RhizomeError::Network(NetworkError::General)
-        })?;
+        let socket = self.bind().await?;
+        self.apply_socket_options(&socket);
 
         let socket_arc = Arc::new(socket);
 
@@ -81,43 +142,141 @@ impl UDPTransport {
             *stop_tx_lock = Some(stop_tx);
         }
 
+        self.start_outbound_scheduler(socket_arc.clone()).await;
+
         let handler = Arc::new(handler);
+        let tap = self.tap.clone();
 
-        tokio::spawn(async move {
-            let mut buf = vec![0u8; 65535];
+        tokio::spawn(
+            async move {
+                let mut buf = vec![0u8; 65535];
 
-            loop {
-                tokio::select! {
-                    _ = &mut stop_rx => {
-                        break;
-                    }
-                    result = socket_arc.recv_from(&mut buf) => {
-                        match result {
-                            Ok((size, addr)) => {
-                                let data = buf[..size].to_vec();
-                                let timestamp = get_now_f64();
-
-                                let msg = Message { data, address: addr, timestamp };
-                                let h = handler.clone();
-
-                                tokio::spawn(async move {
-                                    h(msg).await;
-                                });
-                            }
-                            Err(e) => {
-                                error!("UDP receive error: {}", e);
+                loop {
+                    tokio::select! {
+                        _ = &mut stop_rx => {
+                            break;
+                        }
+                        result = socket_arc.recv_from(&mut buf) => {
+                            match result {
+                                Ok((size, addr)) => {
+                                    let data = buf[..size].to_vec();
+                                    let timestamp = get_now_f64();
+                                    if let Some(tap) = &tap {
+                                        tap.record(CaptureDirection::Inbound, addr, &data);
+                                    }
+
+                                    let msg = Message { data, address: addr, timestamp };
+                                    let h = handler.clone();
+
+                                    tokio::spawn(async move {
+                                        h(msg).await;
+                                    }.in_current_span());
+                                }
+                                Err(e) => {
+                                    error!("UDP receive error: {}", e);
+                                }
                             }
                         }
                     }
                 }
             }
-        });
+            .in_current_span(),
+        );
 
         self.is_running.store(true, Ordering::SeqCst);
         info!(host = %self.host, port = self.port, "UDP transport started");
         Ok(())
     }
 
+    /// Check that the socket can be bound, test socket is closed at once
+    ///
+    /// Return port which the transport will use _(current port if it is running)_.
+    pub async fn probe_bind(&self) -> Result<u16, RhizomeError> {
+        if self.is_running.load(Ordering::SeqCst) {
+            return Ok(self.get_address().await.port());
+        }
+        let socket = self.bind().await?;
+        socket
+            .local_addr()
+            .map(|addr| addr.port())
+            .map_err(|_| RhizomeError::Network(NetworkError::General))
+    }
+
+    /// Bind configured port, if it is taken - the first free port of `port_range`
+    async fn bind(&self) -> Result<UdpSocket, RhizomeError> {
+        let mut ports = vec![self.port];
+        if let Some((first, last)) = self.port_range {
+            ports.extend((first..=last).filter(|port| *port != self.port));
+        }
+
+        let mut last_error = None;
+        for port in ports {
+            match UdpSocket::bind((self.host.as_str(), port)).await {
+                Ok(socket) => {
+                    if port != self.port {
+                        warn!(
+                            configured = self.port,
+                            port = port,
+                            "Configured port is taken, bound port from range"
+                        );
+                    }
+                    return Ok(socket);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        if let Some(e) = last_error {
+            error!("Failed to bind socket: {}", e);
+        }
+        Err(RhizomeError::Network(NetworkError::General))
+    }
+
+    /// Set configured options of the bound socket
+    ///
+    /// Failed option is only logged, the node works with OS default then. OS may also
+    /// limit buffers _(exm. `net.core.rmem_max` on Linux)_, so smaller buffer is reported.
+    fn apply_socket_options(&self, socket: &UdpSocket) {
+        let options = self.socket_options;
+        let sock = SockRef::from(socket);
+        let is_ipv4 = socket.local_addr().is_ok_and(|addr| addr.is_ipv4());
+
+        if let Some(size) = options.recv_buffer_size {
+            if let Err(e) = sock.set_recv_buffer_size(size) {
+                warn!(size, error = %e, "Receive buffer size was not set");
+            } else if let Ok(actual) = sock.recv_buffer_size()
+                && actual < size
+            {
+                warn!(requested = size, actual, "Receive buffer is limited by OS");
+            }
+        }
+        if let Some(size) = options.send_buffer_size {
+            if let Err(e) = sock.set_send_buffer_size(size) {
+                warn!(size, error = %e, "Send buffer size was not set");
+            } else if let Ok(actual) = sock.send_buffer_size()
+                && actual < size
+            {
+                warn!(requested = size, actual, "Send buffer is limited by OS");
+            }
+        }
+        if let Some(ttl) = options.ttl {
+            let result = if is_ipv4 {
+                sock.set_ttl_v4(ttl)
+            } else {
+                sock.set_unicast_hops_v6(ttl)
+            };
+            if let Err(e) = result {
+                warn!(ttl, error = %e, "TTL of packets was not set");
+            }
+        }
+        if let Some(dscp) = options.dscp {
+            if !is_ipv4 {
+                warn!(dscp, "DSCP marking is supported only on IPv4 sockets");
+            } else if let Err(e) = sock.set_tos_v4((dscp.min(63) as u32) << 2) {
+                warn!(dscp, error = %e, "DSCP of packets was not set");
+            }
+        }
+    }
+
     /// Stop the UDP transport
     pub async fn stop(&self) {
         if !self.is_running.load(Ordering::SeqCst) {
@@ -136,29 +295,245 @@ impl UDPTransport {
             *socket_lock = None;
         }
 
+        *self.outbound.lock().await = None;
+
         self.is_running.store(false, Ordering::SeqCst);
         info!("UDP transport stopped");
     }
 
-    /// Send message
+    /// Outbound scheduler
+    ///
+    /// One task sends packets from priority queues. Higher class is always served first,
+    /// so replication bursts do not delay PING/PONG and lookups.
+    async fn start_outbound_scheduler(&self, socket: Arc<UdpSocket>) {
+        let mut senders = Vec::with_capacity(MessagePriority::ALL.len());
+        let mut receivers = Vec::with_capacity(MessagePriority::ALL.len());
+        for priority in MessagePriority::ALL {
+            let (tx, rx) = mpsc::channel::<Outbound>(priority.queue_capacity());
+            senders.push(tx);
+            receivers.push(rx);
+        }
+        *self.outbound.lock().await = Some(senders);
+        let tap = self.tap.clone();
+        #[cfg(feature = "fault-injection")]
+        let faults = self.faults.clone();
+
+        let mut popularity = receivers.pop().unwrap();
+        let mut replication = receivers.pop().unwrap();
+        let mut lookup = receivers.pop().unwrap();
+        let mut control = receivers.pop().unwrap();
+
+        tokio::spawn(
+            async move {
+                loop {
+                    let (packet, priority) = tokio::select! {
+                        biased;
+                        Some(p) = control.recv() => (p, MessagePriority::Control),
+                        Some(p) = lookup.recv() => (p, MessagePriority::Lookup),
+                        Some(p) = replication.recv() => (p, MessagePriority::Replication),
+                        Some(p) = popularity.recv() => (p, MessagePriority::Popularity),
+                        else => break,
+                    };
+
+                    // Bulk classes are not sensitive to latency, packets queued behind the
+                    // first one go out with it
+                    let mut batch = vec![packet];
+                    let queue = match priority {
+                        MessagePriority::Replication => Some(&mut replication),
+                        MessagePriority::Popularity => Some(&mut popularity),
+                        _ => None,
+                    };
+                    if let Some(queue) = queue {
+                        while batch.len() < MAX_SEND_BATCH
+                            && let Ok(packet) = queue.try_recv()
+                        {
+                            batch.push(packet);
+                        }
+                    }
+
+                    #[cfg(feature = "fault-injection")]
+                    if let Some(faults) = &faults {
+                        Self::send_with_faults(&socket, &tap, faults, batch).await;
+                        continue;
+                    }
+                    Self::send_packets(&socket, tap.as_deref(), &batch).await;
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// Send packets as the fault injector plans them
+    ///
+    /// Delayed copies are sent by their own tasks, so packets sent later overtake them.
+    #[cfg(feature = "fault-injection")]
+    async fn send_with_faults(
+        socket: &Arc<UdpSocket>,
+        tap: &Option<Arc<PacketTap>>,
+        faults: &FaultInjector,
+        batch: Vec<Outbound>,
+    ) {
+        for packet in batch {
+            for delay in faults.plan() {
+                if delay.is_zero() {
+                    Self::send_packet(socket, tap.as_deref(), &packet).await;
+                    continue;
+                }
+                let (socket, tap) = (socket.clone(), tap.clone());
+                let packet = Outbound {
+                    data: packet.data.clone(),
+                    address: packet.address,
+                };
+                tokio::spawn(
+                    async move {
+                        tokio::time::sleep(delay).await;
+                        Self::send_packet(&socket, tap.as_deref(), &packet).await;
+                    }
+                    .in_current_span(),
+                );
+            }
+        }
+    }
+
+    /// Write the packet to the socket and the capture
+    async fn send_packet(socket: &UdpSocket, tap: Option<&PacketTap>, packet: &Outbound) {
+        if let Err(e) = socket.send_to(&packet.data, packet.address).await {
+            error!(error = %e, address = %packet.address, "Error sending message");
+        } else if let Some(tap) = tap {
+            tap.record(CaptureDirection::Outbound, packet.address, &packet.data);
+        }
+    }
+
+    /// Write several packets to the socket and the capture
+    ///
+    /// Linux writes the whole batch by one `sendmmsg` call. UDP GSO is not used, it needs
+    /// one destination and equal sizes of packets, while batches go to many peers.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    async fn send_packets(socket: &UdpSocket, tap: Option<&PacketTap>, packets: &[Outbound]) {
+        if packets.len() == 1 {
+            Self::send_packet(socket, tap, &packets[0]).await;
+            return;
+        }
+
+        let mut sent = 0;
+        while sent < packets.len() {
+            if let Err(e) = socket.writable().await {
+                error!(error = %e, "Socket is not writable");
+                return;
+            }
+            let rest = &packets[sent..];
+            match socket.try_io(Interest::WRITABLE, || send_mmsg(socket, rest)) {
+                Ok(count) => {
+                    if let Some(tap) = tap {
+                        for packet in &rest[..count] {
+                            tap.record(CaptureDirection::Outbound, packet.address, &packet.data);
+                        }
+                    }
+                    sent += count;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                // Call fails only on the first packet of the batch, others are sent next time
+                Err(e) => {
+                    error!(error = %e, address = %rest[0].address, "Error sending message");
+                    sent += 1;
+                }
+            }
+        }
+    }
+
+    /// Write several packets to the socket and the capture one by one
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    async fn send_packets(socket: &UdpSocket, tap: Option<&PacketTap>, packets: &[Outbound]) {
+        for packet in packets {
+            Self::send_packet(socket, tap, packet).await;
+        }
+    }
+
+    /// Send message with default priority
     pub async fn send(&self, data: &[u8], address: SocketAddr) -> Result<bool, RhizomeError> {
+        self.send_with_priority(data, address, MessagePriority::default())
+            .await
+    }
+
+    /// Put message in the outbound queue of its priority class
+    ///
+    /// If queue of low class is full the message is dropped and `false` is returned.
+    /// Control messages wait for free place instead.
+    pub async fn send_with_priority(
+        &self,
+        data: &[u8],
+        address: SocketAddr,
+        priority: MessagePriority,
+    ) -> Result<bool, RhizomeError> {
         if !self.is_running.load(Ordering::SeqCst) {
             return Err(RhizomeError::Network(NetworkError::General));
         }
 
-        let socket_lock = self.socket.lock().await;
-        if let Some(socket) = socket_lock.as_ref() {
-            match socket.send_to(data, address).await {
-                Ok(_) => Ok(true),
-                Err(e) => {
-                    error!(error = %e, address = %address, "Error sending message");
-                    Ok(false)
-                }
+        let sender = match self.outbound.lock().await.as_ref() {
+            Some(senders) => senders[priority.index()].clone(),
+            None => {
+                error!("No socket available for sending");
+                return Ok(false);
             }
-        } else {
-            error!("No socket available for sending");
-            Ok(false)
+        };
+
+        let packet = Outbound {
+            data: data.to_vec(),
+            address,
+        };
+
+        if priority == MessagePriority::Control {
+            return Ok(sender.send(packet).await.is_ok());
+        }
+
+        match sender.try_send(packet) {
+            Ok(()) => Ok(true),
+            Err(TrySendError::Full(_)) => {
+                warn!(priority = ?priority, address = %address, "Outbound queue is full, message dropped");
+                Ok(false)
+            }
+            Err(TrySendError::Closed(_)) => Ok(false),
+        }
+    }
+
+    /// Put several messages in the outbound queue of their class at once
+    ///
+    /// Queued together, they are written to the socket by one batch. Return count of
+    /// queued messages, messages which do not fit into the queue of low class are dropped.
+    pub async fn send_batch(
+        &self,
+        packets: Vec<(Vec<u8>, SocketAddr)>,
+        priority: MessagePriority,
+    ) -> Result<usize, RhizomeError> {
+        if !self.is_running.load(Ordering::SeqCst) {
+            return Err(RhizomeError::Network(NetworkError::General));
+        }
+
+        let sender = match self.outbound.lock().await.as_ref() {
+            Some(senders) => senders[priority.index()].clone(),
+            None => {
+                error!("No socket available for sending");
+                return Ok(0);
+            }
+        };
+
+        let total = packets.len();
+        let mut queued = 0;
+        for (data, address) in packets {
+            let packet = Outbound { data, address };
+            let result = if priority == MessagePriority::Control {
+                sender.send(packet).await.is_ok()
+            } else {
+                sender.try_send(packet).is_ok()
+            };
+            if result {
+                queued += 1;
+            }
+        }
+        if queued < total {
+            warn!(priority = ?priority, dropped = total - queued, "Outbound queue is full, messages dropped");
         }
+        Ok(queued)
     }
 
     /// Get transport address
@@ -177,3 +552,47 @@ impl UDPTransport {
         }
     }
 }
+
+/// Write packets by one `sendmmsg` call, return count of sent packets
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_mmsg(socket: &UdpSocket, packets: &[Outbound]) -> io::Result<usize> {
+    use socket2::SockAddr;
+    use std::os::fd::AsRawFd;
+
+    let addresses: Vec<SockAddr> = packets.iter().map(|p| SockAddr::from(p.address)).collect();
+    let mut buffers: Vec<libc::iovec> = packets
+        .iter()
+        .map(|p| libc::iovec {
+            iov_base: p.data.as_ptr() as *mut libc::c_void,
+            iov_len: p.data.len(),
+        })
+        .collect();
+    let mut headers: Vec<libc::mmsghdr> = addresses
+        .iter()
+        .zip(buffers.iter_mut())
+        .map(|(address, buffer)| {
+            // SAFETY: all-zero header is valid, pointers set below outlive the call
+            let mut header: libc::mmsghdr = unsafe { std::mem::zeroed() };
+            header.msg_hdr.msg_name = address.as_ptr() as *mut libc::c_void;
+            header.msg_hdr.msg_namelen = address.len();
+            header.msg_hdr.msg_iov = buffer;
+            header.msg_hdr.msg_iovlen = 1;
+            header
+        })
+        .collect();
+
+    // SAFETY: headers point to addresses and buffers which live until the end of the call
+    let sent = unsafe {
+        libc::sendmmsg(
+            socket.as_raw_fd(),
+            headers.as_mut_ptr(),
+            headers.len() as _,
+            0,
+        )
+    };
+    if sent < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(sent as usize)
+    }
+}
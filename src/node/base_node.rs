@@ -1,25 +1,91 @@
-use rand::Rng;
+#[cfg(feature = "signatures")]
+use futures::future::join_all;
+#[cfg(feature = "signatures")]
+use rsa::RsaPrivateKey;
+use std::collections::HashMap;
 use std::fmt;
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::RwLock;
-use tracing::{debug, error, info, warn};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{Instrument, Span, debug, error, info, info_span, warn};
 
 use crate::config::Config;
+use crate::dht::budget::QueryBudget;
+use crate::dht::holder_pointers::HolderPointers;
+use crate::dht::key_presence::KeyPresenceIndex;
+use crate::dht::negative_cache::NegativeCache;
 use crate::dht::node::{Node, NodeID};
-use crate::dht::protocol::{DHTProtocol, NetworkProtocolTrait};
-use crate::dht::routing_table::RoutingTable;
+use crate::dht::partition::PartitionDetector;
+#[cfg(feature = "signatures")]
+use crate::dht::protocol::NetworkProtocolTrait;
+use crate::dht::protocol::{DHTProtocol, ReadConsistency, StoreResult, ValueWithMeta};
+use crate::dht::routing_table::{RoutingEvent, RoutingTable};
+use crate::events::{EventBus, NodeEvent};
 use crate::exceptions::RhizomeError;
+#[cfg(feature = "signatures")]
+use crate::exceptions::SecurityError;
+use crate::network::capture::PacketTap;
+use crate::network::chaos::FaultConfig;
+#[cfg(feature = "fault-injection")]
+use crate::network::chaos::FaultInjector;
+use crate::network::gossip::{Gossip, GossipMessage, GossipTopic};
+#[cfg(feature = "port-mapping")]
+use crate::network::port_mapping::PortMapper;
 use crate::network::protocol::NetworkProtocol;
-use crate::network::transport::UDPTransport;
+use crate::network::transport::{SocketOptions, UDPTransport, interface_address};
+use crate::node::lifecycle::{Lifecycle, NodeStatus};
+use crate::node::mode::{ModeSwitch, NodeMode};
+use crate::node::preflight::{self, CheckStatus, PreflightReport};
+use crate::node::supervisor::Supervisor;
+#[cfg(feature = "popularity")]
 use crate::popularity::exchanger::PopularityExchanger;
+#[cfg(feature = "popularity")]
 use crate::popularity::metrics::MetricsCollector;
+#[cfg(feature = "popularity")]
 use crate::popularity::ranking::PopularityRanker;
+#[cfg(feature = "replication")]
 use crate::replication::replicator::Replicator;
-use crate::storage::main::Storage;
-use crate::utils::crypto::{generate_node_id, load_node_id, save_node_id};
+use crate::security::admin_tokens::AdminTokens;
+#[cfg(feature = "signatures")]
+use crate::security::audit::AuditEventKind;
+use crate::security::audit::AuditLog;
+use crate::security::blacklist::Blacklist;
+#[cfg(feature = "signatures")]
+use crate::security::blacklist::SignedBlocklist;
+#[cfg(feature = "wasm-plugins")]
+use crate::security::content_policy::ContentPolicy;
+#[cfg(feature = "signatures")]
+use crate::security::identity_link::{IdentityLink, RotationReport};
+use crate::security::puzzle::PuzzleGuard;
+use crate::security::rate_limiter::RateLimiter;
+use crate::storage::disk::DiskWatchdog;
+use crate::storage::keys::Key;
+#[cfg(any(feature = "replication", feature = "signatures"))]
+use crate::storage::keys::KeyManager;
+#[cfg(feature = "signatures")]
+use crate::storage::keys::RECORD_BLOCKLIST;
+use crate::storage::main::{CasOutcome, Durability, Storage};
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::{
+    NodeSigner, generate_keypair, generate_keypair_from, load_private_key, node_id_from_public_key,
+    retire_file, save_private_key,
+};
+use crate::utils::crypto::{hash_key, load_node_id, save_node_id};
+use crate::utils::rng::NodeRng;
+use crate::utils::serialization::WireFormat;
+#[cfg(feature = "port-mapping")]
 use crate::utils::time::get_now_f64;
+use crate::utils::time::{Clock, SystemClock, duration_from_secs};
+#[cfg(feature = "webhooks")]
+use crate::webhooks::WebhookDispatcher;
+
+/// Count of peers with known clock offsets needed to finish warm-up
+pub const WARM_UP_MIN_CLOCKS: usize = 3;
+/// Interval in seconds between checks of warm-up
+const WARM_UP_CHECK_INTERVAL: u64 = 5;
 
 /// Enum of the nodes for computer resources
 ///
@@ -50,6 +116,36 @@ impl fmt::Display for NodeType {
     }
 }
 
+/// Result of joining the network through bootstrap nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BootstrapStatus {
+    /// Node is not started yet
+    #[default]
+    NotStarted,
+    /// Bootstrap nodes are being pinged
+    InProgress,
+    /// At least one bootstrap node answered
+    Connected,
+    /// No bootstrap node answered
+    Unreachable,
+    /// No bootstrap nodes are configured, node waits for peers to come
+    NoBootstrapNodes,
+}
+
+impl fmt::Display for BootstrapStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            BootstrapStatus::NotStarted => "not_started",
+            BootstrapStatus::InProgress => "in_progress",
+            BootstrapStatus::Connected => "connected",
+            BootstrapStatus::Unreachable => "unreachable",
+            BootstrapStatus::NoBootstrapNodes => "no_bootstrap_nodes",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 /// Type for Facade base node
 pub struct BaseNode {
     /// Ref to the client config
@@ -65,21 +161,49 @@ pub struct BaseNode {
     /// For work with UDP socket
     pub transport: Arc<UDPTransport>,
     /// Collect all metrics
-    pub metrics_collector: Arc<RwLock<MetricsCollector>>,
+    #[cfg(feature = "popularity")]
+    pub metrics_collector: Arc<MetricsCollector>,
     /// Ranker of data popularity
+    #[cfg(feature = "popularity")]
     pub popularity_ranker: Arc<PopularityRanker>,
     /// The protocol of network lvl
     pub network_protocol: Arc<NetworkProtocol>,
     /// Protocol of DHT network
     pub dht_protocol: Arc<DHTProtocol>,
     /// Exchanger of some data popularity
+    #[cfg(feature = "popularity")]
     pub popularity_exchanger: Arc<PopularityExchanger>,
     /// Also exchange popularity
+    #[cfg(feature = "replication")]
     pub replicator: Arc<Replicator>,
-    /// Value of node status
-    pub is_running: Arc<RwLock<bool>>,
+    /// Channel with events of the node
+    pub events: EventBus,
+    /// Runner of background loops
+    pub supervisor: Arc<Supervisor>,
+    /// Bloom filters of stored keys of our node and peers
+    pub key_presence: Arc<KeyPresenceIndex>,
+    /// Private key of the node for signatures
+    #[cfg(feature = "signatures")]
+    pub signer: Arc<NodeSigner>,
+    /// Log of security events
+    pub audit: Arc<AuditLog>,
+    /// Tokens of the admin API
+    pub admin_tokens: Arc<AdminTokens>,
+    /// Watcher of free disk space under `data_dir`
+    pub disk_watchdog: Arc<DiskWatchdog>,
+    /// Normal or read-only mode
+    pub mode: Arc<ModeSwitch>,
+    /// Mapping of our port on the home router _(None if mapping is off)_
+    #[cfg(feature = "port-mapping")]
+    pub port_mapper: Option<Arc<PortMapper>>,
+    /// Stage of the node life, background loops work while it is running
+    pub lifecycle: Arc<Lifecycle>,
+    /// Span with the node ID around records of the node tasks
+    pub span: Span,
     /// Time of node start
     pub start_time: Arc<RwLock<Option<f64>>>,
+    /// Result of the last bootstrap
+    bootstrap_status: std::sync::Mutex<BootstrapStatus>,
 }
 
 #[allow(dead_code)]
@@ -90,7 +214,18 @@ impl BaseNode {
     ///  the resources of user device.
     /// Secondly they will generate uniq 160-bits identifier if we don't create it yet.
     /// Finally, we will create all node components for work in strong ordinary.
-    pub async fn new(mut config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_clock(config, Arc::new(SystemClock)).await
+    }
+
+    /// Node which counts time by `clock`, its storage, DHT and network share it
+    ///
+    /// Simulations give nodes a [`VirtualClock`](crate::utils::time::VirtualClock) and move
+    /// it forward to expire TTLs without waiting.
+    pub async fn with_clock(
+        mut config: Config,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         if config.node.auto_detect_type
             && let Some(detected) = Self::detect_node_type(&config)
         {
@@ -104,7 +239,25 @@ impl BaseNode {
             _ => NodeType::Mobile,
         };
 
+        #[cfg(feature = "seeded-rng")]
+        let rng = Arc::new(NodeRng::new(config.node.rng_seed));
+        #[cfg(feature = "seeded-rng")]
+        if rng.is_seeded() {
+            warn!(
+                "Node random generator is seeded, message and bucket refresh IDs and new node key are predictable"
+            );
+        }
+        #[cfg(not(feature = "seeded-rng"))]
+        let rng = Arc::new(NodeRng::default());
+        #[cfg(not(feature = "seeded-rng"))]
+        if config.node.rng_seed.is_some() {
+            warn!("RNG seed is configured, but the node is built without `seeded-rng` feature");
+        }
+
         let node_id_path = PathBuf::from(&config.node.node_id_file);
+        #[cfg(feature = "signatures")]
+        let (node_id_bytes, signer) = Self::load_identity(&config, &node_id_path, &rng)?;
+        #[cfg(not(feature = "signatures"))]
         let node_id_bytes = match load_node_id(&node_id_path) {
             Some(bytes) => {
                 info!(path = ?node_id_path, "Node ID loaded from file");
@@ -112,7 +265,7 @@ impl BaseNode {
             }
             None => {
                 info!("Generating new node ID");
-                let bytes = generate_node_id().to_vec();
+                let bytes = NodeID::random(&rng).0.to_vec();
                 save_node_id(&bytes, &node_id_path)?;
                 bytes
             }
@@ -120,59 +273,274 @@ impl BaseNode {
         let mut id_fixed = [0u8; 20];
         id_fixed.copy_from_slice(&node_id_bytes[..20]);
         let node_id = NodeID::new(id_fixed);
-
-        let routing_table = Arc::new(RwLock::new(RoutingTable::new(
-            node_id,
-            config.dht.k as usize,
-            config.dht.bucket_count as usize,
-        )));
-
-        let storage = Arc::new(Storage::new(config.storage.clone())?);
-
-        let transport = Arc::new(UDPTransport::new(
-            &config.network.listen_host,
-            config.network.listen_port as u16,
+        let span = info_span!("node", id = %hex::encode(&node_id.0[..8]));
+
+        let routing_table = Arc::new(RwLock::new(
+            RoutingTable::new(
+                node_id,
+                config.dht.k as usize,
+                config.dht.bucket_count as usize,
+            )
+            .with_clock(clock.clone()),
         ));
 
-        let metrics_collector = Arc::new(RwLock::new(MetricsCollector::new()));
+        #[cfg(not(feature = "compression"))]
+        if config.storage.compression_threshold.is_some() {
+            warn!("Storage compression is on, but the node is built without `compression` feature");
+        }
+        let storage = Arc::new(Storage::with_clock(config.storage.clone(), clock.clone())?);
+        // Our own data is limited only by the storage size
+        storage.quotas().set_limit(&node_id.0, None);
+
+        let listen_host = match &config.network.listen_interface {
+            Some(name) => interface_address(name)?.to_string(),
+            None => config.network.listen_host.clone(),
+        };
+        let mut transport = UDPTransport::new(&listen_host, config.network.listen_port as u16);
+        transport.port_range = config.network.listen_port_range;
+        transport.socket_options = SocketOptions {
+            recv_buffer_size: config.network.recv_buffer_size,
+            send_buffer_size: config.network.send_buffer_size,
+            ttl: config.network.ip_ttl,
+            dscp: config.network.dscp,
+        };
+        if let Some(path) = &config.network.capture_file {
+            warn!(path = ?path, "Packet capture is on, all traffic is written to the file");
+            transport.tap = Some(Arc::new(PacketTap::open(path)?));
+        }
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = &config.network.faults {
+            if *faults != FaultConfig::default() {
+                warn!(faults = ?faults, "Fault injection is on, sent packets are dropped and delayed");
+            }
+            transport.faults = Some(Arc::new(FaultInjector::new(
+                faults.clone(),
+                config.node.rng_seed,
+            )));
+        }
+        #[cfg(not(feature = "fault-injection"))]
+        if config
+            .network
+            .faults
+            .as_ref()
+            .is_some_and(|faults| *faults != FaultConfig::default())
+        {
+            warn!(
+                "Fault injection is configured, but the node is built without `fault-injection` feature"
+            );
+        }
+        let transport = Arc::new(transport);
+
+        #[cfg(feature = "popularity")]
+        let metrics_collector = Arc::new(
+            MetricsCollector::with_capacity(
+                config.popularity.max_tracked_items,
+                config.popularity.metrics_shards,
+            )
+            .with_clock(clock.clone()),
+        );
 
+        #[cfg(feature = "popularity")]
         let popularity_ranker = Arc::new(PopularityRanker::new(
             config.popularity.popularity_threshold,
             config.popularity.active_threshold,
+            config.popularity.decay_half_life as f64,
         ));
 
-        let listen_addr: std::net::SocketAddr = format!(
-            "{}:{}",
-            config.network.listen_host, config.network.listen_port
-        )
-        .parse()?;
+        let listen_addr =
+            std::net::SocketAddr::new(listen_host.parse()?, config.network.listen_port as u16);
 
-        let network_protocol = Arc::new(NetworkProtocol::new(
+        let mut network_protocol = NetworkProtocol::new(
             transport.clone(),
             node_id,
             listen_addr,
             Some(routing_table.clone()),
             Some(storage.clone()),
+        );
+        let mut gossip = Gossip::new(
+            config.network.gossip_fanout.max(1) as usize,
+            config.network.gossip_ttl.max(0) as u32,
+        )
+        .with_clock(clock.clone());
+        gossip.clock = Some(network_protocol.clock.clone());
+        #[cfg(feature = "signatures")]
+        {
+            gossip.signer = Some(signer.clone()).filter(|signer| signer.is_bound_to_node_id());
+        }
+        let audit = Arc::new(AuditLog::new(
+            config.security.audit_log_file.clone(),
+            config.security.audit_log_max_size,
+            config.security.audit_log_files,
+        ));
+        network_protocol.audit = Some(audit.clone());
+        let admin_tokens = if config.security.admin_tokens.is_empty() {
+            let path = config.admin_token_path();
+            match AdminTokens::load(&path, Some(audit.clone())) {
+                Some(tokens) => Arc::new(tokens),
+                None => {
+                    let (tokens, token) = AdminTokens::generate(Some(audit.clone()));
+                    // Without the file nobody knows the token, so the API stays closed
+                    match AdminTokens::write_token(&path, &token) {
+                        Ok(()) => info!(path = ?path, "Admin API token generated"),
+                        Err(e) => {
+                            warn!(path = ?path, error = %e, "Generated admin API token is not written")
+                        }
+                    }
+                    Arc::new(tokens)
+                }
+            }
+        } else {
+            Arc::new(AdminTokens::new(
+                &config.security.admin_tokens,
+                Some(audit.clone()),
+            ))
+        };
+        let disk_watchdog = Arc::new(DiskWatchdog::new(
+            config.storage.data_dir.clone(),
+            config.storage.min_free_space,
+            config.storage.resume_free_space,
+        ));
+        network_protocol.disk_watchdog = Some(disk_watchdog.clone());
+        let mode = Arc::new(ModeSwitch::default());
+        network_protocol.mode = mode.clone();
+        let events = EventBus::default();
+        let mut blacklist = Blacklist::new(Some(audit.clone())).with_clock(clock.clone());
+        blacklist.events = Some(events.clone());
+        network_protocol.blacklist = Arc::new(blacklist);
+        network_protocol.timestamp_window = config
+            .security
+            .check_timestamps
+            .then_some(config.security.timestamp_window.max(0.0));
+        network_protocol.request_timeout = duration_from_secs(config.dht.request_timeout.max(0.1));
+        network_protocol.ping_timeout = duration_from_secs(config.dht.ping_timeout.max(0.1));
+        network_protocol.rate_limiter = Arc::new(Mutex::new(
+            RateLimiter::new(
+                config.security.rate_limit_requests.max(1) as usize,
+                config.security.rate_limit_window.max(1) as u64,
+                config.security.rate_limit_per_node.max(1) as usize,
+            )
+            .with_clock(clock.clone()),
+        ));
+        network_protocol.puzzles = Arc::new(PuzzleGuard::new(
+            config.security.puzzle_difficulty,
+            config.security.puzzle_load_threshold,
         ));
+        network_protocol.gossip = Arc::new(gossip);
+        network_protocol.rng = rng.clone();
+        network_protocol.endpoints = config.network.endpoints.clone();
+        if matches!(node_type, NodeType::Light | NodeType::Mobile)
+            && config.dht.holder_pointer_ttl > 0
+        {
+            network_protocol.holder_pointers = Some(Arc::new(
+                HolderPointers::new(config.dht.holder_pointer_ttl as f64).with_clock(clock.clone()),
+            ));
+        }
+        network_protocol.network_id = config.network.network_id.clone();
+        network_protocol.wire_format = match WireFormat::from_name(&config.network.wire_format) {
+            Some(format) if format.is_supported() => format,
+            _ => {
+                warn!(format = %config.network.wire_format, "Wire format is not supported, using msgpack");
+                WireFormat::Msgpack
+            }
+        };
+        #[cfg(feature = "signatures")]
+        {
+            network_protocol.receipt_signer = Some(signer.clone());
+        }
+        #[cfg(feature = "wasm-plugins")]
+        if !config.security.policy_plugins.is_empty() {
+            network_protocol.content_policy = Some(Arc::new(ContentPolicy::load(
+                &config.security.policy_plugins,
+                config.security.plugin_fuel,
+                config.security.plugin_memory,
+                config.security.policy_min_score,
+            )?));
+        }
+        #[cfg(not(feature = "wasm-plugins"))]
+        if !config.security.policy_plugins.is_empty() {
+            warn!(
+                "Content policy plugins are configured, but the node is built without `wasm-plugins` feature"
+            );
+        }
+        let network_protocol = Arc::new(network_protocol);
 
-        let dht_protocol = Arc::new(DHTProtocol::new(
+        let mut dht_protocol = DHTProtocol::new(
             routing_table.clone(),
             storage.clone(),
             Some(network_protocol.clone()),
-        ));
+        );
+        let key_presence = Arc::new(KeyPresenceIndex::new());
+
+        dht_protocol.alpha = config.dht.alpha.max(1) as usize;
+        dht_protocol.read_repair_limit = config.dht.read_repair_count.max(0) as usize;
+        dht_protocol.key_presence = Some(key_presence.clone());
+        dht_protocol.reputation = Some(network_protocol.reputation.clone());
+        if config.dht.negative_cache_base > 0.0 {
+            dht_protocol.negative_cache = Some(Arc::new(
+                NegativeCache::new(
+                    config.dht.negative_cache_base,
+                    config.dht.negative_cache_max,
+                )
+                .with_clock(clock.clone()),
+            ));
+        }
+        let dht_protocol = Arc::new(dht_protocol);
 
-        let popularity_exchanger = Arc::new(PopularityExchanger::new(
+        #[cfg(feature = "popularity")]
+        let mut popularity_exchanger = PopularityExchanger::new(
             network_protocol.clone(),
             popularity_ranker.clone(),
             Some(metrics_collector.clone()),
-        ));
+            node_type == NodeType::Seed,
+            config.popularity.global_update_interval as f64,
+            config.popularity.trending_window as f64,
+        );
+        #[cfg(feature = "popularity")]
+        {
+            popularity_exchanger.key_presence = Some(key_presence.clone());
+        }
+        #[cfg(feature = "popularity")]
+        let popularity_exchanger = Arc::new(popularity_exchanger);
 
-        let replicator = Arc::new(Replicator::new(
+        #[cfg(feature = "replication")]
+        let mut replicator = Replicator::new(
             dht_protocol.clone(),
             storage.clone(),
             5,
             10,
-        ));
+            Some(events.clone()),
+        );
+        #[cfg(feature = "replication")]
+        {
+            replicator.key_presence = Some(key_presence.clone());
+            replicator.metrics_collector = Some(metrics_collector.clone());
+            replicator.audit = Some(audit.clone());
+            replicator.disk_watchdog = Some(disk_watchdog.clone());
+            replicator.mode = mode.clone();
+            replicator.reputation = Some(network_protocol.reputation.clone());
+            if config.popularity.thread_bundles {
+                replicator.thread_bundles =
+                    Some(KeyManager::for_network(&config.network.network_id));
+                replicator.bundle_messages = config.popularity.thread_bundle_messages;
+                replicator.bundle_compression_level = config.storage.compression_level;
+            }
+        }
+        #[cfg(feature = "replication")]
+        let replicator = Arc::new(replicator);
+
+        #[cfg(feature = "port-mapping")]
+        let port_mapper = config.network.port_mapping.then(|| {
+            let gateway = config
+                .network
+                .nat_pmp_gateway
+                .as_ref()
+                .and_then(|gateway| gateway.parse().ok());
+            Arc::new(PortMapper::new(config.network.port_mapping_lease, gateway))
+        });
+        #[cfg(not(feature = "port-mapping"))]
+        if config.network.port_mapping {
+            warn!("Port mapping is on, but the node is built without `port-mapping` feature");
+        }
 
         Ok(Self {
             config,
@@ -181,17 +549,83 @@ impl BaseNode {
             routing_table,
             storage,
             transport,
+            #[cfg(feature = "popularity")]
             metrics_collector,
+            #[cfg(feature = "popularity")]
             popularity_ranker,
             network_protocol,
             dht_protocol,
+            #[cfg(feature = "popularity")]
             popularity_exchanger,
+            #[cfg(feature = "replication")]
             replicator,
-            is_running: Arc::new(RwLock::new(false)),
+            supervisor: Arc::new(Supervisor::new(events.clone(), span.clone())),
+            lifecycle: Arc::new(Lifecycle::new(events.clone())),
+            events,
+            key_presence,
+            #[cfg(feature = "signatures")]
+            signer,
+            audit,
+            admin_tokens,
+            disk_watchdog,
+            mode,
+            #[cfg(feature = "port-mapping")]
+            port_mapper,
+            span,
             start_time: Arc::new(RwLock::new(None)),
+            bootstrap_status: std::sync::Mutex::new(BootstrapStatus::default()),
         })
     }
 
+    /// Load node ID and node key, new node gets ID derived from its new key
+    #[cfg(feature = "signatures")]
+    fn load_identity(
+        config: &Config,
+        node_id_path: &std::path::Path,
+        rng: &NodeRng,
+    ) -> Result<(Vec<u8>, Arc<NodeSigner>), Box<dyn std::error::Error>> {
+        // Seeded simulations get reproducible node IDs
+        let new_keypair = || {
+            if rng.is_seeded() {
+                rng.with(generate_keypair_from)
+            } else {
+                generate_keypair()
+            }
+        };
+        let node_key_path = PathBuf::from(&config.node.node_key_file);
+        let mut node_key = load_private_key(&node_key_path);
+        let node_id_bytes = match load_node_id(node_id_path) {
+            Some(bytes) => {
+                info!(path = ?node_id_path, "Node ID loaded from file");
+                bytes
+            }
+            None => {
+                info!("Generating new node ID");
+                let (private_key, public_key) = new_keypair();
+                let bytes = node_id_from_public_key(&public_key).to_vec();
+                save_private_key(&private_key, &node_key_path)?;
+                save_node_id(&bytes, node_id_path)?;
+                node_key = Some(private_key);
+                bytes
+            }
+        };
+        let node_key = match node_key {
+            Some(key) => key,
+            None => {
+                let (private_key, _) = new_keypair();
+                save_private_key(&private_key, &node_key_path)?;
+                private_key
+            }
+        };
+        let signer = Arc::new(NodeSigner::new(node_key, node_id_bytes.clone()));
+        if !signer.is_bound_to_node_id() {
+            warn!(
+                "Node ID is not derived from node key, storage receipts of this node will be rejected"
+            );
+        }
+        Ok((node_id_bytes, signer))
+    }
+
     /// Автоматическое определение типа узла на основе свободного места
     fn detect_node_type(config: &Config) -> Option<NodeType> {
         let path = &config.storage.data_dir;
@@ -212,52 +646,271 @@ impl BaseNode {
         Some(NodeType::Mobile)
     }
 
+    /// Check the environment before start
+    ///
+    /// Port binding, writable `data_dir` with enough free space, LMDB map size, system
+    /// clock and reachability of bootstrap nodes. Node should not be started if the
+    /// report did not pass.
+    pub async fn preflight(&self) -> PreflightReport {
+        let mut report = PreflightReport::default();
+        let storage = &self.config.storage;
+
+        match self.transport.probe_bind().await {
+            Ok(port) => report.push("port", CheckStatus::Passed, format!("UDP port {}", port)),
+            Err(_) => report.push(
+                "port",
+                CheckStatus::Failed,
+                format!("UDP port {} can not be bound", self.transport.port),
+            ),
+        }
+
+        preflight::check_data_dir(&mut report, &storage.data_dir);
+        preflight::check_free_space(
+            &mut report,
+            &storage.data_dir,
+            storage.min_free_space,
+            storage.max_storage_size,
+        );
+        preflight::check_map_size(&mut report, &storage.data_dir, storage.max_storage_size);
+
+        let wait = duration_from_secs(self.config.dht.ping_timeout.max(0.1));
+        let mut offsets = Vec::new();
+        let bootstrap_nodes = &self.config.network.bootstrap_nodes;
+        for addr_str in bootstrap_nodes {
+            let Ok(addr) = addr_str.parse::<std::net::SocketAddr>() else {
+                report.push(
+                    "bootstrap",
+                    CheckStatus::Warning,
+                    format!("{} is not a socket address", addr_str),
+                );
+                continue;
+            };
+            if let Some(offset) =
+                preflight::probe_bootstrap(&self.network_protocol, addr, wait).await
+            {
+                offsets.push(offset);
+            }
+        }
+        preflight::check_clock(&mut report, &offsets);
+
+        if bootstrap_nodes.is_empty() {
+            report.push(
+                "bootstrap",
+                CheckStatus::Passed,
+                "no bootstrap nodes, node starts a new network",
+            );
+        } else if offsets.is_empty() {
+            report.push(
+                "bootstrap",
+                CheckStatus::Warning,
+                "no bootstrap node answered",
+            );
+        } else {
+            report.push(
+                "bootstrap",
+                CheckStatus::Passed,
+                format!(
+                    "{} of {} bootstrap nodes answered",
+                    offsets.len(),
+                    bootstrap_nodes.len()
+                ),
+            );
+        }
+
+        report
+    }
+
+    /// Current stage of the node life
+    pub fn status(&self) -> NodeStatus {
+        self.lifecycle.status()
+    }
+
+    /// Result of the last bootstrap
+    pub fn bootstrap_status(&self) -> BootstrapStatus {
+        *self.bootstrap_status.lock().unwrap()
+    }
+
+    fn set_bootstrap_status(&self, status: BootstrapStatus) {
+        *self.bootstrap_status.lock().unwrap() = status;
+    }
+
     /// Start socket and listen all tasks
+    ///
+    /// Node which is already started is not touched. Concurrent start and stop wait for
+    /// each other.
     pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let mut running = self.is_running.write().await;
-        if *running {
+        let _transition = self.lifecycle.begin_transition().await;
+        if !self.lifecycle.advance(
+            &[NodeStatus::Created, NodeStatus::Stopped],
+            NodeStatus::Bootstrapping,
+        ) {
             return Ok(());
         }
 
         info!(node_id = %hex::encode(&self.node_id.0[..8]), "Starting node");
 
-        *running = true;
-        *self.start_time.write().await = Some(get_now_f64());
+        *self.start_time.write().await = Some(self.storage.now());
+
+        // First node of the network has nobody to learn from
+        if self.config.dht.warm_up
+            && !self.config.network.bootstrap_nodes.is_empty()
+            && self.mode.get() == NodeMode::Normal
+        {
+            self.set_mode(NodeMode::WarmingUp);
+        }
 
         let net = self.network_protocol.clone();
-        net.start().await?;
+        if let Err(e) = net.start().instrument(self.span.clone()).await {
+            self.lifecycle
+                .advance(&[NodeStatus::Bootstrapping], NodeStatus::Stopped);
+            return Err(e.into());
+        }
+
+        self.bootstrap().instrument(self.span.clone()).await;
 
-        self.bootstrap().await;
+        if self.mode.is_warming_up() {
+            let node_ref_warm_up = Arc::new(self.clone_ptrs());
+            self.supervisor
+                .spawn("warm_up", self.lifecycle.clone(), move || {
+                    Self::warm_up_loop(node_ref_warm_up.clone())
+                });
+        }
 
         let node_ref = Arc::new(self.clone_ptrs());
-        tokio::spawn(async move {
-            Self::background_loop(node_ref).await;
-        });
+        self.supervisor
+            .spawn("background", self.lifecycle.clone(), move || {
+                Self::background_loop(node_ref.clone())
+            });
 
-        let node_ref_pop = Arc::new(self.clone_ptrs());
-        tokio::spawn(async move {
-            Self::popularity_loop(node_ref_pop).await;
-        });
+        #[cfg(feature = "popularity")]
+        {
+            let node_ref_pop = Arc::new(self.clone_ptrs());
+            self.supervisor
+                .spawn("popularity", self.lifecycle.clone(), move || {
+                    Self::popularity_loop(node_ref_pop.clone())
+                });
+        }
+
+        if self.storage.durability() == Durability::WriteBack {
+            let node_ref_write_back = Arc::new(self.clone_ptrs());
+            self.supervisor
+                .spawn("write_back", self.lifecycle.clone(), move || {
+                    Self::write_back_loop(node_ref_write_back.clone())
+                });
+        }
+
+        let node_ref_disk = Arc::new(self.clone_ptrs());
+        self.supervisor
+            .spawn("disk_watchdog", self.lifecycle.clone(), move || {
+                Self::disk_watchdog_loop(node_ref_disk.clone())
+            });
+
+        let node_ref_gossip = Arc::new(self.clone_ptrs());
+        self.supervisor
+            .spawn("gossip", self.lifecycle.clone(), move || {
+                Self::gossip_loop(node_ref_gossip.clone())
+            });
+
+        let node_ref_listener = Arc::new(self.clone_ptrs());
+        self.supervisor
+            .spawn("gossip_listener", self.lifecycle.clone(), move || {
+                Self::gossip_listener_loop(node_ref_listener.clone())
+            });
+
+        if self.config.dht.partition_merge_peers > 0 {
+            let node_ref_partition = Arc::new(self.clone_ptrs());
+            self.supervisor
+                .spawn("partition", self.lifecycle.clone(), move || {
+                    Self::partition_loop(node_ref_partition.clone())
+                });
+        }
 
+        if self.config.dht.transfer_on_join {
+            let node_ref_transfer = Arc::new(self.clone_ptrs());
+            self.supervisor
+                .spawn("key_transfer", self.lifecycle.clone(), move || {
+                    Self::key_transfer_loop(node_ref_transfer.clone())
+                });
+        }
+
+        #[cfg(feature = "port-mapping")]
+        if self.port_mapper.is_some() {
+            let node_ref_mapping = Arc::new(self.clone_ptrs());
+            self.supervisor
+                .spawn("port_mapping", self.lifecycle.clone(), move || {
+                    Self::port_mapping_loop(node_ref_mapping.clone())
+                });
+        }
+
+        #[cfg(feature = "webhooks")]
+        if !self.config.webhooks.is_empty() {
+            let node_ref_webhooks = Arc::new(self.clone_ptrs());
+            self.supervisor
+                .spawn("webhooks", self.lifecycle.clone(), move || {
+                    Self::webhook_loop(node_ref_webhooks.clone())
+                });
+        }
+        #[cfg(not(feature = "webhooks"))]
+        if !self.config.webhooks.is_empty() {
+            warn!("Webhooks are configured, but the node is built without `webhooks` feature");
+        }
+
+        #[cfg(feature = "signatures")]
+        if self.config.security.blocklist_subscription.is_some() {
+            let node_ref_blocklist = Arc::new(self.clone_ptrs());
+            self.supervisor
+                .spawn("blocklist", self.lifecycle.clone(), move || {
+                    Self::blocklist_loop(node_ref_blocklist.clone())
+                });
+        }
+        #[cfg(not(feature = "signatures"))]
+        if self.config.security.blocklist_subscription.is_some() {
+            warn!(
+                "Blocklist subscription is configured, but the node is built without `signatures` feature"
+            );
+        }
+
+        self.lifecycle
+            .advance(&[NodeStatus::Bootstrapping], NodeStatus::Ready);
         Ok(())
     }
 
     /// Stop the socket and leave all resources
+    ///
+    /// Node which is not running is not touched.
     pub async fn stop(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let mut running = self.is_running.write().await;
-        if !*running {
+        let _transition = self.lifecycle.begin_transition().await;
+        if !self.lifecycle.advance(
+            &[
+                NodeStatus::Bootstrapping,
+                NodeStatus::Ready,
+                NodeStatus::Degraded,
+            ],
+            NodeStatus::Stopping,
+        ) {
             return Ok(());
         }
 
         info!("Stopping node");
-        *running = false;
 
+        #[cfg(feature = "port-mapping")]
+        if let Some(mapper) = &self.port_mapper {
+            mapper.unmap().await;
+        }
         self.network_protocol.clone().stop().await;
+        self.supervisor.abort_all();
+        // Next start bootstraps again, its status must not tell about the old run
+        self.set_bootstrap_status(BootstrapStatus::NotStarted);
 
+        if let Err(e) = self.storage.flush_writes().await {
+            error!(error = %e, "Failed to commit write-back puts during stop");
+        }
         if let Err(e) = self.save_state().await {
             error!(error = %e, "Failed to save node state during stop");
         }
 
+        self.lifecycle
+            .advance(&[NodeStatus::Stopping], NodeStatus::Stopped);
         info!("Node stopped");
         Ok(())
     }
@@ -275,7 +928,7 @@ impl BaseNode {
         let buckets_with_nodes = rt.buckets.iter().filter(|b| !b.nodes.is_empty()).count();
 
         let state = serde_json::json!({
-            "node_id": hex::encode(self.node_id.0),
+            "node_id": self.node_id.to_string(),
             "node_type": self.node_type.to_string(),
             "start_time": *self.start_time.read().await,
             "is_running": false,
@@ -283,6 +936,7 @@ impl BaseNode {
                 "total_nodes": total_nodes,
                 "buckets_with_nodes": buckets_with_nodes,
             },
+            "storage_stats": self.storage.stats(),
         });
 
         let file = std::fs::File::create(state_file)?;
@@ -303,7 +957,7 @@ impl BaseNode {
         let state: serde_json::Value = serde_json::from_reader(file)?;
 
         if let Some(saved_id_hex) = state.get("node_id").and_then(|v| v.as_str()) {
-            let current_id_hex = hex::encode(self.node_id.0);
+            let current_id_hex = self.node_id.to_string();
             if saved_id_hex != current_id_hex {
                 warn!(
                     saved = %&saved_id_hex[..16],
@@ -322,32 +976,43 @@ impl BaseNode {
         let bootstrap_nodes = &self.config.network.bootstrap_nodes;
         if bootstrap_nodes.is_empty() {
             warn!("No bootstrap nodes configured");
+            self.set_bootstrap_status(BootstrapStatus::NoBootstrapNodes);
             return;
         }
+        self.set_bootstrap_status(BootstrapStatus::InProgress);
+
+        let mut connected = false;
 
         for addr_str in bootstrap_nodes {
             if let Ok(addr) = addr_str.parse::<std::net::SocketAddr>() {
                 let boot_node =
                     Node::new(NodeID::new([0u8; 20]), addr.ip().to_string(), addr.port());
 
-                if self.network_protocol.ping(&boot_node).await {
-                    info!(address = %addr_str, "Bootstrap node connected");
-                    self.routing_table.write().await.add_node(boot_node);
-
-                    let _ = self.dht_protocol.find_node(&self.node_id).await;
+                match self.network_protocol.ping_remote(&boot_node).await {
+                    Some(node) => {
+                        info!(
+                            address = %addr_str,
+                            node_id = %hex::encode(&node.node_id.0[..8]),
+                            "Bootstrap node connected"
+                        );
+                        connected = true;
+                        let _ = self.dht_protocol.find_node(&self.node_id).await;
+                    }
+                    None => warn!(address = %addr_str, "Bootstrap node is not reachable"),
                 }
             }
         }
+        self.set_bootstrap_status(if connected {
+            BootstrapStatus::Connected
+        } else {
+            BootstrapStatus::Unreachable
+        });
     }
 
     /// Exchange data between nodes
+    #[cfg(feature = "popularity")]
     pub async fn exchange_popularity(&self) -> Result<(), RhizomeError> {
-        let all_metrics = self
-            .metrics_collector
-            .read()
-            .await
-            .get_all_metrics()
-            .clone();
+        let all_metrics = self.metrics_collector.get_all_metrics();
         if all_metrics.is_empty() {
             return Ok(());
         }
@@ -376,19 +1041,41 @@ impl BaseNode {
 
     /// Main loop which work on background side and cleanup storage by TTL
     async fn background_loop(node: Arc<BaseNodePtrs>) {
-        while *node.is_running.read().await {
+        while node.lifecycle.is_running() {
+            node.update_health_status();
             if let Ok(deleted) = node.storage.cleanup_expired().await
                 && deleted > 0
             {
                 debug!(count = deleted, "Cleaned up expired data");
             }
+            node.network_protocol.blacklist.cleanup_expired();
+            node.network_protocol.reputation.cleanup_expired();
+            node.network_protocol.peer_stats.cleanup_expired();
+            if let Some(pointers) = &node.network_protocol.holder_pointers {
+                pointers.cleanup_expired();
+            }
+            if let Ok(forgotten) = node.storage.cleanup_processed().await
+                && forgotten > 0
+            {
+                debug!(count = forgotten, "Forgot old processed requests");
+            }
+            // Reads of our copies by peers are counted in popularity too
+            #[cfg(feature = "popularity")]
+            if let Ok(reads) = node.storage.flush_access().await {
+                for (key, access) in reads {
+                    node.metrics_collector
+                        .record_reads(key, access.reads, access.last_access);
+                }
+            }
+            #[cfg(not(feature = "popularity"))]
+            let _ = node.storage.flush_access().await;
 
             let refresh_interval = node.config.dht.refresh_interval as f64;
             let mut buckets_to_refresh = Vec::new();
 
             {
                 let rt = node.routing_table.read().await;
-                let now = get_now_f64();
+                let now = node.storage.now();
                 for (i, bucket) in rt.buckets.iter().enumerate() {
                     if !bucket.nodes.is_empty() && (now - bucket.last_updated) > refresh_interval {
                         buckets_to_refresh.push(i);
@@ -402,33 +1089,189 @@ impl BaseNode {
                 debug!(index = idx, "Bucket refreshed");
             }
 
+            Self::keepalive_idle_nodes(&node).await;
+
             tokio::time::sleep(Duration::from_secs(60)).await;
         }
     }
 
+    /// Group commit of write-back puts
+    async fn write_back_loop(node: Arc<BaseNodePtrs>) {
+        let interval = duration_from_secs(node.config.storage.write_back_interval.max(0.01));
+        while node.lifecycle.is_running() {
+            tokio::time::sleep(interval).await;
+            match node.storage.flush_writes().await {
+                Ok(count) if count > 0 => debug!(count, "Write-back puts committed"),
+                Ok(_) => {}
+                Err(e) => error!(error = %e, "Failed to commit write-back puts"),
+            }
+        }
+    }
+
+    /// Fill routing table and wait for clock estimation, then leave warm-up mode
+    ///
+    /// Node is ready when routing table has `warm_up_min_peers` nodes and clock offsets of
+    /// [`WARM_UP_MIN_CLOCKS`] peers are known. After `warm_up_timeout` it starts serving
+    /// anyway, small networks may have less peers.
+    async fn warm_up_loop(node: Arc<BaseNodePtrs>) {
+        let started = node.storage.now();
+        let mode = &node.network_protocol.mode;
+
+        while node.lifecycle.is_running() && mode.is_warming_up() {
+            let peers = node.routing_table.read().await.get_all_nodes().len();
+            let min_peers = node.config.dht.warm_up_min_peers;
+            let ready = peers >= min_peers
+                && node.network_protocol.clock.peer_count() >= min_peers.min(WARM_UP_MIN_CLOCKS);
+            let timed_out = node.storage.now() - started >= node.config.dht.warm_up_timeout as f64;
+
+            if ready || timed_out {
+                if mode.finish_warm_up() {
+                    info!(peers = peers, timed_out = timed_out, "Warm-up finished");
+                    node.events.emit(NodeEvent::ModeChanged {
+                        mode: NodeMode::Normal,
+                    });
+                }
+                return;
+            }
+
+            let buckets: Vec<usize> = node
+                .routing_table
+                .read()
+                .await
+                .buckets
+                .iter()
+                .enumerate()
+                .filter(|(_, bucket)| !bucket.nodes.is_empty())
+                .map(|(i, _)| i)
+                .collect();
+            for idx in buckets {
+                let random_id = node.generate_random_id_for_bucket(idx);
+                let _ = node.dht_protocol.find_node(&random_id).await;
+            }
+
+            tokio::time::sleep(Duration::from_secs(WARM_UP_CHECK_INTERVAL)).await;
+        }
+    }
+
+    /// Watch free disk space and evict expired data while the node is degraded
+    ///
+    /// In degraded mode checks run more often and every check cleans storage.
+    async fn disk_watchdog_loop(node: Arc<BaseNodePtrs>) {
+        const DEGRADED_CHECK_INTERVAL: u64 = 5;
+        let watchdog = &node.disk_watchdog;
+
+        while node.lifecycle.is_running() {
+            match watchdog.check() {
+                Some(true) => {
+                    let free_bytes = watchdog.status().free_bytes;
+                    warn!(
+                        free_bytes = free_bytes,
+                        min_free_bytes = watchdog.min_free,
+                        "Disk space is low, node is degraded"
+                    );
+                    node.events.emit(NodeEvent::DiskSpaceLow {
+                        free_bytes,
+                        min_free_bytes: watchdog.min_free,
+                    });
+                }
+                Some(false) => {
+                    let free_bytes = watchdog.status().free_bytes;
+                    info!(free_bytes = free_bytes, "Disk space recovered");
+                    node.events
+                        .emit(NodeEvent::DiskSpaceRecovered { free_bytes });
+                }
+                None => {}
+            }
+            node.update_health_status();
+
+            let interval = if watchdog.is_degraded() {
+                if let Ok(deleted) = node.storage.cleanup_expired().await
+                    && deleted > 0
+                {
+                    info!(count = deleted, "Evicted expired data to free disk space");
+                }
+                let _ = node.storage.cleanup_processed().await;
+                DEGRADED_CHECK_INTERVAL
+            } else {
+                node.config.storage.disk_check_interval.max(1) as u64
+            };
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    }
+
+    /// Ping only peers which were silent longer than `keepalive_idle`
+    ///
+    /// Peers which send us any message are refreshed by piggybacking, so they are not pinged.
+    async fn keepalive_idle_nodes(node: &BaseNodePtrs) {
+        const MAX_PINGS_PER_ROUND: usize = 8;
+
+        let idle = node
+            .routing_table
+            .read()
+            .await
+            .idle_nodes(node.config.dht.keepalive_idle as f64);
+
+        for mut peer in idle.into_iter().take(MAX_PINGS_PER_ROUND) {
+            if node.dht_protocol.ping(&mut peer).await {
+                continue;
+            }
+
+            let mut rt = node.routing_table.write().await;
+            let failed = rt.record_failed_ping(&peer.node_id);
+            if failed >= node.config.dht.max_failed_pings.max(1) as u32 {
+                rt.remove_node(&peer.node_id);
+                debug!(
+                    node_id = %hex::encode(&peer.node_id.0[..8]),
+                    failed_pings = failed,
+                    "Removed unresponsive node"
+                );
+            }
+        }
+    }
+
     /// Main fron loop which work with metrics
+    #[cfg(feature = "popularity")]
     async fn popularity_loop(node: Arc<BaseNodePtrs>) {
         let mut last_update = 0.0;
         let mut last_exchange = 0.0;
+        let mut last_cleanup = node.storage.now();
 
-        while *node.is_running.read().await {
-            let now = get_now_f64();
+        while node.lifecycle.is_running() {
+            let now = node.storage.now();
 
             if now - last_update >= node.config.popularity.update_interval as f64 {
-                let metrics = node
-                    .metrics_collector
-                    .read()
-                    .await
-                    .get_all_metrics()
-                    .clone();
+                let metrics = node.metrics_collector.get_all_metrics();
                 let ranked = node.popularity_ranker.rank_items(&metrics, Some(100));
+                node.popularity_exchanger.record_ranking(&ranked).await;
 
                 for item in &ranked {
-                    if item.score >= node.config.popularity.popularity_threshold {
-                        let _ = node.storage.extend_ttl(item.key.clone(), 1.0).await;
+                    if item.score >= node.config.popularity.popularity_threshold
+                        && matches!(
+                            node.storage
+                                .boost_ttl(item.key.clone(), node.config.storage.popular_ttl)
+                                .await,
+                            Ok(true)
+                        )
+                    {
+                        node.metrics_collector.mark_promoted(item.key.clone());
                     }
                 }
 
+                let demoted = node.metrics_collector.take_demoted(
+                    |m| node.popularity_ranker.calculate_score(m, true),
+                    node.config.popularity.demotion_threshold,
+                );
+                for key in &demoted {
+                    Self::demote_content(&node, key).await;
+                }
+                if !demoted.is_empty() {
+                    info!(
+                        count = demoted.len(),
+                        "Demoted content which is not popular anymore"
+                    );
+                }
+
+                #[cfg(feature = "replication")]
                 node.replicator
                     .replicate_popular_items(ranked, node.config.popularity.popularity_threshold)
                     .await;
@@ -437,71 +1280,735 @@ impl BaseNode {
             }
 
             if now - last_exchange >= node.config.popularity.exchange_interval as f64 {
-                let metrics = node
-                    .metrics_collector
-                    .read()
-                    .await
-                    .get_all_metrics()
-                    .clone();
-                let neighbors = node.routing_table.read().await.get_all_nodes();
+                Self::exchange_with_neighbors(&node).await;
+                last_exchange = now;
+            }
 
+            // Seed nodes refresh global ranking in their own loop
+            if !node.popularity_exchanger.is_seed {
                 node.popularity_exchanger
-                    .exchange_top_items(metrics, neighbors, 100)
+                    .refresh_global_ranking_if_stale()
                     .await;
-                last_exchange = now;
             }
 
-            node.metrics_collector.write().await.update_all_freshness();
+            if now - last_cleanup >= node.config.popularity.metrics_cleanup_interval as f64 {
+                node.metrics_collector
+                    .cleanup_old_metrics(node.config.popularity.metrics_max_age_days);
+                last_cleanup = now;
+            }
+
+            node.metrics_collector.update_all_freshness();
 
             tokio::time::sleep(Duration::from_secs(60)).await;
         }
     }
 
-    /// Generate uniq id for Kademlia Bucket
-    fn generate_random_id_for_bucket(&self, bucket_index: usize) -> NodeID {
-        let mut rng = rand::thread_rng();
-        let mut random_id = self.node_id.0;
+    /// Exchange top items with all nodes of routing table
+    #[cfg(feature = "popularity")]
+    async fn exchange_with_neighbors(node: &BaseNodePtrs) {
+        let metrics = node.metrics_collector.get_all_metrics();
+        let neighbors = node.routing_table.read().await.get_all_nodes();
 
-        let byte_idx = bucket_index / 8;
-        let bit_idx = bucket_index % 8;
+        match node.storage.keys().await {
+            Ok(keys) => node.key_presence.set_local_keys(&keys),
+            Err(e) => warn!(error = %e, "Failed to build key filter"),
+        }
 
-        if byte_idx < 20 {
-            let flip_bit = 0x80 >> bit_idx;
-            random_id[byte_idx] ^= flip_bit;
+        node.popularity_exchanger
+            .exchange_top_items(metrics, neighbors, 100)
+            .await;
+    }
 
-            for (i, byte) in random_id
-                .iter_mut()
-                .enumerate()
-                .skip(byte_idx)
-                .take(20 - byte_idx)
+    /// Demote content which is not popular anymore
+    ///
+    /// Boosted TTL returns to the original one. Value which we keep only as an extra
+    /// replica _(we did not publish it and are not one of its k closest nodes)_ is removed.
+    #[cfg(feature = "popularity")]
+    async fn demote_content(node: &BaseNodePtrs, key: &Key) {
+        let stored = match node.storage.get_with_meta(key.clone()).await {
+            Ok(Some(stored)) => stored,
+            Ok(None) => return,
+            Err(e) => {
+                warn!(key = %key.short_hex(), error = %e, "Failed to read demoted value");
+                return;
+            }
+        };
+
+        let result = if stored.origin.hops > 0 && !node.dht_protocol.is_responsible_for(key).await {
+            node.storage.delete(key.clone()).await
+        } else {
+            node.storage
+                .demote(key.clone(), node.config.storage.min_guaranteed_ttl)
+                .await
+                .map(|_| ())
+        };
+        if let Err(e) = result {
+            warn!(key = %key.short_hex(), error = %e, "Failed to demote value");
+        }
+    }
+
+    /// Loop of cluster-wide announcements
+    ///
+    /// Announce our version once, seeds announce themselves every 10 rounds.
+    /// Every round we exchange digest with one random peer.
+    async fn gossip_loop(node: Arc<BaseNodePtrs>) {
+        let net = node.network_protocol.clone();
+        let interval = node.config.network.gossip_interval.max(1) as u64;
+
+        net.gossip_broadcast(
+            GossipTopic::VersionTelemetry,
+            serde_json::json!({
+                "version": env!("CARGO_PKG_VERSION"),
+                "node_type": node.config.node.node_type
+            }),
+        )
+        .await;
+
+        #[cfg(feature = "popularity")]
+        let mut round: u64 = 0;
+        while node.lifecycle.is_running() {
+            #[cfg(feature = "popularity")]
+            if node.popularity_exchanger.is_seed && round.is_multiple_of(10) {
+                net.gossip_broadcast(
+                    GossipTopic::SeedAnnouncement,
+                    serde_json::json!({"archive": node.config.node.archive_volunteer}),
+                )
+                .await;
+            }
+
+            let peers = node.routing_table.read().await.get_all_nodes();
+            if let Some(peer) = net.gossip.select_peers(peers).into_iter().next()
+                && let Err(e) = net.gossip_pull_remote(&peer).await
+            {
+                debug!(error = %e, "Gossip digest exchange failed");
+            }
+
+            net.gossip.cleanup_expired();
+            #[cfg(feature = "popularity")]
             {
-                let mask = if i == byte_idx {
-                    (1 << (7 - bit_idx)) - 1
-                } else {
-                    0xFF
+                round += 1;
+            }
+
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    }
+
+    /// Handle new gossip messages until the node stops
+    async fn gossip_listener_loop(node: Arc<BaseNodePtrs>) {
+        let mut receiver = node.network_protocol.gossip.subscribe();
+        loop {
+            let received = tokio::select! {
+                received = receiver.recv() => received,
+                _ = node.lifecycle.stopping() => break,
+            };
+            match received {
+                Ok(message) => Self::handle_gossip(&node, message).await,
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!(skipped = skipped, "Gossip listener skipped messages");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Push stored keys to nodes which join the routing table closer to them than us
+    async fn key_transfer_loop(node: Arc<BaseNodePtrs>) {
+        let mut receiver = node.routing_table.read().await.subscribe();
+        loop {
+            let received = tokio::select! {
+                received = receiver.recv() => received,
+                _ = node.lifecycle.stopping() => break,
+            };
+            match received {
+                Ok(RoutingEvent::NodeAdded(new_node)) => {
+                    if !node.lifecycle.is_running() {
+                        break;
+                    }
+                    if let Err(e) = node.dht_protocol.transfer_keys_to(&new_node).await {
+                        warn!(error = %e, "Failed to transfer keys to new node");
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!(skipped = skipped, "Key transfer skipped routing events");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Watch nodes joining routing table and reconverge when a network partition heals
+    ///
+    /// Nodes which join during warm-up are expected, they are not counted. Other nodes are
+    /// counted only after they answer our ping with their node ID.
+    async fn partition_loop(node: Arc<BaseNodePtrs>) {
+        let mut detector = PartitionDetector::new(
+            node.network_protocol.node_id,
+            node.config.dht.partition_merge_peers,
+            node.config.dht.partition_merge_window,
+        );
+        let mut receiver = node.routing_table.read().await.subscribe();
+        loop {
+            let received = tokio::select! {
+                received = receiver.recv() => received,
+                _ = node.lifecycle.stopping() => break,
+            };
+            match received {
+                Ok(RoutingEvent::NodeAdded(new_node)) => {
+                    if !node.lifecycle.is_running() {
+                        break;
+                    }
+                    let Ok(address) = new_node.address.parse::<IpAddr>() else {
+                        continue;
+                    };
+                    if node.network_protocol.mode.is_warming_up()
+                        || !detector.counts(&new_node.node_id, address, node.storage.now())
+                    {
+                        continue;
+                    }
+                    let verified = node
+                        .network_protocol
+                        .ping_remote(&new_node)
+                        .await
+                        .is_some_and(|answered| answered.node_id == new_node.node_id);
+                    if !verified
+                        || !detector.record_arrival(&new_node.node_id, address, node.storage.now())
+                    {
+                        continue;
+                    }
+                    info!(
+                        peers = node.config.dht.partition_merge_peers,
+                        window = node.config.dht.partition_merge_window,
+                        "Network partition healed, reconverging data and rankings"
+                    );
+                    Self::reconverge(&node).await;
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!(
+                        skipped = skipped,
+                        "Partition detector skipped routing events"
+                    );
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Republish our keys and exchange popularity without waiting for the timers
+    async fn reconverge(node: &BaseNodePtrs) {
+        // Keys missing in our part of the network may be in the other one
+        if let Some(cache) = &node.dht_protocol.negative_cache {
+            cache.clear();
+        }
+        match node.dht_protocol.republish_keys().await {
+            Ok(count) => info!(count = count, "Republished keys after partition merge"),
+            Err(e) => warn!(error = %e, "Failed to republish keys after partition merge"),
+        }
+        #[cfg(feature = "popularity")]
+        {
+            Self::exchange_with_neighbors(node).await;
+            node.popularity_exchanger.refresh_global_ranking().await;
+        }
+    }
+
+    /// Map our port on the router and renew the mapping at half of the granted lifetime
+    #[cfg(feature = "port-mapping")]
+    async fn port_mapping_loop(node: Arc<BaseNodePtrs>) {
+        let Some(mapper) = node.port_mapper.clone() else {
+            return;
+        };
+        let port = node.network_protocol.transport.get_address().await.port();
+        let mut mapped = false;
+        while node.lifecycle.is_running() {
+            let renew_in = match mapper.map(port).await {
+                Some(mapping) => {
+                    if !mapped {
+                        info!(
+                            method = ?mapping.method,
+                            external_ip = ?mapping.external_ip,
+                            external_port = mapping.external_port,
+                            "Port mapped on the router"
+                        );
+                        mapped = true;
+                    }
+                    mapping.renew_in(get_now_f64())
+                }
+                None => {
+                    warn!("Router does not support port mapping or refused it");
+                    mapped = false;
+                    Duration::from_secs(mapper.lease as u64 / 2)
+                }
+            };
+            tokio::time::sleep(renew_in).await;
+        }
+    }
+
+    /// Current mapping of our port on the router _(None if there is no mapping)_
+    pub async fn port_mapping(&self) -> Option<serde_json::Value> {
+        #[cfg(feature = "port-mapping")]
+        if let Some(mapper) = &self.port_mapper {
+            return mapper
+                .mapping()
+                .await
+                .and_then(|mapping| serde_json::to_value(mapping).ok());
+        }
+        None
+    }
+
+    /// Send node events to configured webhooks
+    #[cfg(feature = "webhooks")]
+    async fn webhook_loop(node: Arc<BaseNodePtrs>) {
+        let dispatcher = WebhookDispatcher::new(
+            &node.network_protocol.node_id.0,
+            node.config.webhooks.clone(),
+        );
+        let mut receiver = node.events.subscribe();
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    dispatcher.dispatch(&event).await;
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(skipped = skipped, "Webhooks skipped node events");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Refresh the subscribed blocklist from the DHT
+    ///
+    /// List is applied only if it is signed by the subscribed author and is newer than the
+    /// applied one, so an old copy on some holder can not roll back the list. Applied list
+    /// is published again on every refresh, so it lives while it has subscribers.
+    #[cfg(feature = "signatures")]
+    async fn blocklist_loop(node: Arc<BaseNodePtrs>) {
+        let Some(subscription) = node.config.security.blocklist_subscription.as_deref() else {
+            return;
+        };
+        let Some(author_id) = hex::decode(subscription.trim())
+            .ok()
+            .filter(|id| id.len() == 20)
+        else {
+            warn!(
+                author = subscription,
+                "Blocklist subscription is not a node ID"
+            );
+            return;
+        };
+        let author = hex::encode(&author_id);
+        let key =
+            KeyManager::for_network(&node.config.network.network_id).get_blocklist_key(&author);
+        let blacklist = node.network_protocol.blacklist.clone();
+        let interval = node.config.security.blocklist_interval();
+        let ttl = node.config.security.blocklist_ttl();
+
+        let mut applied_at = 0.0;
+        while node.lifecycle.is_running() {
+            // Local copy is not read, it would hide newer lists of the network
+            match node.dht_protocol.find_remote_versions(&key).await {
+                Ok(versions) => {
+                    let mut newest: Option<(SignedBlocklist, Vec<u8>)> = None;
+                    let mut invalid = 0;
+                    for version in versions {
+                        match SignedBlocklist::from_bytes(&version.value) {
+                            Some(signed) if signed.signer_id == author_id && signed.verify() => {
+                                if newest.as_ref().is_none_or(|(newest, _)| {
+                                    signed.list.created_at > newest.list.created_at
+                                }) {
+                                    newest = Some((signed, version.value));
+                                }
+                            }
+                            _ => invalid += 1,
+                        }
+                    }
+                    if invalid > 0 {
+                        warn!(author = %author, copies = invalid, "Subscribed blocklist has invalid signature");
+                        if let Some(audit) = &blacklist.audit {
+                            audit.record(
+                                AuditEventKind::SignatureFailure,
+                                Some(&author_id),
+                                None,
+                                "invalid subscribed blocklist",
+                            );
+                        }
+                    }
+                    match newest {
+                        Some((signed, data)) if signed.list.created_at >= applied_at => {
+                            if signed.list.created_at > applied_at {
+                                applied_at = signed.list.created_at;
+                                let count = blacklist.set_subscribed(&signed.list, &author);
+                                info!(author = %author, entries = count, "Subscribed blocklist refreshed");
+                            }
+                            if let Err(e) = node
+                                .dht_protocol
+                                .store_record(&key, &data, ttl, RECORD_BLOCKLIST)
+                                .await
+                            {
+                                debug!(author = %author, error = %e, "Subscribed blocklist is not republished");
+                            }
+                        }
+                        Some(_) => {
+                            debug!(author = %author, "Network has only older subscribed blocklists")
+                        }
+                        None => debug!(author = %author, "Subscribed blocklist is not found"),
+                    }
+                }
+                Err(e) => debug!(author = %author, error = %e, "Subscribed blocklist is not found"),
+            }
+            tokio::time::sleep(Duration::from_secs_f64(interval)).await;
+        }
+    }
+
+    /// React on new gossip message
+    async fn handle_gossip(node: &BaseNodePtrs, message: GossipMessage) {
+        if message.origin == node.network_protocol.node_id.0 {
+            return;
+        }
+
+        match message.topic {
+            #[cfg(feature = "popularity")]
+            GossipTopic::SeedAnnouncement => {
+                node.popularity_exchanger
+                    .seed_registry
+                    .write()
+                    .await
+                    .announce_seed(
+                        message.origin,
+                        message.data["archive"].as_bool().unwrap_or(false),
+                    );
+            }
+            GossipTopic::VersionTelemetry => {
+                debug!(
+                    origin = %hex::encode(&message.origin[..message.origin.len().min(8)]),
+                    version = %message.data["version"],
+                    "Peer version announced"
+                );
+            }
+            GossipTopic::Moderation => {
+                debug!(id = %message.id, "Moderation record received");
+            }
+            #[cfg(feature = "signatures")]
+            GossipTopic::IdentityRotation => {
+                let link: IdentityLink = match serde_json::from_value(message.data) {
+                    Ok(link) => link,
+                    Err(_) => return,
                 };
-                *byte ^= rng.r#gen::<u8>() & mask;
+                if link.old_id != message.origin || !link.verify() {
+                    warn!(id = %message.id, "Invalid identity link dropped");
+                    return;
+                }
+                Self::rebind_peer(node, &link).await;
             }
+            #[cfg(not(feature = "signatures"))]
+            GossipTopic::IdentityRotation => {}
+            #[cfg(not(feature = "popularity"))]
+            GossipTopic::SeedAnnouncement => {}
         }
-        NodeID::new(random_id)
     }
 
-    pub async fn find_value(&self, key: &[u8]) -> Result<Vec<u8>, RhizomeError> {
+    /// Replace old ID of the rotated peer with the new one in our routing table
+    #[cfg(feature = "signatures")]
+    async fn rebind_peer(node: &BaseNodePtrs, link: &IdentityLink) {
+        let (Ok(old_id), Ok(new_id)) = (
+            <[u8; 20]>::try_from(&link.old_id[..]),
+            <[u8; 20]>::try_from(&link.new_id[..]),
+        ) else {
+            return;
+        };
+        let (old_id, new_id) = (NodeID::new(old_id), NodeID::new(new_id));
+
+        let mut routing_table = node.routing_table.write().await;
+        let Some(peer) = routing_table
+            .get_all_nodes()
+            .into_iter()
+            .find(|n| n.node_id == old_id)
+        else {
+            return;
+        };
+        routing_table.remove_node(&old_id);
+        routing_table.add_node(Node {
+            node_id: new_id,
+            ..peer
+        });
+        debug!(
+            old_id = %hex::encode(&link.old_id[..8]),
+            new_id = %hex::encode(&link.new_id[..8]),
+            "Peer rotated its identity"
+        );
+    }
+
+    /// Generate uniq id for Kademlia Bucket
+    fn generate_random_id_for_bucket(&self, bucket_index: usize) -> NodeID {
+        random_id_for_bucket(&self.node_id, bucket_index, &self.network_protocol.rng)
+    }
+
+    pub async fn find_value(&self, key: &Key) -> Result<Vec<u8>, RhizomeError> {
+        #[cfg(feature = "popularity")]
         self.metrics_collector
-            .write()
-            .await
-            .record_find_value(key.to_vec(), Some(self.node_id.0.to_vec()));
+            .record_find_value(key.clone(), Some(self.node_id.0.to_vec()));
         self.dht_protocol.find_value(key).await
     }
 
-    pub async fn store(&self, key: &[u8], value: &[u8], ttl: i32) -> Result<bool, RhizomeError> {
-        let success = self.dht_protocol.store(key, value, ttl).await?;
-        let replication_count = if success { self.config.dht.k as u32 } else { 1 };
+    /// Find value together with the node which supplied it and its freshness
+    pub async fn find_value_with_meta(&self, key: &Key) -> Result<ValueWithMeta, RhizomeError> {
+        #[cfg(feature = "popularity")]
+        self.metrics_collector
+            .record_find_value(key.clone(), Some(self.node_id.0.to_vec()));
+        self.dht_protocol.find_value_with_meta(key).await
+    }
+
+    /// Find value with its meta within the query budget
+    pub async fn find_value_with_budget(
+        &self,
+        key: &Key,
+        budget: &QueryBudget,
+    ) -> Result<ValueWithMeta, RhizomeError> {
+        #[cfg(feature = "popularity")]
         self.metrics_collector
-            .write()
+            .record_find_value(key.clone(), Some(self.node_id.0.to_vec()));
+        self.dht_protocol.find_value_with_budget(key, budget).await
+    }
+
+    /// Find values of several keys in batches
+    ///
+    /// Return only found values
+    pub async fn find_values(&self, keys: &[Key]) -> Result<HashMap<Key, Vec<u8>>, RhizomeError> {
+        #[cfg(feature = "popularity")]
+        for key in keys {
+            self.metrics_collector
+                .record_find_value(key.clone(), Some(self.node_id.0.to_vec()));
+        }
+        self.dht_protocol.find_values(keys).await
+    }
+
+    /// Find value with requested consistency
+    ///
+    /// `ReadConsistency::Quorum` asks several replicas and returns the newest value
+    pub async fn find_value_with_consistency(
+        &self,
+        key: &Key,
+        consistency: ReadConsistency,
+    ) -> Result<Vec<u8>, RhizomeError> {
+        #[cfg(feature = "popularity")]
+        self.metrics_collector
+            .record_find_value(key.clone(), Some(self.node_id.0.to_vec()));
+        self.dht_protocol
+            .find_value_with_consistency(key, consistency)
+            .await
+    }
+
+    /// Quorum read with `dht.read_quorum` from config
+    pub async fn find_value_quorum(&self, key: &Key) -> Result<Vec<u8>, RhizomeError> {
+        let quorum = self.config.dht.read_quorum.max(1) as usize;
+        self.find_value_with_consistency(key, ReadConsistency::Quorum(quorum))
+            .await
+    }
+
+    pub async fn store(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+    ) -> Result<StoreResult, RhizomeError> {
+        self.store_record(key, value, ttl, "").await
+    }
+
+    /// Store data with record type for crawling
+    ///
+    /// Return how many of the closest nodes accepted the data
+    pub async fn store_record(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        record_type: &str,
+    ) -> Result<StoreResult, RhizomeError> {
+        self.store_record_with_budget(key, value, ttl, record_type, &QueryBudget::unlimited())
+            .await
+    }
+
+    /// Store record within the query budget
+    pub async fn store_record_with_budget(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        record_type: &str,
+        budget: &QueryBudget,
+    ) -> Result<StoreResult, RhizomeError> {
+        let result = self
+            .dht_protocol
+            .store_record_with_budget(key, value, ttl, record_type, budget)
+            .await?;
+        #[cfg(feature = "popularity")]
+        self.metrics_collector
+            .record_store(key.clone(), result.succeeded as u32 + 1);
+        Ok(result)
+    }
+
+    /// Switch node to read-only mode or back to normal work
+    ///
+    /// In read-only mode inbound STORE, STORE_CAS and DELETE requests are rejected and
+    /// replication is stopped, lookups are served as usual. Useful during migrations and
+    /// storage compaction.
+    pub fn set_mode(&self, mode: NodeMode) {
+        let previous = self.mode.set(mode);
+        if previous != mode {
+            info!(mode = %mode, "Node mode changed");
+            self.events.emit(NodeEvent::ModeChanged { mode });
+        }
+    }
+
+    /// Rotate keypair and ID of the node
+    ///
+    /// New identity is saved in place of the old one, old files are kept with `.retired`
+    /// suffix. Signed link between identities is announced by gossip, so peers rebind
+    /// us in their routing tables, and all stored keys are pushed to their closest
+    /// nodes. Node keeps working under the old ID until restart.
+    #[cfg(feature = "signatures")]
+    pub async fn rotate_identity(&self) -> Result<RotationReport, RhizomeError> {
+        let (private_key, public_key) = generate_keypair();
+        let new_id = node_id_from_public_key(&public_key).to_vec();
+        let new_signer = NodeSigner::new(private_key.clone(), new_id.clone());
+        let link = IdentityLink::issue(&self.signer, &new_signer);
+
+        self.retire_identity(&private_key, &new_id).map_err(|e| {
+            error!(error = %e, "Failed to save new node identity");
+            SecurityError::General
+        })?;
+
+        self.network_protocol
+            .gossip_broadcast(
+                GossipTopic::IdentityRotation,
+                serde_json::to_value(&link).map_err(|_| SecurityError::General)?,
+            )
+            .await;
+
+        let (keys_transferred, keys_failed) = self.transfer_stored_keys().await?;
+
+        info!(
+            old_id = %hex::encode(&link.old_id[..8]),
+            new_id = %hex::encode(&link.new_id[..8]),
+            transferred = keys_transferred,
+            failed = keys_failed,
+            "Node identity rotated, new ID is used after restart"
+        );
+        self.events.emit(NodeEvent::IdentityRotated {
+            old_id: link.old_id.clone(),
+            new_id: link.new_id.clone(),
+        });
+
+        Ok(RotationReport {
+            link,
+            keys_transferred,
+            keys_failed,
+        })
+    }
+
+    /// Keep old identity files with `.retired` suffix and save the new identity
+    #[cfg(feature = "signatures")]
+    fn retire_identity(&self, private_key: &RsaPrivateKey, new_id: &[u8]) -> std::io::Result<()> {
+        let node_id_path = PathBuf::from(&self.config.node.node_id_file);
+        let node_key_path = PathBuf::from(&self.config.node.node_key_file);
+        for path in [&node_id_path, &node_key_path] {
+            retire_file(path)?;
+        }
+        save_private_key(private_key, &node_key_path)?;
+        save_node_id(new_id, &node_id_path)
+    }
+
+    /// Push all stored keys to their closest nodes with the remaining TTL
+    ///
+    /// Return counts of transferred and failed keys
+    #[cfg(feature = "signatures")]
+    async fn transfer_stored_keys(&self) -> Result<(usize, usize), RhizomeError> {
+        let (mut transferred, mut failed) = (0, 0);
+        for key in self.storage.keys().await? {
+            let Some(stored) = self.storage.get_with_meta(key.clone()).await? else {
+                continue;
+            };
+            let ttl = (stored.expires_at - self.storage.now()) as i32;
+            if ttl <= 0 {
+                continue;
+            }
+
+            let nodes = self.dht_protocol.find_closest_to_key(&key).await?;
+            let origin = stored.origin.forwarded();
+            let store_tasks: Vec<_> = nodes
+                .iter()
+                .map(|node| {
+                    self.network_protocol
+                        .store_with_origin(&key, &stored.value, ttl, &origin, node)
+                })
+                .collect();
+            if join_all(store_tasks)
+                .await
+                .into_iter()
+                .any(|stored| matches!(stored, Ok(true)))
+            {
+                transferred += 1;
+            } else {
+                failed += 1;
+                debug!(key = %key.short_hex(), "No closest node accepted transferred key");
+            }
+        }
+        Ok((transferred, failed))
+    }
+
+    /// Delete data from our storage and the closest nodes
+    ///
+    /// Tombstone is kept for `tombstone_ttl`, so old copies are not resurrected by
+    /// replication. Return how many of the closest nodes accepted the tombstone
+    pub async fn delete(&self, key: &Key) -> Result<StoreResult, RhizomeError> {
+        self.dht_protocol
+            .delete(key, self.config.storage.tombstone_ttl)
             .await
-            .record_store(key.to_vec(), replication_count);
-        Ok(success)
+    }
+
+    /// Read-modify-write of the value with compare-and-swap
+    ///
+    /// `update` gets current value and returns new one. On conflict it is called again
+    /// with the conflicting value, up to `MAX_CAS_ATTEMPTS` times.
+    /// Return `false` if all attempts had conflicts.
+    pub async fn update_value<F>(
+        &self,
+        key: &Key,
+        ttl: i32,
+        record_type: &str,
+        mut update: F,
+    ) -> Result<bool, RhizomeError>
+    where
+        F: FnMut(Option<&[u8]>) -> Vec<u8>,
+    {
+        const MAX_CAS_ATTEMPTS: usize = 5;
+
+        let mut current = self.dht_protocol.find_value(key).await.ok();
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let expected = current.as_deref().map(hash_key);
+            let new_value = update(current.as_deref());
+
+            match self
+                .dht_protocol
+                .store_cas(key, expected, &new_value, ttl, record_type)
+                .await?
+            {
+                CasOutcome::Applied => {
+                    #[cfg(feature = "popularity")]
+                    self.metrics_collector
+                        .record_store(key.clone(), self.config.dht.k as u32);
+                    return Ok(true);
+                }
+                CasOutcome::Conflict(value) => current = value,
+            }
+        }
+
+        warn!(
+            key = %key.short_hex(),
+            "Compare-and-swap update gave up after conflicts"
+        );
+        Ok(false)
     }
 
     /// Method for copy packet references
@@ -510,12 +2017,24 @@ impl BaseNode {
             config: self.config.clone(),
             routing_table: self.routing_table.clone(),
             storage: self.storage.clone(),
+            #[cfg(feature = "popularity")]
             metrics_collector: self.metrics_collector.clone(),
+            #[cfg(feature = "popularity")]
             popularity_ranker: self.popularity_ranker.clone(),
+            network_protocol: self.network_protocol.clone(),
             dht_protocol: self.dht_protocol.clone(),
+            #[cfg(feature = "popularity")]
             popularity_exchanger: self.popularity_exchanger.clone(),
+            #[cfg(feature = "replication")]
             replicator: self.replicator.clone(),
-            is_running: self.is_running.clone(),
+            #[cfg(feature = "popularity")]
+            key_presence: self.key_presence.clone(),
+            events: self.events.clone(),
+            disk_watchdog: self.disk_watchdog.clone(),
+            #[cfg(feature = "port-mapping")]
+            port_mapper: self.port_mapper.clone(),
+            supervisor: self.supervisor.clone(),
+            lifecycle: self.lifecycle.clone(),
         }
     }
 }
@@ -525,16 +2044,72 @@ pub(crate) struct BaseNodePtrs {
     pub(crate) config: Config,
     pub(crate) routing_table: Arc<RwLock<RoutingTable>>,
     storage: Arc<Storage>,
-    pub(crate) metrics_collector: Arc<RwLock<MetricsCollector>>,
+    #[cfg(feature = "popularity")]
+    pub(crate) metrics_collector: Arc<MetricsCollector>,
+    #[cfg(feature = "popularity")]
     pub(crate) popularity_ranker: Arc<PopularityRanker>,
+    network_protocol: Arc<NetworkProtocol>,
     dht_protocol: Arc<DHTProtocol>,
+    #[cfg(feature = "popularity")]
     pub(crate) popularity_exchanger: Arc<PopularityExchanger>,
+    #[cfg(feature = "replication")]
     replicator: Arc<Replicator>,
-    pub(crate) is_running: Arc<RwLock<bool>>,
+    #[cfg(feature = "popularity")]
+    key_presence: Arc<KeyPresenceIndex>,
+    events: EventBus,
+    disk_watchdog: Arc<DiskWatchdog>,
+    #[cfg(feature = "port-mapping")]
+    port_mapper: Option<Arc<PortMapper>>,
+    supervisor: Arc<Supervisor>,
+    pub(crate) lifecycle: Arc<Lifecycle>,
 }
 
 impl BaseNodePtrs {
-    fn generate_random_id_for_bucket(&self, _bucket_index: usize) -> NodeID {
-        NodeID::new([0u8; 20])
+    /// Move between ready and degraded status by health of loops and free disk space
+    fn update_health_status(&self) {
+        if self.supervisor.is_healthy() && !self.disk_watchdog.is_degraded() {
+            self.lifecycle
+                .advance(&[NodeStatus::Degraded], NodeStatus::Ready);
+        } else {
+            self.lifecycle
+                .advance(&[NodeStatus::Ready], NodeStatus::Degraded);
+        }
+    }
+
+    fn generate_random_id_for_bucket(&self, bucket_index: usize) -> NodeID {
+        random_id_for_bucket(
+            &self.network_protocol.node_id,
+            bucket_index,
+            &self.network_protocol.rng,
+        )
+    }
+}
+
+/// Random ID which falls into the bucket with `bucket_index` of `node_id`
+fn random_id_for_bucket(node_id: &NodeID, bucket_index: usize, rng: &NodeRng) -> NodeID {
+    let mut random_id = node_id.0;
+    let noise = NodeID::random(rng).0;
+
+    let byte_idx = bucket_index / 8;
+    let bit_idx = bucket_index % 8;
+
+    if byte_idx < 20 {
+        let flip_bit = 0x80 >> bit_idx;
+        random_id[byte_idx] ^= flip_bit;
+
+        for (i, byte) in random_id
+            .iter_mut()
+            .enumerate()
+            .skip(byte_idx)
+            .take(20 - byte_idx)
+        {
+            let mask = if i == byte_idx {
+                (1 << (7 - bit_idx)) - 1
+            } else {
+                0xFF
+            };
+            *byte ^= noise[i] & mask;
+        }
     }
+    NodeID::new(random_id)
 }
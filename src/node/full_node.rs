@@ -1,6 +1,8 @@
 use crate::config::Config;
 use crate::node::base_node::BaseNode;
+use crate::utils::time::{Clock, SystemClock};
 use std::ops::Deref;
+use std::sync::Arc;
 
 /// Full-node for main work load
 pub struct FullNode {
@@ -11,10 +13,18 @@ impl FullNode {
     /// Constructor for node of full type
     ///
     /// Guarantied that node type is full and has all node conditions without any restrictions
-    pub async fn new(mut config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_clock(config, Arc::new(SystemClock)).await
+    }
+
+    /// Full node which counts time by `clock`, see [`BaseNode::with_clock`]
+    pub async fn with_clock(
+        mut config: Config,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         config.node.node_type = "full".to_string();
 
-        let base = BaseNode::new(config).await?;
+        let base = BaseNode::with_clock(config, clock).await?;
 
         Ok(Self { base })
     }
@@ -0,0 +1,104 @@
+use serde::Serialize;
+use std::fmt;
+use std::sync::RwLock;
+use tokio::sync::{Mutex, MutexGuard, watch};
+
+use crate::events::{EventBus, NodeEvent};
+
+/// Stage of the node life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeStatus {
+    /// Node is built, but not started
+    #[default]
+    Created,
+    /// Socket is open, node connects to bootstrap nodes
+    Bootstrapping,
+    Ready,
+    /// Node works, but a background loop fails or disk space is low
+    Degraded,
+    Stopping,
+    Stopped,
+}
+
+impl NodeStatus {
+    /// Background loops work in this status
+    pub fn is_running(self) -> bool {
+        matches!(
+            self,
+            NodeStatus::Bootstrapping | NodeStatus::Ready | NodeStatus::Degraded
+        )
+    }
+}
+
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeStatus::Created => write!(f, "created"),
+            NodeStatus::Bootstrapping => write!(f, "bootstrapping"),
+            NodeStatus::Ready => write!(f, "ready"),
+            NodeStatus::Degraded => write!(f, "degraded"),
+            NodeStatus::Stopping => write!(f, "stopping"),
+            NodeStatus::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+/// Status of the node shared with its background loops
+///
+/// Every change of the status is sent as [`NodeEvent::StatusChanged`]. Start and stop
+/// hold [`Lifecycle::begin_transition`] for their whole run, so they never interleave,
+/// while status is read without waiting.
+pub struct Lifecycle {
+    status: RwLock<NodeStatus>,
+    transition: Mutex<()>,
+    events: EventBus,
+    /// Status for loops which wait for the stop
+    changes: watch::Sender<NodeStatus>,
+}
+
+impl Lifecycle {
+    pub fn new(events: EventBus) -> Self {
+        Self {
+            status: RwLock::new(NodeStatus::Created),
+            transition: Mutex::new(()),
+            events,
+            changes: watch::Sender::new(NodeStatus::Created),
+        }
+    }
+
+    pub fn status(&self) -> NodeStatus {
+        *self.status.read().unwrap()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status().is_running()
+    }
+
+    /// Wait until the node is not running, loops which wait for events select on it
+    pub async fn stopping(&self) {
+        let mut changes = self.changes.subscribe();
+        let _ = changes.wait_for(|status| !status.is_running()).await;
+    }
+
+    /// Wait for the end of other start or stop
+    pub async fn begin_transition(&self) -> MutexGuard<'_, ()> {
+        self.transition.lock().await
+    }
+
+    /// Move to `to` if the current status is one of `from`
+    ///
+    /// Return `false` if the status is not changed.
+    pub fn advance(&self, from: &[NodeStatus], to: NodeStatus) -> bool {
+        let mut status = self.status.write().unwrap();
+        if *status == to || !from.contains(&*status) {
+            return false;
+        }
+        *status = to;
+        drop(status);
+        self.changes.send_replace(to);
+
+        self.events.emit(NodeEvent::StatusChanged { status: to });
+        true
+    }
+}
@@ -1,5 +1,7 @@
 use crate::config::Config;
 use crate::node::base_node::BaseNode;
+#[cfg(feature = "compression")]
+use crate::storage::compression::DEFAULT_COMPRESSION_THRESHOLD;
 use std::ops::Deref;
 
 /// Light-node systems with limited resources
@@ -11,7 +13,8 @@ pub struct LightNode {
 impl LightNode {
     /// Constructor for node of light type
     ///
-    /// Guarantied that node type is light and max_storage_bytes is 1GB.
+    /// Guarantied that node type is light and max_storage_bytes is 1GB. Large values are
+    /// compressed unless `compression_threshold` is set.
     pub async fn new(mut config: Config) -> Result<Self, Box<dyn std::error::Error>> {
         config.node.node_type = "light".to_string();
 
@@ -19,6 +22,11 @@ impl LightNode {
         if config.storage.max_storage_size > max_light_bytes {
             config.storage.max_storage_size = max_light_bytes;
         }
+        #[cfg(feature = "compression")]
+        config
+            .storage
+            .compression_threshold
+            .get_or_insert(DEFAULT_COMPRESSION_THRESHOLD);
 
         let base = BaseNode::new(config).await?;
 
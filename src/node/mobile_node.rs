@@ -1,5 +1,7 @@
 use crate::config::Config;
 use crate::node::base_node::BaseNode;
+#[cfg(feature = "compression")]
+use crate::storage::compression::DEFAULT_COMPRESSION_THRESHOLD;
 use std::ops::Deref;
 
 /// Mobile-node for mobile device
@@ -12,13 +14,21 @@ impl MobileNode {
     /// Constructor for node of full type
     ///
     /// Guarantied that node type is mobile, max storage is 100mb and max buckets count is 10.
+    /// Idle peers are pinged not often than every 30 minutes for save battery. Large values are
+    /// compressed unless `compression_threshold` is set.
     pub async fn new(mut config: Config) -> Result<Self, Box<dyn std::error::Error>> {
         config.node.node_type = "mobile".to_string();
 
         let max_mobile_bytes: u64 = 100 * 1024 * 1024;
         config.storage.max_storage_size = config.storage.max_storage_size.min(max_mobile_bytes);
+        #[cfg(feature = "compression")]
+        config
+            .storage
+            .compression_threshold
+            .get_or_insert(DEFAULT_COMPRESSION_THRESHOLD);
 
         config.dht.k = 10;
+        config.dht.keepalive_idle = config.dht.keepalive_idle.max(1800);
 
         let base = BaseNode::new(config).await?;
 
@@ -3,9 +3,20 @@ pub mod base_node;
 
 /// Guarantied that node type is full and has all node conditions without any restrictions
 pub mod full_node;
+/// Lifecycle status of the node
+pub mod lifecycle;
 /// Light node saves data in storage no more than 1GB
 pub mod light_node;
 /// Mobile node saves data in storage no more than 100mb and max buckets 10
 pub mod mobile_node;
+/// Normal and read-only mode of the node
+pub mod mode;
+/// Local cluster of several nodes in one process
+pub mod node_set;
+/// Checks of the environment before start
+pub mod preflight;
 /// For work with popularity
+#[cfg(feature = "popularity")]
 pub mod seed_node;
+/// Restart of failed background loops and their health
+pub mod supervisor;
@@ -0,0 +1,63 @@
+use serde::Serialize;
+use std::fmt;
+use std::sync::RwLock;
+
+/// Mode of work of the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeMode {
+    #[default]
+    Normal,
+    /// Inbound stores are rejected and replication is stopped, lookups are served
+    ReadOnly,
+    /// Node has just started and fills its routing table, inbound stores are rejected
+    /// and peers do not add it to their routing tables
+    WarmingUp,
+}
+
+impl fmt::Display for NodeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeMode::Normal => write!(f, "normal"),
+            NodeMode::ReadOnly => write!(f, "read_only"),
+            NodeMode::WarmingUp => write!(f, "warming_up"),
+        }
+    }
+}
+
+/// Current mode shared by the node, network protocol and replicator
+#[derive(Debug, Default)]
+pub struct ModeSwitch {
+    mode: RwLock<NodeMode>,
+}
+
+impl ModeSwitch {
+    pub fn get(&self) -> NodeMode {
+        *self.mode.read().unwrap()
+    }
+
+    /// Set new mode, return previous one
+    pub fn set(&self, mode: NodeMode) -> NodeMode {
+        std::mem::replace(&mut *self.mode.write().unwrap(), mode)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.get() == NodeMode::ReadOnly
+    }
+
+    pub fn is_warming_up(&self) -> bool {
+        self.get() == NodeMode::WarmingUp
+    }
+
+    /// Leave warm-up for normal mode, other modes are kept
+    ///
+    /// Return `true` if the node was warming up.
+    pub fn finish_warm_up(&self) -> bool {
+        let mut mode = self.mode.write().unwrap();
+        if *mode != NodeMode::WarmingUp {
+            return false;
+        }
+        *mode = NodeMode::Normal;
+        true
+    }
+}
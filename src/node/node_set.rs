@@ -0,0 +1,163 @@
+use futures::future::join_all;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::config::Config;
+#[cfg(feature = "fault-injection")]
+use crate::network::chaos::FaultConfig;
+use crate::node::full_node::FullNode;
+use crate::utils::time::{Clock, SystemClock};
+
+/// Local cluster of full nodes in one process
+///
+/// Every node gets its own directory `<base_dir>/node-<index>` _(see [`Config::for_instance`])_
+/// and its own port counted from `first_port`. The first node is the bootstrap node of the
+/// others. It is made for simulations and tests of the network without separate processes.
+/// With `fault-injection` feature transports of all nodes inject faults, they are changed by
+/// `NodeSet::set_faults`.
+/// Nodes made by [`NodeSet::with_clock`] share one clock, so a virtual one moves them all.
+pub struct NodeSet {
+    nodes: Vec<Arc<FullNode>>,
+    base_dir: PathBuf,
+    bootstrap_address: SocketAddr,
+}
+
+impl NodeSet {
+    /// Build `count` nodes from the common config, nodes are not started
+    pub async fn new(
+        config: &Config,
+        base_dir: &Path,
+        count: usize,
+        first_port: u16,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_clock(config, base_dir, count, first_port, Arc::new(SystemClock)).await
+    }
+
+    /// Build `count` nodes which count time by `clock`, nodes are not started
+    pub async fn with_clock(
+        config: &Config,
+        base_dir: &Path,
+        count: usize,
+        first_port: u16,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let host: IpAddr = config.network.listen_host.parse()?;
+        // Peers can not send to the unspecified address, so they use loopback
+        let bootstrap_host = match host {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ip => ip,
+        };
+        let bootstrap_address = SocketAddr::new(bootstrap_host, first_port);
+
+        let mut nodes = Vec::with_capacity(count);
+        for index in 0..count {
+            let port = u16::try_from(index)
+                .ok()
+                .and_then(|offset| first_port.checked_add(offset))
+                .ok_or("Ports of the node set are out of range")?;
+
+            let mut node_config = config.for_instance(&base_dir.join(format!("node-{}", index)));
+            node_config.network.listen_host = host.to_string();
+            node_config.network.listen_interface = None;
+            node_config.network.listen_port = port as i32;
+            node_config.network.listen_port_range = None;
+            node_config.network.port_mapping = false;
+            node_config.network.faults = Some(config.network.faults.clone().unwrap_or_default());
+            node_config.network.bootstrap_nodes = if index == 0 {
+                Vec::new()
+            } else {
+                vec![bootstrap_address.to_string()]
+            };
+            // Cluster can not give more peers than it has
+            node_config.dht.warm_up_min_peers = node_config.dht.warm_up_min_peers.min(count - 1);
+            node_config.node.rng_seed = config
+                .node
+                .rng_seed
+                .map(|seed| seed.wrapping_add(index as u64));
+
+            nodes.push(Arc::new(
+                FullNode::with_clock(node_config, clock.clone()).await?,
+            ));
+        }
+
+        Ok(Self {
+            nodes,
+            base_dir: base_dir.to_path_buf(),
+            bootstrap_address,
+        })
+    }
+
+    /// Start the bootstrap node, then all other nodes together
+    pub async fn start_all(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some((first, others)) = self.nodes.split_first() else {
+            return Ok(());
+        };
+        first.start().await?;
+
+        for result in join_all(others.iter().map(|node| node.start())).await {
+            result?;
+        }
+        info!(
+            nodes = self.nodes.len(),
+            bootstrap = %self.bootstrap_address,
+            "Node set started"
+        );
+        Ok(())
+    }
+
+    /// Stop all nodes, the bootstrap node is the last
+    ///
+    /// Every node is stopped even if some fail, the first error is returned.
+    pub async fn stop_all(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut first_error = None;
+        for (index, node) in self.nodes.iter().enumerate().rev() {
+            if let Err(e) = node.stop().await {
+                warn!(index, error = %e, "Failed to stop node of the set");
+                first_error.get_or_insert(e);
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Replace faults of sent packets on all nodes
+    #[cfg(feature = "fault-injection")]
+    pub fn set_faults(&self, faults: FaultConfig) {
+        for node in &self.nodes {
+            if let Some(injector) = &node.network_protocol.transport.faults {
+                injector.set_config(faults.clone());
+            }
+        }
+    }
+
+    pub fn nodes(&self) -> &[Arc<FullNode>] {
+        &self.nodes
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Arc<FullNode>> {
+        self.nodes.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Directory with directories of all nodes
+    pub fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    /// Address which other nodes use to join the set
+    pub fn bootstrap_address(&self) -> SocketAddr {
+        self.bootstrap_address
+    }
+}
@@ -0,0 +1,246 @@
+use serde::Serialize;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use crate::network::consts::{MSG_PING, MSG_PONG};
+use crate::network::protocol::NetworkProtocol;
+use crate::utils::serialization::decode_message;
+use crate::utils::time::get_now_f64;
+
+/// System clock before this time _(2024-01-01)_ is surely wrong
+const MIN_SANE_TIME: f64 = 1_704_067_200.0;
+/// Clock offset to bootstrap nodes in seconds which is reported as a warning
+const MAX_CLOCK_OFFSET: f64 = 30.0;
+/// LMDB map size must be a multiple of the OS page size
+const LMDB_PAGE_SIZE: u64 = 4096;
+
+/// Result of one check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+    /// Node can start, but something works worse
+    Warning,
+    /// Node should not start
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreflightCheck {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Results of startup checks of the environment
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    pub fn push(&mut self, name: &'static str, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(PreflightCheck {
+            name,
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    /// No check failed, warnings are allowed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Failed)
+    }
+
+    /// Checks which did not pass
+    pub fn problems(&self) -> impl Iterator<Item = &PreflightCheck> {
+        self.checks
+            .iter()
+            .filter(|c| c.status != CheckStatus::Passed)
+    }
+}
+
+/// Write and remove test file in the data directory
+pub fn check_data_dir(report: &mut PreflightReport, data_dir: &Path) {
+    let probe = data_dir.join(".preflight");
+    let result = fs::create_dir_all(data_dir)
+        .and_then(|_| fs::write(&probe, b"rhizome"))
+        .and_then(|_| fs::remove_file(&probe));
+    match result {
+        Ok(()) => report.push(
+            "data_dir",
+            CheckStatus::Passed,
+            format!("{} is writable", data_dir.display()),
+        ),
+        Err(e) => report.push(
+            "data_dir",
+            CheckStatus::Failed,
+            format!("{} is not writable: {}", data_dir.display(), e),
+        ),
+    }
+}
+
+/// Free space under the data directory against the degraded threshold and max storage size
+pub fn check_free_space(
+    report: &mut PreflightReport,
+    data_dir: &Path,
+    min_free_space: u64,
+    max_storage_size: u64,
+) {
+    let free = match fs2::available_space(data_dir) {
+        Ok(free) => free,
+        Err(e) => {
+            report.push(
+                "free_space",
+                CheckStatus::Warning,
+                format!("free space is unknown: {}", e),
+            );
+            return;
+        }
+    };
+    if free < min_free_space {
+        report.push(
+            "free_space",
+            CheckStatus::Warning,
+            format!(
+                "{} bytes free, node is degraded and rejects data below {}",
+                free, min_free_space
+            ),
+        );
+    } else if free < max_storage_size {
+        report.push(
+            "free_space",
+            CheckStatus::Warning,
+            format!(
+                "{} bytes free, storage can not grow to max_storage_size {}",
+                free, max_storage_size
+            ),
+        );
+    } else {
+        report.push(
+            "free_space",
+            CheckStatus::Passed,
+            format!("{} bytes free", free),
+        );
+    }
+}
+
+/// `max_storage_size` is a valid LMDB map size and fits the existing database
+pub fn check_map_size(report: &mut PreflightReport, data_dir: &Path, map_size: u64) {
+    let db_size = fs::metadata(data_dir.join("data.lmdb").join("data.mdb"))
+        .map(|m| m.len())
+        .unwrap_or(0);
+
+    if map_size == 0 || map_size > usize::MAX as u64 {
+        report.push(
+            "lmdb_map_size",
+            CheckStatus::Failed,
+            format!("map size {} is not supported on this platform", map_size),
+        );
+    } else if map_size < db_size {
+        report.push(
+            "lmdb_map_size",
+            CheckStatus::Failed,
+            format!(
+                "map size {} is less than the existing database of {} bytes",
+                map_size, db_size
+            ),
+        );
+    } else if !map_size.is_multiple_of(LMDB_PAGE_SIZE) {
+        report.push(
+            "lmdb_map_size",
+            CheckStatus::Warning,
+            format!(
+                "map size {} is not a multiple of page size {}, LMDB rounds it",
+                map_size, LMDB_PAGE_SIZE
+            ),
+        );
+    } else {
+        report.push(
+            "lmdb_map_size",
+            CheckStatus::Passed,
+            format!("map size {}, database {} bytes", map_size, db_size),
+        );
+    }
+}
+
+/// System clock is not before [`MIN_SANE_TIME`] and agrees with bootstrap nodes
+pub fn check_clock(report: &mut PreflightReport, peer_offsets: &[f64]) {
+    let now = get_now_f64();
+    if now < MIN_SANE_TIME {
+        report.push(
+            "clock",
+            CheckStatus::Failed,
+            format!("system clock is in the past ({})", now),
+        );
+        return;
+    }
+
+    let mut offsets = peer_offsets.to_vec();
+    offsets.sort_by(|a, b| a.total_cmp(b));
+    match offsets.get(offsets.len() / 2) {
+        Some(offset) if offset.abs() > MAX_CLOCK_OFFSET => report.push(
+            "clock",
+            CheckStatus::Warning,
+            format!("clock differs from bootstrap nodes by {:.1}s", offset),
+        ),
+        Some(offset) => report.push(
+            "clock",
+            CheckStatus::Passed,
+            format!("clock differs from bootstrap nodes by {:.1}s", offset),
+        ),
+        None => report.push("clock", CheckStatus::Passed, "no peers to compare with"),
+    }
+}
+
+/// Send PING to the bootstrap node from a temporary socket
+///
+/// Return clock offset of the node _(None if it did not answer)_. PING is marked as
+/// warming up, so the node does not add the temporary address to its routing table.
+pub async fn probe_bootstrap(
+    protocol: &NetworkProtocol,
+    address: SocketAddr,
+    wait: Duration,
+) -> Option<f64> {
+    let socket = UdpSocket::bind(if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })
+    .await
+    .ok()?;
+
+    let msg_id = protocol.generate_msg_id();
+    let payload = serde_json::json!({"node_id": protocol.node_id, "warming_up": true});
+    let data = protocol
+        .pack_message(MSG_PING, msg_id, payload, address)
+        .ok()?;
+    let sent_at = get_now_f64();
+    socket.send_to(&data, address).await.ok()?;
+
+    let mut buf = vec![0u8; 65535];
+    let deadline = tokio::time::Instant::now() + wait;
+    loop {
+        let (size, from) = timeout(
+            deadline.saturating_duration_since(tokio::time::Instant::now()),
+            socket.recv_from(&mut buf),
+        )
+        .await
+        .ok()?
+        .ok()?;
+        if from != address {
+            continue;
+        }
+        if let Ok(m) = decode_message(&buf[..size])
+            && m.msg_type == MSG_PONG
+            && m.id == msg_id
+        {
+            let received_at = get_now_f64();
+            return Some(m.timestamp - (sent_at + received_at) / 2.0);
+        }
+    }
+}
@@ -6,7 +6,6 @@ use tracing::{error, info};
 
 use crate::config::Config;
 use crate::node::base_node::{BaseNode, BaseNodePtrs};
-use crate::utils::time::get_now_f64;
 
 /// Seed-node for work with popularity
 pub struct SeedNode {
@@ -27,63 +26,44 @@ impl SeedNode {
         self.base.start().await?;
 
         let base_ptrs = Arc::new(self.base.clone_ptrs());
-
-        tokio::spawn(async move {
-            Self::seed_loop(base_ptrs).await;
-        });
+        self.base
+            .supervisor
+            .spawn("seed", self.base.lifecycle.clone(), move || {
+                Self::seed_loop(base_ptrs.clone())
+            });
 
         info!("Seed-specific tasks started");
         Ok(())
     }
 
     async fn seed_loop(node: Arc<BaseNodePtrs>) {
-        let global_update_interval = node.config.popularity.global_update_interval as f64;
-        let mut last_global_update = 0.0;
-
-        while *node.is_running.read().await {
-            let current_time = get_now_f64();
-
-            if current_time - last_global_update >= global_update_interval {
-                if let Err(e) = Self::update_global_ranking(&node).await {
-                    error!(error = %e, "Error updating global ranking in seed task");
-                }
-                last_global_update = current_time;
+        while node.lifecycle.is_running() {
+            if let Err(e) = Self::update_global_ranking(&node).await {
+                error!(error = %e, "Error updating global ranking in seed task");
             }
 
             sleep(Duration::from_secs(300)).await;
         }
     }
 
+    /// Refresh global ranking if it is older than `global_update_interval`
     async fn update_global_ranking(node: &BaseNodePtrs) -> Result<(), Box<dyn std::error::Error>> {
-        let all_metrics = node
-            .metrics_collector
-            .read()
-            .await
-            .get_all_metrics()
-            .clone();
-        if all_metrics.is_empty() {
+        if node.metrics_collector.is_empty() {
             return Ok(());
         }
 
-        let local_ranked = node.popularity_ranker.rank_items(&all_metrics, Some(100));
-
-        let mut seed_nodes = Vec::new();
-        let all_nodes = node.routing_table.read().await.get_all_nodes();
-
-        // TODO: filter node like seed by metadata or other bucket
-        for n in all_nodes {
-            seed_nodes.push(n);
-        }
-
-        let global_ranking = node
+        if node
             .popularity_exchanger
-            .aggregate_global_ranking(local_ranked, seed_nodes)
-            .await;
-
-        info!(
-            items = global_ranking.len(),
-            "Updated global ranking on seed node"
-        );
+            .refresh_global_ranking_if_stale()
+            .await
+        {
+            let items = node
+                .popularity_exchanger
+                .get_global_ranking_api()
+                .await
+                .len();
+            info!(items = items, "Updated global ranking on seed node");
+        }
         Ok(())
     }
 }
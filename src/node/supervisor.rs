@@ -0,0 +1,243 @@
+use serde::Serialize;
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::{AbortHandle, JoinHandle};
+use tracing::{Instrument, Span, error, info};
+
+use crate::events::{EventBus, NodeEvent};
+use crate::node::lifecycle::Lifecycle;
+use crate::utils::time::get_now_f64;
+
+/// Delay before the first restart of a failed loop
+pub const INITIAL_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+/// Max delay between restarts of a failing loop
+pub const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(300);
+/// Loop which worked longer than this before failure is restarted without delay growth
+pub const STABLE_RUN_TIME: Duration = Duration::from_secs(600);
+/// Node is reported as degraded during this time in seconds after a loop failure
+pub const DEGRADED_WINDOW: f64 = 600.0;
+
+/// State of one background loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopState {
+    Running,
+    /// Loop failed and waits for restart
+    Restarting,
+    Stopped,
+}
+
+/// Health of one background loop
+#[derive(Debug, Clone, Serialize)]
+pub struct LoopHealth {
+    pub state: LoopState,
+    /// Count of restarts after failures
+    pub restarts: u32,
+    pub last_failure: Option<String>,
+    pub last_failure_at: Option<f64>,
+}
+
+impl LoopHealth {
+    fn new(state: LoopState) -> Self {
+        Self {
+            state,
+            restarts: 0,
+            last_failure: None,
+            last_failure_at: None,
+        }
+    }
+}
+
+/// Runner of background loops of the node
+///
+/// Every loop runs in its own task. When the task panics, failure is logged, sent as
+/// [`NodeEvent::BackgroundTaskFailed`] and the loop is started again with exponential
+/// backoff while the node is running. Loops are aborted by [`Supervisor::abort_all`] on
+/// stop, so the next start does not run them twice.
+pub struct Supervisor {
+    health: Mutex<HashMap<String, LoopHealth>>,
+    /// Supervising tasks of loops, the task of a loop is aborted with them
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+    events: EventBus,
+    /// Span of the node, records of loops of different nodes in one process are told apart by it
+    span: Span,
+}
+
+impl Supervisor {
+    pub fn new(events: EventBus, span: Span) -> Self {
+        Self {
+            health: Mutex::new(HashMap::new()),
+            tasks: Mutex::new(Vec::new()),
+            events,
+            span,
+        }
+    }
+
+    /// Run loop created by `make_loop` under supervision
+    ///
+    /// Loop which returns normally is not restarted.
+    pub fn spawn<F, Fut>(self: &Arc<Self>, name: &str, lifecycle: Arc<Lifecycle>, make_loop: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let supervisor = self.clone();
+        let name = name.to_string();
+        let task = tokio::spawn(
+            async move {
+                let mut backoff = INITIAL_RESTART_BACKOFF;
+                loop {
+                    supervisor.set_state(&name, LoopState::Running);
+                    let started = Instant::now();
+                    let task = tokio::spawn(make_loop().in_current_span());
+                    let _abort = AbortOnDrop(task.abort_handle());
+                    let result = task.await;
+
+                    let error = match result {
+                        Err(e) if e.is_panic() => panic_message(e.into_panic()),
+                        _ => break,
+                    };
+                    let restarts = supervisor.record_failure(&name, &error);
+                    error!(task = %name, error = %error, restarts, "Background task failed");
+                    supervisor.events.emit(NodeEvent::BackgroundTaskFailed {
+                        name: name.clone(),
+                        error,
+                        restarts,
+                    });
+
+                    if started.elapsed() >= STABLE_RUN_TIME {
+                        backoff = INITIAL_RESTART_BACKOFF;
+                    }
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+
+                    if !lifecycle.is_running() {
+                        break;
+                    }
+                    info!(task = %name, "Restarting background task");
+                }
+                supervisor.set_state(&name, LoopState::Stopped);
+            }
+            .instrument(self.span.clone()),
+        );
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(task);
+    }
+
+    /// Abort all loops, they are reported as stopped
+    pub fn abort_all(&self) {
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+        for health in self.health.lock().unwrap().values_mut() {
+            health.state = LoopState::Stopped;
+        }
+    }
+
+    fn set_state(&self, name: &str, state: LoopState) {
+        self.health
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_insert_with(|| LoopHealth::new(state))
+            .state = state;
+    }
+
+    /// Save failure, return count of restarts including the next one
+    fn record_failure(&self, name: &str, error: &str) -> u32 {
+        let mut health = self.health.lock().unwrap();
+        let loop_health = health
+            .entry(name.to_string())
+            .or_insert_with(|| LoopHealth::new(LoopState::Restarting));
+        loop_health.state = LoopState::Restarting;
+        loop_health.restarts += 1;
+        loop_health.last_failure = Some(error.to_string());
+        loop_health.last_failure_at = Some(get_now_f64());
+        loop_health.restarts
+    }
+
+    /// Health of all loops by name
+    pub fn loops(&self) -> HashMap<String, LoopHealth> {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// No loop waits for restart and no loop failed recently
+    pub fn is_healthy(&self) -> bool {
+        let now = get_now_f64();
+        self.health.lock().unwrap().values().all(|h| {
+            h.state != LoopState::Restarting
+                && h.last_failure_at
+                    .is_none_or(|failed_at| now - failed_at > DEGRADED_WINDOW)
+        })
+    }
+}
+
+/// Abort the task of the loop when its supervising task ends or is aborted
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Text of the panic payload
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::lifecycle::NodeStatus;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn aborted_loop_stops_and_stop_wakes_waiters() {
+        let events = EventBus::new(16);
+        let lifecycle = Arc::new(Lifecycle::new(events.clone()));
+        lifecycle.advance(&[NodeStatus::Created], NodeStatus::Ready);
+        let supervisor = Arc::new(Supervisor::new(events, Span::none()));
+
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = ticks.clone();
+        supervisor.spawn("ticker", lifecycle.clone(), move || {
+            let counter = counter.clone();
+            async move {
+                loop {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(ticks.load(Ordering::SeqCst) > 0);
+
+        supervisor.abort_all();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let stopped_at = ticks.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(ticks.load(Ordering::SeqCst), stopped_at);
+        assert_eq!(supervisor.loops()["ticker"].state, LoopState::Stopped);
+
+        let waiting = tokio::spawn({
+            let lifecycle = lifecycle.clone();
+            async move { lifecycle.stopping().await }
+        });
+        lifecycle.advance(&[NodeStatus::Ready], NodeStatus::Stopping);
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
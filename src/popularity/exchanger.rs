@@ -1,13 +1,23 @@
+use serde::Deserialize;
 use serde_json::{Value, json};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
-use crate::dht::node::Node;
+use crate::dht::key_presence::KeyPresenceIndex;
+use crate::dht::node::{Node, NodeID};
 use crate::network::protocol::NetworkProtocol;
 use crate::popularity::metrics::{MetricsCollector, PopularityMetrics};
 use crate::popularity::ranking::{PopularityRanker, RankedItem};
+use crate::popularity::seed_registry::SeedRegistry;
+use crate::popularity::trending::{TrendingItem, TrendingTracker};
+use crate::popularity::validation::{
+    MAX_REPLICATION_STEP, MAX_REPORTED_SCORE, PeerReportAggregator, validate_item,
+    verify_attestation,
+};
+use crate::storage::keys::Key;
+use crate::utils::bloom::BloomFilter;
 use crate::utils::time::get_now_f64;
 
 /// Structure for exchange popularity nodes
@@ -17,18 +27,33 @@ pub struct PopularityExchanger {
     /// For track popular threads
     pub ranker: Arc<PopularityRanker>,
     /// Collector of metrics about threads
-    pub metrics_collector: Option<Arc<RwLock<MetricsCollector>>>,
+    pub metrics_collector: Option<Arc<MetricsCollector>>,
     /// Cache of best of the best threads
     global_ranking: RwLock<Vec<RankedItem>>,
     /// Last update of the global ranking
     global_ranking_updated: RwLock<f64>,
+    /// Is our node a seed node
+    pub is_seed: bool,
+    /// Seed nodes known from exchanges and keys they report
+    pub seed_registry: RwLock<SeedRegistry>,
+    /// Votes of peers about metrics of content
+    peer_reports: RwLock<PeerReportAggregator>,
+    /// Global ranking older than this time in seconds is stale
+    pub global_update_interval: f64,
+    /// Tracker of the score growth
+    pub trending: RwLock<TrendingTracker>,
+    /// Bloom filters of stored keys which we send and receive with popularity
+    pub key_presence: Option<Arc<KeyPresenceIndex>>,
 }
 
 impl PopularityExchanger {
     pub fn new(
         network_protocol: Arc<NetworkProtocol>,
         ranker: Arc<PopularityRanker>,
-        metrics_collector: Option<Arc<RwLock<MetricsCollector>>>,
+        metrics_collector: Option<Arc<MetricsCollector>>,
+        is_seed: bool,
+        global_update_interval: f64,
+        trending_window: f64,
     ) -> Self {
         Self {
             network_protocol,
@@ -36,24 +61,81 @@ impl PopularityExchanger {
             metrics_collector,
             global_ranking: RwLock::new(Vec::new()),
             global_ranking_updated: RwLock::new(0.0),
+            is_seed,
+            seed_registry: RwLock::new(SeedRegistry::new()),
+            peer_reports: RwLock::new(PeerReportAggregator::new()),
+            global_update_interval,
+            trending: RwLock::new(TrendingTracker::new(trending_window)),
+            key_presence: None,
         }
     }
 
-    /// Collect local metrics
-    pub async fn get_local_metrics(&self) -> Option<HashMap<Vec<u8>, PopularityMetrics>> {
-        let collector_lock = self.metrics_collector.as_ref()?;
+    /// Payload of the popularity exchange message with our top items
+    ///
+    /// Items are signed by the key of our node, without the key peers drop them.
+    pub fn build_exchange_payload(&self, items: Vec<Value>) -> Value {
+        let mut payload = json!({
+            "node_id": self.network_protocol.node_id,
+            "node_type": if self.is_seed { "seed" } else { "peer" },
+            "items": items
+        });
+
+        if let Some(bloom) = self
+            .key_presence
+            .as_ref()
+            .and_then(|index| index.local_compressed())
+        {
+            payload["bloom"] = json!(hex::encode(bloom));
+        }
+
+        #[cfg(feature = "signatures")]
+        if let Some(signer) = self
+            .network_protocol
+            .receipt_signer
+            .as_ref()
+            .filter(|signer| signer.is_bound_to_node_id())
+        {
+            super::validation::attach_attestation(signer, &mut payload);
+        }
 
-        let collector = collector_lock.read().await;
-        Some(collector.get_all_metrics().clone())
+        payload
+    }
+
+    /// Save Bloom filter of stored keys from exchange payload of the peer
+    pub fn record_key_filter(&self, sender_id: &[u8], payload: &Value) {
+        let index = match &self.key_presence {
+            Some(index) => index,
+            None => return,
+        };
+
+        let filter = payload
+            .get("bloom")
+            .and_then(|v| v.as_str())
+            .and_then(|s| hex::decode(s).ok())
+            .and_then(|bytes| BloomFilter::from_compressed(&bytes));
+
+        match filter {
+            Some(filter) => index.record_peer(sender_id.to_vec(), filter),
+            None if payload.get("bloom").is_some() => {
+                debug!("Invalid key filter in popularity exchange");
+            }
+            None => {}
+        }
+    }
+
+    /// Collect local metrics
+    pub async fn get_local_metrics(&self) -> Option<HashMap<Key, PopularityMetrics>> {
+        let collector = self.metrics_collector.as_ref()?;
+        Some(collector.get_all_metrics())
     }
 
     /// Exchange top-N elements with neighbor nodes
     pub async fn exchange_top_items(
         &self,
-        local_metrics: HashMap<Vec<u8>, PopularityMetrics>,
+        local_metrics: HashMap<Key, PopularityMetrics>,
         neighbor_nodes: Vec<Node>,
         top_n: usize,
-    ) -> HashMap<Vec<u8>, PopularityMetrics> {
+    ) -> HashMap<Key, PopularityMetrics> {
         let local_ranked = self.ranker.rank_items(&local_metrics, Some(top_n));
 
         let exchange_data: Vec<Value> = local_ranked
@@ -71,24 +153,49 @@ impl PopularityExchanger {
             return local_metrics;
         }
 
-        let mut tasks = Vec::new();
-        for _node in neighbor_nodes.iter().take(5) {
-            tasks.push(exchange_data.clone());
-        }
-
-        let results = tasks;
+        let payload = self.build_exchange_payload(exchange_data);
+        let neighbors = &neighbor_nodes[..neighbor_nodes.len().min(5)];
+        let results = self
+            .network_protocol
+            .exchange_popularity_many(neighbors, payload)
+            .await
+            .unwrap_or_default();
 
         let mut updated_metrics = local_metrics;
         let mut received_count = 0;
 
-        for result in results {
-            let received_items = result;
-            {
-                received_count += received_items.len();
-                for item_val in received_items {
-                    if let Err(e) = self.process_single_item(&mut updated_metrics, item_val) {
-                        warn!(error = %e, "Error processing received item during exchange");
-                    }
+        for response in results.into_iter().flatten() {
+            received_count += response
+                .get("items")
+                .and_then(|v| v.as_array())
+                .map_or(0, Vec::len);
+
+            let (sender_id, sender_is_seed) = Self::parse_sender(&response);
+            let sender_id = match sender_id {
+                Some(id) => id,
+                None => {
+                    warn!("Popularity exchange response without sender, ignored");
+                    continue;
+                }
+            };
+
+            self.record_key_filter(&sender_id, &response);
+
+            let accepted = self.aggregate_peer_items(&sender_id, &response).await;
+            if sender_is_seed {
+                self.record_seed_reports(&sender_id, &accepted).await;
+            }
+
+            let clock_offset = self.network_protocol.clock.offset(&sender_id);
+            for (key, replication, item_val) in accepted {
+                if let Err(e) = self.process_single_item(
+                    &mut updated_metrics,
+                    key,
+                    replication,
+                    item_val,
+                    clock_offset,
+                ) {
+                    warn!(error = %e, "Error processing received item during exchange");
                 }
             }
         }
@@ -103,45 +210,151 @@ impl PopularityExchanger {
         updated_metrics
     }
 
-    /// Function for render one item
+    /// Function for render one validated item
+    ///
+    /// `replication` is the aggregated vote of peers, growth from one round is capped.
+    /// Times of new metrics are moved to our clock by `clock_offset` of the sender.
     fn process_single_item(
         &self,
-        metrics_map: &mut HashMap<Vec<u8>, PopularityMetrics>,
+        metrics_map: &mut HashMap<Key, PopularityMetrics>,
+        key: Key,
+        replication: u32,
         data: Value,
+        clock_offset: f64,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let key_hex = data["key"].as_str().ok_or("Missing key")?;
-        let key = hex::decode(key_hex)?;
-        let received_metrics_val = data.get("metrics").cloned().unwrap_or(Value::Null);
-
         if let Some(existing_metrics) = metrics_map.get_mut(&key) {
-            let received_replication = received_metrics_val["replication_count"]
-                .as_u64()
-                .unwrap_or(1) as u32;
-            existing_metrics.update_replication(received_replication);
+            let capped = replication.min(existing_metrics.replication_count + MAX_REPLICATION_STEP);
+            existing_metrics.update_replication(capped);
         } else {
-            let new_metrics = PopularityMetrics::from_dict(received_metrics_val)?;
+            let received_metrics_val = data.get("metrics").cloned().unwrap_or(Value::Null);
+            let mut new_metrics = PopularityMetrics::from_dict(received_metrics_val)?;
+            new_metrics.replication_count = replication.min(1 + MAX_REPLICATION_STEP);
+            new_metrics.shift_clock(clock_offset);
             metrics_map.insert(key, new_metrics);
         }
         Ok(())
     }
 
-    /// Press received items
-    pub async fn process_received_items(&self, items: Vec<Value>) {
-        let collector_lock = match &self.metrics_collector {
+    /// Validate items of the exchange payload of the peer and save its votes
+    ///
+    /// Return accepted items with median replication count between all peers. Items are
+    /// accepted only if the payload is signed by the key of the sender.
+    async fn aggregate_peer_items(
+        &self,
+        sender_id: &[u8],
+        payload: &Value,
+    ) -> Vec<(Key, u32, Value)> {
+        let items = match payload.get("items").and_then(|v| v.as_array()) {
+            Some(items) if !items.is_empty() => items,
+            _ => return Vec::new(),
+        };
+        if !verify_attestation(sender_id, payload) {
+            warn!(
+                peer = %hex::encode(&sender_id[..sender_id.len().min(8)]),
+                items = items.len(),
+                "Popularity items without valid attestation of the peer, ignored"
+            );
+            return Vec::new();
+        }
+
+        let mut reports = self.peer_reports.write().await;
+        reports.cleanup_expired();
+
+        let mut accepted = Vec::new();
+        let mut rejected = 0;
+        for item in items {
+            match validate_item(item) {
+                Ok(valid) => {
+                    reports.record(
+                        valid.key.clone(),
+                        sender_id.to_vec(),
+                        valid.replication_count,
+                    );
+                    let median = reports
+                        .median_replication(&valid.key)
+                        .unwrap_or(valid.replication_count);
+                    accepted.push((valid.key, median, item.clone()));
+                }
+                Err(reason) => {
+                    rejected += 1;
+                    debug!(reason = reason, "Rejected popularity item");
+                }
+            }
+        }
+
+        if rejected > 0 {
+            warn!(
+                peer = %hex::encode(&sender_id[..sender_id.len().min(8)]),
+                rejected = rejected,
+                "Rejected invalid popularity items from peer"
+            );
+        }
+
+        accepted
+    }
+
+    /// Get sender node ID and seed flag from exchange payload
+    pub fn parse_sender(payload: &Value) -> (Option<Vec<u8>>, bool) {
+        let sender_id = payload
+            .get("node_id")
+            .and_then(|v| NodeID::deserialize(v).ok())
+            .map(|id| id.0.to_vec());
+        let is_seed = payload.get("node_type").and_then(|v| v.as_str()) == Some("seed");
+        (sender_id, is_seed)
+    }
+
+    /// Remember keys reported by seed node and recompute their seed coverage
+    async fn record_seed_reports(&self, seed_id: &[u8], items: &[(Key, u32, Value)]) {
+        let keys: Vec<&Key> = items.iter().map(|(key, _, _)| key).collect();
+
+        let mut registry = self.seed_registry.write().await;
+        registry.cleanup_expired();
+        registry.register_seed(seed_id.to_vec());
+        for key in keys {
+            registry.record_report(key.clone(), seed_id.to_vec());
+        }
+
+        if let Some(collector) = &self.metrics_collector {
+            for (key, _) in collector.get_all_metrics() {
+                collector.update_seed_coverage(&key, registry.coverage(&key));
+            }
+        }
+    }
+
+    /// Press items of the exchange payload received from the peer
+    ///
+    /// Items without valid attestation of the sender are dropped. Replication count is
+    /// the median between votes of all peers, and one exchange can raise it only by
+    /// [`MAX_REPLICATION_STEP`]. If sender is seed node its items are used for seed
+    /// coverage of the content.
+    pub async fn process_received_items(
+        &self,
+        payload: &Value,
+        sender_id: Option<Vec<u8>>,
+        sender_is_seed: bool,
+    ) {
+        let sender_id = match sender_id {
+            Some(id) => id,
+            None => {
+                warn!("Popularity items without sender, ignored");
+                return;
+            }
+        };
+
+        let accepted = self.aggregate_peer_items(&sender_id, payload).await;
+        if sender_is_seed {
+            self.record_seed_reports(&sender_id, &accepted).await;
+        }
+
+        let collector = match &self.metrics_collector {
             Some(c) => c,
             None => return,
         };
 
-        let mut collector = collector_lock.write().await;
-        for item_data in items {
-            if let Some(key_hex) = item_data["key"].as_str()
-                && let Ok(key) = hex::decode(key_hex)
-                && let Some(metrics) = collector.metrics.get_mut(&key)
-            {
-                let rep = item_data["metrics"]["replication_count"]
-                    .as_u64()
-                    .unwrap_or(1) as u32;
-                metrics.update_replication(rep);
+        for (key, replication, _) in accepted {
+            if let Some(current) = collector.get_metrics(&key) {
+                let capped = replication.min(current.replication_count + MAX_REPLICATION_STEP);
+                collector.update_replication(&key, capped);
             }
         }
     }
@@ -152,7 +365,7 @@ impl PopularityExchanger {
         local_rankings: Vec<RankedItem>,
         seed_nodes: Vec<Node>,
     ) -> Vec<RankedItem> {
-        let mut all_scores: HashMap<Vec<u8>, Vec<f64>> = HashMap::new();
+        let mut all_scores: HashMap<Key, Vec<f64>> = HashMap::new();
 
         for item in &local_rankings {
             all_scores
@@ -171,13 +384,21 @@ impl PopularityExchanger {
         }
 
         let results = futures::future::join_all(tasks).await;
+        let mut remote_metrics: HashMap<Key, PopularityMetrics> = HashMap::new();
 
         for received_ranking in results.into_iter().flatten() {
             for item_val in received_ranking {
                 if let (Some(key_hex), Some(score)) =
                     (item_val["key"].as_str(), item_val["score"].as_f64())
-                    && let Ok(key) = hex::decode(key_hex)
+                    && let Some(key) = Key::from_hex(key_hex)
+                    && (0.0..=MAX_REPORTED_SCORE).contains(&score)
                 {
+                    if !remote_metrics.contains_key(&key)
+                        && let Ok(metrics) =
+                            PopularityMetrics::from_dict(item_val["metrics"].clone())
+                    {
+                        remote_metrics.insert(key.clone(), metrics);
+                    }
                     all_scores.entry(key).or_default().push(score);
                 }
             }
@@ -185,7 +406,7 @@ impl PopularityExchanger {
 
         let mut consensus_ranking = Vec::new();
         let collector = if let Some(c) = &self.metrics_collector {
-            c.read().await
+            c
         } else {
             return Vec::new();
         };
@@ -198,11 +419,14 @@ impl PopularityExchanger {
             scores.sort_by(|a, b| a.total_cmp(b));
             let median_score = scores[scores.len() / 2];
 
-            if let Some(metrics) = collector.get_metrics(&key) {
+            let metrics = collector
+                .get_metrics(&key)
+                .or_else(|| remote_metrics.remove(&key));
+            if let Some(metrics) = metrics {
                 consensus_ranking.push(RankedItem {
                     key,
                     score: median_score,
-                    metrics: metrics.clone(),
+                    metrics,
                 });
             }
         }
@@ -225,8 +449,79 @@ impl PopularityExchanger {
         final_top
     }
 
+    /// Check that global ranking was not updated longer than `global_update_interval`
+    pub async fn is_global_ranking_stale(&self) -> bool {
+        get_now_f64() - *self.global_ranking_updated.read().await >= self.global_update_interval
+    }
+
+    /// Find seed nodes for global ranking
+    ///
+    /// Prefer nodes from routing table which are known as seeds from popularity exchange.
+    /// If we do not know any seed, all neighbors are asked.
+    pub async fn discover_seed_nodes(&self) -> Vec<Node> {
+        let all_nodes = match &self.network_protocol.routing_table {
+            Some(rt) => rt.read().await.get_all_nodes(),
+            None => return Vec::new(),
+        };
+
+        let registry = self.seed_registry.read().await;
+        let seeds: Vec<Node> = all_nodes
+            .iter()
+            .filter(|n| registry.is_seed(&n.node_id.0))
+            .cloned()
+            .collect();
+
+        if seeds.is_empty() { all_nodes } else { seeds }
+    }
+
+    /// Refresh global ranking if it is stale
+    ///
+    /// Return `true` if ranking was refreshed
+    pub async fn refresh_global_ranking_if_stale(&self) -> bool {
+        if !self.is_global_ranking_stale().await {
+            return false;
+        }
+        self.refresh_global_ranking().await;
+        true
+    }
+
+    /// Aggregate global ranking from our metrics and rankings of seed nodes now
+    pub async fn refresh_global_ranking(&self) {
+        let local_ranked = match self.get_local_metrics().await {
+            Some(metrics) => self.ranker.rank_items(&metrics, Some(100)),
+            None => Vec::new(),
+        };
+        let seed_nodes = self.discover_seed_nodes().await;
+
+        self.aggregate_global_ranking(local_ranked, seed_nodes)
+            .await;
+    }
+
+    /// Save scores of the local ranking round for trending detection
+    pub async fn record_ranking(&self, ranked: &[RankedItem]) {
+        self.trending.write().await.record_scores(ranked);
+    }
+
+    /// Items with the fastest growing score
+    pub async fn get_trending_items(&self, limit: usize) -> Vec<TrendingItem> {
+        self.trending.read().await.get_trending_items(limit)
+    }
+
+    /// Get trending items in API format
+    pub async fn get_trending_api(&self, limit: usize) -> Vec<Value> {
+        self.get_trending_items(limit)
+            .await
+            .iter()
+            .map(|item| item.to_dict())
+            .collect()
+    }
+
     /// Get global ranking in API format
     pub async fn get_global_ranking_api(&self) -> Vec<Value> {
+        if self.is_global_ranking_stale().await {
+            debug!("Global ranking is stale, serving cached data until next refresh");
+        }
+
         let ranking = self.global_ranking.read().await;
 
         ranking
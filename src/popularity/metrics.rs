@@ -1,12 +1,20 @@
-use crate::utils::time::get_now_f64;
+use crate::storage::keys::Key;
+use crate::utils::hyperloglog::HyperLogLog;
+use crate::utils::time::{Clock, SystemClock};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, RwLock};
 use tracing::{debug, info};
 
+/// Count of request times which are kept for the request rate
+pub const REQUEST_HISTORY: usize = 1000;
+/// Shortest span of request times in seconds which the request rate is counted over
+const MIN_RATE_SPAN: f64 = 60.0;
+
 /// Collect metrics by check all manipulations with data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PopularityMetrics {
-    pub key: Vec<u8>,
+    pub key: Key,
 
     /// Count of requests
     pub request_count: u64,
@@ -16,7 +24,7 @@ pub struct PopularityMetrics {
     pub replication_count: u32,
     /// Evaluates how recent the data is
     pub freshness_score: f64,
-    /// Count of uniq nodes which requests this data
+    /// Count of uniq nodes which requests this data _(estimated for big audience)_
     pub audience_size: usize,
 
     pub social_engagements: u64,
@@ -27,15 +35,18 @@ pub struct PopularityMetrics {
     pub last_request: f64,
     pub created_at: Option<f64>,
 
+    /// Times of requests with count of requests at every time, the latest
+    /// [`REQUEST_HISTORY`] times are kept
     #[serde(skip)]
-    pub request_timestamps: VecDeque<f64>,
+    pub request_timestamps: VecDeque<(f64, u64)>,
+    /// Sketch of nodes which requested this data, its memory does not grow with them
     #[serde(skip)]
-    pub requesting_nodes: HashSet<Vec<u8>>,
+    pub requesting_nodes: HyperLogLog,
 }
 
 impl PopularityMetrics {
-    pub fn new(key: Vec<u8>) -> Self {
-        let now = get_now_f64();
+    /// Metrics of the key first seen at `now`
+    pub fn new(key: Key, now: f64) -> Self {
         Self {
             key,
             request_count: 0,
@@ -49,50 +60,75 @@ impl PopularityMetrics {
             first_seen: now,
             last_request: now,
             created_at: None,
-            request_timestamps: VecDeque::with_capacity(1000),
-            requesting_nodes: HashSet::new(),
+            request_timestamps: VecDeque::with_capacity(REQUEST_HISTORY),
+            requesting_nodes: HyperLogLog::new(),
         }
     }
 
-    /// Register active action
-    pub fn update_request(&mut self, node_id: Option<Vec<u8>>) {
-        let now = get_now_f64();
+    /// Register active action at `now`
+    pub fn update_request(&mut self, node_id: Option<Vec<u8>>, now: f64) {
         self.request_count += 1;
         self.last_request = now;
+        self.push_requests(now, 1);
 
-        if self.request_timestamps.len() >= 1000 {
-            self.request_timestamps.pop_front();
+        if let Some(id) = node_id {
+            self.requesting_nodes.insert(&id);
+            self.audience_size = self.requesting_nodes.estimate();
         }
-        self.request_timestamps.push_back(now);
 
-        if let Some(id) = node_id {
-            self.requesting_nodes.insert(id);
-            self.audience_size = self.requesting_nodes.len();
+        self.update_request_rate();
+    }
+
+    /// Register `count` reads of our stored copy by peers, the last one at `at`
+    pub fn update_reads(&mut self, count: u64, at: f64) {
+        if count == 0 {
+            return;
         }
+        self.request_count += count;
+        self.last_request = self.last_request.max(at);
+        self.push_requests(at, count);
+        self.update_request_rate();
+    }
 
+    /// Keep time of `count` requests, requests at the time of the last kept ones are added
+    /// to them
+    fn push_requests(&mut self, at: f64, count: u64) {
+        if let Some((last, last_count)) = self.request_timestamps.back_mut()
+            && *last == at
+        {
+            *last_count += count;
+            return;
+        }
+        if self.request_timestamps.len() >= REQUEST_HISTORY {
+            self.request_timestamps.pop_front();
+        }
+        self.request_timestamps.push_back((at, count));
+    }
+
+    /// Requests per hour over the kept timestamps
+    fn update_request_rate(&mut self) {
         let ts_len = self.request_timestamps.len();
         if ts_len > 1 {
-            let first = *self.request_timestamps.front().unwrap();
-            let last = *self.request_timestamps.back().unwrap();
-            let time_span = last - first;
-
-            if time_span > 0.0 {
-                self.request_rate = (ts_len as f64 / time_span) * 3600.0;
-            } else {
-                self.request_rate = ts_len as f64 * 3600.0;
-            }
+            // Reads of peers come in batches which may be older than the last request
+            let (first, last) = self.request_timestamps.iter().fold(
+                (f64::INFINITY, f64::NEG_INFINITY),
+                |(first, last), (at, _)| (first.min(*at), last.max(*at)),
+            );
+            let time_span = (last - first).max(MIN_RATE_SPAN);
+            let requests: u64 = self.request_timestamps.iter().map(|(_, count)| count).sum();
+            self.request_rate = (requests as f64 / time_span) * 3600.0;
         } else {
             self.request_rate = if self.request_count > 0 { 1.0 } else { 0.0 };
         }
     }
 
-    /// Update fresh metrics
-    pub fn update_freshness(&mut self, age_seconds: Option<f64>) {
+    /// Update fresh metrics, age is counted to `now` if it is not given
+    pub fn update_freshness(&mut self, age_seconds: Option<f64>, now: f64) {
         let age = match age_seconds {
             Some(a) => a,
             None => {
                 let start_time = self.created_at.unwrap_or(self.first_seen);
-                get_now_f64() - start_time
+                now - start_time
             }
         };
 
@@ -111,24 +147,80 @@ impl PopularityMetrics {
         self.replication_count = self.replication_count.max(count);
     }
 
+    /// Set fraction of known seeds which report this content
+    pub fn update_seed_coverage(&mut self, coverage: f64) {
+        self.seed_coverage = coverage.clamp(0.0, 1.0);
+    }
+
     pub fn update_social_engagement(&mut self, count: u64) {
         self.social_engagements += count;
     }
 
+    /// Add time which users spent viewing the content
+    pub fn update_view_time(&mut self, seconds: f64) {
+        if seconds.is_finite() && seconds > 0.0 {
+            self.view_time += seconds;
+        }
+    }
+
+    /// Multiplier of the score for time without requests, it halves every `half_life`
+    /// seconds _(1.0 if `half_life` is 0)_
+    pub fn decay_factor(&self, now: f64, half_life: f64) -> f64 {
+        if half_life <= 0.0 {
+            return 1.0;
+        }
+        let idle = (now - self.last_request).max(0.0);
+        0.5f64.powf(idle / half_life)
+    }
+
+    /// Convert times of metrics received from peer to our clock
+    ///
+    /// `offset` is how much the peer clock is ahead of ours
+    pub fn shift_clock(&mut self, offset: f64) {
+        self.first_seen -= offset;
+        self.last_request -= offset;
+        if let Some(created_at) = self.created_at.as_mut() {
+            *created_at -= offset;
+        }
+    }
+
     pub fn to_dict(&self) -> serde_json::Value {
         serde_json::to_value(self).unwrap_or(serde_json::Value::Null)
     }
 
     pub fn from_dict(data: serde_json::Value) -> Result<Self, serde_json::Error> {
         let mut metrics: Self = serde_json::from_value(data)?;
-        metrics.request_timestamps = VecDeque::with_capacity(1000);
-        metrics.requesting_nodes = HashSet::new();
+        metrics.request_timestamps = VecDeque::with_capacity(REQUEST_HISTORY);
+        metrics.requesting_nodes = HyperLogLog::new();
         Ok(metrics)
     }
 }
 
+/// Number of shards used when the collector is built with [`MetricsCollector::new`]
+pub const DEFAULT_SHARD_COUNT: usize = 16;
+
+/// Default upper bound of tracked keys
+pub const DEFAULT_MAX_ENTRIES: usize = 100_000;
+
+type Shard = HashMap<Key, PopularityMetrics>;
+
+/// Collector of popularity metrics
+///
+/// Metrics are split into shards by the first byte of the key, so concurrent
+/// `find_value`/`store` calls on different keys do not fight for one lock.
+/// Total size is bounded: when a shard is full the least recently requested
+/// entry is evicted.
 pub struct MetricsCollector {
-    pub metrics: HashMap<Vec<u8>, PopularityMetrics>,
+    shards: Vec<RwLock<Shard>>,
+    /// Max entries for one shard
+    shard_capacity: usize,
+    /// Keys whose TTL is boosted for popularity
+    ///
+    /// Set is kept only in memory and is empty after restart. Values boosted before it are
+    /// not demoted, they keep the boosted TTL until it expires or they are promoted again.
+    promoted: RwLock<HashSet<Key>>,
+    /// Clock of the node
+    time: Arc<dyn Clock>,
 }
 
 impl Default for MetricsCollector {
@@ -139,79 +231,250 @@ impl Default for MetricsCollector {
 
 impl MetricsCollector {
     pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_MAX_ENTRIES, DEFAULT_SHARD_COUNT)
+    }
+
+    /// Create collector with limit of tracked keys split across `shard_count` shards
+    pub fn with_capacity(max_entries: usize, shard_count: usize) -> Self {
+        let shard_count = shard_count.max(1);
+        let shard_capacity = max_entries.div_ceil(shard_count).max(1);
         Self {
-            metrics: HashMap::new(),
+            shards: (0..shard_count)
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            shard_capacity,
+            promoted: RwLock::new(HashSet::new()),
+            time: Arc::new(SystemClock),
         }
     }
 
-    pub fn record_find_value(&mut self, key: Vec<u8>, node_id: Option<Vec<u8>>) {
-        let m = self
-            .metrics
-            .entry(key.clone())
-            .or_insert_with(|| PopularityMetrics::new(key.clone()));
-        m.update_request(node_id);
-        m.update_freshness(None);
+    /// Same collector which dates requests by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
 
-        debug!(
-            "Recorded FIND_VALUE for key: {}",
-            hex::encode(&key[..key.len().min(8)])
-        );
+    /// Choose shard by key prefix
+    fn shard(&self, key: &[u8]) -> &RwLock<Shard> {
+        let idx = key.first().copied().unwrap_or(0) as usize % self.shards.len();
+        &self.shards[idx]
     }
 
-    pub fn record_store(&mut self, key: Vec<u8>, replication_count: u32) {
-        let m = self
-            .metrics
+    /// Run closure on metrics of the key, create them if they do not exist
+    fn with_entry<R>(&self, key: Key, f: impl FnOnce(&mut PopularityMetrics, f64) -> R) -> R {
+        let now = self.time.now();
+        let mut shard = self.shard(&key).write().unwrap_or_else(|e| e.into_inner());
+
+        if !shard.contains_key(&key) && shard.len() >= self.shard_capacity {
+            Self::evict_lru(&mut shard);
+        }
+
+        let m = shard
             .entry(key.clone())
-            .or_insert_with(|| PopularityMetrics::new(key.clone()));
-        m.update_replication(replication_count);
-        m.update_freshness(None);
+            .or_insert_with(|| PopularityMetrics::new(key, now));
+        f(m, now)
+    }
+
+    /// Remove the least recently requested entry from the shard
+    fn evict_lru(shard: &mut Shard) {
+        let oldest = shard
+            .iter()
+            .min_by(|a, b| a.1.last_request.total_cmp(&b.1.last_request))
+            .map(|(k, _)| k.clone());
+
+        if let Some(key) = oldest {
+            shard.remove(&key);
+            debug!("Evicted metrics for key: {}", key.short_hex());
+        }
+    }
+
+    pub fn record_find_value(&self, key: Key, node_id: Option<Vec<u8>>) {
+        let key_hex = key.short_hex();
+        self.with_entry(key, |m, now| {
+            m.update_request(node_id, now);
+            m.update_freshness(None, now);
+        });
+
+        debug!("Recorded FIND_VALUE for key: {}", key_hex);
+    }
+
+    pub fn record_store(&self, key: Key, replication_count: u32) {
+        let key_hex = key.short_hex();
+        self.with_entry(key, |m, now| {
+            m.update_replication(replication_count);
+            m.update_freshness(None, now);
+        });
 
         debug!(
             "Recorded STORE for key: {}, replication: {}",
-            hex::encode(&key[..key.len().min(8)]),
-            replication_count
+            key_hex, replication_count
         );
     }
 
-    pub fn record_social_engagement(&mut self, key: Vec<u8>, count: u64) {
-        let m = self
-            .metrics
-            .entry(key.clone())
-            .or_insert_with(|| PopularityMetrics::new(key.clone()));
-        m.update_social_engagement(count);
+    /// Reads of our stored copy counted by storage _(see [`Storage::record_access`])_
+    ///
+    /// [`Storage::record_access`]: crate::storage::main::Storage::record_access
+    pub fn record_reads(&self, key: Key, count: u64, last_access: f64) {
+        let key_hex = key.short_hex();
+        self.with_entry(key, |m, now| {
+            m.update_reads(count, last_access);
+            m.update_freshness(None, now);
+        });
+
+        debug!("Recorded {} reads for key: {}", count, key_hex);
+    }
+
+    pub fn record_social_engagement(&self, key: Key, count: u64) {
+        let key_hex = key.short_hex();
+        self.with_entry(key, |m, _| m.update_social_engagement(count));
 
         debug!(
             "Recorded social engagement for key: {}, count: {}",
-            hex::encode(&key[..key.len().min(8)]),
-            count
+            key_hex, count
         );
     }
 
-    pub fn get_metrics(&self, key: &[u8]) -> Option<&PopularityMetrics> {
-        self.metrics.get(key)
+    pub fn record_view_time(&self, key: Key, seconds: f64) {
+        let key_hex = key.short_hex();
+        self.with_entry(key, |m, _| m.update_view_time(seconds));
+
+        debug!(
+            "Recorded view time for key: {}, seconds: {}",
+            key_hex, seconds
+        );
+    }
+
+    /// Update replication count of already tracked key
+    ///
+    /// Return `false` if we do not track this key
+    pub fn update_replication(&self, key: &[u8], count: u32) -> bool {
+        let mut shard = self.shard(key).write().unwrap_or_else(|e| e.into_inner());
+        match shard.get_mut(key) {
+            Some(m) => {
+                m.update_replication(count);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Update seed coverage of already tracked key
+    ///
+    /// Return `false` if we do not track this key
+    pub fn update_seed_coverage(&self, key: &[u8], coverage: f64) -> bool {
+        let mut shard = self.shard(key).write().unwrap_or_else(|e| e.into_inner());
+        match shard.get_mut(key) {
+            Some(m) => {
+                m.update_seed_coverage(coverage);
+                true
+            }
+            None => false,
+        }
     }
 
-    pub fn get_all_metrics(&self) -> &HashMap<Vec<u8>, PopularityMetrics> {
-        &self.metrics
+    pub fn get_metrics(&self, key: &[u8]) -> Option<PopularityMetrics> {
+        let shard = self.shard(key).read().unwrap_or_else(|e| e.into_inner());
+        shard.get(key).cloned()
     }
 
-    pub fn update_all_freshness(&mut self) {
-        for m in self.metrics.values_mut() {
-            m.update_freshness(None);
+    /// Snapshot of all metrics from all shards
+    pub fn get_all_metrics(&self) -> HashMap<Key, PopularityMetrics> {
+        let mut all = HashMap::with_capacity(self.len());
+        for shard in &self.shards {
+            let shard = shard.read().unwrap_or_else(|e| e.into_inner());
+            all.extend(shard.iter().map(|(k, v)| (k.clone(), v.clone())));
         }
+        all
+    }
+
+    /// Count of tracked keys
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|s| s.read().unwrap_or_else(|e| e.into_inner()).len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remember that TTL of the key is boosted for popularity _(until restart)_
+    pub fn mark_promoted(&self, key: Key) {
+        self.promoted
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key);
     }
 
-    pub fn cleanup_old_metrics(&mut self, max_age_days: u64) {
-        let now = get_now_f64();
+    /// Take promoted keys whose score is below `threshold`
+    ///
+    /// Keys without metrics _(evicted or cleaned up)_ are taken too. Taken keys are not
+    /// promoted anymore.
+    pub fn take_demoted(
+        &self,
+        score: impl Fn(&PopularityMetrics) -> f64,
+        threshold: f64,
+    ) -> Vec<Key> {
+        let mut promoted = self.promoted.write().unwrap_or_else(|e| e.into_inner());
+        let demoted: Vec<Key> = promoted
+            .iter()
+            .filter(|key| {
+                self.get_metrics(key)
+                    .is_none_or(|metrics| score(&metrics) < threshold)
+            })
+            .cloned()
+            .collect();
+        for key in &demoted {
+            promoted.remove(key);
+        }
+        demoted
+    }
+
+    pub fn update_all_freshness(&self) {
+        let now = self.time.now();
+        for shard in &self.shards {
+            let mut shard = shard.write().unwrap_or_else(|e| e.into_inner());
+            for m in shard.values_mut() {
+                m.update_freshness(None, now);
+            }
+        }
+    }
+
+    pub fn cleanup_old_metrics(&self, max_age_days: u64) {
+        let now = self.time.now();
         let max_age = max_age_days as f64 * 86400.0;
 
-        let initial_len = self.metrics.len();
-        self.metrics
-            .retain(|_, v| (now - v.last_request) <= max_age);
+        let mut removed = 0;
+        for shard in &self.shards {
+            let mut shard = shard.write().unwrap_or_else(|e| e.into_inner());
+            let initial_len = shard.len();
+            shard.retain(|_, v| (now - v.last_request) <= max_age);
+            removed += initial_len - shard.len();
+        }
 
-        let removed = initial_len - self.metrics.len();
         if removed > 0 {
             info!("Cleaned up old metrics, removed count: {}", removed);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_of_reads_is_kept_as_one_time() {
+        let mut metrics = PopularityMetrics::new(Key::new(b"key".to_vec()), 1000.0);
+        metrics.update_reads(5000, 1000.0);
+        assert_eq!(metrics.request_timestamps.len(), 1);
+        assert_eq!(metrics.request_count, 5000);
+        assert_eq!(metrics.request_rate, 1.0);
+
+        metrics.update_reads(100, 1000.0);
+        metrics.update_reads(900, 4600.0);
+        assert_eq!(metrics.request_timestamps.len(), 2);
+        // 6000 reads over an hour
+        assert_eq!(metrics.request_rate, 6000.0);
+    }
+}
@@ -7,3 +7,9 @@ pub mod exchanger;
 pub mod metrics;
 /// Check all metrics and say is need data to exchange or trade
 pub mod ranking;
+/// Registry of seed nodes for computing seed coverage of the content
+pub mod seed_registry;
+/// Detect content with fast growing popularity
+pub mod trending;
+/// Validation of popularity data received from other peers
+pub mod validation;
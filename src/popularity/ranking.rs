@@ -1,31 +1,72 @@
+use serde::Serialize;
 use std::collections::HashMap;
 
 use crate::popularity::metrics::PopularityMetrics;
+use crate::storage::keys::Key;
 use crate::utils::time::get_now_f64;
 
 /// Element with ranting of popularity
 #[derive(Debug, Clone)]
 pub struct RankedItem {
-    pub key: Vec<u8>,
+    pub key: Key,
     pub score: f64,
     pub metrics: PopularityMetrics,
 }
 
+/// Part of the score given by one metric
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreFactor {
+    pub name: &'static str,
+    /// Metric normalized to `0..1`
+    pub value: f64,
+    /// Weight of the metric for the age of the content
+    pub weight: f64,
+    /// Points of the final score _(value * weight * 10 * decay)_
+    pub contribution: f64,
+}
+
+/// How the score of the content is made up
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreExplanation {
+    pub factors: Vec<ScoreFactor>,
+    /// Multiplier for time without requests
+    pub decay: f64,
+    pub score: f64,
+}
+
 pub struct PopularityRanker {
     pub popularity_threshold: f64,
     pub active_threshold: f64,
+    /// Score halves for every this many seconds without requests _(0 disables decay)_
+    pub decay_half_life: f64,
 }
 
 impl PopularityRanker {
-    pub fn new(popularity_threshold: f64, active_threshold: f64) -> Self {
+    pub fn new(popularity_threshold: f64, active_threshold: f64, decay_half_life: f64) -> Self {
         Self {
             popularity_threshold,
             active_threshold,
+            decay_half_life,
         }
     }
 
     /// Calculate ranking of popularity
+    ///
+    /// Score decays while the content is not requested, so once popular content does not
+    /// stay popular forever.
     pub fn calculate_score(&self, metrics: &PopularityMetrics, adaptive_weights: bool) -> f64 {
+        self.breakdown(metrics, adaptive_weights).score
+    }
+
+    /// Explain the score used for ranking: normalized metrics, their weights and points
+    ///
+    /// Helps operators to tune thresholds and see why content is (not) popular.
+    pub fn explain(&self, metrics: &PopularityMetrics) -> ScoreExplanation {
+        self.breakdown(metrics, true)
+    }
+
+    fn breakdown(&self, metrics: &PopularityMetrics, adaptive_weights: bool) -> ScoreExplanation {
+        let now = get_now_f64();
         let w_request_rate = 0.25;
         let w_replication_factor = 0.20;
         let mut w_freshness = 0.15;
@@ -34,7 +75,7 @@ impl PopularityRanker {
         let mut w_seed_coverage = 0.10;
 
         if adaptive_weights {
-            let age_seconds = get_now_f64() - metrics.first_seen;
+            let age_seconds = now - metrics.first_seen;
 
             if age_seconds < 86400.0 {
                 w_freshness = 0.30;
@@ -52,16 +93,39 @@ impl PopularityRanker {
         }
 
         let norm = self.normalize_metrics(metrics);
-
-        let score = (norm.request_rate * w_request_rate
-            + norm.replication_factor * w_replication_factor
-            + norm.freshness * w_freshness
-            + norm.audience_size * w_audience_size
-            + norm.social_engagements * w_social_engagements
-            + norm.seed_coverage * w_seed_coverage)
-            * 10.0;
-
-        score.clamp(0.0, 10.0)
+        let decay = metrics.decay_factor(now, self.decay_half_life);
+
+        let factors: Vec<ScoreFactor> = [
+            ("request_rate", norm.request_rate, w_request_rate),
+            (
+                "replication_factor",
+                norm.replication_factor,
+                w_replication_factor,
+            ),
+            ("freshness", norm.freshness, w_freshness),
+            ("audience_size", norm.audience_size, w_audience_size),
+            (
+                "social_engagements",
+                norm.social_engagements,
+                w_social_engagements,
+            ),
+            ("seed_coverage", norm.seed_coverage, w_seed_coverage),
+        ]
+        .into_iter()
+        .map(|(name, value, weight)| ScoreFactor {
+            name,
+            value,
+            weight,
+            contribution: value * weight * 10.0 * decay,
+        })
+        .collect();
+
+        let score = factors.iter().map(|f| f.contribution).sum::<f64>();
+        ScoreExplanation {
+            factors,
+            decay,
+            score: score.clamp(0.0, 10.0),
+        }
     }
 
     /// Normalize metrics
@@ -79,7 +143,7 @@ impl PopularityRanker {
     /// Rank items
     pub fn rank_items(
         &self,
-        metrics_dict: &HashMap<Vec<u8>, PopularityMetrics>,
+        metrics_dict: &HashMap<Key, PopularityMetrics>,
         limit: Option<usize>,
     ) -> Vec<RankedItem> {
         let mut ranked_items: Vec<RankedItem> = metrics_dict
@@ -106,7 +170,7 @@ impl PopularityRanker {
     /// Get popular items
     pub fn get_popular_items(
         &self,
-        metrics_dict: &HashMap<Vec<u8>, PopularityMetrics>,
+        metrics_dict: &HashMap<Key, PopularityMetrics>,
         limit: usize,
     ) -> Vec<RankedItem> {
         let ranked = self.rank_items(metrics_dict, None);
@@ -122,7 +186,7 @@ impl PopularityRanker {
     /// Get active items
     pub fn get_active_items(
         &self,
-        metrics_dict: &HashMap<Vec<u8>, PopularityMetrics>,
+        metrics_dict: &HashMap<Key, PopularityMetrics>,
         limit: usize,
     ) -> Vec<RankedItem> {
         let ranked = self.rank_items(metrics_dict, None);
@@ -0,0 +1,150 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::storage::keys::Key;
+use crate::utils::time::get_now_f64;
+
+/// Seeds which were not heard from for this time are forgotten
+pub const SEED_EXPIRY_SECONDS: f64 = 86400.0;
+/// Max count of known seeds, the least recently seen one is forgotten for a new one
+pub const MAX_KNOWN_SEEDS: usize = 1024;
+/// Max count of keys with reports, reports of new keys are ignored while it is full
+pub const MAX_REPORTED_KEYS: usize = 10_000;
+
+/// Registry of known seed nodes and keys they report as popular
+///
+/// Filled during popularity exchange and used for `seed_coverage` metric.
+#[derive(Default)]
+pub struct SeedRegistry {
+    /// Seed node ID -> time of last exchange with it
+    seeds: HashMap<Vec<u8>, f64>,
+    /// Key -> seed node IDs which reported this key
+    reports: HashMap<Key, HashSet<Vec<u8>>>,
+    /// Seeds which volunteered to keep archives of inactive threads
+    archivists: HashSet<Vec<u8>>,
+}
+
+impl SeedRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember seed node or refresh its last seen time
+    pub fn register_seed(&mut self, seed_id: Vec<u8>) {
+        if self.seeds.len() >= MAX_KNOWN_SEEDS && !self.seeds.contains_key(&seed_id) {
+            let oldest = self
+                .seeds
+                .iter()
+                .min_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(oldest, _)| oldest.clone());
+            if let Some(oldest) = oldest {
+                self.forget_seed(&oldest);
+            }
+        }
+        self.seeds.insert(seed_id, get_now_f64());
+    }
+
+    /// Remember seed node from its announcement
+    ///
+    /// `archivist` is `true` if seed keeps archives of inactive threads
+    pub fn announce_seed(&mut self, seed_id: Vec<u8>, archivist: bool) {
+        if archivist {
+            self.archivists.insert(seed_id.clone());
+        } else {
+            self.archivists.remove(&seed_id);
+        }
+        self.register_seed(seed_id);
+    }
+
+    /// Known seeds which keep archives
+    pub fn archivists(&self) -> Vec<Vec<u8>> {
+        self.archivists
+            .iter()
+            .filter(|id| self.seeds.contains_key(*id))
+            .cloned()
+            .collect()
+    }
+
+    /// Remember that seed node reports the key in its top
+    pub fn record_report(&mut self, key: Key, seed_id: Vec<u8>) {
+        self.register_seed(seed_id.clone());
+        if self.reports.len() >= MAX_REPORTED_KEYS && !self.reports.contains_key(&key) {
+            return;
+        }
+        self.reports.entry(key).or_default().insert(seed_id);
+    }
+
+    /// Check that node is known seed
+    pub fn is_seed(&self, node_id: &[u8]) -> bool {
+        self.seeds.contains_key(node_id)
+    }
+
+    /// Count of known seed nodes
+    pub fn seed_count(&self) -> usize {
+        self.seeds.len()
+    }
+
+    /// Fraction of known seeds which report the key _(0.0 - 1.0)_
+    pub fn coverage(&self, key: &[u8]) -> f64 {
+        if self.seeds.is_empty() {
+            return 0.0;
+        }
+
+        let reporters = match self.reports.get(key) {
+            Some(r) => r.iter().filter(|id| self.seeds.contains_key(*id)).count(),
+            None => 0,
+        };
+
+        (reporters as f64 / self.seeds.len() as f64).min(1.0)
+    }
+
+    /// Forget seeds which we did not hear for a long time
+    pub fn cleanup_expired(&mut self) {
+        let now = get_now_f64();
+        self.seeds
+            .retain(|_, last_seen| now - *last_seen <= SEED_EXPIRY_SECONDS);
+
+        let seeds = &self.seeds;
+        self.archivists.retain(|id| seeds.contains_key(id));
+        self.reports.retain(|_, reporters| {
+            reporters.retain(|id| seeds.contains_key(id));
+            !reporters.is_empty()
+        });
+    }
+
+    fn forget_seed(&mut self, seed_id: &[u8]) {
+        self.seeds.remove(seed_id);
+        self.archivists.remove(seed_id);
+        self.reports.retain(|_, reporters| {
+            reporters.remove(seed_id);
+            !reporters.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_is_capped() {
+        let mut registry = SeedRegistry::new();
+        registry.announce_seed(b"first".to_vec(), true);
+        registry.record_report(Key::from(b"key".to_vec()), b"first".to_vec());
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        for i in 0..MAX_KNOWN_SEEDS as u32 {
+            registry.register_seed(i.to_be_bytes().to_vec());
+        }
+        assert_eq!(registry.seed_count(), MAX_KNOWN_SEEDS);
+        assert!(!registry.is_seed(b"first"));
+        assert!(registry.archivists().is_empty());
+        assert_eq!(registry.coverage(b"key"), 0.0);
+
+        let seed = 0u32.to_be_bytes().to_vec();
+        for i in 0..MAX_REPORTED_KEYS as u32 + 1 {
+            registry.record_report(Key::from(i.to_be_bytes().to_vec()), seed.clone());
+        }
+        let newest = (MAX_REPORTED_KEYS as u32).to_be_bytes();
+        assert_eq!(registry.coverage(&newest), 0.0);
+        assert!(registry.coverage(&0u32.to_be_bytes()) > 0.0);
+    }
+}
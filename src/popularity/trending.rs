@@ -0,0 +1,116 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::popularity::ranking::RankedItem;
+use crate::storage::keys::Key;
+use crate::utils::time::get_now_f64;
+
+/// Max count of score samples saved for one key
+const MAX_SAMPLES: usize = 64;
+
+/// Content with growing popularity
+#[derive(Debug, Clone)]
+pub struct TrendingItem {
+    pub key: Key,
+    /// Last known popularity score
+    pub score: f64,
+    /// Growth of the score per hour in the sliding window
+    pub velocity: f64,
+}
+
+impl TrendingItem {
+    pub fn to_dict(&self) -> serde_json::Value {
+        serde_json::json!({
+            "key": hex::encode(&self.key),
+            "score": self.score,
+            "velocity": self.velocity,
+        })
+    }
+}
+
+/// Tracker of popularity score changes
+///
+/// Save score samples of every ranking round and compute rate of change, so content
+/// which grows fast is visible before its absolute score catches up.
+pub struct TrendingTracker {
+    /// Size of the sliding window in seconds
+    window_seconds: f64,
+    /// Key -> (time, score) samples
+    history: HashMap<Key, VecDeque<(f64, f64)>>,
+}
+
+impl TrendingTracker {
+    pub fn new(window_seconds: f64) -> Self {
+        Self {
+            window_seconds: window_seconds.max(1.0),
+            history: HashMap::new(),
+        }
+    }
+
+    /// Save scores of the ranking round
+    pub fn record_scores(&mut self, ranked: &[RankedItem]) {
+        self.record_scores_at(ranked, get_now_f64());
+    }
+
+    /// Save scores of the ranking round with explicit time
+    pub fn record_scores_at(&mut self, ranked: &[RankedItem], now: f64) {
+        for item in ranked {
+            let samples = self.history.entry(item.key.clone()).or_default();
+            if samples.len() >= MAX_SAMPLES {
+                samples.pop_front();
+            }
+            samples.push_back((now, item.score));
+        }
+
+        let keep = self.window_seconds * 2.0;
+        self.history.retain(|_, samples| {
+            while let Some(&(ts, _)) = samples.front() {
+                if now - ts > keep {
+                    samples.pop_front();
+                } else {
+                    break;
+                }
+            }
+            !samples.is_empty()
+        });
+    }
+
+    /// Score growth per hour for the key
+    ///
+    /// Compare last sample with the oldest sample inside the window.
+    pub fn velocity(&self, key: &[u8]) -> Option<f64> {
+        let samples = self.history.get(key)?;
+        let &(last_ts, last_score) = samples.back()?;
+
+        let &(first_ts, first_score) = samples
+            .iter()
+            .find(|(ts, _)| last_ts - ts <= self.window_seconds)?;
+
+        let elapsed = last_ts - first_ts;
+        if elapsed <= 0.0 {
+            return None;
+        }
+
+        Some((last_score - first_score) / elapsed * 3600.0)
+    }
+
+    /// Items with the fastest score growth
+    pub fn get_trending_items(&self, limit: usize) -> Vec<TrendingItem> {
+        let mut trending: Vec<TrendingItem> = self
+            .history
+            .iter()
+            .filter_map(|(key, samples)| {
+                let velocity = self.velocity(key)?;
+                let &(_, score) = samples.back()?;
+                (velocity > 0.0).then(|| TrendingItem {
+                    key: key.clone(),
+                    score,
+                    velocity,
+                })
+            })
+            .collect();
+
+        trending.sort_by(|a, b| b.velocity.total_cmp(&a.velocity));
+        trending.truncate(limit);
+        trending
+    }
+}
@@ -0,0 +1,208 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::storage::keys::Key;
+use crate::utils::time::get_now_f64;
+
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::{NodeSigner, node_id_from_public_key_der, verify_signature};
+
+/// Max replication count which one peer can report for content
+pub const MAX_REPORTED_REPLICATION: u32 = 100;
+
+/// Max growth of replication count from one exchange round
+pub const MAX_REPLICATION_STEP: u32 = 5;
+
+/// Max popularity score in the network
+pub const MAX_REPORTED_SCORE: f64 = 10.0;
+
+/// Reports older than this time are not used for aggregation
+pub const REPORT_EXPIRY_SECONDS: f64 = 86400.0;
+
+/// Bytes of the exchanged items covered by the attestation of their sender
+///
+/// Metrics are bound to the node which reported them, so items can not be re-attributed
+/// to other peer or changed on the way without detection.
+#[cfg(feature = "signatures")]
+fn attested_bytes(node_id: &[u8], items: &[Value]) -> Vec<u8> {
+    let mut data = node_id.to_vec();
+    for item in items {
+        let key = item["key"].as_str().unwrap_or_default();
+        data.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        data.extend_from_slice(key.as_bytes());
+        let replication = item["metrics"]["replication_count"].as_u64().unwrap_or(1);
+        data.extend_from_slice(&replication.to_be_bytes());
+        let requests = item["metrics"]["request_count"].as_u64().unwrap_or(0);
+        data.extend_from_slice(&requests.to_be_bytes());
+    }
+    data
+}
+
+/// Sign items of the exchange payload of our node
+///
+/// One signature covers all items, its public key is sent with it.
+#[cfg(feature = "signatures")]
+pub fn attach_attestation(signer: &NodeSigner, payload: &mut Value) {
+    let items = payload["items"].as_array().cloned().unwrap_or_default();
+    let signature = signer.sign(&attested_bytes(signer.node_id(), &items));
+    payload["attestation"] = Value::String(hex::encode(signature));
+    payload["public_key"] = Value::String(hex::encode(signer.public_key_der()));
+}
+
+/// Items of the exchange payload are signed by the key of their sender
+#[cfg(feature = "signatures")]
+pub fn verify_attestation(sender_id: &[u8], payload: &Value) -> bool {
+    let decode = |field: &str| payload[field].as_str().and_then(|v| hex::decode(v).ok());
+    let (Some(public_key), Some(signature)) = (decode("public_key"), decode("attestation")) else {
+        return false;
+    };
+    let items = payload["items"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    node_id_from_public_key_der(&public_key)[..] == sender_id[..]
+        && verify_signature(&public_key, &attested_bytes(sender_id, items), &signature)
+}
+
+/// Node without `signatures` can not check attestations, items of peers are not trusted
+#[cfg(not(feature = "signatures"))]
+pub fn verify_attestation(_sender_id: &[u8], _payload: &Value) -> bool {
+    false
+}
+
+/// Item from peer which passed validation
+pub struct ValidatedItem {
+    pub key: Key,
+    pub replication_count: u32,
+}
+
+/// Check that received item is well-formed and in range
+///
+/// Attestation of the whole payload is checked by [`verify_attestation`].
+pub fn validate_item(item: &Value) -> Result<ValidatedItem, &'static str> {
+    let key_hex = item["key"].as_str().ok_or("Missing key")?;
+    let key = Key::from_hex(key_hex).ok_or("Invalid key")?;
+
+    let score = item["score"].as_f64().unwrap_or(0.0);
+    if !(0.0..=MAX_REPORTED_SCORE).contains(&score) {
+        return Err("Score out of range");
+    }
+
+    let replication = item["metrics"]["replication_count"].as_u64().unwrap_or(1);
+    if replication > MAX_REPORTED_REPLICATION as u64 {
+        return Err("Replication count out of range");
+    }
+
+    Ok(ValidatedItem {
+        key,
+        replication_count: replication as u32,
+    })
+}
+
+/// Vote of one peer: reported replication and time of report
+type PeerVote = (u32, f64);
+
+/// Aggregator of the metrics reported by different peers
+///
+/// Every peer has only one vote per key, and the result is the median of votes,
+/// so single peer can not inflate replication count.
+#[derive(Default)]
+pub struct PeerReportAggregator {
+    /// Key -> peer ID -> (reported replication, time of report)
+    reports: HashMap<Key, HashMap<Vec<u8>, PeerVote>>,
+}
+
+impl PeerReportAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Save the vote of the peer, replacing its previous vote
+    pub fn record(&mut self, key: Key, peer_id: Vec<u8>, replication_count: u32) {
+        self.reports
+            .entry(key)
+            .or_default()
+            .insert(peer_id, (replication_count, get_now_f64()));
+    }
+
+    /// Median replication count between fresh votes of peers
+    pub fn median_replication(&self, key: &[u8]) -> Option<u32> {
+        let now = get_now_f64();
+        let mut votes: Vec<u32> = self
+            .reports
+            .get(key)?
+            .values()
+            .filter(|(_, ts)| now - ts <= REPORT_EXPIRY_SECONDS)
+            .map(|(v, _)| *v)
+            .collect();
+
+        if votes.is_empty() {
+            return None;
+        }
+
+        votes.sort_unstable();
+        Some(votes[(votes.len() - 1) / 2])
+    }
+
+    /// Remove old votes
+    pub fn cleanup_expired(&mut self) {
+        let now = get_now_f64();
+        self.reports.retain(|_, peers| {
+            peers.retain(|_, (_, ts)| now - *ts <= REPORT_EXPIRY_SECONDS);
+            !peers.is_empty()
+        });
+    }
+}
+
+#[cfg(all(test, feature = "signatures"))]
+mod tests {
+    use super::*;
+    use crate::utils::crypto::{generate_keypair, node_id_from_public_key};
+    use serde_json::json;
+
+    fn signer() -> NodeSigner {
+        let (private_key, public_key) = generate_keypair();
+        let node_id = node_id_from_public_key(&public_key);
+        NodeSigner::new(private_key, node_id.to_vec())
+    }
+
+    fn payload() -> Value {
+        json!({
+            "items": [{
+                "key": "7d47d9ce1cc28a86cc45d2f064e9fb1116b6cbb9ced1393d972284731dfa4be0",
+                "score": 0.5,
+                "metrics": {"replication_count": 3, "request_count": 10}
+            }]
+        })
+    }
+
+    #[test]
+    fn attestation_is_bound_to_sender_key_and_items() {
+        let signer = signer();
+        let sender = signer.node_id().to_vec();
+        let mut payload = payload();
+        attach_attestation(&signer, &mut payload);
+
+        assert!(verify_attestation(&sender, &payload));
+        assert!(!verify_attestation(&[7u8; 20], &payload));
+
+        let mut changed = payload.clone();
+        changed["items"][0]["metrics"]["replication_count"] = json!(50);
+        assert!(!verify_attestation(&sender, &changed));
+
+        let mut unsigned = payload.clone();
+        unsigned["attestation"] = Value::Null;
+        assert!(!verify_attestation(&sender, &unsigned));
+    }
+
+    #[test]
+    fn attestation_of_other_key_is_rejected() {
+        let signer = signer();
+        let other = self::signer();
+        let mut payload = payload();
+        attach_attestation(&other, &mut payload);
+        payload["public_key"] = json!(hex::encode(signer.public_key_der()));
+
+        assert!(!verify_attestation(signer.node_id(), &payload));
+    }
+}
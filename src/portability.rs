@@ -0,0 +1,239 @@
+//! # Portability Module
+//!
+//! Moving of the user to other device. The archive keeps the node key with node ID, so
+//! the new device continues with the same identity, and records the node has published:
+//! threads, messages with their parts and the profile. Records are published again on
+//! import, so the content lives on even if the old device never comes back.
+//!
+//! The archive is encrypted by AES-256-GCM with the key derived from the passphrase of
+//! the user by Argon2id, the private key never leaves the device in plain form.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::utils::crypto::{
+    node_id_from_public_key, retire_file, save_node_id, write_private_file,
+};
+use crate::utils::serialization::{from_msgpack, to_msgpack};
+
+/// First bytes of the sealed archive
+const ARCHIVE_MAGIC: &[u8; 4] = b"RZEX";
+/// Version of the archive format
+const ARCHIVE_VERSION: u8 = 1;
+/// Length of random salt of the passphrase
+const SALT_LEN: usize = 16;
+/// Length of AES-GCM nonce
+const NONCE_LEN: usize = 12;
+
+/// Published record kept in the archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortableRecord {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub record_type: String,
+    /// Seconds left to live at export
+    pub ttl: i32,
+}
+
+/// Identity of the node with its published content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdentityArchive {
+    pub node_id: Vec<u8>,
+    /// Private key of the node _(PKCS#8 DER)_
+    pub private_key: Vec<u8>,
+    /// Network of the node, records are valid only in it
+    pub network_id: String,
+    pub records: Vec<PortableRecord>,
+    pub exported_at: i64,
+}
+
+/// Encryption key from the passphrase and salt
+fn derive_key(passphrase: &str, salt: &[u8]) -> Option<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .ok()?;
+    Some(key)
+}
+
+/// Encrypt the archive with the passphrase
+///
+/// Result is `magic | version | salt | nonce | ciphertext`, open it with [`open_archive`].
+pub fn seal_archive(archive: &IdentityArchive, passphrase: &str) -> Option<Vec<u8>> {
+    let salt: [u8; SALT_LEN] = rand::random();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let key = derive_key(passphrase, &salt)?;
+
+    let plain = to_msgpack(archive).ok()?;
+    let ciphertext = Aes256Gcm::new(&key.into())
+        .encrypt(Nonce::from_slice(&nonce), plain.as_slice())
+        .ok()?;
+
+    let mut sealed =
+        Vec::with_capacity(ARCHIVE_MAGIC.len() + 1 + SALT_LEN + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(ARCHIVE_MAGIC);
+    sealed.push(ARCHIVE_VERSION);
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Some(sealed)
+}
+
+/// Decrypt the archive made by [`seal_archive`]
+///
+/// Return `None` if data is broken or the passphrase is wrong.
+pub fn open_archive(sealed: &[u8], passphrase: &str) -> Option<IdentityArchive> {
+    let rest = sealed.strip_prefix(ARCHIVE_MAGIC)?;
+    let (version, rest) = rest.split_first()?;
+    if *version != ARCHIVE_VERSION || rest.len() < SALT_LEN + NONCE_LEN {
+        return None;
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(passphrase, salt)?;
+    let plain = Aes256Gcm::new(&key.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .ok()?;
+    from_msgpack(&plain).ok()
+}
+
+/// Identity files replaced by [`install_identity`]
+#[derive(Debug)]
+pub struct RetiredIdentity {
+    /// Files of the installed identity
+    installed: Vec<PathBuf>,
+    /// Original path -> path of the retired file
+    retired: Vec<(PathBuf, PathBuf)>,
+}
+
+impl RetiredIdentity {
+    /// Remove the installed identity and bring the retired files back
+    pub fn restore(&self) -> io::Result<()> {
+        for path in &self.installed {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        for (path, retired) in self.retired.iter().rev() {
+            fs::rename(retired, path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Save identity of the archive to node files
+///
+/// Private key must belong to the node ID of the archive. Current files get `.retired`
+/// suffix with the first free number, the key is written readable only by the owner.
+/// Returned value brings the current identity back if the node fails with the new one.
+pub fn install_identity(
+    archive: &IdentityArchive,
+    node_id_path: &Path,
+    node_key_path: &Path,
+) -> io::Result<RetiredIdentity> {
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+    if archive.node_id.len() != 20 {
+        return Err(invalid("invalid node ID in archive"));
+    }
+    let private_key = RsaPrivateKey::from_pkcs8_der(&archive.private_key)
+        .map_err(|_| invalid("invalid private key in archive"))?;
+    if node_id_from_public_key(&RsaPublicKey::from(&private_key))[..] != archive.node_id[..] {
+        return Err(invalid("private key of archive does not match its node ID"));
+    }
+
+    let mut identity = RetiredIdentity {
+        installed: Vec::new(),
+        retired: Vec::new(),
+    };
+    let mut install = || {
+        for path in [node_id_path, node_key_path] {
+            if let Some(retired) = retire_file(path)? {
+                identity.retired.push((path.to_path_buf(), retired));
+            }
+        }
+        identity.installed.push(node_key_path.to_path_buf());
+        write_private_file(node_key_path, &archive.private_key)?;
+        identity.installed.push(node_id_path.to_path_buf());
+        save_node_id(&archive.node_id, node_id_path)
+    };
+    match install() {
+        Ok(()) => Ok(identity),
+        Err(e) => {
+            let _ = identity.restore();
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::crypto::generate_keypair;
+    use crate::utils::temp_dir::TempDir;
+    use rsa::pkcs8::EncodePrivateKey;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    fn archive() -> IdentityArchive {
+        let (private_key, public_key) = generate_keypair();
+        IdentityArchive {
+            node_id: node_id_from_public_key(&public_key).to_vec(),
+            private_key: private_key.to_pkcs8_der().unwrap().as_bytes().to_vec(),
+            network_id: "test".to_string(),
+            records: Vec::new(),
+            exported_at: 0,
+        }
+    }
+
+    #[test]
+    fn installed_identity_keeps_every_retired_one_and_restores() {
+        let dir = TempDir::new("portability");
+        let (id_path, key_path) = (dir.join("node_id"), dir.join("node.key"));
+        fs::write(&id_path, [1u8; 20]).unwrap();
+        fs::write(&key_path, b"first").unwrap();
+
+        let archive = archive();
+        install_identity(&archive, &id_path, &key_path).unwrap();
+        let second = install_identity(&archive, &id_path, &key_path).unwrap();
+
+        assert_eq!(fs::read(dir.join("node.key.retired")).unwrap(), b"first");
+        assert_eq!(
+            fs::read(dir.join("node.key.retired.1")).unwrap(),
+            archive.private_key
+        );
+        assert_eq!(fs::read(&id_path).unwrap(), archive.node_id);
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(&key_path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        second.restore().unwrap();
+        assert_eq!(fs::read(&key_path).unwrap(), archive.private_key);
+        assert!(!dir.join("node.key.retired.1").exists());
+    }
+
+    #[test]
+    fn key_of_other_node_is_not_installed() {
+        let dir = TempDir::new("portability");
+        let (id_path, key_path) = (dir.join("node_id"), dir.join("node.key"));
+        fs::write(&key_path, b"current").unwrap();
+
+        let mut archive = archive();
+        archive.node_id = vec![7; 20];
+        let installed = install_identity(&archive, &id_path, &key_path);
+
+        assert_eq!(installed.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&key_path).unwrap(), b"current");
+        assert!(!dir.join("node.key.retired").exists());
+    }
+}
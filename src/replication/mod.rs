@@ -1,4 +1,12 @@
+/// Signed confirmations of stored replicas
+///
+/// Receipts travel in STORE answers, so this module is built without `replication` too.
+pub mod receipt;
 /// Need to transfer data in network
 ///
 /// Exchange data between nodes by using their popularity
+#[cfg(feature = "replication")]
 pub mod replicator;
+/// Detailed results of the replication
+#[cfg(feature = "replication")]
+pub mod report;
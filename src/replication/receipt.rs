@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "replication", feature = "signatures"))]
+use std::collections::HashMap;
+#[cfg(any(feature = "replication", feature = "signatures"))]
+use std::sync::Mutex;
+
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::{NodeSigner, node_id_from_public_key_der, verify_signature};
+#[cfg(feature = "signatures")]
+use crate::utils::time::get_now_f64;
+
+/// Seconds to which promised time of receipts issued by [`ReceiptCache`] is rounded down
+#[cfg(feature = "signatures")]
+const RECEIPT_ROUNDING: f64 = 300.0;
+/// Receipts kept by [`ReceiptCache`], all are dropped when it is full
+#[cfg(feature = "signatures")]
+const MAX_CACHED_RECEIPTS: usize = 4096;
+
+/// Signed confirmation that a node accepted STORE of the key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageReceipt {
+    pub key: Vec<u8>,
+    /// Size of the stored value in bytes
+    pub size: usize,
+    /// Time until which the storer promises to keep the value
+    pub expires_at: f64,
+    /// Node ID of the storer
+    pub storer_id: Vec<u8>,
+    /// DER of the storer public key, its SHA-1 must be equal to `storer_id`
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+#[cfg(feature = "signatures")]
+impl StorageReceipt {
+    /// Sign receipt for accepted value by our node
+    pub fn issue(signer: &NodeSigner, key: &[u8], size: usize, expires_at: f64) -> Self {
+        let data = Self::signed_bytes(key, size, expires_at, signer.node_id());
+        Self {
+            key: key.to_vec(),
+            size,
+            expires_at,
+            storer_id: signer.node_id().to_vec(),
+            public_key: signer.public_key_der().to_vec(),
+            signature: signer.sign(&data),
+        }
+    }
+
+    /// Bytes which are covered by the signature
+    fn signed_bytes(key: &[u8], size: usize, expires_at: f64, storer_id: &[u8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(key.len() + storer_id.len() + 16);
+        data.extend_from_slice(key);
+        data.extend_from_slice(&(size as u64).to_be_bytes());
+        data.extend_from_slice(&expires_at.to_be_bytes());
+        data.extend_from_slice(storer_id);
+        data
+    }
+
+    /// Check that receipt is for `key`, is not expired, is signed by the storer and the
+    /// storer ID belongs to the signing key
+    pub fn verify(&self, key: &[u8]) -> bool {
+        self.key == key
+            && self.expires_at > get_now_f64()
+            && node_id_from_public_key_der(&self.public_key)[..] == self.storer_id[..]
+            && verify_signature(
+                &self.public_key,
+                &Self::signed_bytes(&self.key, self.size, self.expires_at, &self.storer_id),
+                &self.signature,
+            )
+    }
+}
+
+/// Receipts issued by our node for reuse on repeated STORE of the same key
+///
+/// RSA signature is the most expensive part of STORE. Promised time is rounded down to
+/// [`RECEIPT_ROUNDING`] seconds, so republishes and retries of the key within it get the
+/// receipt which was already signed. Promise is never longer than the TTL.
+#[cfg(feature = "signatures")]
+#[derive(Default)]
+pub struct ReceiptCache {
+    receipts: Mutex<HashMap<Vec<u8>, StorageReceipt>>,
+}
+
+#[cfg(feature = "signatures")]
+impl ReceiptCache {
+    /// Receipt for the value kept until `expires_at`, signed only if none is cached
+    pub fn issue(
+        &self,
+        signer: &NodeSigner,
+        key: &[u8],
+        size: usize,
+        expires_at: f64,
+        now: f64,
+    ) -> StorageReceipt {
+        let rounded = (expires_at / RECEIPT_ROUNDING).floor() * RECEIPT_ROUNDING;
+        // Short TTL can not be rounded without an expired promise
+        if rounded <= now {
+            return StorageReceipt::issue(signer, key, size, expires_at);
+        }
+        if let Some(receipt) = self.receipts.lock().unwrap().get(key)
+            && receipt.size == size
+            && receipt.expires_at == rounded
+            && receipt.storer_id == signer.node_id()
+        {
+            return receipt.clone();
+        }
+
+        let receipt = StorageReceipt::issue(signer, key, size, rounded);
+        let mut receipts = self.receipts.lock().unwrap();
+        if receipts.len() >= MAX_CACHED_RECEIPTS {
+            receipts.clear();
+        }
+        receipts.insert(key.to_vec(), receipt.clone());
+        receipt
+    }
+}
+
+/// Receipts of one key by storer ID
+#[cfg(feature = "replication")]
+type ReceiptsByStorer = HashMap<Vec<u8>, StorageReceipt>;
+
+/// Verified receipts of replicas by key
+///
+/// Count of unexpired receipts is the proven replication factor of the key.
+#[cfg(feature = "replication")]
+#[derive(Default)]
+pub struct ReceiptBook {
+    receipts: Mutex<HashMap<Vec<u8>, ReceiptsByStorer>>,
+}
+
+#[cfg(feature = "replication")]
+impl ReceiptBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Save receipt if it is valid for the key
+    ///
+    /// Return `true` if receipt was accepted
+    pub fn record(&self, key: &[u8], receipt: StorageReceipt) -> bool {
+        if !receipt.verify(key) {
+            return false;
+        }
+        self.receipts
+            .lock()
+            .unwrap()
+            .entry(key.to_vec())
+            .or_default()
+            .insert(receipt.storer_id.clone(), receipt);
+        true
+    }
+
+    /// Count of nodes with unexpired receipts for the key
+    pub fn replication_count(&self, key: &[u8]) -> usize {
+        let now = get_now_f64();
+        self.receipts
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|by_storer| by_storer.values().filter(|r| r.expires_at > now).count())
+            .unwrap_or(0)
+    }
+
+    /// Forget expired receipts
+    pub fn cleanup_expired(&self) -> usize {
+        let now = get_now_f64();
+        let mut receipts = self.receipts.lock().unwrap();
+        let mut removed = 0;
+        receipts.retain(|_, by_storer| {
+            let before = by_storer.len();
+            by_storer.retain(|_, r| r.expires_at > now);
+            removed += before - by_storer.len();
+            !by_storer.is_empty()
+        });
+        removed
+    }
+}
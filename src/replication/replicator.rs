@@ -1,10 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tracing::{debug, error, info, warn};
 
+use crate::dht::key_presence::KeyPresenceIndex;
+use crate::dht::node::Node;
 use crate::dht::protocol::DHTProtocol;
+use crate::events::{EventBus, NodeEvent};
+use crate::exceptions::RhizomeError;
+use crate::node::mode::ModeSwitch;
+use crate::popularity::metrics::MetricsCollector;
 use crate::popularity::ranking::RankedItem;
-use crate::storage::main::Storage;
+use crate::replication::receipt::{ReceiptBook, StorageReceipt};
+use crate::replication::report::{KeyReplicationReport, ReplicationReport};
+use crate::security::audit::{AuditEventKind, AuditLog};
+use crate::security::reputation::ReputationTable;
+use crate::storage::bundle::{BundledRecord, RecordHead, ReplicatedThread};
+use crate::storage::disk::DiskWatchdog;
+use crate::storage::keys::{Key, KeyManager, RECORD_MESSAGE, RECORD_THREAD, RECORD_THREAD_BUNDLE};
+use crate::storage::main::{Storage, StoreOrigin};
+
+/// Count of the newest local messages searched for messages of bundled threads
+const MAX_BUNDLE_SCAN: usize = 4096;
+
+/// Max size of bundle records, without compression the bundle is stored in one datagram
+#[cfg(feature = "compression")]
+const MAX_BUNDLE_SIZE: usize = crate::storage::bundle::MAX_THREAD_BUNDLE_SIZE;
+#[cfg(not(feature = "compression"))]
+const MAX_BUNDLE_SIZE: usize = crate::network::fragments::FRAGMENTED_STORE_THRESHOLD;
 
 /// Duplicate data to the other node
 pub struct Replicator {
@@ -16,6 +38,36 @@ pub struct Replicator {
     min_replication_factor: usize,
     /// How many replications should be if data very popular
     popular_replication_factor: usize,
+    /// Channel for replication progress events
+    events: Option<EventBus>,
+    /// Bloom filters of peers for estimating count of replicas
+    pub key_presence: Option<Arc<KeyPresenceIndex>>,
+    /// Verified receipts of peers which accepted our replicas
+    pub receipts: Arc<ReceiptBook>,
+    /// Collector which gets proven replication count of keys
+    pub metrics_collector: Option<Arc<MetricsCollector>>,
+    /// Log of invalid receipts
+    pub audit: Option<Arc<AuditLog>>,
+    /// Free disk space watcher, replication is paused in degraded mode
+    pub disk_watchdog: Option<Arc<DiskWatchdog>>,
+    /// Mode of the node, replication is paused in read-only mode
+    pub mode: Arc<ModeSwitch>,
+    /// Scores of peers, replicas go to reputable peers first
+    pub reputation: Option<Arc<ReputationTable>>,
+    /// Keys of the network, popular threads are replicated as bundles when set
+    pub thread_bundles: Option<KeyManager>,
+    /// Max count of recent messages in a thread bundle
+    pub bundle_messages: usize,
+    /// zstd level of thread bundles
+    pub bundle_compression_level: i32,
+}
+
+/// Packed bundle of popular thread ready for replication
+struct PreparedBundle {
+    key: Key,
+    value: Vec<u8>,
+    /// Keys of messages in the bundle, they are not replicated one by one
+    messages: Vec<Key>,
 }
 
 impl Replicator {
@@ -24,24 +76,59 @@ impl Replicator {
         storage: Arc<Storage>,
         min_replication_factor: usize,
         popular_replication_factor: usize,
+        events: Option<EventBus>,
     ) -> Self {
         Self {
             dht_protocol,
             storage,
             min_replication_factor,
             popular_replication_factor,
+            events,
+            key_presence: None,
+            receipts: Arc::new(ReceiptBook::new()),
+            metrics_collector: None,
+            audit: None,
+            disk_watchdog: None,
+            mode: Arc::new(ModeSwitch::default()),
+            reputation: None,
+            thread_bundles: None,
+            bundle_messages: 0,
+            bundle_compression_level: 0,
+        }
+    }
+
+    /// Reason why replication is paused _(None if it may run)_
+    ///
+    /// Replication writes values to local storage again, so it waits for free disk space
+    /// and for the end of read-only mode.
+    fn paused_reason(&self) -> Option<&'static str> {
+        if self.mode.is_read_only() {
+            Some("node is read-only")
+        } else if self
+            .disk_watchdog
+            .as_ref()
+            .is_some_and(|disk| disk.is_degraded())
+        {
+            Some("disk space is low")
+        } else {
+            None
         }
     }
 
     /// Replication of popular elements
     ///
-    /// Work smth like CDN network
+    /// Work smth like CDN network. Progress of every key is sent to the events channel.
     pub async fn replicate_popular_items(
         &self,
         ranked_items: Vec<RankedItem>,
         popularity_threshold: f64,
-    ) -> HashMap<Vec<u8>, bool> {
-        let mut results = HashMap::new();
+    ) -> ReplicationReport {
+        let mut report = ReplicationReport::new();
+        if let Some(reason) = self.paused_reason() {
+            warn!(reason = reason, "Replication paused");
+            report.finished_at = self.storage.now();
+            return report;
+        }
 
         let popular_items: Vec<&RankedItem> = ranked_items
             .iter()
@@ -54,63 +141,325 @@ impl Replicator {
             "Starting replication"
         );
 
+        let bundles = self.make_thread_bundles(&popular_items).await;
+        let bundled_messages: HashSet<&Key> = bundles
+            .values()
+            .flat_map(|bundle| &bundle.messages)
+            .collect();
+
         for item in popular_items {
             let key = &item.key;
-            let key_hex = hex::encode(&key[..key.len().min(8)]);
-
-            let value_result = self.storage.get(key.clone()).await;
+            let key_hex = key.short_hex();
+            let mut key_report = KeyReplicationReport::new(key.clone());
 
-            match value_result {
-                Ok(Some(value)) => {
-                    let current_replication = item.metrics.replication_count as usize;
-                    let target_replication = self.popular_replication_factor;
+            if let Some(bundle) = bundles.get(key) {
+                self.replicate_bundle(bundle, &mut key_report).await;
+            } else if bundled_messages.contains(key) {
+                key_report.bundled = true;
+                key_report.skipped = true;
+            } else {
+                match self.storage.get_with_meta(key.clone()).await {
+                    Ok(Some(stored)) => {
+                        let value = stored.value;
+                        let current_replication = (item.metrics.replication_count as usize)
+                            .max(self.estimate_coverage(key))
+                            .max(self.receipts.replication_count(key));
+                        let target_replication = self.popular_replication_factor;
 
-                    if current_replication >= target_replication {
-                        results.insert(key.clone(), true);
-                        continue;
+                        if current_replication >= target_replication {
+                            key_report.skipped = true;
+                        } else {
+                            let ttl = 2592000;
+                            key_report.attempts += 1;
+                            match self
+                                .store_latency_diverse(key, &value, ttl, &stored.origin)
+                                .await
+                            {
+                                Ok((contacted, succeeded)) => {
+                                    key_report.peers_contacted = contacted;
+                                    key_report.peers_succeeded = succeeded;
+                                    key_report.bytes_transferred = value.len() * contacted;
+                                    if contacted > 0 && succeeded == 0 {
+                                        key_report.failure =
+                                            Some("No peer accepted STORE".to_string());
+                                        warn!(key = %key_hex, "Replication failed");
+                                    } else {
+                                        debug!(
+                                            key = %key_hex,
+                                            score = item.score,
+                                            target_replication = target_replication,
+                                            "Replicated popular item"
+                                        );
+                                    }
+                                }
+                                Err(e) => {
+                                    error!(key = %key_hex, error = %e, "Error during STORE in replication");
+                                    key_report.failure = Some(format!("STORE error: {}", e));
+                                }
+                            }
+                        }
                     }
-
-                    let ttl = 2592000;
-                    match self.dht_protocol.store(key, &value, ttl).await {
-                        Ok(success) => {
-                            results.insert(key.clone(), success);
-                            if success {
-                                debug!(
-                                    key = %key_hex,
-                                    score = item.score,
-                                    target_replication = target_replication,
-                                    "Replicated popular item"
-                                );
-                            } else {
-                                warn!(key = %key_hex, "Replication failed");
+                    Ok(None) => match self.storage.get_tombstone(key.clone()).await {
+                        Ok(Some(tombstone)) => {
+                            key_report.tombstone = true;
+                            key_report.attempts += 1;
+                            match self
+                                .dht_protocol
+                                .spread_tombstone(
+                                    key,
+                                    tombstone.ttl(self.storage.now()),
+                                    &tombstone.origin,
+                                )
+                                .await
+                            {
+                                Ok(result) => {
+                                    key_report.peers_contacted = result.attempted;
+                                    key_report.peers_succeeded = result.succeeded;
+                                    debug!(key = %key_hex, "Replicated tombstone of deleted item");
+                                }
+                                Err(e) => {
+                                    key_report.failure = Some(format!("DELETE error: {}", e));
+                                }
                             }
                         }
-                        Err(e) => {
-                            error!(key = %key_hex, error = %e, "Error during STORE in replication");
-                            results.insert(key.clone(), false);
+                        _ => {
+                            warn!(key = %key_hex, "Value not found for replication");
+                            key_report.failure =
+                                Some("Value not found in local storage".to_string());
                         }
+                    },
+                    Err(e) => {
+                        error!(key = %key_hex, error = %e, "Error accessing storage for replication");
+                        key_report.failure = Some(format!("Storage error: {}", e));
                     }
                 }
-                Ok(None) => {
-                    warn!(key = %key_hex, "Value not found for replication");
-                    results.insert(key.clone(), false);
-                }
-                Err(e) => {
-                    error!(key = %key_hex, error = %e, "Error accessing storage for replication");
-                    results.insert(key.clone(), false);
-                }
             }
+
+            if let Some(events) = &self.events {
+                events.emit(NodeEvent::ReplicationProgress(key_report.clone()));
+            }
+            report.items.push(key_report);
         }
 
-        let successful = results.values().filter(|&&v| v).count();
+        report.finished_at = self.storage.now();
+        self.receipts.cleanup_expired();
+
         info!(
-            total = results.len(),
-            successful = successful,
-            failed = results.len() - successful,
+            total = report.items.len(),
+            successful = report.successful(),
+            failed = report.failed(),
+            bytes = report.bytes_transferred(),
             "Replication completed"
         );
 
-        results
+        if let Some(events) = &self.events {
+            events.emit(NodeEvent::ReplicationCompleted(report.clone()));
+        }
+
+        report
+    }
+
+    /// Bundles of popular threads by keys of their metadata
+    ///
+    /// Messages are found among [`MAX_BUNDLE_SCAN`] newest local messages, so only
+    /// messages stored on our node get into the bundle. Records without proof of their
+    /// publisher are left out, readers would not trust them.
+    async fn make_thread_bundles(&self, items: &[&RankedItem]) -> HashMap<Key, PreparedBundle> {
+        let mut bundles = HashMap::new();
+        let Some(keys) = &self.thread_bundles else {
+            return bundles;
+        };
+
+        let mut threads = Vec::new();
+        for item in items {
+            if let Ok(Some(stored)) = self.storage.get_with_meta(item.key.clone()).await
+                && stored.origin.record_type == RECORD_THREAD
+                && let Some(proof) = stored.origin.proof
+                && let Some(head) = RecordHead::read(&stored.value)
+            {
+                let meta = BundledRecord {
+                    value: stored.value,
+                    proof,
+                };
+                threads.push((item.key.clone(), head.first, meta));
+            }
+        }
+        if threads.is_empty() {
+            return bundles;
+        }
+
+        let records = match self
+            .storage
+            .records_by_type(RECORD_MESSAGE.to_string(), 0.0, MAX_BUNDLE_SCAN)
+            .await
+        {
+            Ok(records) => records,
+            Err(e) => {
+                warn!(error = %e, "Messages for thread bundles were not read");
+                return bundles;
+            }
+        };
+        let mut messages: HashMap<String, Vec<(f64, Key, BundledRecord)>> = HashMap::new();
+        for record in records {
+            if let Some(thread_id) = RecordHead::read(&record.value).and_then(|head| head.second)
+                && threads.iter().any(|(_, id, _)| *id == thread_id)
+                && let Some(proof) = record.proof
+            {
+                let message = BundledRecord {
+                    value: record.value,
+                    proof,
+                };
+                messages.entry(thread_id).or_default().push((
+                    record.published_at,
+                    record.key,
+                    message,
+                ));
+            }
+        }
+
+        let now = self.storage.now();
+        for (meta_key, thread_id, meta) in threads {
+            let mut thread_messages = messages.remove(&thread_id).unwrap_or_default();
+            thread_messages.sort_by(|a, b| b.0.total_cmp(&a.0));
+            thread_messages.truncate(self.bundle_messages);
+            let (message_keys, values): (Vec<Key>, Vec<BundledRecord>) = thread_messages
+                .into_iter()
+                .map(|(_, key, message)| (key, message))
+                .unzip();
+            let bundle =
+                ReplicatedThread::new(thread_id.clone(), meta, values, now, MAX_BUNDLE_SIZE);
+            if let Some(value) = bundle.pack(self.bundle_compression_level) {
+                bundles.insert(
+                    meta_key,
+                    PreparedBundle {
+                        key: keys.get_thread_bundle_key(&thread_id),
+                        value,
+                        messages: message_keys[..bundle.messages.len()].to_vec(),
+                    },
+                );
+            }
+        }
+        bundles
+    }
+
+    /// Replicate bundle of the thread instead of its metadata
+    async fn replicate_bundle(&self, bundle: &PreparedBundle, report: &mut KeyReplicationReport) {
+        let ttl = 2592000;
+        let mut origin = StoreOrigin::new(
+            self.dht_protocol
+                .routing_table
+                .read()
+                .await
+                .node_id
+                .0
+                .to_vec(),
+            self.storage.now(),
+        )
+        .with_record_type(RECORD_THREAD_BUNDLE);
+        if let Some(net) = &self.dht_protocol.network_protocol {
+            net.sign_origin(&bundle.key, Some(&bundle.value), &mut origin);
+        }
+        report.bundled = true;
+        report.attempts += 1;
+        match self
+            .store_latency_diverse(&bundle.key, &bundle.value, ttl, &origin)
+            .await
+        {
+            Ok((contacted, succeeded)) => {
+                report.peers_contacted = contacted;
+                report.peers_succeeded = succeeded;
+                report.bytes_transferred = bundle.value.len() * contacted;
+                if contacted > 0 && succeeded == 0 {
+                    report.failure = Some("No peer accepted thread bundle".to_string());
+                } else {
+                    debug!(
+                        key = %bundle.key.short_hex(),
+                        messages = bundle.messages.len(),
+                        size = bundle.value.len(),
+                        "Replicated thread bundle"
+                    );
+                }
+            }
+            Err(e) => {
+                error!(key = %bundle.key.short_hex(), error = %e, "Error during STORE of thread bundle");
+                report.failure = Some(format!("STORE error: {}", e));
+            }
+        }
+    }
+
+    /// Count of peers which likely store the key by their Bloom filters _(0 if unknown)_
+    pub fn estimate_coverage(&self, key: &[u8]) -> usize {
+        self.key_presence
+            .as_ref()
+            .map(|index| index.estimate_coverage(key))
+            .unwrap_or(0)
+    }
+
+    /// Store item on latency-diverse subset of k-closest nodes
+    ///
+    /// Popular content is read from all over the world, so replicas on near and far
+    /// nodes give better read latency for everyone than only the nearest ones.
+    ///
+    /// Return count of contacted peers and count of peers which accepted the data
+    async fn store_latency_diverse(
+        &self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        origin: &StoreOrigin,
+    ) -> Result<(usize, usize), RhizomeError> {
+        let mut closest = self.dht_protocol.find_closest_to_key(key).await?;
+        if let Some(reputation) = &self.reputation {
+            closest = reputation.prefer(closest, self.popular_replication_factor);
+        }
+        let targets = select_latency_diverse(closest, self.popular_replication_factor);
+        let (stored, receipts) = self
+            .dht_protocol
+            .store_to_nodes_with_receipts(key, value, ttl, origin, &targets)
+            .await?;
+        self.record_receipts(key, receipts);
+        Ok((targets.len(), stored))
+    }
+
+    /// Save valid receipts and report proven replication count to metrics
+    ///
+    /// Receipt comes with the node which sent it and must be issued by this node. Our own
+    /// copy is counted too. Return count of accepted receipts.
+    pub fn record_receipts(&self, key: &Key, receipts: Vec<(Node, StorageReceipt)>) -> usize {
+        let total = receipts.len();
+        let mut accepted = 0;
+        for (node, receipt) in receipts {
+            let storer_id = receipt.storer_id.clone();
+            if storer_id[..] == node.node_id.0[..] && self.receipts.record(key, receipt) {
+                accepted += 1;
+                continue;
+            }
+            if let (Some(reputation), Ok(address)) = (&self.reputation, node.address.parse()) {
+                reputation.record_validation_failure(address);
+            }
+            if let Some(audit) = &self.audit {
+                audit.record(
+                    AuditEventKind::SignatureFailure,
+                    Some(&storer_id),
+                    None,
+                    format!("invalid storage receipt for key {}", key),
+                );
+            }
+        }
+
+        if accepted < total {
+            warn!(
+                key = %key.short_hex(),
+                rejected = total - accepted,
+                "Invalid storage receipts were rejected"
+            );
+        }
+
+        if let Some(collector) = &self.metrics_collector {
+            let proven = self.receipts.replication_count(key) + 1;
+            collector.record_store(key.clone(), proven as u32);
+        }
+        accepted
     }
 
     /// Replication for basic data
@@ -118,23 +467,40 @@ impl Replicator {
     /// Algo only send this data once to every node in network for their minimal life
     pub async fn ensure_minimal_replication(
         &self,
-        keys: Vec<Vec<u8>>,
+        keys: Vec<Key>,
         min_factor: Option<usize>,
-    ) -> HashMap<Vec<u8>, bool> {
-        let _target_factor = min_factor.unwrap_or(self.min_replication_factor);
+    ) -> HashMap<Key, bool> {
+        let target_factor = min_factor.unwrap_or(self.min_replication_factor);
         let mut results = HashMap::new();
+        if let Some(reason) = self.paused_reason() {
+            warn!(reason = reason, "Minimal replication paused");
+            return keys.into_iter().map(|key| (key, false)).collect();
+        }
 
         for key in keys {
-            let _key_hex = hex::encode(&key[..key.len().min(8)]);
-
             match self.storage.get(key.clone()).await {
                 Ok(Some(value)) => {
                     match self.dht_protocol.store(&key, &value, 86400).await {
-                        Ok(success) => results.insert(key, success),
+                        Ok(stored) => results.insert(key, stored.meets(target_factor)),
                         Err(_) => results.insert(key, false),
                     };
                 }
-                _ => {
+                Ok(None) => {
+                    let spread = match self.storage.get_tombstone(key.clone()).await {
+                        Ok(Some(tombstone)) => self
+                            .dht_protocol
+                            .spread_tombstone(
+                                &key,
+                                tombstone.ttl(self.storage.now()),
+                                &tombstone.origin,
+                            )
+                            .await
+                            .is_ok_and(|result| result.meets(target_factor)),
+                        _ => false,
+                    };
+                    results.insert(key, spread);
+                }
+                Err(_) => {
                     results.insert(key, false);
                 }
             }
@@ -146,18 +512,27 @@ impl Replicator {
     /// Panic replication
     ///
     /// If node leave us bad data should be sent for do not die
-    pub async fn emergency_replication(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
-        let key_hex = hex::encode(&key[..key.len().min(8)]);
+    pub async fn emergency_replication(&self, key: Key, value: Vec<u8>) -> bool {
+        let key_hex = key.short_hex();
         warn!(key = %key_hex, "Emergency replication triggered");
 
         let ttl = 2592000;
         match self.dht_protocol.store(&key, &value, ttl).await {
-            Ok(true) => {
-                info!(key = %key_hex, "Emergency replication successful");
+            Ok(stored) if stored.is_stored() => {
+                info!(
+                    key = %key_hex,
+                    succeeded = stored.succeeded,
+                    attempted = stored.attempted,
+                    "Emergency replication successful"
+                );
                 true
             }
-            Ok(false) => {
-                error!(key = %key_hex, "Emergency replication failed (store returned false)");
+            Ok(stored) => {
+                error!(
+                    key = %key_hex,
+                    attempted = stored.attempted,
+                    "Emergency replication failed (no node accepted the value)"
+                );
                 false
             }
             Err(e) => {
@@ -167,3 +542,32 @@ impl Replicator {
         }
     }
 }
+
+/// Choose `count` nodes spread over the whole range of measured RTT
+///
+/// Nodes are sorted by RTT (unknown RTT goes last) and picked on even steps, so the
+/// result contains both the fastest and the slowest nodes.
+pub fn select_latency_diverse(mut nodes: Vec<Node>, count: usize) -> Vec<Node> {
+    if nodes.len() <= count {
+        return nodes;
+    }
+    if count == 0 {
+        return Vec::new();
+    }
+
+    nodes.sort_by(|a, b| match (a.rtt, b.rtt) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+
+    if count == 1 {
+        return vec![nodes.swap_remove(0)];
+    }
+
+    let last = nodes.len() - 1;
+    (0..count)
+        .map(|i| nodes[i * last / (count - 1)].clone())
+        .collect()
+}
@@ -0,0 +1,83 @@
+use crate::storage::keys::Key;
+use crate::utils::time::get_now_f64;
+
+/// Result of replication of one key
+#[derive(Debug, Clone)]
+pub struct KeyReplicationReport {
+    pub key: Key,
+    /// Count of STORE attempts
+    pub attempts: u32,
+    /// Count of peers which got STORE request
+    pub peers_contacted: usize,
+    /// Count of peers which accepted the data
+    pub peers_succeeded: usize,
+    /// Count of bytes sent to peers
+    pub bytes_transferred: usize,
+    /// Replication was not needed
+    pub skipped: bool,
+    /// Value was deleted, its tombstone was replicated instead
+    pub tombstone: bool,
+    /// Value was replicated inside a thread bundle
+    pub bundled: bool,
+    /// Reason of the failure
+    pub failure: Option<String>,
+}
+
+impl KeyReplicationReport {
+    pub fn new(key: Key) -> Self {
+        Self {
+            key,
+            attempts: 0,
+            peers_contacted: 0,
+            peers_succeeded: 0,
+            bytes_transferred: 0,
+            skipped: false,
+            tombstone: false,
+            bundled: false,
+            failure: None,
+        }
+    }
+
+    /// Key is replicated or replication was not needed
+    pub fn is_success(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Result of replication round
+#[derive(Debug, Clone, Default)]
+pub struct ReplicationReport {
+    pub items: Vec<KeyReplicationReport>,
+    pub started_at: f64,
+    pub finished_at: f64,
+}
+
+impl ReplicationReport {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            started_at: get_now_f64(),
+            finished_at: 0.0,
+        }
+    }
+
+    /// Count of keys which are replicated
+    pub fn successful(&self) -> usize {
+        self.items.iter().filter(|i| i.is_success()).count()
+    }
+
+    /// Count of keys which are not replicated
+    pub fn failed(&self) -> usize {
+        self.items.len() - self.successful()
+    }
+
+    /// Total count of bytes sent to peers
+    pub fn bytes_transferred(&self) -> usize {
+        self.items.iter().map(|i| i.bytes_transferred).sum()
+    }
+
+    /// Report of the key
+    pub fn get(&self, key: &[u8]) -> Option<&KeyReplicationReport> {
+        self.items.iter().find(|i| i.key.as_bytes() == key)
+    }
+}
@@ -0,0 +1,228 @@
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::AdminTokenConfig;
+use crate::exceptions::SecurityError;
+use crate::security::audit::{AuditEventKind, AuditLog};
+use crate::utils::crypto::write_private_file;
+
+/// Length of a generated token in bytes, it is written as hex
+const GENERATED_TOKEN_LEN: usize = 32;
+
+/// Access level of a token of the admin API
+///
+/// Roles are ordered, a token of a higher role passes checks of lower ones.
+#[derive(
+    uniffi::Enum,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminRole {
+    /// Status, statistics, peer and audit queries
+    #[default]
+    ReadOnly,
+    /// Everything, including blacklist changes, identity rotation and export
+    Admin,
+}
+
+/// Tokens of the admin API with their roles
+///
+/// Node does not serve the admin API itself, embedding applications which expose it over
+/// HTTP or gRPC check tokens of requests here. Only SHA-256 of tokens is kept and it is
+/// compared in constant time. Without configured tokens one admin token is generated on the
+/// first start and kept in the token file, so the API is never open without a token.
+pub struct AdminTokens {
+    /// SHA-256 of the token -> its role
+    tokens: Vec<([u8; 32], AdminRole)>,
+    /// Log of rejected tokens
+    pub audit: Option<Arc<AuditLog>>,
+}
+
+impl AdminTokens {
+    pub fn new(tokens: &[AdminTokenConfig], audit: Option<Arc<AuditLog>>) -> Self {
+        Self {
+            tokens: tokens
+                .iter()
+                .filter(|t| !t.token.is_empty())
+                .map(|t| (Self::digest(&t.token), t.role))
+                .collect(),
+            audit,
+        }
+    }
+
+    /// Generate a random admin token, return the tokens with it and the token itself
+    pub fn generate(audit: Option<Arc<AuditLog>>) -> (Self, String) {
+        let mut bytes = [0u8; GENERATED_TOKEN_LEN];
+        // Not the node generator, its seed makes values predictable
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+        (Self::admin(&token, audit), token)
+    }
+
+    /// Admin token generated on an earlier start from `path`
+    ///
+    /// None if the file is missing or does not hold a generated token, then a new one is
+    /// generated and written.
+    pub fn load(path: &Path, audit: Option<Arc<AuditLog>>) -> Option<Self> {
+        let token = std::fs::read_to_string(path).ok()?;
+        let token = token.trim();
+        let generated =
+            token.len() == GENERATED_TOKEN_LEN * 2 && token.bytes().all(|b| b.is_ascii_hexdigit());
+        generated.then(|| Self::admin(token, audit))
+    }
+
+    /// Write the token to `path` readable only by the owner, see [`write_private_file`]
+    pub fn write_token(path: &Path, token: &str) -> io::Result<()> {
+        write_private_file(path, token.as_bytes())
+    }
+
+    fn admin(token: &str, audit: Option<Arc<AuditLog>>) -> Self {
+        Self {
+            tokens: vec![(Self::digest(token), AdminRole::Admin)],
+            audit,
+        }
+    }
+
+    /// Role of the token _(None if the token is unknown)_
+    pub fn role(&self, token: &str) -> Option<AdminRole> {
+        let digest = Self::digest(token);
+        // All tokens are compared, so time does not tell which of them matched
+        let mut role = None;
+        for (known, known_role) in &self.tokens {
+            let diff = known
+                .iter()
+                .zip(digest.iter())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+            if diff == 0 {
+                role = role.max(Some(*known_role));
+            }
+        }
+        role
+    }
+
+    /// Check that the token has at least the `required` role, rejections are audited
+    pub fn authorize(&self, token: &str, required: AdminRole) -> Result<AdminRole, SecurityError> {
+        match self.role(token) {
+            Some(role) if role >= required => Ok(role),
+            role => {
+                if let Some(audit) = &self.audit {
+                    let details = match role {
+                        Some(role) => format!("token of role {:?} used for {:?}", role, required),
+                        None => format!("unknown token used for {:?}", required),
+                    };
+                    audit.record(AuditEventKind::AccessDenied, None, None, details);
+                }
+                Err(SecurityError::AccessDenied)
+            }
+        }
+    }
+
+    fn digest(token: &str) -> [u8; 32] {
+        Sha256::digest(token.as_bytes()).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir::TempDir;
+    use std::fs;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    fn token(token: &str, role: AdminRole) -> AdminTokenConfig {
+        AdminTokenConfig {
+            token: token.to_string(),
+            role,
+        }
+    }
+
+    #[test]
+    fn roles_of_tokens_are_checked() {
+        let tokens = AdminTokens::new(
+            &[
+                token("reader", AdminRole::ReadOnly),
+                token("admin", AdminRole::Admin),
+                token("", AdminRole::Admin),
+            ],
+            None,
+        );
+
+        assert_eq!(tokens.role("reader"), Some(AdminRole::ReadOnly));
+        assert_eq!(tokens.role("admin"), Some(AdminRole::Admin));
+        assert_eq!(tokens.role(""), None);
+        assert_eq!(tokens.role("unknown"), None);
+
+        assert!(tokens.authorize("reader", AdminRole::ReadOnly).is_ok());
+        assert!(matches!(
+            tokens.authorize("reader", AdminRole::Admin),
+            Err(SecurityError::AccessDenied)
+        ));
+        assert_eq!(
+            tokens.authorize("admin", AdminRole::ReadOnly).unwrap(),
+            AdminRole::Admin
+        );
+        assert!(tokens.authorize("unknown", AdminRole::ReadOnly).is_err());
+    }
+
+    #[test]
+    fn same_token_gets_its_highest_role() {
+        let tokens = AdminTokens::new(
+            &[
+                token("shared", AdminRole::ReadOnly),
+                token("shared", AdminRole::Admin),
+            ],
+            None,
+        );
+        assert_eq!(tokens.role("shared"), Some(AdminRole::Admin));
+    }
+
+    #[test]
+    fn generated_token_is_written_for_owner_only() {
+        let dir = TempDir::new("admin-token");
+        let path = dir.join("admin.token");
+        fs::write(&path, "old").unwrap();
+        #[cfg(unix)]
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let (tokens, token) = AdminTokens::generate(None);
+        AdminTokens::write_token(&path, &token).unwrap();
+
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(written, token);
+        assert_eq!(tokens.role(&written), Some(AdminRole::Admin));
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+    }
+
+    #[test]
+    fn generated_token_is_loaded_on_next_start() {
+        let dir = TempDir::new("admin-token-load");
+        let path = dir.join("admin.token");
+        assert!(AdminTokens::load(&path, None).is_none());
+
+        let (_, token) = AdminTokens::generate(None);
+        AdminTokens::write_token(&path, &token).unwrap();
+        let loaded = AdminTokens::load(&path, None).unwrap();
+        assert_eq!(loaded.role(&token), Some(AdminRole::Admin));
+
+        fs::write(&path, "short").unwrap();
+        assert!(AdminTokens::load(&path, None).is_none());
+    }
+}
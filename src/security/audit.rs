@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::utils::time::get_now_f64;
+
+/// Same event from the same address is written only once during this time in seconds
+pub const AUDIT_SUPPRESS_SECONDS: f64 = 10.0;
+/// Records waiting for the writer, newer ones are dropped while it is behind
+const MAX_QUEUED_RECORDS: usize = 10_000;
+
+/// Kind of the security event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    /// Signature of receipt, bundle or other signed data is invalid
+    SignatureFailure,
+    /// Peer exceeded rate limit
+    RateLimitExceeded,
+    /// Peer solved handshake puzzle wrong
+    PuzzleFailed,
+    /// Peer was added to the blacklist
+    BlacklistAdded,
+    /// Peer was removed from the blacklist
+    BlacklistRemoved,
+    /// STORE request was not applied
+    StoreRejected,
+    /// Message timestamp is too far in the past or future
+    TimestampRejected,
+    /// Peer belongs to other network
+    NetworkMismatch,
+    /// Token of the admin API was rejected
+    AccessDenied,
+}
+
+impl AuditEventKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: f64,
+    pub kind: AuditEventKind,
+    /// Hex of the peer node ID
+    pub peer: Option<String>,
+    /// Network address of the peer
+    pub address: Option<String>,
+    pub details: String,
+}
+
+/// Records which are not written yet
+#[derive(Default)]
+struct AuditQueue {
+    records: Vec<AuditRecord>,
+    /// (kind, source) -> time of the last queued record
+    last_written: HashMap<(AuditEventKind, Option<String>), f64>,
+    /// Writer task is already started
+    scheduled: bool,
+}
+
+/// Files of the log, only the writer and queries touch them
+struct AuditFiles {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: Option<File>,
+    size: u64,
+}
+
+/// Append-only local log of security events
+///
+/// Records are JSON lines. When file is bigger than `max_size` it is rotated to `<file>.1`,
+/// older files are moved to `<file>.2` and so on, up to `max_files` files in total.
+/// Events come from the network path, so they are only queued there and written by a
+/// blocking task of the runtime _(in place without a runtime)_.
+pub struct AuditLog {
+    queue: Arc<Mutex<AuditQueue>>,
+    files: Arc<Mutex<AuditFiles>>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf, max_size: u64, max_files: usize) -> Self {
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        Self {
+            queue: Arc::new(Mutex::new(AuditQueue::default())),
+            files: Arc::new(Mutex::new(AuditFiles {
+                path,
+                max_size,
+                max_files: max_files.max(1),
+                file: None,
+                size,
+            })),
+        }
+    }
+
+    /// Queue event, repeated events from the same address are suppressed for a short time
+    pub fn record(
+        &self,
+        kind: AuditEventKind,
+        peer: Option<&[u8]>,
+        address: Option<String>,
+        details: impl Into<String>,
+    ) {
+        let now = get_now_f64();
+        let record = AuditRecord {
+            timestamp: now,
+            kind,
+            peer: peer.map(hex::encode),
+            address,
+            details: details.into(),
+        };
+
+        let mut queue = self.queue.lock().unwrap();
+        let suppress_key = (kind, Self::source(&record));
+        if queue
+            .last_written
+            .get(&suppress_key)
+            .is_some_and(|last| now - last < AUDIT_SUPPRESS_SECONDS)
+        {
+            return;
+        }
+        queue
+            .last_written
+            .retain(|_, last| now - *last < AUDIT_SUPPRESS_SECONDS);
+        queue.last_written.insert(suppress_key, now);
+
+        if queue.records.len() >= MAX_QUEUED_RECORDS {
+            return;
+        }
+        queue.records.push(record);
+        if queue.scheduled {
+            return;
+        }
+        queue.scheduled = true;
+        drop(queue);
+
+        let (queue, files) = (self.queue.clone(), self.files.clone());
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || Self::write_queued(&queue, &files));
+            }
+            Err(_) => Self::write_queued(&queue, &files),
+        }
+    }
+
+    /// IP address of the peer, or its node ID if the address is unknown
+    ///
+    /// Node IDs are chosen by peers themselves, so new IDs from one address must not
+    /// escape the suppression.
+    fn source(record: &AuditRecord) -> Option<String> {
+        match &record.address {
+            Some(address) => Some(
+                address
+                    .parse::<SocketAddr>()
+                    .map_or_else(|_| address.clone(), |address| address.ip().to_string()),
+            ),
+            None => record.peer.clone(),
+        }
+    }
+
+    /// Write all queued records in the order they were queued
+    fn write_queued(queue: &Mutex<AuditQueue>, files: &Mutex<AuditFiles>) {
+        let mut files = files.lock().unwrap();
+        let records = {
+            let mut queue = queue.lock().unwrap();
+            queue.scheduled = false;
+            std::mem::take(&mut queue.records)
+        };
+        for record in &records {
+            if let Err(e) = files.write_line(record) {
+                warn!(error = %e, path = ?files.path, "Failed to write audit record");
+            }
+        }
+    }
+
+    /// Records of `kind` _(all if None)_ written after `since`, the newest first
+    ///
+    /// Queued records are written first, so recent events are not missed.
+    pub fn query(
+        &self,
+        kind: Option<AuditEventKind>,
+        since: f64,
+        limit: usize,
+    ) -> Vec<AuditRecord> {
+        Self::write_queued(&self.queue, &self.files);
+        let files = self.files.lock().unwrap();
+
+        let mut records = Vec::new();
+        let paths = std::iter::once(files.path.clone())
+            .chain((1..files.max_files).map(|i| files.rotated_path(i)));
+        for path in paths {
+            let mut file_records = Self::read_file(&path, kind, since);
+            file_records.reverse();
+            records.extend(file_records);
+            if records.len() >= limit {
+                break;
+            }
+        }
+
+        records.truncate(limit);
+        records
+    }
+
+    fn read_file(path: &Path, kind: Option<AuditEventKind>, since: f64) -> Vec<AuditRecord> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(_) => return Vec::new(),
+        };
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<AuditRecord>(&line).ok())
+            .filter(|r| r.timestamp >= since && kind.is_none_or(|k| r.kind == k))
+            .collect()
+    }
+}
+
+impl AuditFiles {
+    fn write_line(&mut self, record: &AuditRecord) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        if self.size + line.len() as u64 > self.max_size && self.size > 0 {
+            self.file = None;
+            self.rotate()?;
+            self.size = 0;
+        }
+
+        if self.file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            self.file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(&line)?;
+            self.size += line.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Move `<file>.N` to `<file>.N+1` and current file to `<file>.1`, the oldest is dropped
+    fn rotate(&self) -> std::io::Result<()> {
+        if self.max_files == 1 {
+            return fs::remove_file(&self.path);
+        }
+        let _ = fs::remove_file(self.rotated_path(self.max_files - 1));
+        for index in (1..self.max_files - 1).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+}
@@ -0,0 +1,757 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+use crate::events::{EventBus, NodeEvent};
+use crate::security::audit::{AuditEventKind, AuditLog};
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::{NodeSigner, node_id_from_public_key_der, verify_signature};
+#[cfg(feature = "signatures")]
+use crate::utils::serialization::{from_msgpack, to_msgpack};
+use crate::utils::time::{Clock, SystemClock};
+
+/// Max count of active entries taken from the subscribed blocklist
+pub const MAX_SUBSCRIBED_ENTRIES: usize = 100_000;
+/// Max count of addresses blocked because blocked peers were seen from them
+const MAX_SEEN_ADDRESSES: usize = 65_536;
+/// Time in seconds for which the address of a seen blocked peer is blocked
+///
+/// Source address of a packet is not proven, a spoofed packet with blocked ID must not
+/// block the real owner of the address for the whole block time.
+const SEEN_ADDRESS_TTL: f64 = 600.0;
+/// Prefix lengths of IPv4 and IPv6 subnets which are usually held by one operator
+const GROUP_PREFIX_V4: u8 = 24;
+const GROUP_PREFIX_V6: u8 = 48;
+
+/// Range of IP addresses in CIDR notation _(exm. `10.0.0.0/8`, `2001:db8::/32`)_
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subnet {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Subnet {
+    /// Parse CIDR notation, address without prefix is the subnet of this address only
+    pub fn parse(text: &str) -> Option<Self> {
+        let (address, prefix) = match text.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u8>().ok()?)),
+            None => (text.trim(), None),
+        };
+        let address = address.parse::<IpAddr>().ok()?;
+        let bits = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        if prefix > bits {
+            return None;
+        }
+        Some(Self {
+            network: Self::mask(address, prefix),
+            prefix,
+        })
+    }
+
+    /// Address belongs to the subnet, IPv4-mapped IPv6 addresses are matched as IPv4
+    pub fn contains(&self, address: IpAddr) -> bool {
+        let address = address.to_canonical();
+        address.is_ipv4() == self.network.is_ipv4()
+            && Self::mask(address, self.prefix) == self.network
+    }
+
+    /// Subnet of the address which one operator usually holds _(/24 or /48)_
+    ///
+    /// Peers from different groups are likely run by different operators.
+    pub fn group(address: IpAddr) -> Self {
+        let address = address.to_canonical();
+        let prefix = if address.is_ipv4() {
+            GROUP_PREFIX_V4
+        } else {
+            GROUP_PREFIX_V6
+        };
+        Self::of(address, prefix)
+    }
+
+    /// Subnet of the address with the prefix length
+    fn of(address: IpAddr, prefix: u8) -> Self {
+        Self {
+            network: Self::mask(address, prefix),
+            prefix,
+        }
+    }
+
+    /// Address family and prefix length, subnets with the same one are found by one lookup
+    fn shape(&self) -> (bool, u8) {
+        (self.network.is_ipv4(), self.prefix)
+    }
+
+    fn mask(address: IpAddr, prefix: u8) -> IpAddr {
+        match address {
+            IpAddr::V4(address) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                IpAddr::V4((u32::from(address) & mask).into())
+            }
+            IpAddr::V6(address) => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                IpAddr::V6((u128::from(address) & mask).into())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Blocked node IDs, subnets and publishers with times until which they are blocked
+///
+/// Format of exported blocklist files and of blocklists published in the DHT.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Blocklist {
+    /// Node IDs of peers whose messages are dropped
+    pub node_ids: Vec<(Vec<u8>, f64)>,
+    /// Subnets in CIDR notation whose packets are dropped
+    pub subnets: Vec<(String, f64)>,
+    /// Node IDs of publishers whose records are not stored
+    pub publishers: Vec<(Vec<u8>, f64)>,
+    /// Time of making of the list, older lists do not replace a newer subscribed one
+    pub created_at: f64,
+}
+
+impl Blocklist {
+    /// Count of entries of all kinds
+    pub fn len(&self) -> usize {
+        self.node_ids.len() + self.subnets.len() + self.publishers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Blocklist signed by its author
+///
+/// Signature covers the whole list, so a file or a DHT record can not be changed by
+/// nodes which pass it on.
+#[cfg(feature = "signatures")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedBlocklist {
+    pub list: Blocklist,
+    /// Node ID of the author
+    pub signer_id: Vec<u8>,
+    /// DER of the public key of the author
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+#[cfg(feature = "signatures")]
+impl SignedBlocklist {
+    /// Sign the list by the key of the node
+    pub fn issue(list: Blocklist, signer: &NodeSigner) -> Option<Self> {
+        let signature = signer.sign(&to_msgpack(&list).ok()?);
+        Some(Self {
+            list,
+            signer_id: signer.node_id().to_vec(),
+            public_key: signer.public_key_der().to_vec(),
+            signature,
+        })
+    }
+
+    /// Signature is valid and signer ID belongs to the public key
+    pub fn verify(&self) -> bool {
+        if node_id_from_public_key_der(&self.public_key).as_slice() != self.signer_id {
+            return false;
+        }
+        match to_msgpack(&self.list) {
+            Ok(data) => verify_signature(&self.public_key, &data, &self.signature),
+            Err(_) => false,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        to_msgpack(self).ok()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        from_msgpack(data).ok()
+    }
+}
+
+/// Entries of one source with time until which they are blocked
+#[derive(Default)]
+struct BlockedSet {
+    nodes: HashMap<Vec<u8>, f64>,
+    subnets: HashMap<Subnet, f64>,
+    /// Address families and prefix lengths of the subnets
+    shapes: BTreeSet<(bool, u8)>,
+    publishers: HashMap<Vec<u8>, f64>,
+}
+
+impl BlockedSet {
+    /// At most `limit` active entries of the list, subnets which can not be parsed are
+    /// skipped
+    fn from_list(list: &Blocklist, now: f64, limit: usize) -> Self {
+        let mut set = Self::default();
+        for (node_id, until) in list.node_ids.iter().filter(|(_, until)| *until > now) {
+            if set.len() >= limit {
+                return set;
+            }
+            Self::extend(&mut set.nodes, node_id.clone(), *until);
+        }
+        for (subnet, until) in list.subnets.iter().filter(|(_, until)| *until > now) {
+            if set.len() >= limit {
+                return set;
+            }
+            if let Some(subnet) = Subnet::parse(subnet) {
+                set.insert_subnet(subnet, *until);
+            }
+        }
+        for (publisher, until) in list.publishers.iter().filter(|(_, until)| *until > now) {
+            if set.len() >= limit {
+                return set;
+            }
+            Self::extend(&mut set.publishers, publisher.clone(), *until);
+        }
+        set
+    }
+
+    fn insert_subnet(&mut self, subnet: Subnet, until: f64) {
+        Self::extend(&mut self.subnets, subnet, until);
+        self.shapes.insert(subnet.shape());
+    }
+
+    fn remove_subnet(&mut self, subnet: &Subnet) -> bool {
+        let removed = self.subnets.remove(subnet).is_some();
+        self.shapes = self.subnets.keys().map(Subnet::shape).collect();
+        removed
+    }
+
+    /// Keep the later of the present and the new time
+    fn extend<K: std::hash::Hash + Eq>(entries: &mut HashMap<K, f64>, key: K, until: f64) {
+        let entry = entries.entry(key).or_insert(until);
+        *entry = entry.max(until);
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len() + self.subnets.len() + self.publishers.len()
+    }
+
+    fn blocks_node(&self, peer_id: &[u8], now: f64) -> bool {
+        self.nodes.get(peer_id).is_some_and(|until| *until > now)
+    }
+
+    /// One lookup for every prefix length of blocked subnets, not a scan of all subnets
+    fn blocks_address(&self, address: IpAddr, now: f64) -> bool {
+        let address = address.to_canonical();
+        self.shapes
+            .iter()
+            .filter(|(is_ipv4, _)| *is_ipv4 == address.is_ipv4())
+            .any(|(_, prefix)| {
+                self.subnets
+                    .get(&Subnet::of(address, *prefix))
+                    .is_some_and(|until| *until > now)
+            })
+    }
+
+    fn blocks_publisher(&self, publisher_id: &[u8], now: f64) -> bool {
+        self.publishers
+            .get(publisher_id)
+            .is_some_and(|until| *until > now)
+    }
+
+    fn retain_active(&mut self, now: f64) -> usize {
+        let before = self.len();
+        self.nodes.retain(|_, until| *until > now);
+        self.subnets.retain(|_, until| *until > now);
+        self.shapes = self.subnets.keys().map(Subnet::shape).collect();
+        self.publishers.retain(|_, until| *until > now);
+        before - self.len()
+    }
+}
+
+/// Entries of the operator and of the subscribed blocklist under one lock
+#[derive(Default)]
+struct BlockedSets {
+    /// Entries added by the operator or imported from files
+    local: BlockedSet,
+    /// Entries of the subscribed blocklist, replaced on every refresh
+    subscribed: BlockedSet,
+    /// Addresses from which blocked peers came -> the peer and time until which the
+    /// address is blocked
+    seen: HashMap<IpAddr, (Vec<u8>, f64)>,
+}
+
+impl BlockedSets {
+    fn both(&self) -> [&BlockedSet; 2] {
+        [&self.local, &self.subscribed]
+    }
+
+    /// Time until which the peer is blocked by any of the sets _(None if it is not)_
+    fn node_until(&self, peer_id: &[u8], now: f64) -> Option<f64> {
+        self.both()
+            .iter()
+            .filter_map(|set| set.nodes.get(peer_id).copied())
+            .filter(|until| *until > now)
+            .reduce(f64::max)
+    }
+
+    fn blocks_seen(&self, address: IpAddr, now: f64) -> bool {
+        self.seen
+            .get(&address)
+            .is_some_and(|(_, until)| *until > now)
+    }
+}
+
+/// Peers whose messages are dropped
+///
+/// Besides node IDs, whole subnets and publishers of stored records can be blocked.
+/// Node IDs are chosen by peers themselves, so the address from which a blocked peer
+/// comes is blocked for a while _(see [`SEEN_ADDRESS_TTL`])_ and a new ID does not let it
+/// back at once.
+/// Entries added by the operator are kept apart from the subscribed community blocklist,
+/// so refreshing of the subscription never removes them. Every change is written to the
+/// audit log, blocked peers are announced as events.
+pub struct Blacklist {
+    sets: RwLock<BlockedSets>,
+    /// Log of changes
+    pub audit: Option<Arc<AuditLog>>,
+    /// Channel for announcing of blocked peers
+    pub events: Option<EventBus>,
+    /// Clock of the node
+    time: Arc<dyn Clock>,
+}
+
+impl Default for Blacklist {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Blacklist {
+    pub fn new(audit: Option<Arc<AuditLog>>) -> Self {
+        Self {
+            sets: RwLock::new(BlockedSets::default()),
+            audit,
+            events: None,
+            time: Arc::new(SystemClock),
+        }
+    }
+
+    /// Same blacklist which expires entries by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
+
+    /// Block the peer for `seconds`
+    pub fn add(&self, peer_id: &[u8], seconds: f64, reason: &str) {
+        let until = self.time.now() + seconds.max(0.0);
+        self.sets
+            .write()
+            .unwrap()
+            .local
+            .nodes
+            .insert(peer_id.to_vec(), until);
+
+        if let Some(audit) = &self.audit {
+            audit.record(
+                AuditEventKind::BlacklistAdded,
+                Some(peer_id),
+                None,
+                format!("{} (for {}s)", reason, seconds),
+            );
+        }
+        if let Some(events) = &self.events {
+            events.emit(NodeEvent::PeerBlacklisted {
+                peer_id: peer_id.to_vec(),
+                seconds,
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    /// Unblock the peer, return `true` if it was blocked
+    pub fn remove(&self, peer_id: &[u8]) -> bool {
+        let removed = {
+            let mut sets = self.sets.write().unwrap();
+            let removed = sets.local.nodes.remove(peer_id).is_some();
+            if removed {
+                sets.seen.retain(|_, (peer, _)| peer != peer_id);
+            }
+            removed
+        };
+        if removed && let Some(audit) = &self.audit {
+            audit.record(
+                AuditEventKind::BlacklistRemoved,
+                Some(peer_id),
+                None,
+                "removed",
+            );
+        }
+        removed
+    }
+
+    /// Peer is blocked now
+    pub fn contains(&self, peer_id: &[u8]) -> bool {
+        let now = self.time.now();
+        let sets = self.sets.read().unwrap();
+        sets.both().iter().any(|set| set.blocks_node(peer_id, now))
+    }
+
+    /// Peer or its address is blocked now
+    ///
+    /// Address of a blocked peer is blocked too, for [`SEEN_ADDRESS_TTL`] since the peer was
+    /// last seen from it or until the peer is unblocked.
+    pub fn blocks(&self, peer_id: &[u8], address: IpAddr) -> bool {
+        let now = self.time.now();
+        let address = address.to_canonical();
+        let until = {
+            let sets = self.sets.read().unwrap();
+            if sets.blocks_seen(address, now)
+                || sets
+                    .both()
+                    .iter()
+                    .any(|set| set.blocks_address(address, now))
+            {
+                return true;
+            }
+            match sets.node_until(peer_id, now) {
+                Some(until) => until.min(now + SEEN_ADDRESS_TTL),
+                None => return false,
+            }
+        };
+
+        let mut sets = self.sets.write().unwrap();
+        if sets.seen.len() >= MAX_SEEN_ADDRESSES {
+            sets.seen.retain(|_, (_, until)| *until > now);
+        }
+        if sets.seen.len() < MAX_SEEN_ADDRESSES {
+            sets.seen.insert(address, (peer_id.to_vec(), until));
+        }
+        true
+    }
+
+    /// Block all addresses of the subnet for `seconds`
+    pub fn add_subnet(&self, subnet: Subnet, seconds: f64, reason: &str) {
+        let until = self.time.now() + seconds.max(0.0);
+        self.sets
+            .write()
+            .unwrap()
+            .local
+            .insert_subnet(subnet, until);
+        self.record(
+            AuditEventKind::BlacklistAdded,
+            format!("subnet {}: {} (for {}s)", subnet, reason, seconds),
+        );
+    }
+
+    /// Unblock the subnet, return `true` if it was blocked
+    pub fn remove_subnet(&self, subnet: &Subnet) -> bool {
+        let removed = self.sets.write().unwrap().local.remove_subnet(subnet);
+        if removed {
+            self.record(
+                AuditEventKind::BlacklistRemoved,
+                format!("subnet {}: removed", subnet),
+            );
+        }
+        removed
+    }
+
+    /// Address belongs to a blocked subnet
+    pub fn blocks_address(&self, address: IpAddr) -> bool {
+        let now = self.time.now();
+        let sets = self.sets.read().unwrap();
+        sets.both()
+            .iter()
+            .any(|set| set.blocks_address(address, now))
+    }
+
+    /// Refuse records of the publisher for `seconds`
+    pub fn add_publisher(&self, publisher_id: &[u8], seconds: f64, reason: &str) {
+        let until = self.time.now() + seconds.max(0.0);
+        self.sets
+            .write()
+            .unwrap()
+            .local
+            .publishers
+            .insert(publisher_id.to_vec(), until);
+        if let Some(audit) = &self.audit {
+            audit.record(
+                AuditEventKind::BlacklistAdded,
+                Some(publisher_id),
+                None,
+                format!("publisher: {} (for {}s)", reason, seconds),
+            );
+        }
+    }
+
+    /// Accept records of the publisher again, return `true` if it was blocked
+    pub fn remove_publisher(&self, publisher_id: &[u8]) -> bool {
+        let removed = self
+            .sets
+            .write()
+            .unwrap()
+            .local
+            .publishers
+            .remove(publisher_id)
+            .is_some();
+        if removed && let Some(audit) = &self.audit {
+            audit.record(
+                AuditEventKind::BlacklistRemoved,
+                Some(publisher_id),
+                None,
+                "publisher: removed",
+            );
+        }
+        removed
+    }
+
+    /// Records of the publisher are refused now, unknown publisher is never blocked
+    ///
+    /// Publisher must be proven by its signature of the record or be the sender itself.
+    pub fn blocks_publisher(&self, publisher_id: &[u8]) -> bool {
+        if publisher_id.is_empty() {
+            return false;
+        }
+        let now = self.time.now();
+        let sets = self.sets.read().unwrap();
+        sets.both()
+            .iter()
+            .any(|set| set.blocks_publisher(publisher_id, now))
+    }
+
+    /// Active entries added by the operator, the subscribed ones are not exported
+    pub fn export(&self) -> Blocklist {
+        let now = self.time.now();
+        let sets = self.sets.read().unwrap();
+        let local = &sets.local;
+        Blocklist {
+            node_ids: local
+                .nodes
+                .iter()
+                .filter(|(_, until)| **until > now)
+                .map(|(id, until)| (id.clone(), *until))
+                .collect(),
+            subnets: local
+                .subnets
+                .iter()
+                .filter(|(_, until)| **until > now)
+                .map(|(subnet, until)| (subnet.to_string(), *until))
+                .collect(),
+            publishers: local
+                .publishers
+                .iter()
+                .filter(|(_, until)| **until > now)
+                .map(|(id, until)| (id.clone(), *until))
+                .collect(),
+            created_at: now,
+        }
+    }
+
+    /// Add active entries of the list to the operator's ones, return count of them
+    ///
+    /// Entries which are already blocked keep the later of both times.
+    pub fn import(&self, list: &Blocklist, source: &str) -> usize {
+        let imported = BlockedSet::from_list(list, self.time.now(), usize::MAX);
+        let count = imported.len();
+        {
+            let mut sets = self.sets.write().unwrap();
+            let local = &mut sets.local;
+            for (node_id, until) in imported.nodes {
+                BlockedSet::extend(&mut local.nodes, node_id, until);
+            }
+            for (subnet, until) in imported.subnets {
+                local.insert_subnet(subnet, until);
+            }
+            for (publisher, until) in imported.publishers {
+                BlockedSet::extend(&mut local.publishers, publisher, until);
+            }
+        }
+        self.record(
+            AuditEventKind::BlacklistAdded,
+            format!("imported {} entries from {}", count, source),
+        );
+        count
+    }
+
+    /// Replace entries of the subscribed blocklist, return count of active ones
+    ///
+    /// Only first [`MAX_SUBSCRIBED_ENTRIES`] active entries are taken.
+    pub fn set_subscribed(&self, list: &Blocklist, source: &str) -> usize {
+        let subscribed = BlockedSet::from_list(list, self.time.now(), MAX_SUBSCRIBED_ENTRIES);
+        let count = subscribed.len();
+        self.sets.write().unwrap().subscribed = subscribed;
+        let skipped = if count < list.len() && count == MAX_SUBSCRIBED_ENTRIES {
+            format!(", {} over the limit skipped", list.len() - count)
+        } else {
+            String::new()
+        };
+        self.record(
+            AuditEventKind::BlacklistAdded,
+            format!(
+                "subscribed blocklist of {} refreshed, {} entries{}",
+                source, count, skipped
+            ),
+        );
+        count
+    }
+
+    /// Forget entries which are not active anymore
+    pub fn cleanup_expired(&self) -> usize {
+        let now = self.time.now();
+        let mut sets = self.sets.write().unwrap();
+        sets.seen.retain(|_, (_, until)| *until > now);
+        sets.local.retain_active(now) + sets.subscribed.retain_active(now)
+    }
+
+    /// Peers blocked by the operator with time until which they are blocked
+    pub fn entries(&self) -> Vec<(Vec<u8>, f64)> {
+        let now = self.time.now();
+        self.sets
+            .read()
+            .unwrap()
+            .local
+            .nodes
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(peer, until)| (peer.clone(), *until))
+            .collect()
+    }
+
+    /// Audit record which is not about one peer
+    fn record(&self, kind: AuditEventKind, details: String) {
+        if let Some(audit) = &self.audit {
+            audit.record(kind, None, None, details);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::time::{VirtualClock, get_now_f64};
+    use std::time::Duration;
+
+    fn address(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn subnets_are_found_by_prefix_length() {
+        let blacklist = Blacklist::new(None);
+        blacklist.add_subnet(Subnet::parse("10.0.0.0/8").unwrap(), 60.0, "test");
+        blacklist.add_subnet(Subnet::parse("192.168.1.7").unwrap(), 60.0, "test");
+        blacklist.add_subnet(Subnet::parse("2001:db8::/32").unwrap(), 60.0, "test");
+
+        assert!(blacklist.blocks_address(address("10.20.30.40")));
+        assert!(blacklist.blocks_address(address("::ffff:10.1.1.1")));
+        assert!(blacklist.blocks_address(address("192.168.1.7")));
+        assert!(!blacklist.blocks_address(address("192.168.1.8")));
+        assert!(blacklist.blocks_address(address("2001:db8:1::1")));
+        assert!(!blacklist.blocks_address(address("2001:db9::1")));
+
+        assert!(blacklist.remove_subnet(&Subnet::parse("10.0.0.0/8").unwrap()));
+        assert!(!blacklist.blocks_address(address("10.20.30.40")));
+        assert!(blacklist.blocks_address(address("192.168.1.7")));
+    }
+
+    #[test]
+    fn peer_is_blocked_by_id_or_address() {
+        let blacklist = Blacklist::new(None);
+        blacklist.add(&[1u8; 20], 60.0, "test");
+        blacklist.add_subnet(Subnet::parse("10.0.0.0/8").unwrap(), 60.0, "test");
+
+        assert!(blacklist.blocks(&[1u8; 20], address("1.1.1.1")));
+        assert!(blacklist.blocks(&[2u8; 20], address("10.0.0.1")));
+        assert!(!blacklist.blocks(&[2u8; 20], address("1.1.1.2")));
+
+        // New ID from the address of a blocked peer does not help
+        assert!(blacklist.blocks(&[2u8; 20], address("1.1.1.1")));
+        assert!(blacklist.remove(&[1u8; 20]));
+        assert!(!blacklist.blocks(&[2u8; 20], address("1.1.1.1")));
+    }
+
+    #[test]
+    fn address_of_blocked_peer_is_blocked_only_for_a_while() {
+        let clock = Arc::new(VirtualClock::new(1000.0));
+        let blacklist = Blacklist::new(None).with_clock(clock.clone());
+        blacklist.add(&[1u8; 20], 86_400.0, "test");
+        assert!(blacklist.blocks(&[1u8; 20], address("1.1.1.1")));
+        assert!(blacklist.blocks(&[2u8; 20], address("1.1.1.1")));
+
+        clock.advance(Duration::from_secs_f64(SEEN_ADDRESS_TTL + 1.0));
+        assert!(!blacklist.blocks(&[2u8; 20], address("1.1.1.1")));
+        assert!(blacklist.blocks(&[1u8; 20], address("1.1.1.1")));
+    }
+
+    #[test]
+    fn subscribed_list_is_capped_and_kept_apart() {
+        let blacklist = Blacklist::new(None);
+        let until = get_now_f64() + 60.0;
+        let list = Blocklist {
+            node_ids: (0..MAX_SUBSCRIBED_ENTRIES as u32 + 10)
+                .map(|i| (i.to_be_bytes().to_vec(), until))
+                .collect(),
+            publishers: vec![(vec![9u8; 20], until)],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            blacklist.set_subscribed(&list, "author"),
+            MAX_SUBSCRIBED_ENTRIES
+        );
+        assert!(blacklist.contains(&0u32.to_be_bytes()));
+        assert!(!blacklist.blocks_publisher(&[9u8; 20]));
+        // Subscribed entries are not exported
+        assert!(blacklist.export().is_empty());
+
+        blacklist.set_subscribed(&Blocklist::default(), "author");
+        assert!(!blacklist.contains(&0u32.to_be_bytes()));
+    }
+
+    #[test]
+    fn expired_entries_are_not_imported() {
+        let blacklist = Blacklist::new(None);
+        let now = get_now_f64();
+        let list = Blocklist {
+            node_ids: vec![(vec![1u8; 20], now + 60.0), (vec![2u8; 20], now - 1.0)],
+            subnets: vec![
+                ("10.0.0.0/8".to_string(), now + 60.0),
+                ("bad".to_string(), now + 60.0),
+            ],
+            publishers: vec![(vec![3u8; 20], now + 60.0)],
+            created_at: now,
+        };
+
+        assert_eq!(blacklist.import(&list, "file"), 3);
+        assert!(blacklist.contains(&[1u8; 20]));
+        assert!(!blacklist.contains(&[2u8; 20]));
+        assert!(blacklist.blocks_publisher(&[3u8; 20]));
+        assert!(!blacklist.blocks_publisher(&[]));
+    }
+
+    #[cfg(feature = "signatures")]
+    #[test]
+    fn signed_list_can_not_be_changed() {
+        let (private_key, public_key) = crate::utils::crypto::generate_keypair();
+        let node_id = crate::utils::crypto::node_id_from_public_key(&public_key);
+        let signer = NodeSigner::new(private_key, node_id.to_vec());
+        let list = Blocklist {
+            node_ids: vec![(vec![1u8; 20], 100.0)],
+            ..Default::default()
+        };
+
+        let signed = SignedBlocklist::issue(list, &signer).unwrap();
+        let restored = SignedBlocklist::from_bytes(&signed.to_bytes().unwrap()).unwrap();
+        assert!(restored.verify());
+
+        let mut changed = restored.clone();
+        changed.list.node_ids.clear();
+        assert!(!changed.verify());
+
+        let mut other_signer = restored;
+        other_signer.signer_id = vec![7u8; 20];
+        assert!(!other_signer.verify());
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+use wasmi::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Max count of elements in the table of a plugin
+const MAX_TABLE_ELEMENTS: u32 = 10_000;
+
+/// Decision of the content policy about a stored record
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PolicyDecision {
+    /// Record may be stored, score from 0.0 to 1.0
+    Accept {
+        score: f64,
+    },
+    Reject,
+}
+
+/// Content policy implemented by a WASM module
+///
+/// Module must not import anything and must export:
+/// - `memory` - linear memory of the module;
+/// - `alloc(len: i32) -> i32` - pointer to `len` free bytes for the input;
+/// - `check_record(key_ptr: i32, key_len: i32, value_ptr: i32, value_len: i32) -> i32` -
+///   negative result rejects the record, other result accepts it with score `result / 100`.
+///
+/// Every call runs in a new instance with limited fuel and memory, so plugin keeps no state
+/// between records and can not hang the node or take all of its memory.
+pub struct PolicyPlugin {
+    /// File name of the module for logs
+    name: String,
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+    /// Max size of the linear memory in bytes
+    memory: usize,
+}
+
+impl PolicyPlugin {
+    pub fn load(path: &Path, fuel: u64, memory: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &fs::read(path)?)?;
+        Ok(Self {
+            name: path.display().to_string(),
+            engine,
+            module,
+            fuel,
+            memory,
+        })
+    }
+
+    /// Run `check_record` of the module on the record
+    pub fn check(&self, key: &[u8], value: &[u8]) -> Result<PolicyDecision, wasmi::Error> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.memory)
+            .memories(1)
+            .tables(1)
+            .table_elements(MAX_TABLE_ELEMENTS)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.set_fuel(self.fuel)?;
+        let instance = Linker::<StoreLimits>::new(&self.engine)
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| wasmi::Error::new("plugin does not export memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc")?;
+        let check_record =
+            instance.get_typed_func::<(i32, i32, i32, i32), i32>(&store, "check_record")?;
+
+        let mut write = |data: &[u8]| -> Result<i32, wasmi::Error> {
+            let ptr = alloc.call(&mut store, data.len() as i32)?;
+            memory.write(&mut store, ptr as u32 as usize, data)?;
+            Ok(ptr)
+        };
+        let key_ptr = write(key)?;
+        let value_ptr = write(value)?;
+
+        let result = check_record.call(
+            &mut store,
+            (key_ptr, key.len() as i32, value_ptr, value.len() as i32),
+        )?;
+        Ok(if result < 0 {
+            PolicyDecision::Reject
+        } else {
+            PolicyDecision::Accept {
+                score: (result as f64 / 100.0).min(1.0),
+            }
+        })
+    }
+}
+
+/// Operator's content policy for records of other nodes
+///
+/// Record is accepted only if every plugin accepts it and the lowest score is not less
+/// than `min_score`. Plugin which fails _(trap, out of fuel, bad exports)_ rejects the record.
+pub struct ContentPolicy {
+    plugins: Vec<PolicyPlugin>,
+    min_score: f64,
+}
+
+impl ContentPolicy {
+    pub fn new(plugins: Vec<PolicyPlugin>, min_score: f64) -> Self {
+        Self { plugins, min_score }
+    }
+
+    /// Load plugins from WASM files
+    pub fn load<P: AsRef<Path>>(
+        paths: &[P],
+        fuel: u64,
+        memory: usize,
+        min_score: f64,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let plugins = paths
+            .iter()
+            .map(|path| PolicyPlugin::load(path.as_ref(), fuel, memory))
+            .collect::<Result<Vec<_>, _>>()?;
+        for plugin in &plugins {
+            info!(plugin = %plugin.name, "Content policy plugin loaded");
+        }
+        Ok(Self::new(plugins, min_score))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    pub fn check(&self, key: &[u8], value: &[u8]) -> PolicyDecision {
+        let mut lowest = 1.0f64;
+        for plugin in &self.plugins {
+            match plugin.check(key, value) {
+                Ok(PolicyDecision::Accept { score }) => lowest = lowest.min(score),
+                Ok(PolicyDecision::Reject) => return PolicyDecision::Reject,
+                Err(e) => {
+                    warn!(plugin = %plugin.name, error = %e, "Content policy plugin failed");
+                    return PolicyDecision::Reject;
+                }
+            }
+        }
+        if lowest < self.min_score {
+            PolicyDecision::Reject
+        } else {
+            PolicyDecision::Accept { score: lowest }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir::TempDir;
+
+    /// Module which only exports memory of 512 pages _(32 MiB)_
+    const BIG_MEMORY_MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x05, 0x04, 0x01, 0x00, 0x80, 0x04, // memory of 512 pages
+        0x07, 0x0a, 0x01, 0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00, // export
+    ];
+
+    #[test]
+    fn plugin_memory_is_limited() {
+        let dir = TempDir::new("policy");
+        let path = dir.join("policy.wasm");
+        fs::write(&path, BIG_MEMORY_MODULE).unwrap();
+
+        let limited = PolicyPlugin::load(&path, 1000, 1024 * 1024).unwrap();
+        let error = limited.check(b"key", b"value").unwrap_err();
+        assert!(error.to_string().contains("memory allocation"), "{}", error);
+
+        // Module fits the larger limit and fails only on its missing exports
+        let unlimited = PolicyPlugin::load(&path, 1000, 64 * 1024 * 1024).unwrap();
+        let error = unlimited.check(b"key", b"value").unwrap_err();
+        assert!(
+            !error.to_string().contains("memory allocation"),
+            "{}",
+            error
+        );
+    }
+}
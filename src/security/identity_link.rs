@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::crypto::{NodeSigner, node_id_from_public_key_der, verify_signature};
+use crate::utils::time::get_now_f64;
+
+/// Signed statement that the node moved from old ID to new ID
+///
+/// Link is signed by both keys, so only the owner of both identities can make it.
+/// Peers which trust the old ID may move this trust to the new one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdentityLink {
+    pub old_id: Vec<u8>,
+    pub new_id: Vec<u8>,
+    /// DER of the old public key, its SHA-1 must be equal to `old_id`
+    pub old_public_key: Vec<u8>,
+    /// DER of the new public key, its SHA-1 must be equal to `new_id`
+    pub new_public_key: Vec<u8>,
+    /// Time of rotation
+    pub created_at: f64,
+    pub old_signature: Vec<u8>,
+    pub new_signature: Vec<u8>,
+}
+
+impl IdentityLink {
+    /// Link old identity of our node to the new one
+    pub fn issue(old: &NodeSigner, new: &NodeSigner) -> Self {
+        let created_at = get_now_f64();
+        let data = Self::signed_bytes(old.node_id(), new.node_id(), created_at);
+        Self {
+            old_id: old.node_id().to_vec(),
+            new_id: new.node_id().to_vec(),
+            old_public_key: old.public_key_der().to_vec(),
+            new_public_key: new.public_key_der().to_vec(),
+            created_at,
+            old_signature: old.sign(&data),
+            new_signature: new.sign(&data),
+        }
+    }
+
+    /// Bytes which are covered by both signatures
+    fn signed_bytes(old_id: &[u8], new_id: &[u8], created_at: f64) -> Vec<u8> {
+        let mut data = Vec::with_capacity(old_id.len() + new_id.len() + 8);
+        data.extend_from_slice(old_id);
+        data.extend_from_slice(new_id);
+        data.extend_from_slice(&created_at.to_be_bytes());
+        data
+    }
+
+    /// Check that both IDs belong to their keys and both keys signed the link
+    pub fn verify(&self) -> bool {
+        let data = Self::signed_bytes(&self.old_id, &self.new_id, self.created_at);
+        self.old_id != self.new_id
+            && node_id_from_public_key_der(&self.old_public_key)[..] == self.old_id[..]
+            && node_id_from_public_key_der(&self.new_public_key)[..] == self.new_id[..]
+            && verify_signature(&self.old_public_key, &data, &self.old_signature)
+            && verify_signature(&self.new_public_key, &data, &self.new_signature)
+    }
+}
+
+/// Result of the identity rotation of our node
+#[derive(Debug, Clone)]
+pub struct RotationReport {
+    pub link: IdentityLink,
+    /// Count of stored keys accepted by at least one of their closest nodes
+    pub keys_transferred: usize,
+    /// Count of stored keys which no closest node accepted
+    pub keys_failed: usize,
+}
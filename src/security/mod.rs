@@ -1,2 +1,18 @@
+/// Tokens and roles of the admin API
+pub mod admin_tokens;
+/// Append-only log of security events
+pub mod audit;
+/// Peers whose messages are dropped
+pub mod blacklist;
+/// Operator's WASM plugins which accept, reject and score stored records
+#[cfg(feature = "wasm-plugins")]
+pub mod content_policy;
+/// Signed link between old and new identity of a rotated node
+#[cfg(feature = "signatures")]
+pub mod identity_link;
+/// Client puzzles for new peers under high load
+pub mod puzzle;
 /// Saves data transferring
 pub mod rate_limiter;
+/// Scores of peers by their answers, latency and validity of their data
+pub mod reputation;
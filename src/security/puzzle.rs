@@ -0,0 +1,147 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::utils::crypto::hash_key;
+use crate::utils::time::get_now_f64;
+
+/// Time in seconds during which issued puzzle can be solved
+pub const PUZZLE_LIFETIME: f64 = 60.0;
+/// Time in seconds during which peer with solved puzzle is not asked again
+pub const VERIFIED_LIFETIME: f64 = 3600.0;
+/// Max count of remembered verified peers
+pub const MAX_VERIFIED_PEERS: usize = 65536;
+/// Max count of requests waiting for puzzle solution
+pub const MAX_PARKED_REQUESTS: usize = 1024;
+/// Puzzles harder than this are not solved by our node
+pub const MAX_PUZZLE_DIFFICULTY: u8 = 24;
+
+/// Puzzle which peer must solve before its requests are processed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Puzzle {
+    pub challenge: Vec<u8>,
+    /// Time of issue, it is a part of the challenge
+    pub issued_at: f64,
+    /// Required count of leading zero bits of the solution hash
+    pub difficulty: u8,
+}
+
+/// Client puzzle protocol against floods from new peers
+///
+/// When inbound load is close to the rate limit, unknown peers get a puzzle instead of
+/// the answer: find `nonce` such that `SHA-256(challenge | peer_id | nonce)` starts with
+/// `difficulty` zero bits. Challenges are derived from our secret, so nothing is stored
+/// until a peer brings a solution.
+pub struct PuzzleGuard {
+    /// Count of zero bits, puzzles are disabled if 0
+    pub difficulty: u8,
+    /// Part of the global rate limit after which puzzles are required
+    pub load_threshold: f64,
+    secret: [u8; 32],
+    /// Peer node ID -> time of the solution
+    verified: Mutex<HashMap<Vec<u8>, f64>>,
+}
+
+impl PuzzleGuard {
+    pub fn new(difficulty: u8, load_threshold: f64) -> Self {
+        Self {
+            difficulty,
+            load_threshold,
+            secret: rand::thread_rng().r#gen(),
+            verified: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Puzzle is needed for unknown peer under current `load` _(part of rate limit)_
+    pub fn is_required(&self, peer_id: &[u8], load: f64) -> bool {
+        self.difficulty > 0 && load >= self.load_threshold && !self.is_verified(peer_id)
+    }
+
+    /// Peer solved puzzle recently
+    pub fn is_verified(&self, peer_id: &[u8]) -> bool {
+        let now = get_now_f64();
+        self.verified
+            .lock()
+            .unwrap()
+            .get(peer_id)
+            .is_some_and(|solved_at| now - solved_at <= VERIFIED_LIFETIME)
+    }
+
+    /// Create puzzle for the peer
+    pub fn issue(&self, peer_id: &[u8]) -> Puzzle {
+        let issued_at = get_now_f64();
+        Puzzle {
+            challenge: self.challenge(peer_id, issued_at),
+            issued_at,
+            difficulty: self.difficulty,
+        }
+    }
+
+    /// Check solution of the peer, remember peer as verified if it is correct
+    pub fn verify(&self, peer_id: &[u8], challenge: &[u8], issued_at: f64, nonce: u64) -> bool {
+        let now = get_now_f64();
+        if now - issued_at > PUZZLE_LIFETIME
+            || issued_at > now
+            || challenge != self.challenge(peer_id, issued_at).as_slice()
+            || leading_zero_bits(&solution_hash(challenge, peer_id, nonce)) < self.difficulty as u32
+        {
+            return false;
+        }
+
+        let mut verified = self.verified.lock().unwrap();
+        if verified.len() >= MAX_VERIFIED_PEERS {
+            verified.retain(|_, solved_at| now - *solved_at <= VERIFIED_LIFETIME);
+        }
+        if verified.len() < MAX_VERIFIED_PEERS {
+            verified.insert(peer_id.to_vec(), now);
+        }
+        true
+    }
+
+    /// Find nonce for the puzzle issued to our node
+    ///
+    /// Return `None` if puzzle is harder than [`MAX_PUZZLE_DIFFICULTY`]
+    pub fn solve(challenge: &[u8], our_id: &[u8], difficulty: u8) -> Option<u64> {
+        if difficulty > MAX_PUZZLE_DIFFICULTY {
+            return None;
+        }
+        (0..u64::MAX).find(|nonce| {
+            leading_zero_bits(&solution_hash(challenge, our_id, *nonce)) >= difficulty as u32
+        })
+    }
+
+    fn challenge(&self, peer_id: &[u8], issued_at: f64) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.secret.len() + peer_id.len() + 8);
+        data.extend_from_slice(&self.secret);
+        data.extend_from_slice(peer_id);
+        data.extend_from_slice(&issued_at.to_be_bytes());
+        hash_key(&data)[..16].to_vec()
+    }
+}
+
+impl Default for PuzzleGuard {
+    fn default() -> Self {
+        Self::new(16, 0.8)
+    }
+}
+
+fn solution_hash(challenge: &[u8], peer_id: &[u8], nonce: u64) -> [u8; 32] {
+    let mut data = Vec::with_capacity(challenge.len() + peer_id.len() + 8);
+    data.extend_from_slice(challenge);
+    data.extend_from_slice(peer_id);
+    data.extend_from_slice(&nonce.to_be_bytes());
+    hash_key(&data)
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    bits
+}
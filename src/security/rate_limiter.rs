@@ -1,8 +1,10 @@
 use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
 use tracing::warn;
 
 use crate::exceptions::{NetworkError, RhizomeError};
-use crate::utils::time::get_now_f64;
+use crate::network::qos::MessagePriority;
+use crate::utils::time::{Clock, SystemClock};
 
 /// Structure for limit messages peer some period of time
 ///
@@ -20,6 +22,9 @@ pub struct RateLimiter {
 
     /// Requests by node: NodeID -> deque of timestamps
     node_requests: HashMap<Vec<u8>, VecDeque<f64>>,
+
+    /// Clock of the node
+    time: Arc<dyn Clock>,
 }
 
 impl RateLimiter {
@@ -31,23 +36,46 @@ impl RateLimiter {
             per_node_limit,
             request_history: VecDeque::with_capacity(max_requests * 2),
             node_requests: HashMap::new(),
+            time: Arc::new(SystemClock),
         }
     }
 
+    /// Same limiter which counts the window by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
+
     /// Check rate limit
     ///
     /// Main function which work with all requests and can block some requests if they do not fit
     pub fn check_rate_limit(&mut self, node_id: Option<&[u8]>) -> Result<bool, RhizomeError> {
-        let current_time = get_now_f64();
+        self.check_rate_limit_with_priority(node_id, MessagePriority::default())
+    }
+
+    /// Check rate limit for message of the priority class
+    ///
+    /// Every class can use only its [`MessagePriority::limit_share`] of the limits, so under
+    /// load popularity and replication traffic is rejected before control and lookups.
+    pub fn check_rate_limit_with_priority(
+        &mut self,
+        node_id: Option<&[u8]>,
+        priority: MessagePriority,
+    ) -> Result<bool, RhizomeError> {
+        let current_time = self.time.now();
 
         self.cleanup_old_requests(current_time);
 
         let recent_requests = self.request_history.len();
+        let share = priority.limit_share();
+        let max_requests = ((self.max_requests as f64 * share) as usize).max(1);
+        let per_node_limit = ((self.per_node_limit as f64 * share) as usize).max(1);
 
-        if recent_requests >= self.max_requests {
+        if recent_requests >= max_requests {
             warn!(
                 requests = recent_requests,
-                limit = self.max_requests,
+                limit = max_requests,
+                priority = ?priority,
                 "Rate limit exceeded"
             );
             return Err(RhizomeError::Network(NetworkError::RateLimitError));
@@ -62,12 +90,13 @@ impl RateLimiter {
 
             let node_recent = node_history.len();
 
-            if node_recent >= self.per_node_limit {
+            if node_recent >= per_node_limit {
                 let hex_id = hex::encode(&id[..id.len().min(8)]);
                 warn!(
                     node_id = %hex_id,
                     requests = node_recent,
-                    limit = self.per_node_limit,
+                    limit = per_node_limit,
+                    priority = ?priority,
                     "Per-node rate limit exceeded"
                 );
                 return Err(RhizomeError::Network(NetworkError::RateLimitError));
@@ -106,9 +135,15 @@ impl RateLimiter {
         });
     }
 
+    /// Used part of the global limit _(1.0 means the limit is reached)_
+    pub fn load(&mut self) -> f64 {
+        self.cleanup_old_requests(self.time.now());
+        self.request_history.len() as f64 / self.max_requests.max(1) as f64
+    }
+
     /// Getting requests statistics for analyze
     pub fn get_stats(&mut self) -> HashMap<String, f64> {
-        let current_time = get_now_f64();
+        let current_time = self.time.now();
         self.cleanup_old_requests(current_time);
 
         let mut stats = HashMap::new();
@@ -0,0 +1,223 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+use crate::dht::node::Node;
+use crate::utils::time::{Clock, SystemClock};
+
+/// Peers with lower score are asked and used as replicas only if better peers are absent
+pub const MIN_REPUTABLE_SCORE: f64 = 0.2;
+
+/// RTT in seconds which halves the latency part of the score
+const REFERENCE_LATENCY: f64 = 1.0;
+
+/// Weight of the new RTT sample in the smoothed latency
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Every validation failure multiplies the score by this factor
+const VALIDATION_PENALTY: f64 = 0.5;
+
+/// Peers without updates during this time in seconds are forgotten
+pub const REPUTATION_MAX_AGE: f64 = 86400.0;
+
+/// Statistics of our requests to one peer
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PeerReputation {
+    /// Count of answered requests
+    pub responses: u64,
+    /// Count of requests without answer
+    pub failures: u64,
+    /// Count of invalid data from the address of the peer _(bad receipts, wrong puzzle
+    /// solutions)_
+    pub validation_failures: u64,
+    /// Smoothed RTT in seconds _(None if never answered)_
+    pub latency: Option<f64>,
+    /// Time of the last update
+    pub updated_at: f64,
+}
+
+impl PeerReputation {
+    /// Score from 0.0 to 1.0
+    ///
+    /// Response rate _(1/2 for unknown peer)_ multiplied by latency factor and by
+    /// penalty for every validation failure.
+    pub fn score(&self) -> f64 {
+        let response_rate =
+            (self.responses as f64 + 1.0) / ((self.responses + self.failures) as f64 + 2.0);
+        let latency_factor = self
+            .latency
+            .map_or(1.0, |rtt| REFERENCE_LATENCY / (REFERENCE_LATENCY + rtt));
+        let penalty = VALIDATION_PENALTY.powi(self.validation_failures.min(64) as i32);
+        response_rate * latency_factor * penalty
+    }
+}
+
+/// Reputation of peers by their node ID
+///
+/// Fed by network requests and validation of peer data, used for choosing lookup
+/// candidates and replication targets. Validation failures are counted by address of the
+/// sender: node ID of invalid data can be forged, so counting them by the ID would harm
+/// its real owner.
+pub struct ReputationTable {
+    peers: RwLock<HashMap<Vec<u8>, PeerReputation>>,
+    /// Validation failures by address of the sender
+    addresses: RwLock<HashMap<IpAddr, PeerReputation>>,
+    /// Clock of the node
+    time: Arc<dyn Clock>,
+}
+
+impl Default for ReputationTable {
+    fn default() -> Self {
+        Self {
+            peers: RwLock::new(HashMap::new()),
+            addresses: RwLock::new(HashMap::new()),
+            time: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl ReputationTable {
+    /// Same table which ages peers by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
+
+    fn update(&self, peer_id: &[u8], apply: impl FnOnce(&mut PeerReputation)) {
+        let mut peers = self.peers.write().unwrap();
+        let reputation = peers.entry(peer_id.to_vec()).or_default();
+        apply(reputation);
+        reputation.updated_at = self.time.now();
+    }
+
+    /// Peer answered our request in `rtt` seconds
+    pub fn record_response(&self, peer_id: &[u8], rtt: f64) {
+        self.update(peer_id, |r| {
+            r.responses += 1;
+            r.latency = Some(match r.latency {
+                Some(latency) => latency + LATENCY_SMOOTHING * (rtt - latency),
+                None => rtt,
+            });
+        });
+    }
+
+    /// Peer did not answer our request
+    pub fn record_failure(&self, peer_id: &[u8]) {
+        self.update(peer_id, |r| r.failures += 1);
+    }
+
+    /// Data which did not pass validation came from the address
+    pub fn record_validation_failure(&self, address: IpAddr) {
+        let mut addresses = self.addresses.write().unwrap();
+        let reputation = addresses.entry(address.to_canonical()).or_default();
+        reputation.validation_failures += 1;
+        reputation.updated_at = self.time.now();
+    }
+
+    /// Validation failures of the address
+    pub fn address_failures(&self, address: IpAddr) -> u64 {
+        self.addresses
+            .read()
+            .unwrap()
+            .get(&address.to_canonical())
+            .map_or(0, |r| r.validation_failures)
+    }
+
+    pub fn get(&self, peer_id: &[u8]) -> Option<PeerReputation> {
+        self.peers.read().unwrap().get(peer_id).cloned()
+    }
+
+    /// Score of the peer, unknown peer gets score of the peer without history
+    pub fn score(&self, peer_id: &[u8]) -> f64 {
+        self.peers
+            .read()
+            .unwrap()
+            .get(peer_id)
+            .map_or_else(|| PeerReputation::default().score(), |r| r.score())
+    }
+
+    pub fn is_reputable(&self, peer_id: &[u8]) -> bool {
+        self.score(peer_id) >= MIN_REPUTABLE_SCORE
+    }
+
+    /// Score of the node with penalty for validation failures of its address
+    pub fn node_score(&self, node: &Node) -> f64 {
+        let failures = node
+            .address
+            .parse::<IpAddr>()
+            .map_or(0, |address| self.address_failures(address));
+        self.score(&node.node_id.0) * VALIDATION_PENALTY.powi(failures.min(64) as i32)
+    }
+
+    pub fn is_reputable_node(&self, node: &Node) -> bool {
+        self.node_score(node) >= MIN_REPUTABLE_SCORE
+    }
+
+    /// Keep reputable nodes in their order, fill up to `count` with the best of others
+    pub fn prefer(&self, nodes: Vec<Node>, count: usize) -> Vec<Node> {
+        let (mut preferred, mut others): (Vec<Node>, Vec<Node>) =
+            nodes.into_iter().partition(|n| self.is_reputable_node(n));
+        if preferred.len() < count {
+            others.sort_by(|a, b| self.node_score(b).total_cmp(&self.node_score(a)));
+            preferred.extend(others.into_iter().take(count - preferred.len()));
+        }
+        preferred
+    }
+
+    /// Forget peers without updates during [`REPUTATION_MAX_AGE`]
+    pub fn cleanup_expired(&self) -> usize {
+        let now = self.time.now();
+        let mut peers = self.peers.write().unwrap();
+        let mut addresses = self.addresses.write().unwrap();
+        let before = peers.len() + addresses.len();
+        peers.retain(|_, r| now - r.updated_at < REPUTATION_MAX_AGE);
+        addresses.retain(|_, r| now - r.updated_at < REPUTATION_MAX_AGE);
+        before - peers.len() - addresses.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::node::NodeID;
+
+    fn node(id: u8, address: &str) -> Node {
+        Node::new(NodeID::new([id; 20]), address.to_string(), 8468)
+    }
+
+    #[test]
+    fn validation_failures_follow_the_address() {
+        let reputation = ReputationTable::default();
+        let honest = node(1, "10.0.0.1");
+        let liar = node(2, "10.0.0.2");
+        for _ in 0..3 {
+            reputation.record_validation_failure("10.0.0.2".parse().unwrap());
+        }
+
+        assert!(reputation.is_reputable_node(&honest));
+        assert!(!reputation.is_reputable_node(&liar));
+        // Failures of the address do not stick to a node ID it used
+        assert!(reputation.is_reputable(&liar.node_id.0));
+        assert_eq!(
+            reputation.address_failures("::ffff:10.0.0.2".parse().unwrap()),
+            3
+        );
+    }
+
+    #[test]
+    fn preferred_nodes_skip_failing_addresses() {
+        let reputation = ReputationTable::default();
+        reputation.record_validation_failure("10.0.0.2".parse().unwrap());
+        reputation.record_validation_failure("10.0.0.2".parse().unwrap());
+        reputation.record_validation_failure("10.0.0.2".parse().unwrap());
+
+        let nodes = vec![node(2, "10.0.0.2"), node(1, "10.0.0.1")];
+        let preferred = reputation.prefer(nodes.clone(), 1);
+        assert_eq!(preferred.len(), 1);
+        assert_eq!(preferred[0].node_id, nodes[1].node_id);
+
+        let all = reputation.prefer(nodes, 2);
+        assert_eq!(all[1].node_id, NodeID::new([2; 20]));
+    }
+}
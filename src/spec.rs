@@ -0,0 +1,676 @@
+//! Conformance test vectors of the wire protocol
+//!
+//! Vectors are fixed inputs with expected bytes and hashes, so other implementations
+//! _(Python original, WASM build)_ can check that they speak the same protocol.
+//! [`vectors_json`] exports all vectors in one JSON document, [`verify_packed`] checks
+//! a message packed by other implementation and [`verify_all`] checks this build.
+//! Expected bytes are fixtures written out in full, they are never made by the code
+//! which they check.
+
+use serde_json::{Value, json};
+
+use crate::dht::node::NodeID;
+use crate::network::consts::*;
+use crate::network::gossip::GossipMessage;
+use crate::network::peer_stats::PeerSoftware;
+use crate::network::protocol::ProtocolMessage;
+use crate::utils::crypto::{hash_key, node_id_from_public_key_der};
+use crate::utils::serialization::{WireFormat, codec, from_msgpack, to_msgpack};
+
+/// Version of the vectors, it grows with every change of the wire format
+pub const SPEC_VERSION: u32 = 5;
+
+/// Message id of all message vectors
+pub const VECTOR_MSG_ID: [u8; 16] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+];
+/// Sender of all message vectors
+pub const VECTOR_NODE_ID: [u8; 20] = [0x11; 20];
+/// Time of sending of all message vectors
+pub const VECTOR_TIMESTAMP: f64 = 1_700_000_000.5;
+
+/// Sender in payloads, as node IDs are written in JSON
+const VECTOR_NODE_HEX: &str = "1111111111111111111111111111111111111111";
+/// Other node in payloads
+const PEER_ID_HEX: &str = "2222222222222222222222222222222222222222";
+/// Key in payloads _(SHA-256 of `global:threads`)_
+const KEY_HEX: &str = "7d47d9ce1cc28a86cc45d2f064e9fb1116b6cbb9ced1393d972284731dfa4be0";
+/// Value in payloads
+const VALUE: &[u8] = b"hi";
+/// SHA-256 of the value in payloads
+const VALUE_HASH_HEX: &str = "8f434346648f6b96df89dda901c5176b10a6d83961dd3c1ac88b59b2dc327aa4";
+/// Gossip message in payloads
+const GOSSIP_ID: &str = "c3f5e9e3d6dd71c65995faf8eed24d90";
+/// Signature of popularity items in payloads
+const ATTESTATION: &str = "5e1f0a7c9b3d2e4f";
+/// DER public key of the sender in payloads
+const PUBLIC_KEY: &str = "0102030405";
+
+/// Protocol message with its expected MessagePack encoding
+#[derive(Debug, Clone)]
+pub struct MessageVector {
+    pub name: &'static str,
+    pub msg_type: u8,
+    pub payload: Value,
+    /// Hex of the message packed with `to_msgpack`
+    pub packed_hex: &'static str,
+}
+
+impl MessageVector {
+    pub fn message(&self) -> ProtocolMessage {
+        ProtocolMessage {
+            msg_type: self.msg_type,
+            id: VECTOR_MSG_ID,
+            node_id: VECTOR_NODE_ID,
+            payload: self.payload.clone(),
+            timestamp: VECTOR_TIMESTAMP,
+        }
+    }
+}
+
+/// Message of a [`MessageVector`] in other wire format
+#[derive(Debug, Clone)]
+pub struct CodecVector {
+    /// Name of the message vector
+    pub message: &'static str,
+    pub format: WireFormat,
+    /// Hex of the message encoded by the codec of the format
+    pub encoded_hex: &'static str,
+}
+
+/// SHA-256 of the text, used for DHT keys
+#[derive(Debug, Clone)]
+pub struct HashVector {
+    pub name: &'static str,
+    pub input: &'static str,
+    pub sha256_hex: &'static str,
+}
+
+/// Node ID derived from data
+#[derive(Debug, Clone)]
+pub struct NodeIdVector {
+    pub name: &'static str,
+    /// `key` - ID of the data key, `public_key` - ID of the node with DER public key
+    pub source: &'static str,
+    pub input_hex: &'static str,
+    pub node_id_hex: &'static str,
+}
+
+/// XOR distance between node IDs
+#[derive(Debug, Clone)]
+pub struct DistanceVector {
+    pub a_hex: &'static str,
+    pub b_hex: &'static str,
+    pub distance_hex: &'static str,
+}
+
+/// Difference between the vector and the checked implementation
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecMismatch {
+    pub vector: String,
+    pub reason: String,
+}
+
+impl SpecMismatch {
+    fn new(vector: &str, reason: impl Into<String>) -> Self {
+        Self {
+            vector: vector.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+fn key_bytes() -> Vec<u8> {
+    hex::decode(KEY_HEX).unwrap_or_default()
+}
+
+fn gossip_message() -> Value {
+    json!({
+        "id": GOSSIP_ID,
+        "topic": "seed_announcement",
+        "origin": VECTOR_NODE_ID,
+        "data": {"address": "10.0.0.1:8468"},
+        "ttl": 3,
+        "created_at": VECTOR_TIMESTAMP
+    })
+}
+
+/// Software announced in PING and PONG, fixed so vectors do not follow the crate version
+fn software() -> Value {
+    json!({
+        "version": "0.1.0",
+        "protocol": 2,
+        "min_protocol": 1,
+        "features": ["popularity", "signatures"]
+    })
+}
+
+fn popularity_item() -> Value {
+    json!({
+        "key": KEY_HEX,
+        "score": 0.5,
+        "metrics": {"replication_count": 3, "request_count": 10}
+    })
+}
+
+/// One vector for every message type
+pub fn message_vectors() -> Vec<MessageVector> {
+    let key = key_bytes();
+    let node = json!({"node_id": PEER_ID_HEX, "address": "10.0.0.2", "port": 8468});
+    let vector = |name, msg_type, payload, packed_hex| MessageVector {
+        name,
+        msg_type,
+        payload,
+        packed_hex,
+    };
+
+    vec![
+        vector(
+            "ping",
+            MSG_PING,
+            json!({
+                "node_id": VECTOR_NODE_HEX,
+                "codecs": ["msgpack", "cbor"],
+                "software": software()
+            }),
+            "9501dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111183a6636f6465637392a76d73677061636ba463626f72a76e6f64655f6964d92831313131313131313131313131313131313131313131313131313131313131313131313131313131a8736f66747761726584a8666561747572657392aa706f70756c6172697479aa7369676e617475726573ac6d696e5f70726f746f636f6c01a870726f746f636f6c02a776657273696f6ea5302e312e30cb41d954fc40200000",
+        ),
+        vector(
+            "pong",
+            MSG_PONG,
+            json!({
+                "node_id": VECTOR_NODE_HEX,
+                "address": "10.0.0.1:8468",
+                "codecs": ["msgpack", "cbor"],
+                "software": software()
+            }),
+            "9502dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111184a761646472657373ad31302e302e302e313a38343638a6636f6465637392a76d73677061636ba463626f72a76e6f64655f6964d92831313131313131313131313131313131313131313131313131313131313131313131313131313131a8736f66747761726584a8666561747572657392aa706f70756c6172697479aa7369676e617475726573ac6d696e5f70726f746f636f6c01a870726f746f636f6c02a776657273696f6ea5302e312e30cb41d954fc40200000",
+        ),
+        vector(
+            "find_node",
+            MSG_FIND_NODE,
+            json!({"target_id": PEER_ID_HEX}),
+            "9503dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111181a97461726765745f6964d92832323232323232323232323232323232323232323232323232323232323232323232323232323232cb41d954fc40200000",
+        ),
+        vector(
+            "find_node_response",
+            MSG_FIND_NODE_RESPONSE,
+            json!({"nodes": [node]}),
+            "9504dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111181a56e6f6465739183a761646472657373a831302e302e302e32a76e6f64655f6964d92832323232323232323232323232323232323232323232323232323232323232323232323232323232a4706f7274cd2114cb41d954fc40200000",
+        ),
+        vector(
+            "find_value",
+            MSG_FIND_VALUE,
+            json!({"key": key}),
+            "9505dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111181a36b6579dc00207d47ccd9ccce1cccc2cc8acc86cccc45ccd2ccf064cce9ccfb1116ccb6cccbccb9ccceccd1393dcc9722cc84731dccfa4bcce0cb41d954fc40200000",
+        ),
+        vector(
+            "find_value_response",
+            MSG_FIND_VALUE_RESPONSE,
+            json!({
+                "found": true,
+                "value": VALUE,
+                "stored_at": VECTOR_TIMESTAMP,
+                "expires_at": VECTOR_TIMESTAMP + 86400.0,
+                "publisher": VECTOR_NODE_ID
+            }),
+            "9506dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111185aa657870697265735f6174cb41d95550a0200000a5666f756e64c3a97075626c6973686572dc00141111111111111111111111111111111111111111a973746f7265645f6174cb41d954fc40200000a576616c7565926869cb41d954fc40200000",
+        ),
+        vector(
+            "find_value_response_nodes",
+            MSG_FIND_VALUE_RESPONSE,
+            json!({"found": false, "nodes": [node]}),
+            "9506dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111182a5666f756e64c2a56e6f6465739183a761646472657373a831302e302e302e32a76e6f64655f6964d92832323232323232323232323232323232323232323232323232323232323232323232323232323232a4706f7274cd2114cb41d954fc40200000",
+        ),
+        vector(
+            "store",
+            MSG_STORE,
+            json!({
+                "key": key,
+                "value": VALUE,
+                "ttl": 86400,
+                "publisher": VECTOR_NODE_ID,
+                "published_at": VECTOR_TIMESTAMP,
+                "hops": 0,
+                "record_type": "message"
+            }),
+            "9507dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111187a4686f707300a36b6579dc00207d47ccd9ccce1cccc2cc8acc86cccc45ccd2ccf064cce9ccfb1116ccb6cccbccb9ccceccd1393dcc9722cc84731dccfa4bcce0ac7075626c69736865645f6174cb41d954fc40200000a97075626c6973686572dc00141111111111111111111111111111111111111111ab7265636f72645f74797065a76d657373616765a374746cce00015180a576616c7565926869cb41d954fc40200000",
+        ),
+        vector(
+            "store_response",
+            MSG_STORE_RESPONSE,
+            json!({"success": true, "receipt": null}),
+            "9508dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111182a772656365697074c0a773756363657373c3cb41d954fc40200000",
+        ),
+        vector(
+            "popularity_exchange",
+            MSG_POPULARITY_EXCHANGE,
+            json!({
+                "node_id": VECTOR_NODE_HEX,
+                "node_type": "peer",
+                "items": [popularity_item()],
+                "attestation": ATTESTATION,
+                "public_key": PUBLIC_KEY
+            }),
+            "9509dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111185ab6174746573746174696f6eb035653166306137633962336432653466a56974656d739183a36b6579d94037643437643963653163633238613836636334356432663036346539666231313136623663626239636564313339336439373232383437333164666134626530a76d65747269637382b17265706c69636174696f6e5f636f756e7403ad726571756573745f636f756e740aa573636f7265cb3fe0000000000000a76e6f64655f6964d92831313131313131313131313131313131313131313131313131313131313131313131313131313131a96e6f64655f74797065a470656572aa7075626c69635f6b6579aa30313032303330343035cb41d954fc40200000",
+        ),
+        vector(
+            "popularity_exchange_response",
+            MSG_POPULARITY_EXCHANGE_RESPONSE,
+            json!({"node_id": VECTOR_NODE_HEX, "node_type": "seed", "items": []}),
+            "950adc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111183a56974656d7390a76e6f64655f6964d92831313131313131313131313131313131313131313131313131313131313131313131313131313131a96e6f64655f74797065a473656564cb41d954fc40200000",
+        ),
+        vector(
+            "global_ranking_request",
+            MSG_GLOBAL_RANKING_REQUEST,
+            json!({}),
+            "950bdc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111180cb41d954fc40200000",
+        ),
+        vector(
+            "global_ranking_response",
+            MSG_GLOBAL_RANKING_RESPONSE,
+            json!({"ranking": [popularity_item()], "trending": []}),
+            "950cdc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111182a772616e6b696e679183a36b6579d94037643437643963653163633238613836636334356432663036346539666231313136623663626239636564313339336439373232383437333164666134626530a76d65747269637382b17265706c69636174696f6e5f636f756e7403ad726571756573745f636f756e740aa573636f7265cb3fe0000000000000a87472656e64696e6790cb41d954fc40200000",
+        ),
+        vector(
+            "gossip_push",
+            MSG_GOSSIP_PUSH,
+            json!({"messages": [gossip_message()]}),
+            "950ddc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111181a86d657373616765739186aa637265617465645f6174cb41d954fc40200000a46461746181a761646472657373ad31302e302e302e313a38343638a26964d9206333663565396533643664643731633635393935666166386565643234643930a66f726967696edc00141111111111111111111111111111111111111111a5746f706963b1736565645f616e6e6f756e63656d656e74a374746c03cb41d954fc40200000",
+        ),
+        vector(
+            "gossip_digest",
+            MSG_GOSSIP_DIGEST,
+            json!({"digest": [GOSSIP_ID]}),
+            "950edc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111181a664696765737491d9206333663565396533643664643731633635393935666166386565643234643930cb41d954fc40200000",
+        ),
+        vector(
+            "gossip_digest_response",
+            MSG_GOSSIP_DIGEST_RESPONSE,
+            json!({"messages": [gossip_message()], "want": []}),
+            "950fdc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111182a86d657373616765739186aa637265617465645f6174cb41d954fc40200000a46461746181a761646472657373ad31302e302e302e313a38343638a26964d9206333663565396533643664643731633635393935666166386565643234643930a66f726967696edc00141111111111111111111111111111111111111111a5746f706963b1736565645f616e6e6f756e63656d656e74a374746c03a477616e7490cb41d954fc40200000",
+        ),
+        vector(
+            "list_records",
+            MSG_LIST_RECORDS,
+            json!({"record_type": "thread", "since": 0.0, "limit": 50}),
+            "9510dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111183a56c696d697432ab7265636f72645f74797065a6746872656164a573696e6365cb0000000000000000cb41d954fc40200000",
+        ),
+        vector(
+            "list_records_response",
+            MSG_LIST_RECORDS_RESPONSE,
+            json!({"records": [{"key": key, "value": VALUE, "published_at": VECTOR_TIMESTAMP}]}),
+            "9511dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111181a77265636f7264739183a36b6579dc00207d47ccd9ccce1cccc2cc8acc86cccc45ccd2ccf064cce9ccfb1116ccb6cccbccb9ccceccd1393dcc9722cc84731dccfa4bcce0ac7075626c69736865645f6174cb41d954fc40200000a576616c7565926869cb41d954fc40200000",
+        ),
+        vector(
+            "find_values",
+            MSG_FIND_VALUES,
+            json!({"keys": [key]}),
+            "9512dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111181a46b65797391dc00207d47ccd9ccce1cccc2cc8acc86cccc45ccd2ccf064cce9ccfb1116ccb6cccbccb9ccceccd1393dcc9722cc84731dccfa4bcce0cb41d954fc40200000",
+        ),
+        vector(
+            "find_values_response",
+            MSG_FIND_VALUES_RESPONSE,
+            json!({
+                "values": [{"key": key, "value": VALUE}],
+                "missing": [],
+                "remaining": []
+            }),
+            "9513dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111183a76d697373696e6790a972656d61696e696e6790a676616c7565739182a36b6579dc00207d47ccd9ccce1cccc2cc8acc86cccc45ccd2ccf064cce9ccfb1116ccb6cccbccb9ccceccd1393dcc9722cc84731dccfa4bcce0a576616c7565926869cb41d954fc40200000",
+        ),
+        vector(
+            "store_cas",
+            MSG_STORE_CAS,
+            json!({
+                "key": key,
+                "value": VALUE,
+                "ttl": 86400,
+                "expected": VALUE_HASH_HEX,
+                "publisher": VECTOR_NODE_ID,
+                "published_at": VECTOR_TIMESTAMP,
+                "hops": 0,
+                "record_type": "thread"
+            }),
+            "9514dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111188a86578706563746564d94038663433343334363634386636623936646638396464613930316335313736623130613664383339363164643363316163383862353962326463333237616134a4686f707300a36b6579dc00207d47ccd9ccce1cccc2cc8acc86cccc45ccd2ccf064cce9ccfb1116ccb6cccbccb9ccceccd1393dcc9722cc84731dccfa4bcce0ac7075626c69736865645f6174cb41d954fc40200000a97075626c6973686572dc00141111111111111111111111111111111111111111ab7265636f72645f74797065a6746872656164a374746cce00015180a576616c7565926869cb41d954fc40200000",
+        ),
+        vector(
+            "store_cas_response",
+            MSG_STORE_CAS_RESPONSE,
+            json!({"success": false, "current": VALUE}),
+            "9515dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111182a763757272656e74926869a773756363657373c2cb41d954fc40200000",
+        ),
+        vector(
+            "puzzle",
+            MSG_PUZZLE,
+            json!({"challenge": hex::encode([0x33u8; 16]), "issued_at": VECTOR_TIMESTAMP, "difficulty": 8}),
+            "9516dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111183a96368616c6c656e6765d9203333333333333333333333333333333333333333333333333333333333333333aa646966666963756c747908a96973737565645f6174cb41d954fc40200000cb41d954fc40200000",
+        ),
+        vector(
+            "puzzle_solution",
+            MSG_PUZZLE_SOLUTION,
+            json!({"challenge": hex::encode([0x33u8; 16]), "issued_at": VECTOR_TIMESTAMP, "nonce": 12345}),
+            "9517dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111183a96368616c6c656e6765d9203333333333333333333333333333333333333333333333333333333333333333a96973737565645f6174cb41d954fc40200000a56e6f6e6365cd3039cb41d954fc40200000",
+        ),
+        vector(
+            "delete",
+            MSG_DELETE,
+            json!({
+                "key": key,
+                "ttl": 604800,
+                "publisher": VECTOR_NODE_ID,
+                "deleted_at": VECTOR_TIMESTAMP,
+                "hops": 0
+            }),
+            "9518dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111185aa64656c657465645f6174cb41d954fc40200000a4686f707300a36b6579dc00207d47ccd9ccce1cccc2cc8acc86cccc45ccd2ccf064cce9ccfb1116ccb6cccbccb9ccceccd1393dcc9722cc84731dccfa4bcce0a97075626c6973686572dc00141111111111111111111111111111111111111111a374746cce00093a80cb41d954fc40200000",
+        ),
+        vector(
+            "delete_response",
+            MSG_DELETE_RESPONSE,
+            json!({"success": true}),
+            "9519dc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111181a773756363657373c3cb41d954fc40200000",
+        ),
+        vector(
+            "direct",
+            MSG_DIRECT,
+            json!({"envelope": {"id": GOSSIP_ID, "sent_at": 1_700_000_000, "sealed": VALUE}}),
+            "951adc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111181a8656e76656c6f706583a26964d9206333663565396533643664643731633635393935666166386565643234643930a67365616c6564926869a773656e745f6174ce6553f100cb41d954fc40200000",
+        ),
+        vector(
+            "direct_response",
+            MSG_DIRECT_RESPONSE,
+            json!({"delivered": true, "signature": VALUE}),
+            "951bdc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111182a964656c697665726564c3a97369676e6174757265926869cb41d954fc40200000",
+        ),
+        vector(
+            "store_fragment",
+            MSG_STORE_FRAGMENT,
+            json!({"transfer": GOSSIP_ID, "size": 2, "index": 0, "data": VALUE}),
+            "951cdc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111184a464617461926869a5696e64657800a473697a6502a87472616e73666572d9206333663565396533643664643731633635393935666166386565643234643930cb41d954fc40200000",
+        ),
+        vector(
+            "store_fragments_missing",
+            MSG_STORE_FRAGMENTS_MISSING,
+            json!({"transfer": GOSSIP_ID, "missing": [0, 2]}),
+            "951ddc0010000102030405060708090a0b0c0d0e0fdc0014111111111111111111111111111111111111111182a76d697373696e67920002a87472616e73666572d9206333663565396533643664643731633635393935666166386565643234643930cb41d954fc40200000",
+        ),
+    ]
+}
+
+/// Message vectors in CBOR and protobuf
+///
+/// Protobuf vector is checked only by builds with the `protobuf` feature.
+pub fn codec_vectors() -> Vec<CodecVector> {
+    vec![
+        CodecVector {
+            message: "store_response",
+            format: WireFormat::Cbor,
+            encoded_hex: "a564747970650862696490000102030405060708090a0b0c0d0e0f676e6f64655f6964941111111111111111111111111111111111111111677061796c6f6164a26772656365697074f66773756363657373f56974696d657374616d70fb41d954fc40200000",
+        },
+        CodecVector {
+            message: "store_response",
+            format: WireFormat::Protobuf,
+            encoded_hex: "08081210000102030405060708090a0b0c0d0e0f1a141111111111111111111111111111111111111111221f7b2272656365697074223a6e756c6c2c2273756363657373223a747275657d2900002040fc54d941",
+        },
+    ]
+}
+
+/// Hashes of DHT keys
+pub fn hash_vectors() -> Vec<HashVector> {
+    vec![
+        HashVector {
+            name: "empty",
+            input: "",
+            sha256_hex: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        },
+        HashVector {
+            name: "global_threads",
+            input: "global:threads",
+            sha256_hex: "7d47d9ce1cc28a86cc45d2f064e9fb1116b6cbb9ced1393d972284731dfa4be0",
+        },
+        HashVector {
+            name: "thread_meta",
+            input: "thread:t1:meta",
+            sha256_hex: "41250d8041807062a9521846e9cac941b66241a18cd0be839aedbf2407990d82",
+        },
+        HashVector {
+            name: "message",
+            input: "msg:0011223344556677",
+            sha256_hex: "694fc3fe7ecec59d90227c306674361c22c7111f3ee2b4bcbb4fbd868a7ba29c",
+        },
+    ]
+}
+
+/// Node IDs of data keys and public keys
+pub fn node_id_vectors() -> Vec<NodeIdVector> {
+    vec![
+        NodeIdVector {
+            name: "key_sha256",
+            source: "key",
+            input_hex: KEY_HEX,
+            node_id_hex: "7d47d9ce1cc28a86cc45d2f064e9fb1116b6cbb9",
+        },
+        NodeIdVector {
+            name: "key_short",
+            source: "key",
+            input_hex: "abcd",
+            node_id_hex: "abcd000000000000000000000000000000000000",
+        },
+        NodeIdVector {
+            name: "public_key",
+            source: "public_key",
+            input_hex: "0102030405",
+            node_id_hex: "11966ab9c099f8fabefac54c08d5be2bd8c903af",
+        },
+    ]
+}
+
+/// XOR distances
+pub fn distance_vectors() -> Vec<DistanceVector> {
+    vec![DistanceVector {
+        a_hex: "1111111111111111111111111111111111111111",
+        b_hex: "2222222222222222222222222222222222222222",
+        distance_hex: "3333333333333333333333333333333333333333",
+    }]
+}
+
+/// All vectors in one JSON document
+pub fn vectors_json() -> Value {
+    let messages: Vec<Value> = message_vectors()
+        .iter()
+        .map(|v| {
+            json!({
+                "name": v.name,
+                "type": v.msg_type,
+                "id": hex::encode(VECTOR_MSG_ID),
+                "node_id": hex::encode(VECTOR_NODE_ID),
+                "timestamp": VECTOR_TIMESTAMP,
+                "payload": v.payload,
+                "packed": v.packed_hex
+            })
+        })
+        .collect();
+    let codecs: Vec<Value> = codec_vectors()
+        .iter()
+        .map(|v| json!({"message": v.message, "format": v.format, "encoded": v.encoded_hex}))
+        .collect();
+    let hashes: Vec<Value> = hash_vectors()
+        .iter()
+        .map(|v| json!({"name": v.name, "input": v.input, "sha256": v.sha256_hex}))
+        .collect();
+    let node_ids: Vec<Value> = node_id_vectors()
+        .iter()
+        .map(|v| {
+            json!({
+                "name": v.name,
+                "source": v.source,
+                "input": v.input_hex,
+                "node_id": v.node_id_hex
+            })
+        })
+        .collect();
+    let distances: Vec<Value> = distance_vectors()
+        .iter()
+        .map(|v| json!({"a": v.a_hex, "b": v.b_hex, "distance": v.distance_hex}))
+        .collect();
+
+    json!({
+        "version": SPEC_VERSION,
+        "messages": messages,
+        "codecs": codecs,
+        "hashes": hashes,
+        "node_ids": node_ids,
+        "distances": distances
+    })
+}
+
+/// Check message packed by other implementation
+///
+/// Message is compared after decoding, so order of payload fields does not matter.
+pub fn verify_packed(vector: &MessageVector, packed: &[u8]) -> Result<(), SpecMismatch> {
+    let message: ProtocolMessage = from_msgpack(packed)
+        .map_err(|e| SpecMismatch::new(vector.name, format!("decode failed: {}", e)))?;
+
+    let expected = vector.message();
+    if message.msg_type != expected.msg_type {
+        return Err(SpecMismatch::new(vector.name, "message type differs"));
+    }
+    if message.id != expected.id || message.node_id != expected.node_id {
+        return Err(SpecMismatch::new(
+            vector.name,
+            "message id or node id differs",
+        ));
+    }
+    if message.timestamp != expected.timestamp {
+        return Err(SpecMismatch::new(vector.name, "timestamp differs"));
+    }
+    if message.payload != expected.payload {
+        return Err(SpecMismatch::new(vector.name, "payload differs"));
+    }
+    Ok(())
+}
+
+/// Check this build against all vectors
+///
+/// Return found mismatches _(empty if build conforms)_
+pub fn verify_all() -> Vec<SpecMismatch> {
+    let mut mismatches = Vec::new();
+
+    for vector in message_vectors() {
+        match to_msgpack(&vector.message()) {
+            Ok(packed) if hex::encode(&packed) == vector.packed_hex => {}
+            Ok(_) => mismatches.push(SpecMismatch::new(vector.name, "packed bytes differ")),
+            Err(e) => mismatches.push(SpecMismatch::new(vector.name, e.to_string())),
+        }
+        if let Ok(packed) = hex::decode(vector.packed_hex)
+            && let Err(mismatch) = verify_packed(&vector, &packed)
+        {
+            mismatches.push(mismatch);
+        }
+    }
+
+    let messages = message_vectors();
+    for vector in codec_vectors() {
+        let name = format!("{}_{}", vector.message, vector.format);
+        let (Some(codec), Some(message)) = (
+            codec(vector.format),
+            messages.iter().find(|m| m.name == vector.message),
+        ) else {
+            continue;
+        };
+        match codec.encode(&message.message()) {
+            Ok(encoded) if hex::encode(&encoded) == vector.encoded_hex => {}
+            Ok(_) => mismatches.push(SpecMismatch::new(&name, "encoded bytes differ")),
+            Err(e) => mismatches.push(SpecMismatch::new(&name, e.to_string())),
+        }
+        let decoded = hex::decode(vector.encoded_hex)
+            .ok()
+            .and_then(|encoded| codec.decode(&encoded).ok());
+        let expected = message.message();
+        if !decoded.is_some_and(|m| {
+            m.msg_type == expected.msg_type
+                && m.id == expected.id
+                && m.node_id == expected.node_id
+                && m.payload == expected.payload
+                && m.timestamp == expected.timestamp
+        }) {
+            mismatches.push(SpecMismatch::new(&name, "decoded message differs"));
+        }
+    }
+
+    for vector in hash_vectors() {
+        if hex::encode(hash_key(vector.input.as_bytes())) != vector.sha256_hex {
+            mismatches.push(SpecMismatch::new(vector.name, "hash differs"));
+        }
+    }
+
+    for vector in node_id_vectors() {
+        let input = hex::decode(vector.input_hex).unwrap_or_default();
+        let node_id = match vector.source {
+            "public_key" => NodeID::new(node_id_from_public_key_der(&input)),
+            _ => NodeID::from_key(&input),
+        };
+        if node_id.to_string() != vector.node_id_hex {
+            mismatches.push(SpecMismatch::new(vector.name, "node ID differs"));
+        }
+    }
+
+    for vector in distance_vectors() {
+        let distance = match (
+            vector.a_hex.parse::<NodeID>(),
+            vector.b_hex.parse::<NodeID>(),
+        ) {
+            (Ok(a), Ok(b)) => hex::encode(a.distance_to(&b)),
+            _ => String::new(),
+        };
+        if distance != vector.distance_hex {
+            mismatches.push(SpecMismatch::new("distance", "XOR distance differs"));
+        }
+    }
+
+    let announced = message_vectors()
+        .into_iter()
+        .find(|v| v.name == "ping")
+        .and_then(|v| from_msgpack::<ProtocolMessage>(&hex::decode(v.packed_hex).ok()?).ok())
+        .and_then(|m| PeerSoftware::from_payload(&m.payload));
+    let expected_software = PeerSoftware {
+        version: "0.1.0".to_string(),
+        protocol: 2,
+        min_protocol: 1,
+        features: vec!["popularity".to_string(), "signatures".to_string()],
+    };
+    if announced != Some(expected_software) {
+        mismatches.push(SpecMismatch::new("ping", "announced software differs"));
+    }
+
+    let gossip_valid =
+        serde_json::from_value::<GossipMessage>(gossip_message()).is_ok_and(|m| m.is_valid());
+    if !gossip_valid {
+        mismatches.push(SpecMismatch::new("gossip_message", "gossip id differs"));
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_conforms_to_vectors() {
+        assert_eq!(verify_all(), Vec::new());
+    }
+
+    #[test]
+    fn changed_fixture_is_reported() {
+        let vector = message_vectors()
+            .into_iter()
+            .find(|v| v.name == "store_response")
+            .unwrap();
+        // `success: true` becomes `success: false`
+        let changed = vector
+            .packed_hex
+            .replace("a773756363657373c3", "a773756363657373c2");
+        let mismatch = verify_packed(&vector, &hex::decode(changed).unwrap()).unwrap_err();
+        assert_eq!(mismatch.reason, "payload differs");
+    }
+}
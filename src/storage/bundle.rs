@@ -0,0 +1,234 @@
+use serde::de::{Deserializer, IgnoredAny, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::storage::compression::{compress, decompress};
+use crate::storage::proof::PublisherProof;
+use crate::utils::serialization::{from_msgpack, to_msgpack};
+
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::node_id_from_public_key_der;
+
+/// Max size in bytes of unpacked bundle, messages over it are left out
+pub const MAX_THREAD_BUNDLE_SIZE: usize = 256 * 1024;
+/// Encoding overhead of the bundle and of one record over their bytes, rounded up
+const BUNDLE_OVERHEAD: usize = 128;
+const RECORD_OVERHEAD: usize = 64;
+
+/// Record in the bundle with the proof of its original publisher
+///
+/// Node which made the bundle can not change records, the reader checks every proof
+/// against the key of the record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundledRecord {
+    pub value: Vec<u8>,
+    pub proof: PublisherProof,
+}
+
+impl BundledRecord {
+    /// Bytes of the encoded record in the bundle, at most
+    fn size(&self) -> usize {
+        self.value.len()
+            + self.proof.public_key.len()
+            + self.proof.signature.len()
+            + RECORD_OVERHEAD
+    }
+
+    /// Record is signed by its publisher for the key
+    #[cfg(feature = "signatures")]
+    pub fn is_signed_for(&self, key: &[u8], record_type: &str) -> bool {
+        let publisher = node_id_from_public_key_der(&self.proof.public_key);
+        self.proof
+            .verify(&publisher, key, Some(&self.value), record_type)
+    }
+
+    /// Node without `signatures` can not check proofs, it reads records by their keys
+    #[cfg(not(feature = "signatures"))]
+    pub fn is_signed_for(&self, _key: &[u8], _record_type: &str) -> bool {
+        false
+    }
+}
+
+/// Thread metadata with its recent messages replicated as one record
+///
+/// Popular thread is read as a whole, so replicating it key by key costs a STORE and a
+/// lookup for every tiny message. Records are kept as stored with proofs of their
+/// publishers, the reader checks them and decodes records like ones found by their own
+/// keys.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicatedThread {
+    pub thread_id: String,
+    /// Record of thread metadata
+    pub meta: BundledRecord,
+    /// Records of messages, the newest first
+    pub messages: Vec<BundledRecord>,
+    /// Time when the bundle was made
+    pub bundled_at: f64,
+}
+
+/// Stored form of the bundle
+#[derive(Serialize, Deserialize)]
+struct PackedBundle {
+    /// Size of the encoded bundle
+    size: usize,
+    compressed: bool,
+    data: Vec<u8>,
+}
+
+impl ReplicatedThread {
+    /// Bundle of the thread with as many messages as fit into `max_size` bytes
+    ///
+    /// `max_size` is capped by [`MAX_THREAD_BUNDLE_SIZE`].
+    pub fn new(
+        thread_id: String,
+        meta: BundledRecord,
+        messages: Vec<BundledRecord>,
+        bundled_at: f64,
+        max_size: usize,
+    ) -> Self {
+        let max_size = max_size.min(MAX_THREAD_BUNDLE_SIZE);
+        let mut size = BUNDLE_OVERHEAD + thread_id.len() + meta.size();
+        let messages = messages
+            .into_iter()
+            .take_while(|message| {
+                size += message.size();
+                size <= max_size
+            })
+            .collect();
+        Self {
+            thread_id,
+            meta,
+            messages,
+            bundled_at,
+        }
+    }
+
+    /// Encode the bundle, it is zstd compressed if the node is built with `compression`
+    pub fn pack(&self, level: i32) -> Option<Vec<u8>> {
+        let data = to_msgpack(self).ok()?;
+        let packed = match compress(&data, Some(0), level) {
+            Some(compressed) => PackedBundle {
+                size: data.len(),
+                compressed: true,
+                data: compressed,
+            },
+            None => PackedBundle {
+                size: data.len(),
+                compressed: false,
+                data,
+            },
+        };
+        to_msgpack(&packed).ok()
+    }
+
+    /// Decode the bundle made by [`ReplicatedThread::pack`]
+    ///
+    /// Return `None` for broken or too big bundle and for compressed bundle if the node
+    /// is built without `compression`, the reader falls back to single keys then.
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        let packed: PackedBundle = from_msgpack(data).ok()?;
+        if packed.size > MAX_THREAD_BUNDLE_SIZE {
+            return None;
+        }
+        if packed.compressed {
+            from_msgpack(&decompress(&packed.data, packed.size)?).ok()
+        } else {
+            from_msgpack(&packed.data).ok()
+        }
+    }
+}
+
+/// First two fields of a msgpack record, both must be strings
+///
+/// Records are encoded as arrays of fields, thread metadata starts with its ID and
+/// message with its ID and thread ID. Node reads them without knowing the whole record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordHead {
+    pub first: String,
+    pub second: Option<String>,
+}
+
+impl RecordHead {
+    /// Head of the record _(None if record is not an array starting with a string)_
+    pub fn read(data: &[u8]) -> Option<Self> {
+        from_msgpack(data).ok()
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordHead {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct HeadVisitor;
+
+        impl<'de> Visitor<'de> for HeadVisitor {
+            type Value = RecordHead;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("array of record fields")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<RecordHead, A::Error> {
+                let first = seq
+                    .next_element::<String>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+                let second = seq.next_element::<String>()?;
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(RecordHead { first, second })
+            }
+        }
+
+        deserializer.deserialize_seq(HeadVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(value: Vec<u8>) -> BundledRecord {
+        BundledRecord {
+            value,
+            proof: PublisherProof {
+                public_key: vec![1; 294],
+                signed_at: 0.0,
+                signature: vec![2; 256],
+            },
+        }
+    }
+
+    #[test]
+    fn bundle_fits_its_size_limit() {
+        let messages = (0..64).map(|i| record(vec![i; 300])).collect();
+        let bundle =
+            ReplicatedThread::new("thread".into(), record(vec![7; 200]), messages, 0.0, 8192);
+
+        assert!(!bundle.messages.is_empty());
+        assert!(bundle.messages.len() < 64);
+        let packed = to_msgpack(&PackedBundle {
+            size: 0,
+            compressed: false,
+            data: to_msgpack(&bundle).unwrap(),
+        })
+        .unwrap();
+        assert!(packed.len() <= 8192);
+    }
+
+    #[cfg(feature = "signatures")]
+    #[test]
+    fn record_is_signed_only_for_its_key() {
+        use crate::utils::crypto::{NodeSigner, generate_keypair, node_id_from_public_key};
+
+        let (private_key, public_key) = generate_keypair();
+        let signer = NodeSigner::new(private_key, node_id_from_public_key(&public_key).to_vec());
+        let proof = PublisherProof::issue(&signer, b"key", Some(b"message"), 10.0, "message");
+        let mut record = BundledRecord {
+            value: b"message".to_vec(),
+            proof,
+        };
+
+        assert!(record.is_signed_for(b"key", "message"));
+        assert!(!record.is_signed_for(b"other", "message"));
+        assert!(!record.is_signed_for(b"key", "thread"));
+        record.value = b"forged".to_vec();
+        assert!(!record.is_signed_for(b"key", "message"));
+    }
+}
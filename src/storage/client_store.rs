@@ -0,0 +1,365 @@
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use heed::types::Bytes;
+use heed::{Database, Env, EnvOpenOptions, RwTxn};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use crate::exceptions::StorageError;
+use crate::utils::serialization::{from_msgpack, to_msgpack};
+use crate::utils::time::get_now_f64;
+
+/// Old entries are evicted after this count of saves
+const EVICT_EVERY: usize = 256;
+
+/// Saved value with the time of its last sync with the network
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    value: Vec<u8>,
+    synced_at: f64,
+    /// Thread of the saved message _(empty for threads)_
+    #[serde(default)]
+    thread_id: String,
+}
+
+/// Persistent store of threads and messages seen by the API client
+///
+/// Unlike the node storage it keeps only what the app has shown to the user, so after
+/// restart the app can render them at once and ask the network only for changes since
+/// `synced_at` of every thread. Messages never change, they are not asked again at all.
+///
+/// Entries not synced for `max_age` seconds are evicted, and of more than `max_entries`
+/// threads or messages the least recently synced ones. Eviction runs on open and after
+/// every [`EVICT_EVERY`] saves.
+pub struct ClientStore {
+    env: Env,
+    threads: Database<Bytes, Bytes>,
+    messages: Database<Bytes, Bytes>,
+    /// `thread_id \0 message_id` -> empty, messages of one thread are found by prefix
+    thread_messages: Database<Bytes, Bytes>,
+    max_entries: usize,
+    max_age: f64,
+    /// Saves since the last eviction
+    saves: AtomicUsize,
+}
+
+impl ClientStore {
+    /// Open the store in `dir`, its size is limited by `max_size` bytes
+    pub fn open(
+        dir: &Path,
+        max_size: u64,
+        max_entries: usize,
+        max_age: f64,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        fs::create_dir_all(dir)?;
+        let env = unsafe {
+            EnvOpenOptions::new()
+                .map_size(max_size as usize)
+                .max_dbs(3)
+                .open(dir)?
+        };
+
+        let mut wtxn = env.write_txn()?;
+        let threads = env.create_database(&mut wtxn, Some("threads"))?;
+        let messages = env.create_database(&mut wtxn, Some("messages"))?;
+        let thread_messages = env.create_database(&mut wtxn, Some("thread_messages"))?;
+        let store = Self {
+            env: env.clone(),
+            threads,
+            messages,
+            thread_messages,
+            max_entries,
+            max_age,
+            saves: AtomicUsize::new(0),
+        };
+        Evictor::of(&store).evict(&mut wtxn)?;
+        wtxn.commit()?;
+        Ok(store)
+    }
+
+    /// Evictor if the save is the one which runs eviction
+    fn evictor(&self) -> Option<Evictor> {
+        let saves = self.saves.fetch_add(1, Ordering::Relaxed) + 1;
+        saves.is_multiple_of(EVICT_EVERY).then(|| Evictor::of(self))
+    }
+
+    /// Save thread fetched from the network now
+    pub async fn save_thread(&self, thread_id: &str, value: Vec<u8>) -> Result<(), StorageError> {
+        let env = self.env.clone();
+        let threads = self.threads;
+        let key = thread_id.as_bytes().to_vec();
+        let entry = to_msgpack(&Entry {
+            value,
+            synced_at: get_now_f64(),
+            thread_id: String::new(),
+        })
+        .map_err(|_| StorageError::General)?;
+        let evictor = self.evictor();
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            threads.put(&mut txn, &key, &entry)?;
+            if let Some(evictor) = evictor {
+                evictor.evict(&mut txn)?;
+            }
+            txn.commit()
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+        .map_err(map_error)
+    }
+
+    /// All saved threads with times of their last sync
+    pub async fn threads(&self) -> Result<Vec<(Vec<u8>, f64)>, StorageError> {
+        let env = self.env.clone();
+        let threads = self.threads;
+
+        task::spawn_blocking(move || {
+            let txn = env.read_txn()?;
+            let mut saved = Vec::new();
+            for item in threads.iter(&txn)? {
+                let (_, bytes) = item?;
+                if let Ok(entry) = from_msgpack::<Entry>(bytes) {
+                    saved.push((entry.value, entry.synced_at));
+                }
+            }
+            Ok(saved)
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+        .map_err(map_error)
+    }
+
+    /// Save message of the thread
+    pub async fn save_message(
+        &self,
+        thread_id: &str,
+        message_id: &str,
+        value: Vec<u8>,
+    ) -> Result<(), StorageError> {
+        let env = self.env.clone();
+        let messages = self.messages;
+        let thread_messages = self.thread_messages;
+        let key = message_id.as_bytes().to_vec();
+        let index_key = [thread_id.as_bytes(), b"\0", message_id.as_bytes()].concat();
+        let entry = to_msgpack(&Entry {
+            value,
+            synced_at: get_now_f64(),
+            thread_id: thread_id.to_string(),
+        })
+        .map_err(|_| StorageError::General)?;
+        let evictor = self.evictor();
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            messages.put(&mut txn, &key, &entry)?;
+            thread_messages.put(&mut txn, &index_key, &[])?;
+            if let Some(evictor) = evictor {
+                evictor.evict(&mut txn)?;
+            }
+            txn.commit()
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+        .map_err(map_error)
+    }
+
+    /// Remove saved message _(e.g. deleted by its author)_
+    pub async fn remove_message(&self, message_id: &str) -> Result<(), StorageError> {
+        let evictor = Evictor::of(self);
+        let key = message_id.as_bytes().to_vec();
+        task::spawn_blocking(move || evictor.remove_message(&key))
+            .await
+            .map_err(|_| StorageError::General)?
+            .map_err(map_error)
+    }
+
+    /// Saved message by id
+    pub async fn message(&self, message_id: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let env = self.env.clone();
+        let messages = self.messages;
+        let key = message_id.as_bytes().to_vec();
+
+        task::spawn_blocking(move || {
+            let txn = env.read_txn()?;
+            Ok(messages
+                .get(&txn, &key)?
+                .and_then(|bytes| from_msgpack::<Entry>(bytes).ok())
+                .map(|entry| entry.value))
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+        .map_err(map_error)
+    }
+
+    /// All saved messages of the thread
+    pub async fn thread_messages(&self, thread_id: &str) -> Result<Vec<Vec<u8>>, StorageError> {
+        let env = self.env.clone();
+        let messages = self.messages;
+        let thread_messages = self.thread_messages;
+        let prefix = [thread_id.as_bytes(), b"\0"].concat();
+
+        task::spawn_blocking(move || {
+            let txn = env.read_txn()?;
+            let mut saved = Vec::new();
+            for item in thread_messages.prefix_iter(&txn, &prefix)? {
+                let (index_key, _) = item?;
+                if let Some(entry) = messages
+                    .get(&txn, &index_key[prefix.len()..])?
+                    .and_then(|bytes| from_msgpack::<Entry>(bytes).ok())
+                {
+                    saved.push(entry.value);
+                }
+            }
+            Ok(saved)
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+        .map_err(map_error)
+    }
+
+    /// Remove all saved threads and messages
+    pub async fn clear(&self) -> Result<(), StorageError> {
+        let env = self.env.clone();
+        let databases = [self.threads, self.messages, self.thread_messages];
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            for db in databases {
+                db.clear(&mut txn)?;
+            }
+            txn.commit()
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+        .map_err(map_error)
+    }
+}
+
+/// Databases of the store which eviction runs on, sent to blocking tasks
+struct Evictor {
+    env: Env,
+    threads: Database<Bytes, Bytes>,
+    messages: Database<Bytes, Bytes>,
+    thread_messages: Database<Bytes, Bytes>,
+    max_entries: usize,
+    max_age: f64,
+}
+
+impl Evictor {
+    fn of(store: &ClientStore) -> Self {
+        Self {
+            env: store.env.clone(),
+            threads: store.threads,
+            messages: store.messages,
+            thread_messages: store.thread_messages,
+            max_entries: store.max_entries,
+            max_age: store.max_age,
+        }
+    }
+
+    /// Remove expired entries and the least recently synced ones over the limit
+    fn evict(&self, txn: &mut RwTxn) -> heed::Result<()> {
+        let expired_before = get_now_f64() - self.max_age;
+        for db in [self.threads, self.messages] {
+            let mut entries: Vec<(f64, Vec<u8>, String)> = Vec::new();
+            for item in db.iter(txn)? {
+                let (key, bytes) = item?;
+                let (synced_at, thread_id) = match from_msgpack::<Entry>(bytes) {
+                    Ok(entry) => (entry.synced_at, entry.thread_id),
+                    // Unreadable entry is evicted first
+                    Err(_) => (f64::NEG_INFINITY, String::new()),
+                };
+                entries.push((synced_at, key.to_vec(), thread_id));
+            }
+            entries.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let over_limit = entries.len().saturating_sub(self.max_entries);
+            for (index, (synced_at, key, thread_id)) in entries.into_iter().enumerate() {
+                if index >= over_limit && synced_at >= expired_before {
+                    break;
+                }
+                db.delete(txn, &key)?;
+                if !thread_id.is_empty() {
+                    let index_key = [thread_id.as_bytes(), b"\0", &key].concat();
+                    self.thread_messages.delete(txn, &index_key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_message(&self, key: &[u8]) -> heed::Result<()> {
+        let mut txn = self.env.write_txn()?;
+        if let Some(entry) = self
+            .messages
+            .get(&txn, key)?
+            .and_then(|bytes| from_msgpack::<Entry>(bytes).ok())
+        {
+            let index_key = [entry.thread_id.as_bytes(), b"\0", key].concat();
+            self.thread_messages.delete(&mut txn, &index_key)?;
+        }
+        self.messages.delete(&mut txn, key)?;
+        txn.commit()
+    }
+}
+
+fn map_error(error: heed::Error) -> StorageError {
+    StorageError::from(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir::TempDir;
+
+    /// Store in a new temp dir, the store is dropped before its dir if bound after it
+    fn open_store(max_entries: usize, max_age: f64) -> (TempDir, ClientStore) {
+        let dir = TempDir::new("client-store");
+        let store = ClientStore::open(dir.path(), 64 * 1024 * 1024, max_entries, max_age).unwrap();
+        (dir, store)
+    }
+
+    #[tokio::test]
+    async fn least_recently_synced_entries_are_evicted() {
+        let (_dir, store) = open_store(10, 3600.0);
+        for index in 0..EVICT_EVERY {
+            store
+                .save_message("thread", &format!("m{:03}", index), vec![1])
+                .await
+                .unwrap();
+        }
+
+        let kept = store.thread_messages("thread").await.unwrap();
+        assert_eq!(kept.len(), 10);
+        assert!(store.message("m000").await.unwrap().is_none());
+        assert!(
+            store
+                .message(&format!("m{:03}", EVICT_EVERY - 1))
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn removed_message_leaves_its_thread() {
+        let (_dir, store) = open_store(10, 3600.0);
+        store
+            .save_message("thread", "first", vec![1])
+            .await
+            .unwrap();
+        store
+            .save_message("thread", "second", vec![2])
+            .await
+            .unwrap();
+
+        store.remove_message("first").await.unwrap();
+        assert!(store.message("first").await.unwrap().is_none());
+        assert_eq!(
+            store.thread_messages("thread").await.unwrap(),
+            vec![vec![2]]
+        );
+    }
+}
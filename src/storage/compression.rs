@@ -0,0 +1,35 @@
+/// Threshold of light and mobile nodes when `compression_threshold` is not set
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 4096;
+
+/// Compressed value if it is at least `threshold` bytes and compression makes it smaller
+///
+/// Return `None` if the value must be stored as is.
+#[cfg(feature = "compression")]
+pub fn compress(value: &[u8], threshold: Option<usize>, level: i32) -> Option<Vec<u8>> {
+    if value.len() < threshold? {
+        return None;
+    }
+    zstd::bulk::compress(value, level)
+        .ok()
+        .filter(|compressed| compressed.len() < value.len())
+}
+
+/// Node is built without `compression` feature, values are stored as is
+#[cfg(not(feature = "compression"))]
+pub fn compress(_value: &[u8], _threshold: Option<usize>, _level: i32) -> Option<Vec<u8>> {
+    None
+}
+
+/// Original value of `size` bytes
+#[cfg(feature = "compression")]
+pub fn decompress(data: &[u8], size: usize) -> Option<Vec<u8>> {
+    zstd::bulk::decompress(data, size)
+        .ok()
+        .filter(|value| value.len() == size)
+}
+
+/// Value compressed by other build can not be read without `compression` feature
+#[cfg(not(feature = "compression"))]
+pub fn decompress(_data: &[u8], _size: usize) -> Option<Vec<u8>> {
+    None
+}
@@ -0,0 +1,99 @@
+use heed::types::Bytes;
+use heed::{Database, Env, RoTxn, RwTxn};
+use serde::{Deserialize, Serialize};
+
+use crate::exceptions::StorageError;
+use crate::storage::compression::compress;
+use crate::utils::serialization::{from_msgpack, to_msgpack};
+
+/// References to one stored content
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ContentRefs {
+    /// Count of keys with this content
+    refs: u64,
+    /// Content is stored compressed with zstd
+    compressed: bool,
+}
+
+/// Values stored once by SHA-256 of the content
+///
+/// Keys with identical values _(exm. replicated thread bundles)_ refer to one copy, which is
+/// removed when the last of these keys is deleted or expires. Calls are made inside the
+/// write transaction of the key, so references always match the metadata of keys.
+#[derive(Clone, Copy)]
+pub(crate) struct ContentStore {
+    blobs: Database<Bytes, Bytes>,
+    refs: Database<Bytes, Bytes>,
+}
+
+impl ContentStore {
+    pub(crate) fn open(env: &Env, txn: &mut RwTxn) -> heed::Result<Self> {
+        Ok(Self {
+            blobs: env.create_database(txn, Some("blobs"))?,
+            refs: env.create_database(txn, Some("blob_refs"))?,
+        })
+    }
+
+    /// Stored bytes of the content _(compressed if it was compressed on save)_
+    pub(crate) fn get<'t>(
+        &self,
+        txn: &'t RoTxn,
+        hash: &[u8],
+    ) -> Result<Option<&'t [u8]>, StorageError> {
+        Ok(self.blobs.get(txn, hash)?)
+    }
+
+    /// Add reference to the content, content is saved only if nobody refers to it yet
+    ///
+    /// Return `true` if the stored content is compressed.
+    pub(crate) fn acquire(
+        &self,
+        txn: &mut RwTxn,
+        hash: &[u8],
+        value: &[u8],
+        threshold: Option<usize>,
+        level: i32,
+    ) -> Result<bool, StorageError> {
+        let mut refs = self.refs_of(txn, hash)?;
+        if refs.refs == 0 {
+            let compressed = compress(value, threshold, level);
+            refs.compressed = compressed.is_some();
+            self.blobs
+                .put(txn, hash, compressed.as_deref().unwrap_or(value))?;
+        }
+        refs.refs += 1;
+        self.put_refs(txn, hash, &refs)?;
+        Ok(refs.compressed)
+    }
+
+    /// Remove reference to the content, content without references is deleted
+    pub(crate) fn release(&self, txn: &mut RwTxn, hash: &[u8]) -> Result<(), StorageError> {
+        let mut refs = self.refs_of(txn, hash)?;
+        refs.refs = refs.refs.saturating_sub(1);
+        if refs.refs == 0 {
+            self.blobs.delete(txn, hash)?;
+            self.refs.delete(txn, hash)?;
+            Ok(())
+        } else {
+            self.put_refs(txn, hash, &refs)
+        }
+    }
+
+    fn refs_of(&self, txn: &RoTxn, hash: &[u8]) -> Result<ContentRefs, StorageError> {
+        Ok(self
+            .refs
+            .get(txn, hash)?
+            .and_then(|bytes| from_msgpack(bytes).ok())
+            .unwrap_or_default())
+    }
+
+    fn put_refs(
+        &self,
+        txn: &mut RwTxn,
+        hash: &[u8],
+        refs: &ContentRefs,
+    ) -> Result<(), StorageError> {
+        let bytes = to_msgpack(refs).map_err(|_| StorageError::General)?;
+        Ok(self.refs.put(txn, hash, &bytes)?)
+    }
+}
@@ -1,6 +1,159 @@
+use crate::utils::crypto::hash_key;
+use crate::utils::serialization::to_msgpack;
 use crate::utils::time::get_now_i64;
 use serde::{Deserialize, Serialize};
 use serde_json::{self, Map, Value};
+use std::collections::HashMap;
+
+/// Writers kept in a vector clock, see [`VectorClock::prune`]
+pub const MAX_CLOCK_WRITERS: usize = 64;
+/// Writer which counts updates of writers pruned from a vector clock
+pub const PRUNED_WRITERS: &str = "*";
+
+/// Causal order of two versions of a record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Causality {
+    Equal,
+    /// This version is older, the other one has seen all its updates
+    Before,
+    /// This version has seen all updates of the other one
+    After,
+    /// Versions were updated independently and have to be merged
+    Concurrent,
+}
+
+/// Version of a mutable record as count of updates by every writer
+///
+/// Writers are short hex node IDs, so the clock stays small. Versions which were updated
+/// by different writers at the same time are detected and merged, instead of the later
+/// store overwriting the other one.
+#[derive(uniffi::Record, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VectorClock {
+    pub counters: HashMap<String, u64>,
+}
+
+impl VectorClock {
+    /// Count of updates by the writer
+    pub fn get(&self, writer: &str) -> u64 {
+        self.counters.get(writer).copied().unwrap_or(0)
+    }
+
+    /// Count one more update by the writer, return its new count
+    pub fn increment(&mut self, writer: &str) -> u64 {
+        let counter = self.counters.entry(writer.to_string()).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+
+    /// Count of updates by all writers
+    pub fn total(&self) -> u64 {
+        self.counters.values().sum()
+    }
+
+    pub fn compare(&self, other: &VectorClock) -> Causality {
+        let mut behind = false;
+        let mut ahead = false;
+        for writer in self.counters.keys().chain(other.counters.keys()) {
+            let (ours, theirs) = (self.get(writer), other.get(writer));
+            behind |= ours < theirs;
+            ahead |= ours > theirs;
+        }
+        match (behind, ahead) {
+            (false, false) => Causality::Equal,
+            (true, false) => Causality::Before,
+            (false, true) => Causality::After,
+            (true, true) => Causality::Concurrent,
+        }
+    }
+
+    /// Take updates of the other version, every writer keeps the larger count
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (writer, count) in &other.counters {
+            let counter = self.counters.entry(writer.clone()).or_insert(0);
+            *counter = (*counter).max(*count);
+        }
+    }
+
+    /// Fold writers with the fewest updates into [`PRUNED_WRITERS`], so at most
+    /// [`MAX_CLOCK_WRITERS`] are kept
+    ///
+    /// Total count does not change and `keep` is never folded. Versions which differ only
+    /// in folded writers are ordered by their sums, so some concurrent updates of them are
+    /// taken as ordered.
+    pub fn prune(&mut self, keep: &str) {
+        if self.counters.len() <= MAX_CLOCK_WRITERS {
+            return;
+        }
+        let mut writers: Vec<(String, u64)> = self
+            .counters
+            .iter()
+            .filter(|(writer, _)| writer.as_str() != keep && writer.as_str() != PRUNED_WRITERS)
+            .map(|(writer, count)| (writer.clone(), *count))
+            .collect();
+        writers.sort_unstable_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        // Room for `keep` and the folded writers themselves
+        let excess = self.counters.len() + 1 - MAX_CLOCK_WRITERS;
+        let folded: u64 = writers
+            .iter()
+            .take(excess)
+            .filter_map(|(writer, _)| self.counters.remove(writer))
+            .sum();
+        *self.counters.entry(PRUNED_WRITERS.to_string()).or_insert(0) += folded;
+    }
+}
+
+/// Mutable record whose concurrent versions are merged by their vector clocks
+pub trait Versioned: Clone {
+    fn version(&self) -> &VectorClock;
+
+    fn version_mut(&mut self) -> &mut VectorClock;
+
+    /// Take changes of the concurrent version `other`, clocks are merged after it
+    fn merge_fields(&mut self, other: &Self);
+}
+
+/// Version with updates of both, the newer one if they are ordered
+pub fn reconcile<T: Versioned>(version: T, other: T) -> T {
+    match version.version().compare(other.version()) {
+        Causality::Equal | Causality::After => version,
+        Causality::Before => other,
+        Causality::Concurrent => {
+            let mut merged = version;
+            merged.merge_fields(&other);
+            merged.version_mut().merge(other.version());
+            merged
+        }
+    }
+}
+
+/// Message count of merged concurrent versions
+///
+/// Messages counted by clocks are added once, messages counted before versioning are
+/// taken from the version which has more of them.
+pub fn merge_message_count(
+    count: i32,
+    version: &VectorClock,
+    other_count: i32,
+    other_version: &VectorClock,
+) -> i32 {
+    let untracked = (count as i64 - version.total() as i64)
+        .max(other_count as i64 - other_version.total() as i64)
+        .max(0);
+    let mut merged = version.clone();
+    merged.merge(other_version);
+    (untracked + merged.total() as i64).min(i32::MAX as i64) as i32
+}
+
+/// Union of two sorted lists of times, only the latest `max` are kept
+pub fn merge_timestamps(times: &[i64], other: &[i64], max: usize) -> Vec<i64> {
+    let mut merged: Vec<i64> = times.iter().chain(other).copied().collect();
+    merged.sort_unstable();
+    merged.dedup();
+    let overflow = merged.len().saturating_sub(max);
+    merged.drain(..overflow);
+    merged
+}
 
 /// This structure describe the fields of threads
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -107,6 +260,9 @@ pub struct Message {
     #[serde(default = "default_empty_map")]
     /// Other data in JSON
     pub metadata: Value,
+    #[serde(default)]
+    /// Hex hash of the previous message seen by the author _(None for the first one)_
+    pub prev_hash: Option<String>,
 }
 
 fn default_content_type() -> String {
@@ -129,6 +285,7 @@ impl Message {
             content_type: default_content_type(),
             attachments: Vec::new(),
             metadata: default_empty_map(),
+            prev_hash: None,
         }
     }
 
@@ -147,6 +304,147 @@ impl Message {
     }
 }
 
+impl ChainLink for Message {
+    fn link_id(&self) -> &str {
+        &self.id
+    }
+
+    fn prev_hash(&self) -> Option<&str> {
+        self.prev_hash.as_deref()
+    }
+
+    fn chained_bytes(&self) -> Vec<u8> {
+        chained_bytes(
+            &self.id,
+            &self.thread_id,
+            self.parent_id.as_deref(),
+            &self.content,
+            self.author_signature.as_deref(),
+            self.timestamp,
+            self.prev_hash.as_deref(),
+        )
+    }
+}
+
+/// Message of the thread history linked to the previous one by hash
+///
+/// Every message keeps the hash of the message its author saw last, so history forms a
+/// chain. Removed message breaks the chain, reordered one points forward.
+pub trait ChainLink {
+    fn link_id(&self) -> &str;
+
+    fn prev_hash(&self) -> Option<&str>;
+
+    /// Bytes covered by the hash, made by [`chained_bytes`]
+    fn chained_bytes(&self) -> Vec<u8>;
+
+    /// Hex hash of the message, the next message keeps it as `prev_hash`
+    fn chain_hash(&self) -> String {
+        hex::encode(hash_key(&self.chained_bytes()))
+    }
+
+    /// Signature of the author covers the message with its link _(true if not signed
+    /// by a known key)_
+    fn author_verified(&self) -> bool {
+        true
+    }
+}
+
+/// Fields of the message covered by its chain hash
+///
+/// Content must be full, so messages stored in parts are hashed after assembly.
+pub fn chained_bytes(
+    id: &str,
+    thread_id: &str,
+    parent_id: Option<&str>,
+    content: &str,
+    author_signature: Option<&str>,
+    timestamp: i64,
+    prev_hash: Option<&str>,
+) -> Vec<u8> {
+    to_msgpack(&(
+        id,
+        thread_id,
+        parent_id,
+        content,
+        author_signature,
+        timestamp,
+        prev_hash,
+    ))
+    .unwrap_or_default()
+}
+
+/// Kind of break in the thread history
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainIssueKind {
+    /// Message seen by the author is not in the history _(removed or changed)_
+    MissingPrevious,
+    /// Message seen by the author goes after this one
+    Reordered,
+    /// Message without previous one which is not the first message of the history
+    ExtraGenesis,
+    /// Signature of the author does not cover the message and its link
+    BadSignature,
+    /// Latest message of the thread is not in the history _(its tail was cut)_
+    MissingLatest,
+}
+
+/// Break in the thread history found by [`verify_chain`]
+#[derive(uniffi::Record, Debug, Clone, PartialEq)]
+pub struct ChainIssue {
+    /// Message whose link is broken
+    pub message_id: String,
+    pub kind: ChainIssueKind,
+}
+
+/// Check links of the history in its order, the oldest first
+///
+/// Only the first message may have no `prev_hash`. Concurrent messages may point to the
+/// same previous one, it is not an issue. `latest` is the chain hash of the latest
+/// message of the thread, the history must contain it. Empty result means that nothing
+/// was removed, changed or moved between linked messages.
+pub fn verify_chain<T: ChainLink>(messages: &[T], latest: Option<&str>) -> Vec<ChainIssue> {
+    let positions: HashMap<String, usize> = messages
+        .iter()
+        .enumerate()
+        .map(|(index, message)| (message.chain_hash(), index))
+        .collect();
+
+    let mut issues: Vec<ChainIssue> = messages
+        .iter()
+        .enumerate()
+        .filter_map(|(index, message)| {
+            let kind = if !message.author_verified() {
+                ChainIssueKind::BadSignature
+            } else {
+                match message.prev_hash().map(|prev| positions.get(prev)) {
+                    None if index == 0 => return None,
+                    None => ChainIssueKind::ExtraGenesis,
+                    Some(None) => ChainIssueKind::MissingPrevious,
+                    Some(Some(previous)) if *previous >= index => ChainIssueKind::Reordered,
+                    Some(Some(_)) => return None,
+                }
+            };
+            Some(ChainIssue {
+                message_id: message.link_id().to_string(),
+                kind,
+            })
+        })
+        .collect();
+
+    if let Some(latest) = latest
+        && !positions.contains_key(latest)
+    {
+        issues.push(ChainIssue {
+            message_id: messages
+                .last()
+                .map_or_else(String::new, |message| message.link_id().to_string()),
+            kind: ChainIssueKind::MissingLatest,
+        });
+    }
+    issues
+}
+
 /// Container for thread data
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Thread {
@@ -195,3 +493,30 @@ impl Thread {
         Ok(Self { metadata, messages })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pruned_clock_keeps_its_total_and_writer() {
+        let mut clock = VectorClock::default();
+        for writer in 0..MAX_CLOCK_WRITERS as u64 * 2 {
+            clock.counters.insert(format!("{writer:04}"), writer + 2);
+        }
+        clock.counters.insert("ours".to_string(), 1);
+        let total = clock.total();
+
+        clock.prune("ours");
+        assert!(clock.counters.len() <= MAX_CLOCK_WRITERS);
+        assert_eq!(clock.total(), total);
+        assert_eq!(clock.get("ours"), 1);
+        // Writers with most updates stay
+        assert_eq!(clock.get("0127"), 129);
+        assert_eq!(clock.get("0000"), 0);
+
+        let pruned = clock.clone();
+        clock.prune("ours");
+        assert_eq!(clock, pruned);
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::utils::time::get_now_f64;
+
+/// Free space on the disk of the storage
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiskStatus {
+    /// Bytes available under `data_dir` at the last check
+    pub free_bytes: u64,
+    /// Node rejects inbound stores and pauses replication
+    pub degraded: bool,
+    /// Time of the last check _(0.0 if never checked)_
+    pub checked_at: f64,
+}
+
+/// Watcher of free disk space under `data_dir`
+///
+/// Node goes to degraded mode when free space falls below `min_free` and returns to
+/// normal work only when it rises above `resume_free`, so mode does not flap near the
+/// threshold.
+pub struct DiskWatchdog {
+    path: PathBuf,
+    pub min_free: u64,
+    pub resume_free: u64,
+    status: Mutex<DiskStatus>,
+}
+
+impl DiskWatchdog {
+    pub fn new(path: PathBuf, min_free: u64, resume_free: u64) -> Self {
+        Self {
+            path,
+            min_free,
+            resume_free: resume_free.max(min_free),
+            status: Mutex::new(DiskStatus::default()),
+        }
+    }
+
+    /// Measure free space and update mode
+    ///
+    /// Return new mode if it changed _(`true` if node became degraded)_
+    pub fn check(&self) -> Option<bool> {
+        let free_bytes = match fs2::available_space(&self.path) {
+            Ok(free) => free,
+            Err(e) => {
+                warn!(path = ?self.path, error = %e, "Failed to measure free disk space");
+                return None;
+            }
+        };
+        self.update(free_bytes)
+    }
+
+    /// Apply measured free space, return new mode if it changed
+    pub fn update(&self, free_bytes: u64) -> Option<bool> {
+        let mut status = self.status.lock().unwrap();
+        status.free_bytes = free_bytes;
+        status.checked_at = get_now_f64();
+
+        let degraded = if status.degraded {
+            free_bytes < self.resume_free
+        } else {
+            free_bytes < self.min_free
+        };
+        if degraded == status.degraded {
+            return None;
+        }
+        status.degraded = degraded;
+        Some(degraded)
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.status.lock().unwrap().degraded
+    }
+
+    pub fn status(&self) -> DiskStatus {
+        self.status.lock().unwrap().clone()
+    }
+}
@@ -1,78 +1,259 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+
+use crate::dht::node::NodeID;
 use crate::utils::crypto::hash_key;
 
+/// Count of bytes of key shown in logs
+const SHORT_KEY_LEN: usize = 8;
+/// Longest key which LMDB stores, requests with longer keys are dropped
+pub const MAX_KEY_SIZE: usize = 511;
+
+/// Key of data in storage and DHT
+///
+/// Bytes are kept as is, key is never truncated. Only [`Key::node_id`] takes the first
+/// 20 bytes to find place of the key in the ID space.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Key(Vec<u8>);
+
+impl Key {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// Key from SHA-256 of the data
+    pub fn hashed(data: &[u8]) -> Self {
+        Self(hash_key(data).to_vec())
+    }
+
+    /// Key from hex string _(None if string is not valid hex)_
+    pub fn from_hex(hex_key: &str) -> Option<Self> {
+        hex::decode(hex_key).ok().map(Self)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Position of the key in the ID space: first 20 bytes padded with zeros
+    pub fn node_id(&self) -> NodeID {
+        NodeID::from_key(&self.0)
+    }
+
+    /// Hex of the key prefix for logs
+    pub fn short_hex(&self) -> String {
+        hex::encode(&self.0[..self.0.len().min(SHORT_KEY_LEN)])
+    }
+}
+
+impl Deref for Key {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Key {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Borrow<[u8]> for Key {
+    fn borrow(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Key {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for Key {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl From<[u8; 32]> for Key {
+    fn from(hash: [u8; 32]) -> Self {
+        Self(hash.to_vec())
+    }
+}
+
+impl From<Key> for Vec<u8> {
+    fn from(key: Key) -> Self {
+        key.0
+    }
+}
+
+/// Full key in hex
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(&self.0))
+    }
+}
+
+/// Convert from `[12, 14, 10, ...]` to string like: `Key(a1b2c3...)`
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Key({}...)", self.short_hex())
+    }
+}
+
+/// Record type of thread metadata
+pub const RECORD_THREAD: &str = "thread";
+/// Record type of message
+pub const RECORD_MESSAGE: &str = "message";
+/// Record type of part of large message content
+pub const RECORD_MESSAGE_PART: &str = "message_part";
+/// Record type of archived thread bundle
+pub const RECORD_ARCHIVE: &str = "archive";
+/// Record type of replicated thread with its recent messages
+pub const RECORD_THREAD_BUNDLE: &str = "thread_bundle";
+/// Record type of mailbox with direct messages
+pub const RECORD_MAILBOX: &str = "mailbox";
+/// Record type of signed blocklist
+pub const RECORD_BLOCKLIST: &str = "blocklist";
+
 /// DHT key builder
-pub struct DHTKeyBuilder;
+///
+/// Names of keys are prefixed with ID of the network, so deployments _(exm. testnet and
+/// mainnet)_ never share keys. Empty ID is the main network, its keys have no prefix.
+#[derive(Clone, Debug, Default)]
+pub struct DHTKeyBuilder {
+    network_id: String,
+}
 
 #[allow(dead_code)]
 impl DHTKeyBuilder {
+    pub fn new(network_id: &str) -> Self {
+        Self {
+            network_id: network_id.to_string(),
+        }
+    }
+
+    pub fn network_id(&self) -> &str {
+        &self.network_id
+    }
+
+    /// Key from the name in the network namespace
+    fn key(&self, name: &str) -> Key {
+        if self.network_id.is_empty() {
+            Key::hashed(name.as_bytes())
+        } else {
+            Key::hashed(format!("{}:{}", self.network_id, name).as_bytes())
+        }
+    }
+
     /// Key for list of all threads
-    pub fn global_threads() -> [u8; 32] {
-        hash_key("global:threads".as_bytes())
+    pub fn global_threads(&self) -> Key {
+        self.key("global:threads")
     }
 
     /// Key for N-top popular themes
-    pub fn global_popular() -> [u8; 32] {
-        hash_key("global:popular".as_bytes())
+    pub fn global_popular(&self) -> Key {
+        self.key("global:popular")
     }
 
     /// Key for last N messages
-    pub fn global_recent() -> [u8; 32] {
-        hash_key("global:recent".as_bytes())
+    pub fn global_recent(&self) -> Key {
+        self.key("global:recent")
     }
 
     /// Key for list active seed-nodes
-    pub fn global_seeds() -> [u8; 32] {
-        hash_key("global:seeds".as_bytes())
+    pub fn global_seeds(&self) -> Key {
+        self.key("global:seeds")
     }
 
     /// Key for thread metadata
-    pub fn thread_meta(thread_id: &str) -> [u8; 32] {
-        hash_key(format!("thread:{}:meta", thread_id).as_bytes())
+    pub fn thread_meta(&self, thread_id: &str) -> Key {
+        self.key(&format!("thread:{}:meta", thread_id))
     }
 
     /// Key for chronological list of thread messages
-    pub fn thread_index(thread_id: &str) -> [u8; 32] {
-        hash_key(format!("thread:{}:index", thread_id).as_bytes())
+    pub fn thread_index(&self, thread_id: &str) -> Key {
+        self.key(&format!("thread:{}:index", thread_id))
     }
 
     /// Key for popular messages in thread
-    pub fn thread_popular(thread_id: &str) -> [u8; 32] {
-        hash_key(format!("thread:{}:popular", thread_id).as_bytes())
+    pub fn thread_popular(&self, thread_id: &str) -> Key {
+        self.key(&format!("thread:{}:popular", thread_id))
     }
 
     /// Key for thread statistic
-    pub fn thread_stats(thread_id: &str) -> [u8; 32] {
-        hash_key(format!("thread:{}:stats", thread_id).as_bytes())
+    pub fn thread_stats(&self, thread_id: &str) -> Key {
+        self.key(&format!("thread:{}:stats", thread_id))
+    }
+
+    /// Key for archived thread with its messages
+    pub fn thread_archive(&self, thread_id: &str) -> Key {
+        self.key(&format!("thread:{}:archive", thread_id))
+    }
+
+    /// Key for chunk of archived thread messages after the first one
+    pub fn thread_archive_chunk(&self, thread_id: &str, index: u32) -> Key {
+        self.key(&format!("thread:{}:archive:{}", thread_id, index))
+    }
+
+    /// Key for popular thread replicated with its recent messages
+    pub fn thread_bundle(&self, thread_id: &str) -> Key {
+        self.key(&format!("thread:{}:bundle", thread_id))
     }
 
     /// Key for message
-    pub fn message(message_hash: &str) -> [u8; 32] {
-        hash_key(format!("msg:{}", message_hash).as_bytes())
+    pub fn message(&self, message_hash: &str) -> Key {
+        self.key(&format!("msg:{}", message_hash))
+    }
+
+    /// Key for part of large message content by hex hash of the part
+    pub fn message_part(&self, part_hash: &str) -> Key {
+        self.key(&format!("msg:part:{}", part_hash))
     }
 
     /// Key for links ot the reply on message
-    pub fn message_refs(message_hash: &str) -> [u8; 32] {
-        hash_key(format!("msg:{}:refs", message_hash).as_bytes())
+    pub fn message_refs(&self, message_hash: &str) -> Key {
+        self.key(&format!("msg:{}:refs", message_hash))
     }
 
     /// Key for reactions on message
-    pub fn message_votes(message_hash: &str) -> [u8; 32] {
-        hash_key(format!("msg:{}:votes", message_hash).as_bytes())
+    pub fn message_votes(&self, message_hash: &str) -> Key {
+        self.key(&format!("msg:{}:votes", message_hash))
     }
 
     /// Key for user profile
-    pub fn user_profile(pubkey: &str) -> [u8; 32] {
-        hash_key(format!("user:{}:profile", pubkey).as_bytes())
+    pub fn user_profile(&self, pubkey: &str) -> Key {
+        self.key(&format!("user:{}:profile", pubkey))
     }
 
     /// Key for user thread
-    pub fn user_threads(pubkey: &str) -> [u8; 32] {
-        hash_key(format!("user:{}:threads", pubkey).as_bytes())
+    pub fn user_threads(&self, pubkey: &str) -> Key {
+        self.key(&format!("user:{}:threads", pubkey))
     }
 
     /// Key for user reputation
-    pub fn user_reputation(pubkey: &str) -> [u8; 32] {
-        hash_key(format!("user:{}:reputation", pubkey).as_bytes())
+    pub fn user_reputation(&self, pubkey: &str) -> Key {
+        self.key(&format!("user:{}:reputation", pubkey))
+    }
+
+    /// Key for encrypted direct messages to the user
+    pub fn mailbox(&self, user_id: &str) -> Key {
+        self.key(&format!("user:{}:mailbox", user_id))
+    }
+
+    /// Key for signed blocklist published by the node
+    pub fn blocklist(&self, author_id: &str) -> Key {
+        self.key(&format!("blocklist:{}", author_id))
     }
 
     /// TODO: Parsing of the key for finding type
@@ -84,39 +265,79 @@ impl DHTKeyBuilder {
 /// Manager for work with keys
 ///
 /// It is template for work with builder
+#[derive(Clone, Default)]
 pub struct KeyManager {
-    // In Rust, we don't need to save builder instance
-    // if methods are static
-}
-
-impl Default for KeyManager {
-    fn default() -> Self {
-        Self::new()
-    }
+    builder: DHTKeyBuilder,
 }
 
 impl KeyManager {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    /// Manager of keys in the namespace of the network
+    pub fn for_network(network_id: &str) -> Self {
+        Self {
+            builder: DHTKeyBuilder::new(network_id),
+        }
+    }
+
+    pub fn network_id(&self) -> &str {
+        self.builder.network_id()
     }
 
     /// Get key for thread metadata
-    pub fn get_thread_meta_key(&self, thread_id: &str) -> [u8; 32] {
-        DHTKeyBuilder::thread_meta(thread_id)
+    pub fn get_thread_meta_key(&self, thread_id: &str) -> Key {
+        self.builder.thread_meta(thread_id)
+    }
+
+    /// Get key for archived thread
+    pub fn get_thread_archive_key(&self, thread_id: &str) -> Key {
+        self.builder.thread_archive(thread_id)
+    }
+
+    /// Get key for chunk of archived thread
+    pub fn get_thread_archive_chunk_key(&self, thread_id: &str, index: u32) -> Key {
+        self.builder.thread_archive_chunk(thread_id, index)
+    }
+
+    /// Get key for replicated bundle of popular thread
+    pub fn get_thread_bundle_key(&self, thread_id: &str) -> Key {
+        self.builder.thread_bundle(thread_id)
     }
 
     /// Get Key for message
-    pub fn get_message_key(&self, message_hash: &str) -> [u8; 32] {
-        DHTKeyBuilder::message(message_hash)
+    pub fn get_message_key(&self, message_hash: &str) -> Key {
+        self.builder.message(message_hash)
+    }
+
+    /// Get key for part of large message content
+    pub fn get_message_part_key(&self, part_hash: &str) -> Key {
+        self.builder.message_part(part_hash)
     }
 
     /// Get key for global list of threads
-    pub fn get_global_threads_key(&self) -> [u8; 32] {
-        DHTKeyBuilder::global_threads()
+    pub fn get_global_threads_key(&self) -> Key {
+        self.builder.global_threads()
     }
 
     /// Get key for popular threads
-    pub fn get_global_popular_key(&self) -> [u8; 32] {
-        DHTKeyBuilder::global_popular()
+    pub fn get_global_popular_key(&self) -> Key {
+        self.builder.global_popular()
+    }
+
+    /// Get key for profile of the user
+    pub fn get_user_profile_key(&self, pubkey: &str) -> Key {
+        self.builder.user_profile(pubkey)
+    }
+
+    /// Get key for mailbox of the user
+    pub fn get_mailbox_key(&self, user_id: &str) -> Key {
+        self.builder.mailbox(user_id)
+    }
+
+    /// Get key for blocklist published by the node
+    pub fn get_blocklist_key(&self, author_id: &str) -> Key {
+        self.builder.blocklist(author_id)
     }
 }
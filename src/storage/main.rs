@@ -1,35 +1,473 @@
+use std::collections::HashMap;
 use std::fs;
+use std::ops::Bound;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 use crate::config::StorageConfig;
 use crate::exceptions::StorageError;
-use crate::utils::serialization::{deserialize, serialize};
-use crate::utils::time::get_now_f64;
+use crate::storage::compression::decompress;
+use crate::storage::content::ContentStore;
+use crate::storage::keys::Key;
+use crate::storage::proof::PublisherProof;
+use crate::storage::quota::PublisherQuotas;
+use crate::storage::stats::{KeyAccess, MAX_PENDING_ACCESS, StorageCounters, StorageStats};
+use crate::storage::type_index::TypeIndex;
+use crate::utils::crypto::hash_key;
+use crate::utils::serialization::{from_msgpack, to_msgpack};
+use crate::utils::time::{Clock, SystemClock};
 use heed::types::Bytes;
-use heed::{Database, Env, EnvOpenOptions};
+use heed::{Database, Env, EnvOpenOptions, RoTxn, RwTxn};
 use serde::{Deserialize, Serialize};
 use tokio::task;
+use tracing::{debug, warn};
 
 /// Head of data
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct MetaData {
     /// Time of expiration
     pub expires_at: f64,
     /// Size of storing data
     pub size: usize,
+    /// Time when data was stored on this node
+    #[serde(default)]
+    pub stored_at: f64,
+    /// Node ID of the original publisher
+    #[serde(default)]
+    pub publisher: Vec<u8>,
+    /// Time of the original publish
+    #[serde(default)]
+    pub published_at: f64,
+    /// Count of forwards from publisher to this node
+    #[serde(default)]
+    pub hops: u32,
+    /// Type of the record _(exm. `thread`)_
+    #[serde(default)]
+    pub record_type: String,
+    /// Record is a tombstone of deleted value, `published_at` is the time of deletion
+    #[serde(default)]
+    pub deleted: bool,
+    /// Expiration before the popularity boost _(None if not boosted)_
+    #[serde(default)]
+    pub boosted_from: Option<f64>,
+    /// Value is stored compressed with zstd, `size` is the size before compression
+    #[serde(default)]
+    pub compressed: bool,
+    /// SHA-256 of the value in [`ContentStore`] _(None if the value is kept under the key)_
+    #[serde(default)]
+    pub content_hash: Option<[u8; 32]>,
+    /// Count of reads of the value by peers, kept when the value is overwritten
+    #[serde(default)]
+    pub reads: u64,
+    /// Time of the last read by a peer
+    #[serde(default)]
+    pub last_access: f64,
+    /// Signature of the publisher _(None if the publisher is not proven)_
+    #[serde(default)]
+    pub proof: Option<PublisherProof>,
+}
+
+impl MetaData {
+    /// Metadata of the value of `size` bytes put now
+    fn for_put(now: f64, ttl: i32, size: usize, origin: StoreOrigin) -> Self {
+        Self {
+            expires_at: now + ttl as f64,
+            size,
+            stored_at: now,
+            publisher: origin.publisher,
+            published_at: origin.published_at,
+            hops: origin.hops,
+            record_type: origin.record_type,
+            deleted: false,
+            boosted_from: None,
+            compressed: false,
+            content_hash: None,
+            reads: 0,
+            last_access: 0.0,
+            proof: origin.proof,
+        }
+    }
+
+    /// Origin of the stored record
+    fn origin(&self) -> StoreOrigin {
+        StoreOrigin {
+            publisher: self.publisher.clone(),
+            published_at: self.published_at,
+            hops: self.hops,
+            record_type: self.record_type.clone(),
+            proof: self.proof.clone(),
+        }
+    }
+
+    /// Keep reads of the current value of the key
+    fn inherit_access(&mut self, current: Option<&MetaData>) {
+        if let Some(current) = current.filter(|current| !current.deleted) {
+            self.reads = current.reads;
+            self.last_access = current.last_access;
+        }
+    }
+}
+
+/// When a put is written to the disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Put returns after the commit of its own transaction
+    #[default]
+    WriteThrough,
+    /// Put is queued and returns at once, queued puts are committed together
+    ///
+    /// Puts are checked against tombstones and quotas before they are queued, puts of
+    /// the last `write_back_interval` are lost on crash. Puts rejected at the commit
+    /// _(exm. by a deletion after the put was queued)_ are only logged. Reads see queued
+    /// puts, other writes and scans commit the queue first.
+    WriteBack,
+}
+
+impl Durability {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "write_through" => Some(Durability::WriteThrough),
+            "write_back" => Some(Durability::WriteBack),
+            _ => None,
+        }
+    }
+}
+
+/// Publisher and size of a stored value
+type StoredEntry = (Vec<u8>, usize);
+
+/// Put waiting for the group commit
+struct QueuedPut {
+    value: Vec<u8>,
+    meta: MetaData,
+}
+
+/// Write-back puts which are not committed yet
+#[derive(Default)]
+struct WriteBackQueue {
+    queued: HashMap<Key, QueuedPut>,
+    /// Puts of the running commit, reads still find them here
+    flushing: Option<Arc<HashMap<Key, QueuedPut>>>,
+}
+
+/// Handles which blocking tasks of writes take from storage
+#[derive(Clone)]
+struct WriteContext {
+    db: Database<Bytes, Bytes>,
+    meta_db: Database<Bytes, Bytes>,
+    content: ContentStore,
+    types: TypeIndex,
+    quotas: Arc<PublisherQuotas>,
+    compression_threshold: Option<usize>,
+    compression_level: i32,
+}
+
+/// Who and when published the data first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StoreOrigin {
+    /// Node ID of the original publisher _(empty if unknown)_
+    pub publisher: Vec<u8>,
+    /// Time of the original publish
+    pub published_at: f64,
+    /// Count of forwards from publisher _(0 on publisher node)_
+    pub hops: u32,
+    /// Type of the record set by publisher _(empty if untyped)_
+    pub record_type: String,
+    /// Signature of the publisher _(None if the publisher is not proven)_
+    pub proof: Option<PublisherProof>,
+}
+
+impl StoreOrigin {
+    /// Origin of data published by the node at `published_at` _(now by its clock)_
+    pub fn new(publisher: Vec<u8>, published_at: f64) -> Self {
+        Self {
+            publisher,
+            published_at,
+            hops: 0,
+            record_type: String::new(),
+            proof: None,
+        }
+    }
+
+    /// Same origin with record type
+    pub fn with_record_type(mut self, record_type: &str) -> Self {
+        self.record_type = record_type.to_string();
+        self
+    }
+
+    /// Origin of the same data after one more forward
+    pub fn forwarded(&self) -> Self {
+        Self {
+            publisher: self.publisher.clone(),
+            published_at: self.published_at,
+            hops: self.hops.saturating_add(1),
+            record_type: self.record_type.clone(),
+            proof: self.proof.clone(),
+        }
+    }
+}
+
+/// Result of the conditional write
+#[derive(Debug, Clone, PartialEq)]
+pub enum CasOutcome {
+    /// Current value matched expected one and new value was saved
+    Applied,
+    /// Current value is different, write was not applied _(None if there is no value)_
+    Conflict(Option<Vec<u8>>),
+}
+
+/// Record of some type found in storage
+#[derive(Debug, Clone)]
+pub struct TypedRecord {
+    pub key: Key,
+    pub value: Vec<u8>,
+    /// Time of the original publish
+    pub published_at: f64,
+    /// Signature of the original publisher _(None if it is unknown)_
+    pub proof: Option<PublisherProof>,
+}
+
+/// Page of records of one type, see [`Storage::records_by_type_page`]
+#[derive(Debug, Clone, Default)]
+pub struct TypedPage {
+    pub records: Vec<TypedRecord>,
+    /// Cursor of the next page _(None if there are no more records)_
+    pub next: Option<Vec<u8>>,
+}
+
+/// Record of one publisher found in storage
+#[derive(Debug, Clone)]
+pub struct PublishedRecord {
+    pub key: Key,
+    pub value: Vec<u8>,
+    /// Type of the record set by publisher _(empty if untyped)_
+    pub record_type: String,
+    /// Time of expiration
+    pub expires_at: f64,
+}
+
+/// Value from storage with its metadata
+#[derive(Debug, Clone)]
+pub struct StoredValue {
+    pub value: Vec<u8>,
+    /// Time when data was stored on this node _(0.0 for old records)_
+    pub stored_at: f64,
+    /// Time of expiration
+    pub expires_at: f64,
+    /// Original publisher of the data
+    pub origin: StoreOrigin,
+}
+
+/// Mark of deleted value
+#[derive(Debug, Clone)]
+pub struct Tombstone {
+    /// Who deleted the value, `published_at` is the time of deletion
+    pub origin: StoreOrigin,
+    /// Time when tombstone is forgotten
+    pub expires_at: f64,
+}
+
+impl Tombstone {
+    /// Seconds left to live at `now`
+    pub fn ttl(&self, now: f64) -> i32 {
+        (self.expires_at - now).max(0.0) as i32
+    }
+}
+
+/// Answer to already processed request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProcessedRequest {
+    /// Time of processing
+    pub processed_at: f64,
+    /// Type of the answer message
+    pub response_type: u8,
+    /// Serialized answer payload
+    pub response: Vec<u8>,
 }
 
 /// Body of data
 pub struct Storage {
-    #[allow(dead_code)]
     config: StorageConfig,
     env: Env,
     db: Database<Bytes, Bytes>,
     meta_db: Database<Bytes, Bytes>,
+    /// Answers to processed requests by request ID for at-most-once handling
+    requests_db: Database<Bytes, Bytes>,
+    /// Items received for the client by time of receipt and ID, see [`Storage::push_inbox`]
+    inbox_db: Database<Bytes, Bytes>,
+    /// Values shared by keys with identical data
+    content: ContentStore,
+    /// Keys of typed records by time of publish
+    types: TypeIndex,
+    /// Reads by peers which are not written to metadata yet
+    access: Mutex<HashMap<Key, KeyAccess>>,
+    /// Durability of [`Storage::put`] and [`Storage::put_with_origin`]
+    durability: Durability,
+    write_back: Mutex<WriteBackQueue>,
+    /// Only one group commit runs at a time
+    flush_lock: tokio::sync::Mutex<()>,
+    counters: Mutex<StorageCounters>,
+    quotas: Arc<PublisherQuotas>,
+    /// Clock of the node, TTLs and times of publish are counted by it
+    time: Arc<dyn Clock>,
+}
+
+/// Writes of one [`Storage::transaction`]
+///
+/// Reads see the writes made earlier in the same transaction.
+pub struct StorageTxn<'t> {
+    txn: RwTxn<'t>,
+    ctx: WriteContext,
+    requests_db: Database<Bytes, Bytes>,
+    /// Time of the transaction, all its puts are stored at it
+    now: f64,
+    /// Changes of stored bytes of publishers, undone if the transaction is aborted
+    quota_log: Vec<(Vec<u8>, i64)>,
+    /// Replaced and new sizes of puts for counters after commit
+    puts: Vec<(Option<usize>, usize)>,
+    /// Sizes of deleted values for counters after commit
+    deletes: Vec<Option<usize>>,
+}
+
+impl StorageTxn<'_> {
+    /// Not expired value of the key
+    pub fn get(&self, key: &Key) -> Result<Option<Vec<u8>>, StorageError> {
+        let meta: Option<MetaData> = self
+            .ctx
+            .meta_db
+            .get(&self.txn, key)?
+            .and_then(|bytes| from_msgpack(bytes).ok());
+        match meta {
+            Some(meta) if !meta.deleted && self.now <= meta.expires_at => {
+                Storage::read_value(self.ctx.db, self.ctx.content, &self.txn, key, Some(&meta))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Save the value, rejections are the same as of [`Storage::put_with_origin`]
+    pub fn put(
+        &mut self,
+        key: &Key,
+        value: &[u8],
+        ttl: i32,
+        origin: StoreOrigin,
+    ) -> Result<(), StorageError> {
+        let meta = MetaData::for_put(self.now, ttl, value.len(), origin);
+        let (publisher, size) = (meta.publisher.clone(), meta.size);
+        let previous = Storage::apply_put(&self.ctx, &mut self.txn, key, value, meta)?;
+        if let Some((previous_publisher, previous_size)) = &previous {
+            self.quota_log
+                .push((previous_publisher.clone(), -(*previous_size as i64)));
+        }
+        self.quota_log.push((publisher, size as i64));
+        self.puts.push((previous.map(|(_, size)| size), size));
+        Ok(())
+    }
+
+    /// Save the value only if SHA-256 of the current one is `expected_hash`
+    ///
+    /// See [`Storage::compare_and_swap`], the check and the write see the same state.
+    pub fn compare_and_swap(
+        &mut self,
+        key: &Key,
+        expected_hash: Option<[u8; 32]>,
+        value: &[u8],
+        ttl: i32,
+        origin: StoreOrigin,
+    ) -> Result<CasOutcome, StorageError> {
+        let deleted = self
+            .ctx
+            .meta_db
+            .get(&self.txn, key)?
+            .and_then(|bytes| from_msgpack::<MetaData>(bytes).ok())
+            .is_some_and(|meta| {
+                Storage::deleted_after(&meta, &origin.publisher, origin.published_at, self.now)
+            });
+        if deleted {
+            return Err(StorageError::Deleted);
+        }
+        let current = self.get(key)?;
+        if current.as_deref().map(hash_key) != expected_hash {
+            return Ok(CasOutcome::Conflict(current));
+        }
+        self.put(key, value, ttl, origin)?;
+        Ok(CasOutcome::Applied)
+    }
+
+    /// Delete the value and its metadata, return `false` if there was no value
+    pub fn delete(&mut self, key: &Key) -> Result<bool, StorageError> {
+        let current_meta: Option<MetaData> = self
+            .ctx
+            .meta_db
+            .get(&self.txn, key)?
+            .and_then(|bytes| from_msgpack(bytes).ok());
+        let previous = Storage::stored_entry(self.ctx.meta_db, &self.txn, key)?;
+        Storage::release_value(
+            self.ctx.db,
+            self.ctx.content,
+            &mut self.txn,
+            key,
+            current_meta.as_ref(),
+        )?;
+        Storage::reindex(
+            self.ctx.types,
+            &mut self.txn,
+            key,
+            current_meta.as_ref(),
+            None,
+        )?;
+        let existed = self.ctx.meta_db.delete(&mut self.txn, key)?;
+        if let Some((publisher, size)) = &previous {
+            self.ctx.quotas.record_remove(publisher, *size as u64);
+            self.quota_log.push((publisher.clone(), -(*size as i64)));
+        }
+        self.deletes.push(previous.map(|(_, size)| size));
+        Ok(existed)
+    }
+
+    /// Remember answer to the processed request, see [`Storage::record_processed`]
+    pub fn record_processed(
+        &mut self,
+        request_id: &[u8],
+        response_type: u8,
+        response: Vec<u8>,
+    ) -> Result<(), StorageError> {
+        let processed = ProcessedRequest {
+            processed_at: self.now,
+            response_type,
+            response,
+        };
+        let bytes = to_msgpack(&processed).map_err(|_| StorageError::General)?;
+        self.requests_db.put(&mut self.txn, request_id, &bytes)?;
+        Ok(())
+    }
+
+    fn undo_quotas(&self) {
+        Self::undo(&self.ctx, &self.quota_log);
+    }
+
+    fn undo(ctx: &WriteContext, quota_log: &[(Vec<u8>, i64)]) {
+        for (publisher, change) in quota_log.iter().rev() {
+            if *change > 0 {
+                ctx.quotas.record_remove(publisher, *change as u64);
+            } else {
+                ctx.quotas.record_add(publisher, change.unsigned_abs());
+            }
+        }
+    }
 }
 
 impl Storage {
     pub fn new(config: StorageConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Storage which counts time by `clock` _(exm. a virtual one of simulations)_
+    pub fn with_clock(
+        config: StorageConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let data_dir = PathBuf::from(&config.data_dir);
         fs::create_dir_all(&data_dir)?;
 
@@ -49,116 +487,1018 @@ impl Storage {
 
         let db = env.create_database(&mut wtxn, Some("main"))?;
         let meta_db = env.create_database(&mut wtxn, Some("meta"))?;
+        let requests_db = env.create_database(&mut wtxn, Some("requests"))?;
+        let inbox_db = env.create_database(&mut wtxn, Some("inbox"))?;
+        let content = ContentStore::open(&env, &mut wtxn)?;
+        let types = TypeIndex::open(&env, &mut wtxn)?;
+        // Index is new or lost, records stored without it are added
+        if types.is_empty(&wtxn)? {
+            let mut typed: Vec<(Vec<u8>, String, f64)> = Vec::new();
+            for item in meta_db.iter(&wtxn)? {
+                let (key, meta_bytes): (&[u8], &[u8]) = item?;
+                if let Ok(meta) = from_msgpack::<MetaData>(meta_bytes)
+                    && !meta.deleted
+                    && !meta.record_type.is_empty()
+                {
+                    typed.push((key.to_vec(), meta.record_type, meta.published_at));
+                }
+            }
+            for (key, record_type, published_at) in typed {
+                types.insert(&mut wtxn, &record_type, published_at, &key)?;
+            }
+        }
 
         wtxn.commit()?;
 
+        let default_limit = (config.publisher_quota_share > 0.0)
+            .then_some((config.max_storage_size as f64 * config.publisher_quota_share) as u64);
+        let quotas = PublisherQuotas::new(default_limit);
+        for (publisher, limit) in &config.publisher_quotas {
+            if let Ok(publisher) = hex::decode(publisher) {
+                quotas.set_limit(&publisher, (*limit > 0).then_some(*limit));
+            }
+        }
+
+        let durability = Durability::from_name(&config.write_mode).unwrap_or_else(|| {
+            warn!(mode = %config.write_mode, "Unknown storage write mode, using write_through");
+            Durability::WriteThrough
+        });
+
+        let (keys, bytes) = {
+            let rtxn = env.read_txn()?;
+            let mut keys = 0;
+            let mut bytes = 0;
+            for item in meta_db.iter(&rtxn)? {
+                let (_, meta_bytes) = item?;
+                if let Ok(meta) = from_msgpack::<MetaData>(meta_bytes)
+                    && !meta.deleted
+                {
+                    keys += 1;
+                    bytes += meta.size as u64;
+                    quotas.record_add(&meta.publisher, meta.size as u64);
+                }
+            }
+            (keys, bytes)
+        };
+
         Ok(Self {
             config,
             env,
             db,
             meta_db,
+            requests_db,
+            inbox_db,
+            content,
+            types,
+            access: Mutex::new(HashMap::new()),
+            durability,
+            write_back: Mutex::new(WriteBackQueue::default()),
+            flush_lock: tokio::sync::Mutex::new(()),
+            counters: Mutex::new(StorageCounters::new(keys, bytes, clock.now())),
+            quotas: Arc::new(quotas),
+            time: clock,
         })
     }
 
+    /// Counters of stored data and operations
+    pub fn stats(&self) -> StorageStats {
+        self.counters.lock().unwrap().snapshot(self.time.now())
+    }
+
+    /// Current time by the clock of the storage
+    pub fn now(&self) -> f64 {
+        self.time.now()
+    }
+
+    /// Clock of the storage, other components of the node share it
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.time
+    }
+
+    /// Stored bytes and quotas of publishers
+    pub fn quotas(&self) -> &PublisherQuotas {
+        &self.quotas
+    }
+
+    /// Publisher and size of the current value from its metadata _(None for tombstone)_
+    fn stored_entry(
+        meta_db: Database<Bytes, Bytes>,
+        txn: &RoTxn,
+        key: &[u8],
+    ) -> Result<Option<(Vec<u8>, usize)>, StorageError> {
+        Ok(meta_db
+            .get(txn, key)?
+            .and_then(|bytes| from_msgpack::<MetaData>(bytes).ok())
+            .filter(|meta| !meta.deleted)
+            .map(|meta| (meta.publisher, meta.size)))
+    }
+
+    /// Value of the record as it was put
+    ///
+    /// Records written before content deduplication keep the value under their key.
+    fn read_value(
+        db: Database<Bytes, Bytes>,
+        content: ContentStore,
+        txn: &RoTxn,
+        key: &[u8],
+        meta: Option<&MetaData>,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let bytes = match meta.and_then(|meta| meta.content_hash) {
+            Some(hash) => content.get(txn, &hash)?,
+            None => db.get(txn, key)?,
+        };
+        match (bytes, meta) {
+            (Some(bytes), Some(meta)) if meta.compressed => decompress(bytes, meta.size)
+                .map(Some)
+                .ok_or(StorageError::Corrupted),
+            (bytes, _) => Ok(bytes.map(|bytes| bytes.to_vec())),
+        }
+    }
+
+    /// Save the value in content store, `meta` gets its hash and compression
+    fn write_value(
+        content: ContentStore,
+        txn: &mut RwTxn,
+        value: &[u8],
+        meta: &mut MetaData,
+        threshold: Option<usize>,
+        level: i32,
+    ) -> Result<(), StorageError> {
+        let hash = hash_key(value);
+        meta.compressed = content.acquire(txn, &hash, value, threshold, level)?;
+        meta.content_hash = Some(hash);
+        Ok(())
+    }
+
+    /// Drop the value of the record with metadata `meta`, shared content loses one reference
+    fn release_value(
+        db: Database<Bytes, Bytes>,
+        content: ContentStore,
+        txn: &mut RwTxn,
+        key: &[u8],
+        meta: Option<&MetaData>,
+    ) -> Result<(), StorageError> {
+        if let Some(hash) = meta.and_then(|meta| meta.content_hash) {
+            content.release(txn, &hash)?;
+        }
+        db.delete(txn, key)?;
+        Ok(())
+    }
+
+    /// Move the index entry of the key from `previous` to `current` metadata
+    fn reindex(
+        types: TypeIndex,
+        txn: &mut RwTxn,
+        key: &[u8],
+        previous: Option<&MetaData>,
+        current: Option<&MetaData>,
+    ) -> Result<(), StorageError> {
+        if let Some(meta) = previous.filter(|meta| !meta.deleted) {
+            types.remove(txn, &meta.record_type, meta.published_at, key)?;
+        }
+        if let Some(meta) = current.filter(|meta| !meta.deleted) {
+            types.insert(txn, &meta.record_type, meta.published_at, key)?;
+        }
+        Ok(())
+    }
+
+    /// Not expired tombstone is newer than data of `publisher` published at `published_at`
+    ///
+    /// Tombstone of one publisher does not reject data of others.
+    fn deleted_after(meta: &MetaData, publisher: &[u8], published_at: f64, now: f64) -> bool {
+        meta.deleted
+            && now <= meta.expires_at
+            && published_at <= meta.published_at
+            && (meta.publisher.is_empty() || publisher.is_empty() || meta.publisher == publisher)
+    }
+
     /// Save data in storage
-    pub async fn put(&self, key: Vec<u8>, value: Vec<u8>, ttl: i32) -> Result<(), StorageError> {
+    ///
+    /// Publisher is unknown, so data is treated as published right now
+    pub async fn put(&self, key: Key, value: Vec<u8>, ttl: i32) -> Result<(), StorageError> {
+        self.put_with_origin(
+            key,
+            value,
+            ttl,
+            StoreOrigin::new(Vec::new(), self.time.now()),
+        )
+        .await
+    }
+
+    /// Save data in storage with information about its original publisher
+    pub async fn put_with_origin(
+        &self,
+        key: Key,
+        value: Vec<u8>,
+        ttl: i32,
+        origin: StoreOrigin,
+    ) -> Result<(), StorageError> {
+        self.put_with_durability(key, value, ttl, origin, self.durability)
+            .await
+    }
+
+    /// Durability of puts without explicit one _(`write_mode` from config)_
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    /// Save data in storage with chosen durability
+    pub async fn put_with_durability(
+        &self,
+        key: Key,
+        value: Vec<u8>,
+        ttl: i32,
+        origin: StoreOrigin,
+        durability: Durability,
+    ) -> Result<(), StorageError> {
         if !self.has_space(value.len()) {
             return Err(StorageError::StorageFull);
         }
 
-        let expires_at = get_now_f64() + ttl as f64;
-        let meta = MetaData {
-            expires_at,
-            size: value.len(),
-        };
+        let meta = MetaData::for_put(self.time.now(), ttl, value.len(), origin);
+
+        if durability == Durability::WriteBack {
+            // Queued puts of the publisher are not in its quota yet
+            let queued_own: u64 = self
+                .write_back
+                .lock()
+                .unwrap()
+                .queued
+                .iter()
+                .filter(|(queued_key, put)| {
+                    **queued_key != key && put.meta.publisher == meta.publisher
+                })
+                .map(|(_, put)| put.meta.size as u64)
+                .sum();
+            let env = self.env.clone();
+            let ctx = self.write_context();
+            let (check_key, check_meta) = (key.clone(), meta.clone());
+            task::spawn_blocking(move || {
+                let txn = env.read_txn()?;
+                Self::check_put(&ctx, &txn, &check_key, &check_meta, queued_own).map(|_| ())
+            })
+            .await
+            .map_err(|_| StorageError::General)??;
+
+            let full = {
+                let mut write_back = self.write_back.lock().unwrap();
+                write_back.queued.insert(key, QueuedPut { value, meta });
+                write_back.queued.len() >= self.config.write_back_max_batch
+            };
+            if full {
+                self.flush_writes().await?;
+            }
+            return Ok(());
+        }
 
-        let meta_bytes = serialize(&meta, "msgpack").map_err(|_| StorageError::General)?;
+        // Queued older put of the key must not overwrite this one
+        self.write_back.lock().unwrap().queued.remove(&key);
 
         let env = self.env.clone();
-        let db = self.db;
-        let meta_db = self.meta_db;
+        let ctx = self.write_context();
+        let size = value.len();
+        let put_publisher = meta.publisher.clone();
 
-        task::spawn_blocking(move || {
-            let mut txn = env.write_txn().unwrap();
-            db.put(&mut txn, &key, &value).unwrap();
-            meta_db.put(&mut txn, &key, &meta_bytes).unwrap();
-            txn.commit().unwrap();
+        let previous = task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            let previous = Self::apply_put(&ctx, &mut txn, &key, &value, meta)?;
+            if let Err(e) = txn.commit() {
+                Self::undo_put(&ctx, previous.as_ref(), &put_publisher, size);
+                return Err(StorageError::from(e));
+            }
+            Ok(previous.map(|(_, size)| size))
         })
         .await
-        .map_err(|_| StorageError::General)?;
+        .map_err(|_| StorageError::General)??;
 
+        self.counters.lock().unwrap().record_put(previous, size);
         Ok(())
     }
 
+    /// Commit queued write-back puts in one transaction, return count of saved ones
+    ///
+    /// Rejected puts are logged and dropped, their callers already got `Ok`.
+    pub async fn flush_writes(&self) -> Result<usize, StorageError> {
+        let _flush = self.flush_lock.lock().await;
+        let puts = {
+            let mut write_back = self.write_back.lock().unwrap();
+            if write_back.queued.is_empty() {
+                return Ok(0);
+            }
+            let puts = Arc::new(std::mem::take(&mut write_back.queued));
+            write_back.flushing = Some(puts.clone());
+            puts
+        };
+
+        let env = self.env.clone();
+        let ctx = self.write_context();
+
+        let applied = task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            let mut applied = Vec::new();
+            for (key, put) in puts.iter() {
+                match Self::apply_put(&ctx, &mut txn, key, &put.value, put.meta.clone()) {
+                    Ok(previous) => applied.push((previous, put)),
+                    Err(e) => debug!(key = %key.short_hex(), error = %e, "Write-back put rejected"),
+                }
+            }
+            if let Err(e) = txn.commit() {
+                for (previous, put) in &applied {
+                    Self::undo_put(&ctx, previous.as_ref(), &put.meta.publisher, put.meta.size);
+                }
+                return Err(StorageError::from(e));
+            }
+            Ok(applied
+                .into_iter()
+                .map(|(previous, put)| (previous.map(|(_, size)| size), put.meta.size))
+                .collect::<Vec<_>>())
+        })
+        .await;
+        self.write_back.lock().unwrap().flushing = None;
+        let applied = applied.map_err(|_| StorageError::General)??;
+
+        let mut counters = self.counters.lock().unwrap();
+        for (previous, size) in &applied {
+            counters.record_put(*previous, *size);
+        }
+        Ok(applied.len())
+    }
+
+    /// Not expired write-back put of the key which is not committed yet
+    fn queued_value(&self, key: &Key, now: f64) -> Option<StoredValue> {
+        let write_back = self.write_back.lock().unwrap();
+        let put = write_back
+            .queued
+            .get(key)
+            .or_else(|| write_back.flushing.as_ref()?.get(key))
+            .filter(|put| now <= put.meta.expires_at)?;
+        Some(StoredValue {
+            value: put.value.clone(),
+            stored_at: put.meta.stored_at,
+            expires_at: put.meta.expires_at,
+            origin: put.meta.origin(),
+        })
+    }
+
+    fn write_context(&self) -> WriteContext {
+        WriteContext {
+            db: self.db,
+            meta_db: self.meta_db,
+            content: self.content,
+            types: self.types,
+            quotas: self.quotas.clone(),
+            compression_threshold: self.config.compression_threshold,
+            compression_level: self.config.compression_level,
+        }
+    }
+
+    /// Check the put against the tombstone of the key and the quota of its publisher
+    ///
+    /// `queued` bytes of the publisher are counted as used. Return current metadata of the
+    /// key with publisher and size of the value which the put replaces.
+    fn check_put(
+        ctx: &WriteContext,
+        txn: &RoTxn,
+        key: &[u8],
+        meta: &MetaData,
+        queued: u64,
+    ) -> Result<(Option<MetaData>, Option<StoredEntry>), StorageError> {
+        let current_meta: Option<MetaData> = ctx
+            .meta_db
+            .get(txn, key)?
+            .and_then(|bytes| from_msgpack(bytes).ok());
+        if current_meta.as_ref().is_some_and(|current| {
+            Self::deleted_after(current, &meta.publisher, meta.published_at, meta.stored_at)
+        }) {
+            return Err(StorageError::Deleted);
+        }
+        let previous = Self::stored_entry(ctx.meta_db, txn, key)?;
+        let previous_own = previous
+            .as_ref()
+            .filter(|(p, _)| *p == meta.publisher)
+            .map_or(0, |(_, s)| *s as u64);
+        if !ctx
+            .quotas
+            .allows(&meta.publisher, previous_own, meta.size as u64 + queued)
+        {
+            return Err(StorageError::QuotaExceeded);
+        }
+        Ok((current_meta, previous))
+    }
+
+    /// Write the value in the transaction, return publisher and size of the replaced value
+    ///
+    /// Nothing is written if the put is rejected.
+    fn apply_put(
+        ctx: &WriteContext,
+        txn: &mut RwTxn,
+        key: &[u8],
+        value: &[u8],
+        mut meta: MetaData,
+    ) -> Result<Option<(Vec<u8>, usize)>, StorageError> {
+        let (current_meta, previous) = Self::check_put(ctx, txn, key, &meta, 0)?;
+        // New content is referenced before the old one is released, same value is kept
+        Self::write_value(
+            ctx.content,
+            txn,
+            value,
+            &mut meta,
+            ctx.compression_threshold,
+            ctx.compression_level,
+        )?;
+        Self::release_value(ctx.db, ctx.content, txn, key, current_meta.as_ref())?;
+        Self::reindex(ctx.types, txn, key, current_meta.as_ref(), Some(&meta))?;
+        meta.inherit_access(current_meta.as_ref());
+        let meta_bytes = to_msgpack(&meta).map_err(|_| StorageError::General)?;
+        ctx.meta_db.put(txn, key, &meta_bytes)?;
+        if let Some((previous_publisher, previous_size)) = &previous {
+            ctx.quotas
+                .record_remove(previous_publisher, *previous_size as u64);
+        }
+        ctx.quotas.record_add(&meta.publisher, meta.size as u64);
+        Ok(previous)
+    }
+
+    /// Return quotas changed by [`Storage::apply_put`] whose transaction was not committed
+    fn undo_put(
+        ctx: &WriteContext,
+        previous: Option<&(Vec<u8>, usize)>,
+        publisher: &[u8],
+        size: usize,
+    ) {
+        ctx.quotas.record_remove(publisher, size as u64);
+        if let Some((previous_publisher, previous_size)) = previous {
+            ctx.quotas
+                .record_add(previous_publisher, *previous_size as u64);
+        }
+    }
+
+    /// Apply several related writes atomically
+    ///
+    /// `apply` runs on a blocking thread in one write transaction. Its writes are committed
+    /// if it returns `Ok`, on `Err` none of them is applied. Queued write-back puts are
+    /// committed first, writes of the transaction are always write-through.
+    pub async fn transaction<T, F>(&self, apply: F) -> Result<T, StorageError>
+    where
+        F: FnOnce(&mut StorageTxn) -> Result<T, StorageError> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.flush_writes().await?;
+        let env = self.env.clone();
+        let ctx = self.write_context();
+        let requests_db = self.requests_db;
+        let now = self.time.now();
+
+        let (result, puts, deletes) = task::spawn_blocking(move || {
+            let mut txn = StorageTxn {
+                txn: env.write_txn()?,
+                ctx,
+                requests_db,
+                now,
+                quota_log: Vec::new(),
+                puts: Vec::new(),
+                deletes: Vec::new(),
+            };
+            match apply(&mut txn) {
+                Ok(result) => {
+                    let StorageTxn {
+                        txn: rw,
+                        ctx,
+                        quota_log,
+                        puts,
+                        deletes,
+                        ..
+                    } = txn;
+                    match rw.commit() {
+                        Ok(()) => Ok((result, puts, deletes)),
+                        Err(e) => {
+                            StorageTxn::undo(&ctx, &quota_log);
+                            Err(e.into())
+                        }
+                    }
+                }
+                Err(e) => {
+                    // Dropped transaction is aborted, only quotas live outside of it
+                    txn.undo_quotas();
+                    Err(e)
+                }
+            }
+        })
+        .await
+        .map_err(|_| StorageError::General)??;
+
+        let mut counters = self.counters.lock().unwrap();
+        for (previous, size) in puts {
+            counters.record_put(previous, size);
+        }
+        for previous in deletes {
+            counters.record_delete(previous);
+        }
+        Ok(result)
+    }
+
+    /// Conditional write _(compare-and-swap)_
+    ///
+    /// New value is saved only if SHA-256 of the current value equals `expected_hash`.
+    /// `None` means that we expect no value for the key. Check and write are done in one
+    /// transaction, use [`StorageTxn::compare_and_swap`] to write more in it.
+    pub async fn compare_and_swap(
+        &self,
+        key: Key,
+        expected_hash: Option<[u8; 32]>,
+        value: Vec<u8>,
+        ttl: i32,
+        origin: StoreOrigin,
+    ) -> Result<CasOutcome, StorageError> {
+        if !self.has_space(value.len()) {
+            return Err(StorageError::StorageFull);
+        }
+        self.transaction(move |txn| txn.compare_and_swap(&key, expected_hash, &value, ttl, origin))
+            .await
+    }
+
     /// Reading storage and checking TTL
-    pub async fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>, StorageError> {
+    pub async fn get(&self, key: Key) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.get_with_meta(key).await?.map(|stored| stored.value))
+    }
+
+    /// Reading storage with metadata of the value and checking TTL
+    pub async fn get_with_meta(&self, key: Key) -> Result<Option<StoredValue>, StorageError> {
+        self.counters.lock().unwrap().record_get();
+        let current_time = self.time.now();
+        if let Some(queued) = self.queued_value(&key, current_time) {
+            return Ok(Some(queued));
+        }
+
         let env = self.env.clone();
         let db = self.db;
         let meta_db = self.meta_db;
-        let current_time = get_now_f64();
+        let content = self.content;
 
         let key_clone = key.clone();
 
-        let result = task::spawn_blocking(move || {
-            let txn = env.read_txn().unwrap();
+        // Stale record (expired or without value) is removed, tombstone is kept
+        let (result, stale) = task::spawn_blocking(move || -> Result<_, StorageError> {
+            let txn = env.read_txn()?;
 
-            if let Some(meta_bytes) = meta_db.get(&txn, &key_clone).unwrap() {
-                let meta: MetaData = deserialize(meta_bytes, "msgpack").unwrap();
+            let meta: Option<MetaData> = meta_db
+                .get(&txn, &key_clone)?
+                .map(|bytes| from_msgpack(bytes).map_err(|_| StorageError::Corrupted))
+                .transpose()?;
+            if let Some(meta) = &meta {
                 if current_time > meta.expires_at {
-                    return Ok(None);
+                    return Ok((None, true));
+                }
+                if meta.deleted {
+                    return Ok((None, false));
                 }
             }
 
-            let value = db.get(&txn, &key_clone).unwrap().map(|b| b.to_vec());
-            Ok(value)
+            let value = Self::read_value(db, content, &txn, &key_clone, meta.as_ref())?;
+            let value = value.map(|value| match meta {
+                Some(meta) => StoredValue {
+                    value,
+                    stored_at: meta.stored_at,
+                    expires_at: meta.expires_at,
+                    origin: meta.origin(),
+                },
+                None => StoredValue {
+                    value,
+                    stored_at: 0.0,
+                    expires_at: f64::MAX,
+                    origin: StoreOrigin::default(),
+                },
+            });
+            let stale = value.is_none();
+            Ok((value, stale))
         })
         .await
         .map_err(|_| StorageError::General)??;
 
-        if result.is_none() {
-            self.delete(key).await?;
-            return Ok(None);
+        if stale && let Some(size) = self.remove(key).await? {
+            self.counters.lock().unwrap().record_cleanup(1, size as u64);
         }
 
         Ok(result)
     }
 
-    pub async fn delete(&self, key: Vec<u8>) -> Result<(), StorageError> {
+    /// Count read of the value by a peer
+    ///
+    /// Reads are kept in memory and written to metadata by [`Storage::flush_access`] in one
+    /// transaction, so reads do not cause writes. Reads of new keys over
+    /// [`MAX_PENDING_ACCESS`] are not counted until the next flush.
+    pub fn record_access(&self, key: &Key) {
+        let now = self.time.now();
+        let mut pending = self.access.lock().unwrap();
+        if pending.len() >= MAX_PENDING_ACCESS && !pending.contains_key(key) {
+            return;
+        }
+        pending.entry(key.clone()).or_default().merge(&KeyAccess {
+            reads: 1,
+            last_access: now,
+        });
+    }
+
+    /// Write counted reads to metadata, return reads counted since the previous flush
+    ///
+    /// Queued write-back puts are committed first, so reads of them are not lost.
+    pub async fn flush_access(&self) -> Result<Vec<(Key, KeyAccess)>, StorageError> {
+        self.flush_writes().await?;
+        let pending: Vec<(Key, KeyAccess)> = std::mem::take(&mut *self.access.lock().unwrap())
+            .into_iter()
+            .collect();
+        if pending.is_empty() {
+            return Ok(pending);
+        }
+
         let env = self.env.clone();
-        let db = self.db;
         let meta_db = self.meta_db;
 
         task::spawn_blocking(move || {
             let mut txn = env.write_txn().unwrap();
-            db.delete(&mut txn, &key).unwrap();
-            meta_db.delete(&mut txn, &key).unwrap();
+            for (key, access) in &pending {
+                let Some(bytes) = meta_db.get(&txn, key).unwrap() else {
+                    continue;
+                };
+                let mut meta: MetaData = from_msgpack(bytes).unwrap();
+                if meta.deleted {
+                    continue;
+                }
+                meta.reads += access.reads;
+                meta.last_access = meta.last_access.max(access.last_access);
+                meta_db
+                    .put(&mut txn, key, &to_msgpack(&meta).unwrap())
+                    .unwrap();
+            }
             txn.commit().unwrap();
+            pending
+        })
+        .await
+        .map_err(|_| StorageError::General)
+    }
+
+    /// Reads of the value by peers, not flushed ones included _(None if there is no value)_
+    pub async fn access_stats(&self, key: Key) -> Result<Option<KeyAccess>, StorageError> {
+        let pending = self.access.lock().unwrap().get(&key).copied();
+        let env = self.env.clone();
+        let meta_db = self.meta_db;
+
+        task::spawn_blocking(move || {
+            let txn = env.read_txn()?;
+            Ok(meta_db
+                .get(&txn, &key)?
+                .and_then(|bytes| from_msgpack::<MetaData>(bytes).ok())
+                .filter(|meta| !meta.deleted)
+                .map(|meta| {
+                    let mut access = KeyAccess {
+                        reads: meta.reads,
+                        last_access: meta.last_access,
+                    };
+                    if let Some(pending) = &pending {
+                        access.merge(pending);
+                    }
+                    access
+                }))
         })
         .await
-        .map_err(|_| StorageError::General)?;
+        .map_err(|_| StorageError::General)?
+    }
 
+    /// Get all keys which are not expired
+    pub async fn keys(&self) -> Result<Vec<Key>, StorageError> {
+        self.keys_in_range(Vec::new(), None).await
+    }
+
+    /// Get keys from `lower` up to `upper` _(excluded, None - to the last key)_ which are
+    /// not expired
+    ///
+    /// Keys are ordered by bytes, so keys of one region of the ID space are read without a
+    /// scan of all keys.
+    pub async fn keys_in_range(
+        &self,
+        lower: Vec<u8>,
+        upper: Option<Vec<u8>>,
+    ) -> Result<Vec<Key>, StorageError> {
+        self.flush_writes().await?;
+        let env = self.env.clone();
+        let meta_db = self.meta_db;
+        let current_time = self.time.now();
+
+        task::spawn_blocking(move || {
+            let txn = env.read_txn()?;
+            // LMDB does not take empty keys as bounds
+            let lower = if lower.is_empty() {
+                Bound::Unbounded
+            } else {
+                Bound::Included(&lower[..])
+            };
+            let upper = match &upper {
+                Some(upper) => Bound::Excluded(&upper[..]),
+                None => Bound::Unbounded,
+            };
+            let mut keys = Vec::new();
+            for item in meta_db.range(&txn, &(lower, upper))? {
+                let (key_bytes, meta_bytes) = item?;
+                let Ok(meta) = from_msgpack::<MetaData>(meta_bytes) else {
+                    continue;
+                };
+                if !meta.deleted && current_time <= meta.expires_at {
+                    keys.push(Key::from(key_bytes));
+                }
+            }
+            Ok(keys)
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+    }
+
+    /// Get records of the type published after `since`, the newest first
+    pub async fn records_by_type(
+        &self,
+        record_type: String,
+        since: f64,
+        limit: usize,
+    ) -> Result<Vec<TypedRecord>, StorageError> {
+        Ok(self
+            .records_by_type_page(record_type, since, None, limit)
+            .await?
+            .records)
+    }
+
+    /// Get up to `limit` records of the type published after `since`, the newest first
+    ///
+    /// Records are found by the type index, so a page costs reads of its own records only.
+    /// Next page starts after the `cursor` of the previous one.
+    pub async fn records_by_type_page(
+        &self,
+        record_type: String,
+        since: f64,
+        cursor: Option<Vec<u8>>,
+        limit: usize,
+    ) -> Result<TypedPage, StorageError> {
+        self.flush_writes().await?;
+        let env = self.env.clone();
+        let db = self.db;
+        let meta_db = self.meta_db;
+        let content = self.content;
+        let types = self.types;
+        let current_time = self.time.now();
+
+        task::spawn_blocking(move || {
+            let txn = env.read_txn()?;
+            let mut page = TypedPage::default();
+            let mut last_entry = None;
+
+            types.walk_newest(
+                &txn,
+                &record_type,
+                since,
+                cursor.as_deref(),
+                |entry, key| {
+                    if page.records.len() >= limit {
+                        page.next = last_entry.take();
+                        return Ok(false);
+                    }
+                    let Some(meta) = meta_db
+                        .get(&txn, key)?
+                        .and_then(|bytes| from_msgpack::<MetaData>(bytes).ok())
+                    else {
+                        return Ok(true);
+                    };
+                    if meta.deleted
+                        || meta.record_type != record_type
+                        || meta.published_at < since
+                        || current_time > meta.expires_at
+                    {
+                        return Ok(true);
+                    }
+                    // Broken compressed value is not a reason to lose other records
+                    if let Ok(Some(value)) = Self::read_value(db, content, &txn, key, Some(&meta)) {
+                        page.records.push(TypedRecord {
+                            key: Key::from(key),
+                            value,
+                            published_at: meta.published_at,
+                            proof: meta.proof,
+                        });
+                        last_entry = Some(entry.to_vec());
+                    }
+                    Ok(true)
+                },
+            )?;
+            Ok(page)
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+    }
+
+    /// Get all not expired records of the publisher
+    pub async fn records_by_publisher(
+        &self,
+        publisher: Vec<u8>,
+    ) -> Result<Vec<PublishedRecord>, StorageError> {
+        self.flush_writes().await?;
+        let env = self.env.clone();
+        let db = self.db;
+        let meta_db = self.meta_db;
+        let content = self.content;
+        let current_time = self.time.now();
+
+        task::spawn_blocking(move || {
+            let txn = env.read_txn().unwrap();
+            let mut records = Vec::new();
+
+            for item in meta_db.iter(&txn).unwrap() {
+                let (key_bytes, meta_bytes) = item.unwrap();
+                let meta: MetaData = from_msgpack(meta_bytes).unwrap();
+                if meta.publisher != publisher || meta.deleted || current_time > meta.expires_at {
+                    continue;
+                }
+
+                if let Ok(Some(value)) = Self::read_value(db, content, &txn, key_bytes, Some(&meta))
+                {
+                    records.push(PublishedRecord {
+                        key: Key::from(key_bytes),
+                        value,
+                        record_type: meta.record_type,
+                        expires_at: meta.expires_at,
+                    });
+                }
+            }
+            records
+        })
+        .await
+        .map_err(|_| StorageError::General)
+    }
+
+    pub async fn delete(&self, key: Key) -> Result<(), StorageError> {
+        let previous = self.remove(key).await?;
+        self.counters.lock().unwrap().record_delete(previous);
         Ok(())
     }
 
-    /// Set more time to life for data
-    pub async fn extend_ttl(&self, key: Vec<u8>, extension: f64) -> Result<bool, StorageError> {
+    /// Soft delete: replace value with a tombstone which lives `ttl` seconds
+    ///
+    /// Tombstone rejects older copies of the value, so peers which still hold it can not
+    /// bring it back. `origin.published_at` is the time of deletion. Tombstone is not
+    /// applied if the value was published again after that time or if it has other
+    /// publisher. Value of unknown publisher is deleted only by our own tombstone
+    /// _(`origin.hops == 0`)_, value stored with proof of its publisher only by our own or
+    /// by a proven tombstone. Return `true` if the key is deleted now.
+    pub async fn tombstone(
+        &self,
+        key: Key,
+        ttl: i32,
+        origin: StoreOrigin,
+    ) -> Result<bool, StorageError> {
+        self.flush_writes().await?;
+        let now = self.time.now();
+        let meta = MetaData {
+            expires_at: now + ttl as f64,
+            size: 0,
+            stored_at: now,
+            publisher: origin.publisher,
+            published_at: origin.published_at,
+            hops: origin.hops,
+            record_type: origin.record_type,
+            deleted: true,
+            boosted_from: None,
+            compressed: false,
+            content_hash: None,
+            reads: 0,
+            last_access: 0.0,
+            proof: origin.proof,
+        };
+        let meta_bytes = to_msgpack(&meta).map_err(|_| StorageError::General)?;
+
+        let env = self.env.clone();
+        let db = self.db;
+        let meta_db = self.meta_db;
+        let content = self.content;
+        let types = self.types;
+        let quotas = self.quotas.clone();
+
+        let (applied, previous) = task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            let current_meta: Option<MetaData> = meta_db
+                .get(&txn, &key)?
+                .and_then(|bytes| from_msgpack(bytes).ok());
+            if let Some(current) = &current_meta
+                && now <= current.expires_at
+            {
+                if current.deleted && current.published_at >= meta.published_at {
+                    return Ok((true, None));
+                }
+                let other_publisher = if current.publisher.is_empty() {
+                    meta.hops > 0
+                } else {
+                    current.publisher != meta.publisher
+                };
+                let unproven = current.proof.is_some() && meta.proof.is_none() && meta.hops > 0;
+                if !current.deleted
+                    && (current.published_at > meta.published_at || other_publisher || unproven)
+                {
+                    return Ok((false, None));
+                }
+            }
+
+            let previous = Self::stored_entry(meta_db, &txn, &key)?;
+            Self::release_value(db, content, &mut txn, &key, current_meta.as_ref())?;
+            Self::reindex(types, &mut txn, &key, current_meta.as_ref(), None)?;
+            meta_db.put(&mut txn, &key, &meta_bytes)?;
+            txn.commit()?;
+            if let Some((publisher, size)) = &previous {
+                quotas.record_remove(publisher, *size as u64);
+            }
+            Ok::<_, StorageError>((true, previous.map(|(_, size)| size)))
+        })
+        .await
+        .map_err(|_| StorageError::General)??;
+
+        if previous.is_some() {
+            self.counters.lock().unwrap().record_delete(previous);
+        }
+        Ok(applied)
+    }
+
+    /// Not expired tombstone of the key
+    pub async fn get_tombstone(&self, key: Key) -> Result<Option<Tombstone>, StorageError> {
         let env = self.env.clone();
         let meta_db = self.meta_db;
-        let current_time = get_now_f64();
+        let current_time = self.time.now();
 
         task::spawn_blocking(move || {
-            let mut txn = env.write_txn().unwrap();
-            let meta_data = meta_db.get(&txn, &key).unwrap();
+            let txn = env.read_txn()?;
+            Ok(meta_db
+                .get(&txn, &key)?
+                .and_then(|bytes| from_msgpack::<MetaData>(bytes).ok())
+                .filter(|meta| meta.deleted && current_time <= meta.expires_at)
+                .map(|meta| Tombstone {
+                    expires_at: meta.expires_at,
+                    origin: meta.origin(),
+                }))
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+    }
+
+    /// Delete value and its metadata, return size of the deleted value
+    async fn remove(&self, key: Key) -> Result<Option<usize>, StorageError> {
+        self.flush_writes().await?;
+        let env = self.env.clone();
+        let db = self.db;
+        let meta_db = self.meta_db;
+        let content = self.content;
+        let types = self.types;
+        let quotas = self.quotas.clone();
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            let current_meta: Option<MetaData> = meta_db
+                .get(&txn, &key)?
+                .and_then(|bytes| from_msgpack(bytes).ok());
+            let previous = Self::stored_entry(meta_db, &txn, &key)?;
+            Self::release_value(db, content, &mut txn, &key, current_meta.as_ref())?;
+            Self::reindex(types, &mut txn, &key, current_meta.as_ref(), None)?;
+            meta_db.delete(&mut txn, &key)?;
+            txn.commit()?;
+            if let Some((publisher, size)) = &previous {
+                quotas.record_remove(publisher, *size as u64);
+            }
+            Ok(previous.map(|(_, size)| size))
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+    }
+
+    /// Keep popular data for at least `ttl` seconds from now
+    ///
+    /// Expiration before the first boost is remembered, [`Storage::demote`] returns the
+    /// data to it. Return `false` if there is no value for the key.
+    pub async fn boost_ttl(&self, key: Key, ttl: i32) -> Result<bool, StorageError> {
+        self.flush_writes().await?;
+        let env = self.env.clone();
+        let meta_db = self.meta_db;
+        let current_time = self.time.now();
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            let meta_data = meta_db.get(&txn, &key)?;
 
             if let Some(bytes) = meta_data {
-                let mut meta: MetaData = deserialize(bytes, "msgpack").unwrap();
-                let current_ttl = meta.expires_at - current_time;
-                let new_ttl = current_ttl * (1.0 + extension);
-                meta.expires_at = current_time + new_ttl;
-
-                let new_meta_bytes = serialize(&meta, "msgpack").unwrap();
-                meta_db.put(&mut txn, &key, &new_meta_bytes).unwrap();
-                txn.commit().unwrap();
+                let mut meta: MetaData =
+                    from_msgpack(bytes).map_err(|_| StorageError::Corrupted)?;
+                if meta.deleted {
+                    return Ok(false);
+                }
+                let boosted = current_time + ttl as f64;
+                if boosted > meta.expires_at {
+                    meta.boosted_from.get_or_insert(meta.expires_at);
+                    meta.expires_at = boosted;
+
+                    let new_meta_bytes = to_msgpack(&meta).map_err(|_| StorageError::General)?;
+                    meta_db.put(&mut txn, &key, &new_meta_bytes)?;
+                    txn.commit()?;
+                }
                 Ok(true)
             } else {
                 Ok(false)
@@ -168,6 +1508,40 @@ impl Storage {
         .map_err(|_| StorageError::General)?
     }
 
+    /// Return data which is not popular anymore to its expiration before the boost
+    ///
+    /// Data lives at least `min_ttl` seconds more. Return `false` if the data was not
+    /// boosted.
+    pub async fn demote(&self, key: Key, min_ttl: i32) -> Result<bool, StorageError> {
+        self.flush_writes().await?;
+        let env = self.env.clone();
+        let meta_db = self.meta_db;
+        let current_time = self.time.now();
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            let meta_data = meta_db.get(&txn, &key)?;
+
+            let Some(bytes) = meta_data else {
+                return Ok(false);
+            };
+            let mut meta: MetaData = from_msgpack(bytes).map_err(|_| StorageError::Corrupted)?;
+            let Some(boosted_from) = meta.boosted_from.take() else {
+                return Ok(false);
+            };
+            meta.expires_at = meta
+                .expires_at
+                .min(boosted_from.max(current_time + min_ttl as f64));
+
+            let new_meta_bytes = to_msgpack(&meta).map_err(|_| StorageError::General)?;
+            meta_db.put(&mut txn, &key, &new_meta_bytes)?;
+            txn.commit()?;
+            Ok(true)
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+    }
+
     /// For long support to check space
     fn has_space(&self, _size: usize) -> bool {
         true
@@ -175,44 +1549,726 @@ impl Storage {
 
     /// Delete unnecessary data
     pub async fn cleanup_expired(&self) -> Result<i32, StorageError> {
+        self.flush_writes().await?;
         let env = self.env.clone();
         let db = self.db;
         let meta_db = self.meta_db;
-        let current_time = get_now_f64();
+        let content = self.content;
+        let types = self.types;
+        let quotas = self.quotas.clone();
+        let current_time = self.time.now();
 
-        task::spawn_blocking(move || {
+        let (deleted_count, deleted_values, deleted_bytes) = task::spawn_blocking(move || {
             let mut deleted_count = 0;
-            let mut txn = env.write_txn().unwrap();
+            let mut deleted_values = 0;
+            let mut deleted_bytes = 0;
+            let mut txn = env.write_txn()?;
 
             let mut to_delete = Vec::new();
+            let mut released = Vec::new();
 
             {
-                let iter = meta_db.iter(&txn).unwrap();
+                let iter = meta_db.iter(&txn)?;
                 for item in iter {
-                    let (key_bytes, meta_bytes) = item.unwrap();
-                    let meta: MetaData = deserialize(meta_bytes, "msgpack").unwrap();
+                    let (key_bytes, meta_bytes) = item?;
+                    let Ok(meta) = from_msgpack::<MetaData>(meta_bytes) else {
+                        continue;
+                    };
                     if current_time > meta.expires_at {
-                        to_delete.push(key_bytes.to_vec());
+                        if !meta.deleted {
+                            deleted_values += 1;
+                            deleted_bytes += meta.size as u64;
+                            released.push((meta.publisher.clone(), meta.size as u64));
+                        }
+                        to_delete.push((key_bytes.to_vec(), meta));
                     }
                 }
             }
 
-            for key in to_delete {
-                db.delete(&mut txn, &key).unwrap();
-                meta_db.delete(&mut txn, &key).unwrap();
+            for (key, meta) in to_delete {
+                Self::release_value(db, content, &mut txn, &key, Some(&meta))?;
+                Self::reindex(types, &mut txn, &key, Some(&meta), None)?;
+                meta_db.delete(&mut txn, &key)?;
                 deleted_count += 1;
             }
 
-            txn.commit().unwrap();
-            Ok(deleted_count)
+            txn.commit()?;
+            for (publisher, size) in released {
+                quotas.record_remove(&publisher, size);
+            }
+            Ok::<_, StorageError>((deleted_count, deleted_values, deleted_bytes))
+        })
+        .await
+        .map_err(|_| StorageError::General)??;
+
+        self.counters
+            .lock()
+            .unwrap()
+            .record_cleanup(deleted_values, deleted_bytes);
+        Ok(deleted_count)
+    }
+
+    /// Answer to request processed during last `dedup_window` seconds
+    pub async fn processed_response(
+        &self,
+        request_id: Vec<u8>,
+    ) -> Result<Option<ProcessedRequest>, StorageError> {
+        let env = self.env.clone();
+        let requests_db = self.requests_db;
+        let window = self.config.dedup_window as f64;
+        let current_time = self.time.now();
+
+        task::spawn_blocking(move || {
+            let txn = env.read_txn()?;
+            Ok(requests_db
+                .get(&txn, &request_id)?
+                .and_then(|bytes| from_msgpack::<ProcessedRequest>(bytes).ok())
+                .filter(|processed| current_time - processed.processed_at <= window))
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+    }
+
+    /// Remember answer to the processed request
+    pub async fn record_processed(
+        &self,
+        request_id: Vec<u8>,
+        response_type: u8,
+        response: Vec<u8>,
+    ) -> Result<(), StorageError> {
+        let processed = ProcessedRequest {
+            processed_at: self.time.now(),
+            response_type,
+            response,
+        };
+        let bytes = to_msgpack(&processed).map_err(|_| StorageError::General)?;
+
+        let env = self.env.clone();
+        let requests_db = self.requests_db;
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            requests_db.put(&mut txn, &request_id, &bytes)?;
+            Ok(txn.commit()?)
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+    }
+
+    /// Keep the item for the client until [`Storage::take_inbox`], the oldest items over
+    /// `max_items` are dropped
+    ///
+    /// Return `false` if the item with the same ID is already kept.
+    pub async fn push_inbox(
+        &self,
+        id: Vec<u8>,
+        item: Vec<u8>,
+        max_items: usize,
+    ) -> Result<bool, StorageError> {
+        let env = self.env.clone();
+        let inbox_db = self.inbox_db;
+        let received_at = self.time.now();
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            for entry in inbox_db.iter(&txn)? {
+                let (key, _) = entry?;
+                if key.get(8..) == Some(&id[..]) {
+                    return Ok(false);
+                }
+            }
+            // Time is positive, so its bits sort like the times
+            let mut key = received_at.to_bits().to_be_bytes().to_vec();
+            key.extend_from_slice(&id);
+            inbox_db.put(&mut txn, &key, &item)?;
+
+            let overflow = (inbox_db.len(&txn)? as usize).saturating_sub(max_items);
+            for _ in 0..overflow {
+                let Some((oldest, _)) = inbox_db.first(&txn)? else {
+                    break;
+                };
+                let oldest = oldest.to_vec();
+                inbox_db.delete(&mut txn, &oldest)?;
+            }
+            txn.commit()?;
+            Ok(true)
+        })
+        .await
+        .map_err(|_| StorageError::General)?
+    }
+
+    /// Take all items kept for the client, the oldest first
+    pub async fn take_inbox(&self) -> Result<Vec<Vec<u8>>, StorageError> {
+        let env = self.env.clone();
+        let inbox_db = self.inbox_db;
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn()?;
+            let items = inbox_db
+                .iter(&txn)?
+                .map(|entry| entry.map(|(_, item)| item.to_vec()))
+                .collect::<Result<Vec<_>, _>>()?;
+            inbox_db.clear(&mut txn)?;
+            txn.commit()?;
+            Ok(items)
         })
         .await
         .map_err(|_| StorageError::General)?
     }
 
+    /// Forget processed requests older than `dedup_window` and the oldest ones above
+    /// `dedup_max_entries`
+    pub async fn cleanup_processed(&self) -> Result<usize, StorageError> {
+        let env = self.env.clone();
+        let requests_db = self.requests_db;
+        let window = self.config.dedup_window as f64;
+        let max_entries = self.config.dedup_max_entries;
+        let current_time = self.time.now();
+
+        task::spawn_blocking(move || {
+            let mut txn = env.write_txn().unwrap();
+
+            let mut entries: Vec<(Vec<u8>, f64)> = requests_db
+                .iter(&txn)
+                .unwrap()
+                .map(|item| {
+                    let (key_bytes, bytes) = item.unwrap();
+                    let processed_at = from_msgpack::<ProcessedRequest>(bytes)
+                        .map(|p| p.processed_at)
+                        .unwrap_or(0.0);
+                    (key_bytes.to_vec(), processed_at)
+                })
+                .collect();
+
+            let mut to_delete: Vec<Vec<u8>> = Vec::new();
+            entries.retain(|(key, processed_at)| {
+                let expired = current_time - processed_at > window;
+                if expired {
+                    to_delete.push(key.clone());
+                }
+                !expired
+            });
+            if entries.len() > max_entries {
+                entries.sort_by(|a, b| a.1.total_cmp(&b.1));
+                let extra = entries.len() - max_entries;
+                to_delete.extend(entries.drain(..extra).map(|(key, _)| key));
+            }
+
+            for key in &to_delete {
+                requests_db.delete(&mut txn, key).unwrap();
+            }
+            txn.commit().unwrap();
+            to_delete.len()
+        })
+        .await
+        .map_err(|_| StorageError::General)
+    }
+
     pub fn close(self) {
         // In RUST Env close automatically, when leave from scope
         // But we call this method for long support
         // heed do not required close, because we use RAII
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir::TempDir;
+    use crate::utils::time::{VirtualClock, get_now_f64};
+    use std::time::Duration;
+
+    /// Storage in a new temp dir, the storage is dropped before its dir if bound after it
+    fn open_storage(name: &str) -> (TempDir, Storage) {
+        let dir = TempDir::new(&format!("storage-{}", name));
+        let storage = Storage::new(StorageConfig {
+            data_dir: dir.path().to_path_buf(),
+            max_storage_size: 64 * 1024 * 1024,
+            ..StorageConfig::default()
+        })
+        .unwrap();
+        (dir, storage)
+    }
+
+    fn origin(publisher: &[u8], published_at: f64, hops: u32) -> StoreOrigin {
+        StoreOrigin {
+            publisher: publisher.to_vec(),
+            published_at,
+            hops,
+            ..StoreOrigin::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn tombstone_of_other_publisher_is_refused() {
+        let (_dir, storage) = open_storage("other-publisher");
+        let key = Key::new(b"record".to_vec());
+        let now = get_now_f64();
+        storage
+            .put_with_origin(
+                key.clone(),
+                b"value".to_vec(),
+                3600,
+                origin(b"alice", now, 1),
+            )
+            .await
+            .unwrap();
+
+        let deleted = storage
+            .tombstone(key.clone(), 3600, origin(b"mallory", now + 1.0, 1))
+            .await
+            .unwrap();
+        assert!(!deleted);
+        assert_eq!(
+            storage.get(key.clone()).await.unwrap(),
+            Some(b"value".to_vec())
+        );
+
+        let deleted = storage
+            .tombstone(key.clone(), 3600, origin(b"alice", now + 1.0, 1))
+            .await
+            .unwrap();
+        assert!(deleted);
+        assert_eq!(storage.get(key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn proven_value_is_deleted_only_by_proven_tombstone() {
+        let (_dir, storage) = open_storage("proven-value");
+        let key = Key::new(b"record".to_vec());
+        let now = get_now_f64();
+        let proof = |signed_at| PublisherProof {
+            public_key: b"alice-key".to_vec(),
+            signed_at,
+            signature: b"signature".to_vec(),
+        };
+        let proven = |published_at| StoreOrigin {
+            proof: Some(proof(published_at)),
+            ..origin(b"alice", published_at, 1)
+        };
+        storage
+            .put_with_origin(key.clone(), b"value".to_vec(), 3600, proven(now))
+            .await
+            .unwrap();
+
+        let deleted = storage
+            .tombstone(key.clone(), 3600, origin(b"alice", now + 1.0, 1))
+            .await
+            .unwrap();
+        assert!(!deleted);
+        assert!(storage.get(key.clone()).await.unwrap().is_some());
+
+        let deleted = storage
+            .tombstone(key.clone(), 3600, proven(now + 1.0))
+            .await
+            .unwrap();
+        assert!(deleted);
+        assert_eq!(storage.get(key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn tombstone_does_not_delete_newer_publish() {
+        let (_dir, storage) = open_storage("newer-publish");
+        let key = Key::new(b"record".to_vec());
+        let now = get_now_f64();
+        storage
+            .put_with_origin(
+                key.clone(),
+                b"value".to_vec(),
+                3600,
+                origin(b"alice", now, 1),
+            )
+            .await
+            .unwrap();
+
+        let deleted = storage
+            .tombstone(key.clone(), 3600, origin(b"alice", now - 10.0, 1))
+            .await
+            .unwrap();
+        assert!(!deleted);
+        assert_eq!(storage.get(key).await.unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn data_of_unknown_publisher_is_deleted_only_locally() {
+        let (_dir, storage) = open_storage("unknown-publisher");
+        let key = Key::new(b"record".to_vec());
+        let now = get_now_f64();
+        storage
+            .put_with_origin(key.clone(), b"value".to_vec(), 3600, origin(b"", now, 1))
+            .await
+            .unwrap();
+
+        let remote = storage
+            .tombstone(key.clone(), 3600, origin(b"mallory", now + 1.0, 1))
+            .await
+            .unwrap();
+        assert!(!remote);
+
+        let local = storage
+            .tombstone(key.clone(), 3600, origin(b"us", now + 1.0, 0))
+            .await
+            .unwrap();
+        assert!(local);
+        assert_eq!(storage.get(key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn ttl_is_counted_by_the_clock_of_the_storage() {
+        let clock = Arc::new(VirtualClock::starting_now());
+        let dir = TempDir::new("storage-virtual-clock");
+        let storage = Storage::with_clock(
+            StorageConfig {
+                data_dir: dir.path().to_path_buf(),
+                max_storage_size: 64 * 1024 * 1024,
+                ..StorageConfig::default()
+            },
+            clock.clone(),
+        )
+        .unwrap();
+        let (_other_dir, other) = open_storage("system-clock");
+        let key = Key::new(b"expiring".to_vec());
+        storage
+            .put(key.clone(), b"value".to_vec(), 10)
+            .await
+            .unwrap();
+        other.put(key.clone(), b"value".to_vec(), 10).await.unwrap();
+
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(storage.get(key.clone()).await.unwrap(), None);
+        assert!(other.get(key).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn keys_are_read_by_range() {
+        let (_dir, storage) = open_storage("key-range");
+        for first in [0x10u8, 0x20, 0x2F, 0x30] {
+            storage
+                .put(Key::new(vec![first; 32]), b"value".to_vec(), 3600)
+                .await
+                .unwrap();
+        }
+
+        let keys = storage
+            .keys_in_range(vec![0x20], Some(vec![0x30]))
+            .await
+            .unwrap();
+        assert_eq!(
+            keys,
+            vec![Key::new(vec![0x20; 32]), Key::new(vec![0x2F; 32])]
+        );
+        let tail = storage.keys_in_range(vec![0x2F], None).await.unwrap();
+        assert_eq!(tail.len(), 2);
+        assert_eq!(storage.keys().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn write_back_put_is_checked_before_it_is_queued() {
+        let (_dir, storage) = open_storage("write-back-checked");
+        let (deleted, queued) = (Key::new(b"deleted".to_vec()), Key::new(b"queued".to_vec()));
+        let now = get_now_f64();
+        storage
+            .tombstone(deleted.clone(), 3600, origin(b"alice", now, 0))
+            .await
+            .unwrap();
+
+        let older = storage
+            .put_with_durability(
+                deleted,
+                b"old".to_vec(),
+                3600,
+                origin(b"alice", now - 1.0, 1),
+                Durability::WriteBack,
+            )
+            .await;
+        assert!(matches!(older, Err(StorageError::Deleted)));
+
+        storage
+            .put_with_durability(
+                queued.clone(),
+                b"value".to_vec(),
+                3600,
+                origin(b"alice", now, 1),
+                Durability::WriteBack,
+            )
+            .await
+            .unwrap();
+        storage.record_access(&queued);
+        let flushed = storage.flush_access().await.unwrap();
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(
+            storage.access_stats(queued).await.unwrap().unwrap().reads,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn tombstone_rejects_older_copy_of_same_publisher_only() {
+        let (_dir, storage) = open_storage("rejects-copy");
+        let key = Key::new(b"record".to_vec());
+        let now = get_now_f64();
+        storage
+            .tombstone(key.clone(), 3600, origin(b"alice", now, 0))
+            .await
+            .unwrap();
+
+        let older = storage
+            .put_with_origin(
+                key.clone(),
+                b"old".to_vec(),
+                3600,
+                origin(b"alice", now - 1.0, 1),
+            )
+            .await;
+        assert!(matches!(older, Err(StorageError::Deleted)));
+
+        storage
+            .put_with_origin(
+                key.clone(),
+                b"other".to_vec(),
+                3600,
+                origin(b"bob", now - 1.0, 1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(storage.get(key).await.unwrap(), Some(b"other".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn failed_write_returns_error_and_undoes_quotas() {
+        let (_dir, storage) = open_storage("long-key");
+        let long_key = Key::new(vec![7; crate::storage::keys::MAX_KEY_SIZE + 1]);
+
+        let put = storage
+            .put_with_origin(
+                long_key.clone(),
+                b"value".to_vec(),
+                3600,
+                origin(b"alice", get_now_f64(), 0),
+            )
+            .await;
+        assert!(put.is_err());
+        assert_eq!(storage.quotas().used(b"alice"), 0);
+
+        let stored = storage
+            .transaction(move |txn| {
+                txn.put(
+                    &Key::new(b"short".to_vec()),
+                    b"value",
+                    3600,
+                    origin(b"alice", 0.0, 0),
+                )?;
+                txn.put(&long_key, b"value", 3600, origin(b"alice", 0.0, 0))
+            })
+            .await;
+        assert!(stored.is_err());
+        assert_eq!(storage.quotas().used(b"alice"), 0);
+        assert_eq!(
+            storage.get(Key::new(b"short".to_vec())).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn swap_with_stale_hash_conflicts_and_keeps_value() {
+        let (_dir, storage) = open_storage("cas-conflict");
+        let key = Key::new(b"counter".to_vec());
+
+        let created = storage
+            .compare_and_swap(key.clone(), None, b"1".to_vec(), 3600, origin(b"a", 1.0, 0))
+            .await
+            .unwrap();
+        assert_eq!(created, CasOutcome::Applied);
+
+        // Second creator expects no value and loses
+        let again = storage
+            .compare_and_swap(key.clone(), None, b"x".to_vec(), 3600, origin(b"a", 2.0, 0))
+            .await
+            .unwrap();
+        assert_eq!(again, CasOutcome::Conflict(Some(b"1".to_vec())));
+
+        let stale = storage
+            .compare_and_swap(
+                key.clone(),
+                Some(hash_key(b"0")),
+                b"2".to_vec(),
+                3600,
+                origin(b"a", 3.0, 0),
+            )
+            .await
+            .unwrap();
+        assert_eq!(stale, CasOutcome::Conflict(Some(b"1".to_vec())));
+        assert_eq!(storage.get(key.clone()).await.unwrap(), Some(b"1".to_vec()));
+
+        let swapped = storage
+            .compare_and_swap(
+                key.clone(),
+                Some(hash_key(b"1")),
+                b"2".to_vec(),
+                3600,
+                origin(b"a", 4.0, 0),
+            )
+            .await
+            .unwrap();
+        assert_eq!(swapped, CasOutcome::Applied);
+        assert_eq!(storage.get(key).await.unwrap(), Some(b"2".to_vec()));
+        assert_eq!(storage.quotas().used(b"a"), 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_swaps_of_same_value_apply_once() {
+        let (_dir, storage) = open_storage("cas-race");
+        let storage = Arc::new(storage);
+        let key = Key::new(b"lock".to_vec());
+
+        let swaps = (0..8u8).map(|i| {
+            let storage = storage.clone();
+            let key = key.clone();
+            tokio::spawn(async move {
+                storage
+                    .compare_and_swap(key, None, vec![i], 3600, origin(b"a", 1.0, 0))
+                    .await
+                    .unwrap()
+            })
+        });
+        let outcomes = futures::future::join_all(swaps).await;
+        let applied = outcomes
+            .iter()
+            .filter(|outcome| matches!(outcome, Ok(CasOutcome::Applied)))
+            .count();
+        assert_eq!(applied, 1);
+    }
+
+    #[tokio::test]
+    async fn swap_and_its_answer_are_written_together() {
+        let (_dir, storage) = open_storage("cas-answer");
+        let key = Key::new(b"record".to_vec());
+
+        let applied = storage
+            .transaction(move |txn| {
+                let outcome =
+                    txn.compare_and_swap(&key, None, b"value", 3600, origin(b"a", 1.0, 0))?;
+                txn.record_processed(
+                    b"request",
+                    1,
+                    vec![u8::from(outcome == CasOutcome::Applied)],
+                )?;
+                Ok(outcome)
+            })
+            .await
+            .unwrap();
+        assert_eq!(applied, CasOutcome::Applied);
+        let answer = storage
+            .processed_response(b"request".to_vec())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(answer.response, vec![1]);
+
+        // Failed write leaves neither the value nor the answer
+        let failed = storage
+            .transaction(move |txn| {
+                txn.compare_and_swap(
+                    &Key::new(b"other".to_vec()),
+                    None,
+                    b"value",
+                    3600,
+                    origin(b"a", 1.0, 0),
+                )?;
+                txn.record_processed(b"failed", 1, vec![1])?;
+                Err::<(), _>(StorageError::General)
+            })
+            .await;
+        assert!(failed.is_err());
+        assert!(
+            storage
+                .processed_response(b"failed".to_vec())
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            storage.get(Key::new(b"other".to_vec())).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn records_of_type_are_paged_newest_first() {
+        let (_dir, storage) = open_storage("type-index");
+        let now = get_now_f64();
+        for i in 0..5 {
+            let origin = origin(b"alice", now - 100.0 + i as f64, 0).with_record_type("thread");
+            storage
+                .put_with_origin(Key::new(vec![i]), vec![i], 3600, origin)
+                .await
+                .unwrap();
+        }
+        let untyped = origin(b"alice", now, 0);
+        storage
+            .put_with_origin(Key::new(b"other".to_vec()), b"x".to_vec(), 3600, untyped)
+            .await
+            .unwrap();
+        storage
+            .tombstone(Key::new(vec![3]), 3600, origin(b"alice", now, 0))
+            .await
+            .unwrap();
+
+        let first = storage
+            .records_by_type_page("thread".to_string(), 0.0, None, 2)
+            .await
+            .unwrap();
+        let keys: Vec<Vec<u8>> = first.records.iter().map(|r| r.key.to_vec()).collect();
+        assert_eq!(keys, vec![vec![4], vec![2]]);
+        let second = storage
+            .records_by_type_page("thread".to_string(), 0.0, first.next, 2)
+            .await
+            .unwrap();
+        let keys: Vec<Vec<u8>> = second.records.iter().map(|r| r.key.to_vec()).collect();
+        assert_eq!(keys, vec![vec![1], vec![0]]);
+        assert!(second.next.is_none());
+
+        let recent = storage
+            .records_by_type("thread".to_string(), now - 98.5, 10)
+            .await
+            .unwrap();
+        assert_eq!(recent.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn inbox_keeps_items_once_until_taken() {
+        let (_dir, storage) = open_storage("inbox");
+        assert!(
+            storage
+                .push_inbox(b"a".to_vec(), b"1".to_vec(), 2)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !storage
+                .push_inbox(b"a".to_vec(), b"2".to_vec(), 2)
+                .await
+                .unwrap()
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        assert!(
+            storage
+                .push_inbox(b"b".to_vec(), b"3".to_vec(), 2)
+                .await
+                .unwrap()
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        assert!(
+            storage
+                .push_inbox(b"c".to_vec(), b"4".to_vec(), 2)
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(
+            storage.take_inbox().await.unwrap(),
+            vec![b"3".to_vec(), b"4".to_vec()]
+        );
+        assert!(storage.take_inbox().await.unwrap().is_empty());
+    }
+}
@@ -1,8 +1,18 @@
+/// Thread metadata with recent messages packed into one replicated record
+pub mod bundle;
+/// Persistent store of threads and messages seen by the API client
+pub mod client_store;
+/// Compression of large stored values
+pub mod compression;
+/// Values stored once by hash and shared by keys
+pub mod content;
 /// Module for data scheme in protocol
 ///
 /// They convert bytes in to the rust object for using in work.
 /// Also, this module can describe the style of content in threads and messages of the network.
 pub mod data_types;
+/// Watchdog of free disk space which switches the node to degraded mode
+pub mod disk;
 /// This module standardize the keys in network
 ///
 /// It means that by this module anyone can use thread id and choose one uniq hash for data
@@ -11,3 +21,11 @@ pub mod keys;
 ///
 /// Work with TTL and responsible for storaging data on user device
 pub mod main;
+/// Signatures of original publishers over stored records
+pub mod proof;
+/// Stored bytes and quotas of single publishers
+pub mod quota;
+/// Counters of storage operations and size
+pub mod stats;
+/// Index of typed records by time of publish
+pub mod type_index;
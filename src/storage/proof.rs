@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "signatures")]
+use crate::utils::crypto::{NodeSigner, node_id_from_public_key_der, verify_signature};
+#[cfg(feature = "signatures")]
+use sha2::{Digest, Sha256};
+
+/// Signature of the original publisher over the record or over its deletion
+///
+/// Proof travels with the record through replication, so every holder can check who
+/// published it. Records without valid proof are attributed to the node which sent them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublisherProof {
+    /// DER of the publisher public key, its SHA-1 is the node ID of the publisher
+    pub public_key: Vec<u8>,
+    /// Time of the publish or deletion by the clock of the publisher
+    pub signed_at: f64,
+    pub signature: Vec<u8>,
+}
+
+#[cfg(feature = "signatures")]
+impl PublisherProof {
+    /// Sign the record by our node, `value` is None for deletion of the key
+    pub fn issue(
+        signer: &NodeSigner,
+        key: &[u8],
+        value: Option<&[u8]>,
+        signed_at: f64,
+        record_type: &str,
+    ) -> Self {
+        let data = Self::signed_bytes(key, value, signed_at, record_type);
+        Self {
+            public_key: signer.public_key_der().to_vec(),
+            signed_at,
+            signature: signer.sign(&data),
+        }
+    }
+
+    /// Bytes which are covered by the signature
+    fn signed_bytes(
+        key: &[u8],
+        value: Option<&[u8]>,
+        signed_at: f64,
+        record_type: &str,
+    ) -> Vec<u8> {
+        let mut data = Vec::with_capacity(key.len() + record_type.len() + 48);
+        match value {
+            Some(value) => {
+                data.push(1);
+                data.extend_from_slice(&Sha256::digest(value));
+            }
+            None => data.push(0),
+        }
+        data.extend_from_slice(&signed_at.to_be_bytes());
+        data.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        data.extend_from_slice(key);
+        data.extend_from_slice(record_type.as_bytes());
+        data
+    }
+
+    /// Check that proof is signed by `publisher` for this record
+    pub fn verify(
+        &self,
+        publisher: &[u8],
+        key: &[u8],
+        value: Option<&[u8]>,
+        record_type: &str,
+    ) -> bool {
+        self.signed_at.is_finite()
+            && !publisher.is_empty()
+            && node_id_from_public_key_der(&self.public_key)[..] == publisher[..]
+            && verify_signature(
+                &self.public_key,
+                &Self::signed_bytes(key, value, self.signed_at, record_type),
+                &self.signature,
+            )
+    }
+}
+
+#[cfg(all(test, feature = "signatures"))]
+mod tests {
+    use super::*;
+    use crate::utils::crypto::generate_keypair;
+
+    fn signer() -> NodeSigner {
+        let (private_key, public_key) = generate_keypair();
+        let node_id = crate::utils::crypto::node_id_from_public_key(&public_key);
+        NodeSigner::new(private_key, node_id.to_vec())
+    }
+
+    #[test]
+    fn proof_is_bound_to_publisher_and_record() {
+        let signer = signer();
+        let publisher = signer.node_id().to_vec();
+        let proof = PublisherProof::issue(&signer, b"key", None, 100.0, "");
+
+        assert!(proof.verify(&publisher, b"key", None, ""));
+        assert!(!proof.verify(&[7u8; 20], b"key", None, ""));
+        assert!(!proof.verify(&[], b"key", None, ""));
+        assert!(!proof.verify(&publisher, b"other", None, ""));
+        assert!(!proof.verify(&publisher, b"key", Some(b"value"), ""));
+        assert!(!proof.verify(&publisher, b"key", None, "thread"));
+    }
+
+    #[test]
+    fn changed_time_breaks_proof() {
+        let signer = signer();
+        let publisher = signer.node_id().to_vec();
+        let mut proof = PublisherProof::issue(&signer, b"key", Some(b"value"), 100.0, "");
+        assert!(proof.verify(&publisher, b"key", Some(b"value"), ""));
+
+        proof.signed_at = 200.0;
+        assert!(!proof.verify(&publisher, b"key", Some(b"value"), ""));
+        proof.signed_at = f64::INFINITY;
+        assert!(!proof.verify(&publisher, b"key", Some(b"value"), ""));
+    }
+}
@@ -0,0 +1,115 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+
+/// Stored bytes of one publisher
+#[derive(Debug, Clone, Serialize)]
+pub struct PublisherUsage {
+    /// Hex of the publisher node ID
+    pub publisher: String,
+    pub bytes: u64,
+    /// Quota of the publisher in bytes _(None - unlimited)_
+    pub limit: Option<u64>,
+}
+
+/// Bytes stored per publisher and their quotas
+///
+/// Every publisher gets `default_limit` unless it has its own limit. Data from the
+/// network is charged to its proven publisher or to the node which sent it, only local
+/// data of unknown publisher _(empty ID)_ is not limited.
+pub struct PublisherQuotas {
+    default_limit: Option<u64>,
+    /// Publisher node ID -> its own limit _(None - unlimited)_
+    limits: RwLock<HashMap<Vec<u8>, Option<u64>>>,
+    /// Publisher node ID -> stored bytes
+    used: Mutex<HashMap<Vec<u8>, u64>>,
+}
+
+impl PublisherQuotas {
+    pub fn new(default_limit: Option<u64>) -> Self {
+        Self {
+            default_limit,
+            limits: RwLock::new(HashMap::new()),
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set own limit of the publisher _(None - unlimited)_
+    pub fn set_limit(&self, publisher: &[u8], limit: Option<u64>) {
+        self.limits
+            .write()
+            .unwrap()
+            .insert(publisher.to_vec(), limit);
+    }
+
+    /// Quota of the publisher in bytes _(None - unlimited)_
+    pub fn limit(&self, publisher: &[u8]) -> Option<u64> {
+        if publisher.is_empty() {
+            return None;
+        }
+        match self.limits.read().unwrap().get(publisher) {
+            Some(limit) => *limit,
+            None => self.default_limit,
+        }
+    }
+
+    pub fn used(&self, publisher: &[u8]) -> u64 {
+        self.used
+            .lock()
+            .unwrap()
+            .get(publisher)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Publisher may replace `previous` bytes of its data with `size` bytes
+    pub fn allows(&self, publisher: &[u8], previous: u64, size: u64) -> bool {
+        self.limit(publisher)
+            .is_none_or(|limit| self.used(publisher).saturating_sub(previous) + size <= limit)
+    }
+
+    /// Value of `size` bytes of the publisher was stored
+    pub(crate) fn record_add(&self, publisher: &[u8], size: u64) {
+        if publisher.is_empty() {
+            return;
+        }
+        *self
+            .used
+            .lock()
+            .unwrap()
+            .entry(publisher.to_vec())
+            .or_default() += size;
+    }
+
+    /// Value of `size` bytes of the publisher was removed
+    pub(crate) fn record_remove(&self, publisher: &[u8], size: u64) {
+        let mut used = self.used.lock().unwrap();
+        if let Some(bytes) = used.get_mut(publisher) {
+            *bytes = bytes.saturating_sub(size);
+            if *bytes == 0 {
+                used.remove(publisher);
+            }
+        }
+    }
+
+    /// Publishers which stored the most bytes, the biggest first
+    pub fn top(&self, count: usize) -> Vec<PublisherUsage> {
+        let mut usage: Vec<(Vec<u8>, u64)> = self
+            .used
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(publisher, bytes)| (publisher.clone(), *bytes))
+            .collect();
+        usage.sort_by_key(|(_, bytes)| std::cmp::Reverse(*bytes));
+        usage
+            .into_iter()
+            .take(count)
+            .map(|(publisher, bytes)| PublisherUsage {
+                limit: self.limit(&publisher),
+                publisher: hex::encode(publisher),
+                bytes,
+            })
+            .collect()
+    }
+}
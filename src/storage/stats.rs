@@ -0,0 +1,132 @@
+use serde::Serialize;
+use std::time::Duration;
+
+/// Time over which operations per second are averaged
+pub const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Max count of keys with reads waiting for the flush, reads of other keys are not counted
+pub const MAX_PENDING_ACCESS: usize = 10_000;
+
+/// Reads of one stored value by peers
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct KeyAccess {
+    pub reads: u64,
+    /// Time of the last read _(0.0 if never read)_
+    pub last_access: f64,
+}
+
+impl KeyAccess {
+    /// Add reads counted later
+    pub fn merge(&mut self, other: &KeyAccess) {
+        self.reads += other.reads;
+        self.last_access = self.last_access.max(other.last_access);
+    }
+}
+
+/// Snapshot of storage counters
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StorageStats {
+    /// Count of stored keys _(expired but not yet cleaned keys included)_
+    pub keys: u64,
+    /// Summary size of stored values in bytes
+    pub bytes_used: u64,
+    /// Count of writes since start
+    pub puts: u64,
+    /// Count of reads since start
+    pub gets: u64,
+    /// Count of deletes since start
+    pub deletes: u64,
+    /// Count of expired records removed since start
+    pub cleanup_deletions: u64,
+    pub puts_per_sec: f64,
+    pub gets_per_sec: f64,
+    pub deletes_per_sec: f64,
+}
+
+/// Counters of storage operations
+///
+/// Rates are measured over the last full [`RATE_WINDOW`], during the first window over
+/// the time since start.
+pub(crate) struct StorageCounters {
+    stats: StorageStats,
+    /// Time of the window start in seconds
+    window_started: f64,
+    /// Totals of puts, gets and deletes at the window start
+    window_base: [u64; 3],
+    /// Rates of the last full window
+    last_rates: Option<[f64; 3]>,
+}
+
+impl StorageCounters {
+    /// Counters of storage which already holds `keys` values of `bytes` summary size
+    pub(crate) fn new(keys: u64, bytes: u64, now: f64) -> Self {
+        Self {
+            stats: StorageStats {
+                keys,
+                bytes_used: bytes,
+                ..StorageStats::default()
+            },
+            window_started: now,
+            window_base: [0; 3],
+            last_rates: None,
+        }
+    }
+
+    /// Value of `size` bytes written over value of `previous` size _(None if key is new)_
+    pub(crate) fn record_put(&mut self, previous: Option<usize>, size: usize) {
+        self.stats.puts += 1;
+        self.replace(previous, Some(size));
+    }
+
+    pub(crate) fn record_get(&mut self) {
+        self.stats.gets += 1;
+    }
+
+    /// Value of `previous` size deleted _(None if key was absent)_
+    pub(crate) fn record_delete(&mut self, previous: Option<usize>) {
+        self.stats.deletes += 1;
+        self.replace(previous, None);
+    }
+
+    /// `count` expired values of `bytes` summary size removed
+    pub(crate) fn record_cleanup(&mut self, count: u64, bytes: u64) {
+        self.stats.cleanup_deletions += count;
+        self.stats.keys = self.stats.keys.saturating_sub(count);
+        self.stats.bytes_used = self.stats.bytes_used.saturating_sub(bytes);
+    }
+
+    fn replace(&mut self, previous: Option<usize>, current: Option<usize>) {
+        if let Some(size) = previous {
+            self.stats.keys = self.stats.keys.saturating_sub(1);
+            self.stats.bytes_used = self.stats.bytes_used.saturating_sub(size as u64);
+        }
+        if let Some(size) = current {
+            self.stats.keys += 1;
+            self.stats.bytes_used += size as u64;
+        }
+    }
+
+    pub(crate) fn snapshot(&mut self, now: f64) -> StorageStats {
+        let totals = [self.stats.puts, self.stats.gets, self.stats.deletes];
+        let seconds = (now - self.window_started).max(0.0);
+        let current_rates = totals.map(|t| t as f64 / seconds.max(1.0));
+
+        if seconds >= RATE_WINDOW.as_secs_f64() {
+            let mut rates = [0.0; 3];
+            for (i, rate) in rates.iter_mut().enumerate() {
+                *rate = (totals[i] - self.window_base[i]) as f64 / seconds;
+            }
+            self.last_rates = Some(rates);
+            self.window_started = now;
+            self.window_base = totals;
+        }
+
+        let [puts, gets, deletes] = self.last_rates.unwrap_or(current_rates);
+        StorageStats {
+            puts_per_sec: puts,
+            gets_per_sec: gets,
+            deletes_per_sec: deletes,
+            ..self.stats.clone()
+        }
+    }
+}
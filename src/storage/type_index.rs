@@ -0,0 +1,128 @@
+use heed::types::Bytes;
+use heed::{Database, Env, RoTxn, RwTxn};
+use std::ops::Bound;
+
+use crate::exceptions::StorageError;
+use crate::utils::crypto::hash_key;
+
+/// Bytes of the record type hash at the start of index entries
+const TYPE_PREFIX_LEN: usize = 16;
+/// Length of index entry: type hash, publish time and hash of the record key
+const ENTRY_LEN: usize = TYPE_PREFIX_LEN + 8 + 32;
+
+/// Keys of typed records ordered by type and time of publish
+///
+/// Entry is the hash of the record type, the publish time and the hash of the record key,
+/// its value is the record key. Records of one type are read the newest first without a
+/// scan of all metadata. Like the content store it is changed inside the write
+/// transaction of the key, only records which are not deleted have entries.
+#[derive(Clone, Copy)]
+pub(crate) struct TypeIndex {
+    entries: Database<Bytes, Bytes>,
+}
+
+impl TypeIndex {
+    pub(crate) fn open(env: &Env, txn: &mut RwTxn) -> heed::Result<Self> {
+        Ok(Self {
+            entries: env.create_database(txn, Some("type_index"))?,
+        })
+    }
+
+    pub(crate) fn is_empty(&self, txn: &RoTxn) -> Result<bool, StorageError> {
+        Ok(self.entries.is_empty(txn)?)
+    }
+
+    /// Add the record, untyped records are not indexed
+    pub(crate) fn insert(
+        &self,
+        txn: &mut RwTxn,
+        record_type: &str,
+        published_at: f64,
+        key: &[u8],
+    ) -> Result<(), StorageError> {
+        if record_type.is_empty() {
+            return Ok(());
+        }
+        let entry = Self::entry(record_type, published_at, key);
+        Ok(self.entries.put(txn, &entry, key)?)
+    }
+
+    /// Remove the record added with the same type and time
+    pub(crate) fn remove(
+        &self,
+        txn: &mut RwTxn,
+        record_type: &str,
+        published_at: f64,
+        key: &[u8],
+    ) -> Result<(), StorageError> {
+        if record_type.is_empty() {
+            return Ok(());
+        }
+        self.entries
+            .delete(txn, &Self::entry(record_type, published_at, key))?;
+        Ok(())
+    }
+
+    /// Give records of the type published at `since` or later to `visit`, the newest first
+    ///
+    /// Walk starts below the `cursor` entry if it is set and stops when `visit` returns
+    /// `false`. `visit` gets the entry, which is the cursor of the next walk, and the key.
+    pub(crate) fn walk_newest<F>(
+        &self,
+        txn: &RoTxn,
+        record_type: &str,
+        since: f64,
+        cursor: Option<&[u8]>,
+        mut visit: F,
+    ) -> Result<(), StorageError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<bool, StorageError>,
+    {
+        let lowest = Self::entry_bound(record_type, since, 0);
+        let highest = Self::entry_bound(record_type, f64::INFINITY, 0xFF);
+        let upper = match cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Included(&highest[..]),
+        };
+        let range = (Bound::Included(&lowest[..]), upper);
+        for item in self.entries.rev_range(txn, &range)? {
+            let (entry, key) = item?;
+            if !visit(entry, key)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(record_type: &str, published_at: f64, key: &[u8]) -> Vec<u8> {
+        let mut entry = Self::type_prefix(record_type);
+        entry.extend_from_slice(&Self::ordered_time(published_at));
+        entry.extend_from_slice(&hash_key(key));
+        entry
+    }
+
+    /// Entry with the filled key hash, lower or upper bound of entries of the time
+    fn entry_bound(record_type: &str, published_at: f64, fill: u8) -> Vec<u8> {
+        let mut entry = Self::type_prefix(record_type);
+        entry.extend_from_slice(&Self::ordered_time(published_at));
+        entry.resize(ENTRY_LEN, fill);
+        entry
+    }
+
+    fn type_prefix(record_type: &str) -> Vec<u8> {
+        let mut prefix = Vec::with_capacity(ENTRY_LEN);
+        prefix.extend_from_slice(&hash_key(record_type.as_bytes())[..TYPE_PREFIX_LEN]);
+        prefix
+    }
+
+    /// Big endian bytes of the time which sort like the times themselves
+    fn ordered_time(time: f64) -> [u8; 8] {
+        let bits = time.to_bits();
+        let ordered = if time.is_sign_negative() {
+            !bits
+        } else {
+            bits | (1 << 63)
+        };
+        ordered.to_be_bytes()
+    }
+}
@@ -0,0 +1,175 @@
+use crate::storage::keys::Key;
+use crate::utils::crypto::hash_key;
+
+/// Max size of the filter in bytes _(it must fit in one UDP message)_
+pub const MAX_FILTER_BYTES: usize = 4096;
+/// Filters with a larger share of set bits say "maybe present" for too many keys
+pub const MAX_FILL_RATIO: f64 = 0.6;
+
+/// Bloom filter of keys
+///
+/// Answer "maybe present" or "surely absent" for a key with fixed memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    /// Bit array
+    bits: Vec<u8>,
+    /// Count of hash functions
+    hash_count: u32,
+}
+
+impl BloomFilter {
+    /// Filter for `expected_items` keys with `false_positive_rate` probability of errors
+    ///
+    /// Size follows the count of items. Return `None` if such filter is bigger than
+    /// [`MAX_FILTER_BYTES`], a smaller one would be saturated.
+    pub fn with_rate(expected_items: usize, false_positive_rate: f64) -> Option<Self> {
+        let n = expected_items.max(1) as f64;
+        let p = false_positive_rate.clamp(1e-6, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+
+        let bits_count = (-n * p.ln() / (ln2 * ln2)).ceil() as usize;
+        let bytes = bits_count.div_ceil(8).max(8);
+        if bytes > MAX_FILTER_BYTES {
+            return None;
+        }
+        let hash_count = ((bytes * 8) as f64 / n * ln2).round().clamp(1.0, 16.0) as u32;
+
+        Some(Self {
+            bits: vec![0u8; bytes],
+            hash_count,
+        })
+    }
+
+    /// Create filter from list of keys, see [`BloomFilter::with_rate`]
+    pub fn from_keys<'a>(
+        keys: impl ExactSizeIterator<Item = &'a Key>,
+        false_positive_rate: f64,
+    ) -> Option<Self> {
+        let mut filter = Self::with_rate(keys.len(), false_positive_rate)?;
+        for key in keys {
+            filter.insert(key);
+        }
+        Some(filter)
+    }
+
+    /// Share of set bits
+    pub fn fill_ratio(&self) -> f64 {
+        let ones: u32 = self.bits.iter().map(|byte| byte.count_ones()).sum();
+        ones as f64 / (self.bits.len() * 8) as f64
+    }
+
+    /// More than [`MAX_FILL_RATIO`] of bits are set, the filter tells little about keys
+    pub fn is_saturated(&self) -> bool {
+        self.fill_ratio() > MAX_FILL_RATIO
+    }
+
+    /// Bit positions of the key _(double hashing over SHA-256 of the key)_
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = usize> + '_ {
+        let hash = hash_key(key);
+        let h1 = u64::from_be_bytes(hash[..8].try_into().unwrap_or_default());
+        let h2 = u64::from_be_bytes(hash[8..16].try_into().unwrap_or_default()) | 1;
+        let bits_count = (self.bits.len() * 8) as u64;
+
+        (0..self.hash_count as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits_count) as usize)
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        let positions: Vec<usize> = self.positions(key).collect();
+        for pos in positions {
+            self.bits[pos / 8] |= 0x80 >> (pos % 8);
+        }
+    }
+
+    /// Check that key may be in the filter
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.positions(key)
+            .all(|pos| self.bits[pos / 8] & (0x80 >> (pos % 8)) != 0)
+    }
+
+    /// Compressed form of the filter
+    ///
+    /// Format: `[hash_count, bytes...]`, where runs of zero bytes are written as
+    /// `0x00, run_length`. Filters of small storages are mostly zeros.
+    pub fn to_compressed(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.bits.len() / 2 + 1);
+        out.push(self.hash_count as u8);
+
+        let mut i = 0;
+        while i < self.bits.len() {
+            if self.bits[i] == 0 {
+                let mut run = 0usize;
+                while i < self.bits.len() && self.bits[i] == 0 && run < u8::MAX as usize {
+                    run += 1;
+                    i += 1;
+                }
+                out.push(0);
+                out.push(run as u8);
+            } else {
+                out.push(self.bits[i]);
+                i += 1;
+            }
+        }
+
+        out
+    }
+
+    /// Restore filter from [`BloomFilter::to_compressed`] form
+    pub fn from_compressed(data: &[u8]) -> Option<Self> {
+        let (&hash_count, body) = data.split_first()?;
+        if hash_count == 0 {
+            return None;
+        }
+
+        let mut bits = Vec::with_capacity(body.len() * 2);
+        let mut iter = body.iter();
+        while let Some(&byte) = iter.next() {
+            if byte == 0 {
+                let run = *iter.next()? as usize;
+                bits.extend(std::iter::repeat_n(0u8, run));
+            } else {
+                bits.push(byte);
+            }
+            if bits.len() > MAX_FILTER_BYTES {
+                return None;
+            }
+        }
+
+        if bits.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            bits,
+            hash_count: hash_count as u32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(count: u32) -> Vec<Key> {
+        (0..count)
+            .map(|i| Key::new(i.to_be_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn filter_follows_key_count_and_saturation_is_detected() {
+        let small = BloomFilter::from_keys(keys(100).iter(), 0.01).unwrap();
+        let large = BloomFilter::from_keys(keys(2000).iter(), 0.01).unwrap();
+        assert!(large.bits.len() > small.bits.len());
+        assert!(keys(2000).iter().all(|key| large.contains(key)));
+        assert!(!large.is_saturated());
+
+        assert!(BloomFilter::from_keys(keys(100_000).iter(), 0.01).is_none());
+
+        let mut full = vec![1u8];
+        full.extend(std::iter::repeat_n(0xFF, 64));
+        let full = BloomFilter::from_compressed(&full).unwrap();
+        assert!(full.is_saturated());
+        assert!(full.contains(b"any key"));
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::utils::time::get_now_f64;
+
+/// In-memory cache with time-to-live of entries
+///
+/// When cache is full, entry which expires first is dropped.
+pub struct TtlCache<K, V> {
+    /// Time of life of one entry in seconds
+    ttl: f64,
+    /// Max count of entries
+    capacity: usize,
+    /// Key -> (value, expiration time)
+    entries: HashMap<K, (V, f64)>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: f64, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Get value if it is not expired
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let now = get_now_f64();
+        match self.entries.get(key) {
+            Some((value, expires_at)) if *expires_at > now => Some(value.clone()),
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Save value, nothing is saved if cache is disabled _(zero ttl or capacity)_
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.ttl <= 0.0 || self.capacity == 0 {
+            return;
+        }
+
+        let now = get_now_f64();
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.entries.retain(|_, (_, expires_at)| *expires_at > now);
+            if self.entries.len() >= self.capacity
+                && let Some(oldest) = self
+                    .entries
+                    .iter()
+                    .min_by(|a, b| a.1.1.total_cmp(&b.1.1))
+                    .map(|(k, _)| k.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(key, (value, now + self.ttl));
+    }
+
+    /// Drop value of the key
+    pub fn invalidate(&mut self, key: &K) {
+        self.entries.remove(key);
+    }
+
+    /// Drop all values
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -1,27 +1,52 @@
-use rsa::{RsaPrivateKey, RsaPublicKey, pkcs8::EncodePublicKey};
+#[cfg(feature = "mailbox")]
+use aes_gcm::aead::{Aead, KeyInit};
+#[cfg(feature = "mailbox")]
+use aes_gcm::{Aes256Gcm, Nonce};
+#[cfg(feature = "mailbox")]
+use rsa::Oaep;
+#[cfg(feature = "signatures")]
+use rsa::pkcs1v15::{Signature, SigningKey, VerifyingKey};
+#[cfg(feature = "signatures")]
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey};
+#[cfg(feature = "signatures")]
+use rsa::signature::{SignatureEncoding, Signer, Verifier};
+#[cfg(feature = "signatures")]
+use rsa::{RsaPrivateKey, RsaPublicKey};
 use sha1::{Digest as Sha1Digest, Sha1};
 use sha2::Sha256;
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Generation of a 160-bit Node ID
 ///
 /// Returns:
 /// - 20 bytes (160 bits) of the node ID
+#[cfg(feature = "signatures")]
 pub fn generate_node_id() -> [u8; 20] {
-    let mut rng = rand::thread_rng();
-    let bits = 2048;
+    let (_, public_key) = generate_keypair();
+    node_id_from_public_key(&public_key)
+}
 
-    let private_key = RsaPrivateKey::new(&mut rng, bits).expect("failed to generate a key");
-    let public_key = RsaPublicKey::from(&private_key);
+/// Generation of a random 160-bit Node ID _(there is no node key without `signatures`)_
+#[cfg(not(feature = "signatures"))]
+pub fn generate_node_id() -> [u8; 20] {
+    rand::random()
+}
 
+/// Node ID of the key owner _(SHA-1 of DER of the public key)_
+#[cfg(feature = "signatures")]
+pub fn node_id_from_public_key(public_key: &RsaPublicKey) -> [u8; 20] {
     let public_key_der = public_key
         .to_public_key_der()
         .expect("failed to encode public key");
+    node_id_from_public_key_der(public_key_der.as_bytes())
+}
 
+/// Node ID from DER encoded public key
+pub fn node_id_from_public_key_der(public_key_der: &[u8]) -> [u8; 20] {
     let mut hasher = Sha1::new();
-    hasher.update(public_key_der.as_bytes());
+    hasher.update(public_key_der);
     let result = hasher.finalize();
 
     let mut node_id = [0u8; 20];
@@ -62,12 +87,22 @@ pub fn hash_key(key: &[u8]) -> [u8; 32] {
 
 /// Generating a key pair for cryptography
 ///
+/// Keys come from OS entropy, see [`generate_keypair_from`] for seeded simulations.
+///
 /// Returns:
 /// - Tuple (private_key, public_key)
+#[cfg(feature = "signatures")]
 pub fn generate_keypair() -> (RsaPrivateKey, RsaPublicKey) {
-    let mut rng = rand::thread_rng();
+    generate_keypair_from(&mut rand::rngs::OsRng)
+}
+
+/// Generating a key pair by the given generator _(reproducible with a seeded one)_
+#[cfg(feature = "signatures")]
+pub fn generate_keypair_from(
+    rng: &mut (impl rand::CryptoRng + rand::RngCore),
+) -> (RsaPrivateKey, RsaPublicKey) {
     let bits = 2048;
-    let private_key = RsaPrivateKey::new(&mut rng, bits).expect("failed to generate a key");
+    let private_key = RsaPrivateKey::new(rng, bits).expect("failed to generate a key");
     let public_key = RsaPublicKey::from(&private_key);
     (private_key, public_key)
 }
@@ -80,6 +115,56 @@ pub fn save_node_id(node_id: &[u8], file_path: &Path) -> io::Result<()> {
     fs::write(file_path, node_id)
 }
 
+/// Write secret data to the file readable only by the owner
+///
+/// Data goes to a temporary file which is renamed over `file_path`, so the file is never
+/// readable by others or half written, whatever was there before.
+pub fn write_private_file(file_path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp_name = file_path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp = file_path.with_file_name(temp_name);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&temp)?;
+    // Mode of the open is not applied to a file which already existed
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    io::Write::write_all(&mut file, data)?;
+    file.sync_all()?;
+    fs::rename(&temp, file_path)
+}
+
+/// Move identity file out of the way, return where it was moved _(None if it did not exist)_
+///
+/// File gets `.retired` suffix, or `.retired.N` with the first free number, so earlier
+/// retired identities are never overwritten.
+pub fn retire_file(file_path: &Path) -> io::Result<Option<PathBuf>> {
+    if !file_path.exists() {
+        return Ok(None);
+    }
+    let mut number = 0;
+    let retired = loop {
+        let mut retired = file_path.as_os_str().to_owned();
+        retired.push(".retired");
+        if number > 0 {
+            retired.push(format!(".{}", number));
+        }
+        let retired = PathBuf::from(retired);
+        if !retired.exists() {
+            break retired;
+        }
+        number += 1;
+    };
+    fs::rename(file_path, &retired)?;
+    Ok(Some(retired))
+}
+
 /// Load Node ID from file
 pub fn load_node_id(file_path: &Path) -> Option<Vec<u8>> {
     if !file_path.exists() {
@@ -94,3 +179,138 @@ pub fn load_node_id(file_path: &Path) -> Option<Vec<u8>> {
 
     Some(node_id)
 }
+
+/// Save private key of the node in file readable only by the owner _(PKCS#8 DER)_
+#[cfg(feature = "signatures")]
+pub fn save_private_key(private_key: &RsaPrivateKey, file_path: &Path) -> io::Result<()> {
+    let der = private_key
+        .to_pkcs8_der()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    write_private_file(file_path, der.as_bytes())
+}
+
+/// Load private key of the node from file
+#[cfg(feature = "signatures")]
+pub fn load_private_key(file_path: &Path) -> Option<RsaPrivateKey> {
+    let der = fs::read(file_path).ok()?;
+    RsaPrivateKey::from_pkcs8_der(&der).ok()
+}
+
+/// Sign data with PKCS#1 v1.5 over SHA-256
+#[cfg(feature = "signatures")]
+pub fn sign_data(private_key: &RsaPrivateKey, data: &[u8]) -> Vec<u8> {
+    SigningKey::<Sha256>::new(private_key.clone())
+        .sign(data)
+        .to_vec()
+}
+
+/// Verify signature made by [`sign_data`] with DER encoded public key
+#[cfg(feature = "signatures")]
+pub fn verify_signature(public_key_der: &[u8], data: &[u8], signature: &[u8]) -> bool {
+    let Ok(public_key) = RsaPublicKey::from_public_key_der(public_key_der) else {
+        return false;
+    };
+    let Ok(signature) = Signature::try_from(signature) else {
+        return false;
+    };
+    VerifyingKey::<Sha256>::new(public_key)
+        .verify(data, &signature)
+        .is_ok()
+}
+
+/// Length of AES-GCM nonce in sealed data
+#[cfg(feature = "mailbox")]
+const SEAL_NONCE_LEN: usize = 12;
+
+/// Encrypt data to the owner of DER encoded public key
+///
+/// Data is encrypted by random AES-256-GCM key, the key is encrypted by RSA-OAEP.
+/// Result is `key length (u16 BE) | encrypted key | nonce | ciphertext`, open it with
+/// [`NodeSigner::open`]. Return `None` if the public key is not valid.
+#[cfg(feature = "mailbox")]
+pub fn seal_for(public_key_der: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let public_key = RsaPublicKey::from_public_key_der(public_key_der).ok()?;
+    let key: [u8; 32] = rand::random();
+    let nonce: [u8; SEAL_NONCE_LEN] = rand::random();
+
+    let encrypted_key = public_key
+        .encrypt(&mut rand::thread_rng(), Oaep::new::<Sha256>(), &key)
+        .ok()?;
+    let ciphertext = Aes256Gcm::new(&key.into())
+        .encrypt(Nonce::from_slice(&nonce), data)
+        .ok()?;
+
+    let mut sealed = Vec::with_capacity(2 + encrypted_key.len() + nonce.len() + ciphertext.len());
+    sealed.extend_from_slice(&(encrypted_key.len() as u16).to_be_bytes());
+    sealed.extend_from_slice(&encrypted_key);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Some(sealed)
+}
+
+/// Private key of the node for signing data of the node
+#[cfg(feature = "signatures")]
+pub struct NodeSigner {
+    /// Made once, so signing does not copy the key
+    signing_key: SigningKey<Sha256>,
+    public_key_der: Vec<u8>,
+    node_id: Vec<u8>,
+}
+
+#[cfg(feature = "signatures")]
+impl NodeSigner {
+    pub fn new(private_key: RsaPrivateKey, node_id: Vec<u8>) -> Self {
+        let public_key_der = RsaPublicKey::from(&private_key)
+            .to_public_key_der()
+            .map(|der| der.as_bytes().to_vec())
+            .unwrap_or_default();
+        Self {
+            signing_key: SigningKey::new(private_key),
+            public_key_der,
+            node_id,
+        }
+    }
+
+    pub fn node_id(&self) -> &[u8] {
+        &self.node_id
+    }
+
+    /// DER of the public key
+    pub fn public_key_der(&self) -> &[u8] {
+        &self.public_key_der
+    }
+
+    /// Node ID is derived from the key, so signatures can be bound to the node
+    pub fn is_bound_to_node_id(&self) -> bool {
+        node_id_from_public_key_der(&self.public_key_der)[..] == self.node_id[..]
+    }
+
+    /// Sign data, check it with [`verify_signature`]
+    pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(data).to_vec()
+    }
+
+    /// Decrypt data made by [`seal_for`] with our public key
+    ///
+    /// Return `None` if data is broken or sealed for other key.
+    #[cfg(feature = "mailbox")]
+    pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        let key_len = u16::from_be_bytes([*sealed.first()?, *sealed.get(1)?]) as usize;
+        let rest = sealed.get(2..)?;
+        if rest.len() < key_len + SEAL_NONCE_LEN {
+            return None;
+        }
+        let (encrypted_key, rest) = rest.split_at(key_len);
+        let (nonce, ciphertext) = rest.split_at(SEAL_NONCE_LEN);
+
+        let key = self
+            .signing_key
+            .as_ref()
+            .decrypt(Oaep::new::<Sha256>(), encrypted_key)
+            .ok()?;
+        let key: [u8; 32] = key.try_into().ok()?;
+        Aes256Gcm::new(&key.into())
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()
+    }
+}
@@ -0,0 +1,89 @@
+use crate::utils::crypto::hash_key;
+
+/// Bits of the hash which choose the register
+const PRECISION: u32 = 10;
+/// Count of registers of the dense form _(standard error is about 3%)_
+const REGISTERS: usize = 1 << PRECISION;
+/// Max count of hashes kept in the sparse form, it takes as much memory as the dense one
+const SPARSE_LIMIT: usize = REGISTERS / 8;
+
+#[derive(Debug, Clone)]
+enum Registers {
+    /// Sorted hashes of items, count is exact
+    Sparse(Vec<u64>),
+    Dense(Box<[u8; REGISTERS]>),
+}
+
+/// HyperLogLog counter of distinct items
+///
+/// Counts items exactly while there are few of them and switches to fixed
+/// 1 KiB of registers after [`SPARSE_LIMIT`] items, so memory does not grow
+/// with the count of items.
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    registers: Registers,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        Self {
+            registers: Registers::Sparse(Vec::new()),
+        }
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        let hash = hash_key(item);
+        let hash = u64::from_be_bytes(hash[..8].try_into().unwrap_or_default());
+
+        match &mut self.registers {
+            Registers::Sparse(hashes) => {
+                if let Err(pos) = hashes.binary_search(&hash) {
+                    hashes.insert(pos, hash);
+                }
+                if hashes.len() > SPARSE_LIMIT {
+                    let mut dense = Box::new([0u8; REGISTERS]);
+                    for &h in hashes.iter() {
+                        Self::update_register(&mut dense, h);
+                    }
+                    self.registers = Registers::Dense(dense);
+                }
+            }
+            Registers::Dense(dense) => Self::update_register(dense, hash),
+        }
+    }
+
+    /// Register is chosen by the first bits of the hash, it keeps the max rank
+    /// _(position of the first one bit)_ of the rest bits
+    fn update_register(dense: &mut [u8; REGISTERS], hash: u64) {
+        let index = (hash >> (64 - PRECISION)) as usize;
+        let rank = ((hash << PRECISION).leading_zeros() + 1).min(64 - PRECISION + 1) as u8;
+        dense[index] = dense[index].max(rank);
+    }
+
+    /// Estimated count of distinct inserted items
+    pub fn estimate(&self) -> usize {
+        let dense = match &self.registers {
+            Registers::Sparse(hashes) => return hashes.len(),
+            Registers::Dense(dense) => dense,
+        };
+
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = dense.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+
+        // Linear counting is more accurate for small counts
+        let zeros = dense.iter().filter(|&&r| r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
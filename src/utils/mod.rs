@@ -1,6 +1,19 @@
+/// Bloom filter for compact sets of keys
+pub mod bloom;
+/// In-memory cache with expiration of entries
+pub mod cache;
 /// Module for work with node_id
 pub mod crypto;
+/// HyperLogLog counter of distinct items
+pub mod hyperloglog;
+/// Local pacing of writes made by the app
+pub mod pacer;
+/// Seedable source of randomness of the node
+pub mod rng;
 /// Module for work with serialization
 pub mod serialization;
-/// Module for work with time
+/// Temporary directories of tests which are removed after them
+#[cfg(test)]
+pub(crate) mod temp_dir;
+/// Current time from the system or virtual clock
 pub mod time;
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant, sleep};
+
+use crate::exceptions::{NetworkError, RhizomeError};
+use crate::utils::time::{Clock, SystemClock, duration_from_secs};
+
+/// Local pacing of writes made by the app
+///
+/// Every write publishes records to several peers, which count it against their rate
+/// limits. Writes over `limit` per `window` wait in FIFO order until the oldest one leaves
+/// the sliding window, so a burst of the app is spread in time instead of getting the node
+/// greylisted. When `max_queued` writes are waiting, next writes fail at once.
+pub struct WritePacer {
+    limit: usize,
+    window: Duration,
+    max_queued: usize,
+    /// Start times of writes within the window
+    history: Mutex<VecDeque<Instant>>,
+    queued: AtomicUsize,
+    /// Clock of the node which the writes go to
+    time: Arc<dyn Clock>,
+}
+
+impl WritePacer {
+    /// Pacer for `limit` writes per `window` seconds _(0 disables pacing)_
+    pub fn new(limit: usize, window: f64, max_queued: usize) -> Self {
+        Self {
+            limit,
+            window: duration_from_secs(window),
+            max_queued,
+            history: Mutex::new(VecDeque::with_capacity(limit)),
+            queued: AtomicUsize::new(0),
+            time: Arc::new(SystemClock),
+        }
+    }
+
+    /// Same pacer which counts the window by `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.time = clock;
+        self
+    }
+
+    /// Wait for a slot of the next write
+    ///
+    /// Return `RateLimitError` if the queue of waiting writes is full.
+    pub async fn acquire(&self) -> Result<(), RhizomeError> {
+        if self.limit == 0 {
+            return Ok(());
+        }
+        let slot = QueuedSlot(&self.queued);
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            return Err(RhizomeError::Network(NetworkError::RateLimitError));
+        }
+
+        // Lock of tokio is fair, so waiting writes go in order of calls
+        let mut history = self.history.lock().await;
+        drop(slot);
+        if history.len() >= self.limit
+            && let Some(oldest) = history.front()
+        {
+            sleep((*oldest + self.window).saturating_duration_since(self.time.instant())).await;
+        }
+        let now = self.time.instant();
+        while history
+            .front()
+            .is_some_and(|started| now.duration_since(*started) >= self.window)
+        {
+            history.pop_front();
+        }
+        history.push_back(now);
+        Ok(())
+    }
+}
+
+/// Place in the queue of waiting writes, freed also when the write is cancelled
+struct QueuedSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cancelled_write_leaves_the_queue() {
+        let pacer = WritePacer::new(1, 3600.0, 1);
+        pacer.acquire().await.unwrap();
+
+        for _ in 0..3 {
+            // Waiting write is cancelled, its place is free for the next one
+            let waited = tokio::time::timeout(Duration::from_millis(10), pacer.acquire()).await;
+            assert!(waited.is_err());
+        }
+        assert_eq!(pacer.queued.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn full_queue_rejects_writes() {
+        let pacer = WritePacer::new(1, 3600.0, 1);
+        pacer.acquire().await.unwrap();
+        // First write sleeps for its slot, second one waits for the first
+        let (first, second) = (pacer.acquire(), pacer.acquire());
+        tokio::pin!(first, second);
+        for waiting in [first.as_mut(), second.as_mut()] {
+            let waited = tokio::time::timeout(Duration::from_millis(10), waiting).await;
+            assert!(waited.is_err());
+        }
+
+        assert!(matches!(
+            pacer.acquire().await,
+            Err(RhizomeError::Network(NetworkError::RateLimitError))
+        ));
+    }
+
+    #[test]
+    fn invalid_window_does_not_panic() {
+        for window in [f64::NAN, f64::INFINITY, -1.0] {
+            let _ = WritePacer::new(1, window, 1);
+        }
+    }
+}
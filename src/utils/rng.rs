@@ -0,0 +1,47 @@
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::sync::Mutex;
+
+/// Source of randomness for message IDs, bucket refresh IDs and node ID of a new node
+///
+/// Normally it is seeded from OS entropy. Fixed seed makes these values reproducible,
+/// it is only for tests and simulations built with `seeded-rng` feature. With
+/// `signatures` feature the key of a new node is made by the seeded generator too, so
+/// the node ID derived from it is reproducible.
+pub struct NodeRng {
+    rng: Mutex<StdRng>,
+    seeded: bool,
+}
+
+impl NodeRng {
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            rng: Mutex::new(rng),
+            seeded: seed.is_some(),
+        }
+    }
+
+    /// Values are reproducible
+    pub fn is_seeded(&self) -> bool {
+        self.seeded
+    }
+
+    pub fn fill_bytes(&self, dest: &mut [u8]) {
+        self.rng.lock().unwrap().fill_bytes(dest);
+    }
+
+    /// Run `f` with the inner generator, e.g. for key generation
+    pub fn with<T>(&self, f: impl FnOnce(&mut StdRng) -> T) -> T {
+        f(&mut self.rng.lock().unwrap())
+    }
+}
+
+impl Default for NodeRng {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
@@ -1,6 +1,9 @@
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::fmt;
 use thiserror::Error;
 
+use crate::network::protocol::ProtocolMessage;
+
 #[derive(Error, Debug)]
 pub enum SerializationError {
     #[error("Unsupported format: {0}")]
@@ -14,25 +17,31 @@ pub enum SerializationError {
 
     #[error("Msgpack decode error: {0}")]
     MsgpackDecodeError(#[from] rmp_serde::decode::Error),
+
+    #[error("CBOR error: {0}")]
+    CborError(String),
+
+    #[cfg(feature = "protobuf")]
+    #[error("Protobuf decode error: {0}")]
+    ProtobufDecodeError(#[from] prost::DecodeError),
+}
+
+/// Format of serialized data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    MsgPack,
+    Json,
 }
 
 /// Data serialization
 ///
 /// Args:
 /// - data: Data for serialization (any type with the Serialize attribute)
-/// - format: Serialization format ("msgpack" or "json")
-pub fn serialize<T: Serialize>(data: &T, format: &str) -> Result<Vec<u8>, SerializationError> {
+/// - format: Serialization format
+pub fn serialize<T: Serialize>(data: &T, format: Format) -> Result<Vec<u8>, SerializationError> {
     match format {
-        "msgpack" => {
-            let mut buf = Vec::new();
-            data.serialize(&mut rmp_serde::Serializer::new(&mut buf))?;
-            Ok(buf)
-        }
-        "json" => {
-            let json_string = serde_json::to_string(data)?;
-            Ok(json_string.into_bytes())
-        }
-        _ => Err(SerializationError::UnsupportedFormat(format.to_string())),
+        Format::MsgPack => to_msgpack(data),
+        Format::Json => Ok(serde_json::to_vec(data)?),
     }
 }
 
@@ -40,20 +49,203 @@ pub fn serialize<T: Serialize>(data: &T, format: &str) -> Result<Vec<u8>, Serial
 ///
 /// Args:
 /// - data: Serialized data (bytes)
-/// - format: Serialization format ("msgpack" or "json")
+/// - format: Serialization format
 pub fn deserialize<T: DeserializeOwned>(
     data: &[u8],
-    format: &str,
+    format: Format,
 ) -> Result<T, SerializationError> {
     match format {
-        "msgpack" => {
-            let val = rmp_serde::from_slice(data)?;
-            Ok(val)
+        Format::MsgPack => from_msgpack(data),
+        Format::Json => Ok(serde_json::from_slice(data)?),
+    }
+}
+
+/// Serialize data to msgpack, structs are encoded as arrays of fields
+pub fn to_msgpack<T: Serialize>(data: &T) -> Result<Vec<u8>, SerializationError> {
+    let mut buf = Vec::new();
+    data.serialize(&mut rmp_serde::Serializer::new(&mut buf))?;
+    Ok(buf)
+}
+
+/// Deserialize data from msgpack
+pub fn from_msgpack<T: DeserializeOwned>(data: &[u8]) -> Result<T, SerializationError> {
+    Ok(rmp_serde::from_slice(data)?)
+}
+
+/// Format of protocol messages on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    /// Baseline format, every node understands it
+    #[default]
+    Msgpack,
+    Cbor,
+    /// Available with the `protobuf` feature
+    Protobuf,
+}
+
+impl WireFormat {
+    /// Formats which this build can encode and decode
+    pub fn supported() -> Vec<WireFormat> {
+        let mut formats = vec![WireFormat::Msgpack, WireFormat::Cbor];
+        if cfg!(feature = "protobuf") {
+            formats.push(WireFormat::Protobuf);
         }
-        "json" => {
-            let val = serde_json::from_slice(data)?;
-            Ok(val)
+        formats
+    }
+
+    pub fn is_supported(&self) -> bool {
+        Self::supported().contains(self)
+    }
+
+    /// Parse format name from config ("msgpack", "cbor" or "protobuf")
+    pub fn from_name(name: &str) -> Option<WireFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "msgpack" => Some(WireFormat::Msgpack),
+            "cbor" => Some(WireFormat::Cbor),
+            "protobuf" => Some(WireFormat::Protobuf),
+            _ => None,
         }
-        _ => Err(SerializationError::UnsupportedFormat(format.to_string())),
     }
+
+    /// Guess format of the encoded message by its first byte
+    ///
+    /// Msgpack message is a fixarray of 5 fields, CBOR message is a map and protobuf
+    /// message starts with the tag of `msg_type` field.
+    pub fn detect(data: &[u8]) -> Option<WireFormat> {
+        match data.first()? {
+            0x90..=0x9f | 0xdc | 0xdd => Some(WireFormat::Msgpack),
+            0xa0..=0xbf => Some(WireFormat::Cbor),
+            0x08 => Some(WireFormat::Protobuf),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            WireFormat::Msgpack => "msgpack",
+            WireFormat::Cbor => "cbor",
+            WireFormat::Protobuf => "protobuf",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Encoder and decoder of protocol messages in one wire format
+pub trait WireCodec: Send + Sync {
+    fn format(&self) -> WireFormat;
+    fn encode(&self, message: &ProtocolMessage) -> Result<Vec<u8>, SerializationError>;
+    fn decode(&self, data: &[u8]) -> Result<ProtocolMessage, SerializationError>;
+}
+
+/// Message as msgpack array of fields
+pub struct MsgpackCodec;
+
+impl WireCodec for MsgpackCodec {
+    fn format(&self) -> WireFormat {
+        WireFormat::Msgpack
+    }
+
+    fn encode(&self, message: &ProtocolMessage) -> Result<Vec<u8>, SerializationError> {
+        to_msgpack(message)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<ProtocolMessage, SerializationError> {
+        from_msgpack(data)
+    }
+}
+
+/// Message as CBOR map of fields
+pub struct CborCodec;
+
+impl WireCodec for CborCodec {
+    fn format(&self) -> WireFormat {
+        WireFormat::Cbor
+    }
+
+    fn encode(&self, message: &ProtocolMessage) -> Result<Vec<u8>, SerializationError> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(message, &mut buf)
+            .map_err(|e| SerializationError::CborError(e.to_string()))?;
+        Ok(buf)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<ProtocolMessage, SerializationError> {
+        ciborium::from_reader(data).map_err(|e| SerializationError::CborError(e.to_string()))
+    }
+}
+
+/// Protobuf schema of the message
+///
+/// Protobuf has no dynamic maps, so payload is carried as JSON bytes.
+#[cfg(feature = "protobuf")]
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProtobufMessage {
+    #[prost(uint32, tag = "1")]
+    msg_type: u32,
+    #[prost(bytes = "vec", tag = "2")]
+    id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    node_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "4")]
+    payload: Vec<u8>,
+    #[prost(double, tag = "5")]
+    timestamp: f64,
+}
+
+/// Message as protobuf `ProtobufMessage`
+#[cfg(feature = "protobuf")]
+pub struct ProtobufCodec;
+
+#[cfg(feature = "protobuf")]
+impl WireCodec for ProtobufCodec {
+    fn format(&self) -> WireFormat {
+        WireFormat::Protobuf
+    }
+
+    fn encode(&self, message: &ProtocolMessage) -> Result<Vec<u8>, SerializationError> {
+        let proto = ProtobufMessage {
+            msg_type: message.msg_type as u32,
+            id: message.id.to_vec(),
+            node_id: message.node_id.to_vec(),
+            payload: serde_json::to_vec(&message.payload)?,
+            timestamp: message.timestamp,
+        };
+        Ok(prost::Message::encode_to_vec(&proto))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<ProtocolMessage, SerializationError> {
+        let proto: ProtobufMessage = prost::Message::decode(data)?;
+        let invalid = || SerializationError::UnsupportedFormat("malformed protobuf message".into());
+        Ok(ProtocolMessage {
+            msg_type: u8::try_from(proto.msg_type).map_err(|_| invalid())?,
+            id: proto.id.try_into().map_err(|_| invalid())?,
+            node_id: proto.node_id.try_into().map_err(|_| invalid())?,
+            payload: serde_json::from_slice(&proto.payload)?,
+            timestamp: proto.timestamp,
+        })
+    }
+}
+
+/// Codec of the format, `None` if format is not compiled in
+pub fn codec(format: WireFormat) -> Option<&'static dyn WireCodec> {
+    match format {
+        WireFormat::Msgpack => Some(&MsgpackCodec),
+        WireFormat::Cbor => Some(&CborCodec),
+        #[cfg(feature = "protobuf")]
+        WireFormat::Protobuf => Some(&ProtobufCodec),
+        #[cfg(not(feature = "protobuf"))]
+        WireFormat::Protobuf => None,
+    }
+}
+
+/// Decode message in any supported format
+pub fn decode_message(data: &[u8]) -> Result<ProtocolMessage, SerializationError> {
+    let format = WireFormat::detect(data)
+        .ok_or_else(|| SerializationError::UnsupportedFormat("unknown wire format".into()))?;
+    codec(format)
+        .ok_or_else(|| SerializationError::UnsupportedFormat(format.to_string()))?
+        .decode(data)
 }
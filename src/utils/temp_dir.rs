@@ -0,0 +1,35 @@
+use std::path::{Path, PathBuf};
+
+/// Directory of one test under the system temp dir, it is removed on drop
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create new empty directory, `name` tells which test made it
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "rhizome-{}-{}-{}",
+            name,
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of the entry inside the directory
+    pub fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
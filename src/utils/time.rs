@@ -1,16 +1,105 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
 
-/// Return current time in seconds in i64 format
+/// Source of the current time of one node
+///
+/// Every node gets its clock at creation _(system clock by default)_ and gives it to its
+/// storage, DHT and network, so nodes of one process may live in different times.
+/// Simulations and TTL checks give nodes [`VirtualClock`] and move it forward instead of
+/// waiting. Code outside of the node reads the system clock by [`get_now_f64`].
+pub trait Clock: Send + Sync {
+    /// Seconds since the UNIX epoch
+    fn now(&self) -> f64;
+
+    /// Monotonic time for round trips, deadlines and pacing
+    fn instant(&self) -> Instant;
+}
+
+/// Wall clock of the operating system
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock which moves only when it is told to
+///
+/// Nodes which share one clock see the same time.
+pub struct VirtualClock {
+    /// Bits of the current time in seconds
+    now: AtomicU64,
+    start: f64,
+    /// Monotonic time of the start
+    started: Instant,
+}
+
+impl VirtualClock {
+    pub fn new(start: f64) -> Self {
+        Self {
+            now: AtomicU64::new(start.to_bits()),
+            start,
+            started: Instant::now(),
+        }
+    }
+
+    /// Clock which starts at the current system time
+    pub fn starting_now() -> Self {
+        Self::new(SystemClock.now())
+    }
+
+    pub fn set(&self, time: f64) {
+        self.now.store(time.to_bits(), Ordering::SeqCst);
+    }
+
+    /// Move time forward by `by`
+    pub fn advance(&self, by: Duration) {
+        let _ = self
+            .now
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bits| {
+                Some((f64::from_bits(bits) + by.as_secs_f64()).to_bits())
+            });
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> f64 {
+        f64::from_bits(self.now.load(Ordering::SeqCst))
+    }
+
+    /// Start of the clock moved by the time it was moved forward _(never before the start)_
+    fn instant(&self) -> Instant {
+        self.started + duration_from_secs(self.now() - self.start)
+    }
+}
+
+/// Return current time of the system clock in seconds in i64 format
 pub fn get_now_i64() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64
+    get_now_f64() as i64
 }
-/// Return current time in seconds in f64 format
+/// Return current time of the system clock in seconds in f64 format
 pub fn get_now_f64() -> f64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs_f64()
+    SystemClock.now()
+}
+
+/// Longest duration made by [`duration_from_secs`], 136 years
+pub const MAX_DURATION_SECS: f64 = u32::MAX as f64;
+
+/// Duration of `seconds` from config or API which never panics
+///
+/// Negative and NaN values are zero, too long and infinite ones are [`MAX_DURATION_SECS`].
+pub fn duration_from_secs(seconds: f64) -> Duration {
+    if seconds.is_nan() {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(seconds.clamp(0.0, MAX_DURATION_SECS))
 }
@@ -0,0 +1,125 @@
+//! # Webhooks Module
+//!
+//! Delivery of selected node events to external HTTP endpoints, e.g. moderation and
+//! alerting systems. Every event is sent as JSON POST request:
+//!
+//! ```json
+//! {"event": "peer_blacklisted", "node_id": "…", "timestamp": 1700000000.0, "data": {…}}
+//! ```
+//!
+//! Delivery is best effort: failed requests are logged and not repeated.
+
+use futures::future::join_all;
+use serde_json::{Value, json};
+use tracing::{debug, warn};
+
+use crate::config::WebhookConfig;
+use crate::events::NodeEvent;
+use crate::utils::time::{duration_from_secs, get_now_f64};
+
+/// Name of the event in webhook requests and JSON data of the event
+///
+/// Only events which are interesting for external systems have names, others are not sent.
+pub fn event_payload(event: &NodeEvent) -> Option<(&'static str, Value)> {
+    match event {
+        NodeEvent::ThreadCreated { thread_id } => {
+            Some(("thread_created", json!({ "thread_id": thread_id })))
+        }
+        NodeEvent::PeerBlacklisted {
+            peer_id,
+            seconds,
+            reason,
+        } => Some((
+            "peer_blacklisted",
+            json!({
+                "peer_id": hex::encode(peer_id),
+                "seconds": seconds,
+                "reason": reason,
+            }),
+        )),
+        // Node stops accepting data of other nodes until disk space is recovered
+        NodeEvent::DiskSpaceLow {
+            free_bytes,
+            min_free_bytes,
+        } => Some((
+            "storage_full",
+            json!({
+                "free_bytes": free_bytes,
+                "min_free_bytes": min_free_bytes,
+            }),
+        )),
+        _ => None,
+    }
+}
+
+/// Sender of node events to configured webhooks
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    hooks: Vec<WebhookConfig>,
+    /// Hex of our node ID, added to every request
+    node_id: String,
+}
+
+impl WebhookDispatcher {
+    pub fn new(node_id: &[u8], hooks: Vec<WebhookConfig>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            hooks,
+            node_id: hex::encode(node_id),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Webhook wants to receive events with this name
+    fn subscribed(hook: &WebhookConfig, name: &str) -> bool {
+        hook.events.is_empty() || hook.events.iter().any(|e| e == name)
+    }
+
+    /// Send event to all webhooks subscribed to it, return count of successful deliveries
+    pub async fn dispatch(&self, event: &NodeEvent) -> usize {
+        let Some((name, data)) = event_payload(event) else {
+            return 0;
+        };
+        let body = json!({
+            "event": name,
+            "node_id": self.node_id,
+            "timestamp": get_now_f64(),
+            "data": data,
+        });
+
+        let requests = self
+            .hooks
+            .iter()
+            .filter(|hook| Self::subscribed(hook, name))
+            .map(|hook| self.post(hook, &body));
+        join_all(requests)
+            .await
+            .into_iter()
+            .filter(|delivered| *delivered)
+            .count()
+    }
+
+    async fn post(&self, hook: &WebhookConfig, body: &Value) -> bool {
+        let result = self
+            .client
+            .post(&hook.url)
+            .timeout(duration_from_secs(hook.timeout.max(0.1)))
+            .json(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => {
+                debug!(url = %hook.url, event = %body["event"], "Webhook delivered");
+                true
+            }
+            Err(e) => {
+                warn!(url = %hook.url, event = %body["event"], error = %e, "Webhook delivery failed");
+                false
+            }
+        }
+    }
+}